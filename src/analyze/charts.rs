@@ -0,0 +1,123 @@
+//! Terminal charts of the analyze report, for `--charts`
+//!
+//! Bars and sparklines are drawn with block characters in eighths of a cell,
+//! to the width of the terminal: `COLUMNS` when set, else the width of the
+//! terminal on stdout, else 80 columns.
+
+/// Columns charts are drawn to when the terminal width is unknown
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// Blocks of one to eight eighths of a cell, left to right
+const BAR_EIGHTHS: [char; 8] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+
+/// Blocks of one to eight eighths of a cell, bottom up
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Columns of the terminal the report is printed to
+pub fn terminal_width() -> usize {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+        .filter(|&columns| columns > 0)
+    {
+        return columns;
+    }
+    // SAFETY: TIOCGWINSZ only writes a winsize into the struct it is given
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if ok && size.ws_col > 0 {
+        size.ws_col as usize
+    } else {
+        DEFAULT_WIDTH
+    }
+}
+
+/// Bar `fraction` (0-1) of `width` cells long
+pub fn bar(fraction: f64, width: usize) -> String {
+    let eighths = (fraction.clamp(0.0, 1.0) * (width * 8) as f64).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(BAR_EIGHTHS[eighths % 8 - 1]);
+    }
+    bar
+}
+
+/// One line per row: its label, a bar scaled to the largest count, then the
+/// count and its share of the counts, the whole `width` columns wide
+pub fn bar_chart(rows: &[(String, u64)], width: usize) -> Vec<String> {
+    let total: u64 = rows.iter().map(|(_, count)| count).sum();
+    let largest = rows.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    let label_width = rows.iter().map(|(label, _)| label.chars().count()).max();
+    let count_width = largest.to_string().len();
+    // "  label │bar count (100.0%)"
+    let fixed = 2 + label_width.unwrap_or(0) + 2 + 1 + count_width + 9;
+    let bar_width = width.saturating_sub(fixed).max(10);
+    rows.iter()
+        .map(|(label, count)| {
+            let bar = bar(*count as f64 / largest.max(1) as f64, bar_width);
+            format!(
+                "  {:>label$} │{:<bar_width$} {:>count_width$} ({:.1}%)",
+                label,
+                bar,
+                count,
+                *count as f64 / total.max(1) as f64 * 100.0,
+                label = label_width.unwrap_or(0),
+            )
+        })
+        .collect()
+}
+
+/// One character per value, its height scaled from 0 to the largest value.
+/// More values than `width` are merged, each character showing the largest
+/// of its values, so bursts stay visible.
+pub fn sparkline(values: &[f64], width: usize) -> String {
+    let width = width.max(1);
+    let merged: Vec<f64> = if values.len() > width {
+        let per_cell = values.len().div_ceil(width);
+        values
+            .chunks(per_cell)
+            .map(|chunk| chunk.iter().copied().fold(0.0, f64::max))
+            .collect()
+    } else {
+        values.to_vec()
+    };
+    let largest = merged.iter().copied().fold(0.0, f64::max);
+    merged
+        .iter()
+        .map(|&value| {
+            if largest <= 0.0 || value <= 0.0 {
+                ' '
+            } else {
+                let level = (value / largest * SPARK_LEVELS.len() as f64).ceil() as usize;
+                SPARK_LEVELS[level.clamp(1, SPARK_LEVELS.len()) - 1]
+            }
+        })
+        .collect()
+}
+
+/// Counts of a size distribution by range of sizes, smallest first: each size
+/// of its own when there are at most `rows` of them, else powers of two
+/// (`2^(k-1)+1..=2^k`). Ranges are (first size, last size, count).
+pub fn size_ranges(sizes: &[(u32, u64)], rows: usize) -> Vec<(u32, u32, u64)> {
+    let mut sizes = sizes.to_vec();
+    sizes.sort_unstable();
+    if sizes.len() <= rows {
+        return sizes
+            .into_iter()
+            .map(|(size, count)| (size, size, count))
+            .collect();
+    }
+    let mut ranges: Vec<(u32, u32, u64)> = Vec::new();
+    for (size, count) in sizes {
+        let high = match size {
+            0 => 0,
+            _ => size.checked_next_power_of_two().unwrap_or(u32::MAX),
+        };
+        let low = if high <= 1 { high } else { high / 2 + 1 };
+        match ranges.last_mut() {
+            Some(range) if range.1 == high => range.2 += count,
+            _ => ranges.push((low, high, count)),
+        }
+    }
+    ranges
+}
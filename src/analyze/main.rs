@@ -1,13 +1,30 @@
 //! Analyze command implementation
 
-use crate::replay::{DistributionAnalyzer, ProfileReader};
-use anyhow::Result;
+use super::charts;
+use super::report::AnalyzeReport;
+use crate::profile::{ProfileMetadata, SizeBuckets, TimeRange};
+use crate::replay::connections::busiest_share;
+use crate::replay::{
+    DistributionAnalyzer, ProfileReader, ProfileStreamer, ThroughputBucket, ThroughputSeries,
+};
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+use std::time::Duration;
 
-pub fn run(input: &str) -> Result<()> {
-    let reader = ProfileReader::new(input)?;
-    let metadata = reader.metadata();
-    let analysis = DistributionAnalyzer::analyze(reader.events());
+/// Keys counted as hot in the per-client breakdown
+const HOT_KEYS: usize = 10;
+
+/// Most rows of the throughput table when its interval is not given
+pub const THROUGHPUT_ROWS: usize = 60;
 
+/// Windows of the capture a working set is reported for
+pub const WORKING_SET_WINDOWS: usize = 10;
+
+/// Report header, event totals and everything else the metadata gives, for
+/// `total_events` events
+fn print_summary(input: &str, metadata: &ProfileMetadata, total_events: u64) {
     println!("\n╔═══════════════════════════════════════════════════════╗");
     println!("║            Profile Analysis Report                    ║");
     println!("╚═══════════════════════════════════════════════════════╝\n");
@@ -18,8 +35,56 @@ pub fn run(input: &str) -> Result<()> {
 
     // Event statistics
     println!("─ Event Statistics ─");
-    println!("Total events: {}", analysis.total_events);
-    println!("Unique connections: {}\n", metadata.unique_connections);
+    println!("Total events: {}", total_events);
+    println!("Unique connections: {}", metadata.unique_connections);
+    // Sampled profiles also report the totals extrapolated back
+    if let Some(rate) = metadata.connection_sampling {
+        println!(
+            "Connection sampling: {} (about {:.0} connections unsampled)",
+            rate,
+            metadata.unique_connections as f64 / rate.fraction()
+        );
+    }
+    if let Some(probability) = metadata.event_sampling {
+        println!("Event sampling: {:.2}% of commands", probability * 100.0);
+    }
+    if metadata.plaintext_keys {
+        println!("Keys: stored in plaintext (recorded with --no-anonymize)");
+    }
+    if let Some(sketch) = &metadata.key_sketch {
+        println!("Unique keys (estimated): {}", sketch.estimate());
+    }
+    let scale = metadata.sampling_scale();
+    if scale > 1.0 {
+        println!(
+            "Estimated events before sampling: {:.0}",
+            total_events as f64 * scale
+        );
+    }
+    let mut protocol_counts = std::collections::BTreeMap::new();
+    for protocol in metadata.connection_protocols.values() {
+        *protocol_counts.entry(protocol).or_insert(0u32) += 1;
+    }
+    if !protocol_counts.is_empty() {
+        let summary: Vec<String> = protocol_counts
+            .iter()
+            .map(|(protocol, count)| format!("{:?}: {}", protocol, count))
+            .collect();
+        println!("Connection protocols: {}", summary.join(", "));
+    }
+    if !metadata.sources.is_empty() {
+        println!("Merged from {} profiles:", metadata.sources.len());
+        for source in &metadata.sources {
+            println!(
+                "  {}: {} events, connections {}..{}",
+                source.name,
+                source.total_events,
+                source.first_conn_id,
+                source.first_conn_id as u32 + source.unique_connections
+            );
+        }
+    }
+    println!();
 
     // Time range
     let time_range = metadata.time_range;
@@ -29,17 +94,229 @@ pub fn run(input: &str) -> Result<()> {
         println!("Time range: {:.2} seconds\n", duration_secs);
     }
 
+    // Capture counters
+    if let Some(capture) = metadata.capture {
+        println!("─ Capture ─");
+        println!("Packets captured: {}", capture.packets_received);
+        if let Some(dropped) = capture.packets_dropped {
+            println!("Packets dropped by kernel: {}", dropped);
+        }
+        println!("Packets skipped: {}", capture.packets_skipped);
+        println!("Parse failures: {}\n", capture.parse_errors);
+    }
+}
+
+/// Print what the metadata of a profile holds, without reading its events
+pub fn run_summary(input: &str) -> Result<()> {
+    let streamer = ProfileStreamer::new(input)?;
+    print_summary(input, streamer.metadata(), streamer.metadata().total_events);
+    Ok(())
+}
+
+/// How the full report is written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    /// Sections for a reader
+    #[default]
+    Text,
+    /// One JSON document holding the whole `AnalyzeReport`
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            "yaml" => {
+                Err("YAML output is not supported by this build. Use --output json".to_string())
+            }
+            _ => Err(format!(
+                "Invalid output format: '{}'. Use 'text' or 'json'",
+                s
+            )),
+        }
+    }
+}
+
+/// What the full report covers beyond its fixed sections
+#[derive(Debug, Clone, Default)]
+pub struct AnalyzeOptions {
+    /// Part of the capture to analyze
+    pub range: TimeRange,
+    pub output: ReportFormat,
+    /// Also print the miss-ratio curve, simulating this share of the keys
+    pub mrc: Option<f64>,
+    /// Interval of the throughput series; by default the shortest of
+    /// `AUTO_INTERVALS` giving at most `THROUGHPUT_ROWS` rows
+    pub interval: Option<Duration>,
+    /// Also write the throughput series to this CSV file
+    pub throughput_csv: Option<String>,
+    /// Also report every connection
+    pub per_connection: bool,
+    /// Chart the size distributions and the throughput series
+    pub charts: bool,
+}
+
+/// Full report of the events of a profile
+pub fn run(input: &str, options: &AnalyzeOptions) -> Result<()> {
+    if options.interval.is_some_and(|interval| interval.is_zero()) {
+        return Err(anyhow!("the throughput interval must be longer than 0"));
+    }
+    let reader = ProfileReader::with_range(input, options.range)?;
+    let metadata = reader.metadata();
+    let analysis = DistributionAnalyzer::analyze(reader.events());
+    let report = AnalyzeReport::new(input, metadata, reader.events(), analysis, options)?;
+    if let Some(path) = &options.throughput_csv {
+        let file = File::create(path).context(format!("failed to create {}", path))?;
+        let mut out = BufWriter::new(file);
+        report.throughput.write_csv(&mut out)?;
+        out.flush()?;
+    }
+    if options.output == ReportFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    let analysis = &report.analysis;
+    print_summary(input, metadata, analysis.total_events);
+    let scale = metadata.sampling_scale();
+
     // Command distribution
     println!("─ Command Distribution ─");
     let mut cmd_entries: Vec<_> = analysis.command_distribution.iter().collect();
     cmd_entries.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
     for (cmd, count) in cmd_entries {
         let percentage = (*count as f64 / analysis.total_events as f64) * 100.0;
-        println!("{:?}: {} ({:.1}%)", cmd, count, percentage);
+        if scale > 1.0 {
+            println!(
+                "{:?}: {} ({:.1}%), about {:.0} before sampling",
+                cmd,
+                count,
+                percentage,
+                *count as f64 * scale
+            );
+        } else {
+            println!("{:?}: {} ({:.1}%)", cmd, count, percentage);
+        }
+    }
+
+    // Throughput over time, to see bursts the totals hide
+    let series = &report.throughput;
+    if let Some((peak_at, peak)) = series.peak() {
+        println!("\n─ Throughput ─");
+        let span = series.interval.as_secs_f64() * series.buckets.len() as f64;
+        let mean = analysis.total_events as f64 / span;
+        let bytes: u64 = series.buckets.iter().map(|bucket| bucket.bytes).sum();
+        println!("Interval: {:?}", series.interval);
+        println!(
+            "Mean: {:.1} ops/s, {}/s",
+            mean,
+            byte_size((bytes as f64 / span) as u64)
+        );
+        println!(
+            "Peak: {:.1} ops/s at {:.1}s ({:.1}x the mean)",
+            series.rate(peak),
+            peak_at as f64 * series.interval.as_secs_f64(),
+            series.rate(peak) / mean
+        );
+        if scale > 1.0 {
+            println!(
+                "Rates are of the sampled events: about {:.1}x as many before sampling",
+                scale
+            );
+        }
+        let commands = series.commands();
+        if options.charts {
+            print_throughput_chart(series);
+        } else {
+            let names: Vec<String> = commands
+                .iter()
+                .map(|cmd_type| format!("{:>10}", format!("{:?}/s", cmd_type)))
+                .collect();
+            println!(
+                "  {:>10} {:>12} {:>12} {}",
+                "Offset",
+                "ops/s",
+                "bytes/s",
+                names.join(" ")
+            );
+            for (i, bucket) in series.buckets.iter().enumerate() {
+                let per_command: Vec<String> = commands
+                    .iter()
+                    .map(|cmd_type| {
+                        let count = *bucket.per_command.get(cmd_type).unwrap_or(&0);
+                        format!("{:>10.1}", series.rate(count))
+                    })
+                    .collect();
+                println!(
+                    "  {:>9.1}s {:>12.1} {:>12} {}",
+                    i as f64 * series.interval.as_secs_f64(),
+                    series.rate(bucket.commands),
+                    byte_size(series.rate(bucket.bytes) as u64),
+                    per_command.join(" ")
+                );
+            }
+        }
+    }
+    if let Some(path) = &options.throughput_csv {
+        println!("Throughput series written to {}", path);
+    }
+
+    // Responses paired with their commands during capture
+    if let Some(responses) = &report.responses {
+        println!("\n─ Recorded Responses ─");
+        println!(
+            "Answered: {} of {} commands",
+            responses.answered, analysis.total_events
+        );
+        if let Some(hit_rate) = responses.get_hit_rate {
+            println!("GET hit rate: {:.1}%", hit_rate * 100.0);
+        }
+        if responses.errors > 0 {
+            println!("Errors: {}", responses.errors);
+        }
+    }
+
+    // Server latency, from each command to the response paired with it
+    if let Some(latency) = &report.latency {
+        let overall = latency.overall;
+        println!("\n─ Server Latency ─");
+        println!(
+            "p50: {}µs, p90: {}µs, p99: {}µs, p99.9: {}µs, max: {}µs",
+            overall.p50, overall.p90, overall.p99, overall.p999, overall.max
+        );
+        for (cmd_type, summary) in &latency.per_command {
+            println!(
+                "  {:?}: {} answered, p50: {}µs, p99: {}µs",
+                cmd_type, summary.answered, summary.p50, summary.p99
+            );
+        }
+    }
+
+    // Commands per server, when several server ports were recorded
+    let mut server_counts = std::collections::BTreeMap::new();
+    for port in reader.events().iter().filter_map(|event| event.server_port) {
+        *server_counts.entry(port.get()).or_insert(0u64) += 1;
+    }
+    if server_counts.len() > 1 {
+        println!("\n─ Servers ─");
+        for (port, count) in &server_counts {
+            let percentage = (*count as f64 / analysis.total_events as f64) * 100.0;
+            println!("Port {}: {} ({:.1}%)", port, count, percentage);
+        }
     }
 
+    // Sizes of a bucketed profile are labelled with the range of their bucket
+    let buckets = metadata.size_buckets.unwrap_or_default();
+
     // Key size distribution
     println!("\n─ Key Size Distribution ─");
+    if let Some(key) = buckets.key {
+        println!("Bucketed: sizes {} at record time", key.description());
+    }
     if !analysis.key_size_distribution.is_empty() {
         let mut key_sizes: Vec<_> = analysis.key_size_distribution.clone();
         key_sizes.sort_by_key(|(size, _)| *size);
@@ -54,15 +331,23 @@ pub fn run(input: &str) -> Result<()> {
             .sum::<f64>()
             / total_keys.max(1) as f64;
 
-        println!("Min: {} bytes", min_size);
-        println!("Max: {} bytes", max_size);
+        println!("Min: {} bytes", size_label(min_size, buckets.key));
+        println!("Max: {} bytes", size_label(max_size, buckets.key));
         println!("Avg: {:.1} bytes", avg_size);
 
-        if key_sizes.len() <= 10 {
+        if options.charts {
+            println!("\nDistribution:");
+            print_size_chart(&key_sizes, buckets.key);
+        } else if key_sizes.len() <= 10 {
             println!("\nDistribution:");
             for (size, count) in &key_sizes {
                 let percentage = (*count as f64 / total_keys as f64) * 100.0;
-                println!("  {} bytes: {} ({:.1}%)", size, count, percentage);
+                println!(
+                    "  {} bytes: {} ({:.1}%)",
+                    size_label(*size, buckets.key),
+                    count,
+                    percentage
+                );
             }
         } else {
             println!("\nTop 10 sizes:");
@@ -70,13 +355,21 @@ pub fn run(input: &str) -> Result<()> {
             top_sizes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
             for (size, count) in top_sizes.iter().take(10) {
                 let percentage = (*count as f64 / total_keys as f64) * 100.0;
-                println!("  {} bytes: {} ({:.1}%)", size, count, percentage);
+                println!(
+                    "  {} bytes: {} ({:.1}%)",
+                    size_label(*size, buckets.key),
+                    count,
+                    percentage
+                );
             }
         }
     }
 
     // Value size distribution
     println!("\n─ Value Size Distribution ─");
+    if let Some(value) = buckets.value {
+        println!("Bucketed: sizes {} at record time", value.description());
+    }
     if !analysis.value_size_distribution.is_empty() {
         let mut value_sizes: Vec<_> = analysis.value_size_distribution.clone();
         value_sizes.sort_by_key(|(size, _)| *size);
@@ -91,8 +384,8 @@ pub fn run(input: &str) -> Result<()> {
             .sum::<f64>()
             / total_values.max(1) as f64;
 
-        println!("Min: {} bytes", min_size);
-        println!("Max: {} bytes", max_size);
+        println!("Min: {} bytes", size_label(min_size, buckets.value));
+        println!("Max: {} bytes", size_label(max_size, buckets.value));
         println!("Avg: {:.1} bytes", avg_size);
         println!(
             "Total with values: {} ({:.1}%)",
@@ -100,11 +393,19 @@ pub fn run(input: &str) -> Result<()> {
             (total_values as f64 / analysis.total_events as f64) * 100.0
         );
 
-        if value_sizes.len() <= 10 {
+        if options.charts {
+            println!("\nDistribution:");
+            print_size_chart(&value_sizes, buckets.value);
+        } else if value_sizes.len() <= 10 {
             println!("\nDistribution:");
             for (size, count) in &value_sizes {
                 let percentage = (*count as f64 / total_values as f64) * 100.0;
-                println!("  {} bytes: {} ({:.1}%)", size, count, percentage);
+                println!(
+                    "  {} bytes: {} ({:.1}%)",
+                    size_label(*size, buckets.value),
+                    count,
+                    percentage
+                );
             }
         } else {
             println!("\nTop 10 sizes:");
@@ -112,14 +413,360 @@ pub fn run(input: &str) -> Result<()> {
             top_sizes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
             for (size, count) in top_sizes.iter().take(10) {
                 let percentage = (*count as f64 / total_values as f64) * 100.0;
-                println!("  {} bytes: {} ({:.1}%)", size, count, percentage);
+                println!(
+                    "  {} bytes: {} ({:.1}%)",
+                    size_label(*size, buckets.value),
+                    count,
+                    percentage
+                );
             }
         }
     } else {
         println!("No value data in profile");
     }
 
+    // Key popularity
+    println!("\n─ Key Popularity ─");
+    println!("Unique keys: {}", report.unique_keys);
+    match (report.zipf, report.zipf_mandelbrot) {
+        (Some(zipf), Some(mandelbrot)) => {
+            println!("Zipf fit: s={:.3} (R²={:.3})", zipf.s, zipf.r_squared);
+            println!(
+                "Zipf-Mandelbrot fit: s={:.3}, q={:.2} (R²={:.3})",
+                mandelbrot.s, mandelbrot.q, mandelbrot.r_squared
+            );
+
+            if let Some(spec) = report.synthetic_spec(metadata.unique_connections) {
+                println!("\nSuggested synthetic spec (save as a file for replay --synthetic):");
+                for line in spec.to_string().lines() {
+                    println!("  {}", line);
+                }
+            }
+        }
+        _ => println!("Not enough distinct keys to fit a popularity model"),
+    }
+
+    // Hot keys, counted by heavy-hitters sketches
+    if !analysis.hot_keys.is_empty() {
+        println!("\n─ Hot Keys ─");
+        let share: f64 = analysis.hot_keys.iter().map(|hot| hot.share).sum();
+        println!(
+            "Top {} keys: {:.1}% of commands",
+            analysis.hot_keys.len(),
+            share * 100.0
+        );
+        for hot in &analysis.hot_keys {
+            let error = if hot.error > 0 {
+                format!(" (at most {} fewer)", hot.error)
+            } else {
+                String::new()
+            };
+            println!(
+                "  {:#018x}: {} ({:.1}%){}",
+                hot.key_hash,
+                hot.count,
+                hot.share * 100.0,
+                error
+            );
+        }
+        let mut commands: Vec<_> = analysis.hot_keys_by_command.iter().collect();
+        commands.sort_by_key(|(cmd_type, _)| **cmd_type);
+        println!("Hottest keys per command:");
+        for (cmd_type, hot_keys) in commands {
+            let keys: Vec<String> = hot_keys
+                .iter()
+                .take(3)
+                .map(|hot| format!("{:#018x} ({:.1}%)", hot.key_hash, hot.share * 100.0))
+                .collect();
+            println!("  {:?}: {}", cmd_type, keys.join(", "));
+        }
+    }
+
+    // Reuse distances: the hit ratio of an LRU cache of every size
+    let reuse = &report.reuse;
+    if reuse.accesses() > reuse.cold_accesses {
+        println!("\n─ Reuse Distance ─");
+        println!(
+            "Cold accesses: {} ({:.1}%)",
+            reuse.cold_accesses,
+            reuse.cold_accesses as f64 / reuse.accesses() as f64 * 100.0
+        );
+        println!("LRU hit ratio by cache size (keys):");
+        let largest = reuse.distances.last().map_or(1, |(bucket, _)| *bucket);
+        let mut keys = 1;
+        loop {
+            println!("  {:>12}: {:.1}%", keys, reuse.lru_hit_ratio(keys) * 100.0);
+            if keys >= largest {
+                break;
+            }
+            keys *= 4;
+        }
+
+        println!("Working set over time:");
+        let start = metadata.time_range.0;
+        for window in &reuse.working_sets {
+            println!(
+                "  {:>8.1}s-{:>8.1}s: {} keys, {}",
+                window.start.saturating_sub(start) as f64 / 1_000_000.0,
+                window.end.saturating_sub(start) as f64 / 1_000_000.0,
+                window.keys,
+                byte_size(window.bytes)
+            );
+        }
+    }
+
+    // Miss-ratio curve, by sampled LRU simulation
+    if let Some(curve) = &report.miss_ratio_curve {
+        println!("\n─ Miss-Ratio Curve ─");
+        println!(
+            "Simulated keys: {:.2}% ({} accesses)",
+            curve.sampling_rate * 100.0,
+            curve.accesses
+        );
+        println!("All keys fit in: {}", byte_size(curve.footprint));
+        println!("Predicted LRU hit ratio by cache size:");
+        for point in &curve.points {
+            println!(
+                "  {:>10}: {:.1}% hits, {:.1}% misses",
+                byte_size(point.cache_bytes),
+                point.hit_ratio * 100.0,
+                (1.0 - point.hit_ratio) * 100.0
+            );
+        }
+    }
+
+    // Namespaces: keys grouped by their (hashed) first segment
+    let mut namespaces: std::collections::HashMap<u32, (u64, std::collections::HashSet<u64>)> =
+        std::collections::HashMap::new();
+    for event in reader.events() {
+        if let Some([namespace, _, ..]) = event.key_segments.as_deref() {
+            let (events, keys) = namespaces.entry(*namespace).or_default();
+            *events += 1;
+            keys.insert(event.key_hash);
+        }
+    }
+    if !namespaces.is_empty() {
+        println!("\n─ Key Namespaces ─");
+        println!("Namespaces: {}", namespaces.len());
+        let mut top: Vec<_> = namespaces.iter().collect();
+        top.sort_by_key(|(_, (events, _))| std::cmp::Reverse(*events));
+        for (namespace, (events, keys)) in top.iter().take(10) {
+            let percentage = (*events as f64 / analysis.total_events as f64) * 100.0;
+            println!(
+                "  {:08x}: {} events ({:.1}%), {} keys",
+                namespace,
+                events,
+                percentage,
+                keys.len()
+            );
+        }
+    }
+
+    // Clients: commands per (hashed) client address, and how much of each
+    // client's traffic goes to the hottest keys
+    let mut clients: std::collections::HashMap<u32, (u64, std::collections::HashSet<u64>)> =
+        std::collections::HashMap::new();
+    for event in reader.events() {
+        if let Some(client_id) = event.client_id {
+            let (events, keys) = clients.entry(client_id).or_default();
+            *events += 1;
+            keys.insert(event.key_hash);
+        }
+    }
+    if !clients.is_empty() {
+        let hot: std::collections::HashSet<u64> = analysis
+            .hot_keys
+            .iter()
+            .take(HOT_KEYS)
+            .map(|hot| hot.key_hash)
+            .collect();
+        let mut hot_events: std::collections::HashMap<u32, u64> = std::collections::HashMap::new();
+        for event in reader.events() {
+            if let (Some(client_id), true) = (event.client_id, hot.contains(&event.key_hash)) {
+                *hot_events.entry(client_id).or_insert(0) += 1;
+            }
+        }
+
+        println!("\n─ Clients ─");
+        println!("Clients: {}", clients.len());
+        let mut top: Vec<_> = clients.iter().collect();
+        top.sort_by_key(|(_, (events, _))| std::cmp::Reverse(*events));
+        for (client_id, (events, keys)) in top.iter().take(10) {
+            let percentage = (*events as f64 / analysis.total_events as f64) * 100.0;
+            let hot_share = *hot_events.get(client_id).unwrap_or(&0) as f64 / *events as f64;
+            println!(
+                "  {:08x}: {} events ({:.1}%), {} keys, {:.1}% on the {} hottest keys",
+                client_id,
+                events,
+                percentage,
+                keys.len(),
+                hot_share * 100.0,
+                hot.len()
+            );
+        }
+    }
+
+    // Every connection, busiest first
+    if let Some(connections) = &report.connections {
+        println!("\n─ Connections ─");
+        println!("Connections: {}", connections.len());
+        for n in [1, 10] {
+            if connections.len() > n {
+                println!(
+                    "Busiest {}: {:.1}% of events",
+                    n,
+                    busiest_share(connections, n, analysis.total_events) * 100.0
+                );
+            }
+        }
+        for stats in connections {
+            let mut commands: Vec<_> = stats.per_command.iter().collect();
+            commands.sort_by_key(|(cmd_type, count)| (std::cmp::Reverse(**count), **cmd_type));
+            let mix: Vec<String> = commands
+                .iter()
+                .map(|(cmd_type, count)| {
+                    format!(
+                        "{:?} {:.0}%",
+                        cmd_type,
+                        **count as f64 / stats.events as f64 * 100.0
+                    )
+                })
+                .collect();
+            let rate = stats
+                .rate()
+                .map_or("-".to_string(), |rate| format!("{:.1}", rate));
+            let protocol = metadata
+                .connection_protocols
+                .get(&stats.conn_id)
+                .map_or(String::new(), |protocol| format!(", {:?}", protocol));
+            println!(
+                "  conn {}: {} events ({:.1}%), {:.1}s, {} ops/s, {} keys ({:.0}% shared){}",
+                stats.conn_id,
+                stats.events,
+                stats.events as f64 / analysis.total_events as f64 * 100.0,
+                stats.duration() as f64 / 1_000_000.0,
+                rate,
+                stats.keys,
+                stats.overlap() * 100.0,
+                protocol
+            );
+            println!("    {}", mix.join(", "));
+        }
+    }
+
     println!("\n");
 
     Ok(())
 }
+
+/// A recorded size, or the range of sizes its bucket holds
+/// Rows of a size chart before sizes are grouped by power of two
+const SIZE_CHART_ROWS: usize = 16;
+
+/// Bar chart of a size distribution, sizes labelled for `bucket`
+fn print_size_chart(sizes: &[(u32, u64)], bucket: Option<SizeBuckets>) {
+    let rows: Vec<(String, u64)> = charts::size_ranges(sizes, SIZE_CHART_ROWS)
+        .into_iter()
+        .map(|(low, high, count)| {
+            let label = if low == high {
+                format!("{} bytes", size_label(low, bucket))
+            } else {
+                format!("{}-{} bytes", low, high)
+            };
+            (label, count)
+        })
+        .collect();
+    for line in charts::bar_chart(&rows, charts::terminal_width()) {
+        println!("{}", line);
+    }
+}
+
+/// Sparklines of the throughput series, overall and per command, each
+/// labelled and followed by its peak
+fn print_throughput_chart(series: &ThroughputSeries) {
+    let mut lines: Vec<(String, Vec<f64>, String)> = Vec::new();
+    let rates = |count: &dyn Fn(&ThroughputBucket) -> u64| -> Vec<f64> {
+        series
+            .buckets
+            .iter()
+            .map(|bucket| series.rate(count(bucket)))
+            .collect()
+    };
+    let peak = |values: &[f64]| values.iter().copied().fold(0.0, f64::max);
+    let ops = rates(&|bucket| bucket.commands);
+    lines.push((
+        "ops/s".to_string(),
+        ops.clone(),
+        format!("{:.1}", peak(&ops)),
+    ));
+    let bytes = rates(&|bucket| bucket.bytes);
+    lines.push((
+        "bytes/s".to_string(),
+        bytes.clone(),
+        byte_size(peak(&bytes) as u64),
+    ));
+    for cmd_type in series.commands() {
+        let values = rates(&|bucket| *bucket.per_command.get(&cmd_type).unwrap_or(&0));
+        lines.push((
+            format!("{:?}/s", cmd_type),
+            values.clone(),
+            format!("{:.1}", peak(&values)),
+        ));
+    }
+
+    let label_width = lines
+        .iter()
+        .map(|(label, _, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    let peak_width = lines
+        .iter()
+        .map(|(_, _, peak)| peak.len())
+        .max()
+        .unwrap_or(0);
+    // "  label │line│ peak peak"
+    let width = charts::terminal_width()
+        .saturating_sub(2 + label_width + 2 + 2 + 6 + peak_width)
+        .max(10);
+    println!(
+        "{} intervals of {:?}{}",
+        series.buckets.len(),
+        series.interval,
+        if series.buckets.len() > width {
+            ", several to a character (the busiest shown)"
+        } else {
+            ""
+        }
+    );
+    for (label, values, peak) in &lines {
+        println!(
+            "  {:>label_width$} │{}│ peak {:>peak_width$}",
+            label,
+            charts::sparkline(values, width),
+            peak
+        );
+    }
+}
+
+/// `bytes` in the largest binary unit it has at least one of
+fn byte_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn size_label(size: u32, buckets: Option<SizeBuckets>) -> String {
+    match buckets {
+        Some(buckets) => buckets.label(size),
+        None => size.to_string(),
+    }
+}
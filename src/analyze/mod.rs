@@ -1,5 +1,8 @@
 //! Analyze command implementation
 
+pub mod charts;
 pub mod main;
+pub mod report;
 
-pub use main::run as run_analyze;
+pub use main::{run as run_analyze, run_summary, AnalyzeOptions, ReportFormat};
+pub use report::AnalyzeReport;
@@ -0,0 +1,259 @@
+//! Everything the full analyze report shows, as data
+//!
+//! The text report prints it section by section; `--output json` serializes
+//! it whole, for dashboards and CI checks.
+
+use super::main::{AnalyzeOptions, THROUGHPUT_ROWS, WORKING_SET_WINDOWS};
+use crate::convert::metadata_json;
+use crate::profile::{CommandType, Event, ProfileMetadata, ResponseStatus};
+use crate::replay::mrc::CURVE_POINTS;
+use crate::replay::zipf::{fit_zipf, fit_zipf_mandelbrot, ZipfFit};
+use crate::replay::{
+    analyze_reuse, connection_report, miss_ratio_curve, AnalysisResult, ConnectionStats,
+    ReuseAnalysis, SyntheticSpec, ThroughputSeries,
+};
+use anyhow::Result;
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalyzeReport {
+    pub file: String,
+    /// Metadata of the events analyzed, with the estimate of its key sketch
+    /// in place of the registers
+    pub metadata: serde_json::Value,
+    /// Factor the counts of a sampled profile scale up by
+    pub sampling_scale: f64,
+    pub analysis: AnalysisResult,
+    /// Exact number of distinct keys
+    pub unique_keys: u64,
+    pub zipf: Option<ZipfFit>,
+    pub zipf_mandelbrot: Option<ZipfFit>,
+    /// Responses paired with their commands during capture, if any were
+    pub responses: Option<ResponseSummary>,
+    /// Server latency, if any command has one
+    pub latency: Option<LatencyReport>,
+    pub throughput: ThroughputSeries,
+    pub reuse: ReuseAnalysis,
+    /// With `--mrc`
+    pub miss_ratio_curve: Option<CurveReport>,
+    /// With `--per-connection`
+    pub connections: Option<Vec<ConnectionStats>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ResponseSummary {
+    pub answered: u64,
+    pub gets_answered: u64,
+    pub get_hits: u64,
+    /// Share of the answered GETs that hit, if any was answered
+    pub get_hit_rate: Option<f64>,
+    pub errors: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LatencyReport {
+    pub overall: LatencySummary,
+    pub per_command: BTreeMap<CommandType, LatencySummary>,
+}
+
+/// Percentiles of server latencies, in µs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LatencySummary {
+    pub answered: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+impl LatencySummary {
+    fn of(histogram: &Histogram<u64>) -> Self {
+        LatencySummary {
+            answered: histogram.len(),
+            p50: histogram.value_at_quantile(0.5),
+            p90: histogram.value_at_quantile(0.9),
+            p99: histogram.value_at_quantile(0.99),
+            p999: histogram.value_at_quantile(0.999),
+            max: histogram.max(),
+        }
+    }
+}
+
+/// Predicted LRU hit ratio by cache size
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CurveReport {
+    /// Share of the keys simulated
+    pub sampling_rate: f64,
+    /// Accesses to the simulated keys
+    pub accesses: u64,
+    /// Estimated bytes of all the keys
+    pub footprint: u64,
+    pub points: Vec<CurvePoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CurvePoint {
+    pub cache_bytes: u64,
+    pub hit_ratio: f64,
+}
+
+impl AnalyzeReport {
+    /// Report of `events`, which `analysis` was computed from
+    pub fn new(
+        input: &str,
+        metadata: &ProfileMetadata,
+        events: &[Event],
+        analysis: AnalysisResult,
+        options: &AnalyzeOptions,
+    ) -> Result<Self> {
+        let duration = metadata.time_range.1.saturating_sub(metadata.time_range.0);
+        let interval = options
+            .interval
+            .unwrap_or_else(|| ThroughputSeries::auto_interval(duration, THROUGHPUT_ROWS));
+        let miss_ratio_curve = options.mrc.map(|sampling_rate| {
+            let curve = miss_ratio_curve(events, sampling_rate);
+            CurveReport {
+                sampling_rate,
+                accesses: curve.accesses,
+                footprint: curve.footprint,
+                points: curve
+                    .points(CURVE_POINTS)
+                    .into_iter()
+                    .map(|(cache_bytes, hit_ratio)| CurvePoint {
+                        cache_bytes,
+                        hit_ratio,
+                    })
+                    .collect(),
+            }
+        });
+        Ok(AnalyzeReport {
+            file: input.to_string(),
+            metadata: metadata_json(metadata)?,
+            sampling_scale: metadata.sampling_scale(),
+            unique_keys: analysis.key_frequencies.len() as u64,
+            zipf: fit_zipf(&analysis.key_frequencies),
+            zipf_mandelbrot: fit_zipf_mandelbrot(&analysis.key_frequencies),
+            analysis,
+            responses: ResponseSummary::of(events),
+            latency: LatencyReport::of(events),
+            throughput: ThroughputSeries::new(events, interval),
+            reuse: analyze_reuse(events, WORKING_SET_WINDOWS),
+            miss_ratio_curve,
+            connections: options.per_connection.then(|| connection_report(events)),
+        })
+    }
+
+    /// Synthetic workload with the command mix, most common sizes and
+    /// popularity of the profile, over `connections` connections; `None`
+    /// without a popularity fit
+    pub fn synthetic_spec(&self, connections: u32) -> Option<SyntheticSpec> {
+        let best = match (self.zipf, self.zipf_mandelbrot) {
+            (Some(zipf), Some(mandelbrot)) if mandelbrot.r_squared > zipf.r_squared => mandelbrot,
+            (Some(zipf), _) => zipf,
+            _ => return None,
+        };
+        let top_sizes = |distribution: &[(u32, u64)]| {
+            let mut sizes = distribution.to_vec();
+            sizes.sort_by_key(|(size, count)| (std::cmp::Reverse(*count), *size));
+            let mut sizes: Vec<u32> = sizes.iter().take(10).map(|(size, _)| *size).collect();
+            sizes.sort_unstable();
+            sizes
+        };
+        let defaults = SyntheticSpec::default();
+        let key_sizes = top_sizes(&self.analysis.key_size_distribution);
+        let value_sizes = top_sizes(&self.analysis.value_size_distribution);
+        let command_weights: HashMap<CommandType, u64> = self
+            .analysis
+            .command_distribution
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|(cmd_type, count)| (*cmd_type, *count))
+            .collect();
+        Some(SyntheticSpec {
+            connections: connections.clamp(1, u16::MAX as u32) as u16,
+            command_weights: if command_weights.is_empty() {
+                defaults.command_weights
+            } else {
+                command_weights
+            },
+            key_sizes: if key_sizes.is_empty() {
+                defaults.key_sizes
+            } else {
+                key_sizes
+            },
+            value_sizes: if value_sizes.is_empty() {
+                defaults.value_sizes
+            } else {
+                value_sizes
+            },
+            keyspace: Some(self.unique_keys.max(1)),
+            zipf_s: (best.s * 1000.0).round() / 1000.0,
+            zipf_q: (best.q * 100.0).round() / 100.0,
+            interarrival_us: self
+                .analysis
+                .mean_inter_arrival_micros()
+                .map(|mean| mean.round()),
+            ..defaults
+        })
+    }
+}
+
+impl ResponseSummary {
+    /// Summary of the responses of `events`, if any has one
+    pub fn of(events: &[Event]) -> Option<Self> {
+        let mut summary = ResponseSummary {
+            answered: 0,
+            gets_answered: 0,
+            get_hits: 0,
+            get_hit_rate: None,
+            errors: 0,
+        };
+        for event in events {
+            let Some(response) = event.response else {
+                continue;
+            };
+            summary.answered += 1;
+            if event.cmd_type == CommandType::Get {
+                summary.gets_answered += 1;
+                summary.get_hits += (response.status == ResponseStatus::Hit) as u64;
+            }
+            summary.errors += (response.status == ResponseStatus::Error) as u64;
+        }
+        if summary.gets_answered > 0 {
+            summary.get_hit_rate = Some(summary.get_hits as f64 / summary.gets_answered as f64);
+        }
+        (summary.answered > 0).then_some(summary)
+    }
+}
+
+impl LatencyReport {
+    fn of(events: &[Event]) -> Option<Self> {
+        let mut latencies: BTreeMap<CommandType, Histogram<u64>> = BTreeMap::new();
+        for event in events {
+            if let Some(latency) = event.latency {
+                latencies
+                    .entry(event.cmd_type)
+                    .or_insert_with(|| Histogram::new(3).expect("Failed to create histogram"))
+                    .record(latency as u64)
+                    .ok();
+            }
+        }
+        if latencies.is_empty() {
+            return None;
+        }
+        let mut overall = Histogram::<u64>::new(3).expect("Failed to create histogram");
+        for histogram in latencies.values() {
+            overall.add(histogram).ok();
+        }
+        Some(LatencyReport {
+            overall: LatencySummary::of(&overall),
+            per_command: latencies
+                .iter()
+                .map(|(cmd_type, histogram)| (*cmd_type, LatencySummary::of(histogram)))
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,438 @@
+//! Compare command: how the traffic of two profiles differs
+//!
+//! Each side of the comparison is the whole of one profile: its command mix,
+//! key and value sizes, keys, throughput over time and hit rate. Distances
+//! between distributions are total variation distances: half the sum of the
+//! differences in share, from 0 (identical) to 1 (disjoint).
+
+use crate::analyze::main::{ReportFormat, THROUGHPUT_ROWS};
+use crate::analyze::report::ResponseSummary;
+use crate::profile::{CommandType, Event};
+use crate::replay::{AnalysisResult, DistributionAnalyzer, ProfileReader, ThroughputSeries};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompareReport {
+    /// The two profiles, A then B
+    pub profiles: [ProfileSide; 2],
+    pub commands: Vec<CommandShare>,
+    /// Total variation distance between the command mixes
+    pub command_distance: f64,
+    pub key_sizes: SizeComparison,
+    pub value_sizes: SizeComparison,
+    pub keys: KeyOverlap,
+    pub throughput: ThroughputComparison,
+}
+
+/// What one profile of the comparison holds
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProfileSide {
+    pub file: String,
+    pub events: u64,
+    /// Microseconds between the first and last events
+    pub duration: u64,
+    /// Share of the answered GETs that hit, if any was answered
+    pub get_hit_rate: Option<f64>,
+}
+
+/// Share of the commands of each profile one command takes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct CommandShare {
+    pub cmd_type: CommandType,
+    pub a: f64,
+    pub b: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SizeStats {
+    pub mean: f64,
+    pub p50: u32,
+    pub p99: u32,
+    pub max: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SizeComparison {
+    /// None for a profile without sizes of the kind
+    pub a: Option<SizeStats>,
+    pub b: Option<SizeStats>,
+    /// Total variation distance between the distributions
+    pub distance: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct KeyOverlap {
+    pub keys_a: u64,
+    pub keys_b: u64,
+    pub shared: u64,
+    /// Shared keys over the keys of either profile
+    pub jaccard: f64,
+    /// Share of the commands of each profile on the shared keys
+    pub shared_traffic_a: f64,
+    pub shared_traffic_b: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ThroughputShape {
+    /// Commands per second over the capture
+    pub mean: f64,
+    /// Commands per second of the busiest interval
+    pub peak: f64,
+    /// Standard deviation of the rates of the intervals over their mean
+    pub variation: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ThroughputComparison {
+    /// Interval both series are cut into, in µs
+    pub interval: u64,
+    pub a: ThroughputShape,
+    pub b: ThroughputShape,
+    /// Pearson correlation of the rates of the intervals, aligned from the
+    /// start of each capture; None when either series is flat or a single
+    /// interval
+    pub correlation: Option<f64>,
+}
+
+/// Compare the traffic of profile `b` to that of profile `a`
+pub fn compare(a: &str, b: &str) -> Result<CompareReport> {
+    let readers = [
+        ProfileReader::new(a).context(format!("failed to open {}", a))?,
+        ProfileReader::new(b).context(format!("failed to open {}", b))?,
+    ];
+    let analyses = readers
+        .each_ref()
+        .map(|reader| DistributionAnalyzer::analyze(reader.events()));
+    let profiles = [(a, &readers[0]), (b, &readers[1])].map(|(file, reader)| {
+        let (first, last) = reader.metadata().time_range;
+        ProfileSide {
+            file: file.to_string(),
+            events: reader.events().len() as u64,
+            duration: last.saturating_sub(first),
+            get_hit_rate: ResponseSummary::of(reader.events())
+                .and_then(|responses| responses.get_hit_rate),
+        }
+    });
+
+    let mut commands: Vec<CommandType> = analyses
+        .iter()
+        .flat_map(|analysis| analysis.command_distribution.keys().copied())
+        .collect();
+    commands.sort();
+    commands.dedup();
+    let share = |analysis: &AnalysisResult, cmd_type: CommandType| {
+        *analysis.command_distribution.get(&cmd_type).unwrap_or(&0) as f64
+            / analysis.total_events.max(1) as f64
+    };
+    let commands: Vec<CommandShare> = commands
+        .into_iter()
+        .map(|cmd_type| CommandShare {
+            cmd_type,
+            a: share(&analyses[0], cmd_type),
+            b: share(&analyses[1], cmd_type),
+        })
+        .collect();
+    let command_distance = commands.iter().map(|c| (c.a - c.b).abs()).sum::<f64>() / 2.0;
+
+    let key_sizes = SizeComparison::of(
+        &analyses[0].key_size_distribution,
+        &analyses[1].key_size_distribution,
+    );
+    let value_sizes = SizeComparison::of(
+        &analyses[0].value_size_distribution,
+        &analyses[1].value_size_distribution,
+    );
+
+    let duration = profiles[0].duration.max(profiles[1].duration);
+    let interval = ThroughputSeries::auto_interval(duration, THROUGHPUT_ROWS);
+    let series = readers
+        .each_ref()
+        .map(|reader| ThroughputSeries::new(reader.events(), interval));
+
+    Ok(CompareReport {
+        profiles,
+        commands,
+        command_distance,
+        key_sizes,
+        value_sizes,
+        keys: KeyOverlap::of(readers[0].events(), readers[1].events()),
+        throughput: ThroughputComparison::of(&series),
+    })
+}
+
+impl SizeStats {
+    /// Statistics of a distribution of (size, count), if it has any size
+    fn of(distribution: &[(u32, u64)]) -> Option<Self> {
+        let mut sizes = distribution.to_vec();
+        sizes.sort_unstable();
+        let total: u64 = sizes.iter().map(|(_, count)| count).sum();
+        if total == 0 {
+            return None;
+        }
+        let quantile = |q: f64| {
+            let rank = ((total as f64 * q).ceil() as u64).max(1);
+            let mut seen = 0;
+            for &(size, count) in &sizes {
+                seen += count;
+                if seen >= rank {
+                    return size;
+                }
+            }
+            sizes[sizes.len() - 1].0
+        };
+        let sum: f64 = sizes
+            .iter()
+            .map(|&(size, count)| size as f64 * count as f64)
+            .sum();
+        Some(SizeStats {
+            mean: sum / total as f64,
+            p50: quantile(0.5),
+            p99: quantile(0.99),
+            max: sizes[sizes.len() - 1].0,
+        })
+    }
+}
+
+impl SizeComparison {
+    fn of(a: &[(u32, u64)], b: &[(u32, u64)]) -> Self {
+        SizeComparison {
+            a: SizeStats::of(a),
+            b: SizeStats::of(b),
+            distance: distance(a, b),
+        }
+    }
+}
+
+/// Total variation distance between two distributions of (value, count); 0
+/// when either is empty
+fn distance(a: &[(u32, u64)], b: &[(u32, u64)]) -> f64 {
+    let total = |distribution: &[(u32, u64)]| distribution.iter().map(|(_, c)| c).sum::<u64>();
+    let (total_a, total_b) = (total(a), total(b));
+    if total_a == 0 || total_b == 0 {
+        return 0.0;
+    }
+    let mut shares: BTreeMap<u32, f64> = BTreeMap::new();
+    for &(value, count) in a {
+        *shares.entry(value).or_insert(0.0) += count as f64 / total_a as f64;
+    }
+    for &(value, count) in b {
+        *shares.entry(value).or_insert(0.0) -= count as f64 / total_b as f64;
+    }
+    shares.values().map(|share| share.abs()).sum::<f64>() / 2.0
+}
+
+impl KeyOverlap {
+    fn of(a: &[Event], b: &[Event]) -> Self {
+        let counts = |events: &[Event]| {
+            let mut counts: HashMap<u64, u64> = HashMap::new();
+            for event in events {
+                *counts.entry(event.key_hash).or_insert(0) += 1;
+            }
+            counts
+        };
+        let (counts_a, counts_b) = (counts(a), counts(b));
+        let shared: HashSet<u64> = counts_a
+            .keys()
+            .filter(|key| counts_b.contains_key(key))
+            .copied()
+            .collect();
+        let shared_traffic = |counts: &HashMap<u64, u64>, events: usize| {
+            let on_shared: u64 = shared.iter().map(|key| counts[key]).sum();
+            on_shared as f64 / events.max(1) as f64
+        };
+        let union = counts_a.len() + counts_b.len() - shared.len();
+        KeyOverlap {
+            keys_a: counts_a.len() as u64,
+            keys_b: counts_b.len() as u64,
+            shared: shared.len() as u64,
+            jaccard: shared.len() as f64 / union.max(1) as f64,
+            shared_traffic_a: shared_traffic(&counts_a, a.len()),
+            shared_traffic_b: shared_traffic(&counts_b, b.len()),
+        }
+    }
+}
+
+impl ThroughputShape {
+    fn of(series: &ThroughputSeries) -> Self {
+        let rates: Vec<f64> = series
+            .buckets
+            .iter()
+            .map(|bucket| series.rate(bucket.commands))
+            .collect();
+        let mean = rates.iter().sum::<f64>() / rates.len().max(1) as f64;
+        let variance =
+            rates.iter().map(|rate| (rate - mean).powi(2)).sum::<f64>() / rates.len().max(1) as f64;
+        ThroughputShape {
+            mean,
+            peak: rates.iter().copied().fold(0.0, f64::max),
+            variation: if mean > 0.0 {
+                variance.sqrt() / mean
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl ThroughputComparison {
+    fn of(series: &[ThroughputSeries; 2]) -> Self {
+        let rates = series.each_ref().map(|series| {
+            series
+                .buckets
+                .iter()
+                .map(|bucket| bucket.commands as f64)
+                .collect::<Vec<f64>>()
+        });
+        ThroughputComparison {
+            interval: series[0].interval.as_micros() as u64,
+            a: ThroughputShape::of(&series[0]),
+            b: ThroughputShape::of(&series[1]),
+            correlation: correlation(&rates[0], &rates[1]),
+        }
+    }
+}
+
+/// Pearson correlation of the common prefix of `a` and `b`
+fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    let len = a.len().min(b.len());
+    if len < 2 {
+        return None;
+    }
+    let (a, b) = (&a[..len], &b[..len]);
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / len as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a).powi(2);
+        variance_b += (y - mean_b).powi(2);
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return None;
+    }
+    Some(covariance / (variance_a * variance_b).sqrt())
+}
+
+/// Change from `a` to `b`, relative to `a`
+fn relative(a: f64, b: f64) -> String {
+    if a == 0.0 {
+        return "n/a".to_string();
+    }
+    format!("{:+.1}%", (b / a - 1.0) * 100.0)
+}
+
+fn print_sizes(title: &str, sizes: &SizeComparison) {
+    println!("\n─ {} ─", title);
+    let (Some(a), Some(b)) = (sizes.a, sizes.b) else {
+        let missing = if sizes.a.is_none() { "A" } else { "B" };
+        println!("None recorded in {}", missing);
+        return;
+    };
+    println!(
+        "Mean: {:.1} → {:.1} bytes ({})",
+        a.mean,
+        b.mean,
+        relative(a.mean, b.mean)
+    );
+    println!("p50: {} → {} bytes", a.p50, b.p50);
+    println!("p99: {} → {} bytes", a.p99, b.p99);
+    println!("Max: {} → {} bytes", a.max, b.max);
+    println!("Distance: {:.3}", sizes.distance);
+}
+
+pub fn run(a: &str, b: &str, output: ReportFormat) -> Result<()> {
+    let report = compare(a, b)?;
+    if output == ReportFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("\n╔═══════════════════════════════════════════════════════╗");
+    println!("║            Profile Comparison Report                  ║");
+    println!("╚═══════════════════════════════════════════════════════╝\n");
+    for (name, side) in ["A", "B"].iter().zip(&report.profiles) {
+        println!(
+            "{}: {} ({} events over {:.2} seconds)",
+            name,
+            side.file,
+            side.events,
+            side.duration as f64 / 1_000_000.0
+        );
+    }
+
+    println!("\n─ Command Mix ─");
+    for share in &report.commands {
+        println!(
+            "{:?}: {:.1}% → {:.1}% ({:+.1} points)",
+            share.cmd_type,
+            share.a * 100.0,
+            share.b * 100.0,
+            (share.b - share.a) * 100.0
+        );
+    }
+    println!("Distance: {:.3}", report.command_distance);
+
+    print_sizes("Key Sizes", &report.key_sizes);
+    print_sizes("Value Sizes", &report.value_sizes);
+
+    let keys = report.keys;
+    println!("\n─ Key Overlap ─");
+    println!(
+        "Keys: {} in A, {} in B, {} in both",
+        keys.keys_a, keys.keys_b, keys.shared
+    );
+    println!("Jaccard similarity: {:.3}", keys.jaccard);
+    println!(
+        "Commands on shared keys: {:.1}% of A, {:.1}% of B",
+        keys.shared_traffic_a * 100.0,
+        keys.shared_traffic_b * 100.0
+    );
+    if keys.shared == 0 && keys.keys_a > 0 && keys.keys_b > 0 {
+        println!("No key in common: keys hashed with different salts never match");
+    }
+
+    let throughput = report.throughput;
+    println!("\n─ Throughput ─");
+    println!(
+        "Mean: {:.1} → {:.1} ops/s ({})",
+        throughput.a.mean,
+        throughput.b.mean,
+        relative(throughput.a.mean, throughput.b.mean)
+    );
+    println!(
+        "Peak: {:.1} → {:.1} ops/s ({})",
+        throughput.a.peak,
+        throughput.b.peak,
+        relative(throughput.a.peak, throughput.b.peak)
+    );
+    println!(
+        "Variation: {:.2} → {:.2} (standard deviation over mean, per {:?})",
+        throughput.a.variation,
+        throughput.b.variation,
+        std::time::Duration::from_micros(throughput.interval)
+    );
+    if let Some(correlation) = throughput.correlation {
+        println!("Correlation over time: {:.3}", correlation);
+    }
+
+    println!("\n─ Hit Rate ─");
+    match (
+        report.profiles[0].get_hit_rate,
+        report.profiles[1].get_hit_rate,
+    ) {
+        (Some(a), Some(b)) => println!(
+            "GET hit rate: {:.1}% → {:.1}% ({:+.1} points)",
+            a * 100.0,
+            b * 100.0,
+            (b - a) * 100.0
+        ),
+        _ => println!("GET responses were not recorded in both profiles"),
+    }
+    println!();
+
+    Ok(())
+}
@@ -0,0 +1,5 @@
+//! Compare command implementation
+
+pub mod main;
+
+pub use main::{compare, run as run_compare, CompareReport};
@@ -0,0 +1,114 @@
+//! Concat command: append profiles back to back into one longer workload
+
+use crate::merge::main::{combined_capture, shared};
+use crate::profile::{Compression, ProfileMetadata};
+use crate::record::ProfileWriter;
+use crate::replay::ProfileStreamer;
+use anyhow::{anyhow, Context, Result};
+use std::time::Duration;
+
+/// Outcome of a concatenation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConcatReport {
+    pub events: u64,
+    /// First and last timestamps of the concatenated profile
+    pub time_range: (u64, u64),
+}
+
+/// Append the events of `inputs` to a profile at `output`, in the order
+/// given. The timestamps of each input are shifted for it to start `gap`
+/// after the last event before it; connection ids are kept, so the
+/// connections of a replay carry on from one input to the next. The metadata
+/// is rebuilt from the concatenated events.
+pub fn concat(
+    inputs: &[String],
+    output: &str,
+    gap: Duration,
+    compression: Compression,
+) -> Result<ConcatReport> {
+    if inputs.len() < 2 {
+        return Err(anyhow!("concatenating needs at least two profiles"));
+    }
+    for input in inputs {
+        if std::fs::canonicalize(output).ok() == Some(std::fs::canonicalize(input)?) {
+            return Err(anyhow!(
+                "output must differ from the profiles being concatenated"
+            ));
+        }
+    }
+
+    let mut streamers = Vec::with_capacity(inputs.len());
+    for path in inputs {
+        streamers.push(ProfileStreamer::new(path).context(format!("failed to open {}", path))?);
+    }
+    let metadata: Vec<&ProfileMetadata> = streamers.iter().map(|s| s.metadata()).collect();
+    let connection_sampling = shared(&metadata, "connection sampling", |m| m.connection_sampling)?;
+    let event_sampling = shared(&metadata, "event sampling", |m| m.event_sampling)?;
+    let size_buckets = shared(&metadata, "size buckets", |m| m.size_buckets)?;
+
+    let mut writer = ProfileWriter::with_compression(output, compression)?;
+    if let Some(summary) = combined_capture(&metadata) {
+        writer.set_capture_summary(summary);
+    }
+    if let Some(rate) = connection_sampling {
+        writer.set_connection_sampling(rate);
+    }
+    if let Some(probability) = event_sampling {
+        writer.set_event_sampling(probability);
+    }
+    if let Some(buckets) = size_buckets {
+        writer.set_size_buckets(buckets);
+    }
+    if metadata.iter().any(|m| m.plaintext_keys) {
+        writer.set_plaintext_keys();
+    }
+    // A connection keeps the protocol it was first seen speaking
+    for metadata in metadata.iter().rev() {
+        for (&conn_id, &protocol) in &metadata.connection_protocols {
+            writer.record_protocol(conn_id, protocol);
+        }
+    }
+
+    let gap = gap.as_micros() as u64;
+    let mut first = None;
+    let mut last = None;
+    let mut events = 0u64;
+    for mut streamer in streamers {
+        let start = streamer.metadata().time_range.0;
+        // The first input keeps its timestamps
+        let cursor = last.map_or(start, |last: u64| last.saturating_add(gap));
+        while let Some(mut event) = streamer.next_event()? {
+            event.timestamp =
+                cursor.saturating_add_signed(event.timestamp.wrapping_sub(start) as i64);
+            first.get_or_insert(event.timestamp);
+            last = Some(last.map_or(event.timestamp, |last: u64| last.max(event.timestamp)));
+            writer.write_event(&event)?;
+            events += 1;
+        }
+    }
+    writer.finish()?;
+
+    Ok(ConcatReport {
+        events,
+        time_range: (first.unwrap_or(0), last.unwrap_or(0)),
+    })
+}
+
+pub fn run(inputs: &[String], output: &str, gap: Duration, compress: bool) -> Result<()> {
+    let compression = if compress {
+        Compression::Deflate
+    } else {
+        Compression::None
+    };
+    let report = concat(inputs, output, gap, compression)?;
+
+    println!(
+        "Concatenated {} events from {} profiles into {} ({:.2} seconds)",
+        report.events,
+        inputs.len(),
+        output,
+        report.time_range.1.saturating_sub(report.time_range.0) as f64 / 1_000_000.0
+    );
+
+    Ok(())
+}
@@ -0,0 +1,5 @@
+//! Concat command implementation
+
+pub mod main;
+
+pub use main::{concat, run as run_concat, ConcatReport};
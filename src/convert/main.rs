@@ -0,0 +1,172 @@
+//! Convert command: export the events of a profile as CSV, for analysis
+//! tools such as pandas or DuckDB
+
+use crate::inspect::main::{flag_names, status_name};
+use crate::profile::{Event, ProfileMetadata};
+use crate::replay::ProfileStreamer;
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Columns of the table, one row per event
+pub const COLUMNS: [&str; 15] = [
+    "timestamp",
+    "conn_id",
+    "command",
+    "key_hash",
+    "key_size",
+    "value_size",
+    "response_status",
+    "response_value_size",
+    "latency_us",
+    "ttl",
+    "server_port",
+    "client_id",
+    "key_segments",
+    "flags",
+    "key",
+];
+
+/// File format of the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    Csv,
+}
+
+impl TableFormat {
+    /// Format named by the extension of `path`
+    pub fn of_path(path: &str) -> Result<Self, String> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("");
+        extension.parse()
+    }
+}
+
+impl FromStr for TableFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(TableFormat::Csv),
+            _ => Err(format!("Invalid table format: '{}'. Use 'csv'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvertReport {
+    pub events: u64,
+    /// JSON file holding the metadata of the profile, next to the table
+    pub metadata_path: String,
+}
+
+/// Quote a CSV field when it has to be
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Fields of the row of `event`, in the order of `COLUMNS`; absent ones are
+/// empty
+pub fn row(event: &Event) -> Vec<String> {
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    vec![
+        event.timestamp.to_string(),
+        event.conn_id.to_string(),
+        event.cmd_type.name().to_string(),
+        // Hex strings, as 64-bit hashes overflow signed integer columns
+        format!("{:#018x}", event.key_hash),
+        event.key_size.to_string(),
+        optional(event.value_size.map(|size| size.to_string())),
+        optional(event.response.map(|r| status_name(r.status).to_string())),
+        optional(
+            event
+                .response
+                .and_then(|r| r.value_size)
+                .map(|size| size.to_string()),
+        ),
+        optional(event.latency.map(|latency| latency.to_string())),
+        optional(event.ttl.map(|ttl| ttl.to_string())),
+        optional(event.server_port.map(|port| port.to_string())),
+        optional(event.client_id.map(|client_id| client_id.to_string())),
+        optional(event.key_segments.as_ref().map(|segments| {
+            let segments: Vec<String> = segments.iter().map(|s| format!("{:#010x}", s)).collect();
+            segments.join(" ")
+        })),
+        flag_names(event).join(" "),
+        optional(
+            event
+                .key
+                .as_ref()
+                .map(|key| String::from_utf8_lossy(key).into_owned()),
+        ),
+    ]
+}
+
+/// `metadata` as JSON. The registers of the key sketch mean nothing to
+/// analysis tools: its estimate replaces them.
+pub fn metadata_json(metadata: &ProfileMetadata) -> serde_json::Result<serde_json::Value> {
+    let mut json = serde_json::to_value(metadata)?;
+    if let Some(object) = json.as_object_mut() {
+        object.remove("key_sketch");
+        if let Some(sketch) = &metadata.key_sketch {
+            object.insert("estimated_unique_keys".into(), sketch.estimate().into());
+        }
+    }
+    Ok(json)
+}
+
+/// Write the events of `input` to `output` as a table in `format`, one row
+/// per event in the order recorded, and the metadata of the profile as JSON
+/// to `<output>.json`
+pub fn convert(input: &str, output: &str, format: TableFormat) -> Result<ConvertReport> {
+    let mut streamer = ProfileStreamer::new(input).context(format!("failed to open {}", input))?;
+
+    let metadata_path = format!("{}.json", output);
+    let metadata = serde_json::to_string_pretty(&metadata_json(streamer.metadata())?)?;
+    std::fs::write(&metadata_path, metadata + "\n")
+        .context(format!("failed to write {}", metadata_path))?;
+
+    let file = File::create(output).context(format!("failed to create {}", output))?;
+    let mut out = BufWriter::new(file);
+    let mut events = 0;
+    match format {
+        TableFormat::Csv => {
+            writeln!(out, "{}", COLUMNS.join(","))?;
+            while let Some(event) = streamer.next_event()? {
+                let fields: Vec<String> = row(&event).iter().map(|f| csv_field(f)).collect();
+                writeln!(out, "{}", fields.join(","))?;
+                events += 1;
+            }
+        }
+    }
+    out.flush()
+        .map_err(|e| anyhow!("failed to write {}: {}", output, e))?;
+
+    Ok(ConvertReport {
+        events,
+        metadata_path,
+    })
+}
+
+pub fn run(input: &str, output: &str, format: Option<TableFormat>) -> Result<()> {
+    let format = match format {
+        Some(format) => format,
+        None => TableFormat::of_path(output).map_err(|e| anyhow!(e))?,
+    };
+    let report = convert(input, output, format)?;
+
+    println!(
+        "Converted {} events of {} to {} (metadata in {})",
+        report.events, input, output, report.metadata_path
+    );
+
+    Ok(())
+}
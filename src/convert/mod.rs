@@ -0,0 +1,5 @@
+//! Convert command implementation
+
+pub mod main;
+
+pub use main::{convert, metadata_json, run as run_convert, ConvertReport, TableFormat};
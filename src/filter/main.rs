@@ -0,0 +1,72 @@
+//! Filter command: slice a profile down to the events matching predicates
+
+use crate::filter::EventFilter;
+use crate::record::ProfileWriter;
+use crate::replay::ProfileStreamer;
+use anyhow::{anyhow, Context, Result};
+
+/// Outcome of a filter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterReport {
+    /// Events of the input
+    pub events_read: u64,
+    pub events_kept: u64,
+}
+
+/// Copy the events of `input` matching `filter` to a profile at `output`.
+/// The metadata is rebuilt from the events kept; the packet counters of the
+/// capture, which describe all of it, are not copied.
+pub fn filter(input: &str, output: &str, filter: &EventFilter) -> Result<FilterReport> {
+    if std::fs::canonicalize(output).ok() == Some(std::fs::canonicalize(input)?) {
+        return Err(anyhow!("output must differ from the input being filtered"));
+    }
+
+    // The time range is sought through the index; the rest is checked per event
+    let mut streamer = ProfileStreamer::with_range(input, filter.range)
+        .context(format!("failed to open {}", input))?;
+    let metadata = streamer.metadata().clone();
+
+    let mut writer = ProfileWriter::with_compression(output, metadata.compression)?;
+    if let Some(rate) = metadata.connection_sampling {
+        writer.set_connection_sampling(rate);
+    }
+    if let Some(probability) = metadata.event_sampling {
+        writer.set_event_sampling(probability);
+    }
+    if let Some(buckets) = metadata.size_buckets {
+        writer.set_size_buckets(buckets);
+    }
+    if metadata.plaintext_keys {
+        writer.set_plaintext_keys();
+    }
+    writer.set_sources(metadata.sources.clone());
+
+    let mut events_kept = 0u64;
+    while let Some(event) = streamer.next_event()? {
+        if !filter.matches(&event) {
+            continue;
+        }
+        if let Some(&protocol) = metadata.connection_protocols.get(&event.conn_id) {
+            writer.record_protocol(event.conn_id, protocol);
+        }
+        writer.write_event(&event)?;
+        events_kept += 1;
+    }
+    writer.finish()?;
+
+    Ok(FilterReport {
+        events_read: metadata.total_events,
+        events_kept,
+    })
+}
+
+pub fn run(input: &str, output: &str, event_filter: &EventFilter) -> Result<()> {
+    let report = filter(input, output, event_filter)?;
+
+    println!(
+        "Kept {} of {} events in {}",
+        report.events_kept, report.events_read, output
+    );
+
+    Ok(())
+}
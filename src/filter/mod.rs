@@ -0,0 +1,7 @@
+//! Filter command implementation
+
+pub mod main;
+pub mod predicate;
+
+pub use main::{filter, run as run_filter, FilterReport};
+pub use predicate::{CommandSet, ConnIds, EventFilter, KeyHashes, SizeRange};
@@ -0,0 +1,205 @@
+//! Predicates selecting the events a filtered profile keeps
+
+use crate::profile::{CommandType, Event, TimeRange};
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// Events to keep: those matching every predicate given
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    pub range: TimeRange,
+    pub commands: Option<CommandSet>,
+    pub conn_ids: Option<ConnIds>,
+    pub key_hashes: Option<KeyHashes>,
+    pub value_size: Option<SizeRange>,
+}
+
+impl EventFilter {
+    /// Whether `event`, already known to be in `range`, matches the other
+    /// predicates
+    pub fn matches(&self, event: &Event) -> bool {
+        self.commands
+            .as_ref()
+            .is_none_or(|commands| commands.0.contains(&event.cmd_type))
+            && self
+                .conn_ids
+                .as_ref()
+                .is_none_or(|conn_ids| conn_ids.contains(event.conn_id))
+            && self
+                .key_hashes
+                .as_ref()
+                .is_none_or(|hashes| hashes.0.contains(&event.key_hash))
+            && self.value_size.is_none_or(|range| {
+                // The value sent by a storage command, or returned by a GET hit
+                let size = event
+                    .value_size
+                    .or(event.response.and_then(|response| response.value_size));
+                size.is_some_and(|size| range.contains(size.get()))
+            })
+    }
+}
+
+/// Predicates given as one argument: space-separated `NAME=VALUE` pairs
+/// named as the options of the filter command (`commands=get conn-ids=3`)
+impl FromStr for EventFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = EventFilter::default();
+        for predicate in s.split_whitespace() {
+            let (name, value) = predicate.split_once('=').ok_or_else(|| {
+                format!(
+                    "Invalid filter: '{}'. Use space-separated NAME=VALUE predicates: from, to, commands, conn-ids, key-hashes or value-size",
+                    predicate
+                )
+            })?;
+            match name {
+                "from" => filter.range.from = Some(value.parse()?),
+                "to" => filter.range.to = Some(value.parse()?),
+                "commands" => filter.commands = Some(value.parse()?),
+                "conn-ids" => filter.conn_ids = Some(value.parse()?),
+                "key-hashes" => filter.key_hashes = Some(value.parse()?),
+                "value-size" => filter.value_size = Some(value.parse()?),
+                _ => {
+                    return Err(format!(
+                        "Invalid filter predicate: '{}'. Use from, to, commands, conn-ids, key-hashes or value-size",
+                        name
+                    ))
+                }
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// Commands given as a comma-separated list of their names (`get,set`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSet(pub HashSet<CommandType>);
+
+impl FromStr for CommandSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|name| {
+                let name = name.trim().to_lowercase();
+                CommandType::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "Invalid command: '{}'. Use get, set, delete, noop, add, replace, append, prepend, cas, incr, decr, touch or gat",
+                        name
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(CommandSet)
+    }
+}
+
+/// Connection ids given as a comma-separated list of ids and inclusive
+/// ranges (`3,10-20`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnIds {
+    ranges: Vec<(u16, u16)>,
+}
+
+impl ConnIds {
+    pub fn contains(&self, conn_id: u16) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(first, last)| first <= conn_id && conn_id <= last)
+    }
+}
+
+impl FromStr for ConnIds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_id = |id: &str| {
+            id.trim()
+                .parse::<u16>()
+                .map_err(|_| format!("Invalid connection id: '{}'", id.trim()))
+        };
+        let mut ranges = Vec::new();
+        for part in s.split(',') {
+            let range = match part.split_once('-') {
+                Some((first, last)) => (parse_id(first)?, parse_id(last)?),
+                None => {
+                    let id = parse_id(part)?;
+                    (id, id)
+                }
+            };
+            if range.0 > range.1 {
+                return Err(format!("Invalid connection id range: '{}'", part.trim()));
+            }
+            ranges.push(range);
+        }
+        Ok(ConnIds { ranges })
+    }
+}
+
+/// Key hashes given as a comma-separated list, in decimal or `0x` hex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyHashes(pub HashSet<u64>);
+
+impl FromStr for KeyHashes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|hash| {
+                let hash = hash.trim();
+                match hash.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => hash.parse(),
+                }
+                .map_err(|_| format!("Invalid key hash: '{}'. Use decimal or 0x hex", hash))
+            })
+            .collect::<Result<_, _>>()
+            .map(KeyHashes)
+    }
+}
+
+/// Inclusive range of sizes in bytes, `MIN-MAX` with either bound left out
+/// (`1k-64k`, `100-`, `-512`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl SizeRange {
+    pub fn contains(&self, size: u32) -> bool {
+        self.min <= size && size <= self.max
+    }
+}
+
+impl FromStr for SizeRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid size range: '{}'. Use MIN-MAX, MIN- or -MAX (e.g. 1k-64k)",
+                s
+            )
+        };
+        let (min, max) = s.split_once('-').ok_or_else(invalid)?;
+        let bound = |bound: &str, default: u32| {
+            if bound.trim().is_empty() {
+                return Ok(default);
+            }
+            crate::units::parse_count(bound)
+                .ok()
+                .and_then(|size| u32::try_from(size).ok())
+                .ok_or_else(invalid)
+        };
+        let range = SizeRange {
+            min: bound(min, 0)?,
+            max: bound(max, u32::MAX)?,
+        };
+        if range.min > range.max {
+            return Err(invalid());
+        }
+        Ok(range)
+    }
+}
@@ -0,0 +1,114 @@
+//! JSONL traces: one event per line, shaped as `inspect --format jsonl`
+//! prints them
+//!
+//! ```text
+//! {"timestamp":1700000000000000,"conn_id":3,"command":"set","key":"user:1","value_size":512,"ttl":60}
+//! ```
+//!
+//! `timestamp` (microseconds) and `command` are required. The key is either
+//! `key_hash` (a hex string or an integer) or a plaintext `key`, hashed with
+//! the anonymizer of the import and not kept; `key_size` defaults to the
+//! length of `key`. Every other field is optional, as in the dump.
+
+use crate::profile::{CommandType, Event, Flags, Response, ResponseStatus};
+use crate::record::Anonymizer;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::num::NonZero;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonEvent {
+    timestamp: u64,
+    #[serde(default)]
+    conn_id: u16,
+    command: String,
+    key_hash: Option<Value>,
+    key: Option<String>,
+    key_size: Option<u32>,
+    value_size: Option<u32>,
+    response: Option<JsonResponse>,
+    latency_us: Option<u32>,
+    ttl: Option<u32>,
+    server_port: Option<u16>,
+    client_id: Option<u32>,
+    key_segments: Option<Vec<u32>>,
+    #[serde(default)]
+    flags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonResponse {
+    status: String,
+    value_size: Option<u32>,
+}
+
+fn key_hash(value: &Value) -> Result<u64> {
+    let parsed = match value {
+        Value::Number(number) => number.as_u64(),
+        Value::String(hex) => hex
+            .strip_prefix("0x")
+            .and_then(|digits| u64::from_str_radix(digits, 16).ok()),
+        _ => None,
+    };
+    parsed.ok_or_else(|| anyhow!("invalid key_hash: {}", value))
+}
+
+/// The event of one line of a JSONL trace
+pub fn parse_event(line: &str, anonymizer: &Anonymizer) -> Result<Event> {
+    let json: JsonEvent = serde_json::from_str(line)?;
+    let cmd_type = CommandType::from_name(&json.command)
+        .ok_or_else(|| anyhow!("unknown command: '{}'", json.command))?;
+    let key_hash = match (&json.key_hash, &json.key) {
+        (Some(hash), _) => key_hash(hash)?,
+        (None, Some(key)) => anonymizer.hash_key(key.as_bytes()),
+        (None, None) => return Err(anyhow!("missing key_hash or key")),
+    };
+    let key_size = json
+        .key_size
+        .or(json.key.as_ref().map(|key| key.len() as u32))
+        .ok_or_else(|| anyhow!("missing key_size"))?;
+
+    let mut flags = Flags::empty();
+    for flag in &json.flags {
+        flags = match flag.as_str() {
+            "quiet" => flags.with_quiet(),
+            "value" => flags.with_value(),
+            _ => return Err(anyhow!("unknown flag: '{}'", flag)),
+        };
+    }
+    let response = json
+        .response
+        .map(|response| {
+            let status = match response.status.as_str() {
+                "hit" => ResponseStatus::Hit,
+                "miss" => ResponseStatus::Miss,
+                "error" => ResponseStatus::Error,
+                _ => return Err(anyhow!("unknown response status: '{}'", response.status)),
+            };
+            Ok(Response {
+                status,
+                value_size: response.value_size.and_then(NonZero::new),
+            })
+        })
+        .transpose()?;
+
+    Ok(Event {
+        timestamp: json.timestamp,
+        conn_id: json.conn_id,
+        cmd_type,
+        flags,
+        key_hash,
+        key_size,
+        value_size: json.value_size.and_then(NonZero::new),
+        response,
+        ttl: json.ttl.and_then(NonZero::new),
+        server_port: json.server_port.and_then(NonZero::new),
+        key_segments: json.key_segments,
+        key: None,
+        client_id: json.client_id,
+        latency: json.latency_us,
+    })
+}
@@ -0,0 +1,228 @@
+//! Import command: turn a public cache trace into a profile to replay
+
+use super::jsonl;
+use crate::profile::{CommandType, Compression, Event, Protocol};
+use crate::record::{read_key_file, Anonymizer, HashAlgorithm, ProfileWriter};
+use anyhow::{anyhow, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::num::NonZero;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Format of a trace to import
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// Twitter's production cache traces (github.com/twitter/cache-trace):
+    /// CSV of `timestamp,key,key_size,value_size,client_id,operation,ttl`,
+    /// with timestamps in seconds
+    TwitterCache,
+    /// One JSON event per line, as `inspect --format jsonl` prints them
+    Jsonl,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "twitter-cache" => Ok(TraceFormat::TwitterCache),
+            "jsonl" => Ok(TraceFormat::Jsonl),
+            _ => Err(format!(
+                "Invalid trace format: '{}'. Use 'twitter-cache' or 'jsonl'",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    pub events: u64,
+    /// Connections of the profile: for a Twitter trace, its clients
+    pub connections: u64,
+}
+
+/// One request of a Twitter cache trace
+struct TwitterRecord<'a> {
+    /// Seconds from the start of the trace
+    second: u64,
+    key: &'a str,
+    key_size: u32,
+    value_size: u32,
+    client: &'a str,
+    cmd_type: CommandType,
+    ttl: u32,
+}
+
+fn parse_twitter(line: &str) -> Result<TwitterRecord<'_>> {
+    let fields: Vec<&str> = line.split(',').collect();
+    let [second, key, key_size, value_size, client, operation, ttl] = fields[..] else {
+        return Err(anyhow!(
+            "expected 7 fields (timestamp,key,key_size,value_size,client_id,operation,ttl), found {}",
+            fields.len()
+        ));
+    };
+    let number = |name: &str, value: &str| {
+        value
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| anyhow!("invalid {}: '{}'", name, value))
+    };
+    let cmd_type = match operation.trim() {
+        // gets returns the CAS token alongside the value
+        "gets" => CommandType::Get,
+        name => CommandType::from_name(name)
+            .filter(|cmd_type| *cmd_type != CommandType::Noop)
+            .ok_or_else(|| anyhow!("unknown operation: '{}'", name))?,
+    };
+    Ok(TwitterRecord {
+        second: number("timestamp", second)?,
+        key,
+        key_size: number("key size", key_size)?.min(u32::MAX as u64) as u32,
+        value_size: number("value size", value_size)?.min(u32::MAX as u64) as u32,
+        client,
+        cmd_type,
+        ttl: number("TTL", ttl)?.min(u32::MAX as u64) as u32,
+    })
+}
+
+/// Events of one second of the trace, spread evenly over it as they are
+/// written
+struct Second {
+    second: u64,
+    events: Vec<Event>,
+}
+
+impl Second {
+    fn flush(&mut self, writer: &mut ProfileWriter) -> Result<()> {
+        let count = self.events.len() as u64;
+        for (i, mut event) in self.events.drain(..).enumerate() {
+            event.timestamp = self.second * 1_000_000 + i as u64 * 1_000_000 / count;
+            writer.write_event(&event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the requests of the trace at `input` to the profile `output`, keys
+/// hashed with `anonymizer`. Each client of a Twitter trace becomes a
+/// connection (ids wrap past 65536 clients), and the requests of each second
+/// of the trace are spread evenly over it; JSONL events are written as given.
+pub fn import(
+    input: &str,
+    output: &str,
+    format: TraceFormat,
+    anonymizer: &Anonymizer,
+    compression: Compression,
+) -> Result<ImportReport> {
+    let file = File::open(input).context(format!("failed to open {}", input))?;
+    let mut writer = ProfileWriter::with_compression(output, compression)?;
+    let mut clients = HashMap::new();
+    let mut connections = HashSet::new();
+    let mut second = Second {
+        second: 0,
+        events: Vec::new(),
+    };
+    let mut events = 0;
+
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.context(format!("failed to read {}", input))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let at_line = |e: anyhow::Error| anyhow!("{} line {}: {}", input, number + 1, e);
+        if format == TraceFormat::Jsonl {
+            let event = jsonl::parse_event(&line, anonymizer).map_err(at_line)?;
+            connections.insert(event.conn_id);
+            writer.write_event(&event)?;
+            events += 1;
+            continue;
+        }
+        let record = parse_twitter(&line).map_err(at_line)?;
+
+        let next = clients.len() as u64;
+        let rank = *clients.entry(record.client.to_string()).or_insert(next);
+        let conn_id = rank as u16;
+        if rank == next && rank <= u16::MAX as u64 {
+            writer.record_protocol(conn_id, Protocol::Ascii);
+        }
+        connections.insert(conn_id);
+
+        let event = Event {
+            // The trace gives the size of the object, sent by storage commands only
+            value_size: NonZero::new(record.value_size).filter(|_| record.cmd_type.has_value()),
+            ttl: NonZero::new(record.ttl),
+            client_id: Some(anonymizer.hash_key(record.client.as_bytes()) as u32),
+            ..Event::new(
+                0,
+                conn_id,
+                record.cmd_type,
+                anonymizer.hash_key(record.key.as_bytes()),
+                record.key_size,
+            )
+        };
+        if record.second != second.second {
+            second.flush(&mut writer)?;
+            second.second = record.second;
+        }
+        second.events.push(event);
+        events += 1;
+    }
+    second.flush(&mut writer)?;
+    writer.finish()?;
+
+    Ok(ImportReport {
+        events,
+        connections: connections.len() as u64,
+    })
+}
+
+/// How the keys of an imported trace are anonymized, as when recording
+#[derive(Debug, Clone, Default)]
+pub struct ImportKeying {
+    /// Salt of the keyed hash; the current time when neither it nor a key
+    /// file is given
+    pub salt: Option<u64>,
+    pub key_file: Option<String>,
+    pub algorithm: HashAlgorithm,
+}
+
+impl ImportKeying {
+    pub fn anonymizer(&self) -> Result<Anonymizer> {
+        if let Some(path) = &self.key_file {
+            return Ok(Anonymizer::with_key(self.algorithm, &read_key_file(path)?));
+        }
+        let salt = self.salt.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        });
+        Ok(Anonymizer::with_salt(self.algorithm, salt))
+    }
+}
+
+pub fn run(
+    input: &str,
+    output: &str,
+    format: TraceFormat,
+    keying: &ImportKeying,
+    compress: bool,
+) -> Result<()> {
+    let anonymizer = keying.anonymizer()?;
+    let compression = if compress {
+        Compression::Deflate
+    } else {
+        Compression::None
+    };
+    let report = import(input, output, format, &anonymizer, compression)?;
+
+    println!(
+        "Imported {} events on {} connections from {} into {}",
+        report.events, report.connections, input, output
+    );
+
+    Ok(())
+}
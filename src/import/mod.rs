@@ -0,0 +1,6 @@
+//! Import command implementation
+
+pub mod jsonl;
+pub mod main;
+
+pub use main::{import, run as run_import, ImportKeying, ImportReport, TraceFormat};
@@ -0,0 +1,243 @@
+//! Inspect command: print the events of a profile, one per line
+
+use crate::filter::EventFilter;
+use crate::profile::{Event, ResponseStatus};
+use crate::replay::ProfileStreamer;
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::str::FromStr;
+
+/// How events are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `NAME=VALUE` fields of the event, as far as it has them
+    Text,
+    /// One JSON object per event
+    Jsonl,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(Format::Text),
+            "jsonl" => Ok(Format::Jsonl),
+            _ => Err(format!("Invalid format: '{}'. Use 'text' or 'jsonl'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Text => write!(f, "text"),
+            Format::Jsonl => write!(f, "jsonl"),
+        }
+    }
+}
+
+/// Which of the matching events are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Window {
+    #[default]
+    All,
+    /// The first N
+    Head(usize),
+    /// The last N
+    Tail(usize),
+}
+
+/// One line describing `event`
+pub fn format_event(event: &Event, format: Format) -> String {
+    match format {
+        Format::Text => text(event),
+        Format::Jsonl => jsonl(event).to_string(),
+    }
+}
+
+pub fn status_name(status: ResponseStatus) -> &'static str {
+    match status {
+        ResponseStatus::Hit => "hit",
+        ResponseStatus::Miss => "miss",
+        ResponseStatus::Error => "error",
+    }
+}
+
+pub fn flag_names(event: &Event) -> Vec<&'static str> {
+    let mut flags = Vec::new();
+    if event.flags.has_quiet() {
+        flags.push("quiet");
+    }
+    if event.flags.has_value() {
+        flags.push("value");
+    }
+    flags
+}
+
+/// Time of day (UTC) of a timestamp, to the microsecond
+fn time_of_day(timestamp: u64) -> String {
+    let secs = timestamp / 1_000_000 % 86_400;
+    format!(
+        "{:02}:{:02}:{:02}.{:06}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        timestamp % 1_000_000
+    )
+}
+
+fn text(event: &Event) -> String {
+    let mut line = format!(
+        "{} conn={} {} key={:#018x} key_size={}",
+        time_of_day(event.timestamp),
+        event.conn_id,
+        event.cmd_type.name(),
+        event.key_hash,
+        event.key_size
+    );
+    let mut field = |name: &str, value: String| {
+        line.push(' ');
+        line.push_str(name);
+        line.push('=');
+        line.push_str(&value);
+    };
+    if let Some(size) = event.value_size {
+        field("value_size", size.to_string());
+    }
+    if let Some(response) = event.response {
+        let status = status_name(response.status);
+        match response.value_size {
+            Some(size) => field("response", format!("{}:{}", status, size)),
+            None => field("response", status.to_string()),
+        }
+    }
+    if let Some(latency) = event.latency {
+        field("latency", format!("{}us", latency));
+    }
+    if let Some(ttl) = event.ttl {
+        field("ttl", format!("{}s", ttl));
+    }
+    if let Some(port) = event.server_port {
+        field("port", port.to_string());
+    }
+    if let Some(client_id) = event.client_id {
+        field("client", format!("{:#010x}", client_id));
+    }
+    if let Some(segments) = &event.key_segments {
+        let segments: Vec<String> = segments.iter().map(|s| format!("{:#010x}", s)).collect();
+        field("segments", segments.join(","));
+    }
+    let flags = flag_names(event);
+    if !flags.is_empty() {
+        field("flags", flags.join(","));
+    }
+    if let Some(key) = &event.key {
+        field(
+            "key",
+            String::from_utf8_lossy(key).escape_debug().to_string(),
+        );
+    }
+    line
+}
+
+fn jsonl(event: &Event) -> Value {
+    let mut object = Map::new();
+    object.insert("timestamp".into(), json!(event.timestamp));
+    object.insert("conn_id".into(), json!(event.conn_id));
+    object.insert("command".into(), json!(event.cmd_type.name()));
+    // Hex strings, as 64-bit integers do not survive every JSON parser
+    object.insert(
+        "key_hash".into(),
+        json!(format!("{:#018x}", event.key_hash)),
+    );
+    object.insert("key_size".into(), json!(event.key_size));
+    if let Some(size) = event.value_size {
+        object.insert("value_size".into(), json!(size.get()));
+    }
+    if let Some(response) = event.response {
+        object.insert(
+            "response".into(),
+            json!({
+                "status": status_name(response.status),
+                "value_size": response.value_size.map(|size| size.get()),
+            }),
+        );
+    }
+    if let Some(latency) = event.latency {
+        object.insert("latency_us".into(), json!(latency));
+    }
+    if let Some(ttl) = event.ttl {
+        object.insert("ttl".into(), json!(ttl.get()));
+    }
+    if let Some(port) = event.server_port {
+        object.insert("server_port".into(), json!(port.get()));
+    }
+    if let Some(client_id) = event.client_id {
+        object.insert("client_id".into(), json!(client_id));
+    }
+    if let Some(segments) = &event.key_segments {
+        object.insert("key_segments".into(), json!(segments));
+    }
+    let flags = flag_names(event);
+    if !flags.is_empty() {
+        object.insert("flags".into(), json!(flags));
+    }
+    if let Some(key) = &event.key {
+        object.insert("key".into(), json!(String::from_utf8_lossy(key)));
+    }
+    Value::Object(object)
+}
+
+/// Write a line, false once stdout is closed (piped into `head`)
+fn emit(out: &mut impl Write, line: &str) -> Result<bool> {
+    match writeln!(out, "{}", line) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Print the events of `input` matching `filter` to stdout, as far as
+/// `window` selects them
+pub fn run(input: &str, format: Format, window: Window, filter: &EventFilter) -> Result<()> {
+    let mut streamer = ProfileStreamer::with_range(input, filter.range)?;
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let mut tail = VecDeque::new();
+    let mut printed = 0;
+
+    while let Some(event) = streamer.next_event()? {
+        if !filter.matches(&event) {
+            continue;
+        }
+        match window {
+            Window::Head(n) if printed == n => return Ok(()),
+            Window::All | Window::Head(_) => {
+                if !emit(&mut out, &format_event(&event, format))? {
+                    return Ok(());
+                }
+                printed += 1;
+            }
+            Window::Tail(n) => {
+                if tail.len() == n {
+                    tail.pop_front();
+                }
+                if n > 0 {
+                    tail.push_back(event);
+                }
+            }
+        }
+    }
+    for event in &tail {
+        if !emit(&mut out, &format_event(event, format))? {
+            return Ok(());
+        }
+    }
+    match out.flush() {
+        Err(e) if e.kind() != ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,5 @@
+//! Inspect command implementation
+
+pub mod main;
+
+pub use main::{format_event, run as run_inspect, Format, Window};
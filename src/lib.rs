@@ -1,6 +1,22 @@
 pub mod analyze;
+pub mod compare;
+pub mod concat;
+pub mod convert;
+pub mod filter;
+pub mod import;
+pub mod inspect;
+pub mod logging;
+pub mod merge;
 pub mod profile;
 pub mod record;
+pub mod rekey;
+pub mod repair;
 pub mod replay;
+pub mod sample;
+pub mod split;
+#[cfg(feature = "testutil")]
+pub mod testutil;
+pub mod units;
+pub mod validate;
 
-pub use profile::{CommandType, Event, Flags, ProfileMetadata};
+pub use profile::{CommandType, Event, Flags, ProfileMetadata, Protocol};
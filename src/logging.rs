@@ -0,0 +1,73 @@
+//! Log file output with size-based rotation
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Tracing target of progress and final report lines, which stay on the
+/// console when the rest of the logs go to a file
+pub const REPORT_TARGET: &str = "membench::report";
+
+/// Number of rotated files kept next to the active log (`<path>.1` is the newest)
+pub const ROTATED_FILES_KEPT: usize = 5;
+
+/// Appends to a log file, moving it aside once it grows past `max_bytes`
+pub struct RotatingFileWriter {
+    path: PathBuf,
+    max_bytes: Option<u64>,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFileWriter {
+    pub fn new(path: &str, max_bytes: Option<u64>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+
+        Ok(RotatingFileWriter {
+            path: PathBuf::from(path),
+            max_bytes,
+            file,
+            written,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        // Shift <path>.N to <path>.N+1, dropping the oldest
+        for index in (1..ROTATED_FILES_KEPT).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, self.rotated_path(1))?;
+
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(max_bytes) = self.max_bytes {
+            if self.written > 0 && self.written + buf.len() as u64 > max_bytes {
+                self.rotate()?;
+            }
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -1,9 +1,37 @@
 use clap::{Parser, Subcommand};
-use membench::analyze::run_analyze;
-use membench::record::run_record;
-use membench::replay::{run_replay, ProtocolMode};
+use membench::analyze::{run_analyze, run_summary, AnalyzeOptions, ReportFormat};
+use membench::compare::run_compare;
+use membench::concat::run_concat;
+use membench::convert::{run_convert, TableFormat};
+use membench::filter::{run_filter, CommandSet, ConnIds, EventFilter, KeyHashes, SizeRange};
+use membench::import::{run_import, ImportKeying, TraceFormat};
+use membench::inspect::{run_inspect, Format, Window};
+use membench::logging::{RotatingFileWriter, REPORT_TARGET};
+use membench::merge::run_merge;
+use membench::profile::{
+    Compression, SampleRate, SizeBucketing, SizeBuckets, TimeBound, TimeRange,
+};
+use membench::record::{
+    run_with_options, CaptureLimits, FragmentLimits, HashAlgorithm, Passthrough, Ports,
+    RecordOptions, Rotation,
+};
+use membench::rekey::run_rekey;
+use membench::repair::run_repair;
+use membench::replay::client::MAX_KEY_LEN;
+use membench::replay::{
+    run_coordinator, run_find_max, run_replay, run_worker, ConnectLimits, IterationMode, LoopMode,
+    Pacing, ProtocolMode, Ramp, RateSearch, ReconnectPolicy, ReplayOptions, SyntheticSpec,
+    TtlPolicy, ValueFill, Workload,
+};
+use membench::sample::{run_sample, SampleBy};
+use membench::split::{run_split, SplitBy};
+use membench::units::{parse_count, parse_duration, parse_probability};
+use membench::validate::run_validate;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing_subscriber::filter::{filter_fn, LevelFilter};
+use tracing_subscriber::prelude::*;
 
 #[derive(Parser)]
 #[command(name = "membench")]
@@ -13,43 +41,475 @@ struct Cli {
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
 
+    /// Write logs to this file; the console then only shows progress, reports and warnings
+    #[arg(long, global = true, value_name = "FILE")]
+    log_file: Option<String>,
+
+    /// Rotate the log file once it exceeds this size in bytes (e.g. 100M)
+    #[arg(long, global = true, requires = "log_file", value_parser = parse_count)]
+    log_max_size: Option<u64>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+// Parsed once at startup: the size of the Replay variant does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     /// Capture memcache traffic from network interface or PCAP file
     Record {
-        /// Network interface (e.g., eth0, lo0) or PCAP file path to capture from
+        /// Network interface (e.g., eth0, lo0), PCAP file path, or - to read a capture from stdin
         source: String,
         /// Output profile file path
         output: String,
+        /// Server port to capture; several are given as a list and ranges (e.g. 11211-11218,11300)
         #[arg(short, long, default_value = "11211")]
-        port: u16,
+        port: Ports,
         #[arg(short, long)]
         salt: Option<u64>,
+        /// Read the anonymization key from this file (mode 600) instead of --salt
+        #[arg(long, value_name = "PATH", conflicts_with = "salt")]
+        key_file: Option<String>,
+        /// Keyed hash anonymizing keys: siphash13, hmac-sha256 or blake3
+        #[arg(long, value_name = "ALGORITHM", default_value = "siphash13")]
+        anonymizer: HashAlgorithm,
+        /// Stop recording after this long (e.g. 30s, 10m)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        duration: Option<Duration>,
+        /// Stop recording once this many events are written (e.g. 1M)
+        #[arg(long, value_name = "N", value_parser = parse_count)]
+        max_events: Option<u64>,
+        /// Stop recording once the profile holds this many bytes of events (e.g. 500M)
+        #[arg(long, value_name = "BYTES", value_parser = parse_count)]
+        max_size: Option<u64>,
+        /// Start a new numbered profile this often (e.g. 15m)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        rotate_every: Option<Duration>,
+        /// Start a new numbered profile once one holds this many bytes of events (e.g. 1G)
+        #[arg(long, value_name = "BYTES", value_parser = parse_count)]
+        rotate_size: Option<u64>,
+        /// Write deflate-compressed profiles
+        #[arg(long)]
+        compress: bool,
+        /// Give up on a fragmented IP datagram this long after its first fragment
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "30s")]
+        fragment_timeout: Duration,
+        /// Bytes of IP fragments held for reassembly at most (e.g. 64M)
+        #[arg(long, value_name = "BYTES", value_parser = parse_count, default_value = "64M")]
+        fragment_memory: u64,
+        /// Only capture traffic also matching this BPF expression (e.g. "net 10.1.0.0/16")
+        #[arg(long, value_name = "EXPR")]
+        filter: Option<String>,
+        /// Record only this share of the connections, chosen by a hash of their 4-tuple (e.g. 1/16)
+        #[arg(long, value_name = "N/M")]
+        sample_connections: Option<SampleRate>,
+        /// Record each command with this probability (e.g. 0.01 or 1%)
+        #[arg(long, value_name = "P", value_parser = parse_probability)]
+        sample_events: Option<f64>,
+        /// Also hash each segment of keys split on this character (e.g. :), keeping their namespaces
+        #[arg(long, value_name = "CHAR", value_parser = parse_delimiter)]
+        key_delimiter: Option<u8>,
+        /// Round key sizes up into buckets: a width in bytes (e.g. 16) or pow2
+        #[arg(long, value_name = "BUCKETS")]
+        key_buckets: Option<SizeBuckets>,
+        /// Round value sizes up into buckets: a width in bytes (e.g. 1024) or pow2
+        #[arg(long, value_name = "BUCKETS")]
+        value_buckets: Option<SizeBuckets>,
+        /// Store keys in plaintext, for debugging only: the profile then reveals every key
+        #[arg(long, conflicts_with = "key_buckets")]
+        no_anonymize: bool,
     },
     /// Analyze a captured profile file
     Analyze {
         /// Profile file to analyze
         file: String,
+        /// Analyze from this time on: HH:MM[:SS] (UTC) or an offset into the capture (e.g. 90s)
+        #[arg(long, value_name = "TIME")]
+        from: Option<TimeBound>,
+        /// Analyze up to this time: HH:MM[:SS] (UTC) or an offset into the capture
+        #[arg(long, value_name = "TIME")]
+        to: Option<TimeBound>,
+        /// Print only what the metadata holds (totals, estimated unique keys...),
+        /// without reading the events
+        #[arg(long, conflicts_with_all = ["from", "to", "mrc", "interval", "throughput_csv", "per_connection", "output", "charts"])]
+        summary: bool,
+        /// Also predict the hit ratio of LRU caches of a range of sizes, simulating
+        /// a sampled share of the keys (SHARDS)
+        #[arg(long)]
+        mrc: bool,
+        /// Share of the keys the miss-ratio curve simulates (0-1); raise it for
+        /// profiles of few keys
+        #[arg(long, default_value = "0.01", value_parser = parse_probability, requires = "mrc")]
+        mrc_sampling: f64,
+        /// Interval of the throughput series (e.g. 1s, 1m); by default the shortest
+        /// of 1s, 10s, 1m, 10m, 1h and 1d giving at most 60 rows
+        #[arg(long, value_parser = parse_duration)]
+        interval: Option<Duration>,
+        /// Also write the throughput series to this CSV file
+        #[arg(long, value_name = "FILE")]
+        throughput_csv: Option<String>,
+        /// Also report each connection: its events, command mix, duration, rate and
+        /// the share of its keys other connections use too
+        #[arg(long)]
+        per_connection: bool,
+        /// Report format: text, or json (the whole report as one document, for
+        /// dashboards and CI checks)
+        #[arg(long, default_value = "text")]
+        output: ReportFormat,
+        /// Draw the key and value size distributions as bar charts and the throughput
+        /// series as sparklines, to the width of the terminal
+        #[arg(long)]
+        charts: bool,
+    },
+    /// Report how the traffic of a profile differs from that of another
+    Compare {
+        /// Profile compared against (A), e.g. last week's
+        a: String,
+        /// Profile compared (B)
+        b: String,
+        /// Report format: text or json
+        #[arg(long, default_value = "text")]
+        output: ReportFormat,
+    },
+    /// Print the events of a profile, one per line
+    Inspect {
+        /// Profile file to inspect
+        file: String,
+        /// Output format: text or jsonl (one JSON object per event)
+        #[arg(long, default_value = "text")]
+        format: Format,
+        /// Print only the first N matching events
+        #[arg(long, value_name = "N", conflicts_with = "tail")]
+        head: Option<usize>,
+        /// Print only the last N matching events
+        #[arg(long, value_name = "N")]
+        tail: Option<usize>,
+        /// Print only events matching these space-separated predicates, named as the
+        /// options of filter (e.g. "from=10:00 commands=get conn-ids=3-5")
+        #[arg(long, value_name = "PREDICATES")]
+        filter: Option<EventFilter>,
+    },
+    /// Export the events of a profile as CSV, one row per event
+    Convert {
+        /// Profile file to convert
+        input: String,
+        /// Output table file; its extension gives the format unless --format does
+        output: String,
+        /// Table format: csv
+        #[arg(long)]
+        format: Option<TableFormat>,
+    },
+    /// Check the integrity of a profile, reporting its first inconsistency
+    Validate {
+        /// Profile file to validate
+        file: String,
+    },
+    /// Recover the events of a truncated profile into a valid one
+    Repair {
+        /// Damaged profile file
+        input: String,
+        /// Output profile file path
+        output: String,
+    },
+    /// Re-anonymize the keys of a profile with a fresh salt
+    Rekey {
+        /// Profile file to rekey
+        input: String,
+        /// Output profile file path
+        output: String,
+        /// Salt of the second keyed hash applied to every key hash
+        #[arg(short, long)]
+        salt: u64,
+    },
+    /// Write a profile keeping only the events matching every predicate given
+    Filter {
+        /// Profile file to filter
+        input: String,
+        /// Output profile file path
+        output: String,
+        /// Keep events from this time on: HH:MM[:SS] (UTC) or an offset into the capture (e.g. 90s)
+        #[arg(long, value_name = "TIME")]
+        from: Option<TimeBound>,
+        /// Keep events up to this time: HH:MM[:SS] (UTC) or an offset into the capture
+        #[arg(long, value_name = "TIME")]
+        to: Option<TimeBound>,
+        /// Keep these commands, comma-separated (e.g. get,set)
+        #[arg(long, value_name = "COMMANDS")]
+        commands: Option<CommandSet>,
+        /// Keep these connections: ids and inclusive ranges (e.g. 3,10-20)
+        #[arg(long, value_name = "IDS")]
+        conn_ids: Option<ConnIds>,
+        /// Keep these key hashes, comma-separated in decimal or 0x hex
+        #[arg(long, value_name = "HASHES")]
+        key_hashes: Option<KeyHashes>,
+        /// Keep events whose value (sent, or returned by a hit) is this many bytes (e.g. 1k-64k, 100-)
+        #[arg(long, value_name = "MIN-MAX", allow_hyphen_values = true)]
+        value_size: Option<SizeRange>,
+    },
+    /// Combine profiles captured at the same time into one
+    Merge {
+        /// Profiles to merge
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<String>,
+        /// Output profile file path
+        #[arg(short, long)]
+        output: String,
+        /// Write a deflate-compressed profile
+        #[arg(long)]
+        compress: bool,
+    },
+    /// Turn a public cache trace into a profile to replay
+    Import {
+        /// Trace file to import
+        input: String,
+        /// Output profile file path
+        output: String,
+        /// Format of the trace: twitter-cache (CSV of Twitter's production cache traces)
+        /// or jsonl (one JSON event per line, as inspect --format jsonl prints them)
+        #[arg(long, default_value = "twitter-cache")]
+        format: TraceFormat,
+        /// Salt of the keyed hash anonymizing the keys of the trace
+        #[arg(short, long)]
+        salt: Option<u64>,
+        /// Read the anonymization key from this file (mode 600) instead of --salt
+        #[arg(long, value_name = "PATH", conflicts_with = "salt")]
+        key_file: Option<String>,
+        /// Keyed hash anonymizing keys: siphash13, hmac-sha256 or blake3
+        #[arg(long, value_name = "ALGORITHM", default_value = "siphash13")]
+        anonymizer: HashAlgorithm,
+        /// Write a deflate-compressed profile
+        #[arg(long)]
+        compress: bool,
+    },
+    /// Downsample a profile to a representative share of its events
+    Sample {
+        /// Profile file to sample
+        input: String,
+        /// Output profile file path
+        output: String,
+        /// Share of the events to keep (e.g. 0.1 or 10%)
+        #[arg(long, value_parser = parse_probability)]
+        ratio: f64,
+        /// Draw keys (every event on a kept key is kept) or events independently
+        #[arg(long, default_value = "keys")]
+        by: SampleBy,
+        /// Seed of the draw; the same seed keeps the same keys or events
+        #[arg(long, default_value = "0")]
+        seed: u64,
+    },
+    /// Append profiles back to back, each shifted to start where the one before ended
+    Concat {
+        /// Profiles to concatenate, in order, then the output profile file path
+        #[arg(required = true, num_args = 3.., value_name = "PROFILES")]
+        files: Vec<String>,
+        /// Pause between the end of a profile and the start of the next (e.g. 5s)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "0s")]
+        gap: Duration,
+        /// Write a deflate-compressed profile
+        #[arg(long)]
+        compress: bool,
+    },
+    /// Divide a profile into shards, by connection or by time window
+    Split {
+        /// Profile file to split
+        input: String,
+        /// Output profile file path, numbered for each shard (split.profile: split.0000.profile...)
+        output: String,
+        /// Split into this many shards, each connection in the one its id hashes to
+        #[arg(
+            long,
+            value_name = "N",
+            required_unless_present = "window",
+            conflicts_with = "window"
+        )]
+        connections: Option<u32>,
+        /// Split into one shard per window of the capture (e.g. 10m)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        window: Option<Duration>,
     },
     /// Replay traffic from profile against target server
     Replay {
         /// Profile file to replay
-        file: String,
+        #[arg(
+            required_unless_present_any = ["synthetic", "coordinator"],
+            conflicts_with = "synthetic"
+        )]
+        file: Option<String>,
+        /// Server to replay against: host:port, or unix:PATH for a Unix domain socket.
+        /// Several comma-separated servers share the keys by consistent hashing
         #[arg(short, long, default_value = "localhost:11211")]
         target: String,
+        /// A/B replay: mirror every command to this server (A) and --target-b,
+        /// and compare their latencies
+        #[arg(
+            long,
+            value_name = "ADDR",
+            requires = "target_b",
+            conflicts_with = "target"
+        )]
+        target_a: Option<String>,
+        /// Second server of an A/B replay (B), compared against --target-a
+        #[arg(
+            long,
+            value_name = "ADDR",
+            requires = "target_a",
+            conflicts_with = "target"
+        )]
+        target_b: Option<String>,
         /// Loop mode: once, infinite, or times:N
         #[arg(short, long, default_value = "once")]
         loop_mode: String,
-        /// Protocol mode: ascii (old) or meta (new)
-        #[arg(long, default_value = "meta")]
-        protocol_mode: String,
+        /// Between loop iterations: warm (keep connections open) or reset (reconnect)
+        #[arg(long, default_value = "warm", conflicts_with = "synthetic")]
+        iteration_mode: String,
+        /// Pacing: none (as fast as possible) or recorded (at the recorded timestamps)
+        #[arg(long, default_value = "none", conflicts_with = "synthetic")]
+        pacing: String,
+        /// Replay from this time of the capture on: HH:MM[:SS] (UTC) or an offset (e.g. 90s)
+        #[arg(long, value_name = "TIME", conflicts_with = "synthetic")]
+        from: Option<TimeBound>,
+        /// Replay up to this time of the capture: HH:MM[:SS] (UTC) or an offset
+        #[arg(long, value_name = "TIME", conflicts_with = "synthetic")]
+        to: Option<TimeBound>,
+        /// Divide recorded gaps by this factor with --pacing recorded (2.0 replays twice as fast)
+        #[arg(
+            long,
+            value_name = "FACTOR",
+            default_value = "1.0",
+            conflicts_with = "synthetic"
+        )]
+        speed: f64,
+        /// Protocol mode: ascii (old), meta (new) or binary [default: recorded per connection, else meta]
+        #[arg(long)]
+        protocol_mode: Option<String>,
         /// Export statistics to JSON file
         #[arg(long, value_name = "FILE")]
         stats_json: Option<String>,
+        /// Generate traffic from a synthetic workload spec instead of a profile
+        #[arg(long, value_name = "SPEC")]
+        synthetic: Option<String>,
+        /// Event rate in ops/sec (e.g. 50000, 100k): paces synthetic traffic, caps
+        /// profile replay; unbounded if omitted
+        #[arg(long, value_parser = parse_count)]
+        rate: Option<u64>,
+        /// Stop the replay after this duration (e.g. 30s, 10m); a synthetic run
+        /// without it runs until Ctrl+C
+        #[arg(long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+        /// Seed for synthetic traffic sampling (random and logged if omitted)
+        #[arg(long, conflicts_with = "file")]
+        seed: Option<u64>,
+        /// Send a keepalive (mn/version) after a connection idles this long (e.g. 30s);
+        /// keep it below the server's idle timeout
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        keepalive: Option<Duration>,
+        /// Drop mutating commands (set, delete) for a safe shadow replay
+        #[arg(long)]
+        read_only: bool,
+        /// SET the key of every GET miss with a representative value size
+        #[arg(long, conflicts_with = "read_only")]
+        populate_misses: bool,
+        /// Send every command on schedule without waiting for earlier responses,
+        /// timing latency from the scheduled send
+        #[arg(long, conflicts_with = "populate_misses")]
+        open_loop: bool,
+        /// Commands each connection keeps in flight [default: 1, or 64 with --open-loop]
+        #[arg(long, value_name = "N", conflicts_with = "populate_misses")]
+        pipeline_depth: Option<usize>,
+        /// Send the GETs a connection has queued within this window of the first
+        /// (e.g. 1ms) as one multi-key request
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        coalesce_gets: Option<Duration>,
+        /// Compare every response with the one recorded in the profile
+        #[arg(long, conflicts_with = "synthetic")]
+        validate_responses: bool,
+        /// Mismatching commands listed by --validate-responses, earliest first
+        #[arg(
+            long,
+            value_name = "N",
+            default_value = "10",
+            requires = "validate_responses"
+        )]
+        show_mismatches: usize,
+        /// Grow the rate from zero to --rate over this initial period (e.g. 60s),
+        /// left out of the statistics
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with = "find_max")]
+        ramp_up: Option<Duration>,
+        /// Bring the rate back to zero over the last part of --duration (e.g. 30s),
+        /// left out of the statistics
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with = "find_max")]
+        ramp_down: Option<Duration>,
+        /// Leave operations completed during this initial period out of the statistics (e.g. 30s)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        warmup: Option<Duration>,
+        /// Accept live commands (rate N, pause, resume, stats) on this Unix socket
+        #[arg(long, value_name = "PATH")]
+        control_socket: Option<String>,
+        /// Connection attempts in flight at once while setting up the replay
+        #[arg(long, value_name = "N", default_value = "64")]
+        connect_parallelism: usize,
+        /// Abort unless every connection is established within this time
+        #[arg(long, value_name = "DURATION", default_value = "30s", value_parser = parse_duration)]
+        connect_timeout: Duration,
+        /// Reconnect attempts of a connection after an error before it fails
+        #[arg(long, value_name = "N", default_value = "5")]
+        reconnect_retries: u32,
+        /// Wait before the first reconnect attempt, doubling for each next one (up to 5s)
+        #[arg(long, value_name = "DURATION", default_value = "100ms", value_parser = parse_duration)]
+        reconnect_backoff: Duration,
+        /// Values written: repeat (one byte repeated), random, compressible:RATIO
+        /// (compressing to about RATIO of their size) or pattern (derived from the key)
+        #[arg(long, value_name = "FILL", default_value = "repeat")]
+        value_fill: ValueFill,
+        /// Write values derived from their key, and check every GET hit carries the
+        /// value written for its key
+        #[arg(long, conflicts_with_all = ["value_fill", "read_only"])]
+        verify: bool,
+        /// Prepend this to every key sent (e.g. myrun1:), isolating the keys of this run
+        #[arg(long, value_name = "PREFIX", value_parser = parse_key_prefix)]
+        key_prefix: Option<String>,
+        /// Count a command as timed out once its response is this late (e.g. 500ms),
+        /// and replace its connection
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        request_timeout: Option<Duration>,
+        /// Fail a connection on its first error instead of reconnecting
+        #[arg(long, conflicts_with_all = ["reconnect_retries", "reconnect_backoff"])]
+        fail_fast: bool,
+        /// Search for the highest synthetic rate meeting --sla-p99, starting from --rate
+        #[arg(
+            long,
+            requires_all = ["synthetic", "sla_p99"],
+            conflicts_with_all = ["duration", "control_socket"]
+        )]
+        find_max: bool,
+        /// Highest acceptable p99 latency for --find-max (e.g. 2ms)
+        #[arg(long, value_name = "DURATION", requires = "find_max", value_parser = parse_duration)]
+        sla_p99: Option<Duration>,
+        /// How long --find-max offers each rate
+        #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = parse_duration)]
+        step_duration: Duration,
+        /// Coordinate a distributed replay: wait for --workers workers on this
+        /// address (e.g. 0.0.0.0:7878), start them together and report on them all
+        #[arg(
+            long,
+            value_name = "ADDR",
+            requires = "workers",
+            conflicts_with_all = ["file", "synthetic", "worker"]
+        )]
+        coordinator: Option<String>,
+        /// Workers the coordinator waits for
+        #[arg(long, value_name = "N", requires = "coordinator")]
+        workers: Option<usize>,
+        /// Replay a share of the connections as a worker of the coordinator at ADDR
+        #[arg(long, value_name = "ADDR", conflicts_with = "find_max")]
+        worker: Option<String>,
+        /// Multiply recorded TTLs by this factor (0 replays without expiration)
+        #[arg(long, value_name = "FACTOR", default_value = "1.0")]
+        ttl_scale: f64,
+        /// Cap replayed TTLs at this duration (e.g. 1h)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        ttl_max: Option<Duration>,
     },
 }
 
@@ -65,11 +525,10 @@ async fn main() {
         _ => tracing::Level::TRACE,
     };
 
-    tracing_subscriber::fmt()
-        .with_max_level(log_level)
-        .with_target(cli.verbose >= 2) // Show module targets in debug+ mode
-        .with_level(true) // Always show log level
-        .init();
+    if let Err(e) = init_logging(log_level, cli.verbose, &cli.log_file, cli.log_max_size) {
+        eprintln!("Failed to open log file: {}", e);
+        std::process::exit(1);
+    }
 
     match cli.command {
         Commands::Record {
@@ -77,27 +536,287 @@ async fn main() {
             output,
             port,
             salt,
+            key_file,
+            anonymizer,
+            duration,
+            max_events,
+            max_size,
+            rotate_every,
+            rotate_size,
+            compress,
+            fragment_timeout,
+            fragment_memory,
+            filter,
+            sample_connections,
+            sample_events,
+            key_delimiter,
+            key_buckets,
+            value_buckets,
+            no_anonymize,
         } => {
-            if let Err(e) = run_record(&source, port, &output, salt) {
+            let options = RecordOptions {
+                salt,
+                key_file,
+                anonymizer,
+                limits: CaptureLimits {
+                    duration,
+                    max_events,
+                    max_size,
+                },
+                rotation: Rotation {
+                    every: rotate_every,
+                    size: rotate_size,
+                },
+                compression: if compress {
+                    Compression::Deflate
+                } else {
+                    Compression::None
+                },
+                fragments: FragmentLimits {
+                    timeout: fragment_timeout,
+                    max_bytes: fragment_memory as usize,
+                },
+                filter,
+                sample_connections,
+                sample_events,
+                key_delimiter,
+                size_buckets: SizeBucketing {
+                    key: key_buckets,
+                    value: value_buckets,
+                },
+                plaintext_keys: no_anonymize,
+            };
+            if let Err(e) = run_with_options(&source, &port, &output, &options, &mut Passthrough) {
                 eprintln!("Record error: {}", e);
                 std::process::exit(1);
             }
         }
-        Commands::Analyze { file } => {
-            if let Err(e) = run_analyze(&file) {
+        Commands::Analyze {
+            file,
+            from,
+            to,
+            summary,
+            mrc,
+            mrc_sampling,
+            interval,
+            throughput_csv,
+            per_connection,
+            output,
+            charts,
+        } => {
+            let result = if summary {
+                run_summary(&file)
+            } else {
+                let options = AnalyzeOptions {
+                    range: TimeRange { from, to },
+                    output,
+                    mrc: mrc.then_some(mrc_sampling),
+                    interval,
+                    throughput_csv,
+                    per_connection,
+                    charts,
+                };
+                run_analyze(&file, &options)
+            };
+            if let Err(e) = result {
                 eprintln!("Analyze error: {}", e);
                 std::process::exit(1);
             }
         }
+        Commands::Compare { a, b, output } => {
+            if let Err(e) = run_compare(&a, &b, output) {
+                eprintln!("Compare error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Inspect {
+            file,
+            format,
+            head,
+            tail,
+            filter,
+        } => {
+            let window = match (head, tail) {
+                (Some(n), _) => Window::Head(n),
+                (None, Some(n)) => Window::Tail(n),
+                (None, None) => Window::All,
+            };
+            if let Err(e) = run_inspect(&file, format, window, &filter.unwrap_or_default()) {
+                eprintln!("Inspect error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Convert {
+            input,
+            output,
+            format,
+        } => {
+            if let Err(e) = run_convert(&input, &output, format) {
+                eprintln!("Convert error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Validate { file } => {
+            if let Err(e) = run_validate(&file) {
+                eprintln!("Validate error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Repair { input, output } => {
+            if let Err(e) = run_repair(&input, &output) {
+                eprintln!("Repair error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Rekey {
+            input,
+            output,
+            salt,
+        } => {
+            if let Err(e) = run_rekey(&input, &output, salt) {
+                eprintln!("Rekey error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Filter {
+            input,
+            output,
+            from,
+            to,
+            commands,
+            conn_ids,
+            key_hashes,
+            value_size,
+        } => {
+            let event_filter = EventFilter {
+                range: TimeRange { from, to },
+                commands,
+                conn_ids,
+                key_hashes,
+                value_size,
+            };
+            if let Err(e) = run_filter(&input, &output, &event_filter) {
+                eprintln!("Filter error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Merge {
+            inputs,
+            output,
+            compress,
+        } => {
+            if let Err(e) = run_merge(&inputs, &output, compress) {
+                eprintln!("Merge error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Import {
+            input,
+            output,
+            format,
+            salt,
+            key_file,
+            anonymizer,
+            compress,
+        } => {
+            let keying = ImportKeying {
+                salt,
+                key_file,
+                algorithm: anonymizer,
+            };
+            if let Err(e) = run_import(&input, &output, format, &keying, compress) {
+                eprintln!("Import error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Sample {
+            input,
+            output,
+            ratio,
+            by,
+            seed,
+        } => {
+            if let Err(e) = run_sample(&input, &output, ratio, by, seed) {
+                eprintln!("Sample error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Concat {
+            mut files,
+            gap,
+            compress,
+        } => {
+            let output = files.pop().expect("clap requires three files");
+            if let Err(e) = run_concat(&files, &output, gap, compress) {
+                eprintln!("Concat error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Commands::Split {
+            input,
+            output,
+            connections,
+            window,
+        } => {
+            let by = match (connections, window) {
+                (Some(shards), _) => SplitBy::Connection { shards },
+                (None, Some(window)) => SplitBy::Time { window },
+                (None, None) => unreachable!("clap requires --connections or --window"),
+            };
+            if let Err(e) = run_split(&input, &output, by) {
+                eprintln!("Split error: {}", e);
+                std::process::exit(1);
+            }
+        }
         Commands::Replay {
             file,
             target,
+            target_a,
+            target_b,
             loop_mode,
+            iteration_mode,
+            pacing,
+            from,
+            to,
+            speed,
             protocol_mode,
             stats_json,
+            synthetic,
+            rate,
+            duration,
+            seed,
+            keepalive,
+            read_only,
+            populate_misses,
+            open_loop,
+            pipeline_depth,
+            coalesce_gets,
+            validate_responses,
+            show_mismatches,
+            ramp_up,
+            ramp_down,
+            warmup,
+            control_socket,
+            connect_parallelism,
+            connect_timeout,
+            reconnect_retries,
+            reconnect_backoff,
+            key_prefix,
+            value_fill,
+            verify,
+            request_timeout,
+            fail_fast,
+            find_max,
+            sla_p99,
+            step_duration,
+            coordinator,
+            workers,
+            worker,
+            ttl_scale,
+            ttl_max,
         } => {
             // Parse protocol mode at CLI boundary
-            let protocol_mode = match protocol_mode.parse::<ProtocolMode>() {
+            let protocol_mode = match protocol_mode.map(|m| m.parse::<ProtocolMode>()).transpose() {
                 Ok(mode) => mode,
                 Err(e) => {
                     eprintln!("Replay error: {}", e);
@@ -105,6 +824,34 @@ async fn main() {
                 }
             };
 
+            let iteration_mode = match iteration_mode.parse::<IterationMode>() {
+                Ok(mode) => mode,
+                Err(e) => {
+                    eprintln!("Replay error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            if workers == Some(0) {
+                eprintln!("Replay error: --workers must be at least 1");
+                std::process::exit(1);
+            }
+
+            if pipeline_depth == Some(0) {
+                eprintln!("Replay error: --pipeline-depth must be at least 1");
+                std::process::exit(1);
+            }
+
+            if !(speed > 0.0 && speed.is_finite()) {
+                eprintln!("Replay error: --speed must be a positive number");
+                std::process::exit(1);
+            }
+
+            if !(ttl_scale >= 0.0 && ttl_scale.is_finite()) {
+                eprintln!("Replay error: --ttl-scale must be a non-negative number");
+                std::process::exit(1);
+            }
+
             let should_exit = Arc::new(AtomicBool::new(false));
             let should_exit_clone = Arc::clone(&should_exit);
 
@@ -116,19 +863,176 @@ async fn main() {
                 eprintln!("Failed to set signal handler: {}", e);
             });
 
-            if let Err(e) = run_replay(
-                &file,
-                &target,
-                &loop_mode,
+            if let Some(listen) = coordinator {
+                let workers = workers.expect("clap requires --workers with --coordinator");
+                if let Err(e) = run_coordinator(&listen, workers, stats_json, should_exit).await {
+                    eprintln!("Replay error: {}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
+
+            let workload = match (file, synthetic) {
+                (_, Some(spec_path)) => match SyntheticSpec::from_file(&spec_path) {
+                    Ok(spec) => Workload::Synthetic {
+                        spec,
+                        rate,
+                        duration,
+                        seed,
+                    },
+                    Err(e) => {
+                        eprintln!("Replay error: {:#}", e);
+                        std::process::exit(1);
+                    }
+                },
+                (Some(path), None) => {
+                    match (loop_mode.parse::<LoopMode>(), pacing.parse::<Pacing>()) {
+                        (Ok(_), Ok(Pacing::None)) if speed != 1.0 => {
+                            eprintln!("Replay error: --speed requires --pacing recorded");
+                            std::process::exit(1);
+                        }
+                        (Ok(loop_mode), Ok(pacing)) => Workload::Profile {
+                            path,
+                            range: TimeRange { from, to },
+                            loop_mode,
+                            rate,
+                            duration,
+                            pacing: match pacing {
+                                Pacing::Recorded { .. } => Pacing::Recorded { speed },
+                                Pacing::None => Pacing::None,
+                            },
+                        },
+                        (Err(e), _) | (_, Err(e)) => {
+                            eprintln!("Replay error: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                (None, None) => unreachable!("clap requires a profile or --synthetic"),
+            };
+
+            let options = ReplayOptions {
                 protocol_mode,
-                should_exit,
-                stats_json.as_deref(),
-            )
-            .await
-            {
+                keepalive,
+                read_only,
+                populate_misses,
+                warmup,
+                connect: ConnectLimits {
+                    parallelism: connect_parallelism,
+                    timeout: connect_timeout,
+                },
+                iteration_mode,
+                control_socket,
+                stats_json,
+                ttl: TtlPolicy {
+                    scale: ttl_scale,
+                    max: ttl_max,
+                },
+                pipeline_depth: pipeline_depth.unwrap_or(if open_loop { 64 } else { 1 }),
+                open_loop,
+                coalesce_gets,
+                validate: validate_responses.then_some(show_mismatches),
+                mirror: target_a.is_some(),
+                ramp: Ramp {
+                    up: ramp_up,
+                    down: ramp_down,
+                },
+                reconnect: if fail_fast {
+                    ReconnectPolicy::fail_fast()
+                } else {
+                    ReconnectPolicy {
+                        max_retries: reconnect_retries,
+                        backoff: reconnect_backoff,
+                    }
+                },
+                request_timeout,
+                key_prefix,
+                value_fill,
+                verify,
+            };
+            let target = match (target_a, target_b) {
+                (Some(a), Some(b)) => format!("{},{}", a, b),
+                _ => target,
+            };
+
+            let result = match workload {
+                Workload::Synthetic { spec, seed, .. } if find_max => {
+                    let sla_p99 = sla_p99.expect("clap requires --sla-p99 with --find-max");
+                    let search = RateSearch::new(
+                        sla_p99,
+                        rate.unwrap_or(RateSearch::DEFAULT_START_RATE),
+                        step_duration,
+                    );
+                    run_find_max(spec, seed, &target, search, options, should_exit).await
+                }
+                workload => match worker {
+                    Some(coordinator) => {
+                        run_worker(workload, &target, options, &coordinator, should_exit).await
+                    }
+                    None => run_replay(workload, &target, options, should_exit).await,
+                },
+            };
+            if let Err(e) = result {
                 eprintln!("Replay error: {}", e);
                 std::process::exit(1);
             }
         }
     }
 }
+
+/// A key delimiter: one ASCII character
+fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(format!(
+            "invalid delimiter: '{}'. Use one ASCII character",
+            s
+        )),
+    }
+}
+
+/// A key prefix: printable ASCII without spaces, leaving room for the key
+fn parse_key_prefix(s: &str) -> Result<String, String> {
+    if s.is_empty() || s.len() >= MAX_KEY_LEN || !s.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(format!(
+            "invalid key prefix: '{}'. Use up to {} printable ASCII characters without spaces",
+            s,
+            MAX_KEY_LEN - 1
+        ));
+    }
+    Ok(s.to_string())
+}
+
+fn init_logging(
+    log_level: tracing::Level,
+    verbose: u8,
+    log_file: &Option<String>,
+    log_max_size: Option<u64>,
+) -> std::io::Result<()> {
+    let Some(path) = log_file else {
+        tracing_subscriber::fmt()
+            .with_max_level(log_level)
+            .with_target(verbose >= 2) // Show module targets in debug+ mode
+            .with_level(true) // Always show log level
+            .init();
+        return Ok(());
+    };
+
+    // The file gets everything (at least info); the console keeps reports and warnings
+    let writer = RotatingFileWriter::new(path, log_max_size)?;
+    let file_layer = tracing_subscriber::fmt::layer()
+        .with_writer(Mutex::new(writer))
+        .with_ansi(false)
+        .with_filter(LevelFilter::from_level(log_level.max(tracing::Level::INFO)));
+    let console_layer = tracing_subscriber::fmt::layer()
+        .with_target(false)
+        .with_filter(filter_fn(|metadata| {
+            metadata.target() == REPORT_TARGET || *metadata.level() <= tracing::Level::WARN
+        }));
+
+    tracing_subscriber::registry()
+        .with(file_layer)
+        .with(console_layer)
+        .init();
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//! Merge command: combine profiles captured side by side into one
+
+use crate::profile::{CaptureSummary, Compression, Event, ProfileMetadata, ProfileSource};
+use crate::record::ProfileWriter;
+use crate::replay::ProfileStreamer;
+use anyhow::{anyhow, Context, Result};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+use std::path::Path;
+
+/// Most connections a profile can tell apart
+const MAX_CONNECTIONS: u32 = u16::MAX as u32 + 1;
+
+/// Outcome of a merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeReport {
+    pub events_merged: u64,
+    /// Connections of the merged profile, over every input
+    pub connections: u32,
+}
+
+/// A profile being merged
+struct Input {
+    streamer: ProfileStreamer,
+    /// Connection id in the merged profile of each of its connections
+    conn_ids: HashMap<u16, u16>,
+    /// Its next event, once read
+    next: Option<Event>,
+}
+
+/// Interleave the events of `inputs` by timestamp into a profile at `output`.
+/// The connections of each input are renumbered after those of the inputs
+/// before it, so that they stay apart. The metadata is rebuilt from the merged
+/// events and lists the inputs as its sources.
+pub fn merge(inputs: &[String], output: &str, compression: Compression) -> Result<MergeReport> {
+    if inputs.len() < 2 {
+        return Err(anyhow!("merging needs at least two profiles"));
+    }
+    for input in inputs {
+        if std::fs::canonicalize(output).ok() == Some(std::fs::canonicalize(input)?) {
+            return Err(anyhow!("output must differ from the profiles being merged"));
+        }
+    }
+
+    let mut streamers = Vec::with_capacity(inputs.len());
+    for path in inputs {
+        streamers.push(ProfileStreamer::new(path).context(format!("failed to open {}", path))?);
+    }
+    let metadata: Vec<&ProfileMetadata> = streamers.iter().map(|s| s.metadata()).collect();
+    let connection_sampling = shared(&metadata, "connection sampling", |m| m.connection_sampling)?;
+    let event_sampling = shared(&metadata, "event sampling", |m| m.event_sampling)?;
+    let size_buckets = shared(&metadata, "size buckets", |m| m.size_buckets)?;
+
+    let mut writer = ProfileWriter::with_compression(output, compression)?;
+    if let Some(summary) = combined_capture(&metadata) {
+        writer.set_capture_summary(summary);
+    }
+    if let Some(rate) = connection_sampling {
+        writer.set_connection_sampling(rate);
+    }
+    if let Some(probability) = event_sampling {
+        writer.set_event_sampling(probability);
+    }
+    if let Some(buckets) = size_buckets {
+        writer.set_size_buckets(buckets);
+    }
+    if metadata.iter().any(|m| m.plaintext_keys) {
+        writer.set_plaintext_keys();
+    }
+
+    // A first pass over each input finds its connections, renumbered in order
+    let mut sources = Vec::new();
+    let mut merged = Vec::with_capacity(streamers.len());
+    let mut connections = 0u32;
+    for (path, mut streamer) in inputs.iter().zip(streamers) {
+        let mut conn_set = BTreeSet::new();
+        let mut total_events = 0u64;
+        while let Some(event) = streamer.next_event()? {
+            conn_set.insert(event.conn_id);
+            total_events += 1;
+        }
+        streamer.reset()?;
+
+        let first_conn_id = connections;
+        connections += conn_set.len() as u32;
+        if connections > MAX_CONNECTIONS {
+            return Err(anyhow!(
+                "the profiles have more than {} connections between them, the most a profile can tell apart",
+                MAX_CONNECTIONS
+            ));
+        }
+        let conn_ids: HashMap<u16, u16> = conn_set
+            .into_iter()
+            .zip((first_conn_id..).map(|id| id as u16))
+            .collect();
+
+        let metadata = streamer.metadata();
+        for (conn_id, &protocol) in &metadata.connection_protocols {
+            if let Some(&merged_id) = conn_ids.get(conn_id) {
+                writer.record_protocol(merged_id, protocol);
+            }
+        }
+        if metadata.sources.is_empty() {
+            sources.push(ProfileSource {
+                name: Path::new(path)
+                    .file_name()
+                    .map_or_else(|| path.clone(), |name| name.to_string_lossy().into_owned()),
+                total_events,
+                time_range: metadata.time_range,
+                first_conn_id: first_conn_id as u16,
+                unique_connections: conn_ids.len() as u32,
+            });
+        } else {
+            // A merged input keeps its own sources, whose connections are
+            // numbered from 0 there
+            for source in &metadata.sources {
+                sources.push(ProfileSource {
+                    first_conn_id: first_conn_id as u16 + source.first_conn_id,
+                    ..source.clone()
+                });
+            }
+        }
+
+        merged.push(Input {
+            streamer,
+            conn_ids,
+            next: None,
+        });
+    }
+    writer.set_sources(sources);
+
+    // Then the next event of every input, earliest first
+    let mut heads = BinaryHeap::new();
+    for (i, input) in merged.iter_mut().enumerate() {
+        input.next = input.streamer.next_event()?;
+        if let Some(event) = &input.next {
+            heads.push(Reverse((event.timestamp, i)));
+        }
+    }
+    let mut events_merged = 0u64;
+    while let Some(Reverse((_, i))) = heads.pop() {
+        let input = &mut merged[i];
+        let Some(mut event) = input.next.take() else {
+            continue;
+        };
+        event.conn_id = input.conn_ids[&event.conn_id];
+        writer.write_event(&event)?;
+        events_merged += 1;
+
+        input.next = input.streamer.next_event()?;
+        if let Some(event) = &input.next {
+            heads.push(Reverse((event.timestamp, i)));
+        }
+    }
+    writer.finish()?;
+
+    Ok(MergeReport {
+        events_merged,
+        connections,
+    })
+}
+
+/// A recording option every input shares, or an error naming it
+pub fn shared<T: PartialEq>(
+    metadata: &[&ProfileMetadata],
+    option: &str,
+    value: impl Fn(&ProfileMetadata) -> T,
+) -> Result<T> {
+    let first = value(metadata[0]);
+    if metadata[1..].iter().any(|m| value(m) != first) {
+        return Err(anyhow!(
+            "the profiles differ in their {}: combine profiles recorded with the same options",
+            option
+        ));
+    }
+    Ok(first)
+}
+
+/// Packet counters of every capture together, if every input has them
+pub fn combined_capture(metadata: &[&ProfileMetadata]) -> Option<CaptureSummary> {
+    let empty = CaptureSummary {
+        packets_dropped: Some(0),
+        ..CaptureSummary::default()
+    };
+    metadata.iter().try_fold(empty, |total, m| {
+        let capture = m.capture?;
+        Some(CaptureSummary {
+            packets_received: total.packets_received + capture.packets_received,
+            packets_dropped: total
+                .packets_dropped
+                .zip(capture.packets_dropped)
+                .map(|(total, dropped)| total + dropped),
+            packets_skipped: total.packets_skipped + capture.packets_skipped,
+            parse_errors: total.parse_errors + capture.parse_errors,
+        })
+    })
+}
+
+pub fn run(inputs: &[String], output: &str, compress: bool) -> Result<()> {
+    let compression = if compress {
+        Compression::Deflate
+    } else {
+        Compression::None
+    };
+    let report = merge(inputs, output, compression)?;
+
+    println!(
+        "Merged {} events from {} profiles into {} ({} connections)",
+        report.events_merged,
+        inputs.len(),
+        output,
+        report.connections
+    );
+
+    Ok(())
+}
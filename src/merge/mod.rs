@@ -0,0 +1,5 @@
+//! Merge command implementation
+
+pub mod main;
+
+pub use main::{merge, run as run_merge, MergeReport};
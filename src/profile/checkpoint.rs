@@ -0,0 +1,170 @@
+//! Metadata checkpoints
+//!
+//! While recording, the writer periodically appends a checkpoint record to
+//! the event region: a zero length prefix (no event encodes to zero bytes)
+//! followed by `[metadata_len: u16 LE][metadata]`, the metadata of every event
+//! before it. Since version 6, metadata of `LONG_CHECKPOINT` bytes or more is
+//! stored as `[LONG_CHECKPOINT: u16 LE][metadata_len: u32 LE][metadata]`. A
+//! profile whose trailer was never written can still be read up to its last
+//! checkpoint.
+
+use crate::profile::checksum::MAX_METADATA_LEN;
+use crate::profile::compression::{decode_blocks, read_full, BLOCK_MAGIC};
+use crate::profile::ProfileMetadata;
+use anyhow::{anyhow, Result};
+use std::io::Read;
+use std::time::Duration;
+
+/// How often the writer checkpoints a profile it writes events to
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Checkpoint length announcing a 32-bit length after it
+pub const LONG_CHECKPOINT: u16 = u16::MAX;
+
+/// One record of an (uncompressed) event region
+#[derive(Debug, Clone, Copy)]
+pub enum Record<'a> {
+    /// An encoded event
+    Event(&'a [u8]),
+    /// Encoded metadata of a checkpoint
+    Checkpoint(&'a [u8]),
+    /// CRC32C of the records since the previous checksum (see `checksum`)
+    Checksum(u32),
+}
+
+/// Record starting at `offset` of `region` and the offset following it, or
+/// None at the end of the region or on a truncated record
+pub fn read_record(region: &[u8], offset: usize) -> Option<(Record<'_>, usize)> {
+    let len_at = |at: usize| {
+        region
+            .get(at..at + 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+    };
+    match len_at(offset)? {
+        0 if len_at(offset + 2)? == 0 => {
+            let crc = region.get(offset + 4..offset + 8)?;
+            let crc = u32::from_le_bytes(crc.try_into().expect("4 bytes"));
+            Some((Record::Checksum(crc), offset + 8))
+        }
+        0 if len_at(offset + 2)? == LONG_CHECKPOINT as usize => {
+            let len = region.get(offset + 4..offset + 8)?;
+            let len = u32::from_le_bytes(len.try_into().expect("4 bytes")) as usize;
+            let metadata = region.get(offset + 8..offset + 8 + len)?;
+            Some((Record::Checkpoint(metadata), offset + 8 + len))
+        }
+        0 => {
+            let len = len_at(offset + 2)?;
+            let metadata = region.get(offset + 4..offset + 4 + len)?;
+            Some((Record::Checkpoint(metadata), offset + 4 + len))
+        }
+        len => {
+            let event = region.get(offset + 2..offset + 2 + len)?;
+            Some((Record::Event(event), offset + 2 + len))
+        }
+    }
+}
+
+/// Record read from `reader` into `buf`, or None at the end of the stream
+/// (where a lone trailing byte is ignored). A truncated record is an
+/// `UnexpectedEof` error.
+pub fn read_record_from<'a>(
+    reader: &mut impl Read,
+    buf: &'a mut Vec<u8>,
+) -> std::io::Result<Option<Record<'a>>> {
+    let mut len = [0u8; 2];
+    if read_full(reader, &mut len)? < len.len() {
+        return Ok(None);
+    }
+    let truncated = || {
+        std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "event data exceeds file boundary",
+        )
+    };
+    let checkpoint = u16::from_le_bytes(len) == 0;
+    if checkpoint && read_full(reader, &mut len)? < len.len() {
+        return Err(truncated());
+    }
+    if checkpoint && u16::from_le_bytes(len) == 0 {
+        let mut crc = [0u8; 4];
+        if read_full(reader, &mut crc)? < crc.len() {
+            return Err(truncated());
+        }
+        return Ok(Some(Record::Checksum(u32::from_le_bytes(crc))));
+    }
+    let len = if checkpoint && u16::from_le_bytes(len) == LONG_CHECKPOINT {
+        let mut long = [0u8; 4];
+        if read_full(reader, &mut long)? < long.len() {
+            return Err(truncated());
+        }
+        u32::from_le_bytes(long) as usize
+    } else {
+        u16::from_le_bytes(len) as usize
+    };
+    buf.resize(len, 0);
+    if read_full(reader, buf)? < buf.len() {
+        return Err(truncated());
+    }
+    Ok(Some(if checkpoint {
+        Record::Checkpoint(buf)
+    } else {
+        Record::Event(buf)
+    }))
+}
+
+/// Checkpoint record holding `metadata`
+pub fn encode_checkpoint(metadata: &ProfileMetadata) -> Result<Vec<u8>> {
+    let encoded = bincode::serialize(metadata)?;
+    if encoded.len() > MAX_METADATA_LEN {
+        return Err(anyhow!(
+            "metadata of {} bytes exceeds the {} bytes a checkpoint can hold",
+            encoded.len(),
+            MAX_METADATA_LEN
+        ));
+    }
+    let mut record = checkpoint_prefix(encoded.len());
+    record.extend_from_slice(&encoded);
+    Ok(record)
+}
+
+/// Bytes of a checkpoint record before its `len` bytes of metadata
+pub fn checkpoint_prefix(len: usize) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(8);
+    prefix.extend_from_slice(&0u16.to_le_bytes());
+    match u16::try_from(len) {
+        Ok(len) if len < LONG_CHECKPOINT => prefix.extend_from_slice(&len.to_le_bytes()),
+        _ => {
+            prefix.extend_from_slice(&LONG_CHECKPOINT.to_le_bytes());
+            prefix.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+    }
+    prefix
+}
+
+/// Recover a profile missing its trailer: the metadata of its last checkpoint
+/// and the (inflated) event region up to that checkpoint
+pub fn recover(data: &[u8]) -> Result<(ProfileMetadata, Vec<u8>)> {
+    let region = if data.starts_with(&BLOCK_MAGIC) {
+        decode_blocks(data).0
+    } else {
+        data.to_vec()
+    };
+
+    let mut last = None;
+    let mut offset = 0;
+    while let Some((record, next)) = read_record(&region, offset) {
+        if let Record::Checkpoint(bytes) = record {
+            match ProfileMetadata::decode(bytes) {
+                Ok(metadata) => last = Some((metadata, next)),
+                Err(_) => break,
+            }
+        }
+        offset = next;
+    }
+
+    let (metadata, end) =
+        last.ok_or_else(|| anyhow!("invalid file format: missing end marker and no checkpoint"))?;
+    let mut region = region;
+    region.truncate(end);
+    Ok((metadata, region))
+}
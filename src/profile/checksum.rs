@@ -0,0 +1,264 @@
+//! CRC32C checksums of the event region and the metadata
+//!
+//! Since profile version 4, the writer appends a checksum record to the event
+//! region at least every `BLOCK_SIZE` bytes (at the end of every block of a
+//! compressed profile): a zero length prefix and a zero checkpoint length,
+//! followed by `[crc32c: u32 LE]` of every record since the previous one.
+//! The trailer of such a profile checksums the metadata as well:
+//!
+//! ```text
+//! [metadata][metadata_len: u32 LE][metadata_crc32c: u32 LE][WIDE_END_MARKER: u32 LE]
+//! ```
+//!
+//! Profiles of version 4 and 5 store a u16 `metadata_len` before
+//! `CHECKSUMMED_END_MARKER`; older ones end with
+//! `[metadata][metadata_len: u16 LE][END_MARKER: u32 LE]`.
+
+use crate::profile::checkpoint::{checkpoint_prefix, Record};
+use crate::profile::Compression;
+use anyhow::{anyhow, Result};
+
+/// Last bytes of a profile whose trailer has no checksum
+pub const END_MARKER: u32 = 0xDEADBEEF;
+
+/// Last bytes of a profile whose trailer checksums its metadata
+pub const CHECKSUMMED_END_MARKER: u32 = 0xC3C3BEEF;
+
+/// Last bytes of a profile whose trailer holds a 32-bit metadata length
+pub const WIDE_END_MARKER: u32 = 0xC3C4BEEF;
+
+/// Profile version whose event region and metadata are checksummed
+pub const CHECKSUM_VERSION: u8 = 4;
+
+/// Profile version whose metadata and checkpoint lengths are 32-bit
+pub const WIDE_LENGTH_VERSION: u8 = 6;
+
+/// Longest metadata a trailer can locate
+pub const MAX_METADATA_LEN: usize = u32::MAX as usize;
+
+/// Bytes of a checksum record
+pub const CHECKSUM_RECORD_LEN: usize = 8;
+
+/// Reflected CRC32C (Castagnoli) polynomial
+const POLYNOMIAL: u32 = 0x82F6_3B78;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Running CRC32C of the bytes fed to it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crc32c {
+    state: u32,
+    len: u64,
+}
+
+impl Crc32c {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        let mut crc = !self.state;
+        for &byte in bytes {
+            crc = TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+        }
+        self.state = !crc;
+        self.len += bytes.len() as u64;
+    }
+
+    pub fn value(&self) -> u32 {
+        self.state
+    }
+
+    /// Bytes fed so far
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// CRC32C of `bytes`
+pub fn crc32c(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32c::new();
+    crc.update(bytes);
+    crc.value()
+}
+
+/// Checksum record covering the records fed to `crc`
+pub fn encode_checksum(crc: &Crc32c) -> [u8; CHECKSUM_RECORD_LEN] {
+    let mut record = [0u8; CHECKSUM_RECORD_LEN];
+    record[4..].copy_from_slice(&crc.value().to_le_bytes());
+    record
+}
+
+/// Feed a record, as written, to `crc`
+pub fn feed_record(crc: &mut Crc32c, record: &Record) {
+    match *record {
+        Record::Event(event) => {
+            crc.update(&(event.len() as u16).to_le_bytes());
+            crc.update(event);
+        }
+        Record::Checkpoint(metadata) => {
+            crc.update(&checkpoint_prefix(metadata.len()));
+            crc.update(metadata);
+        }
+        Record::Checksum(_) => {}
+    }
+}
+
+/// Verifies the checksum records of an event region as it is read
+#[derive(Debug)]
+pub struct RecordChecksums {
+    crc: Crc32c,
+    /// Offset in the (inflated) region of the first record `crc` covers
+    start: u64,
+    compressed: bool,
+}
+
+impl RecordChecksums {
+    pub fn new(compression: Compression) -> Self {
+        RecordChecksums {
+            crc: Crc32c::new(),
+            start: 0,
+            compressed: compression != Compression::None,
+        }
+    }
+
+    /// Verifier of the records of an uncompressed region from `start` on
+    pub fn starting_at(start: u64) -> Self {
+        RecordChecksums {
+            start,
+            ..Self::new(Compression::None)
+        }
+    }
+
+    /// Account for a record read, checking it against its checksum if it is one
+    pub fn check(&mut self, record: &Record) -> Result<()> {
+        let Record::Checksum(expected) = *record else {
+            feed_record(&mut self.crc, record);
+            return Ok(());
+        };
+        let end = self.start + self.crc.len();
+        if self.crc.value() != expected {
+            return Err(anyhow!(
+                "checksum mismatch in bytes {}..{} of the {}event region: the profile is corrupt",
+                self.start,
+                end,
+                if self.compressed { "decompressed " } else { "" }
+            ));
+        }
+        self.crc = Crc32c::new();
+        self.start = end + CHECKSUM_RECORD_LEN as u64;
+        Ok(())
+    }
+}
+
+/// Trailer closing a finished profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trailer {
+    pub metadata_len: u64,
+    /// CRC32C of the metadata, in profiles of version 4 and later
+    pub metadata_crc: Option<u32>,
+    /// Whether `metadata_len` is stored on 32 bits (version 6 and later)
+    pub wide: bool,
+}
+
+impl Trailer {
+    /// Bytes of the longest trailer, after the metadata
+    pub const MAX_LEN: usize = 12;
+
+    /// Trailer at the end of `tail`, the last bytes of a file (up to
+    /// `MAX_LEN` of them), or None if the file does not end with one
+    pub fn parse(tail: &[u8]) -> Option<Self> {
+        let le_u32 = |at: usize| -> Option<u32> {
+            let start = tail.len().checked_sub(at)?;
+            Some(u32::from_le_bytes(tail[start..start + 4].try_into().ok()?))
+        };
+        let le_u16 = |at: usize| -> Option<u64> {
+            let start = tail.len().checked_sub(at)?;
+            Some(u16::from_le_bytes(tail[start..start + 2].try_into().ok()?) as u64)
+        };
+        match le_u32(4)? {
+            END_MARKER => Some(Trailer {
+                metadata_len: le_u16(6)?,
+                metadata_crc: None,
+                wide: false,
+            }),
+            CHECKSUMMED_END_MARKER => Some(Trailer {
+                metadata_len: le_u16(10)?,
+                metadata_crc: Some(le_u32(8)?),
+                wide: false,
+            }),
+            WIDE_END_MARKER => Some(Trailer {
+                metadata_len: le_u32(12)? as u64,
+                metadata_crc: Some(le_u32(8)?),
+                wide: true,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Bytes of the file after the metadata
+    pub fn encoded_len(&self) -> u64 {
+        match (self.metadata_crc, self.wide) {
+            (_, true) => 12,
+            (Some(_), false) => 10,
+            (None, false) => 6,
+        }
+    }
+
+    /// Offset where the metadata of a file of `file_len` bytes starts
+    pub fn metadata_start(&self, file_len: u64) -> Result<u64> {
+        file_len
+            .checked_sub(self.encoded_len() + self.metadata_len)
+            .ok_or_else(|| anyhow!("metadata length exceeds file size"))
+    }
+
+    /// Check `metadata`, starting at `start` in the file, against its checksum
+    pub fn verify(&self, metadata: &[u8], start: u64) -> Result<()> {
+        match self.metadata_crc {
+            Some(expected) if crc32c(metadata) != expected => Err(anyhow!(
+                "checksum mismatch in the metadata at bytes {}..{}: the profile is corrupt",
+                start,
+                start + metadata.len() as u64
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The trailer of a profile of the current version following `metadata`
+    pub fn encode(metadata: &[u8]) -> Result<Vec<u8>> {
+        let len = u32::try_from(metadata.len()).map_err(|_| {
+            anyhow!(
+                "metadata of {} bytes exceeds the {} bytes a profile can hold",
+                metadata.len(),
+                MAX_METADATA_LEN
+            )
+        })?;
+        let mut trailer = Vec::with_capacity(Self::MAX_LEN);
+        trailer.extend_from_slice(&len.to_le_bytes());
+        trailer.extend_from_slice(&crc32c(metadata).to_le_bytes());
+        trailer.extend_from_slice(&WIDE_END_MARKER.to_le_bytes());
+        Ok(trailer)
+    }
+}
@@ -0,0 +1,194 @@
+//! Compressed event blocks
+//!
+//! A compressed profile starts with `BLOCK_MAGIC`, followed by blocks of
+//! `[compressed_len: u32 LE][compressed bytes]`. Each block inflates to a run
+//! of ordinary length-prefixed events, so readers decompress the event region
+//! (all at once, or a block at a time with `BlockReader`) and decode it
+//! exactly like an uncompressed profile.
+
+use anyhow::{anyhow, Result};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+/// First bytes of a compressed profile. Uncompressed profiles start with the
+/// u16 length of their first event, which is never this large.
+pub const BLOCK_MAGIC: [u8; 4] = *b"MBZ\x01";
+
+/// Uncompressed bytes of events gathered into one block
+pub const BLOCK_SIZE: usize = 64 << 10;
+
+/// Encoding of the event region of a profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Compression {
+    /// Length-prefixed events, as written by every profile version
+    #[default]
+    None,
+    /// Blocks of deflate-compressed events
+    Deflate,
+}
+
+/// Compress one block of length-prefixed events into its on-disk form
+pub fn encode_block(events: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(events)?;
+    let compressed = encoder.finish()?;
+    let mut block = Vec::with_capacity(4 + compressed.len());
+    block.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+    block.extend_from_slice(&compressed);
+    Ok(block)
+}
+
+/// Length-prefixed events of an event region
+pub fn decode_region(region: &[u8], compression: Compression) -> Result<Cow<'_, [u8]>> {
+    match compression {
+        Compression::None => Ok(Cow::Borrowed(region)),
+        Compression::Deflate => {
+            if !region.starts_with(&BLOCK_MAGIC) {
+                return Err(anyhow!("compressed profile without block header"));
+            }
+            let (events, consumed) = decode_blocks(region);
+            if consumed != region.len() {
+                return Err(anyhow!("corrupt compressed block at offset {}", consumed));
+            }
+            Ok(Cow::Owned(events))
+        }
+    }
+}
+
+/// Inflate the blocks of a compressed event region (starting with
+/// `BLOCK_MAGIC`), stopping at the first truncated or corrupt one. Returns
+/// the events of the complete blocks and the bytes of `region` they span.
+pub fn decode_blocks(region: &[u8]) -> (Vec<u8>, usize) {
+    let Some(mut blocks) = region.strip_prefix(&BLOCK_MAGIC) else {
+        return (Vec::new(), 0);
+    };
+    let mut events = Vec::new();
+    let mut consumed = BLOCK_MAGIC.len();
+    while let Some(len_bytes) = blocks.get(..4) {
+        let len = u32::from_le_bytes(len_bytes.try_into().expect("4 bytes")) as usize;
+        let Some(compressed) = blocks.get(4..4 + len) else {
+            break;
+        };
+        let mut block = Vec::new();
+        if DeflateDecoder::new(compressed)
+            .read_to_end(&mut block)
+            .is_err()
+        {
+            break;
+        }
+        events.extend_from_slice(&block);
+        blocks = &blocks[4 + len..];
+        consumed += 4 + len;
+    }
+    (events, consumed)
+}
+
+/// Inflates a compressed event region block by block as it is read, holding
+/// one block in memory at a time
+pub struct BlockReader<R> {
+    inner: R,
+    block: Vec<u8>,
+    position: usize,
+    /// Bytes of the region consumed so far
+    consumed: u64,
+    /// End quietly at a truncated or corrupt block, as when recovering
+    lenient: bool,
+}
+
+impl<R: Read> BlockReader<R> {
+    /// Reader of the region `inner` starts at, checking its `BLOCK_MAGIC`
+    pub fn new(mut inner: R, lenient: bool) -> Result<Self> {
+        let mut magic = [0u8; BLOCK_MAGIC.len()];
+        if read_full(&mut inner, &mut magic)? != magic.len() || magic != BLOCK_MAGIC {
+            return Err(anyhow!("compressed profile without block header"));
+        }
+        Ok(BlockReader {
+            inner,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            position: 0,
+            consumed: BLOCK_MAGIC.len() as u64,
+            lenient,
+        })
+    }
+
+    /// Reader of the blocks of a region from the one `inner` starts at, at
+    /// `offset` in the region (past its `BLOCK_MAGIC`)
+    pub fn at_block(inner: R, offset: u64, lenient: bool) -> Self {
+        BlockReader {
+            inner,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            position: 0,
+            consumed: offset,
+            lenient,
+        }
+    }
+
+    /// Inflate the next block, false at the end of the region
+    fn next_block(&mut self) -> std::io::Result<bool> {
+        let mut len = [0u8; 4];
+        match read_full(&mut self.inner, &mut len)? {
+            0 => return Ok(false),
+            4 => {}
+            _ => return self.corrupt(),
+        }
+        let len = u32::from_le_bytes(len) as u64;
+        let mut compressed = Vec::new();
+        (&mut self.inner).take(len).read_to_end(&mut compressed)?;
+        if compressed.len() as u64 != len {
+            return self.corrupt();
+        }
+
+        self.block.clear();
+        self.position = 0;
+        if DeflateDecoder::new(&compressed[..])
+            .read_to_end(&mut self.block)
+            .is_err()
+        {
+            self.block.clear();
+            return self.corrupt();
+        }
+        self.consumed += 4 + len;
+        Ok(true)
+    }
+
+    fn corrupt(&self) -> std::io::Result<bool> {
+        if self.lenient {
+            return Ok(false);
+        }
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("corrupt compressed block at offset {}", self.consumed),
+        ))
+    }
+}
+
+impl<R: Read> Read for BlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.block.len() {
+            if !self.next_block()? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.block.len() - self.position);
+        buf[..n].copy_from_slice(&self.block[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Read into `buf` until it is full or `reader` ends, returning the bytes read
+pub fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,171 @@
+//! Index of the event region, for seeks to a time range
+//!
+//! A finished profile (since version 5) carries an index section between its
+//! events and its metadata, located by `ProfileMetadata::index`. It holds one
+//! entry for every run of events covered by a checksum (at most
+//! `INDEX_INTERVAL` events, or a compressed block), each
+//! `[timestamp: u64 LE][offset: u64 LE][events: u64 LE]`. Reading can start at
+//! any entry: a compressed block starts there, and checksums restart there.
+
+use crate::units::parse_duration;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Profile version whose finished profiles carry an index
+pub const INDEX_VERSION: u8 = 5;
+
+/// Most events between two index entries
+pub const INDEX_INTERVAL: u64 = 1024;
+
+/// Bytes of one index entry
+pub const ENTRY_LEN: usize = 24;
+
+/// Where the index section of a profile is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexSection {
+    /// Offset of the section in the file, where the event region ends
+    pub offset: u64,
+    pub entries: u64,
+    /// CRC32C of the section
+    pub crc: u32,
+}
+
+impl IndexSection {
+    /// Bytes of the section
+    pub fn len(&self) -> u64 {
+        self.entries * ENTRY_LEN as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+}
+
+/// A point of the event region reading can start at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Latest timestamp of the events before the entry (0 before the first)
+    pub timestamp: u64,
+    /// Offset in the file of the first record after the entry
+    pub offset: u64,
+    /// Events before the entry
+    pub events: u64,
+}
+
+pub fn encode_index(entries: &[IndexEntry]) -> Vec<u8> {
+    let mut section = Vec::with_capacity(entries.len() * ENTRY_LEN);
+    for entry in entries {
+        section.extend_from_slice(&entry.timestamp.to_le_bytes());
+        section.extend_from_slice(&entry.offset.to_le_bytes());
+        section.extend_from_slice(&entry.events.to_le_bytes());
+    }
+    section
+}
+
+pub fn decode_index(section: &[u8]) -> Result<Vec<IndexEntry>> {
+    if !section.len().is_multiple_of(ENTRY_LEN) {
+        return Err(anyhow!("index section of {} bytes", section.len()));
+    }
+    let le_u64 = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().expect("8 bytes"));
+    Ok(section
+        .chunks_exact(ENTRY_LEN)
+        .map(|entry| IndexEntry {
+            timestamp: le_u64(&entry[..8]),
+            offset: le_u64(&entry[8..16]),
+            events: le_u64(&entry[16..]),
+        })
+        .collect())
+}
+
+/// Last entry every event before which is earlier than `from`: where reading
+/// events from `from` on starts. None to start at the first event.
+pub fn seek(entries: &[IndexEntry], from: u64) -> Option<IndexEntry> {
+    let after = entries.partition_point(|entry| entry.timestamp < from);
+    after.checked_sub(1).map(|last| entries[last])
+}
+
+const MICROS_PER_DAY: u64 = 86_400_000_000;
+
+/// Bound of a time range, as given on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBound {
+    /// `HH:MM[:SS]`: time of day (UTC), in seconds from midnight
+    OfDay(u64),
+    /// Offset from the start of the capture, in microseconds
+    Offset(u64),
+}
+
+impl TimeBound {
+    /// Timestamp of the bound, for a capture starting at `start`. A time of
+    /// day is its first occurrence at or after `start`.
+    pub fn resolve(&self, start: u64) -> u64 {
+        match *self {
+            TimeBound::Offset(micros) => start.saturating_add(micros),
+            TimeBound::OfDay(secs) => {
+                let at = start - start % MICROS_PER_DAY + secs * 1_000_000;
+                if at < start {
+                    at + MICROS_PER_DAY
+                } else {
+                    at
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for TimeBound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid time: '{}'. Use HH:MM[:SS] (UTC) or an offset from the start of the capture such as 90s",
+                s
+            )
+        };
+        if s.contains(':') {
+            let parts: Vec<u64> = s
+                .split(':')
+                .map(|part| part.parse::<u64>().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?;
+            let (hours, minutes, seconds) = match parts[..] {
+                [hours, minutes] => (hours, minutes, 0),
+                [hours, minutes, seconds] => (hours, minutes, seconds),
+                _ => return Err(invalid()),
+            };
+            if hours > 23 || minutes > 59 || seconds > 59 {
+                return Err(invalid());
+            }
+            return Ok(TimeBound::OfDay(hours * 3600 + minutes * 60 + seconds));
+        }
+        parse_duration(s)
+            .map(|offset| TimeBound::Offset(offset.as_micros() as u64))
+            .map_err(|_| invalid())
+    }
+}
+
+/// Part of a capture to read, from `from` to `to` inclusive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub from: Option<TimeBound>,
+    pub to: Option<TimeBound>,
+}
+
+impl TimeRange {
+    pub fn is_all(&self) -> bool {
+        self.from.is_none() && self.to.is_none()
+    }
+
+    /// First and last timestamps of the range, for a capture starting at
+    /// `start`; a time of day `to` is its first occurrence at or after `from`
+    pub fn resolve(&self, start: u64) -> (u64, u64) {
+        let from = self.from.map_or(start, |from| from.resolve(start));
+        let to = match self.to {
+            Some(to @ TimeBound::OfDay(_)) => to.resolve(from),
+            Some(to) => to.resolve(start),
+            None => u64::MAX,
+        };
+        (from, to)
+    }
+}
@@ -0,0 +1,99 @@
+//! Memory-mapped profile files
+//!
+//! Readers decode events straight from the pages of the file instead of
+//! copying the whole file into the heap first. The mapping is advised as
+//! sequential, so the kernel reads ahead and drops pages once they are read.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::ops::Deref;
+
+/// A profile file mapped read-only into memory. It must not be truncated
+/// while mapped.
+pub struct MappedFile {
+    map: Map,
+}
+
+enum Map {
+    #[cfg(unix)]
+    Mapped { ptr: *mut libc::c_void, len: usize },
+    /// Empty files, which cannot be mapped, and platforms without mmap
+    Heap(Vec<u8>),
+}
+
+// The mapping is read-only and owned by MappedFile
+unsafe impl Send for MappedFile {}
+unsafe impl Sync for MappedFile {}
+
+impl MappedFile {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = File::open(path).context(format!("failed to open {}", path))?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(MappedFile {
+                map: Map::Heap(Vec::new()),
+            });
+        }
+        Self::map(&file, len).context(format!("failed to map {}", path))
+    }
+
+    #[cfg(unix)]
+    fn map(file: &File, len: usize) -> std::io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        // SAFETY: a fresh read-only private mapping of an open file, unmapped on drop
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        // Only a hint: reading works the same without it
+        unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+        Ok(MappedFile {
+            map: Map::Mapped { ptr, len },
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn map(mut file: &File, len: usize) -> std::io::Result<Self> {
+        use std::io::Read;
+
+        let mut data = Vec::with_capacity(len);
+        file.read_to_end(&mut data)?;
+        Ok(MappedFile {
+            map: Map::Heap(data),
+        })
+    }
+}
+
+impl Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.map {
+            // SAFETY: the mapping spans `len` readable bytes until dropped
+            #[cfg(unix)]
+            Map::Mapped { ptr, len } => unsafe {
+                std::slice::from_raw_parts(*ptr as *const u8, *len)
+            },
+            Map::Heap(data) => data,
+        }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Map::Mapped { ptr, len } = self.map {
+            unsafe { libc::munmap(ptr, len) };
+        }
+    }
+}
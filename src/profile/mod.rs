@@ -1,13 +1,128 @@
+pub mod checkpoint;
+pub mod checksum;
+pub mod compression;
+pub mod index;
+pub mod mmap;
+pub mod packed;
+pub mod sketch;
+
+pub use compression::Compression;
+pub use index::{IndexSection, TimeBound, TimeRange};
+pub use sketch::KeySketch;
+
+use bincode::Options;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use std::num::NonZero;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Hash)]
 pub enum CommandType {
     Get,
     Set,
     Delete,
     Noop,
+    // Appended so that profiles written before them keep decoding
+    Add,
+    Replace,
+    Append,
+    Prepend,
+    Cas,
+    Incr,
+    Decr,
+    Touch,
+    /// Get and touch
+    Gat,
+}
+
+impl CommandType {
+    pub const ALL: [CommandType; 13] = [
+        CommandType::Get,
+        CommandType::Set,
+        CommandType::Delete,
+        CommandType::Noop,
+        CommandType::Add,
+        CommandType::Replace,
+        CommandType::Append,
+        CommandType::Prepend,
+        CommandType::Cas,
+        CommandType::Incr,
+        CommandType::Decr,
+        CommandType::Touch,
+        CommandType::Gat,
+    ];
+
+    /// Command named as in the ASCII protocol (`noop` for Noop)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let cmd_type = match name {
+            "get" => CommandType::Get,
+            "set" => CommandType::Set,
+            "delete" => CommandType::Delete,
+            "noop" => CommandType::Noop,
+            "add" => CommandType::Add,
+            "replace" => CommandType::Replace,
+            "append" => CommandType::Append,
+            "prepend" => CommandType::Prepend,
+            "cas" => CommandType::Cas,
+            "incr" => CommandType::Incr,
+            "decr" => CommandType::Decr,
+            "touch" => CommandType::Touch,
+            "gat" => CommandType::Gat,
+            _ => return None,
+        };
+        Some(cmd_type)
+    }
+
+    /// Name of the command, as `from_name` takes it
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommandType::Get => "get",
+            CommandType::Set => "set",
+            CommandType::Delete => "delete",
+            CommandType::Noop => "noop",
+            CommandType::Add => "add",
+            CommandType::Replace => "replace",
+            CommandType::Append => "append",
+            CommandType::Prepend => "prepend",
+            CommandType::Cas => "cas",
+            CommandType::Incr => "incr",
+            CommandType::Decr => "decr",
+            CommandType::Touch => "touch",
+            CommandType::Gat => "gat",
+        }
+    }
+
+    /// Whether the command changes server state
+    pub fn is_mutation(&self) -> bool {
+        !matches!(self, CommandType::Get | CommandType::Noop)
+    }
+
+    /// Whether the command returns the value of its key
+    pub fn is_retrieval(&self) -> bool {
+        matches!(self, CommandType::Get | CommandType::Gat)
+    }
+
+    /// Whether the command sends a value (the storage commands)
+    pub fn has_value(&self) -> bool {
+        matches!(
+            self,
+            CommandType::Set
+                | CommandType::Add
+                | CommandType::Replace
+                | CommandType::Append
+                | CommandType::Prepend
+                | CommandType::Cas
+        )
+    }
+}
+
+/// Wire protocol a connection was observed speaking
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Hash)]
+pub enum Protocol {
+    Ascii,
+    Meta,
+    Binary,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -53,9 +168,103 @@ pub struct Event {
     pub key_size: u32,
     // Value info
     pub value_size: Option<NonZero<u32>>,
+    // Server answer, when the capture saw it
+    pub response: Option<Response>,
+    // Expiration set by the command, in seconds from the command; None if it never expires
+    pub ttl: Option<NonZero<u32>>,
+    // Server port the command was sent to, when the capture recorded it
+    pub server_port: Option<NonZero<u16>>,
+    // Hash of each delimited segment of the key, when recorded with a key delimiter
+    pub key_segments: Option<Vec<u32>>,
+    // The key itself, only when recorded without anonymization
+    pub key: Option<Vec<u8>>,
+    // Hash of the client IP, with the key salt, when the capture recorded it
+    pub client_id: Option<u32>,
+    // Microseconds from the command to its response, when the capture saw both
+    pub latency: Option<u32>,
+}
+
+/// How the server answered a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Hash)]
+pub enum ResponseStatus {
+    /// Value returned, stored or deleted
+    Hit,
+    /// Key not found, or not stored
+    Miss,
+    /// ERROR, CLIENT_ERROR or SERVER_ERROR
+    Error,
+}
+
+/// Server response paired with the command it answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Response {
+    pub status: ResponseStatus,
+    /// Size of the value returned by a GET hit
+    pub value_size: Option<NonZero<u32>>,
+}
+
+/// Event layout of version 2 profiles, the last to encode events with bincode
+#[derive(Deserialize)]
+struct V2Event {
+    timestamp: u64,
+    conn_id: u16,
+    cmd_type: CommandType,
+    flags: Flags,
+    key_hash: u64,
+    key_size: u32,
+    value_size: Option<NonZero<u32>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Event {
+    /// Event of a command without value, flags or anything the capture may
+    /// add (response, TTL, server port, segments, key, client, latency)
+    pub fn new(
+        timestamp: u64,
+        conn_id: u16,
+        cmd_type: CommandType,
+        key_hash: u64,
+        key_size: u32,
+    ) -> Self {
+        Event {
+            timestamp,
+            conn_id,
+            cmd_type,
+            flags: Flags::empty(),
+            key_hash,
+            key_size,
+            value_size: None,
+            response: None,
+            ttl: None,
+            server_port: None,
+            key_segments: None,
+            key: None,
+            client_id: None,
+            latency: None,
+        }
+    }
+
+    /// Decode exactly one event of a version 2 profile; later versions pack
+    /// their events (see `packed`)
+    pub fn decode(bytes: &[u8]) -> bincode::Result<Self> {
+        let old: V2Event = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .reject_trailing_bytes()
+            .deserialize(bytes)?;
+        Ok(Event {
+            flags: old.flags,
+            value_size: old.value_size,
+            ..Event::new(
+                old.timestamp,
+                old.conn_id,
+                old.cmd_type,
+                old.key_hash,
+                old.key_size,
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProfileMetadata {
     pub magic: u32,
     pub version: u8,
@@ -63,19 +272,349 @@ pub struct ProfileMetadata {
     pub time_range: (u64, u64),
     pub unique_connections: u32,
     pub command_distribution: HashMap<CommandType, u64>,
+    /// Protocol observed on each connection
+    pub connection_protocols: HashMap<u16, Protocol>,
+    /// Encoding of the events before the metadata
+    pub compression: Compression,
+    /// Packet counters of the capture, when it was recorded live or from a file
+    pub capture: Option<CaptureSummary>,
+    /// Share of the connections recorded, when only a sample of them was
+    pub connection_sampling: Option<SampleRate>,
+    /// Probability each command of a recorded connection was kept with, when
+    /// commands were sampled
+    pub event_sampling: Option<f64>,
+    /// Buckets key and value sizes were rounded up into, when they were
+    pub size_buckets: Option<SizeBucketing>,
+    /// Whether events carry their keys in plaintext
+    pub plaintext_keys: bool,
+    /// Index section after the events, in finished profiles since version 5
+    pub index: Option<IndexSection>,
+    /// Profiles this one was merged from, in the order they were given
+    pub sources: Vec<ProfileSource>,
+    /// Sketch of the distinct key hashes of the events, in profiles written
+    /// since it was added
+    pub key_sketch: Option<KeySketch>,
+}
+
+/// A profile merged into another, and the connections it became there
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileSource {
+    /// File name of the profile
+    pub name: String,
+    pub total_events: u64,
+    pub time_range: (u64, u64),
+    /// Its connections, renumbered from `first_conn_id` on
+    pub first_conn_id: u16,
+    pub unique_connections: u32,
+}
+
+/// `numerator` out of every `denominator`, written `1/16`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampleRate {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl SampleRate {
+    /// The rate as a fraction in (0, 1]
+    pub fn fraction(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl FromStr for SampleRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid sample rate: '{}'. Use N/M with 0 < N <= M", s);
+        let (numerator, denominator) = s.split_once('/').ok_or_else(invalid)?;
+        let numerator: u32 = numerator.trim().parse().map_err(|_| invalid())?;
+        let denominator: u32 = denominator.trim().parse().map_err(|_| invalid())?;
+        if numerator == 0 || numerator > denominator {
+            return Err(invalid());
+        }
+        Ok(SampleRate {
+            numerator,
+            denominator,
+        })
+    }
+}
+
+impl fmt::Display for SampleRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// Memcache's limit on ASCII keys, which rounded key sizes stay within
+const MAX_KEY_SIZE: u32 = 250;
+
+/// Buckets a recorded size is rounded up into, hiding its exact value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SizeBuckets {
+    /// Up to a multiple of this many bytes, written as the number (`16`)
+    Multiple(u32),
+    /// Up to a power of two, written `pow2`
+    PowerOfTwo,
+}
+
+impl SizeBuckets {
+    /// Upper bound of the bucket `size` falls in
+    pub fn bucket(&self, size: u32) -> u32 {
+        match *self {
+            SizeBuckets::Multiple(width) => size.div_ceil(width).saturating_mul(width),
+            SizeBuckets::PowerOfTwo if size == 0 => 0,
+            SizeBuckets::PowerOfTwo => size.checked_next_power_of_two().unwrap_or(u32::MAX),
+        }
+    }
+
+    /// Sizes a bucketed size may have been recorded from, e.g. `17-32`
+    pub fn label(&self, bucket: u32) -> String {
+        let first = match *self {
+            SizeBuckets::Multiple(width) => bucket.saturating_sub(width) + 1,
+            SizeBuckets::PowerOfTwo => bucket / 2 + 1,
+        }
+        .min(bucket);
+        if first == bucket {
+            bucket.to_string()
+        } else {
+            format!("{}-{}", first, bucket)
+        }
+    }
+
+    /// The rounding, as analyze describes it
+    pub fn description(&self) -> String {
+        match self {
+            SizeBuckets::Multiple(width) => format!("rounded up to a multiple of {} bytes", width),
+            SizeBuckets::PowerOfTwo => "rounded up to a power of two".to_string(),
+        }
+    }
+}
+
+impl FromStr for SizeBuckets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "pow2" {
+            return Ok(SizeBuckets::PowerOfTwo);
+        }
+        match s.parse::<u32>() {
+            Ok(width) if width > 1 => Ok(SizeBuckets::Multiple(width)),
+            _ => Err(format!(
+                "Invalid size buckets: '{}'. Use a bucket width in bytes (e.g. 16) or 'pow2'",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SizeBuckets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SizeBuckets::Multiple(width) => write!(f, "{}", width),
+            SizeBuckets::PowerOfTwo => write!(f, "pow2"),
+        }
+    }
+}
+
+/// Bucketing of the key and value sizes of a recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SizeBucketing {
+    pub key: Option<SizeBuckets>,
+    /// Applies to the values of storage commands and of GET hits alike
+    pub value: Option<SizeBuckets>,
+}
+
+impl SizeBucketing {
+    /// Whether any size is bucketed
+    pub fn is_enabled(&self) -> bool {
+        self.key.is_some() || self.value.is_some()
+    }
+
+    /// Round the sizes of `event` up into their buckets
+    pub fn apply(&self, event: &mut Event) {
+        if let Some(key) = self.key {
+            // A key within the ASCII limit stays within it
+            let limit = MAX_KEY_SIZE.max(event.key_size);
+            event.key_size = key.bucket(event.key_size).min(limit);
+        }
+        if let Some(value) = self.value {
+            let round = |size: NonZero<u32>| NonZero::new(value.bucket(size.get())).unwrap_or(size);
+            event.value_size = event.value_size.map(round);
+            if let Some(response) = event.response.as_mut() {
+                response.value_size = response.value_size.map(round);
+            }
+        }
+    }
+}
+
+/// Packet counters of a recording, as last sampled before its profile was
+/// finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct CaptureSummary {
+    /// Packets the capture delivered
+    pub packets_received: u64,
+    /// Packets the kernel dropped, when the capture source reports it
+    pub packets_dropped: Option<u64>,
+    /// Packets that were not IP/TCP
+    pub packets_skipped: u64,
+    /// Request lines that could not be parsed
+    pub parse_errors: u64,
+}
+
+/// Metadata layout of version 5 profiles
+#[derive(Deserialize)]
+struct V5ProfileMetadata {
+    magic: u32,
+    version: u8,
+    total_events: u64,
+    time_range: (u64, u64),
+    unique_connections: u32,
+    command_distribution: HashMap<CommandType, u64>,
+    connection_protocols: HashMap<u16, Protocol>,
+    compression: Compression,
+    capture: Option<CaptureSummary>,
+    connection_sampling: Option<SampleRate>,
+    event_sampling: Option<f64>,
+    size_buckets: Option<SizeBucketing>,
+    plaintext_keys: bool,
+    index: Option<IndexSection>,
+}
+
+/// Metadata layout of version 3 and 4 profiles
+#[derive(Deserialize)]
+struct V3ProfileMetadata {
+    magic: u32,
+    version: u8,
+    total_events: u64,
+    time_range: (u64, u64),
+    unique_connections: u32,
+    command_distribution: HashMap<CommandType, u64>,
+    connection_protocols: HashMap<u16, Protocol>,
+    compression: Compression,
+    capture: Option<CaptureSummary>,
+    connection_sampling: Option<SampleRate>,
+    event_sampling: Option<f64>,
+    size_buckets: Option<SizeBucketing>,
+    plaintext_keys: bool,
+}
+
+/// Metadata layout of version 2 profiles
+#[derive(Deserialize)]
+struct V2ProfileMetadata {
+    magic: u32,
+    version: u8,
+    total_events: u64,
+    time_range: (u64, u64),
+    unique_connections: u32,
+    command_distribution: HashMap<CommandType, u64>,
 }
 
 impl ProfileMetadata {
+    /// Offset where the event region ends, in a profile whose metadata starts
+    /// at `metadata_start`: at the index section, when there is one
+    pub fn events_end(&self, metadata_start: u64) -> u64 {
+        self.index.map_or(metadata_start, |index| index.offset)
+    }
+
+    /// Decode metadata in the layout of its version, which follows the magic
+    pub fn decode(bytes: &[u8]) -> bincode::Result<Self> {
+        let options = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .reject_trailing_bytes();
+        let (_magic, version): (u32, u8) = bincode::deserialize(bytes)?;
+        let metadata = match version {
+            ..=2 => {
+                let old: V2ProfileMetadata = options.deserialize(bytes)?;
+                ProfileMetadata {
+                    magic: old.magic,
+                    version: old.version,
+                    total_events: old.total_events,
+                    time_range: old.time_range,
+                    unique_connections: old.unique_connections,
+                    command_distribution: old.command_distribution,
+                    ..ProfileMetadata::new()
+                }
+            }
+            3 | 4 => {
+                let old: V3ProfileMetadata = options.deserialize(bytes)?;
+                ProfileMetadata {
+                    magic: old.magic,
+                    version: old.version,
+                    total_events: old.total_events,
+                    time_range: old.time_range,
+                    unique_connections: old.unique_connections,
+                    command_distribution: old.command_distribution,
+                    connection_protocols: old.connection_protocols,
+                    compression: old.compression,
+                    capture: old.capture,
+                    connection_sampling: old.connection_sampling,
+                    event_sampling: old.event_sampling,
+                    size_buckets: old.size_buckets,
+                    plaintext_keys: old.plaintext_keys,
+                    ..ProfileMetadata::new()
+                }
+            }
+            5 => {
+                let old: V5ProfileMetadata = options.deserialize(bytes)?;
+                ProfileMetadata {
+                    magic: old.magic,
+                    version: old.version,
+                    total_events: old.total_events,
+                    time_range: old.time_range,
+                    unique_connections: old.unique_connections,
+                    command_distribution: old.command_distribution,
+                    connection_protocols: old.connection_protocols,
+                    compression: old.compression,
+                    capture: old.capture,
+                    connection_sampling: old.connection_sampling,
+                    event_sampling: old.event_sampling,
+                    size_buckets: old.size_buckets,
+                    plaintext_keys: old.plaintext_keys,
+                    index: old.index,
+                    ..ProfileMetadata::new()
+                }
+            }
+            checksum::WIDE_LENGTH_VERSION => options.deserialize(bytes)?,
+            _ => {
+                return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                    "profile version {} is newer than this build reads (up to {})",
+                    version,
+                    checksum::WIDE_LENGTH_VERSION
+                ))))
+            }
+        };
+        Ok(metadata)
+    }
+
     pub fn new() -> Self {
         ProfileMetadata {
             magic: 0xDEADBEEF,
-            version: 2, // Changed: packed layout with Option<NonZero<u32>>, u16 conn_id, u8 flags
+            // 32-bit metadata length
+            version: checksum::WIDE_LENGTH_VERSION,
             total_events: 0,
             time_range: (0, 0),
             unique_connections: 0,
             command_distribution: HashMap::new(),
+            connection_protocols: HashMap::new(),
+            compression: Compression::None,
+            capture: None,
+            connection_sampling: None,
+            event_sampling: None,
+            size_buckets: None,
+            plaintext_keys: false,
+            index: None,
+            sources: Vec::new(),
+            key_sketch: None,
         }
     }
+
+    /// Factor from the recorded events back to the traffic they were sampled
+    /// from: 1 for a profile of every command
+    pub fn sampling_scale(&self) -> f64 {
+        let connections = self.connection_sampling.map_or(1.0, |rate| rate.fraction());
+        1.0 / (connections * self.event_sampling.unwrap_or(1.0))
+    }
 }
 
 impl Default for ProfileMetadata {
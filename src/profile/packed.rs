@@ -0,0 +1,308 @@
+//! Packed event records, written since profile version 3
+//!
+//! Each event is a fixed-width little-endian header followed by an optional
+//! section holding only the fields the event has, in the order of their bits
+//! in `fields`:
+//!
+//! ```text
+//! [timestamp_delta: i32][conn_id: u16][cmd_type: u8][flags: u8]
+//! [fields: u16][key_size: u32][key_hash: u64][optional section]
+//! ```
+//!
+//! Timestamps are deltas from the previous event of the profile (since
+//! version 5, of the run since the last checksum record), so events decode in
+//! the order they were written. A delta that does not fit an i32 is written
+//! as 0, with the absolute timestamp in the optional section.
+//! Sizes, TTLs, latencies and lengths there are LEB128 varints.
+
+use super::index::INDEX_VERSION;
+use super::{CommandType, Event, Flags, Response, ResponseStatus};
+use std::num::NonZero;
+
+/// Profile version whose events are packed records
+pub const PACKED_VERSION: u8 = 3;
+
+/// Bytes of the fixed-width header of a packed event
+pub const HEADER_LEN: usize = 22;
+
+const VALUE_SIZE: u16 = 1 << 0;
+const RESPONSE: u16 = 1 << 1;
+const TTL: u16 = 1 << 2;
+const SERVER_PORT: u16 = 1 << 3;
+const KEY_SEGMENTS: u16 = 1 << 4;
+const KEY: u16 = 1 << 5;
+const CLIENT_ID: u16 = 1 << 6;
+const LATENCY: u16 = 1 << 7;
+const TIMESTAMP: u16 = 1 << 8;
+
+/// Packs the events of one profile, in the order they are written
+#[derive(Debug, Default)]
+pub struct EventEncoder {
+    previous: u64,
+}
+
+impl EventEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append the packed record of `event` to `buf`
+    pub fn encode(&mut self, event: &Event, buf: &mut Vec<u8>) {
+        let delta = i32::try_from(event.timestamp as i128 - self.previous as i128).ok();
+        self.previous = event.timestamp;
+        buf.reserve(HEADER_LEN);
+
+        let mut fields = 0;
+        for (present, bit) in [
+            (event.value_size.is_some(), VALUE_SIZE),
+            (event.response.is_some(), RESPONSE),
+            (event.ttl.is_some(), TTL),
+            (event.server_port.is_some(), SERVER_PORT),
+            (event.key_segments.is_some(), KEY_SEGMENTS),
+            (event.key.is_some(), KEY),
+            (event.client_id.is_some(), CLIENT_ID),
+            (event.latency.is_some(), LATENCY),
+            (delta.is_none(), TIMESTAMP),
+        ] {
+            if present {
+                fields |= bit;
+            }
+        }
+
+        buf.extend_from_slice(&delta.unwrap_or(0).to_le_bytes());
+        buf.extend_from_slice(&event.conn_id.to_le_bytes());
+        buf.push(command_index(event.cmd_type));
+        buf.push(event.flags.bits);
+        buf.extend_from_slice(&fields.to_le_bytes());
+        buf.extend_from_slice(&event.key_size.to_le_bytes());
+        buf.extend_from_slice(&event.key_hash.to_le_bytes());
+
+        if let Some(size) = event.value_size {
+            put_varint(buf, size.get());
+        }
+        if let Some(response) = event.response {
+            buf.push(match response.status {
+                ResponseStatus::Hit => 0,
+                ResponseStatus::Miss => 1,
+                ResponseStatus::Error => 2,
+            });
+            put_varint(buf, response.value_size.map_or(0, NonZero::get));
+        }
+        if let Some(ttl) = event.ttl {
+            put_varint(buf, ttl.get());
+        }
+        if let Some(port) = event.server_port {
+            buf.extend_from_slice(&port.get().to_le_bytes());
+        }
+        if let Some(segments) = &event.key_segments {
+            put_varint(buf, segments.len() as u32);
+            for segment in segments {
+                buf.extend_from_slice(&segment.to_le_bytes());
+            }
+        }
+        if let Some(key) = &event.key {
+            put_varint(buf, key.len() as u32);
+            buf.extend_from_slice(key);
+        }
+        if let Some(client_id) = event.client_id {
+            buf.extend_from_slice(&client_id.to_le_bytes());
+        }
+        if let Some(latency) = event.latency {
+            put_varint(buf, latency);
+        }
+        if delta.is_none() {
+            buf.extend_from_slice(&event.timestamp.to_le_bytes());
+        }
+    }
+}
+
+/// Decodes the events of a profile of any version, in the order written
+#[derive(Debug)]
+pub struct EventDecoder {
+    version: u8,
+    previous: u64,
+}
+
+impl EventDecoder {
+    /// Decoder of the events of a profile whose metadata has `version`
+    pub fn new(version: u8) -> Self {
+        EventDecoder {
+            version,
+            previous: 0,
+        }
+    }
+
+    /// Note a checksum record: the next event of a profile of version 5 or
+    /// later has an absolute timestamp, so reading can start there
+    pub fn restart(&mut self) {
+        if self.version >= INDEX_VERSION {
+            self.previous = 0;
+        }
+    }
+
+    /// Decode exactly one encoded event
+    pub fn decode(&mut self, bytes: &[u8]) -> bincode::Result<Event> {
+        if self.version < PACKED_VERSION {
+            return Event::decode(bytes);
+        }
+        let event = decode_packed(bytes, self.previous)?;
+        self.previous = event.timestamp;
+        Ok(event)
+    }
+}
+
+fn decode_packed(bytes: &[u8], previous: u64) -> bincode::Result<Event> {
+    let mut input = Input(bytes);
+    let delta = i32::from_le_bytes(input.array()?);
+    let conn_id = u16::from_le_bytes(input.array()?);
+    let cmd_type = *CommandType::ALL
+        .get(input.byte()? as usize)
+        .ok_or_else(|| malformed("unknown command"))?;
+    let flags = Flags {
+        bits: input.byte()?,
+    };
+    let fields = u16::from_le_bytes(input.array()?);
+    let key_size = u32::from_le_bytes(input.array()?);
+    let key_hash = u64::from_le_bytes(input.array()?);
+
+    let has = |bit: u16| fields & bit != 0;
+    let value_size = if has(VALUE_SIZE) {
+        NonZero::new(input.varint()?)
+    } else {
+        None
+    };
+    let response = if has(RESPONSE) {
+        let status = match input.byte()? {
+            0 => ResponseStatus::Hit,
+            1 => ResponseStatus::Miss,
+            2 => ResponseStatus::Error,
+            _ => return Err(malformed("unknown response status")),
+        };
+        Some(Response {
+            status,
+            value_size: NonZero::new(input.varint()?),
+        })
+    } else {
+        None
+    };
+    let ttl = if has(TTL) {
+        NonZero::new(input.varint()?)
+    } else {
+        None
+    };
+    let server_port = if has(SERVER_PORT) {
+        NonZero::new(u16::from_le_bytes(input.array()?))
+    } else {
+        None
+    };
+    let key_segments = if has(KEY_SEGMENTS) {
+        let count = input.varint()? as usize;
+        let mut segments = Vec::with_capacity(count.min(input.0.len() / 4));
+        for _ in 0..count {
+            segments.push(u32::from_le_bytes(input.array()?));
+        }
+        Some(segments)
+    } else {
+        None
+    };
+    let key = if has(KEY) {
+        let len = input.varint()? as usize;
+        Some(input.take(len)?.to_vec())
+    } else {
+        None
+    };
+    let client_id = if has(CLIENT_ID) {
+        Some(u32::from_le_bytes(input.array()?))
+    } else {
+        None
+    };
+    let latency = if has(LATENCY) {
+        Some(input.varint()?)
+    } else {
+        None
+    };
+    let timestamp = if has(TIMESTAMP) {
+        u64::from_le_bytes(input.array()?)
+    } else {
+        previous.wrapping_add_signed(delta as i64)
+    };
+    if !input.0.is_empty() {
+        return Err(malformed("trailing bytes"));
+    }
+
+    Ok(Event {
+        timestamp,
+        conn_id,
+        cmd_type,
+        flags,
+        key_hash,
+        key_size,
+        value_size,
+        response,
+        ttl,
+        server_port,
+        key_segments,
+        key,
+        client_id,
+        latency,
+    })
+}
+
+fn command_index(cmd_type: CommandType) -> u8 {
+    CommandType::ALL
+        .iter()
+        .position(|&c| c == cmd_type)
+        .expect("every command is in ALL") as u8
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn malformed(what: &str) -> bincode::Error {
+    Box::new(bincode::ErrorKind::Custom(format!(
+        "malformed packed event: {}",
+        what
+    )))
+}
+
+/// Bytes of a packed event left to decode
+struct Input<'a>(&'a [u8]);
+
+impl<'a> Input<'a> {
+    fn take(&mut self, len: usize) -> bincode::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(malformed("truncated"));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> bincode::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("N bytes"))
+    }
+
+    fn byte(&mut self) -> bincode::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> bincode::Result<u32> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u32)
+                .checked_shl(shift)
+                .filter(|_| shift < 28 || byte < 0x10)
+                .ok_or_else(|| malformed("varint overflow"))?;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(malformed("varint overflow"))
+    }
+}
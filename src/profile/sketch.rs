@@ -0,0 +1,79 @@
+//! HyperLogLog sketch of the distinct keys of a profile
+//!
+//! The writer feeds every key hash to the sketch and stores it in the
+//! metadata, so the number of distinct keys is known without a pass over the
+//! events. With 2^12 registers of one byte, estimates are within about 1.6%
+//! (one standard error) of the true count. Sketches of the same hash merge by
+//! taking the larger register.
+
+use serde::{Deserialize, Serialize};
+
+/// Bits of the hash choosing a register
+pub const PRECISION: u32 = 12;
+
+const REGISTERS: usize = 1 << PRECISION;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeySketch {
+    /// Highest rank seen by each register: the position of the first set bit
+    /// after the register bits, from 1
+    registers: Vec<u8>,
+}
+
+impl KeySketch {
+    pub fn new() -> Self {
+        KeySketch {
+            registers: vec![0; REGISTERS],
+        }
+    }
+
+    /// Note a key hash. Key hashes are mixed again first, as those of imported
+    /// traces need not be uniform.
+    pub fn insert(&mut self, key_hash: u64) {
+        let hash = mix(key_hash);
+        let register = (hash >> (64 - PRECISION)) as usize;
+        let rank = ((hash << PRECISION).leading_zeros() + 1).min(64 - PRECISION + 1) as u8;
+        let slot = &mut self.registers[register];
+        *slot = (*slot).max(rank);
+    }
+
+    /// Add the keys of `other`
+    pub fn merge(&mut self, other: &KeySketch) {
+        for (slot, &rank) in self.registers.iter_mut().zip(&other.registers) {
+            *slot = (*slot).max(rank);
+        }
+    }
+
+    /// Estimated number of distinct keys inserted
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&rank| (-(rank as f64)).exp2())
+            .sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&rank| rank == 0).count();
+        // Linear counting is more accurate while registers are still empty
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as u64
+    }
+}
+
+impl Default for KeySketch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Finalizer of SplitMix64: spreads any key hash over every bit
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
@@ -1,23 +1,166 @@
+use crate::record::keyed_hash::{blake3, hmac_sha256, sha256};
+use anyhow::{anyhow, Context, Result};
 use siphasher::sip::SipHasher13;
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::str::FromStr;
+
+/// Keyed hash anonymizing the keys of a recording
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    #[default]
+    SipHash13,
+    /// HMAC-SHA256, truncated to 64 bits
+    HmacSha256,
+    /// Keyed BLAKE3, truncated to 64 bits
+    Blake3,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "siphash13" => Ok(HashAlgorithm::SipHash13),
+            "hmac-sha256" => Ok(HashAlgorithm::HmacSha256),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            _ => Err(format!(
+                "Invalid anonymizer: '{}'. Use 'siphash13', 'hmac-sha256' or 'blake3'",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HashAlgorithm::SipHash13 => "siphash13",
+            HashAlgorithm::HmacSha256 => "hmac-sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+enum KeyedHasher {
+    SipHash13([u8; 16]),
+    HmacSha256(Vec<u8>),
+    Blake3([u8; 32]),
+}
 
 pub struct Anonymizer {
-    salt: u64,
+    hasher: KeyedHasher,
+    /// Byte separating the segments of a key, when segments are hashed too
+    delimiter: Option<u8>,
 }
 
 impl Anonymizer {
+    /// SipHash-1-3 anonymizer keyed by `salt`
     pub fn new(salt: u64) -> Self {
-        Anonymizer { salt }
+        Self::with_salt(HashAlgorithm::SipHash13, salt)
+    }
+
+    /// Anonymizer hashing with `algorithm`, keyed by `salt`. SipHash-1-3 keeps
+    /// the hashes of recordings made before algorithms could be chosen.
+    pub fn with_salt(algorithm: HashAlgorithm, salt: u64) -> Self {
+        match algorithm {
+            HashAlgorithm::SipHash13 => {
+                let salt = salt.to_le_bytes();
+                let mut key = [0u8; 16];
+                key[0..8].copy_from_slice(&salt);
+                key[8..16].copy_from_slice(&salt);
+                Anonymizer {
+                    hasher: KeyedHasher::SipHash13(key),
+                    delimiter: None,
+                }
+            }
+            _ => Self::with_key(algorithm, &salt.to_le_bytes()),
+        }
+    }
+
+    /// Anonymizer hashing with `algorithm`, keyed by the bytes of `key`.
+    /// SipHash-1-3 and BLAKE3 take fixed-size keys, derived from `key` with
+    /// SHA-256.
+    pub fn with_key(algorithm: HashAlgorithm, key: &[u8]) -> Self {
+        let hasher = match algorithm {
+            HashAlgorithm::SipHash13 => {
+                let digest = sha256(&[key]);
+                KeyedHasher::SipHash13(digest[..16].try_into().expect("16 bytes"))
+            }
+            HashAlgorithm::HmacSha256 => KeyedHasher::HmacSha256(key.to_vec()),
+            HashAlgorithm::Blake3 => KeyedHasher::Blake3(sha256(&[key])),
+        };
+        Anonymizer {
+            hasher,
+            delimiter: None,
+        }
+    }
+
+    /// Also hash each segment of a key split on `delimiter`, so that keys
+    /// like `user:1234:profile` keep their namespace structure
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = Some(delimiter);
+        self
     }
 
     pub fn hash_key(&self, key: &[u8]) -> u64 {
-        let key_bytes = self.salt.to_le_bytes();
-        let mut hasher_key = [0u8; 16];
-        hasher_key[0..8].copy_from_slice(&key_bytes);
-        hasher_key[8..16].copy_from_slice(&key_bytes);
+        match &self.hasher {
+            KeyedHasher::SipHash13(hasher_key) => {
+                let mut hasher = SipHasher13::new_with_key(hasher_key);
+                key.hash(&mut hasher);
+                hasher.finish()
+            }
+            KeyedHasher::HmacSha256(secret) => truncate(&hmac_sha256(secret, key)),
+            KeyedHasher::Blake3(secret) => truncate(&blake3(Some(secret), key)),
+        }
+    }
+
+    /// Hash of a client address, truncated to 32 bits: an anonymous id for
+    /// the client
+    pub fn hash_ip(&self, ip: IpAddr) -> u32 {
+        let id = match ip {
+            IpAddr::V4(ip) => self.hash_key(&ip.octets()),
+            IpAddr::V6(ip) => self.hash_key(&ip.octets()),
+        };
+        id as u32
+    }
 
-        let mut hasher = SipHasher13::new_with_key(&hasher_key);
-        key.hash(&mut hasher);
-        hasher.finish()
+    /// Hash of each segment of `key`, truncated to 32 bits, when a delimiter
+    /// is set. Equal segments hash alike wherever they appear.
+    pub fn hash_segments(&self, key: &[u8]) -> Option<Vec<u32>> {
+        let delimiter = self.delimiter?;
+        Some(
+            key.split(|&byte| byte == delimiter)
+                .map(|segment| self.hash_key(segment) as u32)
+                .collect(),
+        )
+    }
+}
+
+fn truncate(digest: &[u8; 32]) -> u64 {
+    u64::from_le_bytes(digest[..8].try_into().expect("8 bytes"))
+}
+
+/// Read an anonymization key from `path`, refusing files that other users
+/// could read. The whole file is the key.
+pub fn read_key_file(path: &str) -> Result<Vec<u8>> {
+    let mode = std::fs::metadata(path)
+        .context(format!("failed to open key file {}", path))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(anyhow!(
+            "key file {} is accessible by other users (mode {:o}); restrict it with chmod 600",
+            path,
+            mode & 0o777
+        ));
+    }
+    let key = std::fs::read(path).context(format!("failed to read key file {}", path))?;
+    if key.is_empty() {
+        return Err(anyhow!("key file {} is empty", path));
     }
+    Ok(key)
 }
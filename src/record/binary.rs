@@ -0,0 +1,245 @@
+//! Binary memcache protocol frames
+//!
+//! Every frame is a 24-byte header followed by extras, key and value. The
+//! header gives the three lengths, so frames are delimited without looking at
+//! their contents. Responses echo the opaque of the request they answer.
+
+use crate::profile::{CommandType, Flags, Protocol, Response, ResponseStatus};
+use crate::record::parser::{Framed, FramedResponse, ParsedCommand};
+use std::num::NonZero;
+
+pub const REQUEST_MAGIC: u8 = 0x80;
+pub const RESPONSE_MAGIC: u8 = 0x81;
+pub const HEADER_LEN: usize = 24;
+
+/// Header fields shared by requests and responses
+struct Header {
+    opcode: u8,
+    key_len: usize,
+    extras_len: usize,
+    /// vbucket id of a request, status of a response
+    vbucket_or_status: u16,
+    body_len: usize,
+    opaque: u32,
+    cas: u64,
+}
+
+impl Header {
+    fn parse(input: &[u8]) -> Option<Self> {
+        let header = input.get(..HEADER_LEN)?;
+        Some(Header {
+            opcode: header[1],
+            key_len: u16::from_be_bytes([header[2], header[3]]) as usize,
+            extras_len: header[4] as usize,
+            vbucket_or_status: u16::from_be_bytes([header[6], header[7]]),
+            body_len: u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize,
+            opaque: u32::from_be_bytes([header[12], header[13], header[14], header[15]]),
+            cas: u64::from_be_bytes(header[16..24].try_into().ok()?),
+        })
+    }
+
+    fn frame_len(&self) -> usize {
+        HEADER_LEN + self.body_len
+    }
+
+    fn value_len(&self) -> Option<usize> {
+        self.body_len.checked_sub(self.extras_len + self.key_len)
+    }
+}
+
+/// Command type of a request opcode, and whether the opcode is a quiet variant
+fn opcode_command(opcode: u8) -> Option<(CommandType, bool)> {
+    let command = match opcode {
+        0x00 | 0x0c => (CommandType::Get, false), // get, getk
+        0x09 | 0x0d => (CommandType::Get, true),  // getq, getkq
+        0x01 => (CommandType::Set, false),
+        0x11 => (CommandType::Set, true),
+        0x02 => (CommandType::Add, false),
+        0x12 => (CommandType::Add, true),
+        0x03 => (CommandType::Replace, false),
+        0x13 => (CommandType::Replace, true),
+        0x0e => (CommandType::Append, false),
+        0x19 => (CommandType::Append, true),
+        0x0f => (CommandType::Prepend, false),
+        0x1a => (CommandType::Prepend, true),
+        0x05 => (CommandType::Incr, false),
+        0x15 => (CommandType::Incr, true),
+        0x06 => (CommandType::Decr, false),
+        0x16 => (CommandType::Decr, true),
+        0x04 => (CommandType::Delete, false),
+        0x14 => (CommandType::Delete, true),
+        0x1c => (CommandType::Touch, false),
+        0x1d => (CommandType::Gat, false),
+        0x1e => (CommandType::Gat, true),
+        0x0a => (CommandType::Noop, false),
+        _ => return None,
+    };
+    Some(command)
+}
+
+/// Opcode of the request sending `cmd_type`; a cas is a set carrying a cas value
+pub fn command_opcode(cmd_type: CommandType) -> u8 {
+    match cmd_type {
+        CommandType::Get => 0x00,
+        CommandType::Set | CommandType::Cas => 0x01,
+        CommandType::Add => 0x02,
+        CommandType::Replace => 0x03,
+        CommandType::Delete => 0x04,
+        CommandType::Incr => 0x05,
+        CommandType::Decr => 0x06,
+        CommandType::Noop => 0x0a,
+        CommandType::Append => 0x0e,
+        CommandType::Prepend => 0x0f,
+        CommandType::Touch => 0x1c,
+        CommandType::Gat => 0x1d,
+    }
+}
+
+/// Opcode of the quiet variant of `cmd_type`, answered only on errors and
+/// (for retrievals) hits. Touch and noop have none.
+pub fn quiet_opcode(cmd_type: CommandType) -> Option<u8> {
+    let opcode = match cmd_type {
+        CommandType::Get => 0x09,
+        CommandType::Set | CommandType::Cas => 0x11,
+        CommandType::Add => 0x12,
+        CommandType::Replace => 0x13,
+        CommandType::Delete => 0x14,
+        CommandType::Incr => 0x15,
+        CommandType::Decr => 0x16,
+        CommandType::Append => 0x19,
+        CommandType::Prepend => 0x1a,
+        CommandType::Gat => 0x1e,
+        CommandType::Touch | CommandType::Noop => return None,
+    };
+    Some(opcode)
+}
+
+/// Request frame of `opcode` with its extras, key and value
+pub fn encode_request(
+    opcode: u8,
+    opaque: u32,
+    cas: u64,
+    extras: &[u8],
+    key: &[u8],
+    value: &[u8],
+) -> Vec<u8> {
+    let body_len = extras.len() + key.len() + value.len();
+    let mut frame = Vec::with_capacity(HEADER_LEN + body_len);
+    frame.push(REQUEST_MAGIC);
+    frame.push(opcode);
+    frame.extend_from_slice(&(key.len() as u16).to_be_bytes());
+    frame.push(extras.len() as u8);
+    // Data type, vbucket id
+    frame.extend_from_slice(&[0, 0, 0]);
+    frame.extend_from_slice(&(body_len as u32).to_be_bytes());
+    frame.extend_from_slice(&opaque.to_be_bytes());
+    frame.extend_from_slice(&cas.to_be_bytes());
+    frame.extend_from_slice(extras);
+    frame.extend_from_slice(key);
+    frame.extend_from_slice(value);
+    frame
+}
+
+/// Whether a response frame reports a missing key
+pub fn is_not_found(response: &[u8]) -> bool {
+    response.first() == Some(&RESPONSE_MAGIC) && response.get(6..8) == Some(&[0x00, 0x01])
+}
+
+/// Value carried by a successful response frame, `None` for any other response
+pub fn response_value(response: &[u8]) -> Option<&[u8]> {
+    let header = Header::parse(response)?;
+    if response[0] != RESPONSE_MAGIC || header.vbucket_or_status != 0 {
+        return None;
+    }
+    let start = HEADER_LEN + header.extras_len + header.key_len;
+    response.get(start..header.frame_len())
+}
+
+/// Quiet opcodes not recorded as events (quitq, flushq, the range
+/// commands): only errors are answered
+fn is_quiet_opcode(opcode: u8) -> bool {
+    matches!(opcode, 0x17 | 0x18 | 0x32 | 0x34 | 0x36)
+}
+
+/// Frame the binary request at the front of `input`
+pub fn frame_request(input: &[u8]) -> Framed {
+    let Some(header) = Header::parse(input) else {
+        return Framed::Incomplete;
+    };
+    let length = header.frame_len();
+    if input.len() < length {
+        return Framed::Incomplete;
+    }
+    let Some(value_len) = header.value_len() else {
+        // Inconsistent lengths: not a frame we can trust
+        return Framed::Skip(length);
+    };
+
+    let Some((mut cmd_type, quiet)) = opcode_command(header.opcode) else {
+        return if is_quiet_opcode(header.opcode) {
+            Framed::Skip(length)
+        } else {
+            Framed::Other(length)
+        };
+    };
+    // A set carrying a cas value only stores if the item is unchanged
+    if cmd_type == CommandType::Set && header.cas != 0 {
+        cmd_type = CommandType::Cas;
+    }
+    let extras = &input[HEADER_LEN..HEADER_LEN + header.extras_len];
+    // Storage extras: flags, exptime; touch and gat extras: exptime
+    let exptime_offset = match cmd_type {
+        CommandType::Set | CommandType::Cas | CommandType::Add | CommandType::Replace => Some(4),
+        CommandType::Touch | CommandType::Gat => Some(0),
+        _ => None,
+    };
+    let exptime = exptime_offset
+        .and_then(|offset| extras.get(offset..offset + 4))
+        .map_or(0, |bytes| {
+            u32::from_be_bytes(bytes.try_into().expect("4 bytes")) as i64
+        });
+    let key_start = HEADER_LEN + header.extras_len;
+    let flags = if quiet {
+        Flags::empty().with_quiet()
+    } else {
+        Flags::empty()
+    };
+    Framed::Command(
+        ParsedCommand {
+            cmd_type,
+            key_range: key_start..key_start + header.key_len,
+            more_keys: Vec::new(),
+            value_size: cmd_type.has_value().then_some(value_len as u32),
+            flags,
+            protocol: Protocol::Binary,
+            opaque: Some(header.opaque),
+            exptime,
+        },
+        length,
+    )
+}
+
+/// Frame the binary response at the front of `input`
+pub fn frame_response(input: &[u8]) -> FramedResponse {
+    let Some(header) = Header::parse(input) else {
+        return FramedResponse::Incomplete;
+    };
+    let length = header.frame_len();
+    if input.len() < length {
+        return FramedResponse::Incomplete;
+    }
+
+    let status = match header.vbucket_or_status {
+        0x0000 => ResponseStatus::Hit,
+        // Key not found, key exists, item not stored
+        0x0001 | 0x0002 | 0x0005 => ResponseStatus::Miss,
+        _ => ResponseStatus::Error,
+    };
+    let value_size = match opcode_command(header.opcode) {
+        Some((cmd_type, _)) if cmd_type.is_retrieval() && status == ResponseStatus::Hit => {
+            header.value_len().and_then(|len| NonZero::new(len as u32))
+        }
+        _ => None,
+    };
+    FramedResponse::Response(Response { status, value_size }, length, Some(header.opaque))
+}
@@ -1,11 +1,46 @@
-use anyhow::{Context, Result};
-use pcap::Capture;
+use crate::record::defrag::is_fragment;
+use crate::record::packet::{link_payload, parse_ip, LinkType};
+use crate::record::pcapng::{is_pcapng, PcapngReader};
+use crate::record::ports::Ports;
+use anyhow::{anyhow, Context, Result};
+use pcap::{BpfProgram, Capture, Linktype};
+use std::collections::hash_map::{Entry, HashMap};
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::path::Path;
 
+/// BPF filter selecting the traffic of `ports`, along with every IP fragment:
+/// fragments past the first carry no TCP header to match the port on. A user
+/// `filter` further restricts what is captured.
+pub fn capture_filter(ports: &Ports, filter: Option<&str>) -> String {
+    let selection = format!(
+        "({}) or (ip[6:2] & 0x3fff != 0) or (ip6 and ip6[6] == 44)",
+        ports.filter()
+    );
+    match filter {
+        Some(filter) => format!("({}) and ({})", selection, filter),
+        None => selection,
+    }
+}
+
+/// Source name that reads a capture from stdin
+pub const STDIN_SOURCE: &str = "-";
+
+/// A packet read from a capture source
+#[derive(Debug, Clone, Copy)]
+pub struct CapturedPacket<'a> {
+    pub data: &'a [u8],
+    /// pcap data link type (DLT) of this packet
+    pub datalink: i32,
+    /// Capture time in microseconds since the Unix epoch, when the source
+    /// records it (capture files)
+    pub timestamp: Option<u64>,
+}
+
 /// Common interface for packet capture backends
 pub trait PacketSource {
     /// Read next packet from source
-    fn next_packet(&mut self) -> Result<&[u8]>;
+    fn next_packet(&mut self) -> Result<CapturedPacket<'_>>;
 
     /// Get human-readable source description (interface name or file path)
     fn source_info(&self) -> &str;
@@ -13,6 +48,10 @@ pub trait PacketSource {
     /// Whether source is finite (file) vs continuous (interface)
     fn is_finite(&self) -> bool;
 
+    /// pcap data link type (DLT) of the captured packets; a pcapng file
+    /// gives that of its first interface
+    fn datalink(&self) -> i32;
+
     /// Optional: Get capture statistics (when available)
     fn stats(&mut self) -> Option<CaptureStats> {
         None // Default: no stats
@@ -34,7 +73,7 @@ pub struct LiveCapture {
 }
 
 impl LiveCapture {
-    pub fn new(interface: &str, port: u16) -> Result<Self> {
+    pub fn new(interface: &str, ports: &Ports, filter: Option<&str>) -> Result<Self> {
         let mut cap = Capture::from_device(interface)
             .context(format!("failed to open device: {}", interface))?
             .promisc(true)
@@ -42,7 +81,7 @@ impl LiveCapture {
             .open()
             .context("failed to open capture")?;
 
-        let filter = format!("tcp port {}", port);
+        let filter = capture_filter(ports, filter);
         cap.filter(&filter, true).context("failed to set filter")?;
 
         Ok(LiveCapture {
@@ -53,11 +92,16 @@ impl LiveCapture {
 }
 
 impl PacketSource for LiveCapture {
-    fn next_packet(&mut self) -> Result<&[u8]> {
+    fn next_packet(&mut self) -> Result<CapturedPacket<'_>> {
+        let datalink = self.handle.get_datalink().0;
         self.handle
             .next_packet()
             .context("failed to read packet")
-            .map(|pkt| pkt.data)
+            .map(|pkt| CapturedPacket {
+                data: pkt.data,
+                datalink,
+                timestamp: None,
+            })
     }
 
     fn source_info(&self) -> &str {
@@ -68,6 +112,10 @@ impl PacketSource for LiveCapture {
         false // Network interface is continuous
     }
 
+    fn datalink(&self) -> i32 {
+        self.handle.get_datalink().0
+    }
+
     fn stats(&mut self) -> Option<CaptureStats> {
         self.handle.stats().ok().map(|s| CaptureStats {
             packets_received: s.received as u64,
@@ -84,11 +132,26 @@ pub struct FileCapture {
 }
 
 impl FileCapture {
-    pub fn new(path: &str, port: u16) -> Result<Self> {
-        let mut cap =
+    pub fn new(path: &str, ports: &Ports, filter: Option<&str>) -> Result<Self> {
+        let cap =
             Capture::from_file(path).context(format!("failed to open pcap file: {}", path))?;
+        Self::with_handle(cap, path, ports, filter)
+    }
 
-        let filter = format!("tcp port {}", port);
+    /// Capture stream read from stdin, e.g. piped from `tcpdump -w -`
+    pub fn stdin(ports: &Ports, filter: Option<&str>) -> Result<Self> {
+        // SAFETY: the capture takes stdin over; nothing else in record reads it
+        let cap = unsafe { Capture::from_raw_fd(0) }.context("failed to read pcap from stdin")?;
+        Self::with_handle(cap, STDIN_SOURCE, ports, filter)
+    }
+
+    fn with_handle(
+        mut cap: Capture<pcap::Offline>,
+        path: &str,
+        ports: &Ports,
+        filter: Option<&str>,
+    ) -> Result<Self> {
+        let filter = capture_filter(ports, filter);
         cap.filter(&filter, true).context("failed to set filter")?;
 
         Ok(FileCapture {
@@ -99,11 +162,18 @@ impl FileCapture {
 }
 
 impl PacketSource for FileCapture {
-    fn next_packet(&mut self) -> Result<&[u8]> {
+    fn next_packet(&mut self) -> Result<CapturedPacket<'_>> {
+        let datalink = self.handle.get_datalink().0;
         self.handle
             .next_packet()
             .context("failed to read packet")
-            .map(|pkt| pkt.data)
+            .map(|pkt| CapturedPacket {
+                data: pkt.data,
+                datalink,
+                timestamp: Some(
+                    pkt.header.ts.tv_sec as u64 * 1_000_000 + pkt.header.ts.tv_usec as u64,
+                ),
+            })
     }
 
     fn source_info(&self) -> &str {
@@ -113,6 +183,121 @@ impl PacketSource for FileCapture {
     fn is_finite(&self) -> bool {
         true // File has end
     }
+
+    fn datalink(&self) -> i32 {
+        self.handle.get_datalink().0
+    }
+}
+
+/// pcapng file capture (offline), read without libpcap
+pub struct PcapngCapture {
+    reader: PcapngReader<BufReader<File>>,
+    path: String,
+    ports: Ports,
+    /// User filter, compiled for each link type of the capture
+    filter: Option<String>,
+    programs: HashMap<i32, BpfProgram>,
+    datalink: i32,
+    packets_read: u64,
+}
+
+impl PcapngCapture {
+    pub fn new(path: &str, ports: &Ports, filter: Option<&str>) -> Result<Self> {
+        let file = File::open(path).context(format!("failed to open pcapng file: {}", path))?;
+        let mut reader = PcapngReader::new(BufReader::new(file))
+            .context(format!("failed to read pcapng file: {}", path))?;
+        let datalink = reader.datalink()?;
+
+        // Compiled up front so that an invalid filter fails before any packet
+        let mut programs = HashMap::new();
+        if let Some(filter) = filter {
+            programs.insert(datalink, compile_filter(filter, datalink)?);
+        }
+
+        Ok(PcapngCapture {
+            reader,
+            path: path.to_string(),
+            ports: ports.clone(),
+            filter: filter.map(str::to_string),
+            programs,
+            datalink,
+            packets_read: 0,
+        })
+    }
+}
+
+/// `filter` compiled for packets of link type `datalink`, as libpcap would
+/// apply it to a capture of that link type
+fn compile_filter(filter: &str, datalink: i32) -> Result<BpfProgram> {
+    Capture::dead(Linktype(datalink))
+        .context("failed to open a pcap handle to compile the filter")?
+        .compile(filter, true)
+        .context(format!("invalid filter: {}", filter))
+}
+
+impl PacketSource for PcapngCapture {
+    fn next_packet(&mut self) -> Result<CapturedPacket<'_>> {
+        loop {
+            if !self.reader.read_packet()? {
+                return Err(anyhow!("end of pcapng file"));
+            }
+            self.packets_read += 1;
+            let packet = self.reader.packet().expect("packet just read");
+
+            // Same selection as the filter of libpcap captures
+            let selected = LinkType::from_dlt(packet.datalink)
+                .and_then(|link_type| link_payload(link_type, packet.data))
+                .is_some_and(|ip| {
+                    is_fragment(ip)
+                        || parse_ip(ip).is_some_and(|segment| {
+                            self.ports.contains(segment.tuple.src_port)
+                                || self.ports.contains(segment.tuple.dst_port)
+                        })
+                });
+            let selected = selected
+                && match &self.filter {
+                    None => true,
+                    Some(filter) => {
+                        let program = match self.programs.entry(packet.datalink) {
+                            Entry::Occupied(entry) => entry.into_mut(),
+                            Entry::Vacant(entry) => {
+                                entry.insert(compile_filter(filter, packet.datalink)?)
+                            }
+                        };
+                        program.filter(packet.data)
+                    }
+                };
+            if selected {
+                break;
+            }
+        }
+        let packet = self.reader.packet().expect("packet just read");
+        Ok(CapturedPacket {
+            data: packet.data,
+            datalink: packet.datalink,
+            timestamp: packet.timestamp,
+        })
+    }
+
+    fn source_info(&self) -> &str {
+        &self.path
+    }
+
+    fn is_finite(&self) -> bool {
+        true
+    }
+
+    fn datalink(&self) -> i32 {
+        self.datalink
+    }
+
+    fn stats(&mut self) -> Option<CaptureStats> {
+        self.reader.dropped().map(|dropped| CaptureStats {
+            packets_received: self.packets_read,
+            packets_dropped: dropped,
+            bytes_received: 0,
+        })
+    }
 }
 
 pub struct PacketCapture {
@@ -125,13 +310,24 @@ impl PacketCapture {
         Path::new(source).is_file()
     }
 
-    /// Create a packet capture from a source (interface or PCAP file)
+    /// Create a packet capture from a source (interface, PCAP file, or `-`
+    /// for a capture piped to stdin)
     /// Auto-detects the type by checking if source is a file
-    pub fn from_source(source: &str, port: u16) -> Result<Self> {
-        let packet_source: Box<dyn PacketSource> = if Self::is_file(source) {
-            Box::new(FileCapture::new(source, port)?)
+    pub fn from_source(source: &str, ports: &Ports, filter: Option<&str>) -> Result<Self> {
+        let packet_source: Box<dyn PacketSource> = if source == STDIN_SOURCE {
+            Box::new(FileCapture::stdin(ports, filter)?)
+        } else if Self::is_file(source) {
+            let mut header = [0u8; 4];
+            let read = File::open(source)
+                .and_then(|mut file| file.read(&mut header))
+                .unwrap_or(0);
+            if is_pcapng(&header[..read]) {
+                Box::new(PcapngCapture::new(source, ports, filter)?)
+            } else {
+                Box::new(FileCapture::new(source, ports, filter)?)
+            }
         } else {
-            Box::new(LiveCapture::new(source, port)?)
+            Box::new(LiveCapture::new(source, ports, filter)?)
         };
 
         Ok(PacketCapture {
@@ -140,8 +336,8 @@ impl PacketCapture {
     }
 
     /// Legacy method for backwards compatibility
-    pub fn new(interface: &str, port: u16) -> Result<Self> {
-        Self::from_source(interface, port)
+    pub fn new(interface: &str, ports: &Ports) -> Result<Self> {
+        Self::from_source(interface, ports, None)
     }
 
     pub fn list_devices() -> Result<Vec<String>> {
@@ -149,7 +345,7 @@ impl PacketCapture {
         Ok(devices.into_iter().map(|d| d.name).collect())
     }
 
-    pub fn next_packet(&mut self) -> Result<&[u8]> {
+    pub fn next_packet(&mut self) -> Result<CapturedPacket<'_>> {
         self.source.next_packet()
     }
 
@@ -164,4 +360,15 @@ impl PacketCapture {
     pub fn stats(&mut self) -> Option<CaptureStats> {
         self.source.stats()
     }
+
+    /// pcap data link type (DLT) of the captured packets
+    pub fn datalink(&self) -> i32 {
+        self.source.datalink()
+    }
+
+    /// Link-layer framing of the captured packets
+    pub fn link_type(&self) -> Result<LinkType> {
+        let dlt = self.source.datalink();
+        LinkType::from_dlt(dlt).ok_or_else(|| anyhow!("unsupported link type (DLT {})", dlt))
+    }
 }
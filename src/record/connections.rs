@@ -0,0 +1,76 @@
+//! Connection identity: one conn_id per live client connection
+//!
+//! Connections are identified by their client-to-server 4-tuple. Each gets
+//! the lowest conn_id not held by another live connection, and gives it back
+//! when it closes, so conn_ids stay within the number of concurrent
+//! connections however many come and go during a capture.
+
+use crate::record::packet::FourTuple;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+#[derive(Default)]
+pub struct ConnectionTracker {
+    ids: HashMap<FourTuple, u16>,
+    /// Ids released by closed connections, lowest first
+    free: BinaryHeap<Reverse<u16>>,
+    /// Lowest id never handed out
+    next_id: u32,
+    opened: u64,
+}
+
+impl ConnectionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// conn_id of the connection with client-to-server tuple `client`,
+    /// assigned on first sight; `None` when every conn_id is in use
+    pub fn conn_id(&mut self, client: FourTuple) -> Option<u16> {
+        if let Some(&id) = self.ids.get(&client) {
+            return Some(id);
+        }
+        let id = match self.free.pop() {
+            Some(Reverse(id)) => id,
+            None if self.next_id <= u16::MAX as u32 => {
+                self.next_id += 1;
+                (self.next_id - 1) as u16
+            }
+            None => return None,
+        };
+        self.ids.insert(client, id);
+        self.opened += 1;
+        tracing::debug!("Connection {} opened: {:?}", id, client);
+        Some(id)
+    }
+
+    /// A SYN on `client`: a connection reusing the tuple of one that closed
+    /// unseen gets a conn_id of its own
+    pub fn open(&mut self, client: FourTuple) -> Option<u16> {
+        self.close(client);
+        self.conn_id(client)
+    }
+
+    /// Release the conn_id of a closed connection, returning it
+    pub fn close(&mut self, client: FourTuple) -> Option<u16> {
+        let id = self.ids.remove(&client)?;
+        self.free.push(Reverse(id));
+        tracing::debug!("Connection {} closed: {:?}", id, client);
+        Some(id)
+    }
+
+    /// Connections currently open
+    pub fn active(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Connections seen since the capture started
+    pub fn opened(&self) -> u64 {
+        self.opened
+    }
+
+    /// Highest number of connections open at once
+    pub fn peak(&self) -> usize {
+        self.next_id as usize
+    }
+}
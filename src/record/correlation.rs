@@ -0,0 +1,346 @@
+//! Pairing server responses with the commands they answer
+//!
+//! Memcache answers the commands of a connection in order, so each response
+//! goes to the oldest unanswered command of its connection. Events are held
+//! until answered and released in the order their commands were sent, so the
+//! profile keeps its timeline.
+//!
+//! Binary responses carry the opaque of their request instead. Quiet binary
+//! and meta commands are not answered on their usual outcome: a later
+//! response on the connection that is not theirs, or the `MN` ending a meta
+//! batch, means every quiet command before it succeeded (or, for a get,
+//! missed). Text `noreply` commands are never answered.
+//!
+//! A multi-key text retrieval (`get a b c`) is recorded as one event per key,
+//! all answered by the same response: a key hit if one of its VALUE blocks
+//! names it, and missed otherwise.
+
+use crate::profile::{CommandType, Event, Protocol, Response, ResponseStatus};
+use crate::record::packet::FourTuple;
+use crate::record::parser::ParsedCommand;
+use std::collections::{HashMap, VecDeque};
+
+/// Commands held for their response before being released unanswered
+pub const MAX_UNANSWERED: usize = 100_000;
+
+struct Held {
+    event: Event,
+    done: bool,
+}
+
+impl Held {
+    fn answer(&mut self, response: Response, timestamp: u64) {
+        self.done = true;
+        self.event.response = Some(response);
+        let latency = timestamp.saturating_sub(self.event.timestamp);
+        self.event.latency = Some(latency.min(u32::MAX as u64) as u32);
+    }
+}
+
+/// A command waiting for its response
+struct Outstanding {
+    /// Position of its event, `None` for a command that is not recorded
+    position: Option<u64>,
+    opaque: Option<u32>,
+    /// Outcome the server keeps quiet about, for a quiet command
+    quiet: Option<ResponseStatus>,
+    /// Every key of a multi-key retrieval, with the position of its event
+    /// (`None` if not recorded); empty for other commands
+    keys: Vec<(Vec<u8>, Option<u64>)>,
+}
+
+/// Which outcomes of `command` the server answers
+enum Answers {
+    All,
+    /// Every outcome but this one (quiet binary and meta commands)
+    AllBut(ResponseStatus),
+    /// None (text commands sent with noreply)
+    None,
+}
+
+impl Answers {
+    fn of(command: &ParsedCommand) -> Self {
+        if !command.flags.has_quiet() {
+            return Answers::All;
+        }
+        match command.protocol {
+            Protocol::Ascii => Answers::None,
+            // Quiet retrievals are only answered on a hit
+            _ => match command.cmd_type {
+                CommandType::Get | CommandType::Gat | CommandType::Touch => {
+                    Answers::AllBut(ResponseStatus::Miss)
+                }
+                _ => Answers::AllBut(ResponseStatus::Hit),
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ResponseCorrelator {
+    /// Events in request order; `released` of them were already given back
+    held: VecDeque<Held>,
+    released: u64,
+    /// Positions of each connection's unanswered commands, oldest first
+    outstanding: HashMap<FourTuple, VecDeque<Outstanding>>,
+    answered: u64,
+    unanswered: u64,
+}
+
+impl ResponseCorrelator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold `event`, parsed as `command` from the connection with
+    /// client-to-server tuple `client`
+    pub fn request(&mut self, client: FourTuple, event: Event, command: &ParsedCommand) {
+        let position = self.released + self.held.len() as u64;
+        let quiet = match Answers::of(command) {
+            Answers::All => None,
+            Answers::AllBut(status) => Some(status),
+            Answers::None => {
+                // Nothing will answer it: released in its turn, without response
+                self.held.push_back(Held { event, done: true });
+                return;
+            }
+        };
+        self.held.push_back(Held { event, done: false });
+        self.outstanding
+            .entry(client)
+            .or_default()
+            .push_back(Outstanding {
+                position: Some(position),
+                opaque: command.opaque,
+                quiet,
+                keys: Vec::new(),
+            });
+    }
+
+    /// Hold the events of a multi-key retrieval parsed as `command`, one for
+    /// each of its keys (`None` for a key not recorded), which a single
+    /// response answers
+    pub fn request_keys(
+        &mut self,
+        client: FourTuple,
+        events: Vec<(Vec<u8>, Option<Event>)>,
+        command: &ParsedCommand,
+    ) {
+        let mut keys = Vec::with_capacity(events.len());
+        for (key, event) in events {
+            let position = event.map(|event| {
+                self.held.push_back(Held { event, done: false });
+                self.released + self.held.len() as u64 - 1
+            });
+            keys.push((key, position));
+        }
+        self.outstanding
+            .entry(client)
+            .or_default()
+            .push_back(Outstanding {
+                position: None,
+                opaque: command.opaque,
+                quiet: None,
+                keys,
+            });
+    }
+
+    /// A command the server may answer but that is not recorded: its
+    /// response must not go to the commands after it. `command` is `None`
+    /// for lines that are not parsed (stats, unknown commands...).
+    pub fn unrecorded(&mut self, client: FourTuple, command: Option<&ParsedCommand>) {
+        let (opaque, quiet) = match command.map(|command| (command, Answers::of(command))) {
+            None => (None, None),
+            Some((command, Answers::All)) => (command.opaque, None),
+            Some((command, Answers::AllBut(status))) => (command.opaque, Some(status)),
+            Some((_, Answers::None)) => return,
+        };
+        self.outstanding
+            .entry(client)
+            .or_default()
+            .push_back(Outstanding {
+                position: None,
+                opaque,
+                quiet,
+                keys: Vec::new(),
+            });
+    }
+
+    /// Attach `response`, captured at `timestamp` (microseconds), to the
+    /// command of `client` it answers: the oldest unanswered one that could
+    /// have been answered so, or the one with the same `opaque` for a binary
+    /// response. False if nothing was waiting for it.
+    pub fn response(
+        &mut self,
+        client: FourTuple,
+        response: Response,
+        opaque: Option<u32>,
+        timestamp: u64,
+    ) -> bool {
+        self.attach(client, response, &[], opaque, timestamp, false)
+    }
+
+    /// Attach a text retrieval `response` returning `values`, the key and
+    /// size of each of its VALUE blocks, which tell the keys of a multi-key
+    /// retrieval apart
+    pub fn retrieval(
+        &mut self,
+        client: FourTuple,
+        response: Response,
+        values: &[(&[u8], u32)],
+        timestamp: u64,
+    ) -> bool {
+        self.attach(client, response, values, None, timestamp, false)
+    }
+
+    /// Attach the `MN` ending a batch of meta commands: the quiet commands
+    /// still waiting before it had the outcome the server keeps quiet about
+    pub fn noop(&mut self, client: FourTuple, response: Response, timestamp: u64) -> bool {
+        self.attach(client, response, &[], None, timestamp, true)
+    }
+
+    fn attach(
+        &mut self,
+        client: FourTuple,
+        response: Response,
+        values: &[(&[u8], u32)],
+        opaque: Option<u32>,
+        timestamp: u64,
+        ends_batch: bool,
+    ) -> bool {
+        let Some(queue) = self.outstanding.get_mut(&client) else {
+            return false;
+        };
+        if opaque.is_some() && !queue.iter().any(|waiting| waiting.opaque == opaque) {
+            return false;
+        }
+        while let Some(waiting) = queue.pop_front() {
+            let answered = match (opaque, waiting.quiet) {
+                (Some(_), _) => waiting.opaque == opaque,
+                (None, None) => true,
+                // The outcome kept quiet about, or the end of the batch, is
+                // not the answer of a quiet command
+                (None, Some(quiet)) => !ends_batch && response.status != quiet,
+            };
+            if !waiting.keys.is_empty() {
+                // Each value goes to the first key it names still without one
+                let mut values = values.to_vec();
+                for (key, position) in waiting.keys {
+                    let Some(index) =
+                        position.and_then(|position| position.checked_sub(self.released))
+                    else {
+                        continue;
+                    };
+                    let held = &mut self.held[index as usize];
+                    if !answered {
+                        held.done = true;
+                        self.unanswered += 1;
+                        continue;
+                    }
+                    let value = values
+                        .iter()
+                        .position(|(value_key, _)| *value_key == key.as_slice())
+                        .map(|index| values.remove(index).1);
+                    let key_response = match value {
+                        Some(size) => Response {
+                            status: ResponseStatus::Hit,
+                            value_size: std::num::NonZero::new(size),
+                        },
+                        // The values returned were of other keys
+                        None if response.status == ResponseStatus::Hit => Response {
+                            status: ResponseStatus::Miss,
+                            value_size: None,
+                        },
+                        None => response,
+                    };
+                    held.answer(key_response, timestamp);
+                    self.answered += 1;
+                }
+                if answered {
+                    return true;
+                }
+                continue;
+            }
+            // The command was not recorded, or was already released unanswered
+            let Some(index) = waiting
+                .position
+                .and_then(|position| position.checked_sub(self.released))
+            else {
+                if answered {
+                    return false;
+                }
+                continue;
+            };
+            let held = &mut self.held[index as usize];
+            held.done = true;
+            if answered {
+                held.answer(response, timestamp);
+                self.answered += 1;
+                return true;
+            }
+            if let Some(status) = waiting.quiet {
+                // Skipped over by a later response: the quiet command had the
+                // outcome that is not answered
+                held.event.response = Some(Response {
+                    status,
+                    value_size: None,
+                });
+                self.answered += 1;
+            } else {
+                self.unanswered += 1;
+            }
+        }
+        false
+    }
+
+    /// The connection closed: its unanswered commands will not be answered
+    pub fn close(&mut self, client: FourTuple) {
+        for waiting in self.outstanding.remove(&client).unwrap_or_default() {
+            let positions = waiting
+                .keys
+                .iter()
+                .map(|(_, position)| *position)
+                .chain([waiting.position]);
+            for index in positions
+                .flatten()
+                .filter_map(|position| position.checked_sub(self.released))
+            {
+                self.held[index as usize].done = true;
+                self.unanswered += 1;
+            }
+        }
+    }
+
+    /// Next event whose turn has come: answered, given up on, or pushed out
+    /// by too many commands waiting behind it
+    pub fn pop_ready(&mut self) -> Option<Event> {
+        let front = self.held.front()?;
+        if !front.done {
+            if self.held.len() <= MAX_UNANSWERED {
+                return None;
+            }
+            self.unanswered += 1;
+        }
+        self.released += 1;
+        self.held.pop_front().map(|held| held.event)
+    }
+
+    /// Release every event still held, answered or not (end of capture)
+    pub fn drain(&mut self) -> impl Iterator<Item = Event> + '_ {
+        let unanswered = self.held.iter().filter(|held| !held.done).count() as u64;
+        self.unanswered += unanswered;
+        self.released += self.held.len() as u64;
+        self.outstanding.clear();
+        self.held.drain(..).map(|held| held.event)
+    }
+
+    /// Commands paired with a response
+    pub fn answered(&self) -> u64 {
+        self.answered
+    }
+
+    /// Commands released without a response
+    pub fn unanswered(&self) -> u64 {
+        self.unanswered
+    }
+}
@@ -0,0 +1,298 @@
+//! IP fragment reassembly: rebuild fragmented IPv4 and IPv6 datagrams before
+//! their TCP segment is parsed
+
+use crate::record::packet::ipv6_fragment_header;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+/// Bounds on the fragments held while waiting for the rest of their datagram
+#[derive(Debug, Clone, Copy)]
+pub struct FragmentLimits {
+    /// Time after its first fragment a datagram is given up on
+    pub timeout: Duration,
+    /// Bytes of fragment data held over every pending datagram
+    pub max_bytes: usize,
+}
+
+impl Default for FragmentLimits {
+    fn default() -> Self {
+        FragmentLimits {
+            timeout: Duration::from_secs(30),
+            max_bytes: 64 << 20,
+        }
+    }
+}
+
+/// Outcome of pushing a packet through the reassembler
+#[derive(Debug, PartialEq, Eq)]
+pub enum Defragmented<'a> {
+    /// Not a fragment: the packet as captured
+    Whole(&'a [u8]),
+    /// The last missing fragment of a datagram: the rebuilt datagram
+    Reassembled(Vec<u8>),
+    /// A fragment held until the rest of its datagram arrives
+    Pending,
+}
+
+/// Addresses, protocol family and identification shared by the fragments of
+/// one datagram
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DatagramId {
+    src: [u8; 16],
+    dst: [u8; 16],
+    id: u32,
+    /// IPv4 protocol; 0 for IPv6
+    protocol: u8,
+}
+
+/// One fragment, as read from its packet
+struct Fragment<'a> {
+    id: DatagramId,
+    /// Byte offset of the data in the datagram's fragmentable part
+    offset: usize,
+    more: bool,
+    data: &'a [u8],
+    /// Headers preceding the fragmentable part, rebuilt for the datagram; set
+    /// on the first fragment only
+    header: Option<Vec<u8>>,
+}
+
+struct Datagram {
+    header: Option<Vec<u8>>,
+    pieces: BTreeMap<usize, Vec<u8>>,
+    /// Length of the fragmentable part, known once the last fragment arrived
+    len: Option<usize>,
+    bytes: usize,
+    first_seen: u64,
+}
+
+/// Holds the fragments of incomplete datagrams
+pub struct FragmentReassembler {
+    limits: FragmentLimits,
+    pending: HashMap<DatagramId, Datagram>,
+    buffered: usize,
+    reassembled: u64,
+    /// Datagrams given up on: timed out or evicted to stay within the limits
+    abandoned: u64,
+}
+
+impl FragmentReassembler {
+    pub fn new(limits: FragmentLimits) -> Self {
+        FragmentReassembler {
+            limits,
+            pending: HashMap::new(),
+            buffered: 0,
+            reassembled: 0,
+            abandoned: 0,
+        }
+    }
+
+    /// Push an IP packet captured at `timestamp` (microseconds)
+    pub fn push<'a>(&mut self, ip: &'a [u8], timestamp: u64) -> Defragmented<'a> {
+        self.expire(timestamp);
+        let Some(fragment) = parse_fragment(ip) else {
+            return Defragmented::Whole(ip);
+        };
+        // A datagram is at most 64KB
+        if fragment.offset + fragment.data.len() > u16::MAX as usize {
+            return Defragmented::Pending;
+        }
+
+        while self.buffered + fragment.data.len() > self.limits.max_bytes {
+            if !self.evict_oldest() {
+                return Defragmented::Pending;
+            }
+        }
+
+        let datagram = self
+            .pending
+            .entry(fragment.id.clone())
+            .or_insert_with(|| Datagram {
+                header: None,
+                pieces: BTreeMap::new(),
+                len: None,
+                bytes: 0,
+                first_seen: timestamp,
+            });
+        if let Some(header) = fragment.header {
+            datagram.header = Some(header);
+        }
+        if !fragment.more {
+            datagram.len = Some(fragment.offset + fragment.data.len());
+        }
+        if let Entry::Vacant(piece) = datagram.pieces.entry(fragment.offset) {
+            piece.insert(fragment.data.to_vec());
+            datagram.bytes += fragment.data.len();
+            self.buffered += fragment.data.len();
+        }
+
+        if !is_complete(datagram) {
+            return Defragmented::Pending;
+        }
+        let datagram = self.pending.remove(&fragment.id).expect("pending datagram");
+        self.buffered -= datagram.bytes;
+        self.reassembled += 1;
+        Defragmented::Reassembled(rebuild(datagram))
+    }
+
+    /// Datagrams rebuilt from their fragments
+    pub fn reassembled(&self) -> u64 {
+        self.reassembled
+    }
+
+    /// Datagrams dropped incomplete, on timeout or to stay within the limits
+    pub fn abandoned(&self) -> u64 {
+        self.abandoned
+    }
+
+    /// Fragment bytes currently held
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    fn expire(&mut self, now: u64) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let timeout = self.limits.timeout.as_micros() as u64;
+        let (mut expired, mut expired_bytes) = (0, 0);
+        self.pending.retain(|_, datagram| {
+            let keep = now.saturating_sub(datagram.first_seen) < timeout;
+            if !keep {
+                expired += 1;
+                expired_bytes += datagram.bytes;
+            }
+            keep
+        });
+        self.abandoned += expired;
+        self.buffered -= expired_bytes;
+    }
+
+    fn evict_oldest(&mut self) -> bool {
+        let Some(oldest) = self
+            .pending
+            .iter()
+            .min_by_key(|(_, datagram)| datagram.first_seen)
+            .map(|(id, _)| id.clone())
+        else {
+            return false;
+        };
+        let datagram = self.pending.remove(&oldest).expect("oldest datagram");
+        self.buffered -= datagram.bytes;
+        self.abandoned += 1;
+        true
+    }
+}
+
+/// Whether the pieces cover the whole fragmentable part, and the headers of
+/// the first fragment are known
+fn is_complete(datagram: &Datagram) -> bool {
+    let (Some(len), Some(_)) = (datagram.len, &datagram.header) else {
+        return false;
+    };
+    let mut covered = 0;
+    for (&offset, piece) in &datagram.pieces {
+        if offset > covered {
+            return false;
+        }
+        covered = covered.max(offset + piece.len());
+    }
+    covered >= len
+}
+
+/// The datagram of complete fragments, as one unfragmented IP packet
+fn rebuild(datagram: Datagram) -> Vec<u8> {
+    let len = datagram.len.expect("complete datagram");
+    let mut packet = datagram.header.expect("complete datagram");
+    let header_len = packet.len();
+    packet.resize(header_len + len, 0);
+    for (offset, piece) in datagram.pieces {
+        let end = (offset + piece.len()).min(len);
+        packet[header_len + offset..header_len + end].copy_from_slice(&piece[..end - offset]);
+    }
+
+    if packet[0] >> 4 == 4 {
+        packet[2..4].copy_from_slice(&((header_len + len) as u16).to_be_bytes());
+        // Keep the don't-fragment flag, clear more-fragments and the offset
+        packet[6] &= 0x40;
+        packet[7] = 0;
+    } else {
+        packet[4..6].copy_from_slice(&((header_len - 40 + len) as u16).to_be_bytes());
+    }
+    packet
+}
+
+fn parse_fragment(ip: &[u8]) -> Option<Fragment<'_>> {
+    match *ip.first()? >> 4 {
+        4 => parse_ipv4_fragment(ip),
+        6 => parse_ipv6_fragment(ip),
+        _ => None,
+    }
+}
+
+fn parse_ipv4_fragment(ip: &[u8]) -> Option<Fragment<'_>> {
+    let header_len = ((*ip.first()? & 0x0f) as usize) * 4;
+    if header_len < 20 || ip.len() < header_len {
+        return None;
+    }
+    let flags_offset = u16::from_be_bytes([ip[6], ip[7]]);
+    let more = flags_offset & 0x2000 != 0;
+    let offset = (flags_offset & 0x1fff) as usize * 8;
+    if !more && offset == 0 {
+        return None;
+    }
+    let total_len = u16::from_be_bytes([ip[2], ip[3]]) as usize;
+    let data = ip.get(header_len..total_len.min(ip.len()))?;
+
+    let mut src = [0u8; 16];
+    let mut dst = [0u8; 16];
+    src[..4].copy_from_slice(&ip[12..16]);
+    dst[..4].copy_from_slice(&ip[16..20]);
+    Some(Fragment {
+        id: DatagramId {
+            src,
+            dst,
+            id: u16::from_be_bytes([ip[4], ip[5]]) as u32,
+            protocol: ip[9],
+        },
+        offset,
+        more,
+        data,
+        header: (offset == 0).then(|| ip[..header_len].to_vec()),
+    })
+}
+
+fn parse_ipv6_fragment(ip: &[u8]) -> Option<Fragment<'_>> {
+    let (next_header_at, fragment_at) = ipv6_fragment_header(ip)?;
+    let fragment = &ip[fragment_at..fragment_at + 8];
+    let offset_more = u16::from_be_bytes([fragment[2], fragment[3]]);
+    let offset = (offset_more >> 3) as usize * 8;
+    let more = offset_more & 1 != 0;
+    let payload_len = u16::from_be_bytes([ip[4], ip[5]]) as usize;
+    let data = ip.get(fragment_at + 8..(40 + payload_len).min(ip.len()))?;
+
+    // The unfragmentable headers, now naming what followed the fragment header
+    let header = (offset == 0).then(|| {
+        let mut header = ip[..fragment_at].to_vec();
+        header[next_header_at] = fragment[0];
+        header
+    });
+    Some(Fragment {
+        id: DatagramId {
+            src: ip[8..24].try_into().ok()?,
+            dst: ip[24..40].try_into().ok()?,
+            id: u32::from_be_bytes([fragment[4], fragment[5], fragment[6], fragment[7]]),
+            protocol: 0,
+        },
+        offset,
+        more,
+        data,
+        header,
+    })
+}
+
+/// Whether an IP packet is a fragment of a larger datagram
+pub fn is_fragment(ip: &[u8]) -> bool {
+    parse_fragment(ip).is_some()
+}
@@ -3,20 +3,129 @@
 use anyhow::Result;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::profile::Event;
-use crate::record::{Anonymizer, MemcacheParser, PacketCapture, ProfileWriter};
+use crate::logging::REPORT_TARGET;
+use crate::profile::{CaptureSummary, Compression, Event, SampleRate, SizeBucketing};
+use crate::record::binary;
+use crate::record::parser::{retrieved_values, ttl_from_exptime};
+use crate::record::{
+    link_payload, parse_ip, read_key_file, Anonymizer, ConnectionSampler, ConnectionTracker,
+    Defragmented, EventSampler, EventTransform, FourTuple, FragmentLimits, FragmentReassembler,
+    Framed, FramedResponse, HashAlgorithm, LinkType, MemcacheParser, PacketCapture, Passthrough,
+    Ports, ResponseCorrelator, RotatingWriter, Rotation, StreamReassembler,
+};
+
+/// Unframed bytes a stream may buffer before they are dropped; larger than
+/// any memcache item (1MB by default) with its command line
+const MAX_REQUEST_BYTES: usize = 2 << 20;
+
+/// Longest key stored in plaintext: memcached's key limit
+const MAX_PLAINTEXT_KEY: usize = 250;
+
+/// How often capture statistics are reported while recording
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Settings of a recording beyond its source, ports and output
+#[derive(Debug, Clone, Default)]
+pub struct RecordOptions {
+    /// Key anonymization salt; the current time if unset
+    pub salt: Option<u64>,
+    /// File holding the anonymization key, used instead of the salt
+    pub key_file: Option<String>,
+    /// Keyed hash anonymizing the keys
+    pub anonymizer: HashAlgorithm,
+    /// When the recording stops on its own
+    pub limits: CaptureLimits,
+    /// Split the capture into numbered profiles
+    pub rotation: Rotation,
+    /// Encoding of the event region of every profile written
+    pub compression: Compression,
+    /// Bounds on the IP fragments held for reassembly
+    pub fragments: FragmentLimits,
+    /// BPF expression the captured traffic must match besides the ports
+    pub filter: Option<String>,
+    /// Record only this share of the connections
+    pub sample_connections: Option<SampleRate>,
+    /// Record each command with this probability
+    pub sample_events: Option<f64>,
+    /// Also hash the segments of keys split on this byte
+    pub key_delimiter: Option<u8>,
+    /// Round key and value sizes up into buckets
+    pub size_buckets: SizeBucketing,
+    /// Store each key in plaintext next to its hash
+    pub plaintext_keys: bool,
+}
+
+/// Bounds after which a recording stops and finalizes its profile
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaptureLimits {
+    /// Wall-clock time since the capture started
+    pub duration: Option<Duration>,
+    /// Events written
+    pub max_events: Option<u64>,
+    /// Bytes of events written, not counting metadata trailers
+    pub max_size: Option<u64>,
+}
+
+impl CaptureLimits {
+    /// The limit `writer` reached, if any
+    fn reached(&self, writer: &RotatingWriter) -> Option<&'static str> {
+        if self
+            .max_events
+            .is_some_and(|max| writer.events_written() >= max)
+        {
+            Some("--max-events")
+        } else if self
+            .max_size
+            .is_some_and(|max| writer.bytes_written() >= max)
+        {
+            Some("--max-size")
+        } else {
+            None
+        }
+    }
+}
 
 pub fn run(source: &str, port: u16, output: &str, salt: Option<u64>) -> Result<()> {
-    let salt = salt.unwrap_or_else(|| {
+    run_with_transform(source, port, output, salt, &mut Passthrough)
+}
+
+/// Record like `run`, passing every parsed event through `transform` before
+/// it is written
+pub fn run_with_transform(
+    source: &str,
+    port: u16,
+    output: &str,
+    salt: Option<u64>,
+    transform: &mut dyn EventTransform,
+) -> Result<()> {
+    let options = RecordOptions {
+        salt,
+        ..RecordOptions::default()
+    };
+    run_with_options(source, &Ports::from(port), output, &options, transform)
+}
+
+/// Record the traffic of every port in `ports` with every setting of `options`
+pub fn run_with_options(
+    source: &str,
+    ports: &Ports,
+    output: &str,
+    options: &RecordOptions,
+    transform: &mut dyn EventTransform,
+) -> Result<()> {
+    let limits = options.limits;
+    let salt = options.salt.unwrap_or_else(|| {
         SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_secs()
     });
 
-    let mut capture = PacketCapture::from_source(source, port)?;
+    let key = options.key_file.as_deref().map(read_key_file).transpose()?;
+
+    let mut capture = PacketCapture::from_source(source, ports, options.filter.as_deref())?;
     let source_type = if capture.is_finite() {
         "file"
     } else {
@@ -26,10 +135,23 @@ pub fn run(source: &str, port: u16, output: &str, salt: Option<u64>) -> Result<(
         "Recording from {} ({}):{} to {}",
         source_type,
         source,
-        port,
+        ports,
         output
     );
-    tracing::debug!("Salt: {}", salt);
+    if let Some(filter) = &options.filter {
+        tracing::info!("Capture filter: {}", filter);
+    }
+    match &options.key_file {
+        Some(path) => tracing::info!(
+            "Anonymizing keys with {} keyed by {}",
+            options.anonymizer,
+            path
+        ),
+        None => {
+            tracing::info!("Anonymizing keys with {}", options.anonymizer);
+            tracing::debug!("Salt: {}", salt);
+        }
+    }
     tracing::info!("Capturing memcache traffic... Press Ctrl+C to stop.");
 
     if !capture.is_finite() {
@@ -44,112 +166,334 @@ pub fn run(source: &str, port: u16, output: &str, salt: Option<u64>) -> Result<(
         source_type,
         capture.source_info()
     );
+    let link_type = capture.link_type()?;
+    let datalink = capture.datalink();
+    tracing::debug!("Link type: {:?}", link_type);
     let parser = MemcacheParser::new();
-    let anonymizer = Anonymizer::new(salt);
-    let mut writer = ProfileWriter::new(output)?;
+    let mut defragmenter = FragmentReassembler::new(options.fragments);
+    let mut reassembler = StreamReassembler::new();
+    let mut connections = ConnectionTracker::new();
+    let mut correlator = ResponseCorrelator::new();
+    let mut anonymizer = match &key {
+        Some(key) => Anonymizer::with_key(options.anonymizer, key),
+        None => Anonymizer::with_salt(options.anonymizer, salt),
+    };
+    if let Some(delimiter) = options.key_delimiter {
+        anonymizer = anonymizer.with_delimiter(delimiter);
+    }
+    let mut writer = RotatingWriter::new(output, options.rotation, options.compression)?;
+    let sampler = options.sample_connections.map(|rate| {
+        tracing::info!("Recording {} of the connections", rate);
+        writer.set_connection_sampling(rate);
+        ConnectionSampler::new(rate)
+    });
+    // Seeded from the salt, so a recording with a fixed salt samples alike
+    let mut event_sampler = options.sample_events.map(|probability| {
+        tracing::info!("Recording {:.2}% of the commands", probability * 100.0);
+        writer.set_event_sampling(probability);
+        EventSampler::new(probability, salt)
+    });
+    let buckets = options.size_buckets;
+    if buckets.is_enabled() {
+        if let Some(key) = buckets.key {
+            tracing::info!("Key sizes {}", key.description());
+        }
+        if let Some(value) = buckets.value {
+            tracing::info!("Value sizes {}", value.description());
+        }
+        writer.set_size_buckets(buckets);
+    }
+    if options.plaintext_keys {
+        tracing::warn!("*****************************************************************");
+        tracing::warn!(
+            "Anonymization is OFF: keys are stored in plaintext in {}",
+            output
+        );
+        tracing::warn!("Keep the profile on trusted machines and never share it");
+        tracing::warn!("*****************************************************************");
+        writer.set_plaintext_keys();
+    }
 
     // Set up signal handling for graceful shutdown
     let should_exit = Arc::new(AtomicBool::new(false));
     let should_exit_clone = Arc::clone(&should_exit);
 
-    ctrlc::set_handler(move || {
+    // Only one handler can be installed per process: a second recording
+    // from the same process keeps the first one's
+    if let Err(e) = ctrlc::set_handler(move || {
         tracing::info!("Received Ctrl+C, shutting down gracefully...");
         should_exit_clone.store(true, Ordering::SeqCst);
-    })
-    .expect("Error setting Ctrl+C handler");
+    }) {
+        tracing::debug!("Ctrl+C handler not installed: {}", e);
+    }
 
     // Track connection state
     let mut packet_count = 0u64;
     let mut event_count = 0u64;
+    let mut dropped_count = 0u64;
+    let mut skipped_count = 0u64;
+    let mut parse_errors = 0u64;
+    let mut sampled_out = 0u64;
 
     tracing::info!("Capturing packets... (Press Ctrl+C to stop)");
+    let started = Instant::now();
+    let mut limit_reached = None;
+    let mut last_report = Instant::now();
+    let mut events_at_report = 0u64;
+
+    'capture: loop {
+        // Write the events whose commands were answered, in request order
+        while let Some(mut event) = correlator.pop_ready() {
+            buckets.apply(&mut event);
+            writer.write_event(&event)?;
+            event_count += 1;
+
+            if let Some(limit) = limits.reached(&writer) {
+                limit_reached = Some(limit);
+                break 'capture;
+            }
+        }
 
-    loop {
         // Check if we should exit
         if should_exit.load(Ordering::SeqCst) {
             tracing::info!("Shutdown signal received");
             break;
         }
+        if limits
+            .duration
+            .is_some_and(|duration| started.elapsed() >= duration)
+        {
+            tracing::info!("Capture duration reached");
+            break;
+        }
+        writer.rotate_if_due()?;
+
+        if last_report.elapsed() >= STATS_INTERVAL {
+            let summary = CaptureSummary {
+                packets_received: packet_count,
+                packets_dropped: kernel_drops(&mut capture),
+                packets_skipped: skipped_count,
+                parse_errors,
+            };
+            let rate =
+                (event_count - events_at_report) as f64 / last_report.elapsed().as_secs_f64();
+            tracing::info!(
+                target: REPORT_TARGET,
+                "Captured {} packets{}, {} events ({:.0}/s), {} parse failures",
+                packet_count,
+                summary
+                    .packets_dropped
+                    .map(|dropped| format!(" ({} dropped by kernel)", dropped))
+                    .unwrap_or_default(),
+                event_count,
+                rate,
+                parse_errors
+            );
+            writer.set_capture_summary(summary);
+            last_report = Instant::now();
+            events_at_report = event_count;
+        }
 
         // Capture packet
         match capture.next_packet() {
-            Ok(packet_data) => {
+            Ok(packet) => {
                 packet_count += 1;
+                // Capture files keep the time each packet was captured at
+                let timestamp = packet.timestamp.unwrap_or_else(|| {
+                    SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap()
+                        .as_micros() as u64
+                });
 
-                // pcap returns full packets with headers. For loopback (lo0) on macOS,
-                // we need to skip the link layer header (typically 14 bytes for ethernet,
-                // but loopback has a different format)
-                // Try to find memcache protocol markers to skip headers
-                let payload = if let Some(pos) = packet_data.windows(2).position(|w| w == b"\r\n") {
-                    // Found \r\n which suggests we're at or near application data
-                    // Search backwards for command start (GET, SET, etc.)
-                    if let Some(cmd_start) = packet_data[..pos]
-                        .windows(3)
-                        .rposition(|w| w == b"get" || w == b"set" || w == b"del" || w == b"noo")
-                    {
-                        &packet_data[cmd_start..]
-                    } else {
-                        packet_data
+                // Files may mix link types (pcapng interfaces)
+                let link_type = if packet.datalink == datalink {
+                    Some(link_type)
+                } else {
+                    LinkType::from_dlt(packet.datalink)
+                };
+                let Some(ip) = link_type.and_then(|link| link_payload(link, packet.data)) else {
+                    skipped_count += 1;
+                    continue;
+                };
+                let datagram;
+                let ip = match defragmenter.push(ip, timestamp) {
+                    Defragmented::Whole(ip) => ip,
+                    Defragmented::Reassembled(reassembled) => {
+                        datagram = reassembled;
+                        &datagram
                     }
+                    Defragmented::Pending => continue,
+                };
+                let Some(segment) = parse_ip(ip) else {
+                    skipped_count += 1;
+                    continue;
+                };
+                // Fragments of other traffic pass the capture filter too
+                let from_client = ports.contains(segment.tuple.dst_port);
+                if !from_client && !ports.contains(segment.tuple.src_port) {
+                    continue;
+                }
+                let client = if from_client {
+                    segment.tuple
                 } else {
-                    packet_data
+                    segment.tuple.reversed()
                 };
-
-                // Try to parse as memcache command
-                if let Ok(data_str) = std::str::from_utf8(payload) {
-                    if data_str.contains('\r') && data_str.contains('\n') {
-                        // Try parsing as a command
-                        match parser.parse_command(payload) {
-                            Ok((cmd, _)) => {
-                                // Extract the actual key from the payload
-                                let key_bytes = &payload[cmd.key_range.clone()];
-                                let key_size = cmd.key_range.len() as u32;
-
-                                // Create event from parsed command
-                                let event = Event {
-                                    timestamp: SystemTime::now()
-                                        .duration_since(SystemTime::UNIX_EPOCH)
-                                        .unwrap()
-                                        .as_micros()
-                                        as u64,
-                                    conn_id: (packet_count % 32) as u16, // Connection ID derived from packet count
-                                    cmd_type: cmd.cmd_type,
-                                    key_hash: anonymizer.hash_key(key_bytes), // Hash the actual key
-                                    key_size,
-                                    value_size: cmd.value_size.and_then(std::num::NonZero::new),
-                                    flags: cmd.flags,
-                                };
-
-                                writer.write_event(&event)?;
-                                event_count += 1;
-
-                                if packet_count.is_multiple_of(1000) {
-                                    tracing::info!(
-                                        "Captured {} packets, {} events",
-                                        packet_count,
-                                        event_count
-                                    );
-                                }
-                            }
-                            Err(e) => {
-                                if packet_count <= 10 {
-                                    let data_preview = String::from_utf8_lossy(packet_data);
-                                    let preview = if data_preview.len() > 100 {
-                                        format!("{}...", &data_preview[..100])
+                if sampler
+                    .as_ref()
+                    .is_some_and(|sampler| !sampler.keeps(&client))
+                {
+                    continue;
+                }
+                let closed = segment.is_fin() || segment.is_rst();
+                // Server-to-client streams carry the responses
+                if !from_client {
+                    if let Some(stream) = reassembler.push(&segment) {
+                        let mut consumed = 0;
+                        while consumed < stream.len() {
+                            match parser.frame_response(&stream[consumed..]) {
+                                FramedResponse::Incomplete => break,
+                                FramedResponse::Skip(length) => consumed += length,
+                                FramedResponse::Response(response, length, opaque) => {
+                                    let framed = &stream[consumed..consumed + length];
+                                    // MN ends a batch of quiet meta commands
+                                    let paired = if framed.starts_with(b"MN") {
+                                        correlator.noop(client, response, timestamp)
+                                    } else if framed.starts_with(b"VALUE") {
+                                        let values = retrieved_values(framed);
+                                        correlator.retrieval(client, response, &values, timestamp)
                                     } else {
-                                        data_preview.to_string()
+                                        correlator.response(client, response, opaque, timestamp)
                                     };
+                                    consumed += length;
+                                    if !paired {
+                                        tracing::trace!("Response without a recorded command");
+                                    }
+                                }
+                            }
+                        }
+                        stream.drain(..consumed);
+                        if stream.len() > MAX_REQUEST_BYTES {
+                            stream.clear();
+                        }
+                    }
+                    if closed {
+                        close_connection(
+                            client,
+                            &mut connections,
+                            &mut reassembler,
+                            &mut correlator,
+                        );
+                    }
+                    continue;
+                }
+                if segment.is_syn() {
+                    connections.open(client);
+                }
+
+                if let Some(stream) = reassembler.push(&segment) {
+                    let Some(conn_id) = connections.conn_id(client) else {
+                        tracing::warn!("Every conn_id is in use, dropping traffic of {:?}", client);
+                        stream.clear();
+                        continue;
+                    };
+                    let mut consumed = 0;
+                    while consumed < stream.len() {
+                        let request = &stream[consumed..];
+                        let (cmd, length) = match parser.frame_request(request) {
+                            Framed::Incomplete => break,
+                            Framed::Skip(length) => {
+                                // The server answers an unknown text command with ERROR
+                                if request.first() != Some(&binary::REQUEST_MAGIC) {
+                                    correlator.unrecorded(client, None);
+                                }
+                                parse_errors += 1;
+                                if parse_errors <= 10 {
                                     tracing::debug!(
-                                        "Parse error on packet {}: {} | Data (len={}): {:?}",
+                                        "Skipping unparsed line on packet {}: {:?}",
                                         packet_count,
-                                        e,
-                                        packet_data.len(),
-                                        preview
+                                        String::from_utf8_lossy(&request[..length.min(100)])
                                     );
                                 }
+                                consumed += length;
+                                continue;
+                            }
+                            Framed::Other(length) => {
+                                correlator.unrecorded(client, None);
+                                consumed += length;
+                                continue;
                             }
+                            Framed::Command(cmd, length) => (cmd, length),
+                        };
+                        consumed += length;
+
+                        if event_sampler
+                            .as_mut()
+                            .is_some_and(|sampler| !sampler.keeps())
+                        {
+                            sampled_out += 1;
+                            correlator.unrecorded(client, Some(&cmd));
+                            continue;
+                        }
+
+                        // One event per key: `get a b c` is three retrievals
+                        let mut events = Vec::with_capacity(1 + cmd.more_keys.len());
+                        for key_range in std::iter::once(&cmd.key_range).chain(&cmd.more_keys) {
+                            // Extract the actual key from the request
+                            let key_bytes = &request[key_range.clone()];
+                            let key_size = key_range.len() as u32;
+
+                            // Create event from parsed command
+                            let event = Event {
+                                timestamp,
+                                conn_id,
+                                cmd_type: cmd.cmd_type,
+                                key_hash: anonymizer.hash_key(key_bytes), // Hash the actual key
+                                key_size,
+                                value_size: cmd.value_size.and_then(std::num::NonZero::new),
+                                flags: cmd.flags,
+                                response: None,
+                                ttl: ttl_from_exptime(cmd.exptime, timestamp / 1_000_000),
+                                server_port: std::num::NonZero::new(client.dst_port),
+                                key_segments: anonymizer.hash_segments(key_bytes),
+                                key: (options.plaintext_keys
+                                    && key_bytes.len() <= MAX_PLAINTEXT_KEY)
+                                    .then(|| key_bytes.to_vec()),
+                                client_id: Some(anonymizer.hash_ip(client.src_ip)),
+                                latency: None,
+                            };
+
+                            let event = transform.transform(event, key_bytes);
+                            if event.is_none() {
+                                dropped_count += 1;
+                            }
+                            events.push((key_bytes.to_vec(), event));
+                        }
+                        let Some(recorded) = events.iter().find_map(|(_, event)| event.as_ref())
+                        else {
+                            correlator.unrecorded(client, Some(&cmd));
+                            continue;
+                        };
+                        writer.record_protocol(recorded.conn_id, cmd.protocol);
+                        if cmd.more_keys.is_empty() {
+                            let (_, Some(event)) = events.remove(0) else {
+                                unreachable!("a recorded key");
+                            };
+                            correlator.request(client, event, &cmd);
+                        } else {
+                            correlator.request_keys(client, events, &cmd);
                         }
                     }
+                    stream.drain(..consumed);
+
+                    // A request this large is not memcache traffic we can frame
+                    if stream.len() > MAX_REQUEST_BYTES {
+                        tracing::debug!("Dropping {} unframed bytes of a stream", stream.len());
+                        stream.clear();
+                    }
+                }
+                if closed {
+                    close_connection(client, &mut connections, &mut reassembler, &mut correlator);
                 }
             }
             Err(_) => {
@@ -165,14 +509,117 @@ pub fn run(source: &str, port: u16, output: &str, salt: Option<u64>) -> Result<(
         }
     }
 
+    // Commands still waiting for a response are written without one, unless
+    // the profile is already full
+    match limit_reached {
+        Some(limit) => tracing::info!("Stopping: {} reached", limit),
+        None => {
+            for mut event in correlator.drain() {
+                buckets.apply(&mut event);
+                writer.write_event(&event)?;
+                event_count += 1;
+                if limits.reached(&writer).is_some() {
+                    break;
+                }
+            }
+        }
+    }
+
     // Finalize profile
     tracing::info!("Finalizing profile...");
-    writer.finish()?;
+    let packets_dropped = kernel_drops(&mut capture);
+    writer.set_capture_summary(CaptureSummary {
+        packets_received: packet_count,
+        packets_dropped,
+        packets_skipped: skipped_count,
+        parse_errors,
+    });
+    let profiles = writer.finish()?;
 
-    tracing::info!("✓ Recording complete");
-    tracing::info!("  Profile: {}", output);
-    tracing::info!("  Packets captured: {}", packet_count);
-    tracing::info!("  Events recorded: {}", event_count);
+    tracing::info!(target: REPORT_TARGET, "✓ Recording complete");
+    match profiles.as_slice() {
+        [profile] => tracing::info!(target: REPORT_TARGET, "  Profile: {}", profile),
+        [first, .., last] => tracing::info!(
+            target: REPORT_TARGET,
+            "  Profiles: {} ({} to {})",
+            profiles.len(),
+            first,
+            last
+        ),
+        [] => {}
+    }
+    tracing::info!(target: REPORT_TARGET, "  Packets captured: {}", packet_count);
+    if let Some(dropped) = packets_dropped {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "  Packets dropped by kernel: {}",
+            dropped
+        );
+    }
+    tracing::info!(target: REPORT_TARGET, "  Events recorded: {}", event_count);
+    tracing::info!(
+        target: REPORT_TARGET,
+        "  Responses matched: {} ({} commands unanswered)",
+        correlator.answered(),
+        correlator.unanswered()
+    );
+    tracing::info!(
+        target: REPORT_TARGET,
+        "  Connections: {} ({} open at most)",
+        connections.opened(),
+        connections.peak()
+    );
+    if skipped_count > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "  Packets skipped (not IP/TCP): {}",
+            skipped_count
+        );
+    }
+    if defragmenter.reassembled() > 0 || defragmenter.abandoned() > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "  IP datagrams reassembled: {} ({} dropped incomplete)",
+            defragmenter.reassembled(),
+            defragmenter.abandoned()
+        );
+    }
+    if parse_errors > 0 {
+        tracing::info!(target: REPORT_TARGET, "  Parse failures: {}", parse_errors);
+    }
+    if sampled_out > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "  Commands sampled out: {}",
+            sampled_out
+        );
+    }
+    if dropped_count > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "  Events dropped by transform: {}",
+            dropped_count
+        );
+    }
 
     Ok(())
 }
+
+/// Packets the kernel dropped so far, when the capture source reports it
+fn kernel_drops(capture: &mut PacketCapture) -> Option<u64> {
+    capture.stats().map(|stats| stats.packets_dropped)
+}
+
+/// Forget a closed connection: release its conn_id and streams, and stop
+/// waiting for responses to its commands
+fn close_connection(
+    client: FourTuple,
+    connections: &mut ConnectionTracker,
+    reassembler: &mut StreamReassembler,
+    correlator: &mut ResponseCorrelator,
+) {
+    correlator.close(client);
+    connections.close(client);
+    reassembler.remove(&client);
+    reassembler.remove(&client.reversed());
+}
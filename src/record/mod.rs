@@ -1,13 +1,36 @@
 //! Capture and anonymization logic
 
 pub mod anonymizer;
+pub mod binary;
 pub mod capture;
+pub mod connections;
+pub mod correlation;
+pub mod defrag;
+pub mod keyed_hash;
 pub mod main;
+pub mod packet;
 pub mod parser;
+pub mod pcapng;
+pub mod ports;
+pub mod reassembly;
+pub mod rotation;
+pub mod sampling;
+pub mod transform;
 pub mod writer;
 
-pub use anonymizer::Anonymizer;
-pub use capture::PacketCapture;
-pub use main::run as run_record;
-pub use parser::MemcacheParser;
+pub use anonymizer::{read_key_file, Anonymizer, HashAlgorithm};
+pub use capture::{CapturedPacket, PacketCapture};
+pub use connections::ConnectionTracker;
+pub use correlation::ResponseCorrelator;
+pub use defrag::{Defragmented, FragmentLimits, FragmentReassembler};
+pub use main::{
+    run as run_record, run_with_options, run_with_transform, CaptureLimits, RecordOptions,
+};
+pub use packet::{link_payload, parse_ip, parse_packet, FourTuple, LinkType, TcpSegment};
+pub use parser::{Framed, FramedResponse, MemcacheParser};
+pub use ports::Ports;
+pub use reassembly::StreamReassembler;
+pub use rotation::{RotatingWriter, Rotation};
+pub use sampling::{ConnectionSampler, EventSampler};
+pub use transform::{EventTransform, Passthrough, TransformChain};
 pub use writer::ProfileWriter;
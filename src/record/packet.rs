@@ -0,0 +1,260 @@
+//! Link-layer, IP and TCP decapsulation of captured packets
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Link-layer framing of a capture, from its pcap data link type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkType {
+    /// Ethernet II (also Linux loopback)
+    Ethernet,
+    /// BSD loopback: 4-byte address family in host byte order (macOS lo0)
+    Null,
+    /// OpenBSD loopback: 4-byte address family in network byte order
+    Loop,
+    /// Linux cooked capture (`-i any`)
+    LinuxSll,
+    /// Bare IP packets, no link-layer header
+    Raw,
+}
+
+impl LinkType {
+    /// Map a pcap DLT value, `None` for framings we cannot decapsulate
+    pub fn from_dlt(dlt: i32) -> Option<Self> {
+        match dlt {
+            0 => Some(LinkType::Null),
+            1 => Some(LinkType::Ethernet),
+            12 | 14 | 101 => Some(LinkType::Raw),
+            108 => Some(LinkType::Loop),
+            113 => Some(LinkType::LinuxSll),
+            _ => None,
+        }
+    }
+}
+
+/// Addresses and ports of one direction of a TCP connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FourTuple {
+    pub src_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_ip: IpAddr,
+    pub dst_port: u16,
+}
+
+impl FourTuple {
+    /// The same connection seen from the other side
+    pub fn reversed(&self) -> Self {
+        FourTuple {
+            src_ip: self.dst_ip,
+            src_port: self.dst_port,
+            dst_ip: self.src_ip,
+            dst_port: self.src_port,
+        }
+    }
+}
+
+pub const TCP_FIN: u8 = 0x01;
+pub const TCP_SYN: u8 = 0x02;
+pub const TCP_RST: u8 = 0x04;
+pub const TCP_ACK: u8 = 0x10;
+
+/// A decapsulated TCP segment, borrowing its payload from the packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpSegment<'a> {
+    pub tuple: FourTuple,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub payload: &'a [u8],
+}
+
+impl TcpSegment<'_> {
+    pub fn is_syn(&self) -> bool {
+        self.flags & TCP_SYN != 0
+    }
+
+    pub fn is_fin(&self) -> bool {
+        self.flags & TCP_FIN != 0
+    }
+
+    pub fn is_rst(&self) -> bool {
+        self.flags & TCP_RST != 0
+    }
+}
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+/// 802.1Q VLAN tag, and the outer tags of 802.1ad (QinQ) stacking
+const ETHERTYPE_VLAN: [u16; 3] = [0x8100, 0x88a8, 0x9100];
+const AF_INET: u32 = 2;
+/// AF_INET6 of the BSDs (24), FreeBSD (28) and macOS (30)
+const AF_INET6: [u32; 3] = [24, 28, 30];
+const IPPROTO_TCP: u8 = 6;
+
+/// IPv6 extension headers that may precede the TCP header
+const IPV6_HOP_BY_HOP: u8 = 0;
+const IPV6_ROUTING: u8 = 43;
+const IPV6_FRAGMENT: u8 = 44;
+const IPV6_AUTH: u8 = 51;
+const IPV6_DEST_OPTIONS: u8 = 60;
+
+/// Extract the TCP segment of a captured packet, `None` if the packet is not
+/// IPv4 or IPv6 TCP, or is truncated. VLAN tags are skipped. The payload is
+/// exactly the TCP payload: link-layer padding past the IP length is dropped.
+pub fn parse_packet(link_type: LinkType, data: &[u8]) -> Option<TcpSegment<'_>> {
+    link_payload(link_type, data).and_then(parse_ip)
+}
+
+/// The IP packet of a captured frame, `None` if it carries something else
+pub fn link_payload(link_type: LinkType, data: &[u8]) -> Option<&[u8]> {
+    let ip = match link_type {
+        LinkType::Ethernet => {
+            let mut ethertype_at = 12;
+            let mut ethertype = u16::from_be_bytes([*data.get(12)?, *data.get(13)?]);
+            while ETHERTYPE_VLAN.contains(&ethertype) {
+                ethertype_at += 4;
+                ethertype =
+                    u16::from_be_bytes([*data.get(ethertype_at)?, *data.get(ethertype_at + 1)?]);
+            }
+            is_ip_ethertype(ethertype).then_some(data.get(ethertype_at + 2..)?)?
+        }
+        LinkType::Null | LinkType::Loop => {
+            let header: [u8; 4] = data.get(..4)?.try_into().ok()?;
+            let family = if link_type == LinkType::Null {
+                // Host byte order of the capturing machine: accept either
+                u32::from_le_bytes(header).min(u32::from_be_bytes(header))
+            } else {
+                u32::from_be_bytes(header)
+            };
+            (family == AF_INET || AF_INET6.contains(&family)).then_some(data.get(4..)?)?
+        }
+        LinkType::LinuxSll => {
+            let protocol = u16::from_be_bytes([*data.get(14)?, *data.get(15)?]);
+            is_ip_ethertype(protocol).then_some(data.get(16..)?)?
+        }
+        LinkType::Raw => data,
+    };
+    Some(ip)
+}
+
+/// Extract the TCP segment of an IPv4 or IPv6 packet
+pub fn parse_ip(ip: &[u8]) -> Option<TcpSegment<'_>> {
+    match *ip.first()? >> 4 {
+        4 => parse_ipv4(ip),
+        6 => parse_ipv6(ip),
+        _ => None,
+    }
+}
+
+fn is_ip_ethertype(ethertype: u16) -> bool {
+    ethertype == ETHERTYPE_IPV4 || ethertype == ETHERTYPE_IPV6
+}
+
+fn parse_ipv4(ip: &[u8]) -> Option<TcpSegment<'_>> {
+    let version_ihl = *ip.first()?;
+    if ip.len() < 20 {
+        return None;
+    }
+    let header_len = ((version_ihl & 0x0f) as usize) * 4;
+    let total_len = u16::from_be_bytes([*ip.get(2)?, *ip.get(3)?]) as usize;
+    if header_len < 20 || total_len < header_len || ip[9] != IPPROTO_TCP {
+        return None;
+    }
+    // Fragments other than the first carry no TCP header
+    let fragment_offset = u16::from_be_bytes([ip[6], ip[7]]) & 0x1fff;
+    if fragment_offset != 0 {
+        return None;
+    }
+    let src_ip = IpAddr::V4(Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]));
+    let dst_ip = IpAddr::V4(Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]));
+    // Captures with a snaplen shorter than the packet keep what was captured
+    let tcp = ip.get(header_len..total_len.min(ip.len()))?;
+    parse_tcp(tcp, src_ip, dst_ip)
+}
+
+fn parse_ipv6(ip: &[u8]) -> Option<TcpSegment<'_>> {
+    let header = ip.get(..40)?;
+    let payload_len = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let src: [u8; 16] = header[8..24].try_into().ok()?;
+    let dst: [u8; 16] = header[24..40].try_into().ok()?;
+    // A zero payload length is a jumbogram: the payload runs to the end
+    let end = if payload_len == 0 {
+        ip.len()
+    } else {
+        (40 + payload_len).min(ip.len())
+    };
+
+    let mut next_header = header[6];
+    let mut offset = 40;
+    loop {
+        match next_header {
+            IPPROTO_TCP => break,
+            IPV6_HOP_BY_HOP | IPV6_ROUTING | IPV6_DEST_OPTIONS => {
+                next_header = *ip.get(offset)?;
+                offset += (*ip.get(offset + 1)? as usize + 1) * 8;
+            }
+            IPV6_AUTH => {
+                next_header = *ip.get(offset)?;
+                offset += (*ip.get(offset + 1)? as usize + 2) * 4;
+            }
+            IPV6_FRAGMENT => {
+                // Fragments other than the first carry no TCP header
+                let fragment_offset =
+                    u16::from_be_bytes([*ip.get(offset + 2)?, *ip.get(offset + 3)?]) >> 3;
+                if fragment_offset != 0 {
+                    return None;
+                }
+                next_header = *ip.get(offset)?;
+                offset += 8;
+            }
+            _ => return None,
+        }
+    }
+
+    let tcp = ip.get(offset..end)?;
+    parse_tcp(
+        tcp,
+        IpAddr::V6(Ipv6Addr::from(src)),
+        IpAddr::V6(Ipv6Addr::from(dst)),
+    )
+}
+
+/// Offsets in an IPv6 packet of its fragment header and of the next header
+/// field that names it, if the packet is a fragment
+pub fn ipv6_fragment_header(ip: &[u8]) -> Option<(usize, usize)> {
+    let mut next_header_at = 6;
+    let mut offset = 40;
+    loop {
+        match *ip.get(next_header_at)? {
+            IPV6_FRAGMENT => return (offset + 8 <= ip.len()).then_some((next_header_at, offset)),
+            IPV6_HOP_BY_HOP | IPV6_ROUTING | IPV6_DEST_OPTIONS => {
+                next_header_at = offset;
+                offset += (*ip.get(offset + 1)? as usize + 1) * 8;
+            }
+            IPV6_AUTH => {
+                next_header_at = offset;
+                offset += (*ip.get(offset + 1)? as usize + 2) * 4;
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn parse_tcp(tcp: &[u8], src_ip: IpAddr, dst_ip: IpAddr) -> Option<TcpSegment<'_>> {
+    let header: &[u8; 20] = tcp.get(..20)?.try_into().ok()?;
+    let data_offset = ((header[12] >> 4) as usize) * 4;
+    if data_offset < 20 {
+        return None;
+    }
+    Some(TcpSegment {
+        tuple: FourTuple {
+            src_ip,
+            src_port: u16::from_be_bytes([header[0], header[1]]),
+            dst_ip,
+            dst_port: u16::from_be_bytes([header[2], header[3]]),
+        },
+        seq: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+        ack: u32::from_be_bytes([header[8], header[9], header[10], header[11]]),
+        flags: header[13],
+        payload: tcp.get(data_offset..)?,
+    })
+}
@@ -1,11 +1,47 @@
-use crate::profile::{CommandType, Flags};
+use crate::profile::{CommandType, Flags, Protocol, Response, ResponseStatus};
+use crate::record::binary;
 use anyhow::{anyhow, Result};
+use std::num::NonZero;
 
 pub struct ParsedCommand {
     pub cmd_type: CommandType,
     pub key_range: std::ops::Range<usize>,
+    /// Keys after the first of a multi-key text retrieval (`get a b c`)
+    pub more_keys: Vec<std::ops::Range<usize>>,
     pub value_size: Option<u32>,
     pub flags: Flags,
+    pub protocol: Protocol,
+    /// Binary protocol opaque, echoed by the response
+    pub opaque: Option<u32>,
+    /// Expiration as sent: 0 for none, seconds from now, or a Unix time
+    pub exptime: i64,
+}
+
+/// Longest relative exptime: larger ones are Unix times (memcached semantics)
+const MAX_RELATIVE_EXPTIME: i64 = 60 * 60 * 24 * 30;
+
+/// TTL of a command sent at `now` (Unix seconds) with `exptime`. An exptime
+/// already in the past expires the item at once, recorded as a 1s TTL.
+pub fn ttl_from_exptime(exptime: i64, now: u64) -> Option<NonZero<u32>> {
+    let ttl = match exptime {
+        0 => return None,
+        ..0 => 1,
+        1..=MAX_RELATIVE_EXPTIME => exptime,
+        _ => (exptime - now as i64).max(1),
+    };
+    NonZero::new(ttl.min(u32::MAX as i64) as u32)
+}
+
+/// What the front of a request stream holds
+pub enum Framed {
+    /// A complete command, spanning this many bytes (including any data block)
+    Command(ParsedCommand, usize),
+    /// A command the server answers but that is not recorded (stats, version...)
+    Other(usize),
+    /// A line that is not a supported command, to be skipped
+    Skip(usize),
+    /// More bytes are needed
+    Incomplete,
 }
 
 pub struct MemcacheParser;
@@ -26,7 +62,8 @@ impl MemcacheParser {
             .iter()
             .position(|&b| b == b'\n')
             .ok_or(anyhow!("no newline"))?;
-        let line = &input[..line_end - 1]; // exclude \r
+        let line = &input[..line_end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
         let rest = &input[line_end + 1..];
 
         let parts: Vec<&[u8]> = line.split(|&b| b == b' ').collect();
@@ -35,39 +72,277 @@ impl MemcacheParser {
         }
 
         let cmd = std::str::from_utf8(parts[0])?.to_lowercase();
+        let protocol = match cmd.as_str() {
+            "mg" | "ms" | "md" | "ma" | "mn" => Protocol::Meta,
+            _ => Protocol::Ascii,
+        };
+        // Meta flags follow the key, and the data length for ms
+        let meta_flags = match cmd.as_str() {
+            "ms" => parts.get(3..).unwrap_or_default(),
+            _ => parts.get(2..).unwrap_or_default(),
+        };
         let cmd_type = match cmd.as_str() {
-            "get" => CommandType::Get,
-            "mg" => CommandType::Get, // Meta protocol
-            "set" => CommandType::Set,
-            "ms" => CommandType::Set, // Meta protocol
-            "delete" => CommandType::Delete,
-            "md" => CommandType::Delete, // Meta protocol
-            "noop" => CommandType::Noop,
-            "mn" => CommandType::Noop, // Meta protocol
-            _ => return Err(anyhow!("unknown command: {}", cmd)),
+            "gets" => CommandType::Get,
+            "gats" => CommandType::Gat,
+            "mg" | "ms" | "ma" => meta_command_type(&cmd, meta_flags),
+            "md" => CommandType::Delete,
+            "mn" => CommandType::Noop,
+            name => CommandType::from_name(name).ok_or(anyhow!("unknown command: {}", cmd))?,
         };
 
-        if parts.len() < 2 {
-            return Err(anyhow!("missing key"));
-        }
+        // gat <exptime> <key>, every other command names its key first
+        let key_field = match cmd.as_str() {
+            "gat" | "gats" => 2,
+            _ => 1,
+        };
+        let field_range = |field: usize| {
+            let start = parts[..field].iter().map(|part| part.len() + 1).sum();
+            start..start + parts[field].len()
+        };
+        let key_range = match parts.get(key_field) {
+            Some(_) => field_range(key_field),
+            None if cmd_type == CommandType::Noop => 0..0,
+            None => return Err(anyhow!("missing key")),
+        };
+        // get, gets, gat and gats take any number of keys
+        let more_keys = match cmd.as_str() {
+            "get" | "gets" | "gat" | "gats" => {
+                (key_field + 1..parts.len()).map(field_range).collect()
+            }
+            _ => Vec::new(),
+        };
+
+        // ms <key> <datalen>, set <key> <flags> <exptime> <bytes>
+        let size_field = match protocol {
+            Protocol::Meta => 2,
+            _ => 4,
+        };
+        let value_size = match parts.get(size_field) {
+            Some(size) if cmd_type.has_value() => Some(std::str::from_utf8(size)?.parse()?),
+            _ => None,
+        };
 
-        let key_start = parts[0].len() + 1;
-        let key_end = key_start + parts[1].len();
+        // set <key> <flags> <exptime> <bytes>, touch <key> <exptime>,
+        // gat <exptime> <key>; meta commands take a T<exptime> flag
+        let exptime_field = match cmd.as_str() {
+            _ if protocol == Protocol::Meta => {
+                meta_flags.iter().find_map(|flag| flag.strip_prefix(b"T"))
+            }
+            "gat" | "gats" => parts.get(1).copied(),
+            "touch" => parts.get(2).copied(),
+            _ if cmd_type.has_value() => parts.get(3).copied(),
+            _ => None,
+        };
+        let exptime = match exptime_field {
+            Some(field) => std::str::from_utf8(field)?.parse()?,
+            None => 0,
+        };
 
-        let value_size = if cmd_type == CommandType::Set && parts.len() > 2 {
-            Some(std::str::from_utf8(parts[2])?.parse()?)
+        // Meta commands take a q flag; text commands end with noreply, except
+        // retrievals, which are always answered
+        let quiet = match protocol {
+            Protocol::Meta => meta_flags.contains(&b"q".as_slice()),
+            _ if cmd_type.is_retrieval() => false,
+            _ => parts.last() == Some(&b"noreply".as_slice()),
+        };
+        let flags = if quiet {
+            Flags::empty().with_quiet()
         } else {
-            None
+            Flags::empty()
         };
 
         Ok((
             ParsedCommand {
                 cmd_type,
-                key_range: key_start..key_end,
+                key_range,
+                more_keys,
                 value_size,
-                flags: Flags::empty(),
+                flags,
+                protocol,
+                opaque: None,
+                exptime,
             },
             rest,
         ))
     }
+
+    /// Frame the request at the front of `input`: a command is complete once
+    /// its line and, for a storage command, its data block and trailing CRLF have arrived.
+    /// Binary requests are told apart from text by their magic byte.
+    pub fn frame_request(&self, input: &[u8]) -> Framed {
+        if input.first() == Some(&binary::REQUEST_MAGIC) {
+            return binary::frame_request(input);
+        }
+        let Some(line_end) = input.iter().position(|&b| b == b'\n') else {
+            return Framed::Incomplete;
+        };
+        match self.parse_command(input) {
+            Ok((cmd, _)) => {
+                let length = match cmd.value_size {
+                    Some(size) => line_end + 1 + size as usize + 2,
+                    None => line_end + 1,
+                };
+                if input.len() < length {
+                    Framed::Incomplete
+                } else {
+                    Framed::Command(cmd, length)
+                }
+            }
+            Err(_) => {
+                let word = input[..line_end].split(|&b| b == b' ' || b == b'\r').next();
+                match word.unwrap_or_default() {
+                    b"stats" | b"version" | b"flush_all" | b"verbosity" => {
+                        Framed::Other(line_end + 1)
+                    }
+                    _ => Framed::Skip(line_end + 1),
+                }
+            }
+        }
+    }
+}
+
+/// What the front of a response stream holds
+pub enum FramedResponse {
+    /// A complete response, spanning this many bytes (including any values),
+    /// with the opaque of a binary response
+    Response(Response, usize, Option<u32>),
+    /// A line that is not a response to a recorded command, to be skipped
+    Skip(usize),
+    /// More bytes are needed
+    Incomplete,
+}
+
+impl MemcacheParser {
+    /// Frame the response at the front of `input`. ASCII retrievals span every
+    /// VALUE block up to END; meta VA responses include their data block.
+    /// Binary responses are told apart from text by their magic byte.
+    pub fn frame_response(&self, input: &[u8]) -> FramedResponse {
+        if input.first() == Some(&binary::RESPONSE_MAGIC) {
+            return binary::frame_response(input);
+        }
+        let mut offset = 0;
+        let mut value_size: Option<u32> = None;
+        loop {
+            let Some(line_end) = input[offset..].iter().position(|&b| b == b'\n') else {
+                return FramedResponse::Incomplete;
+            };
+            let line = &input[offset..offset + line_end];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let next = offset + line_end + 1;
+            let mut parts = line.split(|&b| b == b' ');
+            let word = parts.next().unwrap_or_default();
+
+            // A retrieval cut short of its END still returned its values
+            if let Some(size) = value_size {
+                if word != b"VALUE" && word != b"END" {
+                    let response = Response {
+                        status: ResponseStatus::Hit,
+                        value_size: NonZero::new(size),
+                    };
+                    return FramedResponse::Response(response, offset, None);
+                }
+            }
+
+            let status = match word {
+                // ASCII retrieval: values until END
+                b"VALUE" => {
+                    let Some(bytes) = parts.nth(2).and_then(parse_size) else {
+                        return FramedResponse::Skip(next);
+                    };
+                    offset = next + bytes as usize + 2;
+                    if input.len() < offset {
+                        return FramedResponse::Incomplete;
+                    }
+                    *value_size.get_or_insert(0) += bytes;
+                    continue;
+                }
+                b"END" if value_size.is_some() => ResponseStatus::Hit,
+                b"END" | b"EN" | b"NF" | b"NS" | b"EX" | b"NOT_FOUND" | b"NOT_STORED"
+                | b"EXISTS" => ResponseStatus::Miss,
+                b"VA" => {
+                    let Some(bytes) = parts.next().and_then(parse_size) else {
+                        return FramedResponse::Skip(next);
+                    };
+                    let end = next + bytes as usize + 2;
+                    if input.len() < end {
+                        return FramedResponse::Incomplete;
+                    }
+                    return FramedResponse::Response(
+                        Response {
+                            status: ResponseStatus::Hit,
+                            value_size: NonZero::new(bytes),
+                        },
+                        end,
+                        None,
+                    );
+                }
+                b"HD" | b"MN" | b"STORED" | b"DELETED" | b"TOUCHED" | b"OK" | b"VERSION" => {
+                    ResponseStatus::Hit
+                }
+                // incr/decr: the new value
+                _ if !word.is_empty() && word.iter().all(u8::is_ascii_digit) => ResponseStatus::Hit,
+                b"ERROR" | b"CLIENT_ERROR" | b"SERVER_ERROR" => ResponseStatus::Error,
+                _ => return FramedResponse::Skip(next),
+            };
+            let response = Response {
+                status,
+                value_size: value_size.and_then(NonZero::new),
+            };
+            return FramedResponse::Response(response, next, None);
+        }
+    }
+}
+
+/// Command type of a meta command from its flags: the mode of ms and ma,
+/// a cas value for ms, a TTL update for mg
+fn meta_command_type(cmd: &str, flags: &[&[u8]]) -> CommandType {
+    let mode = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix(b"M").and_then(|mode| mode.first()));
+    let has_flag = |name: u8| flags.iter().any(|flag| flag.first() == Some(&name));
+    match cmd {
+        "ms" => match mode.map(u8::to_ascii_uppercase) {
+            Some(b'E') => CommandType::Add,
+            Some(b'R') => CommandType::Replace,
+            Some(b'A') => CommandType::Append,
+            Some(b'P') => CommandType::Prepend,
+            _ if has_flag(b'C') => CommandType::Cas,
+            _ => CommandType::Set,
+        },
+        "ma" => match mode.map(u8::to_ascii_uppercase) {
+            Some(b'D' | b'-') => CommandType::Decr,
+            _ => CommandType::Incr,
+        },
+        _ if has_flag(b'T') && has_flag(b'v') => CommandType::Gat,
+        _ if has_flag(b'T') => CommandType::Touch,
+        _ => CommandType::Get,
+    }
+}
+
+/// Key and data length of each VALUE block of a framed text retrieval
+/// response, in order
+pub fn retrieved_values(response: &[u8]) -> Vec<(&[u8], u32)> {
+    let mut values = Vec::new();
+    let mut offset = 0;
+    while let Some(line_end) = response
+        .get(offset..)
+        .and_then(|rest| rest.iter().position(|&b| b == b'\n'))
+    {
+        let line = &response[offset..offset + line_end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let mut parts = line.split(|&b| b == b' ');
+        if parts.next() != Some(b"VALUE".as_slice()) {
+            break;
+        }
+        let (Some(key), Some(bytes)) = (parts.next(), parts.nth(1).and_then(parse_size)) else {
+            break;
+        };
+        values.push((key, bytes));
+        offset += line_end + 1 + bytes as usize + 2;
+    }
+    values
+}
+
+fn parse_size(field: &[u8]) -> Option<u32> {
+    std::str::from_utf8(field).ok()?.parse().ok()
 }
@@ -0,0 +1,315 @@
+//! pcapng file reading
+//!
+//! libpcap only hands out packets of a single link type, so pcapng captures
+//! (the default of tshark and dumpcap) are read here: every interface of a
+//! section keeps its own link type and timestamp resolution, and interface
+//! statistics report the packets the capture dropped.
+
+use anyhow::{anyhow, Result};
+use std::io::{ErrorKind, Read};
+use std::ops::Range;
+
+/// Block type of the Section Header Block, also the first bytes of the file
+pub const SECTION_HEADER: u32 = 0x0A0D0D0A;
+const INTERFACE_DESCRIPTION: u32 = 1;
+const SIMPLE_PACKET: u32 = 3;
+const INTERFACE_STATISTICS: u32 = 5;
+const ENHANCED_PACKET: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+
+const OPT_END: u16 = 0;
+const IF_TSRESOL: u16 = 9;
+const EPB_DROPCOUNT: u16 = 4;
+const ISB_IFDROP: u16 = 5;
+
+/// Largest block accepted; packets are at most 256KB in practice
+const MAX_BLOCK_LEN: usize = 16 << 20;
+
+/// Whether `header` (the first bytes of a file) starts a pcapng capture
+pub fn is_pcapng(header: &[u8]) -> bool {
+    header.starts_with(&SECTION_HEADER.to_le_bytes())
+}
+
+struct Interface {
+    link_type: i32,
+    snaplen: u32,
+    /// Timestamp units per second
+    ts_resolution: u64,
+    /// Packets dropped, from the last statistics block
+    ifdrop: Option<u64>,
+    /// Packets dropped, summed over the drop counts of packet blocks
+    dropcount: u64,
+}
+
+/// One packet of a pcapng capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcapngPacket<'a> {
+    pub data: &'a [u8],
+    /// pcap data link type (DLT) of the interface the packet was captured on
+    pub datalink: i32,
+    /// Capture time in microseconds since the Unix epoch, if recorded
+    pub timestamp: Option<u64>,
+}
+
+/// Reads the packets of a pcapng stream, section after section
+pub struct PcapngReader<R> {
+    reader: R,
+    big_endian: bool,
+    interfaces: Vec<Interface>,
+    /// Body of the last block read
+    block: Vec<u8>,
+    /// Bytes of `block`, link type and timestamp of the packet last read
+    current: Option<(Range<usize>, i32, Option<u64>)>,
+}
+
+impl<R: Read> PcapngReader<R> {
+    /// Read the section header of a pcapng stream
+    pub fn new(reader: R) -> Result<Self> {
+        let mut pcapng = PcapngReader {
+            reader,
+            big_endian: false,
+            interfaces: Vec::new(),
+            block: Vec::new(),
+            current: None,
+        };
+        match pcapng.read_block()? {
+            Some(SECTION_HEADER) => Ok(pcapng),
+            _ => Err(anyhow!("not a pcapng capture")),
+        }
+    }
+
+    /// Link type of the first interface, reading ahead to its description
+    pub fn datalink(&mut self) -> Result<i32> {
+        while self.interfaces.is_empty() {
+            match self.read_block()? {
+                Some(INTERFACE_DESCRIPTION) => self.describe_interface()?,
+                Some(ENHANCED_PACKET | SIMPLE_PACKET) => {
+                    return Err(anyhow!("pcapng packet before any interface"))
+                }
+                Some(_) => {}
+                None => return Err(anyhow!("pcapng capture describes no interface")),
+            }
+        }
+        Ok(self.interfaces[0].link_type)
+    }
+
+    /// Read the next packet, returning false at the end of the capture
+    pub fn read_packet(&mut self) -> Result<bool> {
+        self.current = None;
+        loop {
+            let packet = match self.read_block()? {
+                None => return Ok(false),
+                Some(INTERFACE_DESCRIPTION) => {
+                    self.describe_interface()?;
+                    continue;
+                }
+                Some(INTERFACE_STATISTICS) => {
+                    self.record_statistics();
+                    continue;
+                }
+                Some(ENHANCED_PACKET) => self.enhanced_packet()?,
+                Some(SIMPLE_PACKET) => self.simple_packet()?,
+                Some(_) => continue,
+            };
+            self.current = Some(packet);
+            return Ok(true);
+        }
+    }
+
+    /// The packet last read, if the last read found one
+    pub fn packet(&self) -> Option<PcapngPacket<'_>> {
+        self.current
+            .as_ref()
+            .map(|(range, datalink, timestamp)| PcapngPacket {
+                data: &self.block[range.clone()],
+                datalink: *datalink,
+                timestamp: *timestamp,
+            })
+    }
+
+    /// Packets the capture reported dropping, summed over its interfaces, if
+    /// any interface reported drops
+    pub fn dropped(&self) -> Option<u64> {
+        let reported = self
+            .interfaces
+            .iter()
+            .any(|interface| interface.ifdrop.is_some() || interface.dropcount > 0);
+        reported.then(|| {
+            self.interfaces
+                .iter()
+                .map(|interface| interface.ifdrop.unwrap_or(interface.dropcount))
+                .sum()
+        })
+    }
+
+    /// Read the next block into `self.block`, returning its type. A section
+    /// header switches the byte order and forgets the interfaces.
+    fn read_block(&mut self) -> Result<Option<u32>> {
+        let mut header = [0u8; 8];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let block_type = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+
+        if block_type == SECTION_HEADER {
+            // The byte order magic follows the length, which is in that order
+            let mut magic = [0u8; 4];
+            self.reader.read_exact(&mut magic)?;
+            self.big_endian = match u32::from_le_bytes(magic) {
+                BYTE_ORDER_MAGIC => false,
+                magic if magic.swap_bytes() == BYTE_ORDER_MAGIC => true,
+                _ => return Err(anyhow!("invalid pcapng byte order magic")),
+            };
+            self.interfaces.clear();
+            let len = self.read_u32(&header[4..8]) as usize;
+            self.read_body(len, 12)?;
+            return Ok(Some(SECTION_HEADER));
+        }
+
+        let block_type = self.read_u32(&header[..4]);
+        let len = self.read_u32(&header[4..8]) as usize;
+        self.read_body(len, 8)?;
+        Ok(Some(block_type))
+    }
+
+    /// Read the rest of a block of `len` bytes, `read` of them already
+    /// consumed, keeping the body before its trailing length
+    fn read_body(&mut self, len: usize, read: usize) -> Result<()> {
+        if len < read + 4 || len > MAX_BLOCK_LEN {
+            return Err(anyhow!("invalid pcapng block length {}", len));
+        }
+        self.block.resize(len - read, 0);
+        self.reader.read_exact(&mut self.block)?;
+        self.block.truncate(len - read - 4);
+        Ok(())
+    }
+
+    fn read_u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    fn read_u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn read_u64(&self, bytes: &[u8]) -> u64 {
+        let (high, low) = if self.big_endian {
+            (self.read_u32(&bytes[..4]), self.read_u32(&bytes[4..8]))
+        } else {
+            (self.read_u32(&bytes[4..8]), self.read_u32(&bytes[..4]))
+        };
+        (high as u64) << 32 | low as u64
+    }
+
+    /// Options of the current block starting at `offset`, as (code, value)
+    fn options(&self, offset: usize) -> Vec<(u16, &[u8])> {
+        let mut options = Vec::new();
+        let mut at = offset;
+        while at + 4 <= self.block.len() {
+            let code = self.read_u16(&self.block[at..]);
+            let len = self.read_u16(&self.block[at + 2..]) as usize;
+            if code == OPT_END {
+                break;
+            }
+            let Some(value) = self.block.get(at + 4..at + 4 + len) else {
+                break;
+            };
+            options.push((code, value));
+            at += 4 + len.div_ceil(4) * 4;
+        }
+        options
+    }
+
+    fn describe_interface(&mut self) -> Result<()> {
+        if self.block.len() < 8 {
+            return Err(anyhow!("truncated pcapng interface description"));
+        }
+        let mut interface = Interface {
+            link_type: self.read_u16(&self.block[..2]) as i32,
+            snaplen: self.read_u32(&self.block[4..8]),
+            ts_resolution: 1_000_000,
+            ifdrop: None,
+            dropcount: 0,
+        };
+        for (code, value) in self.options(8) {
+            if code == IF_TSRESOL && !value.is_empty() {
+                let exponent = (value[0] & 0x7f) as u32;
+                let base: u64 = if value[0] & 0x80 == 0 { 10 } else { 2 };
+                interface.ts_resolution = base
+                    .checked_pow(exponent)
+                    .ok_or_else(|| anyhow!("unsupported pcapng timestamp resolution"))?;
+            }
+        }
+        self.interfaces.push(interface);
+        Ok(())
+    }
+
+    fn record_statistics(&mut self) {
+        if self.block.len() < 12 {
+            return;
+        }
+        let interface_id = self.read_u32(&self.block[..4]) as usize;
+        let ifdrop = self
+            .options(12)
+            .into_iter()
+            .find(|(code, value)| *code == ISB_IFDROP && value.len() == 8)
+            .map(|(_, value)| self.read_u64(value));
+        if let (Some(interface), Some(ifdrop)) = (self.interfaces.get_mut(interface_id), ifdrop) {
+            interface.ifdrop = Some(ifdrop);
+        }
+    }
+
+    fn enhanced_packet(&mut self) -> Result<(Range<usize>, i32, Option<u64>)> {
+        if self.block.len() < 20 {
+            return Err(anyhow!("truncated pcapng packet"));
+        }
+        let interface_id = self.read_u32(&self.block[..4]) as usize;
+        let ts = (self.read_u32(&self.block[4..8]) as u64) << 32
+            | self.read_u32(&self.block[8..12]) as u64;
+        let captured = self.read_u32(&self.block[12..16]) as usize;
+        if 20 + captured > self.block.len() {
+            return Err(anyhow!("truncated pcapng packet"));
+        }
+        let dropcount = self
+            .options(20 + captured.div_ceil(4) * 4)
+            .into_iter()
+            .find(|(code, value)| *code == EPB_DROPCOUNT && value.len() == 8)
+            .map(|(_, value)| self.read_u64(value));
+
+        let interface = self
+            .interfaces
+            .get_mut(interface_id)
+            .ok_or_else(|| anyhow!("pcapng packet of unknown interface {}", interface_id))?;
+        interface.dropcount += dropcount.unwrap_or(0);
+        let timestamp = (ts as u128 * 1_000_000 / interface.ts_resolution as u128) as u64;
+        Ok((20..20 + captured, interface.link_type, Some(timestamp)))
+    }
+
+    fn simple_packet(&mut self) -> Result<(Range<usize>, i32, Option<u64>)> {
+        if self.block.len() < 4 {
+            return Err(anyhow!("truncated pcapng packet"));
+        }
+        let original = self.read_u32(&self.block[..4]) as usize;
+        let interface = self
+            .interfaces
+            .first()
+            .ok_or_else(|| anyhow!("pcapng packet before any interface"))?;
+        let mut captured = original.min(self.block.len() - 4);
+        if interface.snaplen > 0 {
+            captured = captured.min(interface.snaplen as usize);
+        }
+        Ok((4..4 + captured, interface.link_type, None))
+    }
+}
@@ -0,0 +1,100 @@
+//! Server ports selected for capture
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Server ports a recording captures: one port or several, given as a
+/// comma-separated list of ports and inclusive ranges (`11211,11300-11308`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ports {
+    /// Inclusive ranges, sorted and non-overlapping
+    ranges: Vec<(u16, u16)>,
+}
+
+impl Ports {
+    /// Whether `port` is one of the selected ports
+    pub fn contains(&self, port: u16) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(first, last)| first <= port && port <= last)
+    }
+
+    /// BPF expression matching TCP traffic to or from any of the ports
+    pub fn filter(&self) -> String {
+        self.ranges
+            .iter()
+            .map(|&(first, last)| {
+                if first == last {
+                    format!("tcp port {}", first)
+                } else {
+                    format!("tcp portrange {}-{}", first, last)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" or ")
+    }
+}
+
+impl From<u16> for Ports {
+    fn from(port: u16) -> Self {
+        Ports {
+            ranges: vec![(port, port)],
+        }
+    }
+}
+
+impl FromStr for Ports {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_port = |port: &str| match port.trim().parse::<u16>() {
+            Ok(0) | Err(_) => Err(format!("Invalid port: '{}'", port.trim())),
+            Ok(port) => Ok(port),
+        };
+
+        let mut ranges = Vec::new();
+        for part in s.split(',') {
+            let range = match part.split_once('-') {
+                Some((first, last)) => (parse_port(first)?, parse_port(last)?),
+                None => {
+                    let port = parse_port(part)?;
+                    (port, port)
+                }
+            };
+            if range.0 > range.1 {
+                return Err(format!("Invalid port range: '{}'", part.trim()));
+            }
+            ranges.push(range);
+        }
+
+        // Merge overlapping and adjacent ranges
+        ranges.sort_unstable();
+        let mut merged: Vec<(u16, u16)> = Vec::with_capacity(ranges.len());
+        for (first, last) in ranges {
+            match merged.last_mut() {
+                Some(previous) if first <= previous.1.saturating_add(1) => {
+                    previous.1 = previous.1.max(last);
+                }
+                _ => merged.push((first, last)),
+            }
+        }
+        Ok(Ports { ranges: merged })
+    }
+}
+
+impl fmt::Display for Ports {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|&(first, last)| {
+                if first == last {
+                    first.to_string()
+                } else {
+                    format!("{}-{}", first, last)
+                }
+            })
+            .collect();
+        write!(f, "{}", ranges.join(","))
+    }
+}
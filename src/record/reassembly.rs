@@ -0,0 +1,161 @@
+//! TCP stream reassembly: turns captured segments back into the byte stream
+//! each side of a connection sent
+//!
+//! Segments are ordered by sequence number. Retransmitted and overlapping
+//! bytes are dropped, and segments that arrive ahead of a gap wait until the
+//! gap is filled, or until too much is waiting and the gap is given up on.
+
+use crate::record::packet::{FourTuple, TcpSegment};
+use std::collections::HashMap;
+
+/// Out-of-order bytes a stream may hold before skipping the gap
+pub const MAX_PENDING_BYTES: usize = 1 << 20;
+
+struct Stream {
+    /// Sequence number of the next byte expected
+    next_seq: u32,
+    /// Contiguous bytes not consumed by the parser yet
+    data: Vec<u8>,
+    /// Segments past a gap, by sequence number
+    pending: Vec<(u32, Vec<u8>)>,
+    pending_bytes: usize,
+}
+
+/// Distance from `from` to `to` in sequence space, negative if `to` is behind
+fn seq_offset(from: u32, to: u32) -> i64 {
+    to.wrapping_sub(from) as i32 as i64
+}
+
+impl Stream {
+    fn new(next_seq: u32) -> Self {
+        Stream {
+            next_seq,
+            data: Vec::new(),
+            pending: Vec::new(),
+            pending_bytes: 0,
+        }
+    }
+
+    /// Append the part of `payload` (starting at `seq`) not seen yet; false if
+    /// it starts past a gap
+    fn append(&mut self, seq: u32, payload: &[u8]) -> bool {
+        let offset = seq_offset(self.next_seq, seq);
+        if offset > 0 {
+            return false;
+        }
+        let seen = (-offset) as usize;
+        if seen < payload.len() {
+            self.data.extend_from_slice(&payload[seen..]);
+            self.next_seq = seq.wrapping_add(payload.len() as u32);
+        }
+        true
+    }
+
+    /// Add a segment; true if it made new bytes contiguous
+    fn push(&mut self, seq: u32, payload: &[u8]) -> bool {
+        let expected = self.next_seq;
+        if !self.append(seq, payload) {
+            // Keep the longest copy of a segment retransmitted before the gap closes
+            match self.pending.iter_mut().find(|(s, _)| *s == seq) {
+                Some((_, held)) if held.len() >= payload.len() => {}
+                Some((_, held)) => {
+                    self.pending_bytes += payload.len() - held.len();
+                    *held = payload.to_vec();
+                }
+                None => {
+                    self.pending_bytes += payload.len();
+                    self.pending.push((seq, payload.to_vec()));
+                }
+            }
+            if self.pending_bytes > MAX_PENDING_BYTES {
+                self.skip_gap();
+            }
+        }
+        self.drain_pending();
+        self.next_seq != expected
+    }
+
+    /// Move every pending segment that became contiguous into `data`
+    fn drain_pending(&mut self) {
+        while let Some(index) = self
+            .pending
+            .iter()
+            .position(|(seq, _)| seq_offset(self.next_seq, *seq) <= 0)
+        {
+            let (seq, payload) = self.pending.swap_remove(index);
+            self.pending_bytes -= payload.len();
+            self.append(seq, &payload);
+        }
+    }
+
+    /// Give up on the missing bytes: resume at the earliest pending segment.
+    /// The partial request before the gap cannot be completed, so it is dropped.
+    fn skip_gap(&mut self) {
+        let next_seq = self.next_seq;
+        if let Some(&(seq, _)) = self
+            .pending
+            .iter()
+            .min_by_key(|(seq, _)| seq_offset(next_seq, *seq))
+        {
+            tracing::debug!(
+                "Skipping {} missing bytes of a stream",
+                seq_offset(next_seq, seq)
+            );
+            self.next_seq = seq;
+            self.data.clear();
+        }
+    }
+}
+
+/// Reassembles the streams of every connection direction seen in a capture
+#[derive(Default)]
+pub struct StreamReassembler {
+    streams: HashMap<FourTuple, Stream>,
+}
+
+impl StreamReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of streams being reassembled
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    /// Add a segment, returning its stream's unconsumed contiguous bytes when
+    /// the segment added any. The caller drains what it parses from the front.
+    pub fn push(&mut self, segment: &TcpSegment<'_>) -> Option<&mut Vec<u8>> {
+        if segment.is_syn() {
+            // A new connection reusing the tuple starts a fresh stream
+            self.streams
+                .insert(segment.tuple, Stream::new(segment.seq.wrapping_add(1)));
+        }
+        if segment.payload.is_empty() {
+            return None;
+        }
+
+        // A capture started mid-connection picks the stream up at its first segment
+        let stream = self
+            .streams
+            .entry(segment.tuple)
+            .or_insert_with(|| Stream::new(segment.seq));
+        let seq = if segment.is_syn() {
+            segment.seq.wrapping_add(1)
+        } else {
+            segment.seq
+        };
+        stream
+            .push(seq, segment.payload)
+            .then_some(&mut stream.data)
+    }
+
+    /// Forget a stream once its connection is closed
+    pub fn remove(&mut self, tuple: &FourTuple) {
+        self.streams.remove(tuple);
+    }
+}
@@ -0,0 +1,199 @@
+//! Profile rotation: split a long capture into numbered, individually valid
+//! profiles
+
+use crate::profile::{CaptureSummary, Compression, Event, Protocol, SampleRate, SizeBucketing};
+use crate::record::ProfileWriter;
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+/// When the profile being written is closed and the next one started
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rotation {
+    /// Wall-clock time a profile covers
+    pub every: Option<Duration>,
+    /// Bytes of events a profile holds
+    pub size: Option<u64>,
+}
+
+impl Rotation {
+    pub fn is_enabled(&self) -> bool {
+        self.every.is_some() || self.size.is_some()
+    }
+}
+
+/// Path of the `index`th profile of a rotated capture to `output`:
+/// `capture.profile` becomes `capture.0000.profile`, `capture.0001.profile`...
+pub fn rotated_path(output: &str, index: u32) -> String {
+    let path = std::path::Path::new(output);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => path
+            .with_file_name(format!(
+                "{}.{:04}.{}",
+                stem.to_string_lossy(),
+                index,
+                extension.to_string_lossy()
+            ))
+            .to_string_lossy()
+            .into_owned(),
+        _ => format!("{}.{:04}", output, index),
+    }
+}
+
+/// Writes a capture to one profile, or to a numbered series of them
+pub struct RotatingWriter {
+    writer: ProfileWriter,
+    output: String,
+    rotation: Rotation,
+    compression: Compression,
+    connection_sampling: Option<SampleRate>,
+    event_sampling: Option<f64>,
+    size_buckets: Option<SizeBucketing>,
+    plaintext_keys: bool,
+    /// Profiles finished so far
+    finished: Vec<String>,
+    opened: Instant,
+    /// Events and bytes written before the current profile
+    previous_events: u64,
+    previous_bytes: u64,
+}
+
+impl RotatingWriter {
+    pub fn new(output: &str, rotation: Rotation, compression: Compression) -> Result<Self> {
+        let path = if rotation.is_enabled() {
+            rotated_path(output, 0)
+        } else {
+            output.to_string()
+        };
+        Ok(RotatingWriter {
+            writer: ProfileWriter::with_compression(&path, compression)?,
+            output: output.to_string(),
+            rotation,
+            compression,
+            connection_sampling: None,
+            event_sampling: None,
+            size_buckets: None,
+            plaintext_keys: false,
+            finished: Vec::new(),
+            opened: Instant::now(),
+            previous_events: 0,
+            previous_bytes: 0,
+        })
+    }
+
+    /// Path of the profile being written
+    pub fn current_path(&self) -> String {
+        if self.rotation.is_enabled() {
+            rotated_path(&self.output, self.finished.len() as u32)
+        } else {
+            self.output.clone()
+        }
+    }
+
+    pub fn write_event(&mut self, event: &Event) -> Result<()> {
+        let full = self
+            .rotation
+            .size
+            .is_some_and(|size| self.writer.bytes_written() >= size);
+        if full {
+            self.rotate()?;
+        }
+        self.writer.write_event(event)
+    }
+
+    pub fn record_protocol(&mut self, conn_id: u16, protocol: Protocol) {
+        self.writer.record_protocol(conn_id, protocol);
+    }
+
+    /// Capture counters to store in the metadata of the current profile
+    pub fn set_capture_summary(&mut self, summary: CaptureSummary) {
+        self.writer.set_capture_summary(summary);
+    }
+
+    /// Share of the connections the recording keeps, stored in the metadata of
+    /// every profile
+    pub fn set_connection_sampling(&mut self, rate: SampleRate) {
+        self.connection_sampling = Some(rate);
+        self.writer.set_connection_sampling(rate);
+    }
+
+    /// Probability the recording keeps each command with, stored in the
+    /// metadata of every profile
+    pub fn set_event_sampling(&mut self, probability: f64) {
+        self.event_sampling = Some(probability);
+        self.writer.set_event_sampling(probability);
+    }
+
+    /// Buckets the recording rounds sizes into, stored in the metadata of
+    /// every profile
+    pub fn set_size_buckets(&mut self, buckets: SizeBucketing) {
+        self.size_buckets = Some(buckets);
+        self.writer.set_size_buckets(buckets);
+    }
+
+    /// Mark every profile as holding plaintext keys
+    pub fn set_plaintext_keys(&mut self) {
+        self.plaintext_keys = true;
+        self.writer.set_plaintext_keys();
+    }
+
+    /// Start the next profile if the current one has covered its time
+    pub fn rotate_if_due(&mut self) -> Result<()> {
+        if self
+            .rotation
+            .every
+            .is_some_and(|every| self.opened.elapsed() >= every)
+        {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// Finish the current profile and open the next one. Connections keep
+    /// their recorded protocols across profiles.
+    fn rotate(&mut self) -> Result<()> {
+        let path = self.current_path();
+        let next_path = rotated_path(&self.output, self.finished.len() as u32 + 1);
+        let mut next = ProfileWriter::with_compression(&next_path, self.compression)?;
+        if let Some(rate) = self.connection_sampling {
+            next.set_connection_sampling(rate);
+        }
+        if let Some(probability) = self.event_sampling {
+            next.set_event_sampling(probability);
+        }
+        if let Some(buckets) = self.size_buckets {
+            next.set_size_buckets(buckets);
+        }
+        if self.plaintext_keys {
+            next.set_plaintext_keys();
+        }
+        for (&conn_id, &protocol) in self.writer.connection_protocols() {
+            next.record_protocol(conn_id, protocol);
+        }
+        let finished = std::mem::replace(&mut self.writer, next);
+        self.previous_events += finished.events_written();
+        self.previous_bytes += finished.bytes_written();
+        finished.finish()?;
+        tracing::info!("Finished profile {}, writing {}", path, next_path);
+        self.finished.push(path);
+        self.opened = Instant::now();
+        Ok(())
+    }
+
+    /// Events written over every profile
+    pub fn events_written(&self) -> u64 {
+        self.previous_events + self.writer.events_written()
+    }
+
+    /// Bytes of events written over every profile
+    pub fn bytes_written(&self) -> u64 {
+        self.previous_bytes + self.writer.bytes_written()
+    }
+
+    /// Finish the current profile, returning the path of every profile written
+    pub fn finish(mut self) -> Result<Vec<String>> {
+        let path = self.current_path();
+        self.writer.finish()?;
+        self.finished.push(path);
+        Ok(self.finished)
+    }
+}
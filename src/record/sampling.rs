@@ -0,0 +1,60 @@
+//! Sampling during record: a deterministic subset of the connections, and a
+//! random share of the commands
+
+use crate::profile::SampleRate;
+use crate::record::packet::FourTuple;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use siphasher::sip::SipHasher13;
+use std::hash::Hasher;
+use std::net::IpAddr;
+
+/// Selects connections by a hash of their client-to-server 4-tuple, so a
+/// connection is recorded whole or not at all, and the same connections are
+/// selected by every recording of the same traffic
+pub struct ConnectionSampler {
+    rate: SampleRate,
+}
+
+impl ConnectionSampler {
+    pub fn new(rate: SampleRate) -> Self {
+        ConnectionSampler { rate }
+    }
+
+    /// Whether the connection with client-to-server tuple `client` is recorded
+    pub fn keeps(&self, client: &FourTuple) -> bool {
+        let mut hasher = SipHasher13::new();
+        for (ip, port) in [
+            (client.src_ip, client.src_port),
+            (client.dst_ip, client.dst_port),
+        ] {
+            match ip {
+                IpAddr::V4(ip) => hasher.write(&ip.octets()),
+                IpAddr::V6(ip) => hasher.write(&ip.octets()),
+            }
+            hasher.write_u16(port);
+        }
+        hasher.finish() % u64::from(self.rate.denominator) < u64::from(self.rate.numerator)
+    }
+}
+
+/// Keeps each command independently with a fixed probability
+pub struct EventSampler {
+    probability: f64,
+    rng: StdRng,
+}
+
+impl EventSampler {
+    /// Sampler keeping commands with `probability`, drawing from `seed`
+    pub fn new(probability: f64, seed: u64) -> Self {
+        EventSampler {
+            probability,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Whether the next command is recorded
+    pub fn keeps(&mut self) -> bool {
+        self.rng.gen_bool(self.probability)
+    }
+}
@@ -0,0 +1,64 @@
+//! Hooks applied to recorded events between parsing and writing
+
+use crate::profile::Event;
+
+/// Rewrites or drops events before they are written to the profile.
+///
+/// `key` is the raw (not yet anonymized) key of the command, so a transform
+/// can act on key structure such as a tenant prefix; only the returned event
+/// is written.
+pub trait EventTransform: Send {
+    /// The event to write, or `None` to drop it
+    fn transform(&mut self, event: Event, key: &[u8]) -> Option<Event>;
+}
+
+impl<F> EventTransform for F
+where
+    F: FnMut(Event, &[u8]) -> Option<Event> + Send,
+{
+    fn transform(&mut self, event: Event, key: &[u8]) -> Option<Event> {
+        self(event, key)
+    }
+}
+
+/// Writes every event unchanged
+pub struct Passthrough;
+
+impl EventTransform for Passthrough {
+    fn transform(&mut self, event: Event, _key: &[u8]) -> Option<Event> {
+        Some(event)
+    }
+}
+
+/// Applies transforms in order, stopping at the first one that drops the event
+#[derive(Default)]
+pub struct TransformChain {
+    transforms: Vec<Box<dyn EventTransform>>,
+}
+
+impl TransformChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, transform: impl EventTransform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.transforms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+}
+
+impl EventTransform for TransformChain {
+    fn transform(&mut self, event: Event, key: &[u8]) -> Option<Event> {
+        self.transforms
+            .iter_mut()
+            .try_fold(event, |event, transform| transform.transform(event, key))
+    }
+}
@@ -1,43 +1,112 @@
-use crate::profile::{Event, ProfileMetadata};
+use crate::profile::checkpoint::{encode_checkpoint, CHECKPOINT_INTERVAL};
+use crate::profile::checksum::{crc32c, encode_checksum, Crc32c, Trailer, MAX_METADATA_LEN};
+use crate::profile::compression::{encode_block, BLOCK_MAGIC, BLOCK_SIZE};
+use crate::profile::index::{encode_index, IndexEntry, INDEX_INTERVAL};
+use crate::profile::packed::EventEncoder;
+use crate::profile::{
+    CaptureSummary, Compression, Event, IndexSection, KeySketch, ProfileMetadata, ProfileSource,
+    Protocol, SampleRate, SizeBucketing,
+};
 use anyhow::Result;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufWriter, Write};
+use std::time::Instant;
 
 pub struct ProfileWriter {
     file: BufWriter<File>,
     metadata: ProfileMetadata,
     events_written: u64,
+    bytes_written: u64,
     first_timestamp: Option<u64>,
     last_timestamp: Option<u64>,
     connections: HashSet<u16>,
+    /// Distinct key hashes of the events written
+    keys: KeySketch,
+    /// Events gathered for the next compressed block
+    block: Vec<u8>,
+    encoder: EventEncoder,
+    /// Record of the event being written
+    record: Vec<u8>,
+    /// Checksum of the records since the last checksum record
+    checksum: Crc32c,
+    /// Entries of the index, one per run of events ended by a checksum
+    index: Vec<IndexEntry>,
+    /// Offset of the first record of the current run
+    run_offset: u64,
+    /// Events of the current run, which has an index entry once it has one
+    run_events: u64,
+    /// Latest timestamp of the events written so far
+    latest_timestamp: u64,
+    last_checkpoint: Instant,
 }
 
 impl ProfileWriter {
     pub fn new(path: &str) -> Result<Self> {
+        Self::with_compression(path, Compression::None)
+    }
+
+    /// Writer encoding its events with `compression`
+    pub fn with_compression(path: &str, compression: Compression) -> Result<Self> {
         let file = File::create(path)?;
-        let writer = BufWriter::new(file);
-        let metadata = ProfileMetadata::new();
+        let mut writer = BufWriter::new(file);
+        let mut metadata = ProfileMetadata::new();
+        metadata.compression = compression;
+        let mut bytes_written = 0;
+        if compression == Compression::Deflate {
+            writer.write_all(&BLOCK_MAGIC)?;
+            bytes_written += BLOCK_MAGIC.len() as u64;
+        }
 
         Ok(ProfileWriter {
             file: writer,
             metadata,
             events_written: 0,
+            bytes_written,
             first_timestamp: None,
             last_timestamp: None,
             connections: HashSet::new(),
+            keys: KeySketch::new(),
+            block: Vec::new(),
+            encoder: EventEncoder::new(),
+            record: Vec::new(),
+            checksum: Crc32c::new(),
+            index: Vec::new(),
+            run_offset: bytes_written,
+            run_events: 0,
+            latest_timestamp: 0,
+            last_checkpoint: Instant::now(),
         })
     }
 
     pub fn write_event(&mut self, event: &Event) -> Result<()> {
-        let encoded = bincode::serialize(event)?;
+        if self.run_events == 0 {
+            self.index.push(IndexEntry {
+                timestamp: self.latest_timestamp,
+                offset: self.run_offset,
+                events: self.events_written,
+            });
+        }
+        self.latest_timestamp = self.latest_timestamp.max(event.timestamp);
 
         // Write event with u16 length prefix
-        self.file.write_all(&(encoded.len() as u16).to_le_bytes())?;
-        self.file.write_all(&encoded)?;
+        let mut record = std::mem::take(&mut self.record);
+        record.clear();
+        record.extend_from_slice(&[0, 0]);
+        self.encoder.encode(event, &mut record);
+        let len = (record.len() - 2) as u16;
+        record[..2].copy_from_slice(&len.to_le_bytes());
+        let written = self.write_record(&record);
+        self.record = record;
+        written?;
+        self.run_events += 1;
+        if self.run_events >= INDEX_INTERVAL {
+            self.end_run()?;
+        }
 
         self.events_written += 1;
         self.connections.insert(event.conn_id);
+        self.keys.insert(event.key_hash);
 
         if self.first_timestamp.is_none() {
             self.first_timestamp = Some(event.timestamp);
@@ -50,25 +119,179 @@ impl ProfileWriter {
             .entry(event.cmd_type)
             .or_insert(0) += 1;
 
+        if self.last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL {
+            self.checkpoint()?;
+        }
         Ok(())
     }
 
-    pub fn finish(mut self) -> Result<()> {
-        self.metadata.total_events = self.events_written;
-        self.metadata.unique_connections = self.connections.len() as u32;
+    /// Write a checkpoint of the events so far and sync the file, so that
+    /// they can be recovered if the trailer is never written
+    pub fn checkpoint(&mut self) -> Result<()> {
+        let record = encode_checkpoint(&self.current_metadata())?;
+        self.write_record(&record)?;
+        self.flush_block()?;
+        self.file.flush()?;
+        self.file.get_ref().sync_data()?;
+        self.last_checkpoint = Instant::now();
+        Ok(())
+    }
 
+    /// Metadata describing the events written so far
+    fn current_metadata(&self) -> ProfileMetadata {
+        let mut metadata = self.metadata.clone();
+        metadata.total_events = self.events_written;
+        metadata.unique_connections = self.connections.len() as u32;
+        metadata.key_sketch = Some(self.keys.clone());
+        // The sketch is only an estimate: dropped rather than let it push the
+        // metadata past what a trailer can locate
+        if bincode::serialized_size(&metadata).is_ok_and(|len| len > MAX_METADATA_LEN as u64) {
+            tracing::warn!("Metadata too large to hold the key sketch, leaving it out");
+            metadata.key_sketch = None;
+        }
         if let (Some(first), Some(last)) = (self.first_timestamp, self.last_timestamp) {
-            self.metadata.time_range = (first, last);
+            metadata.time_range = (first, last);
         }
+        metadata
+    }
 
-        // Write metadata: data first, then length prefix
-        let encoded_metadata = bincode::serialize(&self.metadata)?;
-        self.file.write_all(&encoded_metadata)?;
-        self.file
-            .write_all(&(encoded_metadata.len() as u16).to_le_bytes())?;
+    /// Append a record to the event region, to be covered by the next checksum
+    fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        self.checksum.update(record);
+        match self.metadata.compression {
+            Compression::None => {
+                self.file.write_all(record)?;
+                self.bytes_written += record.len() as u64;
+                if self.checksum.len() >= BLOCK_SIZE as u64 {
+                    self.write_checksum()?;
+                }
+            }
+            Compression::Deflate => {
+                self.block.extend_from_slice(record);
+                if self.block.len() >= BLOCK_SIZE {
+                    self.flush_block()?;
+                }
+            }
+        }
+        Ok(())
+    }
 
-        // Write end marker: magic number so we know where metadata ends
-        self.file.write_all(&0xDEADBEEFu32.to_le_bytes())?;
+    /// End the run of records covered by one checksum: reading can start
+    /// after it, at the next index entry
+    fn end_run(&mut self) -> Result<()> {
+        match self.metadata.compression {
+            Compression::None => self.write_checksum(),
+            Compression::Deflate => self.flush_block(),
+        }
+    }
+
+    /// Checksum the records of an uncompressed profile written since the last checksum
+    fn write_checksum(&mut self) -> Result<()> {
+        if self.checksum.is_empty() {
+            return Ok(());
+        }
+        let record = encode_checksum(&self.checksum);
+        self.file.write_all(&record)?;
+        self.bytes_written += record.len() as u64;
+        self.start_run();
+        Ok(())
+    }
+
+    /// Start a run of records, whose first event has an absolute timestamp
+    fn start_run(&mut self) {
+        self.checksum = Crc32c::new();
+        self.encoder = EventEncoder::new();
+        self.run_offset = self.bytes_written;
+        self.run_events = 0;
+    }
+
+    /// Compress the gathered events, ending with their checksum, into a block
+    fn flush_block(&mut self) -> Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        self.block
+            .extend_from_slice(&encode_checksum(&self.checksum));
+        let block = encode_block(&self.block)?;
+        self.file.write_all(&block)?;
+        self.bytes_written += block.len() as u64;
+        self.block.clear();
+        self.start_run();
+        Ok(())
+    }
+
+    /// Events written so far
+    pub fn events_written(&self) -> u64 {
+        self.events_written
+    }
+
+    /// Bytes of events and checkpoints written so far; the metadata trailer
+    /// comes on top. A compressed profile counts its blocks as they are written.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Protocols recorded so far, by conn_id
+    pub fn connection_protocols(&self) -> &HashMap<u16, Protocol> {
+        &self.metadata.connection_protocols
+    }
+
+    /// Capture counters to store in the metadata
+    pub fn set_capture_summary(&mut self, summary: CaptureSummary) {
+        self.metadata.capture = Some(summary);
+    }
+
+    /// Share of the connections the recording keeps, to store in the metadata
+    pub fn set_connection_sampling(&mut self, rate: SampleRate) {
+        self.metadata.connection_sampling = Some(rate);
+    }
+
+    /// Probability the recording keeps each command with, to store in the metadata
+    pub fn set_event_sampling(&mut self, probability: f64) {
+        self.metadata.event_sampling = Some(probability);
+    }
+
+    /// Buckets the recording rounds sizes into, to store in the metadata
+    pub fn set_size_buckets(&mut self, buckets: SizeBucketing) {
+        self.metadata.size_buckets = Some(buckets);
+    }
+
+    /// Mark the profile as holding plaintext keys
+    pub fn set_plaintext_keys(&mut self) {
+        self.metadata.plaintext_keys = true;
+    }
+
+    /// Profiles merged into this one, to store in the metadata
+    pub fn set_sources(&mut self, sources: Vec<ProfileSource>) {
+        self.metadata.sources = sources;
+    }
+
+    /// Remember the protocol a connection speaks; the first one observed wins
+    pub fn record_protocol(&mut self, conn_id: u16, protocol: Protocol) {
+        self.metadata
+            .connection_protocols
+            .entry(conn_id)
+            .or_insert(protocol);
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.end_run()?;
+
+        // Write the index, located by the metadata
+        let index = encode_index(&self.index);
+        self.metadata.index = Some(IndexSection {
+            offset: self.bytes_written,
+            entries: self.index.len() as u64,
+            crc: crc32c(&index),
+        });
+        self.file.write_all(&index)?;
+
+        // Write metadata: data first, then length prefix, checksum and end
+        // marker, so we know where metadata ends
+        let encoded_metadata = bincode::serialize(&self.current_metadata())?;
+        let trailer = Trailer::encode(&encoded_metadata)?;
+        self.file.write_all(&encoded_metadata)?;
+        self.file.write_all(&trailer)?;
 
         self.file.flush()?;
         Ok(())
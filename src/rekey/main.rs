@@ -0,0 +1,78 @@
+//! Rekey command: re-anonymize the keys of a profile with a fresh salt
+
+use crate::record::{Anonymizer, ProfileWriter};
+use crate::replay::ProfileReader;
+use anyhow::{anyhow, Result};
+
+/// Outcome of a rekey
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RekeyReport {
+    pub events_rekeyed: u64,
+    /// Whether the input held plaintext keys, which are not copied
+    pub plaintext_keys_removed: bool,
+}
+
+/// Copy `input` to `output` with every key hash, key segment hash and client
+/// id hashed again under `salt`. Equal hashes stay equal, so key popularity and
+/// namespaces survive, but the hashes no longer match those of other profiles
+/// recorded with the original salt.
+pub fn rekey(input: &str, output: &str, salt: u64) -> Result<RekeyReport> {
+    if std::fs::canonicalize(output).ok() == Some(std::fs::canonicalize(input)?) {
+        return Err(anyhow!("output must differ from the input being rekeyed"));
+    }
+
+    let reader = ProfileReader::new(input)?;
+    let metadata = reader.metadata();
+    let anonymizer = Anonymizer::new(salt);
+
+    let mut writer = ProfileWriter::with_compression(output, metadata.compression)?;
+    for (&conn_id, &protocol) in &metadata.connection_protocols {
+        writer.record_protocol(conn_id, protocol);
+    }
+    if let Some(summary) = metadata.capture {
+        writer.set_capture_summary(summary);
+    }
+    if let Some(rate) = metadata.connection_sampling {
+        writer.set_connection_sampling(rate);
+    }
+    if let Some(probability) = metadata.event_sampling {
+        writer.set_event_sampling(probability);
+    }
+    if let Some(buckets) = metadata.size_buckets {
+        writer.set_size_buckets(buckets);
+    }
+
+    let mut events_rekeyed = 0u64;
+    for event in reader.events() {
+        let mut event = event.clone();
+        event.key_hash = anonymizer.hash_key(&event.key_hash.to_le_bytes());
+        if let Some(segments) = event.key_segments.as_mut() {
+            for segment in segments.iter_mut() {
+                *segment = anonymizer.hash_key(&segment.to_le_bytes()) as u32;
+            }
+        }
+        event.client_id = event
+            .client_id
+            .map(|id| anonymizer.hash_key(&id.to_le_bytes()) as u32);
+        event.key = None;
+        writer.write_event(&event)?;
+        events_rekeyed += 1;
+    }
+    writer.finish()?;
+
+    Ok(RekeyReport {
+        events_rekeyed,
+        plaintext_keys_removed: metadata.plaintext_keys,
+    })
+}
+
+pub fn run(input: &str, output: &str, salt: u64) -> Result<()> {
+    let report = rekey(input, output, salt)?;
+
+    println!("Rekeyed {} events into {}", report.events_rekeyed, output);
+    if report.plaintext_keys_removed {
+        println!("{} held plaintext keys; they were not copied", input);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,5 @@
+//! Rekey command implementation
+
+pub mod main;
+
+pub use main::{rekey, run as run_rekey, RekeyReport};
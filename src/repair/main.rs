@@ -0,0 +1,198 @@
+//! Repair command: recover the events of a profile missing its trailer
+
+use crate::profile::checkpoint::{read_record, Record};
+use crate::profile::checksum::{RecordChecksums, Trailer};
+use crate::profile::compression::{decode_blocks, BLOCK_MAGIC};
+use crate::profile::packed::{EventDecoder, PACKED_VERSION};
+use crate::profile::{Compression, Event, ProfileMetadata};
+use crate::record::ProfileWriter;
+use anyhow::{anyhow, Context, Result};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Outcome of a repair
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairReport {
+    pub events_recovered: u64,
+    /// Bytes of event data that could not be decoded (truncated or corrupt tail)
+    pub bytes_discarded: u64,
+    /// Whether the input already had a valid metadata trailer
+    pub had_trailer: bool,
+}
+
+/// Copy every decodable event of `input` to a valid profile at `output`,
+/// stopping at the first truncated or corrupt record. Metadata is rebuilt
+/// from the recovered events.
+pub fn repair(input: &str, output: &str) -> Result<RepairReport> {
+    if std::fs::canonicalize(output).ok() == Some(std::fs::canonicalize(input)?) {
+        return Err(anyhow!("output must differ from the input being repaired"));
+    }
+
+    let mut file = File::open(input).context(format!("failed to open {}", input))?;
+    let file_len = file.metadata()?.len();
+
+    let trailer = find_trailer(&mut file, file_len)?;
+    let event_end = trailer
+        .as_ref()
+        .map_or(file_len, |(start, metadata)| metadata.events_end(*start));
+    file.seek(SeekFrom::Start(0))?;
+
+    // Without a trailer, a compressed profile is told apart by its block header
+    let compression = match &trailer {
+        Some((_, metadata)) => metadata.compression,
+        None => {
+            let mut header = [0u8; 4];
+            let compressed = file.read_exact(&mut header).is_ok() && header == BLOCK_MAGIC;
+            file.seek(SeekFrom::Start(0))?;
+            if compressed {
+                Compression::Deflate
+            } else {
+                Compression::None
+            }
+        }
+    };
+
+    let mut data = Vec::new();
+    file.take(event_end).read_to_end(&mut data)?;
+
+    // Blocks that fail to inflate are discarded with everything after them
+    let mut blocks_discarded = 0u64;
+    let region = match compression {
+        Compression::None => Cow::Borrowed(&data[..]),
+        Compression::Deflate => {
+            let (events, consumed) = decode_blocks(&data);
+            blocks_discarded = event_end - consumed as u64;
+            Cow::Owned(events)
+        }
+    };
+    // Events are encoded with the version of the trailer or, without one, of
+    // the first checkpoint; a recording too short to checkpoint is current
+    let version = match &trailer {
+        Some((_, metadata)) => metadata.version,
+        None => first_checkpoint(&region).map_or(PACKED_VERSION, |metadata| metadata.version),
+    };
+    let mut decoder = EventDecoder::new(version);
+
+    let mut writer = ProfileWriter::with_compression(output, compression)?;
+    let mut checksums = RecordChecksums::new(compression);
+    // Events since the last checksum, written once it matches
+    let mut unverified = Vec::new();
+    let mut verified_offset = 0;
+    let mut offset = 0;
+    let mut events_recovered = 0u64;
+
+    while let Some((record, next)) = read_record(&region, offset) {
+        // A mismatch discards the events it covers with everything after them
+        if checksums.check(&record).is_err() {
+            unverified.clear();
+            offset = verified_offset;
+            break;
+        }
+        match record {
+            // A record only counts if it decodes to exactly its length prefix
+            Record::Event(bytes) => {
+                let Some(event) = decode_event(&mut decoder, bytes) else {
+                    break;
+                };
+                unverified.push(event);
+            }
+            Record::Checksum(_) => {
+                decoder.restart();
+                events_recovered += write_events(&mut writer, &mut unverified)?;
+                verified_offset = next;
+            }
+            // Checkpoints carry the protocols of the connections before them
+            Record::Checkpoint(bytes) => {
+                let Ok(metadata) = ProfileMetadata::decode(bytes) else {
+                    break;
+                };
+                for (conn_id, protocol) in metadata.connection_protocols {
+                    writer.record_protocol(conn_id, protocol);
+                }
+            }
+        }
+        offset = next;
+    }
+    // Events after the last checksum cannot be verified; keep those that decode
+    events_recovered += write_events(&mut writer, &mut unverified)?;
+
+    writer.finish()?;
+
+    Ok(RepairReport {
+        events_recovered,
+        bytes_discarded: blocks_discarded + (region.len() - offset) as u64,
+        had_trailer: trailer.is_some(),
+    })
+}
+
+/// Offset and metadata of a valid metadata trailer, if the file has one
+fn find_trailer(file: &mut File, file_len: u64) -> Result<Option<(u64, ProfileMetadata)>> {
+    let mut tail = vec![0u8; Trailer::MAX_LEN.min(file_len as usize)];
+    file.seek(SeekFrom::Start(file_len - tail.len() as u64))?;
+    file.read_exact(&mut tail)?;
+    let Some(trailer) = Trailer::parse(&tail) else {
+        return Ok(None);
+    };
+    let Ok(metadata_start) = trailer.metadata_start(file_len) else {
+        return Ok(None);
+    };
+
+    let mut metadata = vec![0u8; trailer.metadata_len as usize];
+    file.seek(SeekFrom::Start(metadata_start))?;
+    file.read_exact(&mut metadata)?;
+    if trailer.verify(&metadata, metadata_start).is_err() {
+        return Ok(None);
+    }
+
+    Ok(ProfileMetadata::decode(&metadata)
+        .ok()
+        .map(|metadata| (metadata_start, metadata)))
+}
+
+fn write_events(writer: &mut ProfileWriter, events: &mut Vec<Event>) -> Result<u64> {
+    let written = events.len() as u64;
+    for event in events.drain(..) {
+        writer.write_event(&event)?;
+    }
+    Ok(written)
+}
+
+fn decode_event(decoder: &mut EventDecoder, bytes: &[u8]) -> Option<Event> {
+    decoder.decode(bytes).ok()
+}
+
+/// Metadata of the first checkpoint of an (inflated) event region
+fn first_checkpoint(region: &[u8]) -> Option<ProfileMetadata> {
+    let mut offset = 0;
+    while let Some((record, next)) = read_record(region, offset) {
+        if let Record::Checkpoint(bytes) = record {
+            return ProfileMetadata::decode(bytes).ok();
+        }
+        offset = next;
+    }
+    None
+}
+
+pub fn run(input: &str, output: &str) -> Result<()> {
+    let report = repair(input, output)?;
+
+    if report.had_trailer {
+        println!(
+            "{} already has a valid trailer; events were copied as-is",
+            input
+        );
+    }
+    println!(
+        "Recovered {} events into {}",
+        report.events_recovered, output
+    );
+    if report.bytes_discarded > 0 {
+        println!(
+            "Discarded {} trailing bytes of truncated or corrupt event data",
+            report.bytes_discarded
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,5 @@
+//! Repair command implementation
+
+pub mod main;
+
+pub use main::{repair, run as run_repair, RepairReport};
@@ -1,12 +1,53 @@
+use super::hot_keys::{HeavyHitters, HotKey};
 use crate::profile::{CommandType, Event};
+use serde::Serialize;
 use std::collections::HashMap;
 
-#[derive(Clone)]
+/// Keys reported as the hottest, overall and per command
+pub const TOP_KEYS: usize = 10;
+
+#[derive(Debug, Clone, Serialize)]
 pub struct AnalysisResult {
     pub total_events: u64,
     pub command_distribution: HashMap<CommandType, u64>,
     pub key_size_distribution: Vec<(u32, u64)>,
     pub value_size_distribution: Vec<(u32, u64)>,
+    /// Access count per distinct key_hash, sorted in descending order. Left
+    /// out of serialized reports: one number per key.
+    #[serde(skip_serializing)]
+    pub key_frequencies: Vec<u64>,
+    /// Most frequent keys, from a heavy-hitters sketch
+    pub hot_keys: Vec<HotKey>,
+    /// Most frequent keys of each command
+    pub hot_keys_by_command: HashMap<CommandType, Vec<HotKey>>,
+    /// Gaps between consecutive events of the same connection, in µs,
+    /// bucketed by power of two: (bucket upper bound, count)
+    pub inter_arrival_distribution: Vec<(u64, u64)>,
+}
+
+impl AnalysisResult {
+    /// Mean gap between consecutive events of the same connection, in µs
+    pub fn mean_inter_arrival_micros(&self) -> Option<f64> {
+        let count: u64 = self.inter_arrival_distribution.iter().map(|(_, c)| c).sum();
+        if count == 0 {
+            return None;
+        }
+        let total: f64 = self
+            .inter_arrival_distribution
+            .iter()
+            .map(|(bucket, c)| (*bucket as f64 * 0.75) * *c as f64)
+            .sum();
+        Some(total / count as f64)
+    }
+}
+
+/// Power-of-two bucket holding an inter-arrival gap (0 stays 0)
+pub fn inter_arrival_bucket(gap_micros: u64) -> u64 {
+    if gap_micros == 0 {
+        0
+    } else {
+        gap_micros.checked_next_power_of_two().unwrap_or(1 << 63)
+    }
 }
 
 pub struct DistributionAnalyzer;
@@ -16,21 +57,49 @@ impl DistributionAnalyzer {
         let mut cmd_dist = HashMap::new();
         let mut key_size_dist = HashMap::new();
         let mut value_size_dist = HashMap::new();
+        let mut key_counts: HashMap<u64, u64> = HashMap::new();
+        let mut last_timestamps: HashMap<u16, u64> = HashMap::new();
+        let mut inter_arrival_dist = HashMap::new();
+        let mut hot_keys = HeavyHitters::default();
+        let mut hot_keys_by_command: HashMap<CommandType, HeavyHitters> = HashMap::new();
 
         for event in events {
             *cmd_dist.entry(event.cmd_type).or_insert(0) += 1;
             *key_size_dist.entry(event.key_size).or_insert(0) += 1;
+            *key_counts.entry(event.key_hash).or_insert(0) += 1;
+            hot_keys.insert(event.key_hash);
+            hot_keys_by_command
+                .entry(event.cmd_type)
+                .or_default()
+                .insert(event.key_hash);
+
+            if let Some(last) = last_timestamps.insert(event.conn_id, event.timestamp) {
+                let gap = event.timestamp.saturating_sub(last);
+                *inter_arrival_dist
+                    .entry(inter_arrival_bucket(gap))
+                    .or_insert(0) += 1;
+            }
 
             if let Some(size) = event.value_size {
                 *value_size_dist.entry(size.get()).or_insert(0) += 1;
             }
         }
 
+        let mut key_frequencies: Vec<u64> = key_counts.into_values().collect();
+        key_frequencies.sort_unstable_by(|a, b| b.cmp(a));
+
         AnalysisResult {
             total_events: events.len() as u64,
             command_distribution: cmd_dist,
             key_size_distribution: key_size_dist.into_iter().collect::<Vec<_>>(),
             value_size_distribution: value_size_dist.into_iter().collect::<Vec<_>>(),
+            key_frequencies,
+            hot_keys: hot_keys.top(TOP_KEYS),
+            hot_keys_by_command: hot_keys_by_command
+                .into_iter()
+                .map(|(cmd_type, sketch)| (cmd_type, sketch.top(TOP_KEYS)))
+                .collect(),
+            inter_arrival_distribution: inter_arrival_dist.into_iter().collect::<Vec<_>>(),
         }
     }
 }
@@ -1,85 +1,477 @@
+use super::transport::Transport;
+use super::value::{KeyPattern, ValueFill, ValueGenerator};
 use super::ProtocolMode;
 use crate::profile::{CommandType, Event};
-use anyhow::Result;
+use crate::record::binary::{
+    command_opcode, encode_request, is_not_found, quiet_opcode, response_value,
+};
+use crate::record::{FramedResponse, MemcacheParser};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
 
 pub struct ReplayClient {
-    stream: TcpStream,
+    stream: Transport,
     buffer: Vec<u8>,
+    /// Bytes received but not yet framed into responses, when pipelining
+    received: Vec<u8>,
     protocol_mode: ProtocolMode,
+    /// Opaque of the last binary request
+    opaque: u32,
+    /// Prepended to every key sent
+    key_prefix: String,
+    /// Fills the values of storage commands
+    values: Box<dyn ValueGenerator>,
+    /// Check retrieval hits against the values written
+    verify: bool,
 }
 
+/// Longest key memcached accepts
+pub const MAX_KEY_LEN: usize = 250;
+
 impl ReplayClient {
+    /// Connect to `target`, a TCP `host:port` or `unix:<path>`
     pub async fn new(target: &str, protocol_mode: ProtocolMode) -> Result<Self> {
-        let stream = TcpStream::connect(target).await?;
-
-        // Disable Nagle's algorithm for low-latency communication
-        stream.set_nodelay(true)?;
+        let stream = Transport::connect(target).await?;
 
         Ok(ReplayClient {
             stream,
             buffer: vec![0u8; 65536],
+            received: Vec::new(),
             protocol_mode,
+            opaque: 0,
+            key_prefix: String::new(),
+            values: ValueFill::default().generator(),
+            verify: false,
         })
     }
 
+    /// Prepend `prefix` to every key sent, isolating the keys of this replay
+    /// from the others against the same target
+    pub fn with_key_prefix(mut self, prefix: &str) -> Self {
+        self.key_prefix = prefix.to_string();
+        self
+    }
+
+    /// Fill the values of storage commands with `values`
+    pub fn with_values(mut self, values: Box<dyn ValueGenerator>) -> Self {
+        self.values = values;
+        self
+    }
+
+    /// Write every value from the key pattern, and check every retrieval hit
+    /// against it (see `check_integrity`)
+    pub fn with_verification(mut self) -> Self {
+        self.values = ValueFill::Pattern.generator();
+        self.verify = true;
+        self
+    }
+
+    /// When verifying and `response` is a retrieval hit, whether it is intact:
+    /// on the key of `event`, and carrying a value the key pattern wrote.
+    /// Every value of a key is a prefix of its longest one, so the check
+    /// holds whatever size was last written.
+    pub fn check_integrity(&self, event: &Event, response: &[u8]) -> Option<bool> {
+        if !self.verify || !event.cmd_type.is_retrieval() {
+            return None;
+        }
+        let (key, value) = self.hit_value(response)?;
+        if key.is_some_and(|key| key != self.key_of(event).as_bytes()) {
+            return Some(false);
+        }
+        let mut expected = Vec::with_capacity(value.len());
+        KeyPattern.fill(event.key_hash, value.len(), &mut expected);
+        Some(value == expected)
+    }
+
+    /// Key and value of a retrieval hit, `None` for any other response.
+    /// Only ASCII responses name their key.
+    fn hit_value<'a>(&self, response: &'a [u8]) -> Option<(Option<&'a [u8]>, &'a [u8])> {
+        let text_value = |line_end: usize, bytes: &[u8]| {
+            let bytes: usize = std::str::from_utf8(bytes).ok()?.parse().ok()?;
+            response.get(line_end + 1..line_end + 1 + bytes)
+        };
+        match self.protocol_mode {
+            ProtocolMode::Ascii | ProtocolMode::Meta => {
+                let line_end = response.iter().position(|&b| b == b'\n')?;
+                let mut parts = response[..line_end].trim_ascii_end().split(|&b| b == b' ');
+                match parts.next()? {
+                    // VALUE <key> <flags> <bytes> [<cas>]
+                    b"VALUE" => {
+                        let key = parts.next()?;
+                        let bytes = parts.nth(1)?;
+                        Some((Some(key), text_value(line_end, bytes)?))
+                    }
+                    // VA <bytes> <flags>*
+                    b"VA" => Some((None, text_value(line_end, parts.next()?)?)),
+                    _ => None,
+                }
+            }
+            ProtocolMode::Binary => Some((None, response_value(response)?)),
+        }
+    }
+
     pub async fn send_command(&mut self, event: &Event) -> Result<()> {
-        let cmd = self.build_command_string(event);
-        self.stream.write_all(cmd.as_bytes()).await?;
+        self.send_commands(std::slice::from_ref(event)).await
+    }
+
+    /// Send `events` in a single write. Several GETs go as one multi-key
+    /// `get` in ASCII, and as pipelined requests in the other protocols.
+    pub async fn send_commands(&mut self, events: &[Event]) -> Result<()> {
+        let cmd = if self.is_multi_get(events) {
+            let keys: Vec<String> = events.iter().map(|event| self.key_of(event)).collect();
+            format!("get {}\r\n", keys.join(" ")).into_bytes()
+        } else {
+            let mut cmd = Vec::new();
+            for event in events {
+                cmd.extend(self.build_command(event));
+            }
+            cmd
+        };
+        self.stream.write_all(&cmd).await?;
         // Flush to ensure immediate send
         self.stream.flush().await?;
         Ok(())
     }
 
+    /// Read the next whole response, values included
     pub async fn read_response(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(response) = self.next_response() {
+                return Ok(response);
+            }
+            self.receive().await?;
+        }
+    }
+
+    /// Read the response to each of `events`, sent together by `send_commands`
+    pub async fn read_responses(&mut self, events: &[Event]) -> Result<Vec<Vec<u8>>> {
+        loop {
+            if let Some(responses) = self.next_responses(events) {
+                return Ok(responses);
+            }
+            self.receive().await?;
+        }
+    }
+
+    /// Read whatever the server sent next, to be framed by `next_response`.
+    /// Cancel safe: nothing is lost when the read is dropped before completing.
+    pub async fn receive(&mut self) -> Result<()> {
         let n = self.stream.read(&mut self.buffer).await?;
-        Ok(self.buffer[..n].to_vec())
+        if n == 0 {
+            bail!("connection closed by server");
+        }
+        self.received.extend_from_slice(&self.buffer[..n]);
+        Ok(())
     }
 
-    fn build_command_string(&self, event: &Event) -> String {
-        let key = self.generate_key(event.key_hash, event.key_size);
+    /// The oldest complete response received; commands are answered in
+    /// order. A line that is not a known response still answers one command.
+    pub fn next_response(&mut self) -> Option<Vec<u8>> {
+        self.skip_quiet_responses();
+        match MemcacheParser::new().frame_response(&self.received) {
+            FramedResponse::Response(_, len, _) | FramedResponse::Skip(len) => {
+                Some(self.received.drain(..len).collect())
+            }
+            FramedResponse::Incomplete => None,
+        }
+    }
 
+    /// The responses to `events`, sent together by `send_commands`, once all
+    /// of them are received. The response to a multi-key `get` is split into
+    /// the response each key would have had alone.
+    pub fn next_responses(&mut self, events: &[Event]) -> Option<Vec<Vec<u8>>> {
+        if self.is_multi_get(events) {
+            let response = self.next_response()?;
+            let keys: Vec<String> = events.iter().map(|event| self.key_of(event)).collect();
+            return Some(split_multi_get(&response, &keys));
+        }
+        // Nothing is consumed until every response is in
+        self.skip_quiet_responses();
+        let parser = MemcacheParser::new();
+        let mut lengths = Vec::with_capacity(events.len());
+        let mut offset = 0;
+        for _ in events {
+            match parser.frame_response(&self.received[offset..]) {
+                FramedResponse::Response(_, len, _) | FramedResponse::Skip(len) => {
+                    lengths.push(len);
+                    offset += len;
+                }
+                FramedResponse::Incomplete => return None,
+            }
+        }
+        Some(
+            lengths
+                .into_iter()
+                .map(|len| self.received.drain(..len).collect())
+                .collect(),
+        )
+    }
+
+    /// Whether `event` is sent quietly, so that no response is waited for:
+    /// flagged quiet when recorded, with a quiet variant in this protocol.
+    /// Meta and binary quiet commands are still answered on errors and
+    /// retrieval hits; those responses are skipped.
+    pub fn is_quiet(&self, event: &Event) -> bool {
+        event.flags.has_quiet()
+            && match self.protocol_mode {
+                // noreply: never answered
+                ProtocolMode::Ascii => !matches!(
+                    event.cmd_type,
+                    CommandType::Get | CommandType::Gat | CommandType::Noop
+                ),
+                ProtocolMode::Meta => event.cmd_type != CommandType::Noop,
+                ProtocolMode::Binary => quiet_opcode(event.cmd_type).is_some(),
+            }
+    }
+
+    /// Drop the responses to quiet commands at the front of what was
+    /// received, told apart by the opaque their commands carry
+    fn skip_quiet_responses(&mut self) {
+        while let FramedResponse::Response(_, len, opaque) =
+            MemcacheParser::new().frame_response(&self.received)
+        {
+            let quiet = match self.protocol_mode {
+                ProtocolMode::Ascii => false,
+                ProtocolMode::Meta => {
+                    let line_end = self.received[..len]
+                        .iter()
+                        .position(|&b| b == b'\n')
+                        .unwrap_or(len);
+                    self.received[..line_end]
+                        .trim_ascii_end()
+                        .split(|&b| b == b' ')
+                        .any(|token| token == QUIET_META_OPAQUE.as_bytes())
+                }
+                ProtocolMode::Binary => opaque.is_some_and(|opaque| opaque & QUIET_OPAQUE_BIT != 0),
+            };
+            if !quiet {
+                break;
+            }
+            self.received.drain(..len);
+        }
+    }
+
+    /// Whether `events` are sent as one multi-key `get`
+    fn is_multi_get(&self, events: &[Event]) -> bool {
+        self.protocol_mode == ProtocolMode::Ascii
+            && events.len() > 1
+            && events
+                .iter()
+                .all(|event| event.cmd_type == CommandType::Get)
+    }
+
+    /// Whether a GET response reports a miss
+    pub fn is_miss(&self, response: &[u8]) -> bool {
         match self.protocol_mode {
-            ProtocolMode::Ascii => self.build_ascii_command(&key, event),
-            ProtocolMode::Meta => self.build_meta_command(&key, event),
+            ProtocolMode::Ascii => response.starts_with(b"END"),
+            ProtocolMode::Meta => response.starts_with(b"EN"),
+            ProtocolMode::Binary => is_not_found(response),
         }
     }
 
-    /// Build ASCII protocol command (get, set, delete)
-    fn build_ascii_command(&self, key: &str, event: &Event) -> String {
-        match event.cmd_type {
-            CommandType::Get => {
-                format!("get {}\r\n", key)
+    /// SET the key of `event` with a `value_size` value (used to backfill
+    /// misses) and consume the response
+    pub async fn send_backfill(&mut self, event: &Event, value_size: u32) -> Result<()> {
+        let set = Event {
+            cmd_type: CommandType::Set,
+            value_size: std::num::NonZero::new(value_size),
+            ..event.clone()
+        };
+        self.send_command(&set).await?;
+        self.read_response().await?;
+        Ok(())
+    }
+
+    /// Send a no-op (`version` or `mn`) to keep an idle connection open.
+    /// The response is consumed but not recorded in any stats.
+    pub async fn send_keepalive(&mut self) -> Result<()> {
+        let cmd = match self.protocol_mode {
+            ProtocolMode::Ascii => b"version\r\n".to_vec(),
+            ProtocolMode::Meta => b"mn\r\n".to_vec(),
+            ProtocolMode::Binary => {
+                self.opaque = self.opaque.wrapping_add(1) & !QUIET_OPAQUE_BIT;
+                encode_request(
+                    command_opcode(CommandType::Noop),
+                    self.opaque,
+                    0,
+                    &[],
+                    &[],
+                    &[],
+                )
             }
-            CommandType::Set => {
-                let size = event.value_size.map(|nz| nz.get()).unwrap_or(0);
-                let value = self.generate_value(size);
-                format!("set {} 0 0 {}\r\n{}\r\n", key, size, value)
+        };
+        self.stream.write_all(&cmd).await?;
+        self.stream.flush().await?;
+        self.read_response().await?;
+        Ok(())
+    }
+
+    /// Key sent for `event`, after the key prefix: recorded keys are replayed
+    /// as they were, when they are valid text. Generated keys are shortened
+    /// to keep the prefixed key within `MAX_KEY_LEN`.
+    fn key_of(&self, event: &Event) -> String {
+        let key = match event.key.as_deref().map(std::str::from_utf8) {
+            Some(Ok(key)) => key.to_string(),
+            _ => {
+                let room = MAX_KEY_LEN.saturating_sub(self.key_prefix.len()) as u32;
+                self.generate_key(event.key_hash, event.key_size.min(room))
+            }
+        };
+        if self.key_prefix.is_empty() {
+            key
+        } else {
+            format!("{}{}", self.key_prefix, key)
+        }
+    }
+
+    fn build_command(&mut self, event: &Event) -> Vec<u8> {
+        let key = self.key_of(event);
+
+        let quiet = self.is_quiet(event);
+        match self.protocol_mode {
+            ProtocolMode::Ascii => self.build_ascii_command(&key, event, quiet),
+            ProtocolMode::Meta => self.build_meta_command(&key, event, quiet),
+            ProtocolMode::Binary => {
+                self.opaque = self.opaque.wrapping_add(1) & !QUIET_OPAQUE_BIT;
+                let opaque = if quiet {
+                    self.opaque | QUIET_OPAQUE_BIT
+                } else {
+                    self.opaque
+                };
+                self.build_binary_command(&key, event, opaque, quiet)
             }
-            CommandType::Delete => {
-                format!("delete {}\r\n", key)
+        }
+    }
+
+    /// `header`, then a value of `size` bytes and its line end
+    fn with_value(&mut self, header: String, key_hash: u64, size: u32) -> Vec<u8> {
+        let mut command = header.into_bytes();
+        self.values.fill(key_hash, size as usize, &mut command);
+        command.extend_from_slice(b"\r\n");
+        command
+    }
+
+    /// Build ASCII protocol command (get, the storage commands, delete,
+    /// incr/decr, touch, gat), with `noreply` when `quiet`
+    fn build_ascii_command(&mut self, key: &str, event: &Event, quiet: bool) -> Vec<u8> {
+        let size = event.value_size.map(|nz| nz.get()).unwrap_or(0);
+        let exptime = exptime(event);
+        let noreply = if quiet { " noreply" } else { "" };
+        let header = |name: &str| format!("{} {} 0 {} {}{}\r\n", name, key, exptime, size, noreply);
+        let mut storage = |header: String| self.with_value(header, event.key_hash, size);
+        match event.cmd_type {
+            CommandType::Get => format!("get {}\r\n", key).into_bytes(),
+            CommandType::Set => storage(header("set")),
+            CommandType::Add => storage(header("add")),
+            CommandType::Replace => storage(header("replace")),
+            CommandType::Append => storage(header("append")),
+            CommandType::Prepend => storage(header("prepend")),
+            CommandType::Cas => {
+                // The recorded cas value is not kept: a stale one is replayed
+                storage(format!(
+                    "cas {} 0 {} {} 0{}\r\n",
+                    key, exptime, size, noreply
+                ))
             }
-            CommandType::Noop => "version\r\n".to_string(),
+            CommandType::Delete => format!("delete {}{}\r\n", key, noreply).into_bytes(),
+            CommandType::Incr => format!("incr {} 1{}\r\n", key, noreply).into_bytes(),
+            CommandType::Decr => format!("decr {} 1{}\r\n", key, noreply).into_bytes(),
+            CommandType::Touch => format!("touch {} {}{}\r\n", key, exptime, noreply).into_bytes(),
+            CommandType::Gat => format!("gat {} {}\r\n", exptime, key).into_bytes(),
+            CommandType::Noop => b"version\r\n".to_vec(),
+        }
+    }
+
+    /// Build Meta protocol command (mg, ms, md, ma, mn). A quiet command
+    /// takes the q flag, and an opaque telling its responses apart.
+    fn build_meta_command(&mut self, key: &str, event: &Event, quiet: bool) -> Vec<u8> {
+        let mut command = self.build_meta_line(key, event);
+        if quiet {
+            let line_end = command
+                .windows(2)
+                .position(|window| window == b"\r\n")
+                .expect("meta commands end with CRLF");
+            let flags = format!(" q {}", QUIET_META_OPAQUE).into_bytes();
+            command.splice(line_end..line_end, flags);
         }
+        command
     }
 
-    /// Build Meta protocol command (mg, ms, md, mn)
-    fn build_meta_command(&self, key: &str, event: &Event) -> String {
+    fn build_meta_line(&mut self, key: &str, event: &Event) -> Vec<u8> {
+        let size = event.value_size.map(|nz| nz.get()).unwrap_or(0);
+        let exptime = exptime(event);
+        let mut storage = |flags: &str| {
+            let ttl = match exptime {
+                0 => String::new(),
+                exptime => format!(" T{}", exptime),
+            };
+            let header = format!("ms {} {}{}{}\r\n", key, size, ttl, flags);
+            self.with_value(header, event.key_hash, size)
+        };
         match event.cmd_type {
-            CommandType::Get => {
-                format!("mg {} v\r\n", key)
+            CommandType::Get => format!("mg {} v\r\n", key).into_bytes(),
+            CommandType::Set => storage(""),
+            CommandType::Add => storage(" ME"),
+            CommandType::Replace => storage(" MR"),
+            CommandType::Append => storage(" MA"),
+            CommandType::Prepend => storage(" MP"),
+            CommandType::Cas => storage(" C0"),
+            CommandType::Delete => format!("md {}\r\n", key).into_bytes(),
+            CommandType::Incr => format!("ma {}\r\n", key).into_bytes(),
+            CommandType::Decr => format!("ma {} MD\r\n", key).into_bytes(),
+            CommandType::Touch => format!("mg {} T{}\r\n", key, exptime).into_bytes(),
+            CommandType::Gat => format!("mg {} v T{}\r\n", key, exptime).into_bytes(),
+            CommandType::Noop => b"mn\r\n".to_vec(),
+        }
+    }
+
+    /// Build a binary protocol request frame, with the quiet opcode when `quiet`
+    fn build_binary_command(
+        &mut self,
+        key: &str,
+        event: &Event,
+        opaque: u32,
+        quiet: bool,
+    ) -> Vec<u8> {
+        let size = event.value_size.map(|nz| nz.get()).unwrap_or(0) as usize;
+        let exptime = (exptime(event) as u32).to_be_bytes();
+        let mut extras = Vec::new();
+        let mut value = Vec::new();
+        match event.cmd_type {
+            CommandType::Set | CommandType::Add | CommandType::Replace | CommandType::Cas => {
+                // Flags, then exptime
+                extras.extend_from_slice(&0u32.to_be_bytes());
+                extras.extend_from_slice(&exptime);
+                self.values.fill(event.key_hash, size, &mut value);
             }
-            CommandType::Set => {
-                let size = event.value_size.map(|nz| nz.get()).unwrap_or(0);
-                let value = self.generate_value(size);
-                format!("ms {} {}\r\n{}\r\n", key, size, value)
+            CommandType::Append | CommandType::Prepend => {
+                self.values.fill(event.key_hash, size, &mut value)
             }
-            CommandType::Delete => {
-                format!("md {}\r\n", key)
+            CommandType::Incr | CommandType::Decr => {
+                // Delta 1, no initial value: a missing counter is not created,
+                // like the text commands
+                extras.extend_from_slice(&1u64.to_be_bytes());
+                extras.extend_from_slice(&0u64.to_be_bytes());
+                extras.extend_from_slice(&u32::MAX.to_be_bytes());
             }
-            CommandType::Noop => "mn\r\n".to_string(),
+            CommandType::Touch | CommandType::Gat => extras.extend_from_slice(&exptime),
+            CommandType::Get | CommandType::Delete | CommandType::Noop => {}
         }
+        let key = match event.cmd_type {
+            CommandType::Noop => "",
+            _ => key,
+        };
+        // The recorded cas value is not kept: a stale one is replayed
+        let cas = match event.cmd_type {
+            CommandType::Cas => 1,
+            _ => 0,
+        };
+        let opcode = match quiet_opcode(event.cmd_type) {
+            Some(opcode) if quiet => opcode,
+            _ => command_opcode(event.cmd_type),
+        };
+        encode_request(opcode, opaque, cas, &extras, key.as_bytes(), &value)
     }
 
     /// Generate a deterministic key from hash and size
@@ -100,17 +492,63 @@ impl ReplayClient {
 
         key
     }
+}
 
-    /// Generate a value payload of specified size
-    /// Uses a repeating pattern to fill the size
-    fn generate_value(&self, size: u32) -> String {
-        if size == 0 {
-            return String::new();
-        }
+/// Opaque of quiet meta commands, echoed by the responses they still get
+const QUIET_META_OPAQUE: &str = "Oquiet";
+
+/// Opaque bit set on quiet binary requests, echoed by their responses
+const QUIET_OPAQUE_BIT: u32 = 1 << 31;
+
+/// Split the response to `get <keys>` into the response to a `get` of each
+/// key alone: its value and `END`, or `END` for a miss. An error answers
+/// every key.
+fn split_multi_get(response: &[u8], keys: &[String]) -> Vec<Vec<u8>> {
+    if !response.starts_with(b"VALUE ") && !response.starts_with(b"END") {
+        return vec![response.to_vec(); keys.len()];
+    }
+    let mut values: HashMap<&[u8], &[u8]> = HashMap::new();
+    let mut rest = response;
+    while rest.starts_with(b"VALUE ") {
+        let Some(line_end) = rest.iter().position(|&b| b == b'\n') else {
+            break;
+        };
+        // VALUE <key> <flags> <bytes> [<cas>]
+        let mut parts = rest[..line_end].trim_ascii_end().split(|&b| b == b' ');
+        let key = parts.nth(1).unwrap_or_default();
+        let Some(bytes) = parts
+            .nth(1)
+            .and_then(|bytes| std::str::from_utf8(bytes).ok()?.parse::<usize>().ok())
+        else {
+            break;
+        };
+        let end = (line_end + 1 + bytes + 2).min(rest.len());
+        values.entry(key).or_insert(&rest[..end]);
+        rest = &rest[end..];
+    }
+    keys.iter()
+        .map(|key| match values.get(key.as_bytes()) {
+            Some(value) => [value, b"END\r\n".as_slice()].concat(),
+            None => b"END\r\n".to_vec(),
+        })
+        .collect()
+}
 
-        // Generate payload matching size
-        let pattern = "x";
-        pattern.repeat(size as usize)
+/// Longest exptime memcached reads as relative; larger ones are Unix times
+const MAX_RELATIVE_EXPTIME: u64 = 60 * 60 * 24 * 30;
+
+/// Exptime field sending the TTL of `event`: 0 for none
+fn exptime(event: &Event) -> u64 {
+    match event.ttl.map(|ttl| ttl.get() as u64) {
+        None => 0,
+        Some(ttl) if ttl <= MAX_RELATIVE_EXPTIME => ttl,
+        Some(ttl) => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            now + ttl
+        }
     }
 }
 
@@ -0,0 +1,148 @@
+//! A/B replay: every command mirrored to two targets, and their latencies
+//! compared
+
+use super::connection_task::ConnectionMessage;
+use super::rate_limit::RateLimiter;
+use super::stats::TargetStats;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Percentiles compared between the two targets (100 is the maximum)
+pub const COMPARED_PERCENTILES: [f64; 5] = [50.0, 90.0, 99.0, 99.9, 100.0];
+
+/// Forward every message of one replayed connection to its connection to
+/// each target. With a rate limit, a mirrored command takes one token.
+pub async fn mirror(
+    mut rx: mpsc::Receiver<ConnectionMessage>,
+    queues: Vec<mpsc::Sender<ConnectionMessage>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+) {
+    while let Some(message) = rx.recv().await {
+        if let (Some(limiter), ConnectionMessage::Event(..)) = (&rate_limiter, &message) {
+            limiter.acquire().await;
+        }
+        for tx in &queues {
+            if tx.send(message.clone()).await.is_err() {
+                return; // Connection task stopped
+            }
+        }
+    }
+}
+
+/// Pairs the latency of each command on target A with the same command on
+/// target B. Both connections of a replayed connection complete its commands
+/// in the same order, so the n-th latency of one matches the n-th of the other.
+#[derive(Debug, Default)]
+pub struct LatencyPairing {
+    /// Latencies (µs) waiting for the other target's, per connection and
+    /// target; `None` during the warmup
+    pending: HashMap<u16, [VecDeque<Option<u64>>; 2]>,
+    /// Commands B answered faster than A, slower, and as fast
+    pub b_faster: u64,
+    pub b_slower: u64,
+    pub ties: u64,
+}
+
+impl LatencyPairing {
+    /// Add the latencies of the next commands of `connection_id` on `target` (0 or 1)
+    pub fn add(&mut self, connection_id: u16, target: usize, latencies: Vec<Option<u64>>) {
+        let queues = self.pending.entry(connection_id).or_default();
+        let Some(queue) = queues.get_mut(target) else {
+            return;
+        };
+        queue.extend(latencies);
+        let [a, b] = queues;
+        while let (Some(&a_latency), Some(&b_latency)) = (a.front(), b.front()) {
+            a.pop_front();
+            b.pop_front();
+            // Left out unless measured on both sides
+            let (Some(a_latency), Some(b_latency)) = (a_latency, b_latency) else {
+                continue;
+            };
+            match b_latency.cmp(&a_latency) {
+                Ordering::Less => self.b_faster += 1,
+                Ordering::Greater => self.b_slower += 1,
+                Ordering::Equal => self.ties += 1,
+            }
+        }
+    }
+
+    /// Commands measured on both targets
+    pub fn compared(&self) -> u64 {
+        self.b_faster + self.b_slower + self.ties
+    }
+}
+
+/// Latency at one percentile on both targets
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PercentileDelta {
+    pub percentile: f64,
+    pub a_micros: u64,
+    pub b_micros: u64,
+    /// B minus A: positive when B is slower
+    pub delta_micros: i64,
+}
+
+impl PercentileDelta {
+    /// Delta relative to A, in percent
+    pub fn delta_percent(&self) -> f64 {
+        if self.a_micros == 0 {
+            return 0.0;
+        }
+        self.delta_micros as f64 / self.a_micros as f64 * 100.0
+    }
+}
+
+/// Comparison of target B against target A
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComparisonReport {
+    pub target_a: String,
+    pub target_b: String,
+    pub percentiles: Vec<PercentileDelta>,
+    /// Commands B answered faster than A, slower, and as fast
+    pub b_faster: u64,
+    pub b_slower: u64,
+    pub ties: u64,
+}
+
+impl ComparisonReport {
+    pub fn new(
+        (target_a, a): (&str, &TargetStats),
+        (target_b, b): (&str, &TargetStats),
+        pairing: &LatencyPairing,
+    ) -> Self {
+        let percentiles = COMPARED_PERCENTILES
+            .iter()
+            .map(|&percentile| {
+                let a_micros = a.percentile(percentile).unwrap_or(0);
+                let b_micros = b.percentile(percentile).unwrap_or(0);
+                PercentileDelta {
+                    percentile,
+                    a_micros,
+                    b_micros,
+                    delta_micros: b_micros as i64 - a_micros as i64,
+                }
+            })
+            .collect();
+        ComparisonReport {
+            target_a: target_a.to_string(),
+            target_b: target_b.to_string(),
+            percentiles,
+            b_faster: pairing.b_faster,
+            b_slower: pairing.b_slower,
+            ties: pairing.ties,
+        }
+    }
+
+    /// Share of the compared commands in `count`, in percent
+    pub fn share(&self, count: u64) -> f64 {
+        let compared = self.b_faster + self.b_slower + self.ties;
+        if compared == 0 {
+            return 0.0;
+        }
+        count as f64 / compared as f64 * 100.0
+    }
+}
@@ -1,58 +1,780 @@
 use super::client::ReplayClient;
-use super::stats::{ConnectionStats, StatsSnapshot};
-use super::ProtocolMode;
-use crate::profile::Event;
-use anyhow::Result;
-use std::time::Instant;
-use tokio::sync::mpsc;
-
-/// Spawns a single connection task that processes commands from a queue
+use super::rate_limit::RateLimiter;
+use super::stats::{ConnectionStats, ErrorType, StatsSnapshot};
+use super::value::ValueFill;
+use super::{IterationMode, ProtocolMode};
+use crate::profile::{CommandType, Event};
+use anyhow::{anyhow, bail, Result};
+use std::collections::{HashMap, VecDeque};
+use std::num::NonZero;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinSet;
+
+/// Work item of a connection queue
+#[derive(Debug, Clone)]
+pub enum ConnectionMessage {
+    /// An event, and when it was meant to be sent: its scheduled time, or
+    /// when it was queued
+    Event(Event, Instant),
+    /// The reader finished a pass over the profile; what follows belongs to the next one
+    EndOfIteration,
+}
+
+/// Settings of one replay connection
+#[derive(Clone)]
+pub struct ConnectionConfig {
+    pub protocol_mode: ProtocolMode,
+    /// Send a no-op whenever the queue stays idle this long
+    pub keepalive: Option<Duration>,
+    /// Drop mutating commands instead of sending them
+    pub read_only: bool,
+    /// SET keys whose GET missed, with this value size
+    pub backfill_value_size: Option<u32>,
+    pub iteration_mode: IterationMode,
+    /// Operations completing before this instant only count as warmup
+    pub warmup_until: Option<Instant>,
+    /// Operations completing from this instant on only count as ramp-down
+    pub ramp_down_from: Option<Instant>,
+    /// Rewrites the recorded TTL of every command
+    pub ttl: TtlPolicy,
+    /// Throughput cap shared with the other connections
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Commands sent ahead of their responses, which arrive in order; 1 waits
+    /// for every response before sending the next command
+    pub pipeline_depth: usize,
+    /// Time each command from when it was meant to be sent rather than from
+    /// when it went out (open loop)
+    pub open_loop: bool,
+    /// Send the GETs queued within this window of the first one as a single
+    /// multi-key request
+    pub coalesce_gets: Option<Duration>,
+    /// Compare responses with the recorded ones, keeping this many mismatches
+    pub validate: Option<usize>,
+    /// Index of the target connected to, among the replay's targets
+    pub target_index: usize,
+    /// Report the latency of every command, to compare the targets of an A/B replay
+    pub log_latencies: bool,
+    /// How a connection recovers from errors
+    pub reconnect: ReconnectPolicy,
+    /// Longest wait for the response to a command; the connection is
+    /// replaced once a response is late, as it could still arrive
+    pub request_timeout: Option<Duration>,
+    /// Prepended to every key sent; empty for none
+    pub key_prefix: String,
+    /// Payload of the values written
+    pub value_fill: ValueFill,
+    /// Write values from the key pattern and check retrieval hits against it
+    pub verify: bool,
+}
+
+impl ConnectionConfig {
+    pub fn new(protocol_mode: ProtocolMode) -> Self {
+        ConnectionConfig {
+            protocol_mode,
+            keepalive: None,
+            read_only: false,
+            backfill_value_size: None,
+            iteration_mode: IterationMode::Warm,
+            warmup_until: None,
+            ramp_down_from: None,
+            ttl: TtlPolicy::default(),
+            rate_limiter: None,
+            pipeline_depth: 1,
+            open_loop: false,
+            coalesce_gets: None,
+            validate: None,
+            target_index: 0,
+            log_latencies: false,
+            reconnect: ReconnectPolicy::default(),
+            request_timeout: None,
+            key_prefix: String::new(),
+            value_fill: ValueFill::default(),
+            verify: false,
+        }
+    }
+}
+
+/// How a connection recovers from an error: the requests in flight fail,
+/// then it reconnects, waiting `backoff` before the first attempt and twice
+/// as long before each of the next ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// Attempts before giving up on the connection; 0 fails it on the first error
+    pub max_retries: u32,
+    pub backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_retries: 5,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Fail the connection on its first error
+    pub fn fail_fast() -> Self {
+        ReconnectPolicy {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_fail_fast(&self) -> bool {
+        self.max_retries == 0
+    }
+
+    /// Wait before the reconnect attempt numbered `attempt`, from 0
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(MAX_BACKOFF)
+    }
+}
+
+/// How recorded TTLs are replayed: scaled, then capped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TtlPolicy {
+    /// Factor applied to every TTL; 0 replays every item without expiration
+    pub scale: f64,
+    /// Longest TTL replayed
+    pub max: Option<Duration>,
+}
+
+impl Default for TtlPolicy {
+    fn default() -> Self {
+        TtlPolicy {
+            scale: 1.0,
+            max: None,
+        }
+    }
+}
+
+impl TtlPolicy {
+    /// TTL to replay for a command recorded with `ttl`. A scaled TTL never
+    /// drops below 1s, which would mean no expiration.
+    pub fn apply(&self, ttl: Option<NonZero<u32>>) -> Option<NonZero<u32>> {
+        if self.scale == 0.0 {
+            return None;
+        }
+        let scaled = (ttl?.get() as f64 * self.scale).round().max(1.0);
+        let capped = match self.max {
+            Some(max) => scaled.min(max.as_secs().max(1) as f64),
+            None => scaled,
+        };
+        NonZero::new(capped.min(u32::MAX as f64) as u32)
+    }
+}
+
+/// How connections to the target are established before the replay starts
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectLimits {
+    /// Connection attempts in flight at once
+    pub parallelism: usize,
+    /// Deadline for every connection to be established
+    pub timeout: Duration,
+}
+
+impl Default for ConnectLimits {
+    fn default() -> Self {
+        ConnectLimits {
+            parallelism: 64,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Connect every `(connection_id, protocol)` to `target` concurrently.
+/// Fails unless all of them connect before the deadline, so a replay never
+/// starts with part of its connections missing.
+pub async fn establish_connections(
+    target: &str,
+    connections: &[(u16, ProtocolMode)],
+    limits: ConnectLimits,
+) -> Result<HashMap<u16, ReplayClient>> {
+    let started = Instant::now();
+    let deadline = tokio::time::Instant::now() + limits.timeout;
+    let permits = Arc::new(Semaphore::new(limits.parallelism.max(1)));
+    let mut attempts = JoinSet::new();
+    for &(connection_id, protocol_mode) in connections {
+        let permits = Arc::clone(&permits);
+        let target = target.to_string();
+        attempts.spawn(async move {
+            let _permit = permits
+                .acquire_owned()
+                .await
+                .expect("semaphore is never closed");
+            (
+                connection_id,
+                ReplayClient::new(&target, protocol_mode).await,
+            )
+        });
+    }
+
+    let mut clients = HashMap::with_capacity(connections.len());
+    let mut failed = 0usize;
+    let mut first_error = None;
+    loop {
+        match tokio::time::timeout_at(deadline, attempts.join_next()).await {
+            Ok(Some(joined)) => match joined? {
+                (connection_id, Ok(client)) => {
+                    clients.insert(connection_id, client);
+                }
+                (connection_id, Err(e)) => {
+                    failed += 1;
+                    first_error
+                        .get_or_insert_with(|| anyhow!("connection {}: {}", connection_id, e));
+                }
+            },
+            Ok(None) => break,
+            Err(_) => {
+                attempts.abort_all();
+                bail!(
+                    "Timed out after {:?} connecting to {}: {} of {} connections established, {} failed, {} pending",
+                    limits.timeout,
+                    target,
+                    clients.len(),
+                    connections.len(),
+                    failed,
+                    connections.len() - clients.len() - failed
+                );
+            }
+        }
+    }
+
+    if let Some(e) = first_error {
+        bail!(
+            "{} of {} connections to {} could not be established (first error: {})",
+            failed,
+            connections.len(),
+            target,
+            e
+        );
+    }
+    tracing::info!(
+        "Established {} connections in {:.2}s",
+        clients.len(),
+        started.elapsed().as_secs_f64()
+    );
+    Ok(clients)
+}
+
+/// Most GETs coalesced into one request
+const MAX_COALESCED_GETS: usize = 100;
+
+/// Longest wait for the responses in flight when a replay is stopped
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest wait between two reconnect attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// A pipelined request waiting for its responses
+struct InFlight {
+    /// Its commands: several only when GETs were coalesced
+    events: Vec<Event>,
+    /// Where its latency counts from
+    start: Instant,
+    /// When it was sent, where its timeout counts from
+    sent: Instant,
+}
+
+/// A response that did not arrive within the request timeout
+#[derive(Debug, thiserror::Error)]
+#[error("no response within {0:?}")]
+pub struct RequestTimeout(pub Duration);
+
+/// Run `io`, failing with `RequestTimeout` unless it completes within `timeout`
+async fn within<T>(
+    timeout: Option<Duration>,
+    io: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+    match timeout {
+        Some(limit) => tokio::time::timeout(limit, io)
+            .await
+            .map_err(|_| RequestTimeout(limit))?,
+        None => io.await,
+    }
+}
+
+/// How to count `error`: as a timeout when it is one, else as `otherwise`
+fn error_type(error: &anyhow::Error, otherwise: ErrorType) -> ErrorType {
+    if error.is::<RequestTimeout>() {
+        ErrorType::Timeout
+    } else {
+        otherwise
+    }
+}
+
+/// The message held back by the last coalescing, else the next one queued.
+/// Cancel safe: a held message is returned on the first poll.
+async fn next_message(
+    held: &mut Option<ConnectionMessage>,
+    rx: &mut mpsc::Receiver<ConnectionMessage>,
+) -> Option<ConnectionMessage> {
+    match held.take() {
+        Some(message) => Some(message),
+        None => rx.recv().await,
+    }
+}
+
+/// `first` and the GETs queued after it within `window`, up to
+/// `MAX_COALESCED_GETS`. The first other message is held back.
+async fn coalesce_gets(
+    first: Event,
+    window: Duration,
+    rx: &mut mpsc::Receiver<ConnectionMessage>,
+    held: &mut Option<ConnectionMessage>,
+) -> Vec<Event> {
+    let deadline = tokio::time::Instant::now() + window;
+    let mut gets = vec![first];
+    while gets.len() < MAX_COALESCED_GETS {
+        match tokio::time::timeout_at(deadline, rx.recv()).await {
+            Ok(Some(ConnectionMessage::Event(event, _)))
+                if event.cmd_type == CommandType::Get && !event.flags.has_quiet() =>
+            {
+                gets.push(event);
+            }
+            Ok(Some(message)) => {
+                *held = Some(message);
+                break;
+            }
+            Ok(None) | Err(_) => break,
+        }
+    }
+    gets
+}
+
+/// Record a completed command and its response; a corrupted hit only
+/// counts as an error
+fn record_completion(
+    client: &ReplayClient,
+    stats: &mut ConnectionStats,
+    event: &Event,
+    response: &[u8],
+    latency: Duration,
+) {
+    match client.check_integrity(event, response) {
+        Some(false) => {
+            stats.record_error(event.cmd_type, ErrorType::IntegrityError);
+            stats.record_sent(event);
+            return;
+        }
+        Some(true) => stats.record_verified(),
+        None => {}
+    }
+    let miss = event.cmd_type.is_retrieval() && client.is_miss(response);
+    stats.record_success(event.cmd_type, latency);
+    stats.record_namespace(event, latency, miss);
+    stats.record_sent(event);
+    stats.record_response(event, response);
+    if miss {
+        stats.record_miss();
+    }
+}
+
+/// Record the responses received for the oldest commands in flight
+fn complete_responses(
+    client: &mut ReplayClient,
+    in_flight: &mut VecDeque<InFlight>,
+    stats: &mut ConnectionStats,
+) {
+    while let Some(front) = in_flight.front() {
+        let Some(responses) = client.next_responses(&front.events) else {
+            break;
+        };
+        let InFlight { events, start, .. } = in_flight.pop_front().expect("request in flight");
+        let latency = start.elapsed();
+        for (event, response) in events.iter().zip(&responses) {
+            record_completion(client, stats, event, response, latency);
+        }
+    }
+}
+
+/// Wait for the response to every command in flight. A request timing out
+/// is counted and taken off the queue.
+async fn drain_responses(
+    client: &mut ReplayClient,
+    in_flight: &mut VecDeque<InFlight>,
+    stats: &mut ConnectionStats,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    while let Some(front) = in_flight.front() {
+        let received = match timeout {
+            Some(limit) => {
+                let deadline = tokio::time::Instant::from_std(front.sent + limit);
+                match tokio::time::timeout_at(deadline, client.receive()).await {
+                    Ok(received) => received,
+                    Err(_) => {
+                        let request = in_flight.pop_front().expect("request in flight");
+                        stats.record_error(request.events[0].cmd_type, ErrorType::Timeout);
+                        return Err(RequestTimeout(limit).into());
+                    }
+                }
+            }
+            None => client.receive().await,
+        };
+        received?;
+        complete_responses(client, in_flight, stats);
+    }
+    Ok(())
+}
+
+/// Recovery of a connection task from its errors
+struct Reconnector {
+    target: String,
+    protocol_mode: ProtocolMode,
+    key_prefix: String,
+    value_fill: ValueFill,
+    verify: bool,
+    policy: ReconnectPolicy,
+    connection_id: u16,
+    cancel_token: tokio_util::sync::CancellationToken,
+}
+
+impl Reconnector {
+    /// Fail the requests in flight after `error`, unless failing fast
+    fn abandon(
+        &self,
+        error: anyhow::Error,
+        in_flight: &mut VecDeque<InFlight>,
+        stats: &mut ConnectionStats,
+    ) -> Result<anyhow::Error> {
+        if self.policy.is_fail_fast() {
+            return Err(error);
+        }
+        for request in in_flight.drain(..) {
+            stats.record_error(request.events[0].cmd_type, ErrorType::ConnectionError);
+        }
+        Ok(error)
+    }
+
+    /// Fail the requests in flight after `error` and reconnect `client`.
+    /// Returns false when cancelled before reconnecting.
+    async fn recover(
+        &self,
+        error: anyhow::Error,
+        client: &mut ReplayClient,
+        in_flight: &mut VecDeque<InFlight>,
+        stats: &mut ConnectionStats,
+    ) -> Result<bool> {
+        let error = self.abandon(error, in_flight, stats)?;
+        tracing::warn!(
+            "Connection {}: {:#}, reconnecting",
+            self.connection_id,
+            error
+        );
+        self.reconnect(error, client, stats).await
+    }
+
+    /// Apply the connection's key prefix, value fill and verification to `client`
+    fn prepare(&self, client: ReplayClient) -> ReplayClient {
+        let client = client
+            .with_key_prefix(&self.key_prefix)
+            .with_values(self.value_fill.generator());
+        if self.verify {
+            client.with_verification()
+        } else {
+            client
+        }
+    }
+
+    async fn connect(&self) -> Result<ReplayClient> {
+        let client = ReplayClient::new(&self.target, self.protocol_mode).await?;
+        Ok(self.prepare(client))
+    }
+
+    /// Replace `client` with a new connection, retrying with backoff.
+    /// Fails with `error` once out of attempts.
+    async fn reconnect(
+        &self,
+        error: anyhow::Error,
+        client: &mut ReplayClient,
+        stats: &mut ConnectionStats,
+    ) -> Result<bool> {
+        for attempt in 0..self.policy.max_retries {
+            tokio::select! {
+                _ = self.cancel_token.cancelled() => return Ok(false),
+                _ = tokio::time::sleep(self.policy.backoff(attempt)) => {}
+            }
+            match self.connect().await {
+                Ok(reconnected) => {
+                    *client = reconnected;
+                    stats.record_reconnect();
+                    return Ok(true);
+                }
+                Err(e) => tracing::debug!(
+                    "Connection {}: reconnect attempt {} failed: {}",
+                    self.connection_id,
+                    attempt + 1,
+                    e
+                ),
+            }
+        }
+        Err(error.context(format!(
+            "connection {} lost after {} reconnect attempts",
+            self.connection_id, self.policy.max_retries
+        )))
+    }
+}
+
+/// Spawns a single connection task that processes commands from a queue,
+/// over an already established client
 pub async fn spawn_connection_task(
+    client: ReplayClient,
     target: &str,
-    rx: mpsc::Receiver<Event>,
+    rx: mpsc::Receiver<ConnectionMessage>,
     stats_tx: mpsc::Sender<StatsSnapshot>,
     connection_id: u16,
-    protocol_mode: ProtocolMode,
+    config: ConnectionConfig,
     cancel_token: tokio_util::sync::CancellationToken,
 ) -> Result<tokio::task::JoinHandle<Result<()>>> {
     let target = target.to_string();
+    let ConnectionConfig {
+        protocol_mode,
+        keepalive,
+        read_only,
+        backfill_value_size,
+        iteration_mode,
+        warmup_until,
+        ramp_down_from,
+        ttl,
+        rate_limiter,
+        pipeline_depth,
+        open_loop,
+        coalesce_gets: coalesce_window,
+        validate,
+        target_index,
+        log_latencies,
+        reconnect: reconnect_policy,
+        request_timeout,
+        key_prefix,
+        value_fill,
+        verify,
+    } = config;
+    let window = pipeline_depth.max(1);
+    let pipelined = window > 1 || open_loop;
+
+    let reconnector = Reconnector {
+        target,
+        protocol_mode,
+        key_prefix,
+        value_fill,
+        verify,
+        policy: reconnect_policy,
+        connection_id,
+        cancel_token: cancel_token.clone(),
+    };
 
     let handle = tokio::spawn(async move {
-        let mut client = ReplayClient::new(&target, protocol_mode).await?;
+        let mut client = reconnector.prepare(client);
         let mut rx = rx;
-        let mut local_stats = ConnectionStats::new(connection_id);
+        let mut local_stats = ConnectionStats::new(connection_id).with_target(target_index);
+        if let Some(until) = warmup_until {
+            local_stats = local_stats.with_warmup_until(until);
+        }
+        if let Some(from) = ramp_down_from {
+            local_stats = local_stats.with_ramp_down_from(from);
+        }
+        if let Some(limit) = validate {
+            local_stats = local_stats.with_validation(limit);
+        }
+        if log_latencies {
+            local_stats = local_stats.with_latency_log();
+        }
         let mut interval = tokio::time::interval(std::time::Duration::from_secs(2));
+        let mut last_activity = tokio::time::Instant::now();
+        let mut keepalives = 0u64;
+        let mut dropped = 0u64;
+        let mut reconnect = false;
+        let mut in_flight: VecDeque<InFlight> = VecDeque::new();
+        let mut held = None;
 
         loop {
+            // When the oldest request in flight times out
+            let response_deadline = request_timeout
+                .zip(in_flight.front())
+                .map(|(limit, front)| tokio::time::Instant::from_std(front.sent + limit));
             tokio::select! {
                 _ = cancel_token.cancelled() => {
                     tracing::debug!("Connection {} cancelled", connection_id);
+                    // Commands already sent are counted, unless the target stalls
+                    let drain = drain_responses(&mut client, &mut in_flight, &mut local_stats, request_timeout);
+                    match tokio::time::timeout(DRAIN_TIMEOUT, drain).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => {
+                            reconnector.abandon(e, &mut in_flight, &mut local_stats)?;
+                        }
+                        Err(_) => tracing::warn!(
+                            "Connection {}: {} responses still pending when stopping",
+                            connection_id,
+                            in_flight.len()
+                        ),
+                    }
                     break;
                 }
-                event_opt = rx.recv() => {
-                    match event_opt {
-                        Some(event) => {
+                message_opt = next_message(&mut held, &mut rx), if in_flight.len() < window => {
+                    match message_opt {
+                        Some(ConnectionMessage::EndOfIteration) => {
+                            // Responses still in flight belong to the iteration ending
+                            if let Err(e) = drain_responses(&mut client, &mut in_flight, &mut local_stats, request_timeout).await {
+                                if !reconnector.recover(e, &mut client, &mut in_flight, &mut local_stats).await? {
+                                    break;
+                                }
+                            }
+                            if stats_tx.send(local_stats.end_iteration()).await.is_err() {
+                                break; // Receiver dropped
+                            }
+                            // Reconnect lazily, so no connection is opened after the last iteration
+                            reconnect = iteration_mode == IterationMode::Reset;
+                        }
+                        Some(ConnectionMessage::Event(event, _)) if read_only && event.cmd_type.is_mutation() => {
+                            dropped += 1;
+                        }
+                        Some(ConnectionMessage::Event(event, intended)) => {
+                            let event = Event {
+                                ttl: ttl.apply(event.ttl),
+                                ..event
+                            };
+                            let events = match coalesce_window {
+                                Some(coalesce_window) if event.cmd_type == CommandType::Get && !event.flags.has_quiet() => {
+                                    coalesce_gets(event, coalesce_window, &mut rx, &mut held).await
+                                }
+                                _ => vec![event],
+                            };
+                            let cmd_type = events[0].cmd_type;
+                            if reconnect {
+                                match reconnector.connect().await {
+                                    Ok(reconnected) => client = reconnected,
+                                    Err(e) if reconnect_policy.is_fail_fast() => return Err(e),
+                                    Err(e) => {
+                                        if !reconnector.reconnect(e, &mut client, &mut local_stats).await? {
+                                            break;
+                                        }
+                                    }
+                                }
+                                reconnect = false;
+                            }
+                            if let Some(limiter) = &rate_limiter {
+                                tokio::select! {
+                                    _ = cancel_token.cancelled() => break,
+                                    _ = limiter.acquire_many(events.len()) => {}
+                                }
+                            }
                             let start = Instant::now();
 
-                            if let Err(e) = client.send_command(&event).await {
-                                local_stats.record_error(event.cmd_type, super::stats::ErrorType::ConnectionError);
-                                return Err(e);
+                            if let Err(e) = client.send_commands(&events).await {
+                                local_stats.record_error(cmd_type, ErrorType::ConnectionError);
+                                if !reconnector.recover(e, &mut client, &mut in_flight, &mut local_stats).await? {
+                                    break;
+                                }
+                                continue;
                             }
-
-                            if let Err(e) = client.read_response().await {
-                                local_stats.record_error(event.cmd_type, super::stats::ErrorType::ProtocolError);
-                                return Err(e);
+                            if let [event] = events.as_slice() {
+                                if client.is_quiet(event) {
+                                    // Nothing to wait for: the response only comes on errors or hits
+                                    local_stats.record_quiet();
+                                    local_stats.record_sent(event);
+                                    last_activity = tokio::time::Instant::now();
+                                    continue;
+                                }
+                            } else {
+                                local_stats.record_multi_get(events.len());
+                            }
+                            if pipelined {
+                                let sent = start;
+                                let start = if open_loop { intended } else { start };
+                                in_flight.push_back(InFlight { events, start, sent });
+                                last_activity = tokio::time::Instant::now();
+                                continue;
                             }
 
+                            let responses = match within(request_timeout, client.read_responses(&events)).await {
+                                Ok(responses) => responses,
+                                Err(e) => {
+                                    local_stats.record_error(cmd_type, error_type(&e, ErrorType::ProtocolError));
+                                    if !reconnector.recover(e, &mut client, &mut in_flight, &mut local_stats).await? {
+                                        break;
+                                    }
+                                    continue;
+                                }
+                            };
+
                             let latency = start.elapsed();
-                            local_stats.record_success(event.cmd_type, latency);
+                            let mut backfill_error = None;
+                            for (event, response) in events.iter().zip(&responses) {
+                                record_completion(&client, &mut local_stats, event, response, latency);
+                                let Some(value_size) = backfill_value_size else {
+                                    continue;
+                                };
+                                if event.cmd_type.is_retrieval() && client.is_miss(response) {
+                                    // Kept out of latency stats and the sent traffic
+                                    if let Err(e) = within(request_timeout, client.send_backfill(event, value_size)).await {
+                                        local_stats.record_error(CommandType::Set, error_type(&e, ErrorType::ConnectionError));
+                                        backfill_error = Some(e);
+                                        break;
+                                    }
+                                    local_stats.record_backfill();
+                                }
+                            }
+                            if let Some(e) = backfill_error {
+                                if !reconnector.recover(e, &mut client, &mut in_flight, &mut local_stats).await? {
+                                    break;
+                                }
+                            }
+                            last_activity = tokio::time::Instant::now();
                         }
                         None => {
                             // Channel closed
                             tracing::debug!("Connection {} channel closed", connection_id);
+                            if let Err(e) = drain_responses(&mut client, &mut in_flight, &mut local_stats, request_timeout).await {
+                                // Nothing left to send: no point reconnecting
+                                reconnector.abandon(e, &mut in_flight, &mut local_stats)?;
+                            }
+                            break;
+                        }
+                    }
+                }
+                received = client.receive(), if !in_flight.is_empty() => {
+                    if let Err(e) = received {
+                        if let Some(pending) = in_flight.pop_front() {
+                            local_stats.record_error(pending.events[0].cmd_type, ErrorType::ProtocolError);
+                        }
+                        if !reconnector.recover(e, &mut client, &mut in_flight, &mut local_stats).await? {
+                            break;
+                        }
+                        last_activity = tokio::time::Instant::now();
+                        continue;
+                    }
+                    complete_responses(&mut client, &mut in_flight, &mut local_stats);
+                    last_activity = tokio::time::Instant::now();
+                }
+                _ = tokio::time::sleep_until(response_deadline.unwrap_or(last_activity)), if response_deadline.is_some() => {
+                    let request = in_flight.pop_front().expect("request in flight");
+                    local_stats.record_error(request.events[0].cmd_type, ErrorType::Timeout);
+                    let e = RequestTimeout(request_timeout.unwrap_or_default()).into();
+                    if !reconnector.recover(e, &mut client, &mut in_flight, &mut local_stats).await? {
+                        break;
+                    }
+                    last_activity = tokio::time::Instant::now();
+                }
+                _ = tokio::time::sleep_until(last_activity + keepalive.unwrap_or_default()), if keepalive.is_some() && in_flight.is_empty() => {
+                    // Not a replayed command: kept out of latency stats
+                    if let Err(e) = within(request_timeout, client.send_keepalive()).await {
+                        local_stats.record_error(CommandType::Noop, error_type(&e, ErrorType::ConnectionError));
+                        let _ = stats_tx.send(local_stats.snapshot()).await;
+                        let e = e.context(format!("keepalive failed on connection {}", connection_id));
+                        if !reconnector.recover(e, &mut client, &mut in_flight, &mut local_stats).await? {
                             break;
                         }
+                        last_activity = tokio::time::Instant::now();
+                        continue;
                     }
+                    keepalives += 1;
+                    last_activity = tokio::time::Instant::now();
                 }
                 _ = interval.tick() => {
                     if stats_tx.send(local_stats.snapshot()).await.is_err() {
@@ -63,7 +785,12 @@ pub async fn spawn_connection_task(
         }
 
         let _ = stats_tx.send(local_stats.snapshot()).await;
-        tracing::debug!("Connection {} exiting", connection_id);
+        tracing::debug!(
+            "Connection {} exiting ({} keepalives, {} mutations dropped)",
+            connection_id,
+            keepalives,
+            dropped
+        );
         Ok(())
     });
 
@@ -0,0 +1,93 @@
+//! Behavior of each connection of a profile
+//!
+//! Traffic dominated by a few busy clients replays differently from traffic
+//! spread over many: the report gives each connection its commands, how long
+//! it was active, and how many of its keys other connections use too.
+
+use crate::profile::{CommandType, Event};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionStats {
+    pub conn_id: u16,
+    pub events: u64,
+    pub per_command: HashMap<CommandType, u64>,
+    /// First and last timestamps of the events of the connection
+    pub first: u64,
+    pub last: u64,
+    /// Distinct keys of the connection
+    pub keys: u64,
+    /// Distinct keys of the connection some other connection uses as well
+    pub shared_keys: u64,
+}
+
+impl ConnectionStats {
+    /// Microseconds between the first and last events
+    pub fn duration(&self) -> u64 {
+        self.last - self.first
+    }
+
+    /// Events per second while the connection was active, if it was for
+    /// longer than an instant
+    pub fn rate(&self) -> Option<f64> {
+        let duration = self.duration();
+        (duration > 0).then(|| self.events as f64 / (duration as f64 / 1_000_000.0))
+    }
+
+    /// Share of the keys of the connection other connections use as well
+    pub fn overlap(&self) -> f64 {
+        self.shared_keys as f64 / self.keys.max(1) as f64
+    }
+}
+
+/// Statistics of every connection of `events`, busiest first
+pub fn connection_report(events: &[Event]) -> Vec<ConnectionStats> {
+    let mut connections: HashMap<u16, ConnectionStats> = HashMap::new();
+    // Keys of each connection, and the first connection using each key with
+    // whether another one does too
+    let mut accessed: HashSet<(u16, u64)> = HashSet::new();
+    let mut users: HashMap<u64, (u16, bool)> = HashMap::new();
+    for event in events {
+        let stats = connections
+            .entry(event.conn_id)
+            .or_insert_with(|| ConnectionStats {
+                conn_id: event.conn_id,
+                events: 0,
+                per_command: HashMap::new(),
+                first: event.timestamp,
+                last: event.timestamp,
+                keys: 0,
+                shared_keys: 0,
+            });
+        stats.events += 1;
+        *stats.per_command.entry(event.cmd_type).or_insert(0) += 1;
+        stats.first = stats.first.min(event.timestamp);
+        stats.last = stats.last.max(event.timestamp);
+        if accessed.insert((event.conn_id, event.key_hash)) {
+            stats.keys += 1;
+            let (first_user, shared) = users
+                .entry(event.key_hash)
+                .or_insert((event.conn_id, false));
+            *shared |= *first_user != event.conn_id;
+        }
+    }
+    for (conn_id, key_hash) in accessed {
+        if users[&key_hash].1 {
+            connections
+                .get_mut(&conn_id)
+                .expect("every connection accessing a key has stats")
+                .shared_keys += 1;
+        }
+    }
+
+    let mut report: Vec<ConnectionStats> = connections.into_values().collect();
+    report.sort_by_key(|stats| (std::cmp::Reverse(stats.events), stats.conn_id));
+    report
+}
+
+/// Share of `total` events the `n` busiest connections of `report` account for
+pub fn busiest_share(report: &[ConnectionStats], n: usize, total: u64) -> f64 {
+    let events: u64 = report.iter().take(n).map(|stats| stats.events).sum();
+    events as f64 / total.max(1) as f64
+}
@@ -0,0 +1,188 @@
+//! Live reconfiguration of a running replay through a control socket
+//!
+//! The socket accepts one command per line and answers each with one line:
+//! `rate N`, `pause`, `resume` and `stats`.
+
+use crate::units::parse_count;
+use anyhow::{bail, Context, Result};
+use std::os::unix::fs::FileTypeExt;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixListener;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+/// Running totals published by the stats aggregator for the `stats` command
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LiveStats {
+    pub operations: u64,
+    pub errors: u64,
+    pub get_misses: u64,
+    pub elapsed_secs: f64,
+    pub throughput: f64,
+}
+
+/// Settings operators can change while a replay runs
+pub struct ReplayControl {
+    paused: watch::Sender<bool>,
+    /// Requested rate; `None` for replays without one, which cannot be re-rated
+    rate: watch::Sender<Option<u64>>,
+    live_stats: watch::Sender<LiveStats>,
+}
+
+/// What event producers watch: whether to hold events back, and at what rate to send
+#[derive(Debug, Clone)]
+pub struct ControlHandle {
+    pub paused: watch::Receiver<bool>,
+    pub rate: watch::Receiver<Option<u64>>,
+}
+
+impl ReplayControl {
+    pub fn new(rate: Option<u64>) -> Self {
+        ReplayControl {
+            paused: watch::Sender::new(false),
+            rate: watch::Sender::new(rate),
+            live_stats: watch::Sender::new(LiveStats::default()),
+        }
+    }
+
+    pub fn handle(&self) -> ControlHandle {
+        ControlHandle {
+            paused: self.paused.subscribe(),
+            rate: self.rate.subscribe(),
+        }
+    }
+
+    /// The requested rate, for a ramp to change it
+    pub fn rate(&self) -> watch::Sender<Option<u64>> {
+        self.rate.clone()
+    }
+
+    /// Where the stats aggregator publishes its totals
+    pub fn live_stats(&self) -> watch::Sender<LiveStats> {
+        self.live_stats.clone()
+    }
+
+    /// Execute one command line and return its one-line answer
+    pub fn execute(&self, line: &str) -> String {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("rate"), Some(value), None) => {
+                if self.rate.borrow().is_none() {
+                    return "error: rate can only be changed on a replay started with --rate"
+                        .to_string();
+                }
+                match parse_count(value) {
+                    Ok(0) => "error: rate must be positive".to_string(),
+                    Ok(rate) => {
+                        self.rate.send_replace(Some(rate));
+                        tracing::info!("Control: rate set to {} ops/sec", rate);
+                        format!("ok rate {}", rate)
+                    }
+                    Err(e) => format!("error: {}", e),
+                }
+            }
+            (Some("pause"), None, None) => {
+                self.paused.send_replace(true);
+                tracing::info!("Control: replay paused");
+                "ok paused".to_string()
+            }
+            (Some("resume"), None, None) => {
+                self.paused.send_replace(false);
+                tracing::info!("Control: replay resumed");
+                "ok resumed".to_string()
+            }
+            (Some("stats"), None, None) => {
+                let stats = *self.live_stats.borrow();
+                let rate = match *self.rate.borrow() {
+                    Some(rate) => rate.to_string(),
+                    None => "unbounded".to_string(),
+                };
+                format!(
+                    "ok operations={} errors={} get_misses={} elapsed={:.2}s throughput={:.0} paused={} rate={}",
+                    stats.operations,
+                    stats.errors,
+                    stats.get_misses,
+                    stats.elapsed_secs,
+                    stats.throughput,
+                    *self.paused.borrow(),
+                    rate
+                )
+            }
+            _ => format!(
+                "error: unknown command '{}'. Use 'rate N', 'pause', 'resume' or 'stats'",
+                line.trim()
+            ),
+        }
+    }
+}
+
+impl ControlHandle {
+    /// Wait while the replay is paused; false if cancelled in the meantime
+    pub async fn wait_until_resumed(&mut self, cancel_token: &CancellationToken) -> bool {
+        if !*self.paused.borrow_and_update() {
+            return true;
+        }
+        tokio::select! {
+            _ = cancel_token.cancelled() => false,
+            // Without a sender nobody can pause again: carry on
+            _ = self.paused.wait_for(|paused| !paused) => true,
+        }
+    }
+}
+
+/// Bind the control socket at `path`, replacing a stale one
+pub fn bind_control_socket(path: &str) -> Result<UnixListener> {
+    // Only replace a stale socket, never a regular file
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            bail!("{} exists and is not a socket", path);
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale control socket {}", path))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind control socket {}", path))?;
+    tracing::info!("Control socket listening on {}", path);
+    Ok(listener)
+}
+
+/// Answer commands on `listener` until cancelled, then remove the socket at `path`
+pub async fn serve_control(
+    control: Arc<ReplayControl>,
+    listener: UnixListener,
+    path: &str,
+    cancel_token: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = cancel_token.cancelled() => break,
+            accepted = listener.accept() => {
+                let (stream, _) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("Control socket accept failed: {}", e);
+                        continue;
+                    }
+                };
+                let control = Arc::clone(&control);
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if line.trim().is_empty() {
+                            continue;
+                        }
+                        let mut answer = control.execute(&line);
+                        answer.push('\n');
+                        if writer.write_all(answer.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    let _ = std::fs::remove_file(path);
+}
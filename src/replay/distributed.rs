@@ -0,0 +1,365 @@
+//! Distributed replay: a coordinator shares the connections of a workload
+//! between workers on several load-generator hosts, starts them together and
+//! gathers their statistics
+//!
+//! Coordinator and workers exchange bincode messages, each prefixed with its
+//! length as a little-endian u32. A worker joins, is assigned its share of
+//! the connections, reports ready once connected to the target, starts at the
+//! time the coordinator names, then streams its snapshots until done.
+
+use super::fidelity::TrafficSummary;
+use super::stats::{ErrorType, NamespaceStats, StatsSnapshot};
+use super::validation::ResponseValidation;
+use crate::profile::CommandType;
+use anyhow::{anyhow, bail, Context, Result};
+use hdrhistogram::serialization::{Deserializer, Serializer, V2Serializer};
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// Largest message accepted; snapshots are far smaller
+const MAX_MESSAGE_LEN: usize = 64 << 20;
+
+/// Share of the connections replayed by one worker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partition {
+    /// Index of the worker, from 0
+    pub index: usize,
+    pub workers: usize,
+}
+
+impl Partition {
+    /// Connections of this worker: every `workers`-th of the sorted ids,
+    /// so every worker picks the same split of the same workload
+    pub fn select(&self, mut connections: Vec<u16>) -> Vec<u16> {
+        connections.sort_unstable();
+        connections
+            .into_iter()
+            .enumerate()
+            .filter(|(position, _)| position % self.workers == self.index)
+            .map(|(_, conn_id)| conn_id)
+            .collect()
+    }
+
+    /// Worker replaying `conn_id` out of every connection of the workload,
+    /// `connections`, sorted; `None` if it is not one of them
+    pub fn owner(&self, connections: &[u16], conn_id: u16) -> Option<usize> {
+        connections
+            .binary_search(&conn_id)
+            .ok()
+            .map(|position| position % self.workers)
+    }
+
+    /// This worker's share of a rate over every worker, rounded up
+    pub fn share(&self, rate: u64) -> u64 {
+        rate.div_ceil(self.workers as u64).max(1)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Message {
+    /// Worker to coordinator, once connected
+    Join,
+    /// Coordinator to worker: its share of the connections
+    Assign { index: usize, workers: usize },
+    /// Worker to coordinator: connected to the target, waiting to start
+    Ready,
+    /// Coordinator to worker: start replaying at this wall-clock time
+    Start { at_unix_micros: u64 },
+    /// Worker to coordinator: statistics of one of its connections
+    Snapshot(Box<WireSnapshot>),
+    /// Worker to coordinator: replay finished, against these targets
+    Done { targets: Vec<String> },
+}
+
+async fn write_message(stream: &mut TcpStream, message: &Message) -> Result<()> {
+    let bytes = bincode::serialize(message)?;
+    stream
+        .write_all(&(bytes.len() as u32).to_le_bytes())
+        .await?;
+    stream.write_all(&bytes).await?;
+    Ok(())
+}
+
+/// Next message from `stream`, `None` once the peer closed the connection
+async fn read_message(stream: &mut TcpStream) -> Result<Option<Message>> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        bail!(
+            "message of {} bytes exceeds the {} byte limit",
+            len,
+            MAX_MESSAGE_LEN
+        );
+    }
+    let mut bytes = vec![0u8; len];
+    stream.read_exact(&mut bytes).await?;
+    Ok(Some(bincode::deserialize(&bytes)?))
+}
+
+/// A statistics snapshot as sent over the network: histograms in the
+/// HdrHistogram V2 format, and times as wall-clock µs since the Unix epoch
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WireSnapshot {
+    connection_id: u16,
+    target: usize,
+    histograms: HashMap<CommandType, Vec<u8>>,
+    success_counts: HashMap<CommandType, u64>,
+    error_counts: HashMap<ErrorType, u64>,
+    traffic: TrafficSummary,
+    get_misses: u64,
+    backfills: u64,
+    reconnects: u64,
+    verified_hits: u64,
+    multi_gets: u64,
+    coalesced_gets: u64,
+    quiet_operations: u64,
+    warmup_operations: u64,
+    ramp_down_operations: u64,
+    first_operation: Option<u64>,
+    last_operation: Option<u64>,
+    end_of_iteration: bool,
+    validation: Option<(ResponseValidation, usize)>,
+    latency_log: Vec<Option<u64>>,
+    /// Latency histogram, retrievals and misses of each key namespace
+    namespaces: HashMap<u32, (Vec<u8>, u64, u64)>,
+}
+
+impl WireSnapshot {
+    pub fn encode(snapshot: &StatsSnapshot) -> Result<Self> {
+        let mut histograms = HashMap::new();
+        for (&cmd_type, histogram) in &snapshot.histograms {
+            let mut bytes = Vec::new();
+            V2Serializer::new().serialize(histogram, &mut bytes)?;
+            histograms.insert(cmd_type, bytes);
+        }
+        let mut namespaces = HashMap::new();
+        for (&namespace, stats) in &snapshot.namespaces {
+            let mut bytes = Vec::new();
+            V2Serializer::new().serialize(stats.latency(), &mut bytes)?;
+            namespaces.insert(namespace, (bytes, stats.retrievals(), stats.get_misses()));
+        }
+        Ok(WireSnapshot {
+            connection_id: snapshot.connection_id,
+            target: snapshot.target,
+            histograms,
+            success_counts: snapshot.success_counts.clone(),
+            error_counts: snapshot.error_counts.clone(),
+            traffic: snapshot.traffic.clone(),
+            get_misses: snapshot.get_misses,
+            backfills: snapshot.backfills,
+            reconnects: snapshot.reconnects,
+            verified_hits: snapshot.verified_hits,
+            multi_gets: snapshot.multi_gets,
+            coalesced_gets: snapshot.coalesced_gets,
+            quiet_operations: snapshot.quiet_operations,
+            warmup_operations: snapshot.warmup_operations,
+            ramp_down_operations: snapshot.ramp_down_operations,
+            first_operation: snapshot.first_operation.map(to_unix_micros),
+            last_operation: snapshot.last_operation.map(to_unix_micros),
+            end_of_iteration: snapshot.end_of_iteration,
+            validation: snapshot.validation.clone().map(|validation| {
+                let limit = validation.limit();
+                (validation, limit)
+            }),
+            latency_log: snapshot.latency_log.clone(),
+            namespaces,
+        })
+    }
+
+    pub fn decode(self) -> Result<StatsSnapshot> {
+        let mut histograms = HashMap::new();
+        for (cmd_type, bytes) in self.histograms {
+            let histogram: Histogram<u64> = Deserializer::new().deserialize(&mut &bytes[..])?;
+            histograms.insert(cmd_type, histogram);
+        }
+        let mut namespaces = HashMap::new();
+        for (namespace, (bytes, retrievals, get_misses)) in self.namespaces {
+            let latency: Histogram<u64> = Deserializer::new().deserialize(&mut &bytes[..])?;
+            namespaces.insert(
+                namespace,
+                NamespaceStats::from_parts(latency, retrievals, get_misses),
+            );
+        }
+        Ok(StatsSnapshot {
+            connection_id: self.connection_id,
+            target: self.target,
+            histograms,
+            success_counts: self.success_counts,
+            error_counts: self.error_counts,
+            traffic: self.traffic,
+            get_misses: self.get_misses,
+            backfills: self.backfills,
+            reconnects: self.reconnects,
+            verified_hits: self.verified_hits,
+            multi_gets: self.multi_gets,
+            coalesced_gets: self.coalesced_gets,
+            quiet_operations: self.quiet_operations,
+            warmup_operations: self.warmup_operations,
+            ramp_down_operations: self.ramp_down_operations,
+            first_operation: self.first_operation.map(from_unix_micros),
+            last_operation: self.last_operation.map(from_unix_micros),
+            end_of_iteration: self.end_of_iteration,
+            // The limit is not part of the serialized validation
+            validation: self.validation.map(|(validation, limit)| {
+                let mut limited = ResponseValidation::new(limit);
+                limited.merge(validation);
+                limited
+            }),
+            latency_log: self.latency_log,
+            namespaces,
+        })
+    }
+}
+
+fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Wall-clock time of `instant`; hosts compare these, so their clocks must
+/// be synchronized (NTP)
+fn to_unix_micros(instant: Instant) -> u64 {
+    unix_now().saturating_sub(instant.elapsed()).as_micros() as u64
+}
+
+fn from_unix_micros(micros: u64) -> Instant {
+    let ago = unix_now().saturating_sub(Duration::from_micros(micros));
+    Instant::now().checked_sub(ago).unwrap_or_else(Instant::now)
+}
+
+/// A worker's connection to its coordinator
+pub struct WorkerLink {
+    stream: TcpStream,
+    pub partition: Partition,
+}
+
+impl WorkerLink {
+    /// Join the coordinator at `coordinator` and receive this worker's share
+    /// of the connections
+    pub async fn join(coordinator: &str) -> Result<Self> {
+        let mut stream = TcpStream::connect(coordinator)
+            .await
+            .context(format!("failed to connect to coordinator {}", coordinator))?;
+        stream.set_nodelay(true)?;
+        write_message(&mut stream, &Message::Join).await?;
+        match read_message(&mut stream).await? {
+            Some(Message::Assign { index, workers }) => Ok(WorkerLink {
+                stream,
+                partition: Partition { index, workers },
+            }),
+            other => Err(unexpected(other, "an assignment")),
+        }
+    }
+
+    /// Report ready, then wait for the start time set by the coordinator
+    pub async fn wait_for_start(&mut self) -> Result<()> {
+        write_message(&mut self.stream, &Message::Ready).await?;
+        let at = match read_message(&mut self.stream).await? {
+            Some(Message::Start { at_unix_micros }) => Duration::from_micros(at_unix_micros),
+            other => return Err(unexpected(other, "the start time")),
+        };
+        tokio::time::sleep(at.saturating_sub(unix_now())).await;
+        Ok(())
+    }
+
+    pub async fn send_snapshot(&mut self, snapshot: &StatsSnapshot) -> Result<()> {
+        let message = Message::Snapshot(Box::new(WireSnapshot::encode(snapshot)?));
+        write_message(&mut self.stream, &message).await
+    }
+
+    /// Tell the coordinator every snapshot was sent
+    pub async fn finish(mut self, targets: Vec<String>) -> Result<()> {
+        write_message(&mut self.stream, &Message::Done { targets }).await?;
+        self.stream.shutdown().await?;
+        Ok(())
+    }
+}
+
+fn unexpected(message: Option<Message>, expected: &str) -> anyhow::Error {
+    match message {
+        Some(message) => anyhow!("expected {}, received {:?}", expected, message),
+        None => anyhow!("connection closed while waiting for {}", expected),
+    }
+}
+
+/// The coordinator's connection to one of its workers
+pub struct WorkerSession {
+    pub index: usize,
+    pub peer: SocketAddr,
+    stream: TcpStream,
+}
+
+/// Accept `workers` workers on `listener`, assigning each its share of the
+/// connections in the order they join
+pub async fn gather_workers(listener: &TcpListener, workers: usize) -> Result<Vec<WorkerSession>> {
+    let mut sessions = Vec::with_capacity(workers);
+    while sessions.len() < workers {
+        let (mut stream, peer) = listener.accept().await?;
+        match read_message(&mut stream).await {
+            Ok(Some(Message::Join)) => {}
+            _ => {
+                tracing::warn!("Ignoring connection from {}: not a worker", peer);
+                continue;
+            }
+        }
+        let index = sessions.len();
+        write_message(&mut stream, &Message::Assign { index, workers }).await?;
+        tracing::info!("Worker {} joined from {}", index, peer);
+        sessions.push(WorkerSession {
+            index,
+            peer,
+            stream,
+        });
+    }
+    Ok(sessions)
+}
+
+/// Wait until every worker is ready, then start them all `delay` from now
+pub async fn start_workers(sessions: &mut [WorkerSession], delay: Duration) -> Result<()> {
+    for session in sessions.iter_mut() {
+        match read_message(&mut session.stream).await? {
+            Some(Message::Ready) => {}
+            other => return Err(unexpected(other, "the worker to be ready")),
+        }
+    }
+    let at_unix_micros = (unix_now() + delay).as_micros() as u64;
+    for session in sessions.iter_mut() {
+        write_message(&mut session.stream, &Message::Start { at_unix_micros }).await?;
+    }
+    Ok(())
+}
+
+/// Forward the snapshots of a started worker to `stats` until it is done,
+/// returning the targets it replayed against
+pub async fn collect_snapshots(
+    mut session: WorkerSession,
+    stats: mpsc::Sender<StatsSnapshot>,
+) -> Result<Vec<String>> {
+    loop {
+        match read_message(&mut session.stream).await? {
+            Some(Message::Snapshot(snapshot)) => {
+                // Keep reading once the aggregator stopped, to see the worker finish
+                stats.send(snapshot.decode()?).await.ok();
+            }
+            Some(Message::Done { targets }) => return Ok(targets),
+            None => bail!(
+                "worker {} ({}) disconnected before finishing its replay",
+                session.index,
+                session.peer
+            ),
+            Some(other) => return Err(unexpected(Some(other), "a snapshot")),
+        }
+    }
+}
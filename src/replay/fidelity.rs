@@ -0,0 +1,175 @@
+//! Replay fidelity: how closely the traffic actually sent matches the profile
+
+use crate::profile::{CommandType, Event};
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Command, key size and connection counts of a stream of events
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrafficSummary {
+    pub command_counts: HashMap<CommandType, u64>,
+    pub key_size_counts: HashMap<u32, u64>,
+    pub connection_counts: HashMap<u16, u64>,
+}
+
+impl TrafficSummary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        *self.command_counts.entry(event.cmd_type).or_insert(0) += 1;
+        *self.key_size_counts.entry(event.key_size).or_insert(0) += 1;
+        *self.connection_counts.entry(event.conn_id).or_insert(0) += 1;
+    }
+
+    pub fn merge(&mut self, other: TrafficSummary) {
+        for (cmd_type, count) in other.command_counts {
+            *self.command_counts.entry(cmd_type).or_insert(0) += count;
+        }
+        for (size, count) in other.key_size_counts {
+            *self.key_size_counts.entry(size).or_insert(0) += count;
+        }
+        for (conn_id, count) in other.connection_counts {
+            *self.connection_counts.entry(conn_id).or_insert(0) += count;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.command_counts.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+}
+
+/// Comparison of the sent traffic against the expected (profile) traffic.
+/// Expected counts are already scaled by the number of loop iterations;
+/// they are unknown for infinite loops, where only the shares are compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FidelityReport {
+    pub expected_total: Option<u64>,
+    pub sent_total: u64,
+    /// (command, expected share, sent share), in command order
+    pub command_mix: Vec<(CommandType, f64, f64)>,
+    /// Total variation distance between the key size distributions (0 = identical, 1 = disjoint)
+    pub key_size_divergence: f64,
+    /// Connections whose sent count differs from the expected one: (conn_id, expected, sent)
+    pub connection_shortfalls: Vec<(u16, u64, u64)>,
+    /// (percentile, recorded µs, replayed µs), when the profile holds server
+    /// latencies
+    pub latencies: Vec<(f64, u64, u64)>,
+}
+
+impl FidelityReport {
+    /// Compare `sent` against one pass of `expected` repeated `iterations`
+    /// times (`None` for an unbounded number of passes)
+    pub fn compare(
+        expected: &TrafficSummary,
+        sent: &TrafficSummary,
+        iterations: Option<u64>,
+    ) -> Self {
+        let mut commands: Vec<CommandType> = expected
+            .command_counts
+            .keys()
+            .chain(sent.command_counts.keys())
+            .copied()
+            .collect();
+        commands.sort_unstable();
+        commands.dedup();
+
+        let command_mix = commands
+            .into_iter()
+            .map(|cmd_type| {
+                (
+                    cmd_type,
+                    share(&expected.command_counts, &cmd_type),
+                    share(&sent.command_counts, &cmd_type),
+                )
+            })
+            .collect();
+
+        let mut connection_shortfalls: Vec<(u16, u64, u64)> = match iterations {
+            Some(iterations) => expected
+                .connection_counts
+                .iter()
+                .map(|(&conn_id, &count)| {
+                    let sent_count = sent.connection_counts.get(&conn_id).copied().unwrap_or(0);
+                    (conn_id, count.saturating_mul(iterations), sent_count)
+                })
+                .filter(|(_, expected, sent)| expected != sent)
+                .collect(),
+            None => Vec::new(),
+        };
+        connection_shortfalls.sort_unstable();
+
+        FidelityReport {
+            expected_total: iterations.map(|n| expected.total().saturating_mul(n)),
+            sent_total: sent.total(),
+            command_mix,
+            key_size_divergence: total_variation(&expected.key_size_counts, &sent.key_size_counts),
+            connection_shortfalls,
+            latencies: Vec::new(),
+        }
+    }
+
+    /// Also compare the latencies measured by the replay against those the
+    /// profile recorded
+    pub fn with_latencies(mut self, recorded: &Histogram<u64>, replayed: &Histogram<u64>) -> Self {
+        self.latencies = [50.0, 90.0, 99.0, 99.9]
+            .into_iter()
+            .map(|percentile| {
+                (
+                    percentile,
+                    recorded.value_at_percentile(percentile),
+                    replayed.value_at_percentile(percentile),
+                )
+            })
+            .collect();
+        self
+    }
+
+    /// Fraction of the expected events that were sent, when known
+    pub fn completion(&self) -> Option<f64> {
+        self.expected_total.map(|expected| {
+            if expected == 0 {
+                1.0
+            } else {
+                self.sent_total as f64 / expected as f64
+            }
+        })
+    }
+
+    /// Largest absolute difference between expected and sent command shares
+    pub fn command_mix_divergence(&self) -> f64 {
+        self.command_mix
+            .iter()
+            .map(|(_, expected, sent)| (expected - sent).abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+fn share<K: std::hash::Hash + Eq>(counts: &HashMap<K, u64>, key: &K) -> f64 {
+    let total: u64 = counts.values().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    counts.get(key).copied().unwrap_or(0) as f64 / total as f64
+}
+
+fn total_variation(a: &HashMap<u32, u64>, b: &HashMap<u32, u64>) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return if a.is_empty() && b.is_empty() {
+            0.0
+        } else {
+            1.0
+        };
+    }
+    let keys: std::collections::HashSet<&u32> = a.keys().chain(b.keys()).collect();
+    keys.into_iter()
+        .map(|k| (share(a, k) - share(b, k)).abs())
+        .sum::<f64>()
+        / 2.0
+}
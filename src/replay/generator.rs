@@ -0,0 +1,269 @@
+use super::analyzer::AnalysisResult;
+use super::zipf::{fit_zipf_mandelbrot, ZipfSampler};
+use crate::profile::{CommandType, Event};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::num::NonZero;
+
+/// How generated events pick their key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyPopularity {
+    /// Fresh random key per event (no repeats, so ~0% hit rate)
+    Uniform,
+    /// Zipf-Mandelbrot over a finite keyspace of `keyspace` keys
+    Zipf { keyspace: u64, s: f64, q: f64 },
+}
+
+/// How far apart consecutive events of one connection are
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterArrival {
+    /// No gaps: every event is due immediately
+    Immediate,
+    /// Exponential gaps (Poisson arrivals) with the given mean, in µs
+    Poisson { mean_micros: f64 },
+    /// Power-of-two bucketed gaps as produced by DistributionAnalyzer
+    Empirical(Vec<(u64, u64)>),
+}
+
+/// Traffic generator sampling commands, key sizes and value sizes from an
+/// analyzed distribution. The same seed always yields the same event sequence.
+pub struct TrafficGenerator {
+    command_distribution: Vec<(CommandType, u64)>,
+    key_size_distribution: Vec<(u32, u64)>,
+    value_size_distribution: Vec<(u32, u64)>,
+    popularity: Option<ZipfSampler>,
+    inter_arrival: InterArrival,
+    connections: u16,
+    /// Next due timestamp per connection, earliest first
+    schedule: BinaryHeap<Reverse<(u64, u16)>>,
+    /// Probability that a GET reuses a recently set key
+    get_after_set: f64,
+    locality_window: usize,
+    recent_sets: VecDeque<(u64, u32)>,
+    rng: StdRng,
+}
+
+impl TrafficGenerator {
+    pub fn new(analysis: AnalysisResult) -> Self {
+        // Sample from sorted distributions: HashMap iteration order would
+        // otherwise make seeded runs diverge
+        let mut command_distribution: Vec<_> = analysis.command_distribution.into_iter().collect();
+        command_distribution.sort_unstable();
+        let mut key_size_distribution = analysis.key_size_distribution;
+        key_size_distribution.sort_unstable();
+        let mut value_size_distribution = analysis.value_size_distribution;
+        value_size_distribution.sort_unstable();
+
+        // Model popularity on the recorded keys when the analysis has them
+        let popularity = fit_zipf_mandelbrot(&analysis.key_frequencies)
+            .map(|fit| ZipfSampler::new(analysis.key_frequencies.len() as u64, fit.s, fit.q));
+
+        let inter_arrival = if analysis.inter_arrival_distribution.is_empty() {
+            InterArrival::Immediate
+        } else {
+            let mut distribution = analysis.inter_arrival_distribution;
+            distribution.sort_unstable();
+            InterArrival::Empirical(distribution)
+        };
+
+        TrafficGenerator {
+            command_distribution,
+            key_size_distribution,
+            value_size_distribution,
+            popularity,
+            inter_arrival,
+            connections: 1,
+            schedule: BinaryHeap::from([Reverse((0, 0))]),
+            get_after_set: 0.0,
+            locality_window: 0,
+            recent_sets: VecDeque::new(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Override the inter-arrival model
+    pub fn with_inter_arrival(mut self, inter_arrival: InterArrival) -> Self {
+        self.inter_arrival = inter_arrival;
+        self
+    }
+
+    /// Override the key popularity model
+    pub fn with_popularity(mut self, popularity: KeyPopularity) -> Self {
+        self.popularity = match popularity {
+            KeyPopularity::Uniform => None,
+            KeyPopularity::Zipf { keyspace, s, q } => Some(ZipfSampler::new(keyspace, s, q)),
+        };
+        self
+    }
+
+    /// Make a GET target one of the last `window` SET keys with the given
+    /// probability, instead of sampling the popularity model
+    pub fn with_get_after_set(mut self, probability: f64, window: usize) -> Self {
+        self.get_after_set = probability.clamp(0.0, 1.0);
+        self.locality_window = window;
+        self.recent_sets = VecDeque::with_capacity(window);
+        self
+    }
+
+    /// Use a deterministic PRNG seeded with `seed`
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Spread generated events over `connections` connection ids (0..connections)
+    pub fn with_connections(mut self, connections: u16) -> Self {
+        self.connections = connections.max(1);
+        self.schedule = (0..self.connections).map(|c| Reverse((0, c))).collect();
+        self
+    }
+
+    pub fn connections(&self) -> u16 {
+        self.connections
+    }
+
+    /// Next event, in timestamp order. Timestamps are µs from the start of the
+    /// generated trace; each connection advances by its own sampled gaps.
+    pub fn next_command(&mut self) -> Event {
+        let Reverse((timestamp, conn_id)) = self.schedule.pop().expect("schedule is never empty");
+        let gap = self.sample_gap();
+        self.schedule
+            .push(Reverse((timestamp.saturating_add(gap), conn_id)));
+
+        let cmd_type = self.sample_command();
+        let (key_hash, key_size) = match cmd_type {
+            CommandType::Get | CommandType::Gat => self
+                .sample_recent_set()
+                .unwrap_or_else(|| self.sample_key()),
+            _ => self.sample_key(),
+        };
+        let value_size = if cmd_type.has_value() {
+            self.remember_set(key_hash, key_size);
+            NonZero::new(self.sample_value_size())
+        } else {
+            None
+        };
+
+        Event {
+            value_size,
+            ..Event::new(timestamp, conn_id, cmd_type, key_hash, key_size)
+        }
+    }
+
+    fn sample_gap(&mut self) -> u64 {
+        match &self.inter_arrival {
+            InterArrival::Immediate => 0,
+            InterArrival::Poisson { mean_micros } => {
+                // Inverse CDF of the exponential distribution
+                let u: f64 = self.rng.gen();
+                (-(1.0 - u).ln() * mean_micros).round() as u64
+            }
+            InterArrival::Empirical(distribution) => {
+                let total: u64 = distribution.iter().map(|(_, c)| c).sum();
+                let mut r = self.rng.gen_range(0..total.max(1));
+                for &(bucket, count) in distribution {
+                    if r < count {
+                        // Uniform within the bucket (bucket / 2, bucket]
+                        return if bucket <= 1 {
+                            bucket
+                        } else {
+                            self.rng.gen_range(bucket / 2 + 1..=bucket)
+                        };
+                    }
+                    r -= count;
+                }
+                0
+            }
+        }
+    }
+
+    fn sample_command(&mut self) -> CommandType {
+        let total: u64 = self.command_distribution.iter().map(|(_, c)| c).sum();
+        if total == 0 {
+            return CommandType::Get;
+        }
+        let mut r = self.rng.gen_range(0..total);
+
+        for (cmd, count) in &self.command_distribution {
+            if r < *count {
+                return *cmd;
+            }
+            r -= count;
+        }
+
+        CommandType::Get
+    }
+
+    /// Pick a key hash and its size. Keys from the finite keyspace map to a
+    /// fixed hash and a fixed size, so repeated ranks hit the same server key.
+    fn sample_key(&mut self) -> (u64, u32) {
+        match &self.popularity {
+            Some(sampler) => {
+                let rank = sampler.sample(&mut self.rng);
+                let key_hash = splitmix64(rank);
+                let key_size =
+                    Self::pick_weighted(key_hash, &self.key_size_distribution).unwrap_or(10);
+                (key_hash, key_size)
+            }
+            None => {
+                let key_hash = self.rng.gen::<u64>();
+                let key_size =
+                    Self::sample_size(&mut self.rng, &self.key_size_distribution).unwrap_or(10);
+                (key_hash, key_size)
+            }
+        }
+    }
+
+    fn sample_recent_set(&mut self) -> Option<(u64, u32)> {
+        if self.recent_sets.is_empty() || self.rng.gen::<f64>() >= self.get_after_set {
+            return None;
+        }
+        let index = self.rng.gen_range(0..self.recent_sets.len());
+        self.recent_sets.get(index).copied()
+    }
+
+    fn remember_set(&mut self, key_hash: u64, key_size: u32) {
+        if self.locality_window == 0 {
+            return;
+        }
+        if self.recent_sets.len() == self.locality_window {
+            self.recent_sets.pop_front();
+        }
+        self.recent_sets.push_back((key_hash, key_size));
+    }
+
+    fn sample_value_size(&mut self) -> u32 {
+        Self::sample_size(&mut self.rng, &self.value_size_distribution).unwrap_or(100)
+    }
+
+    fn sample_size(rng: &mut impl Rng, distribution: &[(u32, u64)]) -> Option<u32> {
+        Self::pick_weighted(rng.gen::<u64>(), distribution)
+    }
+
+    fn pick_weighted(r: u64, distribution: &[(u32, u64)]) -> Option<u32> {
+        let total: u64 = distribution.iter().map(|(_, c)| c).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut r = r % total;
+
+        for (size, count) in distribution {
+            if r < *count {
+                return Some(*size);
+            }
+            r -= count;
+        }
+
+        None
+    }
+}
+
+/// Bijective 64-bit mix, used to turn keyspace ranks into key hashes
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,101 @@
+//! Hottest keys of a stream of events, in bounded memory
+//!
+//! Space-Saving (Metwally et al.): a fixed number of counters follows the
+//! keys seen so far; a key without a counter takes over the smallest one,
+//! inheriting its count as its possible overestimate. Every key seen more
+//! than `total / capacity` times has a counter, and no count is off by more
+//! than that.
+
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// Counters of each heavy-hitters sketch
+pub const HOT_KEY_COUNTERS: usize = 1024;
+
+/// A key among the most frequent of a stream
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HotKey {
+    pub key_hash: u64,
+    /// Times the key was seen, overestimated by at most `error`
+    pub count: u64,
+    pub error: u64,
+    /// Share of the stream on the key, from `count`
+    pub share: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Counter {
+    count: u64,
+    error: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct HeavyHitters {
+    capacity: usize,
+    total: u64,
+    counters: HashMap<u64, Counter>,
+    /// `(count, key_hash)` of every counter, smallest first
+    by_count: BTreeSet<(u64, u64)>,
+}
+
+impl HeavyHitters {
+    /// Sketch following the `capacity` most frequent keys
+    pub fn new(capacity: usize) -> Self {
+        HeavyHitters {
+            capacity: capacity.max(1),
+            total: 0,
+            counters: HashMap::with_capacity(capacity),
+            by_count: BTreeSet::new(),
+        }
+    }
+
+    pub fn insert(&mut self, key_hash: u64) {
+        self.total += 1;
+        let counter = match self.counters.get(&key_hash) {
+            Some(&counter) => {
+                self.by_count.remove(&(counter.count, key_hash));
+                Counter {
+                    count: counter.count + 1,
+                    error: counter.error,
+                }
+            }
+            None if self.counters.len() < self.capacity => Counter { count: 1, error: 0 },
+            None => {
+                let (smallest, evicted) = self.by_count.pop_first().expect("full sketch");
+                self.counters.remove(&evicted);
+                Counter {
+                    count: smallest + 1,
+                    error: smallest,
+                }
+            }
+        };
+        self.counters.insert(key_hash, counter);
+        self.by_count.insert((counter.count, key_hash));
+    }
+
+    /// Keys seen
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The `n` most frequent keys, most frequent first
+    pub fn top(&self, n: usize) -> Vec<HotKey> {
+        self.by_count
+            .iter()
+            .rev()
+            .take(n)
+            .map(|&(count, key_hash)| HotKey {
+                key_hash,
+                count,
+                error: self.counters[&key_hash].error,
+                share: count as f64 / self.total as f64,
+            })
+            .collect()
+    }
+}
+
+impl Default for HeavyHitters {
+    fn default() -> Self {
+        Self::new(HOT_KEY_COUNTERS)
+    }
+}
@@ -0,0 +1,71 @@
+//! Consistent hashing of commands over several replay targets, ketama style:
+//! each target owns many points of a ring, and a key goes to the target of
+//! the first point at or after its hash
+
+use super::connection_task::ConnectionMessage;
+use crate::record::keyed_hash::sha256;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Points each target places on the ring
+const POINTS_PER_TARGET: usize = 160;
+
+/// Ring of the points of every target, sorted
+pub struct HashRing {
+    points: Vec<(u64, usize)>,
+}
+
+impl HashRing {
+    /// Ring over `targets`. The points of a target depend on its address
+    /// only, so adding a target moves only the keys it takes over.
+    pub fn new<S: AsRef<str>>(targets: &[S]) -> Self {
+        let mut points = Vec::with_capacity(targets.len() * POINTS_PER_TARGET);
+        for (index, target) in targets.iter().enumerate() {
+            // Four points out of every digest, as ketama does
+            for replica in 0..POINTS_PER_TARGET / 4 {
+                let digest = sha256(&[format!("{}-{}", target.as_ref(), replica).as_bytes()]);
+                for point in digest.chunks_exact(8) {
+                    let point = u64::from_le_bytes(point.try_into().expect("8 bytes"));
+                    points.push((point, index));
+                }
+            }
+        }
+        points.sort_unstable();
+        HashRing { points }
+    }
+
+    /// Index of the target serving the key hashed to `key_hash`
+    pub fn target_for(&self, key_hash: u64) -> usize {
+        let next = self.points.partition_point(|&(point, _)| point < key_hash);
+        // Past the last point, the ring wraps around to the first
+        let (_, index) = self.points.get(next).unwrap_or(&self.points[0]);
+        *index
+    }
+}
+
+/// Forward the messages of one replayed connection to its connections to
+/// each target: every event to the target owning its key, and the end of an
+/// iteration to all of them
+pub async fn route_by_key(
+    mut rx: mpsc::Receiver<ConnectionMessage>,
+    queues: Vec<mpsc::Sender<ConnectionMessage>>,
+    ring: Arc<HashRing>,
+) {
+    while let Some(message) = rx.recv().await {
+        match &message {
+            ConnectionMessage::Event(event, _) => {
+                let tx = &queues[ring.target_for(event.key_hash)];
+                if tx.send(message).await.is_err() {
+                    break; // Connection task stopped
+                }
+            }
+            ConnectionMessage::EndOfIteration => {
+                for tx in &queues {
+                    if tx.send(ConnectionMessage::EndOfIteration).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
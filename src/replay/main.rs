@@ -1,41 +1,476 @@
 //! Replay command: stream profile events to memcache server with connection topology preservation
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use hdrhistogram::Histogram;
 use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
-use crate::profile::{CommandType, Event};
+use crate::logging::REPORT_TARGET;
+use crate::profile::{CommandType, TimeRange};
+use crate::replay::distributed::{collect_snapshots, gather_workers, start_workers};
+use crate::replay::ramp::ramp_rate;
+use crate::replay::synthetic::{synthetic_task, SyntheticSpec};
+use crate::replay::validation::describe as describe_response;
 use crate::replay::{
-    reader_task, spawn_connection_task, spawn_stats_aggregator, stats::StatsSnapshot, LoopMode,
-    ProfileReader, ProtocolMode,
+    bind_control_socket,
+    compare::mirror,
+    connection_task::{ConnectionConfig, ConnectionMessage},
+    establish_connections,
+    ketama::route_by_key,
+    reader_task, serve_control, spawn_connection_task, spawn_stats_aggregator,
+    stats::{AggregatedStats, StatsSnapshot},
+    ComparisonReport, ConnectLimits, FidelityReport, HashRing, IterationMode, LoopMode, Pacing,
+    ProfileStreamer, ProtocolMode, Ramp, RateLimiter, RateSearch, ReconnectPolicy, ReplayControl,
+    ResponseValidation, Routes, TrafficSummary, TtlPolicy, ValueFill, WorkerLink,
 };
 
+/// Time between the last worker reporting ready and the start of a
+/// distributed replay, for the start message to reach every worker
+const WORKER_START_DELAY: Duration = Duration::from_secs(1);
+
+/// Source of the events sent during a replay
+pub enum Workload {
+    /// Recorded profile, replayed according to the loop mode and pacing,
+    /// optionally capped in throughput and stopped after a duration
+    Profile {
+        path: String,
+        /// Part of the capture to replay
+        range: TimeRange,
+        loop_mode: LoopMode,
+        pacing: Pacing,
+        rate: Option<u64>,
+        duration: Option<Duration>,
+    },
+    /// Events drawn from a TrafficGenerator, optionally rate limited and time bounded
+    Synthetic {
+        spec: SyntheticSpec,
+        rate: Option<u64>,
+        duration: Option<Duration>,
+        seed: Option<u64>,
+    },
+}
+
+/// Replay settings shared by every workload
+#[derive(Debug, Clone, Default)]
+pub struct ReplayOptions {
+    /// Force one protocol for every connection; otherwise each connection
+    /// uses its recorded protocol
+    pub protocol_mode: Option<ProtocolMode>,
+    /// Idle time after which a connection sends a keepalive
+    pub keepalive: Option<Duration>,
+    /// Drop mutating commands instead of sending them
+    pub read_only: bool,
+    /// SET the key of every GET miss, with a representative value size
+    pub populate_misses: bool,
+    /// Leave operations completed this early out of the statistics
+    pub warmup: Option<Duration>,
+    /// Parallelism and deadline of connection establishment
+    pub connect: ConnectLimits,
+    /// Keep connections open across loop iterations, or reconnect for each
+    pub iteration_mode: IterationMode,
+    /// Accept live reconfiguration commands on this Unix socket
+    pub control_socket: Option<String>,
+    /// Export final statistics to this JSON file
+    pub stats_json: Option<String>,
+    /// Scaling and capping of recorded TTLs
+    pub ttl: TtlPolicy,
+    /// Commands each connection sends ahead of their responses
+    pub pipeline_depth: usize,
+    /// Time latency from the scheduled send rather than the actual one
+    pub open_loop: bool,
+    /// Send the GETs queued on a connection within this window as one request
+    pub coalesce_gets: Option<Duration>,
+    /// Compare every response with the recorded one, reporting this many of
+    /// the first mismatches
+    pub validate: Option<usize>,
+    /// Send every command to both targets (A and B) and compare their
+    /// latencies, instead of sharing the keys between the targets
+    pub mirror: bool,
+    /// Grow the rate from zero at the start, and bring it back to zero
+    /// before the end; both ramps are left out of the statistics
+    pub ramp: Ramp,
+    /// Reconnect a connection after an error, or fail it
+    pub reconnect: ReconnectPolicy,
+    /// Count a command as timed out once its response is this late
+    pub request_timeout: Option<Duration>,
+    /// Prepended to every key, isolating the keys of this run
+    pub key_prefix: Option<String>,
+    /// Payload of the values written
+    pub value_fill: ValueFill,
+    /// Write values derived from their key, and check every retrieval hit
+    /// against the value its key should hold
+    pub verify: bool,
+}
+
+/// Statistics of a finished replay, and what the profile led to expect
+struct ReplayOutcome {
+    stats: AggregatedStats,
+    expected: Option<(TrafficSummary, Option<u64>)>,
+    /// Server latencies recorded in the profile, in µs
+    recorded_latency: Option<Histogram<u64>>,
+    /// Link to the coordinator, once every snapshot was forwarded over it
+    worker: Option<WorkerLink>,
+}
+
+/// Replay `workload` against `target`
 pub async fn run(
-    input: &str,
+    workload: Workload,
+    target: &str,
+    options: ReplayOptions,
+    should_exit: Arc<AtomicBool>,
+) -> Result<()> {
+    let ReplayOutcome {
+        stats,
+        expected,
+        recorded_latency,
+        ..
+    } = execute(workload, target, &options, should_exit, None).await?;
+
+    // Final summary
+    print_final_summary(&stats);
+    print_iteration_summaries(&stats);
+    print_target_summaries(&stats);
+    print_namespace_summaries(&stats);
+    if let Some(comparison) = stats.comparison() {
+        print_comparison_report(&comparison);
+    }
+    if let Some((summary, iterations)) = expected {
+        let mut report = FidelityReport::compare(&summary, stats.traffic(), iterations);
+        if let (Some(recorded), Some(replayed)) = (&recorded_latency, stats.latency_histogram()) {
+            report = report.with_latencies(recorded, &replayed);
+        }
+        print_fidelity_report(&report);
+    }
+    if let Some(validation) = stats.validation() {
+        print_validation_report(validation);
+    }
+
+    // Export JSON if requested
+    if let Some(json_path) = &options.stats_json {
+        let json = stats.to_json()?;
+        std::fs::write(json_path, json)?;
+        tracing::info!("Statistics exported to {}", json_path);
+    }
+
+    Ok(())
+}
+
+/// Offer `spec` at increasing rates, one step of `search.step_duration` each,
+/// and report the highest rate `target` sustains within the p99 SLA
+pub async fn find_max(
+    spec: SyntheticSpec,
+    seed: Option<u64>,
     target: &str,
-    loop_mode: &str,
-    protocol_mode: ProtocolMode,
+    search: RateSearch,
+    options: ReplayOptions,
     should_exit: Arc<AtomicBool>,
-    stats_json: Option<&str>,
 ) -> Result<()> {
     tracing::info!(
-        "Starting replay: input={}, target={}, mode={}, protocol={}",
-        input,
-        target,
-        loop_mode,
-        protocol_mode
+        target: REPORT_TARGET,
+        "Searching for the maximum rate with p99 <= {}μs ({:?} per step)",
+        search.sla_p99.as_micros(),
+        search.step_duration
     );
 
+    let mut steps = Vec::new();
+    while let Some(rate) = search.next_rate(&steps) {
+        let workload = Workload::Synthetic {
+            spec: spec.clone(),
+            rate: Some(rate),
+            duration: Some(search.step_duration),
+            seed,
+        };
+        let ReplayOutcome { stats, .. } =
+            execute(workload, target, &options, Arc::clone(&should_exit), None).await?;
+        // An interrupted step says nothing about the rate
+        if should_exit.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let step = search.evaluate(
+            rate,
+            stats.throughput(),
+            stats.latency_percentile(99.0).unwrap_or(0),
+            stats.total_errors(),
+        );
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Rate {}: achieved {:.0} ops/sec, p99 {}μs, {} errors - {}",
+            step.offered,
+            step.achieved,
+            step.p99_us,
+            step.errors,
+            if step.passed { "ok" } else { "violated" }
+        );
+        steps.push(step);
+    }
+
+    let best = RateSearch::best(&steps);
+    tracing::info!(target: REPORT_TARGET, "=== Rate Search Complete ===");
+    match best {
+        Some(best) => tracing::info!(
+            target: REPORT_TARGET,
+            "Maximum sustainable throughput: {:.0} ops/sec (offered {}, p99 {}μs)",
+            best.achieved,
+            best.offered,
+            best.p99_us
+        ),
+        None => tracing::warn!(
+            target: REPORT_TARGET,
+            "No rate met the SLA, down to {} ops/sec",
+            steps.last().map_or(search.start_rate, |step| step.offered)
+        ),
+    }
+
+    if let Some(json_path) = &options.stats_json {
+        let json = serde_json::to_string_pretty(&serde_json::json!({
+            "sla_p99_us": search.sla_p99.as_micros() as u64,
+            "max": best,
+            "steps": steps,
+        }))?;
+        std::fs::write(json_path, json)?;
+        tracing::info!("Search results exported to {}", json_path);
+    }
+
+    Ok(())
+}
+
+/// Replay this worker's share of `workload` against `target`, started by and
+/// reporting to the coordinator at `coordinator`
+pub async fn run_worker(
+    workload: Workload,
+    target: &str,
+    options: ReplayOptions,
+    coordinator: &str,
+    should_exit: Arc<AtomicBool>,
+) -> Result<()> {
+    let link = WorkerLink::join(coordinator).await?;
+    tracing::info!(
+        target: REPORT_TARGET,
+        "Joined coordinator {} as worker {} of {}",
+        coordinator,
+        link.partition.index + 1,
+        link.partition.workers
+    );
+    let ReplayOutcome { stats, worker, .. } =
+        execute(workload, target, &options, should_exit, Some(link)).await?;
+
+    // The coordinator reports on every worker; this is the local share
+    print_final_summary(&stats);
+    worker
+        .expect("a worker replay keeps its link")
+        .finish(stats.target_names().to_vec())
+        .await
+}
+
+/// Wait for `workers` workers on `listen`, start them together, and report
+/// on their combined replay
+pub async fn coordinate(
+    listen: &str,
+    workers: usize,
+    stats_json: Option<String>,
+    should_exit: Arc<AtomicBool>,
+) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    tracing::info!(
+        target: REPORT_TARGET,
+        "Waiting for {} workers on {}",
+        workers,
+        listener.local_addr()?
+    );
+    let sessions = tokio::select! {
+        sessions = async {
+            let mut sessions = gather_workers(&listener, workers).await?;
+            tracing::info!("Every worker joined, waiting for their connections");
+            start_workers(&mut sessions, WORKER_START_DELAY).await?;
+            anyhow::Ok(sessions)
+        } => sessions?,
+        _ = async {
+            while !should_exit.load(Ordering::Relaxed) {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        } => bail!("interrupted before every worker started"),
+    };
+    tracing::info!(target: REPORT_TARGET, "Starting {} workers", workers);
+
+    let (stats_tx, stats_rx) = mpsc::channel::<StatsSnapshot>(1000);
+    let (live_stats, _) = tokio::sync::watch::channel(Default::default());
+    let stats_handle = spawn_stats_aggregator(stats_rx, live_stats).await;
+    let collectors: Vec<_> = sessions
+        .into_iter()
+        .map(|session| tokio::spawn(collect_snapshots(session, stats_tx.clone())))
+        .collect();
+    drop(stats_tx);
+
+    let mut targets = Vec::new();
+    for collector in collectors {
+        targets = collector.await??;
+    }
+    let mut stats = stats_handle.await?;
+    stats.set_target_names(targets);
+
+    print_final_summary(&stats);
+    print_iteration_summaries(&stats);
+    print_target_summaries(&stats);
+    print_namespace_summaries(&stats);
+    if let Some(comparison) = stats.comparison() {
+        print_comparison_report(&comparison);
+    }
+    if let Some(validation) = stats.validation() {
+        print_validation_report(validation);
+    }
+
+    if let Some(json_path) = &stats_json {
+        std::fs::write(json_path, stats.to_json()?)?;
+        tracing::info!("Statistics exported to {}", json_path);
+    }
+
+    Ok(())
+}
+
+async fn execute(
+    workload: Workload,
+    target: &str,
+    options: &ReplayOptions,
+    should_exit: Arc<AtomicBool>,
+    mut worker: Option<WorkerLink>,
+) -> Result<ReplayOutcome> {
+    // Several comma-separated targets share the keys by consistent hashing
+    let targets: Vec<&str> = target
+        .split(',')
+        .map(str::trim)
+        .filter(|target| !target.is_empty())
+        .collect();
+    if targets.is_empty() {
+        bail!("no target to replay against");
+    }
+    if options.mirror && targets.len() != 2 {
+        bail!("an A/B replay takes one target on each side");
+    }
+    let protocol_desc = options
+        .protocol_mode
+        .map_or("recorded".to_string(), |mode| mode.to_string());
+    if let Workload::Profile {
+        loop_mode: LoopMode::Times(_) | LoopMode::Infinite,
+        ..
+    } = &workload
+    {
+        tracing::info!(
+            "Connections between iterations: {:?}",
+            options.iteration_mode
+        );
+    }
+    let (rate, duration) = match &workload {
+        Workload::Profile { rate, duration, .. } | Workload::Synthetic { rate, duration, .. } => {
+            (*rate, *duration)
+        }
+    };
+    if !options.ramp.is_none() {
+        let ramps = options.ramp.up.unwrap_or_default() + options.ramp.down.unwrap_or_default();
+        if rate.is_none() {
+            bail!("a ramp changes the rate: set --rate");
+        }
+        match duration {
+            None if options.ramp.down.is_some() => {
+                bail!("--ramp-down needs the end of the replay: set --duration")
+            }
+            Some(duration) if ramps > duration => {
+                bail!("the ramps last longer than --duration")
+            }
+            _ => {}
+        }
+    }
+    if options.read_only {
+        tracing::info!("Read-only replay: mutating commands are dropped");
+    }
+    if options.verify {
+        tracing::info!("Verifying the data of every hit");
+    }
+    if let Some(prefix) = &options.key_prefix {
+        tracing::info!("Keys prefixed with '{}'", prefix);
+    }
+    if options.open_loop {
+        match &workload {
+            Workload::Profile {
+                pacing: Pacing::None,
+                ..
+            } => bail!("--open-loop needs a schedule: replay with --pacing recorded"),
+            Workload::Synthetic { rate: None, .. } => {
+                bail!("--open-loop needs a schedule: set --rate for a synthetic replay")
+            }
+            _ => tracing::info!(
+                "Open-loop replay: up to {} commands in flight per connection",
+                options.pipeline_depth
+            ),
+        }
+    } else if options.pipeline_depth > 1 {
+        tracing::info!(
+            "Pipelining up to {} commands per connection",
+            options.pipeline_depth
+        );
+    }
+    if let Some(window) = options.coalesce_gets {
+        tracing::info!(
+            "Coalescing GETs queued within {:?} into multi-key requests",
+            window
+        );
+    }
+    match &workload {
+        Workload::Profile {
+            path,
+            loop_mode,
+            range,
+            pacing,
+            rate,
+            duration,
+        } => tracing::info!(
+            "Starting replay: input={}, range={:?}, target={}, mode={:?}, pacing={:?}, rate={:?}, duration={:?}, protocol={}",
+            path,
+            range,
+            target,
+            loop_mode,
+            pacing,
+            rate,
+            duration,
+            protocol_desc
+        ),
+        Workload::Synthetic { rate, duration, .. } => tracing::info!(
+            "Starting synthetic replay: target={}, rate={:?}, duration={:?}, protocol={}",
+            target,
+            rate,
+            duration,
+            protocol_desc
+        ),
+    }
+
     // Create cancellation token for coordinated shutdown
     let cancel_token = CancellationToken::new();
 
+    // Live controls, served on the control socket when one is configured
+    let control = Arc::new(ReplayControl::new(match &workload {
+        Workload::Synthetic { rate, .. } | Workload::Profile { rate, .. } => *rate,
+    }));
+    let control_handle = match &options.control_socket {
+        Some(path) => {
+            let listener = bind_control_socket(path)?;
+            let (control, path, cancel) =
+                (Arc::clone(&control), path.clone(), cancel_token.clone());
+            Some(tokio::spawn(async move {
+                serve_control(control, listener, &path, cancel).await
+            }))
+        }
+        None => None,
+    };
+
     // Spawn signal handler to trigger cancellation on Ctrl+C
     let cancel_token_for_signal = cancel_token.clone();
     tokio::spawn(async move {
-        loop {
+        // Stops with the replay, which cancels the token once it is done
+        while !cancel_token_for_signal.is_cancelled() {
             if should_exit.load(Ordering::Relaxed) {
                 tracing::info!("External exit signal received, cancelling all tasks");
                 cancel_token_for_signal.cancel();
@@ -45,73 +480,347 @@ pub async fn run(
         }
     });
 
-    // Parse loop mode
-    let loop_mode = match loop_mode {
-        "once" => LoopMode::Once,
-        "infinite" => LoopMode::Infinite,
-        s if s.starts_with("times:") => {
-            let count = s
-                .strip_prefix("times:")
-                .and_then(|s| s.parse::<usize>().ok())
-                .ok_or_else(|| anyhow::anyhow!("Invalid loop mode: {}", s))?;
-            LoopMode::Times(count)
-        }
-        _ => LoopMode::Once,
+    // Phase 1: Identify unique connections (from the profile, or the synthetic spec).
+    // One pass of the profile is also summarized for the fidelity report.
+    let mut expected = None;
+    let mut recorded_latency: Option<Histogram<u64>> = None;
+    let mut recorded_protocols = HashMap::new();
+    // Total bytes and count of the SET values
+    let mut set_value_sizes = (0u64, 0u64);
+    let unique_connections: Vec<u16> = match &workload {
+        Workload::Profile {
+            path,
+            range,
+            loop_mode,
+            ..
+        } => {
+            let mut streamer = ProfileStreamer::with_range(path, *range)?;
+            let mut unique_connections = HashSet::<u16>::new();
+            let mut summary = TrafficSummary::new();
+            while let Some(event) = streamer.next_event()? {
+                unique_connections.insert(event.conn_id);
+                summary.record(&event);
+                if let Some(latency) = event.latency {
+                    recorded_latency
+                        .get_or_insert_with(|| {
+                            Histogram::new(3).expect("Failed to create histogram")
+                        })
+                        .record(latency as u64)
+                        .ok();
+                }
+                if let Some(size) = event.value_size {
+                    set_value_sizes.0 += size.get() as u64;
+                    set_value_sizes.1 += 1;
+                }
+            }
+            let iterations = match loop_mode {
+                LoopMode::Once => Some(1),
+                LoopMode::Times(n) => Some(*n as u64),
+                LoopMode::Infinite => None,
+            };
+            expected = Some((summary, iterations));
+            let metadata = streamer.metadata();
+            recorded_protocols = metadata.connection_protocols.clone();
+            if let Some(rate) = metadata.connection_sampling {
+                tracing::info!(
+                    "Profile holds {} of the recorded connections: the replay offers that share of the load",
+                    rate
+                );
+            }
+            if metadata.plaintext_keys {
+                tracing::warn!("Profile holds plaintext keys: replaying the recorded keys");
+            }
+            unique_connections.into_iter().collect()
+        }
+        Workload::Synthetic { spec, .. } => {
+            set_value_sizes = (
+                spec.value_sizes.iter().map(|&size| size as u64).sum(),
+                spec.value_sizes.len() as u64,
+            );
+            (0..spec.connections).collect()
+        }
+    };
+    tracing::info!("Found {} unique connections", unique_connections.len());
+    // Every connection, sorted, when this worker replays a share of them
+    let mut all_connections = Vec::new();
+    let unique_connections = match &worker {
+        Some(link) => {
+            all_connections = unique_connections.clone();
+            all_connections.sort_unstable();
+            let share = link.partition.select(unique_connections);
+            tracing::info!("Replaying {} of them on this worker", share.len());
+            share
+        }
+        None => unique_connections,
     };
 
-    // Phase 1: Read profile metadata and identify unique connections
-    let reader = ProfileReader::new(input)?;
-    let mut unique_connections = HashSet::<u16>::new();
-    for event in reader.events() {
-        unique_connections.insert(event.conn_id);
+    // Backfilled values use the mean recorded SET value size
+    let backfill_value_size = options.populate_misses.then(|| {
+        let (total, count) = set_value_sizes;
+        (total / count.max(1)).max(1) as u32
+    });
+    if let Some(size) = backfill_value_size {
+        tracing::info!("Backfilling GET misses with {} byte values", size);
     }
-    let unique_connections: Vec<u16> = unique_connections.into_iter().collect();
-    tracing::info!("Found {} unique connections", unique_connections.len());
 
-    // Phase 1.5: Create stats aggregator
-    let (stats_tx, stats_rx) = mpsc::channel::<StatsSnapshot>(1000);
-    let stats_handle = spawn_stats_aggregator(stats_rx, cancel_token.clone()).await;
+    // Phase 1.5: Establish every connection up front, concurrently
+    let connections: Vec<(u16, ProtocolMode)> = unique_connections
+        .iter()
+        .map(|&conn_id| {
+            let protocol_mode =
+                options
+                    .protocol_mode
+                    .unwrap_or_else(|| match recorded_protocols.get(&conn_id) {
+                        Some(&protocol) => ProtocolMode::from_recorded(protocol),
+                        None => ProtocolMode::Meta,
+                    });
+            (conn_id, protocol_mode)
+        })
+        .collect();
+    let mut clients = Vec::with_capacity(targets.len());
+    for target in &targets {
+        clients.push(establish_connections(target, &connections, options.connect).await?);
+    }
+    if options.mirror {
+        tracing::info!(
+            "A/B replay: mirroring every command to {} (A) and {} (B)",
+            targets[0],
+            targets[1]
+        );
+    }
+    let ring = (targets.len() > 1 && !options.mirror).then(|| {
+        tracing::info!(
+            "Distributing keys over {} targets by consistent hashing",
+            targets.len()
+        );
+        Arc::new(HashRing::new(&targets))
+    });
+
+    // Workers start together, once all of them are connected
+    if let Some(link) = &mut worker {
+        link.wait_for_start().await?;
+    }
+
+    // The warmup starts once every connection is up
+    // The ramp-up counts as warmup
+    let started = Instant::now();
+    let warmup = options.warmup.max(options.ramp.up);
+    let warmup_until = warmup.map(|warmup| {
+        tracing::info!("Excluding the first {:?} from statistics (warmup)", warmup);
+        started + warmup
+    });
+    let ramp_down_from = options.ramp.down_from(started, duration);
+    if let Some(down) = options.ramp.down {
+        tracing::info!("Excluding the last {:?} from statistics (ramp-down)", down);
+    }
+
+    // Create stats aggregator
+    let (stats_tx, mut stats_rx) = mpsc::channel::<StatsSnapshot>(1000);
+    // A worker forwards every snapshot to its coordinator too
+    let forwarder = worker.map(|link| {
+        let (local_tx, local_rx) = mpsc::channel::<StatsSnapshot>(1000);
+        let snapshots = std::mem::replace(&mut stats_rx, local_rx);
+        (
+            link.partition,
+            tokio::spawn(forward_snapshots(snapshots, local_tx, link)),
+        )
+    });
+    let stats_handle = spawn_stats_aggregator(stats_rx, control.live_stats()).await;
+
+    // A profile replay with a rate shares one token bucket over its connections,
+    // following rate changes made on the control socket. The rate is over
+    // every worker of a distributed replay.
+    let partition = forwarder.as_ref().map(|(partition, _)| *partition);
+    let share = move |rate: u64| partition.map_or(rate, |partition| partition.share(rate));
+    let rate_limiter = match &workload {
+        Workload::Profile {
+            rate: Some(rate), ..
+        } => {
+            let limiter = Arc::new(RateLimiter::new(share(*rate)));
+            let (follower, mut rate_changes, cancel) = (
+                Arc::clone(&limiter),
+                control.handle().rate,
+                cancel_token.clone(),
+            );
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        _ = cancel.cancelled() => break,
+                        changed = rate_changes.changed() => {
+                            if changed.is_err() {
+                                break;
+                            }
+                            if let Some(rate) = *rate_changes.borrow_and_update() {
+                                follower.set_rate(share(rate));
+                            }
+                        }
+                    }
+                }
+            });
+            Some(limiter)
+        }
+        _ => None,
+    };
 
     // Phase 2: Create SPSC queues for each connection
-    let mut connection_queues: HashMap<u16, mpsc::Sender<Event>> = HashMap::new();
+    let mut connection_queues: HashMap<u16, mpsc::Sender<ConnectionMessage>> = HashMap::new();
     let mut connection_tasks = Vec::new();
 
-    for &conn_id in &unique_connections {
+    for &(conn_id, protocol_mode) in &connections {
         let (tx, rx) = mpsc::channel(1000); // Buffer size: 1000 events
         connection_queues.insert(conn_id, tx);
 
-        let target = target.to_string();
-        let stats_tx_clone = stats_tx.clone();
-
-        let task_handle = spawn_connection_task(
-            &target,
-            rx,
-            stats_tx_clone,
-            conn_id,
-            protocol_mode,
-            cancel_token.clone(),
-        )
-        .await?;
-        connection_tasks.push(task_handle);
+        // With several targets, the connection is replayed over one
+        // connection to each, fed by the target owning each key
+        let receivers = if targets.len() == 1 {
+            vec![rx]
+        } else {
+            let (senders, receivers) = (0..targets.len()).map(|_| mpsc::channel(1000)).unzip();
+            match &ring {
+                Some(ring) => tokio::spawn(route_by_key(rx, senders, Arc::clone(ring))),
+                // Mirrored commands take their token once, for both targets
+                None => tokio::spawn(mirror(rx, senders, rate_limiter.clone())),
+            };
+            receivers
+        };
+        for (target_index, rx) in receivers.into_iter().enumerate() {
+            let client = clients[target_index]
+                .remove(&conn_id)
+                .expect("every connection is established");
+            let stats_tx_clone = stats_tx.clone();
+            let task_handle = spawn_connection_task(
+                client,
+                targets[target_index],
+                rx,
+                stats_tx_clone,
+                conn_id,
+                ConnectionConfig {
+                    keepalive: options.keepalive,
+                    read_only: options.read_only,
+                    backfill_value_size,
+                    iteration_mode: options.iteration_mode,
+                    warmup_until,
+                    ramp_down_from,
+                    ttl: options.ttl,
+                    rate_limiter: if options.mirror {
+                        None
+                    } else {
+                        rate_limiter.clone()
+                    },
+                    pipeline_depth: options.pipeline_depth,
+                    open_loop: options.open_loop,
+                    coalesce_gets: options.coalesce_gets,
+                    validate: options.validate,
+                    target_index,
+                    log_latencies: options.mirror,
+                    reconnect: options.reconnect,
+                    request_timeout: options.request_timeout,
+                    key_prefix: options.key_prefix.clone().unwrap_or_default(),
+                    value_fill: options.value_fill,
+                    verify: options.verify,
+                    ..ConnectionConfig::new(protocol_mode)
+                },
+                cancel_token.clone(),
+            )
+            .await?;
+            connection_tasks.push(task_handle);
+        }
     }
 
     // Drop our copy of stats_tx so aggregator can finish when all connections close
     drop(stats_tx);
 
+    // A profile replay stops after its duration as on Ctrl+C: commands in
+    // flight complete and are counted
+    if let Workload::Profile {
+        duration: Some(duration),
+        ..
+    } = &workload
+    {
+        let (duration, cancel) = (*duration, cancel_token.clone());
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = cancel.cancelled() => {}
+                _ = tokio::time::sleep(duration) => {
+                    tracing::info!("Replay duration of {:?} reached, stopping", duration);
+                    cancel.cancel();
+                }
+            }
+        });
+    }
+
+    let ramp = (!options.ramp.is_none()).then(|| {
+        tracing::info!(
+            "Ramping the rate up over {:?} and down over {:?}",
+            options.ramp.up.unwrap_or_default(),
+            options.ramp.down.unwrap_or_default()
+        );
+        let cancel = cancel_token.child_token();
+        let handle = tokio::spawn(ramp_rate(
+            options.ramp,
+            started,
+            duration,
+            control.rate(),
+            cancel.clone(),
+        ));
+        (cancel, handle)
+    });
+
     // Phase 3: Spawn reader task
     let reader_task_handle = {
-        let input_clone = input.to_string();
         let cancel_token_clone = cancel_token.clone();
+        let control_events = control.handle();
 
-        tokio::spawn(async move {
-            reader_task(
-                &input_clone,
-                connection_queues,
+        match workload {
+            Workload::Profile {
+                path,
+                range,
                 loop_mode,
-                cancel_token_clone,
-            )
-            .await
-        })
+                pacing,
+                ..
+            } => tokio::spawn(async move {
+                let routes = Routes {
+                    queues: connection_queues,
+                    partition: partition.map(|partition| (partition, all_connections)),
+                };
+                reader_task(
+                    &path,
+                    range,
+                    routes,
+                    loop_mode,
+                    pacing,
+                    control_events,
+                    cancel_token_clone,
+                )
+                .await
+            }),
+            Workload::Synthetic {
+                spec,
+                rate,
+                duration,
+                seed,
+            } => {
+                // Always seed, so any run can be reproduced from its logged seed
+                let seed = seed.unwrap_or_else(rand::random);
+                tracing::info!("Synthetic seed: {}", seed);
+                let generator = spec
+                    .generator()
+                    .with_inter_arrival(spec.inter_arrival(rate))
+                    .with_seed(seed);
+
+                tokio::spawn(async move {
+                    synthetic_task(
+                        generator,
+                        connection_queues,
+                        rate,
+                        duration,
+                        control_events,
+                        cancel_token_clone,
+                    )
+                    .await
+                })
+            }
+        }
     };
 
     // Phase 4: Wait for reader task to complete (signals that all events processed)
@@ -126,38 +835,135 @@ pub async fn run(
     tracing::info!("All connection tasks completed");
 
     // Phase 6: Cancel stats aggregator and get final results
-    let final_stats = stats_handle.await?;
+    let worker = match forwarder {
+        Some((_, forwarder)) => Some(forwarder.await??),
+        None => None,
+    };
+    let mut stats = stats_handle.await?;
+    stats.set_target_names(targets.iter().map(|target| target.to_string()).collect());
+    let requested_rate = match ramp {
+        Some((cancel, handle)) => {
+            cancel.cancel();
+            handle.await?
+        }
+        None => *control.handle().rate.borrow(),
+    };
+    stats.set_requested_rate(requested_rate.map(share));
+    // Everything else is done: this stops the control socket and the signal watcher
+    cancel_token.cancel();
+    if let Some(handle) = control_handle {
+        handle.await?;
+    }
 
-    // Final summary
-    print_final_summary(&final_stats);
+    Ok(ReplayOutcome {
+        stats,
+        expected,
+        recorded_latency,
+        worker,
+    })
+}
 
-    // Export JSON if requested
-    if let Some(json_path) = stats_json {
-        let json = final_stats.to_json()?;
-        std::fs::write(json_path, json)?;
-        tracing::info!("Statistics exported to {}", json_path);
+/// Send every snapshot of a worker's connections to its coordinator, and on
+/// to the local aggregator. Returns the link once the connections are done.
+async fn forward_snapshots(
+    mut snapshots: mpsc::Receiver<StatsSnapshot>,
+    local: mpsc::Sender<StatsSnapshot>,
+    mut link: WorkerLink,
+) -> Result<WorkerLink> {
+    while let Some(snapshot) = snapshots.recv().await {
+        link.send_snapshot(&snapshot).await?;
+        // The local aggregator stops early on cancellation
+        local.send(snapshot).await.ok();
     }
-
-    Ok(())
+    Ok(link)
 }
 
-fn print_final_summary(stats: &crate::replay::stats::AggregatedStats) {
-    tracing::info!("=== Replay Complete ===");
-    tracing::info!("Elapsed: {:.2}s", stats.elapsed_secs());
-    tracing::info!("Total Operations: {}", stats.total_operations());
-    tracing::info!("Throughput: {:.2} ops/sec", stats.throughput());
+fn print_final_summary(stats: &AggregatedStats) {
+    tracing::info!(target: REPORT_TARGET, "=== Replay Complete ===");
+    tracing::info!(target: REPORT_TARGET, "Elapsed: {:.2}s", stats.elapsed_secs());
+    tracing::info!(target: REPORT_TARGET, "Total Operations: {}", stats.total_operations());
+    match stats.requested_rate() {
+        Some(rate) => tracing::info!(
+            target: REPORT_TARGET,
+            "Throughput: {:.2} ops/sec (requested {} ops/sec, {:.1}% achieved)",
+            stats.throughput(),
+            rate,
+            stats.throughput() / rate as f64 * 100.0
+        ),
+        None => {
+            tracing::info!(target: REPORT_TARGET, "Throughput: {:.2} ops/sec", stats.throughput())
+        }
+    }
+    if stats.warmup_operations() > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Warmup: {} operations excluded",
+            stats.warmup_operations()
+        );
+    }
+    if stats.ramp_down_operations() > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Ramp-down: {} operations excluded",
+            stats.ramp_down_operations()
+        );
+    }
+    if stats.get_misses() > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Get misses: {} ({} backfilled)",
+            stats.get_misses(),
+            stats.backfills()
+        );
+    }
+    if stats.verified_hits() > 0 || stats.integrity_errors() > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Integrity: {} hits verified, {} with a wrong key or value",
+            stats.verified_hits(),
+            stats.integrity_errors()
+        );
+    }
+    if stats.timeouts() > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Timeouts: {} ({:.2}% of requests)",
+            stats.timeouts(),
+            stats.timeout_rate()
+        );
+    }
+    if stats.reconnects() > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Reconnects: {} after connection errors ({} errors)",
+            stats.reconnects(),
+            stats.total_errors()
+        );
+    }
+    if stats.quiet_operations() > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Quiet: {} commands sent without waiting for a response",
+            stats.quiet_operations()
+        );
+    }
+    if stats.multi_gets() > 0 {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Coalesced GETs: {} in {} multi-key requests ({:.1} keys per request)",
+            stats.coalesced_gets(),
+            stats.multi_gets(),
+            stats.coalesced_gets() as f64 / stats.multi_gets() as f64
+        );
+    }
 
-    for cmd_type in [
-        CommandType::Get,
-        CommandType::Set,
-        CommandType::Delete,
-        CommandType::Noop,
-    ] {
+    for cmd_type in CommandType::ALL {
         if let Some(p50) = stats.percentile(cmd_type, 50.0) {
             let p95 = stats.percentile(cmd_type, 95.0).unwrap_or(0);
             let p99 = stats.percentile(cmd_type, 99.0).unwrap_or(0);
 
             tracing::info!(
+                target: REPORT_TARGET,
                 "{:?} latency (μs) - p50: {}, p95: {}, p99: {}",
                 cmd_type,
                 p50,
@@ -167,3 +973,199 @@ fn print_final_summary(stats: &crate::replay::stats::AggregatedStats) {
         }
     }
 }
+
+fn print_target_summaries(stats: &AggregatedStats) {
+    let targets = stats.targets();
+    if targets.is_empty() {
+        return;
+    }
+
+    tracing::info!(target: REPORT_TARGET, "=== Targets ===");
+    for (target, target_stats) in targets {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "{}: {} ops, p50: {}μs, p99: {}μs, {} misses, {} errors",
+            target,
+            target_stats.operations(),
+            target_stats.percentile(50.0).unwrap_or(0),
+            target_stats.percentile(99.0).unwrap_or(0),
+            target_stats.get_misses(),
+            target_stats.errors()
+        );
+    }
+}
+
+fn print_namespace_summaries(stats: &AggregatedStats) {
+    let namespaces = stats.namespaces();
+    if namespaces.is_empty() {
+        return;
+    }
+
+    tracing::info!(target: REPORT_TARGET, "=== Key Namespaces ===");
+    for (namespace, namespace_stats) in namespaces.iter().take(10) {
+        let hit_rate = match namespace_stats.hit_rate() {
+            Some(rate) => format!("{:.1}% hits", rate * 100.0),
+            None => "no retrievals".to_string(),
+        };
+        tracing::info!(
+            target: REPORT_TARGET,
+            "{:08x}: {} ops, p50: {}μs, p99: {}μs, {}",
+            namespace,
+            namespace_stats.operations(),
+            namespace_stats.percentile(50.0).unwrap_or(0),
+            namespace_stats.percentile(99.0).unwrap_or(0),
+            hit_rate
+        );
+    }
+}
+
+fn print_comparison_report(report: &ComparisonReport) {
+    tracing::info!(target: REPORT_TARGET, "=== A/B Comparison ===");
+    tracing::info!(
+        target: REPORT_TARGET,
+        "A: {}, B: {}",
+        report.target_a,
+        report.target_b
+    );
+    for delta in &report.percentiles {
+        let label = if delta.percentile == 100.0 {
+            "max".to_string()
+        } else {
+            format!("p{}", delta.percentile)
+        };
+        tracing::info!(
+            target: REPORT_TARGET,
+            "{}: A {}μs, B {}μs ({:+}μs, {:+.1}%)",
+            label,
+            delta.a_micros,
+            delta.b_micros,
+            delta.delta_micros,
+            delta.delta_percent()
+        );
+    }
+    tracing::info!(
+        target: REPORT_TARGET,
+        "Per command: B faster {} ({:.1}%), slower {} ({:.1}%), tied {} ({:.1}%)",
+        report.b_faster,
+        report.share(report.b_faster),
+        report.b_slower,
+        report.share(report.b_slower),
+        report.ties,
+        report.share(report.ties)
+    );
+}
+
+fn print_iteration_summaries(stats: &AggregatedStats) {
+    let iterations = stats.iterations();
+    if iterations.len() < 2 {
+        return;
+    }
+
+    tracing::info!(target: REPORT_TARGET, "=== Iterations ===");
+    for (index, iteration) in iterations.iter().enumerate() {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Iteration {}: {} ops in {:.2}s ({:.0} ops/sec), p50: {}μs, p99: {}μs, {} misses, {} errors",
+            index + 1,
+            iteration.operations(),
+            iteration.elapsed_secs(),
+            iteration.throughput(),
+            iteration.percentile(50.0).unwrap_or(0),
+            iteration.percentile(99.0).unwrap_or(0),
+            iteration.get_misses(),
+            iteration.errors()
+        );
+    }
+}
+
+fn print_fidelity_report(report: &FidelityReport) {
+    tracing::info!(target: REPORT_TARGET, "=== Replay Fidelity ===");
+    match (report.expected_total, report.completion()) {
+        (Some(expected), Some(completion)) => tracing::info!(
+            target: REPORT_TARGET,
+            "Events sent: {} of {} ({:.2}%)",
+            report.sent_total,
+            expected,
+            completion * 100.0
+        ),
+        _ => tracing::info!(target: REPORT_TARGET, "Events sent: {}", report.sent_total),
+    }
+
+    for (cmd_type, expected, sent) in &report.command_mix {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "{:?}: profile {:.2}%, sent {:.2}% ({:+.2})",
+            cmd_type,
+            expected * 100.0,
+            sent * 100.0,
+            (sent - expected) * 100.0
+        );
+    }
+    tracing::info!(
+        target: REPORT_TARGET,
+        "Key size divergence (total variation): {:.4}",
+        report.key_size_divergence
+    );
+
+    for (percentile, recorded, replayed) in &report.latencies {
+        tracing::info!(
+            target: REPORT_TARGET,
+            "Latency p{}: recorded {}µs, replayed {}µs",
+            percentile,
+            recorded,
+            replayed
+        );
+    }
+
+    if !report.connection_shortfalls.is_empty() {
+        tracing::warn!(
+            target: REPORT_TARGET,
+            "{} connection(s) did not send their expected events",
+            report.connection_shortfalls.len()
+        );
+        for (conn_id, expected, sent) in report.connection_shortfalls.iter().take(10) {
+            tracing::warn!(
+                target: REPORT_TARGET,
+                "  conn {}: expected {}, sent {}",
+                conn_id,
+                expected,
+                sent
+            );
+        }
+    }
+}
+
+fn print_validation_report(validation: &ResponseValidation) {
+    tracing::info!(target: REPORT_TARGET, "=== Response Validation ===");
+    if validation.compared == 0 {
+        tracing::warn!(
+            target: REPORT_TARGET,
+            "No response compared: the profile holds no recorded responses"
+        );
+        return;
+    }
+    tracing::info!(
+        target: REPORT_TARGET,
+        "Compared: {} responses, {:.2}% as recorded",
+        validation.compared,
+        validation.match_percent()
+    );
+    tracing::info!(
+        target: REPORT_TARGET,
+        "Status diverged: {}, value size diverged: {}",
+        validation.status_mismatches,
+        validation.value_size_mismatches
+    );
+    for mismatch in &validation.mismatches {
+        tracing::warn!(
+            target: REPORT_TARGET,
+            "  conn {} at {}µs: {:?} {:016x}: recorded {}, replayed {}",
+            mismatch.conn_id,
+            mismatch.timestamp,
+            mismatch.cmd_type,
+            mismatch.key_hash,
+            describe_response(Some(mismatch.recorded)),
+            describe_response(mismatch.replayed)
+        );
+    }
+}
@@ -1,26 +1,73 @@
 //! Replay infrastructure and protocol handling
 
+use crate::profile::Protocol;
 use std::fmt;
 use std::str::FromStr;
 
 pub mod analyzer;
 pub mod client;
+pub mod compare;
 pub mod connection_task;
+pub mod connections;
+pub mod control;
+pub mod distributed;
+pub mod fidelity;
+pub mod generator;
+pub mod hot_keys;
+pub mod ketama;
 pub mod main;
+pub mod mrc;
+pub mod ramp;
+pub mod rate_limit;
 pub mod reader;
 pub mod reader_task;
+pub mod reuse;
+pub mod scheduler;
+pub mod search;
 pub mod stats;
 mod stats_aggregator;
 pub mod streamer;
+pub mod synthetic;
+pub mod throughput;
+pub mod transport;
+pub mod validation;
+pub mod value;
+pub mod zipf;
 
 pub use analyzer::{AnalysisResult, DistributionAnalyzer};
 pub use client::ReplayClient;
-pub use connection_task::spawn_connection_task;
-pub use main::run as run_replay;
+pub use compare::{ComparisonReport, LatencyPairing};
+pub use connection_task::{
+    establish_connections, spawn_connection_task, ConnectLimits, ConnectionMessage,
+    ReconnectPolicy, RequestTimeout, TtlPolicy,
+};
+pub use connections::{connection_report, ConnectionStats};
+pub use control::{bind_control_socket, serve_control, ControlHandle, LiveStats, ReplayControl};
+pub use distributed::{Partition, WorkerLink};
+pub use fidelity::{FidelityReport, TrafficSummary};
+pub use generator::{InterArrival, KeyPopularity, TrafficGenerator};
+pub use hot_keys::{HeavyHitters, HotKey};
+pub use ketama::HashRing;
+pub use main::{
+    coordinate as run_coordinator, find_max as run_find_max, run as run_replay, run_worker,
+    ReplayOptions, Workload,
+};
+pub use mrc::{miss_ratio_curve, MissRatioCurve};
+pub use ramp::Ramp;
+pub use rate_limit::RateLimiter;
 pub use reader::ProfileReader;
-pub use reader_task::{reader_task, LoopMode};
+pub use reader_task::{reader_task, IterationMode, LoopMode, Pacing, Routes};
+pub use reuse::{analyze_reuse, ReuseAnalysis, WorkingSet};
+pub use scheduler::TimingWheel;
+pub use search::{RateSearch, RateStep};
 pub use stats_aggregator::spawn_stats_aggregator;
 pub use streamer::ProfileStreamer;
+pub use synthetic::SyntheticSpec;
+pub use throughput::{ThroughputBucket, ThroughputSeries};
+pub use transport::Transport;
+pub use validation::{Mismatch, ResponseValidation};
+pub use value::{ValueFill, ValueGenerator};
+pub use zipf::{ZipfFit, ZipfSampler};
 
 /// Protocol mode for command generation during replay
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -29,6 +76,19 @@ pub enum ProtocolMode {
     Ascii,
     /// Meta protocol (mg, ms, md, mn)
     Meta,
+    /// Binary protocol (request frames with opcodes, extras and opaque)
+    Binary,
+}
+
+impl ProtocolMode {
+    /// Mode used to replay a connection recorded with `protocol`
+    pub fn from_recorded(protocol: Protocol) -> Self {
+        match protocol {
+            Protocol::Ascii => ProtocolMode::Ascii,
+            Protocol::Meta => ProtocolMode::Meta,
+            Protocol::Binary => ProtocolMode::Binary,
+        }
+    }
 }
 
 impl FromStr for ProtocolMode {
@@ -38,8 +98,9 @@ impl FromStr for ProtocolMode {
         match s.to_lowercase().as_str() {
             "ascii" => Ok(ProtocolMode::Ascii),
             "meta" => Ok(ProtocolMode::Meta),
+            "binary" => Ok(ProtocolMode::Binary),
             _ => Err(format!(
-                "Invalid protocol mode: '{}'. Use 'ascii' or 'meta'",
+                "Invalid protocol mode: '{}'. Use 'ascii', 'meta' or 'binary'",
                 s
             )),
         }
@@ -51,6 +112,7 @@ impl fmt::Display for ProtocolMode {
         match self {
             ProtocolMode::Ascii => write!(f, "ascii"),
             ProtocolMode::Meta => write!(f, "meta"),
+            ProtocolMode::Binary => write!(f, "binary"),
         }
     }
 }
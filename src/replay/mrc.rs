@@ -0,0 +1,102 @@
+//! Miss-ratio curve of a profile, by sampled LRU simulation
+//!
+//! An LRU cache of `c` bytes hits an access when the keys accessed since the
+//! previous access to its key, itself included, take at most `c` bytes: the
+//! byte reuse distance of every access gives the hit ratio of every cache
+//! size at once. SHARDS (Waldspurger et al., FAST '15) computes the distances
+//! over the accesses to a spatially sampled share `R` of the keys only, and
+//! scales them by `1 / R`; the curve stays accurate to a few tenths of a
+//! percent at rates down to 0.1% on traces of millions of keys.
+//!
+//! A key takes its key size plus its latest value size, so the curve assumes
+//! no per-item overhead: compare with the memory used by items in the cache
+//! (`bytes` of memcached stats), not with its `-m` limit.
+
+use super::reuse::Fenwick;
+use crate::profile::Event;
+use siphasher::sip::SipHasher13;
+use std::collections::HashMap;
+use std::hash::Hasher;
+
+/// Cache sizes the curve is printed at
+pub const CURVE_POINTS: usize = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissRatioCurve {
+    /// Share of the keys simulated
+    pub sampling_rate: f64,
+    /// Accesses to the simulated keys
+    pub accesses: u64,
+    /// Cache bytes each reuse of a simulated key needs to hit, scaled to all
+    /// the keys, sorted
+    pub distances: Vec<u64>,
+    /// Bytes of all the keys, estimated from the simulated ones: no larger
+    /// cache hits more
+    pub footprint: u64,
+}
+
+impl MissRatioCurve {
+    /// Predicted share of the accesses an LRU cache of `bytes` hits
+    pub fn hit_ratio(&self, bytes: u64) -> f64 {
+        let hits = self
+            .distances
+            .partition_point(|&distance| distance <= bytes);
+        hits as f64 / self.accesses.max(1) as f64
+    }
+
+    /// Hit ratio at `count` cache sizes doubling up to the footprint, rounded
+    /// to powers of two
+    pub fn points(&self, count: usize) -> Vec<(u64, f64)> {
+        let largest = self.footprint.max(1).next_power_of_two();
+        (0..count as u32)
+            .rev()
+            .filter_map(|shift| largest.checked_shr(shift).filter(|&bytes| bytes > 0))
+            .map(|bytes| (bytes, self.hit_ratio(bytes)))
+            .collect()
+    }
+}
+
+/// Miss-ratio curve of `events`, simulating the keys a `sampling_rate` share
+/// of key hashes selects
+pub fn miss_ratio_curve(events: &[Event], sampling_rate: f64) -> MissRatioCurve {
+    let threshold = (sampling_rate * u64::MAX as f64) as u64;
+    let sampled = |key_hash: u64| {
+        let mut hasher = SipHasher13::new_with_keys(0, 0);
+        hasher.write_u64(key_hash);
+        hasher.finish() < threshold || threshold == u64::MAX
+    };
+    let events: Vec<&Event> = events.iter().filter(|e| sampled(e.key_hash)).collect();
+
+    // Position of the latest access to each key, and the bytes it takes
+    let mut latest = Fenwick::new(events.len());
+    let mut keys: HashMap<u64, (usize, u64)> = HashMap::new();
+    let mut distances = Vec::new();
+    for (i, event) in events.iter().enumerate() {
+        let value_size = event
+            .value_size
+            .or(event.response.and_then(|r| r.value_size))
+            .map(|size| size.get() as u64);
+        let previous = keys.get(&event.key_hash).copied();
+        let size = event.key_size as u64
+            + value_size.unwrap_or_else(|| {
+                previous.map_or(0, |(_, size)| size.saturating_sub(event.key_size as u64))
+            });
+        if let Some((position, previous_size)) = previous {
+            // The other keys accessed since, and this one at its new size
+            let between = latest.prefix(i) - latest.prefix(position + 1);
+            distances.push(((between + size) as f64 / sampling_rate) as u64);
+            latest.add(position, -(previous_size as i64));
+        }
+        latest.add(i, size as i64);
+        keys.insert(event.key_hash, (i, size));
+    }
+    distances.sort_unstable();
+
+    let footprint: u64 = keys.values().map(|&(_, size)| size).sum();
+    MissRatioCurve {
+        sampling_rate,
+        accesses: events.len() as u64,
+        distances,
+        footprint: (footprint as f64 / sampling_rate) as u64,
+    }
+}
@@ -0,0 +1,79 @@
+//! Gradual ramp of the requested rate at the start and end of a replay
+
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+/// Time between two rate changes of a ramp
+const RAMP_STEP: Duration = Duration::from_millis(100);
+
+/// How long the rate takes to grow from zero to the requested rate, and to
+/// fall back to zero before the end of the replay
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ramp {
+    pub up: Option<Duration>,
+    pub down: Option<Duration>,
+}
+
+impl Ramp {
+    pub fn is_none(&self) -> bool {
+        self.up.is_none() && self.down.is_none()
+    }
+
+    /// Share of the requested rate to offer `elapsed` into a replay lasting
+    /// `total`
+    pub fn fraction(&self, elapsed: Duration, total: Option<Duration>) -> f64 {
+        let mut fraction: f64 = 1.0;
+        if let Some(up) = self.up.filter(|up| !up.is_zero()) {
+            fraction = fraction.min(elapsed.as_secs_f64() / up.as_secs_f64());
+        }
+        if let (Some(down), Some(total)) = (self.down.filter(|down| !down.is_zero()), total) {
+            let left = total.saturating_sub(elapsed);
+            fraction = fraction.min(left.as_secs_f64() / down.as_secs_f64());
+        }
+        fraction.clamp(0.0, 1.0)
+    }
+
+    /// When the ramp-down of a replay started at `start` and lasting `total` begins
+    pub fn down_from(&self, start: Instant, total: Option<Duration>) -> Option<Instant> {
+        Some(start + total?.saturating_sub(self.down?))
+    }
+}
+
+/// Lower `rate` along `ramp` for a replay started at `start`, until
+/// `cancel`. Between the ramps the rate follows the changes made on the control
+/// socket, and the ramp-down starts from the rate then requested. Returns the
+/// full rate, which the rate is left at.
+pub async fn ramp_rate(
+    ramp: Ramp,
+    start: Instant,
+    total: Option<Duration>,
+    rate: watch::Sender<Option<u64>>,
+    cancel: CancellationToken,
+) -> Option<u64> {
+    let mut full = *rate.borrow();
+    let mut ramping = false;
+    let mut steps = tokio::time::interval(RAMP_STEP);
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = steps.tick() => {}
+        }
+        let fraction = ramp.fraction(start.elapsed(), total);
+        if fraction < 1.0 {
+            let Some(full) = full else {
+                break;
+            };
+            rate.send_replace(Some(((full as f64 * fraction) as u64).max(1)));
+            ramping = true;
+        } else if ramping {
+            // Ramp-up complete
+            rate.send_replace(full);
+            ramping = false;
+        } else {
+            full = *rate.borrow();
+        }
+    }
+    rate.send_replace(full);
+    full
+}
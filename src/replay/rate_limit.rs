@@ -0,0 +1,77 @@
+//! Throughput cap shared by the connection tasks of a replay
+
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Token bucket capping the commands sent per second over every connection.
+/// It holds up to 10ms worth of tokens, so short bursts pass unthrottled.
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    rate: f64,
+    /// Tokens available; negative when commands reserved future tokens
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn capacity(&self) -> f64 {
+        (self.rate / 100.0).max(1.0)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity());
+        self.refilled = now;
+    }
+}
+
+impl RateLimiter {
+    /// Limiter allowing `rate` commands per second, starting with a full bucket
+    pub fn new(rate: u64) -> Self {
+        let rate = rate.max(1) as f64;
+        RateLimiter {
+            bucket: Mutex::new(Bucket {
+                rate,
+                tokens: (rate / 100.0).max(1.0),
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Change the rate, keeping the tokens already earned
+    pub fn set_rate(&self, rate: u64) {
+        let mut bucket = self.bucket.lock().expect("rate limiter lock poisoned");
+        bucket.refill(Instant::now());
+        bucket.rate = rate.max(1) as f64;
+        bucket.tokens = bucket.tokens.min(bucket.capacity());
+    }
+
+    /// Commands per second currently allowed
+    pub fn rate(&self) -> u64 {
+        self.bucket.lock().expect("rate limiter lock poisoned").rate as u64
+    }
+
+    /// Take a token, waiting until it is earned. Tokens go to waiters in the
+    /// order they asked for them.
+    pub async fn acquire(&self) {
+        self.acquire_many(1).await
+    }
+
+    /// Take `tokens` tokens at once, for a request carrying several commands
+    pub async fn acquire_many(&self, tokens: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().expect("rate limiter lock poisoned");
+            bucket.refill(Instant::now());
+            bucket.tokens -= tokens as f64;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / bucket.rate)
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
@@ -1,6 +1,12 @@
-use crate::profile::{Event, ProfileMetadata};
+use crate::profile::checkpoint::{read_record, recover, Record};
+use crate::profile::checksum::{RecordChecksums, Trailer};
+use crate::profile::compression::decode_region;
+use crate::profile::mmap::MappedFile;
+use crate::profile::packed::EventDecoder;
+use crate::profile::{Event, KeySketch, ProfileMetadata, TimeRange};
+use crate::replay::ProfileStreamer;
 use anyhow::Result;
-use std::fs;
+use std::collections::HashSet;
 
 pub struct ProfileReader {
     metadata: ProfileMetadata,
@@ -9,63 +15,85 @@ pub struct ProfileReader {
 
 impl ProfileReader {
     pub fn new(path: &str) -> Result<Self> {
-        let data = fs::read(path)?;
+        // Events decode straight from the mapped file
+        let data = MappedFile::open(path)?;
 
         if data.len() < 4 {
             return Err(anyhow::anyhow!("file too small"));
         }
 
-        // Last 4 bytes are the end marker
-        let end_marker_pos = data.len() - 4;
-        let end_marker = u32::from_le_bytes([
-            data[end_marker_pos],
-            data[end_marker_pos + 1],
-            data[end_marker_pos + 2],
-            data[end_marker_pos + 3],
-        ]);
+        // Trailer after the metadata: its length, checksum and the end marker
+        let tail = &data[data.len().saturating_sub(Trailer::MAX_LEN)..];
+        let Some(trailer) = Trailer::parse(tail) else {
+            // Crashed recording: fall back to its last checkpoint
+            let (metadata, region) = recover(&data)?;
+            tracing::warn!(
+                "{} has no trailer, reading {} events up to its last checkpoint",
+                path,
+                metadata.total_events
+            );
+            return Self::decode(metadata, &region);
+        };
+
+        // Metadata format: [metadata_data][trailer]
+        let metadata_start = trailer.metadata_start(data.len() as u64)? as usize;
+        let metadata_bytes = &data[metadata_start..metadata_start + trailer.metadata_len as usize];
+        trailer.verify(metadata_bytes, metadata_start as u64)?;
+        let metadata = ProfileMetadata::decode(metadata_bytes)?;
+        let events_end = metadata.events_end(metadata_start as u64) as usize;
+        let region = decode_region(&data[..events_end], metadata.compression)?;
+        Self::decode(metadata, &region)
+    }
 
-        if end_marker != 0xDEADBEEF {
-            return Err(anyhow::anyhow!("invalid file format: missing end marker"));
+    /// Read the events of `range` only, seeking to it through the index; the
+    /// metadata describes those events
+    pub fn with_range(path: &str, range: TimeRange) -> Result<Self> {
+        if range.is_all() {
+            return Self::new(path);
         }
-
-        // Metadata format: [metadata_data][metadata_len:2 bytes][end_marker:4 bytes]
-        // So read metadata_len from before the end marker
-        if end_marker_pos < 2 {
-            return Err(anyhow::anyhow!("file too small for metadata"));
+        let mut streamer = ProfileStreamer::with_range(path, range)?;
+        let mut events = Vec::new();
+        while let Some(event) = streamer.next_event()? {
+            events.push(event);
         }
 
-        let metadata_len_pos = end_marker_pos - 2;
-        let metadata_len =
-            u16::from_le_bytes([data[metadata_len_pos], data[metadata_len_pos + 1]]) as usize;
-
-        if metadata_len_pos < metadata_len {
-            return Err(anyhow::anyhow!("metadata length exceeds file size"));
+        let mut metadata = streamer.metadata().clone();
+        metadata.total_events = events.len() as u64;
+        if let (Some(first), Some(last)) = (events.first(), events.last()) {
+            metadata.time_range = (first.timestamp, last.timestamp);
         }
+        metadata.unique_connections = events
+            .iter()
+            .map(|event| event.conn_id)
+            .collect::<HashSet<_>>()
+            .len() as u32;
+        metadata.command_distribution.clear();
+        let mut keys = KeySketch::new();
+        for event in &events {
+            *metadata
+                .command_distribution
+                .entry(event.cmd_type)
+                .or_insert(0) += 1;
+            keys.insert(event.key_hash);
+        }
+        metadata.key_sketch = Some(keys);
+        Ok(ProfileReader { metadata, events })
+    }
 
-        let metadata_start = metadata_len_pos - metadata_len;
-        let metadata_bytes = &data[metadata_start..metadata_len_pos];
-        let metadata: ProfileMetadata = bincode::deserialize(metadata_bytes)?;
-
-        // Read events from beginning up to metadata
+    /// Decode the events of an (inflated) event region, skipping checkpoints
+    fn decode(metadata: ProfileMetadata, region: &[u8]) -> Result<Self> {
+        let mut decoder = EventDecoder::new(metadata.version);
+        let mut checksums = RecordChecksums::new(metadata.compression);
         let mut events = Vec::new();
         let mut offset = 0;
-
-        while offset < metadata_start {
-            if offset + 2 > metadata_start {
-                break;
+        while let Some((record, next)) = read_record(region, offset) {
+            checksums.check(&record)?;
+            match record {
+                Record::Event(event_bytes) => events.push(decoder.decode(event_bytes)?),
+                Record::Checksum(_) => decoder.restart(),
+                Record::Checkpoint(_) => {}
             }
-
-            let len = u16::from_le_bytes([data[offset], data[offset + 1]]) as usize;
-            offset += 2;
-
-            if offset + len > metadata_start {
-                break;
-            }
-
-            let event_bytes = &data[offset..offset + len];
-            let event: Event = bincode::deserialize(event_bytes)?;
-            events.push(event);
-            offset += len;
+            offset = next;
         }
 
         Ok(ProfileReader { metadata, events })
@@ -1,24 +1,123 @@
-use crate::profile::Event;
+use super::connection_task::ConnectionMessage;
+use super::control::ControlHandle;
+use super::distributed::Partition;
+use super::scheduler::{TimingWheel, SCHEDULE_AHEAD, TICK};
+use super::streamer::ProfileStreamer;
+use crate::profile::{Event, TimeRange};
 use anyhow::Result;
 use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::time::Instant;
 
+/// Gaps shorter than this are sent right away rather than slept through
+const PACING_SLACK: Duration = Duration::from_micros(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LoopMode {
     Once,
     Times(usize),
     Infinite,
 }
 
+impl FromStr for LoopMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "once" => Ok(LoopMode::Once),
+            "infinite" => Ok(LoopMode::Infinite),
+            s if s.starts_with("times:") => s
+                .strip_prefix("times:")
+                .and_then(|n| n.parse::<usize>().ok())
+                .map(LoopMode::Times)
+                .ok_or_else(|| format!("Invalid loop mode: {}", s)),
+            _ => Err(format!(
+                "Invalid loop mode: '{}'. Use 'once', 'infinite' or 'times:N'",
+                s
+            )),
+        }
+    }
+}
+
+/// What connections do between loop iterations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IterationMode {
+    /// Keep connections open, so every iteration after the first runs warm
+    #[default]
+    Warm,
+    /// Reconnect at the start of every iteration
+    Reset,
+}
+
+impl FromStr for IterationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warm" => Ok(IterationMode::Warm),
+            "reset" => Ok(IterationMode::Reset),
+            _ => Err(format!(
+                "Invalid iteration mode: '{}'. Use 'warm' or 'reset'",
+                s
+            )),
+        }
+    }
+}
+
+/// When the events of a profile are sent
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Pacing {
+    /// As fast as the connections accept them
+    #[default]
+    None,
+    /// At their recorded offset from the first event, keeping bursts and idle
+    /// gaps; `speed` divides every offset (2.0 replays twice as fast)
+    Recorded { speed: f64 },
+}
+
+impl FromStr for Pacing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Pacing::None),
+            "recorded" => Ok(Pacing::Recorded { speed: 1.0 }),
+            _ => Err(format!("Invalid pacing: '{}'. Use 'none' or 'recorded'", s)),
+        }
+    }
+}
+
+/// Where the reader sends the events of each connection
+pub struct Routes {
+    pub queues: HashMap<u16, mpsc::Sender<ConnectionMessage>>,
+    /// In a distributed replay, the share of this worker and every connection
+    /// of the profile, sorted: the events of the others are other workers' to send
+    pub partition: Option<(Partition, Vec<u16>)>,
+}
+
+impl Routes {
+    /// Routes of a replay on a single host
+    pub fn local(queues: HashMap<u16, mpsc::Sender<ConnectionMessage>>) -> Self {
+        Routes {
+            queues,
+            partition: None,
+        }
+    }
+}
+
 /// Main reader task: streams events from profile, routes to connection queues, handles looping
 pub async fn reader_task(
     profile_path: &str,
-    connection_queues: HashMap<u16, mpsc::Sender<Event>>,
+    range: TimeRange,
+    routes: Routes,
     loop_mode: LoopMode,
+    pacing: Pacing,
+    mut control: ControlHandle,
     cancel_token: tokio_util::sync::CancellationToken,
 ) -> Result<()> {
-    use super::streamer::ProfileStreamer;
-
-    let mut streamer = ProfileStreamer::new(profile_path)?;
+    let mut streamer = ProfileStreamer::with_range(profile_path, range)?;
 
     let loop_count = match loop_mode {
         LoopMode::Once => 1,
@@ -27,7 +126,7 @@ pub async fn reader_task(
     };
 
     // Ensure queues are always closed on exit using a guard
-    struct QueueGuard(Option<HashMap<u16, mpsc::Sender<Event>>>);
+    struct QueueGuard(Option<HashMap<u16, mpsc::Sender<ConnectionMessage>>>);
     impl Drop for QueueGuard {
         fn drop(&mut self) {
             if let Some(queues) = self.0.take() {
@@ -36,7 +135,8 @@ pub async fn reader_task(
             }
         }
     }
-    let guard = QueueGuard(Some(connection_queues));
+    let Routes { queues, partition } = routes;
+    let guard = QueueGuard(Some(queues));
     let connection_queues = guard.0.as_ref().unwrap();
 
     for iteration in 0..loop_count {
@@ -47,50 +147,173 @@ pub async fn reader_task(
 
         tracing::debug!("Reader task iteration {}", iteration);
 
-        loop {
-            // Check cancellation before processing next event
-            if cancel_token.is_cancelled() {
-                tracing::info!("Reader task cancelled during event processing");
-                break;
+        let dispatch = Dispatch {
+            queues: connection_queues,
+            partition: &partition,
+            cancel_token: &cancel_token,
+        };
+        let finished = match pacing {
+            Pacing::None => send_unpaced(&mut streamer, &dispatch, &mut control).await?,
+            Pacing::Recorded { speed } => {
+                send_recorded(&mut streamer, &dispatch, &mut control, speed).await?
             }
+        };
+        if !finished {
+            continue;
+        }
 
-            // Read next event synchronously
-            match streamer.next_event()? {
-                Some(event) => {
-                    let conn_id = event.conn_id;
-
-                    if let Some(tx) = connection_queues.get(&conn_id) {
-                        // Send event to connection queue with cancellation awareness
-                        tokio::select! {
-                            _ = cancel_token.cancelled() => {
-                                tracing::info!("Reader task cancelled during send");
-                                break;
-                            }
-                            result = tx.send(event) => {
-                                if result.is_err() {
-                                    tracing::warn!("Connection {} task closed unexpectedly", conn_id);
-                                    break;
-                                }
-                            }
-                        }
-                    } else {
-                        tracing::warn!("Unknown connection ID: {}", conn_id);
-                    }
-                }
-                None => {
-                    // End of profile file
-                    if iteration < loop_count - 1 {
-                        tracing::debug!("End of profile, resetting for next iteration");
-                        streamer.reset()?;
-                    } else {
-                        tracing::info!("All replay iterations complete");
-                    }
-                    break;
+        // End of profile file
+        if iteration < loop_count - 1 {
+            tracing::debug!("End of profile, resetting for next iteration");
+            streamer.reset()?;
+            // Let connections close their iteration in order with its events
+            for tx in connection_queues.values() {
+                if tx.send(ConnectionMessage::EndOfIteration).await.is_err() {
+                    tracing::warn!("Connection task closed before iteration end");
                 }
             }
+        } else {
+            tracing::info!("All replay iterations complete");
         }
     }
 
     // Guard will automatically drop queues when function exits
     Ok(())
 }
+
+/// What the reader needs to hand an event to its connection
+struct Dispatch<'a> {
+    queues: &'a HashMap<u16, mpsc::Sender<ConnectionMessage>>,
+    partition: &'a Option<(Partition, Vec<u16>)>,
+    cancel_token: &'a tokio_util::sync::CancellationToken,
+}
+
+impl Dispatch<'_> {
+    /// Queue `event`, intended to be sent at `intended`; false if the replay
+    /// stops (cancelled, or its connection gone)
+    async fn send(&self, event: Event, intended: Instant) -> bool {
+        let conn_id = event.conn_id;
+        let Some(tx) = self.queues.get(&conn_id) else {
+            let foreign = self
+                .partition
+                .as_ref()
+                .and_then(|(partition, connections)| partition.owner(connections, conn_id))
+                .is_some();
+            // Another worker of a distributed replay sends it
+            if !foreign {
+                // Not a connection of any worker
+                tracing::warn!("Unknown connection ID: {}", conn_id);
+            }
+            return true;
+        };
+        // Send event to connection queue with cancellation awareness
+        tokio::select! {
+            _ = self.cancel_token.cancelled() => {
+                tracing::info!("Reader task cancelled during send");
+                false
+            }
+            result = tx.send(ConnectionMessage::Event(event, intended.into_std())) => {
+                if result.is_err() {
+                    tracing::warn!("Connection {} task closed unexpectedly", conn_id);
+                }
+                result.is_ok()
+            }
+        }
+    }
+}
+
+/// Send every event of the profile as fast as the connections take them;
+/// false if the replay stopped before the end
+async fn send_unpaced(
+    streamer: &mut ProfileStreamer,
+    dispatch: &Dispatch<'_>,
+    control: &mut ControlHandle,
+) -> Result<bool> {
+    loop {
+        if dispatch.cancel_token.is_cancelled()
+            || !control.wait_until_resumed(dispatch.cancel_token).await
+        {
+            tracing::info!("Reader task cancelled during event processing");
+            return Ok(false);
+        }
+        let Some(event) = streamer.next_event()? else {
+            return Ok(true);
+        };
+        if !dispatch.send(event, Instant::now()).await {
+            return Ok(false);
+        }
+    }
+}
+
+/// Send every event of the profile at its recorded offset from the first,
+/// divided by `speed`. Events are read up to a lookahead ahead of the clock
+/// into a timing wheel, and those due are sent together on each wakeup, so
+/// the pace does not depend on one timer per event. Time spent paused delays
+/// the rest of the timeline. False if the replay stopped before the end.
+async fn send_recorded(
+    streamer: &mut ProfileStreamer,
+    dispatch: &Dispatch<'_>,
+    control: &mut ControlHandle,
+    speed: f64,
+) -> Result<bool> {
+    let mut pending = streamer.next_event()?;
+    let Some(first) = pending.as_ref().map(|event| event.timestamp) else {
+        return Ok(true);
+    };
+    // Offset of an event from the start of the replay, in µs
+    let offset = |event: &Event| (event.timestamp.saturating_sub(first) as f64 / speed) as u64;
+    let tick_micros = TICK.as_micros() as u64;
+    let mut wheel = TimingWheel::new();
+    let mut expired = Vec::new();
+    let mut start = Instant::now();
+
+    loop {
+        let paused_at = Instant::now();
+        if dispatch.cancel_token.is_cancelled()
+            || !control.wait_until_resumed(dispatch.cancel_token).await
+        {
+            tracing::info!("Reader task cancelled during event processing");
+            return Ok(false);
+        }
+        let paused = paused_at.elapsed();
+        if paused > PACING_SLACK {
+            start += paused;
+        }
+
+        // Schedule what falls within the lookahead, then send what is due
+        let now = start.elapsed().as_micros() as u64;
+        let horizon = now + SCHEDULE_AHEAD.as_micros() as u64;
+        while let Some(event) = pending.take_if(|event| offset(event) <= horizon) {
+            wheel.insert(offset(&event) / tick_micros, event);
+            pending = streamer.next_event()?;
+        }
+        wheel.advance(now / tick_micros, &mut expired);
+        for event in expired.drain(..) {
+            let intended = start + Duration::from_micros(offset(&event));
+            if !dispatch.send(event, intended).await {
+                return Ok(false);
+            }
+        }
+        if wheel.is_empty() && pending.is_none() {
+            return Ok(true);
+        }
+
+        // A single timer per wakeup: the next deadline, never further than
+        // the lookahead. Pausing wakes the task early.
+        let next = wheel
+            .next_deadline()
+            .map(|deadline| deadline * tick_micros)
+            .or(pending.as_ref().map(offset))
+            .unwrap_or(now);
+        let wait = Duration::from_micros(next.saturating_sub(start.elapsed().as_micros() as u64));
+        let wake = Instant::now() + wait.clamp(TICK, SCHEDULE_AHEAD);
+        tokio::select! {
+            _ = dispatch.cancel_token.cancelled() => {
+                tracing::info!("Reader task cancelled while pacing");
+                return Ok(false);
+            }
+            _ = tokio::time::sleep_until(wake) => {}
+            _ = control.paused.changed() => {}
+        }
+    }
+}
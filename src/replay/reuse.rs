@@ -0,0 +1,159 @@
+//! Reuse distances and working sets of the keys of a profile
+//!
+//! The reuse (LRU stack) distance of an access is the number of distinct
+//! other keys accessed since the previous access to its key. An LRU cache of
+//! `n` keys hits exactly the accesses whose distance is below `n` (Mattson et
+//! al.), so the distribution of distances gives the hit ratio of every cache
+//! size at once. Distances are found in O(log n) per access with a Fenwick
+//! tree marking the latest access to each key.
+
+use crate::profile::Event;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Distinct keys of a window of the capture, and the bytes caching them takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct WorkingSet {
+    /// First and last timestamps of the window
+    pub start: u64,
+    pub end: u64,
+    pub keys: u64,
+    /// Key sizes plus the latest value size seen of each key
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReuseAnalysis {
+    /// Accesses to a key for the first time: misses whatever the cache size
+    pub cold_accesses: u64,
+    /// Reuses counted by the power-of-two bucket of their distance plus one,
+    /// which is the smallest LRU cache (in keys) of that bucket hitting them:
+    /// (bucket upper bound, count)
+    pub distances: Vec<(u64, u64)>,
+    pub working_sets: Vec<WorkingSet>,
+}
+
+impl ReuseAnalysis {
+    /// Accesses analyzed
+    pub fn accesses(&self) -> u64 {
+        self.cold_accesses + self.distances.iter().map(|(_, count)| count).sum::<u64>()
+    }
+
+    /// Share of the accesses an LRU cache of `keys` keys hits, counting each
+    /// distance bucket as hit once `keys` reaches its upper bound
+    pub fn lru_hit_ratio(&self, keys: u64) -> f64 {
+        let hits: u64 = self
+            .distances
+            .iter()
+            .filter(|(bucket, _)| *bucket <= keys)
+            .map(|(_, count)| count)
+            .sum();
+        hits as f64 / self.accesses().max(1) as f64
+    }
+}
+
+/// Reuse distances of every access of `events`, and the working set of each
+/// of `windows` equal windows of the capture
+pub fn analyze_reuse(events: &[Event], windows: usize) -> ReuseAnalysis {
+    let mut latest = Fenwick::new(events.len());
+    let mut last_access: HashMap<u64, usize> = HashMap::new();
+    let mut buckets: HashMap<u64, u64> = HashMap::new();
+    let mut cold_accesses = 0;
+    for (i, event) in events.iter().enumerate() {
+        match last_access.insert(event.key_hash, i) {
+            Some(previous) => {
+                // Keys whose latest access is between the two
+                let distance = latest.prefix(i) - latest.prefix(previous + 1);
+                *buckets
+                    .entry((distance + 1).next_power_of_two())
+                    .or_insert(0) += 1;
+                latest.add(previous, -1);
+            }
+            None => cold_accesses += 1,
+        }
+        latest.add(i, 1);
+    }
+    let mut distances: Vec<(u64, u64)> = buckets.into_iter().collect();
+    distances.sort_unstable();
+
+    ReuseAnalysis {
+        cold_accesses,
+        distances,
+        working_sets: working_sets(events, windows),
+    }
+}
+
+fn working_sets(events: &[Event], windows: usize) -> Vec<WorkingSet> {
+    let (Some(first), Some(last)) = (
+        events.iter().map(|e| e.timestamp).min(),
+        events.iter().map(|e| e.timestamp).max(),
+    ) else {
+        return Vec::new();
+    };
+    let windows = windows.max(1) as u64;
+    let width = ((last - first) / windows).max(1);
+    // Key size and latest value size of each key, per window
+    let mut sets: Vec<HashMap<u64, (u32, u32)>> = vec![HashMap::new(); windows as usize];
+    for event in events {
+        let window = ((event.timestamp - first) / width).min(windows - 1) as usize;
+        let value_size = event
+            .value_size
+            .or(event.response.and_then(|r| r.value_size))
+            .map(|size| size.get());
+        let entry = sets[window]
+            .entry(event.key_hash)
+            .or_insert((event.key_size, 0));
+        entry.0 = event.key_size;
+        if let Some(size) = value_size {
+            entry.1 = size;
+        }
+    }
+    sets.iter()
+        .enumerate()
+        .map(|(i, keys)| WorkingSet {
+            start: first + i as u64 * width,
+            end: if i as u64 == windows - 1 {
+                last
+            } else {
+                first + (i as u64 + 1) * width - 1
+            },
+            keys: keys.len() as u64,
+            bytes: keys
+                .values()
+                .map(|&(key, value)| key as u64 + value as u64)
+                .sum(),
+        })
+        .collect()
+}
+
+/// Weights of positions, summed over prefixes in O(log n)
+pub struct Fenwick {
+    tree: Vec<i64>,
+}
+
+impl Fenwick {
+    pub fn new(len: usize) -> Self {
+        Fenwick {
+            tree: vec![0; len + 1],
+        }
+    }
+
+    pub fn add(&mut self, position: usize, delta: i64) {
+        let mut i = position + 1;
+        while i < self.tree.len() {
+            self.tree[i] += delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Sum of the weights of the positions before `end`
+    pub fn prefix(&self, end: usize) -> u64 {
+        let mut sum = 0;
+        let mut i = end;
+        while i > 0 {
+            sum += self.tree[i];
+            i -= i & i.wrapping_neg();
+        }
+        sum as u64
+    }
+}
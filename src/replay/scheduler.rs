@@ -0,0 +1,193 @@
+//! Hierarchical timing wheel for dispatching events at their deadlines
+//!
+//! Deadlines are expressed in ticks. Level `l` has `SLOTS` slots, each
+//! spanning `SLOTS^l` ticks, so inserting and expiring are O(1) amortized
+//! and a single timer per tick replaces one timer per event.
+//!
+//! Two modes schedule through the wheel: synthetic replay with `--rate`,
+//! and profile replay with `--pacing recorded`. Unpaced profile replay and
+//! closed-loop synthetic replay send as soon as a connection accepts, and
+//! never touch it.
+
+use std::time::Duration;
+
+/// Resolution of the dispatch schedule
+pub const TICK: Duration = Duration::from_micros(100);
+
+/// How far ahead of the clock events are scheduled
+pub const SCHEDULE_AHEAD: Duration = Duration::from_millis(10);
+
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const SLOT_MASK: u64 = SLOTS as u64 - 1;
+const LEVELS: usize = 6;
+
+/// Ticks covered by the wheel; deadlines in a later span wait in the overflow
+const SPAN_BITS: u32 = SLOT_BITS * LEVELS as u32;
+
+pub struct TimingWheel<T> {
+    current: u64,
+    levels: Vec<Vec<Vec<(u64, T)>>>,
+    /// Bit `s` of `occupied[l]` is set when slot `s` of level `l` holds items
+    occupied: [u64; LEVELS],
+    /// Deadlines beyond the span of the wheel, moved in when it gets there
+    overflow: Vec<(u64, T)>,
+    len: usize,
+}
+
+impl<T> Default for TimingWheel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TimingWheel<T> {
+    pub fn new() -> Self {
+        TimingWheel {
+            current: 0,
+            levels: (0..LEVELS)
+                .map(|_| (0..SLOTS).map(|_| Vec::new()).collect())
+                .collect(),
+            occupied: [0; LEVELS],
+            overflow: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Tick the wheel has advanced to
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+
+    /// Schedule `item` at tick `deadline`; past deadlines expire on the next advance
+    pub fn insert(&mut self, deadline: u64, item: T) {
+        self.len += 1;
+        self.place(deadline, item);
+    }
+
+    /// Earliest pending deadline, if any
+    pub fn next_deadline(&self) -> Option<u64> {
+        // Items of a lower level always expire before those of a higher one,
+        // and the lowest occupied slot of a level holds its earliest items
+        let wheel = (0..LEVELS)
+            .find(|&level| self.occupied[level] != 0)
+            .map(|level| {
+                let slot = self.occupied[level].trailing_zeros() as usize;
+                self.levels[level][slot]
+                    .iter()
+                    .map(|(deadline, _)| (*deadline).max(self.current))
+                    .min()
+                    .expect("occupied slot is not empty")
+            });
+        wheel.or_else(|| self.overflow.iter().map(|(deadline, _)| *deadline).min())
+    }
+
+    /// Advance to tick `now`, appending every item due by then to `expired`
+    /// in deadline order
+    pub fn advance(&mut self, now: u64, expired: &mut Vec<T>) {
+        loop {
+            self.expire_current(expired);
+            if self.current >= now {
+                return;
+            }
+            match self.next_stop() {
+                Some(next) if next <= now => {
+                    self.current = next;
+                    self.cascade();
+                }
+                _ => {
+                    // Nothing is due or moves down before `now`
+                    self.current = now;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Next tick at which items move down a level or expire
+    fn next_stop(&self) -> Option<u64> {
+        for level in 0..LEVELS {
+            let shift = SLOT_BITS * level as u32;
+            let digit = (self.current >> shift) & SLOT_MASK;
+            let ahead = if digit == SLOT_MASK {
+                0
+            } else {
+                self.occupied[level] & (u64::MAX << (digit + 1))
+            };
+            if ahead != 0 {
+                let base = self.current & !((1 << (shift + SLOT_BITS)) - 1);
+                return Some(base + ((ahead.trailing_zeros() as u64) << shift));
+            }
+        }
+        // The wheel is empty: jump to the start of the earliest overflow span
+        self.overflow
+            .iter()
+            .map(|(deadline, _)| deadline & !((1 << SPAN_BITS) - 1))
+            .min()
+    }
+
+    fn expire_current(&mut self, expired: &mut Vec<T>) {
+        let slot = (self.current & SLOT_MASK) as usize;
+        if self.occupied[0] & (1 << slot) == 0 {
+            return;
+        }
+        let mut items = std::mem::take(&mut self.levels[0][slot]);
+        self.occupied[0] &= !(1 << slot);
+        self.len -= items.len();
+        // Only past deadlines placed on the current tick can be out of order
+        items.sort_by_key(|(deadline, _)| *deadline);
+        expired.extend(items.into_iter().map(|(_, item)| item));
+    }
+
+    /// Move down the items whose slot the current tick has just entered, at
+    /// every level it is aligned to, and the overflow when it enters a new span
+    fn cascade(&mut self) {
+        if self.current & ((1 << SPAN_BITS) - 1) == 0 && !self.overflow.is_empty() {
+            for (deadline, item) in std::mem::take(&mut self.overflow) {
+                self.place(deadline, item);
+            }
+        }
+        for level in (1..LEVELS).rev() {
+            let shift = SLOT_BITS * level as u32;
+            if self.current & ((1 << shift) - 1) != 0 {
+                continue;
+            }
+            let slot = ((self.current >> shift) & SLOT_MASK) as usize;
+            if self.occupied[level] & (1 << slot) == 0 {
+                continue;
+            }
+            let items = std::mem::take(&mut self.levels[level][slot]);
+            self.occupied[level] &= !(1 << slot);
+            for (deadline, item) in items {
+                self.place(deadline, item);
+            }
+        }
+    }
+
+    /// Store an item at the level of the highest digit its deadline differs
+    /// from the current tick in, so it never lands on a slot already passed
+    fn place(&mut self, deadline: u64, item: T) {
+        let differing = deadline.max(self.current) ^ self.current;
+        if differing >> SPAN_BITS != 0 {
+            self.overflow.push((deadline, item));
+            return;
+        }
+        let level = if differing == 0 {
+            0
+        } else {
+            ((63 - differing.leading_zeros()) / SLOT_BITS) as usize
+        };
+        let slot =
+            ((deadline.max(self.current) >> (SLOT_BITS * level as u32)) & SLOT_MASK) as usize;
+        self.levels[level][slot].push((deadline, item));
+        self.occupied[level] |= 1 << slot;
+    }
+}
@@ -0,0 +1,106 @@
+//! Search for the highest synthetic rate a target sustains within a latency SLA
+//!
+//! The rate doubles from a starting point until a step violates the SLA, then
+//! a binary search narrows the gap between the best passing and the lowest
+//! failing rate.
+
+use serde::Serialize;
+use std::time::Duration;
+
+/// Fraction of the offered rate a step must achieve to pass
+pub const ACHIEVED_TOLERANCE: f64 = 0.05;
+
+/// Steps run before the search gives up on converging
+pub const MAX_STEPS: usize = 20;
+
+/// Settings of a `--find-max` search
+#[derive(Debug, Clone, Copy)]
+pub struct RateSearch {
+    /// Highest acceptable p99 latency over all commands
+    pub sla_p99: Duration,
+    /// Rate of the first step, in ops/sec
+    pub start_rate: u64,
+    /// How long each rate is offered
+    pub step_duration: Duration,
+    /// Stop once the failing rate is within this fraction of the passing one
+    pub precision: f64,
+}
+
+/// Outcome of offering one rate
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RateStep {
+    pub offered: u64,
+    pub achieved: f64,
+    pub p99_us: u64,
+    pub errors: u64,
+    pub passed: bool,
+}
+
+impl RateSearch {
+    /// First rate offered when --rate is not given
+    pub const DEFAULT_START_RATE: u64 = 1000;
+
+    pub fn new(sla_p99: Duration, start_rate: u64, step_duration: Duration) -> Self {
+        RateSearch {
+            sla_p99,
+            start_rate,
+            step_duration,
+            precision: 0.05,
+        }
+    }
+
+    /// Judge a step: within the SLA, without errors, and keeping up with the offered rate
+    pub fn evaluate(&self, offered: u64, achieved: f64, p99_us: u64, errors: u64) -> RateStep {
+        let passed = p99_us as u128 <= self.sla_p99.as_micros()
+            && errors == 0
+            && achieved >= offered as f64 * (1.0 - ACHIEVED_TOLERANCE);
+        RateStep {
+            offered,
+            achieved,
+            p99_us,
+            errors,
+            passed,
+        }
+    }
+
+    /// Rate of the next step given the steps so far, or `None` once the search is done
+    pub fn next_rate(&self, steps: &[RateStep]) -> Option<u64> {
+        let Some(last) = steps.last() else {
+            return Some(self.start_rate.max(1));
+        };
+        if steps.len() >= MAX_STEPS {
+            return None;
+        }
+
+        let passing = steps.iter().filter(|s| s.passed).map(|s| s.offered).max();
+        let failing = steps
+            .iter()
+            .filter(|s| !s.passed && passing.is_none_or(|p| s.offered > p))
+            .map(|s| s.offered)
+            .min();
+
+        let next = match (passing, failing) {
+            // Ramp up until the target falls behind
+            (Some(_), None) => last.offered.checked_mul(2)?,
+            // Even the starting rate fails: back off
+            (None, Some(failing)) => failing / 2,
+            (Some(passing), Some(failing)) => {
+                if (failing - passing) as f64 <= passing as f64 * self.precision {
+                    return None;
+                }
+                passing + (failing - passing) / 2
+            }
+            (None, None) => unreachable!("at least one step was run"),
+        };
+        (next > 0 && !steps.iter().any(|s| s.offered == next)).then_some(next)
+    }
+
+    /// Highest passing step
+    pub fn best(steps: &[RateStep]) -> Option<RateStep> {
+        steps
+            .iter()
+            .filter(|s| s.passed)
+            .max_by_key(|s| s.offered)
+            .copied()
+    }
+}
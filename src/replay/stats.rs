@@ -1,26 +1,62 @@
-use crate::profile::CommandType;
+use super::compare::{ComparisonReport, LatencyPairing};
+use super::fidelity::TrafficSummary;
+use super::validation::ResponseValidation;
+use crate::profile::{CommandType, Event};
 use hdrhistogram::Histogram;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ErrorType {
     Timeout,
     ConnectionError,
     ProtocolError,
+    /// A retrieval hit on the wrong key, or with a value other than written
+    IntegrityError,
 }
 
 #[derive(Debug, Clone)]
 pub struct StatsSnapshot {
     pub connection_id: u16,
+    /// Index of the target the connection replays against
+    pub target: usize,
     pub histograms: HashMap<CommandType, Histogram<u64>>,
     pub success_counts: HashMap<CommandType, u64>,
     pub error_counts: HashMap<ErrorType, u64>,
+    pub traffic: TrafficSummary,
+    pub get_misses: u64,
+    pub backfills: u64,
+    /// Connections re-established after an error
+    pub reconnects: u64,
+    /// Retrieval hits found intact by `--verify`
+    pub verified_hits: u64,
+    /// Requests coalescing several GETs, and the GETs they carried
+    pub multi_gets: u64,
+    pub coalesced_gets: u64,
+    /// Quiet commands sent, not waited for (so without latency)
+    pub quiet_operations: u64,
+    /// Operations completed during the warmup, excluded from everything else
+    pub warmup_operations: u64,
+    /// Operations completed during the ramp-down, excluded likewise
+    pub ramp_down_operations: u64,
+    /// Completion times of the first and last measured operations
+    pub first_operation: Option<Instant>,
+    pub last_operation: Option<Instant>,
+    /// Last snapshot of the connection for the current loop iteration
+    pub end_of_iteration: bool,
+    /// Comparison of the responses with the recorded ones, when validating
+    pub validation: Option<ResponseValidation>,
+    /// Latency (µs) of every command completed, in order, when mirroring;
+    /// `None` during the warmup
+    pub latency_log: Vec<Option<u64>>,
+    /// Operations by key namespace, for keys with several segments
+    pub namespaces: HashMap<u32, NamespaceStats>,
 }
 
 pub struct ConnectionStats {
     pub connection_id: u16,
+    pub target: usize,
 
     // Per-operation histograms (microsecond precision)
     histograms: HashMap<CommandType, Histogram<u64>>,
@@ -30,20 +66,191 @@ pub struct ConnectionStats {
 
     // Error tracking
     error_counts: HashMap<ErrorType, u64>,
+
+    // Commands completed, for the fidelity report
+    traffic: TrafficSummary,
+
+    // GET misses, and the SETs issued to backfill them
+    get_misses: u64,
+    backfills: u64,
+
+    // Connections re-established after an error
+    reconnects: u64,
+
+    // Retrieval hits checked intact
+    verified_hits: u64,
+
+    // Multi-key requests sent, and the GETs coalesced into them
+    multi_gets: u64,
+    coalesced_gets: u64,
+
+    // Quiet commands sent without waiting for a response
+    quiet_operations: u64,
+
+    // Operations before this instant only count as warmup
+    warmup_until: Option<Instant>,
+    warmup_operations: u64,
+    // Operations from this instant on only count as ramp-down
+    ramp_down_from: Option<Instant>,
+    ramp_down_operations: u64,
+
+    // Measurement window of this interval
+    first_operation: Option<Instant>,
+    last_operation: Option<Instant>,
+
+    // Responses compared with the recorded ones
+    validation: Option<ResponseValidation>,
+
+    // Latency of each command, to pair with the mirrored connection's
+    latency_log: Option<Vec<Option<u64>>>,
+
+    // Operations by the first segment of their key
+    namespaces: HashMap<u32, NamespaceStats>,
 }
 
 impl ConnectionStats {
     pub fn new(connection_id: u16) -> Self {
         ConnectionStats {
             connection_id,
+            target: 0,
             histograms: HashMap::new(),
             success_counts: HashMap::new(),
             error_counts: HashMap::new(),
+            traffic: TrafficSummary::new(),
+            get_misses: 0,
+            backfills: 0,
+            reconnects: 0,
+            verified_hits: 0,
+            multi_gets: 0,
+            coalesced_gets: 0,
+            quiet_operations: 0,
+            warmup_until: None,
+            warmup_operations: 0,
+            ramp_down_from: None,
+            ramp_down_operations: 0,
+            first_operation: None,
+            last_operation: None,
+            validation: None,
+            latency_log: None,
+            namespaces: HashMap::new(),
+        }
+    }
+
+    /// Log the latency of every command, in order, to compare it with the
+    /// same command mirrored to another target
+    pub fn with_latency_log(mut self) -> Self {
+        self.latency_log = Some(Vec::new());
+        self
+    }
+
+    /// Attribute the operations to the target with this index, when replaying
+    /// against several
+    pub fn with_target(mut self, target: usize) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Exclude operations completed before `until` from latency, throughput and misses
+    pub fn with_warmup_until(mut self, until: Instant) -> Self {
+        self.warmup_until = Some(until);
+        self
+    }
+
+    /// Exclude operations completed from `from` on, like the warmup
+    pub fn with_ramp_down_from(mut self, from: Instant) -> Self {
+        self.ramp_down_from = Some(from);
+        self
+    }
+
+    /// Compare every response with the recorded one, keeping the first
+    /// `limit` mismatches
+    pub fn with_validation(mut self, limit: usize) -> Self {
+        self.validation = Some(ResponseValidation::new(limit));
+        self
+    }
+
+    /// Compare the target's `response` to `event` with the recorded one, when validating
+    pub fn record_response(&mut self, event: &Event, response: &[u8]) {
+        if let Some(validation) = &mut self.validation {
+            validation.check(event, response);
+        }
+    }
+
+    fn warming_up(&self, now: Instant) -> bool {
+        self.warmup_until.is_some_and(|until| now < until)
+    }
+
+    fn ramping_down(&self, now: Instant) -> bool {
+        self.ramp_down_from.is_some_and(|from| now >= from)
+    }
+
+    /// Count an operation completed at `now` if it falls outside the
+    /// measured period, returning whether it did
+    fn unmeasured(&mut self, now: Instant) -> bool {
+        if self.warming_up(now) {
+            self.warmup_operations += 1;
+        } else if self.ramping_down(now) {
+            self.ramp_down_operations += 1;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    pub fn record_miss(&mut self) {
+        let now = Instant::now();
+        if self.warming_up(now) || self.ramping_down(now) {
+            return;
         }
+        self.get_misses += 1;
+    }
+
+    pub fn record_backfill(&mut self) {
+        self.backfills += 1;
+    }
+
+    pub fn record_reconnect(&mut self) {
+        self.reconnects += 1;
+    }
+
+    pub fn record_verified(&mut self) {
+        self.verified_hits += 1;
+    }
+
+    /// Count a request carrying `keys` coalesced GETs
+    pub fn record_multi_get(&mut self, keys: usize) {
+        self.multi_gets += 1;
+        self.coalesced_gets += keys as u64;
+    }
+
+    /// Count a completed event towards the sent traffic summary
+    pub fn record_sent(&mut self, event: &Event) {
+        self.traffic.record(event);
+    }
+
+    /// Count a quiet command, sent without waiting for its response
+    pub fn record_quiet(&mut self) {
+        let now = Instant::now();
+        if self.unmeasured(now) {
+            return;
+        }
+        self.first_operation.get_or_insert(now);
+        self.last_operation = Some(now);
+        self.quiet_operations += 1;
     }
 
     pub fn record_success(&mut self, cmd_type: CommandType, latency: Duration) {
+        let now = Instant::now();
         let micros = latency.as_micros() as u64;
+        let unmeasured = self.unmeasured(now);
+        if let Some(log) = &mut self.latency_log {
+            log.push((!unmeasured).then_some(micros));
+        }
+        if unmeasured {
+            return;
+        }
+        self.first_operation.get_or_insert(now);
+        self.last_operation = Some(now);
 
         // Update histogram
         let histogram = self
@@ -56,6 +263,22 @@ impl ConnectionStats {
         *self.success_counts.entry(cmd_type).or_insert(0) += 1;
     }
 
+    /// Count a completed operation towards the namespace of its key, the
+    /// first of its segments when it has several
+    pub fn record_namespace(&mut self, event: &Event, latency: Duration, miss: bool) {
+        let Some([namespace, _, ..]) = event.key_segments.as_deref() else {
+            return;
+        };
+        let now = Instant::now();
+        if self.warming_up(now) || self.ramping_down(now) {
+            return;
+        }
+        self.namespaces
+            .entry(*namespace)
+            .or_default()
+            .record(event.cmd_type, latency, miss);
+    }
+
     pub fn record_error(&mut self, _cmd_type: CommandType, error_type: ErrorType) {
         *self.error_counts.entry(error_type).or_insert(0) += 1;
     }
@@ -72,9 +295,30 @@ impl ConnectionStats {
     pub fn snapshot(&mut self) -> StatsSnapshot {
         let snapshot = StatsSnapshot {
             connection_id: self.connection_id,
+            target: self.target,
             histograms: self.histograms.clone(),
             success_counts: self.success_counts.clone(),
             error_counts: self.error_counts.clone(),
+            traffic: std::mem::take(&mut self.traffic),
+            get_misses: std::mem::take(&mut self.get_misses),
+            backfills: std::mem::take(&mut self.backfills),
+            reconnects: std::mem::take(&mut self.reconnects),
+            verified_hits: std::mem::take(&mut self.verified_hits),
+            multi_gets: std::mem::take(&mut self.multi_gets),
+            coalesced_gets: std::mem::take(&mut self.coalesced_gets),
+            quiet_operations: std::mem::take(&mut self.quiet_operations),
+            warmup_operations: std::mem::take(&mut self.warmup_operations),
+            ramp_down_operations: std::mem::take(&mut self.ramp_down_operations),
+            first_operation: self.first_operation.take(),
+            last_operation: self.last_operation.take(),
+            end_of_iteration: false,
+            validation: self.validation.as_mut().map(ResponseValidation::take),
+            latency_log: self
+                .latency_log
+                .as_mut()
+                .map(std::mem::take)
+                .unwrap_or_default(),
+            namespaces: std::mem::take(&mut self.namespaces),
         };
 
         // Reset for next interval
@@ -84,6 +328,14 @@ impl ConnectionStats {
 
         snapshot
     }
+
+    /// Take the final snapshot of the current loop iteration
+    pub fn end_iteration(&mut self) -> StatsSnapshot {
+        StatsSnapshot {
+            end_of_iteration: true,
+            ..self.snapshot()
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -93,6 +345,68 @@ pub struct JsonStats {
     pub throughput: f64,
     pub operations: HashMap<String, OperationStats>,
     pub errors: HashMap<String, u64>,
+    pub get_misses: u64,
+    pub backfills: u64,
+    pub reconnects: u64,
+    pub verified_hits: u64,
+    pub multi_gets: u64,
+    pub coalesced_gets: u64,
+    /// Quiet commands, counted in the total but not in any latency
+    pub quiet_operations: u64,
+    pub warmup_operations: u64,
+    pub ramp_down_operations: u64,
+    /// Throughput the replay was asked for, in ops/sec
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requested_rate: Option<u64>,
+    /// Divergence of the responses from the recorded ones
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validation: Option<ResponseValidation>,
+    /// Per-iteration breakdown of looped replays
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub iterations: Vec<JsonIterationStats>,
+    /// Per-target breakdown of replays against several targets
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<JsonTargetStats>,
+    /// Per-namespace breakdown of keys with several segments
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub namespaces: Vec<JsonNamespaceStats>,
+    /// Target B against target A, in an A/B replay
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<ComparisonReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonIterationStats {
+    pub iteration: usize,
+    pub elapsed_secs: f64,
+    pub total_operations: u64,
+    pub throughput: f64,
+    pub errors: u64,
+    pub get_misses: u64,
+    pub p50_micros: u64,
+    pub p99_micros: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonTargetStats {
+    pub target: String,
+    pub total_operations: u64,
+    pub errors: u64,
+    pub get_misses: u64,
+    pub p50_micros: u64,
+    pub p99_micros: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonNamespaceStats {
+    pub namespace: String,
+    pub total_operations: u64,
+    pub retrievals: u64,
+    pub get_misses: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hit_rate: Option<f64>,
+    pub p50_micros: u64,
+    pub p99_micros: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -105,6 +419,195 @@ pub struct OperationStats {
     pub max_micros: u64,
 }
 
+/// Operations of one loop iteration, across all connections
+#[derive(Debug, Clone)]
+pub struct IterationStats {
+    latency: Histogram<u64>,
+    operations: u64,
+    errors: u64,
+    get_misses: u64,
+    started_at: Instant,
+    /// Last snapshot merged: every connection's last one ends its iteration
+    updated_at: Instant,
+}
+
+impl IterationStats {
+    fn new(started_at: Instant) -> Self {
+        IterationStats {
+            latency: Histogram::new(3).expect("Failed to create histogram"),
+            operations: 0,
+            errors: 0,
+            get_misses: 0,
+            started_at,
+            updated_at: started_at,
+        }
+    }
+
+    fn merge(&mut self, snapshot: &StatsSnapshot) {
+        for hist in snapshot.histograms.values() {
+            self.latency.add(hist).ok();
+        }
+        self.operations +=
+            snapshot.success_counts.values().sum::<u64>() + snapshot.quiet_operations;
+        self.errors += snapshot.error_counts.values().sum::<u64>();
+        self.get_misses += snapshot.get_misses;
+    }
+
+    pub fn operations(&self) -> u64 {
+        self.operations
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    pub fn get_misses(&self) -> u64 {
+        self.get_misses
+    }
+
+    /// Latency percentile over every command type
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        (!self.latency.is_empty()).then(|| self.latency.value_at_percentile(percentile))
+    }
+
+    /// Time from the first connection starting the iteration to the last one
+    /// finishing it
+    pub fn elapsed_secs(&self) -> f64 {
+        self.updated_at
+            .saturating_duration_since(self.started_at)
+            .as_secs_f64()
+    }
+
+    pub fn throughput(&self) -> f64 {
+        let elapsed = self.elapsed_secs();
+        if elapsed > 0.0 {
+            self.operations as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Operations sent to one target, across all connections
+#[derive(Debug, Clone)]
+pub struct TargetStats {
+    latency: Histogram<u64>,
+    operations: u64,
+    errors: u64,
+    get_misses: u64,
+}
+
+impl TargetStats {
+    fn new() -> Self {
+        TargetStats {
+            latency: Histogram::new(3).expect("Failed to create histogram"),
+            operations: 0,
+            errors: 0,
+            get_misses: 0,
+        }
+    }
+
+    fn merge(&mut self, snapshot: &StatsSnapshot) {
+        for hist in snapshot.histograms.values() {
+            self.latency.add(hist).ok();
+        }
+        self.operations +=
+            snapshot.success_counts.values().sum::<u64>() + snapshot.quiet_operations;
+        self.errors += snapshot.error_counts.values().sum::<u64>();
+        self.get_misses += snapshot.get_misses;
+    }
+
+    pub fn operations(&self) -> u64 {
+        self.operations
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    pub fn get_misses(&self) -> u64 {
+        self.get_misses
+    }
+
+    /// Latency percentile over every command type
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        (!self.latency.is_empty()).then(|| self.latency.value_at_percentile(percentile))
+    }
+}
+
+/// Operations on the keys of one namespace, across all connections
+#[derive(Debug, Clone)]
+pub struct NamespaceStats {
+    latency: Histogram<u64>,
+    retrievals: u64,
+    get_misses: u64,
+}
+
+impl NamespaceStats {
+    pub fn new() -> Self {
+        NamespaceStats {
+            latency: Histogram::new(3).expect("Failed to create histogram"),
+            retrievals: 0,
+            get_misses: 0,
+        }
+    }
+
+    /// Rebuild from the parts sent over the network
+    pub fn from_parts(latency: Histogram<u64>, retrievals: u64, get_misses: u64) -> Self {
+        NamespaceStats {
+            latency,
+            retrievals,
+            get_misses,
+        }
+    }
+
+    fn record(&mut self, cmd_type: CommandType, latency: Duration, miss: bool) {
+        self.latency.record(latency.as_micros() as u64).ok();
+        if cmd_type.is_retrieval() {
+            self.retrievals += 1;
+            self.get_misses += miss as u64;
+        }
+    }
+
+    fn merge(&mut self, other: &NamespaceStats) {
+        self.latency.add(&other.latency).ok();
+        self.retrievals += other.retrievals;
+        self.get_misses += other.get_misses;
+    }
+
+    pub fn latency(&self) -> &Histogram<u64> {
+        &self.latency
+    }
+
+    pub fn operations(&self) -> u64 {
+        self.latency.len()
+    }
+
+    pub fn retrievals(&self) -> u64 {
+        self.retrievals
+    }
+
+    pub fn get_misses(&self) -> u64 {
+        self.get_misses
+    }
+
+    /// Share of the retrievals that hit, if there were any
+    pub fn hit_rate(&self) -> Option<f64> {
+        (self.retrievals > 0).then(|| 1.0 - self.get_misses as f64 / self.retrievals as f64)
+    }
+
+    /// Latency percentile over every command type
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        (!self.latency.is_empty()).then(|| self.latency.value_at_percentile(percentile))
+    }
+}
+
+impl Default for NamespaceStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct AggregatedStats {
     // Merged histograms per operation type
     histograms: HashMap<CommandType, Histogram<u64>>,
@@ -112,9 +615,37 @@ pub struct AggregatedStats {
     // Total counters
     success_counts: HashMap<CommandType, u64>,
     error_counts: HashMap<ErrorType, u64>,
+    traffic: TrafficSummary,
+    get_misses: u64,
+    backfills: u64,
+    reconnects: u64,
+    verified_hits: u64,
+    multi_gets: u64,
+    coalesced_gets: u64,
+    quiet_operations: u64,
+    warmup_operations: u64,
+    ramp_down_operations: u64,
+    requested_rate: Option<u64>,
+    validation: Option<ResponseValidation>,
 
-    // Timing
+    // Loop iterations, and the one each connection (to each target) is in
+    iterations: Vec<IterationStats>,
+    connection_iterations: HashMap<(u16, usize), usize>,
+
+    // Breakdown by target, and their addresses
+    targets: Vec<TargetStats>,
+    target_names: Vec<String>,
+
+    // Command-by-command latencies of the two targets of an A/B replay
+    pairing: Option<LatencyPairing>,
+
+    // Breakdown by key namespace
+    namespaces: HashMap<u32, NamespaceStats>,
+
+    // Timing: the measurement window spans the first to the last operation
     start_time: std::time::Instant,
+    first_operation: Option<Instant>,
+    last_operation: Option<Instant>,
 }
 
 impl Default for AggregatedStats {
@@ -129,11 +660,49 @@ impl AggregatedStats {
             histograms: HashMap::new(),
             success_counts: HashMap::new(),
             error_counts: HashMap::new(),
+            traffic: TrafficSummary::new(),
+            get_misses: 0,
+            backfills: 0,
+            reconnects: 0,
+            verified_hits: 0,
+            multi_gets: 0,
+            coalesced_gets: 0,
+            quiet_operations: 0,
+            warmup_operations: 0,
+            ramp_down_operations: 0,
+            requested_rate: None,
+            validation: None,
+            iterations: Vec::new(),
+            connection_iterations: HashMap::new(),
+            targets: Vec::new(),
+            target_names: Vec::new(),
+            pairing: None,
+            namespaces: HashMap::new(),
             start_time: std::time::Instant::now(),
+            first_operation: None,
+            last_operation: None,
         }
     }
 
     pub fn merge(&mut self, snapshot: StatsSnapshot) {
+        self.merge_iteration(&snapshot);
+        if self.targets.len() <= snapshot.target {
+            self.targets
+                .resize_with(snapshot.target + 1, TargetStats::new);
+        }
+        self.targets[snapshot.target].merge(&snapshot);
+        if !snapshot.latency_log.is_empty() {
+            self.pairing.get_or_insert_with(Default::default).add(
+                snapshot.connection_id,
+                snapshot.target,
+                snapshot.latency_log,
+            );
+        }
+
+        for (namespace, stats) in &snapshot.namespaces {
+            self.namespaces.entry(*namespace).or_default().merge(stats);
+        }
+
         // Merge histograms
         for (cmd_type, hist) in snapshot.histograms {
             let agg_hist = self
@@ -152,10 +721,183 @@ impl AggregatedStats {
         for (error_type, count) in snapshot.error_counts {
             *self.error_counts.entry(error_type).or_insert(0) += count;
         }
+
+        self.traffic.merge(snapshot.traffic);
+        self.get_misses += snapshot.get_misses;
+        self.backfills += snapshot.backfills;
+        self.reconnects += snapshot.reconnects;
+        self.verified_hits += snapshot.verified_hits;
+        self.multi_gets += snapshot.multi_gets;
+        self.coalesced_gets += snapshot.coalesced_gets;
+        self.quiet_operations += snapshot.quiet_operations;
+        self.warmup_operations += snapshot.warmup_operations;
+        self.ramp_down_operations += snapshot.ramp_down_operations;
+        if let Some(validation) = snapshot.validation {
+            self.validation
+                .get_or_insert_with(Default::default)
+                .merge(validation);
+        }
+        self.first_operation = match (self.first_operation, snapshot.first_operation) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.last_operation = self.last_operation.max(snapshot.last_operation);
+    }
+
+    /// Attribute a snapshot to the iteration its connection is in
+    fn merge_iteration(&mut self, snapshot: &StatsSnapshot) {
+        let iteration = self
+            .connection_iterations
+            .entry((snapshot.connection_id, snapshot.target))
+            .or_insert(0);
+        if self.iterations.is_empty() {
+            self.iterations.push(IterationStats::new(self.start_time));
+        }
+        let now = Instant::now();
+        let stats = &mut self.iterations[*iteration];
+        stats.merge(snapshot);
+        stats.updated_at = now;
+
+        if snapshot.end_of_iteration {
+            *iteration += 1;
+            if *iteration == self.iterations.len() {
+                // First connection to start the next iteration
+                self.iterations.push(IterationStats::new(now));
+            }
+        }
+    }
+
+    /// Per-iteration breakdown; a single entry unless the replay looped
+    pub fn iterations(&self) -> &[IterationStats] {
+        &self.iterations
+    }
+
+    /// Name the targets, in the order of their indices
+    pub fn set_target_names(&mut self, names: Vec<String>) {
+        self.target_names = names;
+    }
+
+    /// Addresses of the targets, in the order of their indices
+    pub fn target_names(&self) -> &[String] {
+        &self.target_names
+    }
+
+    /// Per-target breakdown, with each target's address; empty unless the
+    /// replay had several targets
+    pub fn targets(&self) -> Vec<(&str, &TargetStats)> {
+        if self.target_names.len() < 2 {
+            return Vec::new();
+        }
+        self.target_names
+            .iter()
+            .map(String::as_str)
+            .zip(&self.targets)
+            .collect()
+    }
+
+    /// Target B against target A, when the commands were mirrored to both
+    pub fn comparison(&self) -> Option<ComparisonReport> {
+        let pairing = self.pairing.as_ref()?;
+        match self.targets().as_slice() {
+            &[a, b] => Some(ComparisonReport::new(a, b, pairing)),
+            _ => None,
+        }
+    }
+
+    /// Per-namespace breakdown, busiest first; empty unless keys had
+    /// several segments
+    pub fn namespaces(&self) -> Vec<(u32, &NamespaceStats)> {
+        let mut namespaces: Vec<_> = self
+            .namespaces
+            .iter()
+            .map(|(namespace, stats)| (*namespace, stats))
+            .collect();
+        namespaces
+            .sort_by_key(|(namespace, stats)| (std::cmp::Reverse(stats.operations()), *namespace));
+        namespaces
+    }
+
+    pub fn get_misses(&self) -> u64 {
+        self.get_misses
+    }
+
+    /// Operations completed during the warmup (not counted as operations)
+    pub fn warmup_operations(&self) -> u64 {
+        self.warmup_operations
+    }
+
+    /// Operations completed during the ramp-down (not counted as operations)
+    pub fn ramp_down_operations(&self) -> u64 {
+        self.ramp_down_operations
+    }
+
+    /// SETs issued to backfill GET misses (not counted as operations)
+    pub fn backfills(&self) -> u64 {
+        self.backfills
+    }
+
+    /// Connections re-established after an error
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    /// Retrieval hits found intact when verifying
+    pub fn verified_hits(&self) -> u64 {
+        self.verified_hits
+    }
+
+    /// Retrieval hits on the wrong key or with a wrong value
+    pub fn integrity_errors(&self) -> u64 {
+        self.error_counts
+            .get(&ErrorType::IntegrityError)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Multi-key requests sent by coalescing GETs
+    pub fn multi_gets(&self) -> u64 {
+        self.multi_gets
+    }
+
+    /// GETs sent within multi-key requests (each counted as an operation)
+    pub fn coalesced_gets(&self) -> u64 {
+        self.coalesced_gets
+    }
+
+    /// Quiet commands sent without waiting for a response (counted as
+    /// operations, but in no latency)
+    pub fn quiet_operations(&self) -> u64 {
+        self.quiet_operations
+    }
+
+    /// Summary of all completed events
+    pub fn traffic(&self) -> &TrafficSummary {
+        &self.traffic
     }
 
     pub fn total_operations(&self) -> u64 {
-        self.success_counts.values().sum()
+        self.success_counts.values().sum::<u64>() + self.quiet_operations
+    }
+
+    pub fn total_errors(&self) -> u64 {
+        self.error_counts.values().sum()
+    }
+
+    /// Commands whose response did not arrive within the request timeout
+    pub fn timeouts(&self) -> u64 {
+        self.error_counts
+            .get(&ErrorType::Timeout)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Share of the commands waited for that timed out, in percent
+    pub fn timeout_rate(&self) -> f64 {
+        let requests = self.total_operations() - self.quiet_operations + self.total_errors();
+        if requests == 0 {
+            return 0.0;
+        }
+        self.timeouts() as f64 / requests as f64 * 100.0
     }
 
     pub fn percentile(&self, cmd_type: CommandType, percentile: f64) -> Option<u64> {
@@ -164,8 +906,28 @@ impl AggregatedStats {
             .map(|h| h.value_at_percentile(percentile))
     }
 
+    /// Latency percentile over every command type
+    pub fn latency_percentile(&self, percentile: f64) -> Option<u64> {
+        Some(self.latency_histogram()?.value_at_percentile(percentile))
+    }
+
+    /// Latencies of every operation type merged, in µs
+    pub fn latency_histogram(&self) -> Option<Histogram<u64>> {
+        let mut histograms = self.histograms.values();
+        let mut merged = histograms.next()?.clone();
+        for hist in histograms {
+            merged.add(hist).ok();
+        }
+        Some(merged)
+    }
+
+    /// Length of the measurement window, from the first completed operation
+    /// to the last one (excluding the warmup and connection setup)
     pub fn elapsed_secs(&self) -> f64 {
-        self.start_time.elapsed().as_secs_f64()
+        match (self.first_operation, self.last_operation) {
+            (Some(first), Some(last)) => last.saturating_duration_since(first).as_secs_f64(),
+            _ => 0.0,
+        }
     }
 
     pub fn throughput(&self) -> f64 {
@@ -177,6 +939,20 @@ impl AggregatedStats {
         }
     }
 
+    /// Record the throughput the replay was asked for, to report next to the achieved one
+    pub fn set_requested_rate(&mut self, rate: Option<u64>) {
+        self.requested_rate = rate;
+    }
+
+    pub fn requested_rate(&self) -> Option<u64> {
+        self.requested_rate
+    }
+
+    /// Comparison of the replayed responses with the recorded ones, when validating
+    pub fn validation(&self) -> Option<&ResponseValidation> {
+        self.validation.as_ref()
+    }
+
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
         let mut operations = HashMap::new();
 
@@ -204,10 +980,69 @@ impl AggregatedStats {
             throughput: self.throughput(),
             operations,
             errors,
+            get_misses: self.get_misses,
+            backfills: self.backfills,
+            reconnects: self.reconnects,
+            verified_hits: self.verified_hits,
+            multi_gets: self.multi_gets,
+            coalesced_gets: self.coalesced_gets,
+            quiet_operations: self.quiet_operations,
+            warmup_operations: self.warmup_operations,
+            ramp_down_operations: self.ramp_down_operations,
+            requested_rate: self.requested_rate,
+            validation: self.validation.clone(),
+            iterations: if self.iterations.len() > 1 {
+                self.iterations_json()
+            } else {
+                Vec::new()
+            },
+            targets: self
+                .targets()
+                .into_iter()
+                .map(|(target, stats)| JsonTargetStats {
+                    target: target.to_string(),
+                    total_operations: stats.operations(),
+                    errors: stats.errors(),
+                    get_misses: stats.get_misses(),
+                    p50_micros: stats.percentile(50.0).unwrap_or(0),
+                    p99_micros: stats.percentile(99.0).unwrap_or(0),
+                })
+                .collect(),
+            namespaces: self
+                .namespaces()
+                .into_iter()
+                .map(|(namespace, stats)| JsonNamespaceStats {
+                    namespace: format!("{:08x}", namespace),
+                    total_operations: stats.operations(),
+                    retrievals: stats.retrievals(),
+                    get_misses: stats.get_misses(),
+                    hit_rate: stats.hit_rate(),
+                    p50_micros: stats.percentile(50.0).unwrap_or(0),
+                    p99_micros: stats.percentile(99.0).unwrap_or(0),
+                })
+                .collect(),
+            comparison: self.comparison(),
         };
 
         serde_json::to_string_pretty(&json_stats)
     }
+
+    fn iterations_json(&self) -> Vec<JsonIterationStats> {
+        self.iterations
+            .iter()
+            .enumerate()
+            .map(|(index, stats)| JsonIterationStats {
+                iteration: index + 1,
+                elapsed_secs: stats.elapsed_secs(),
+                total_operations: stats.operations(),
+                throughput: stats.throughput(),
+                errors: stats.errors(),
+                get_misses: stats.get_misses(),
+                p50_micros: stats.percentile(50.0).unwrap_or(0),
+                p99_micros: stats.percentile(99.0).unwrap_or(0),
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -303,4 +1138,183 @@ mod tests {
         assert!(json.contains("\"Get\""));
         assert!(json.contains("\"Set\""));
     }
+
+    #[test]
+    fn test_backfills_counted_separately() {
+        let mut agg = AggregatedStats::new();
+
+        let mut stats = ConnectionStats::new(1);
+        stats.record_success(CommandType::Get, Duration::from_micros(100));
+        stats.record_miss();
+        stats.record_backfill();
+
+        agg.merge(stats.snapshot());
+
+        assert_eq!(agg.total_operations(), 1);
+        assert_eq!(agg.get_misses(), 1);
+        assert_eq!(agg.backfills(), 1);
+        assert!(agg.to_json().unwrap().contains("\"backfills\": 1"));
+    }
+
+    #[test]
+    fn test_quiet_operations_count_without_latency() {
+        let mut agg = AggregatedStats::new();
+
+        let mut stats = ConnectionStats::new(1);
+        stats.record_success(CommandType::Get, Duration::from_micros(100));
+        stats.record_quiet();
+        stats.record_quiet();
+        agg.merge(stats.snapshot());
+
+        assert_eq!(agg.total_operations(), 3);
+        assert_eq!(agg.quiet_operations(), 2);
+        assert_eq!(agg.iterations()[0].operations(), 3);
+        assert_eq!(agg.latency_histogram().unwrap().len(), 1);
+        assert!(agg.to_json().unwrap().contains("\"quiet_operations\": 2"));
+    }
+
+    #[test]
+    fn test_iterations_split_at_end_of_iteration() {
+        let mut agg = AggregatedStats::new();
+
+        let mut stats1 = ConnectionStats::new(1);
+        let mut stats2 = ConnectionStats::new(2);
+        stats1.record_success(CommandType::Get, Duration::from_micros(100));
+        agg.merge(stats1.end_iteration());
+        stats1.record_success(CommandType::Get, Duration::from_micros(100));
+        stats1.record_success(CommandType::Get, Duration::from_micros(100));
+        agg.merge(stats1.snapshot());
+        // Connection 2 lags behind: its events still belong to the first iteration
+        stats2.record_success(CommandType::Set, Duration::from_micros(100));
+        agg.merge(stats2.end_iteration());
+
+        let iterations = agg.iterations();
+        assert_eq!(iterations.len(), 2);
+        assert_eq!(iterations[0].operations(), 2);
+        assert_eq!(iterations[1].operations(), 2);
+        assert_eq!(agg.total_operations(), 4);
+        assert!(agg.to_json().unwrap().contains("\"iterations\""));
+    }
+
+    #[test]
+    fn test_targets_broken_down() {
+        let mut agg = AggregatedStats::new();
+        agg.set_target_names(vec!["a:11211".to_string(), "b:11211".to_string()]);
+
+        // One replayed connection, connected to both targets
+        let mut to_a = ConnectionStats::new(1);
+        let mut to_b = ConnectionStats::new(1).with_target(1);
+        to_a.record_success(CommandType::Get, Duration::from_micros(100));
+        to_b.record_success(CommandType::Get, Duration::from_micros(300));
+        to_b.record_error(CommandType::Get, ErrorType::Timeout);
+        agg.merge(to_a.end_iteration());
+        agg.merge(to_b.end_iteration());
+
+        let targets = agg.targets();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].0, "a:11211");
+        assert_eq!((targets[0].1.operations(), targets[0].1.errors()), (1, 0));
+        assert_eq!((targets[1].1.operations(), targets[1].1.errors()), (1, 1));
+        // Both connections ended the same iteration
+        assert_eq!(agg.iterations()[0].operations(), 2);
+        assert!(agg.to_json().unwrap().contains("\"b:11211\""));
+    }
+
+    #[test]
+    fn test_namespaces_broken_down() {
+        let event = |cmd_type, key_segments| Event {
+            key_segments,
+            ..Event::new(0, 1, cmd_type, 0, 8)
+        };
+        let mut agg = AggregatedStats::new();
+        let mut stats = ConnectionStats::new(1);
+        let user = event(CommandType::Get, Some(vec![7, 1]));
+        stats.record_namespace(&user, Duration::from_micros(100), false);
+        stats.record_namespace(&user, Duration::from_micros(300), true);
+        stats.record_namespace(&user, Duration::from_micros(200), false);
+        let session = event(CommandType::Set, Some(vec![9, 1, 2]));
+        stats.record_namespace(&session, Duration::from_micros(50), false);
+        // Keys of a single segment have no namespace
+        let flat = event(CommandType::Get, Some(vec![3]));
+        stats.record_namespace(&flat, Duration::from_micros(50), false);
+        agg.merge(stats.snapshot());
+
+        let namespaces = agg.namespaces();
+        assert_eq!(namespaces.len(), 2);
+        let (namespace, user) = namespaces[0];
+        assert_eq!(namespace, 7);
+        assert_eq!((user.operations(), user.get_misses()), (3, 1));
+        assert!((user.hit_rate().unwrap() - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(user.percentile(50.0), Some(200));
+        let (namespace, session) = namespaces[1];
+        assert_eq!(namespace, 9);
+        assert_eq!(session.hit_rate(), None);
+        assert!(agg.to_json().unwrap().contains("\"00000007\""));
+    }
+
+    #[test]
+    fn test_elapsed_spans_measured_operations() {
+        let mut agg = AggregatedStats::new();
+        assert_eq!(agg.elapsed_secs(), 0.0);
+
+        // Setup time before the first operation is not measured
+        std::thread::sleep(Duration::from_millis(50));
+        let mut stats = ConnectionStats::new(1);
+        stats.record_success(CommandType::Get, Duration::from_micros(100));
+        std::thread::sleep(Duration::from_millis(10));
+        stats.record_success(CommandType::Get, Duration::from_micros(100));
+        agg.merge(stats.snapshot());
+
+        let elapsed = agg.elapsed_secs();
+        assert!((0.01..0.05).contains(&elapsed), "{}", elapsed);
+    }
+
+    #[test]
+    fn test_warmup_operations_excluded() {
+        let mut agg = AggregatedStats::new();
+
+        let mut stats =
+            ConnectionStats::new(1).with_warmup_until(Instant::now() + Duration::from_secs(60));
+        stats.record_success(CommandType::Get, Duration::from_micros(100));
+        stats.record_miss();
+        agg.merge(stats.snapshot());
+
+        assert_eq!(agg.total_operations(), 0);
+        assert_eq!(agg.get_misses(), 0);
+        assert_eq!(agg.warmup_operations(), 1);
+        assert_eq!(agg.percentile(CommandType::Get, 50.0), None);
+    }
+
+    #[test]
+    fn test_ramp_down_operations_excluded() {
+        let mut agg = AggregatedStats::new();
+
+        let mut stats = ConnectionStats::new(1).with_ramp_down_from(Instant::now());
+        stats.record_success(CommandType::Get, Duration::from_micros(100));
+        stats.record_quiet();
+        stats.record_miss();
+        agg.merge(stats.snapshot());
+
+        assert_eq!(agg.total_operations(), 0);
+        assert_eq!(agg.get_misses(), 0);
+        assert_eq!(agg.ramp_down_operations(), 2);
+        assert_eq!(agg.warmup_operations(), 0);
+    }
+
+    #[test]
+    fn test_timeout_rate_over_requests_waited_for() {
+        let mut agg = AggregatedStats::new();
+
+        let mut stats = ConnectionStats::new(1);
+        for _ in 0..3 {
+            stats.record_success(CommandType::Get, Duration::from_micros(100));
+        }
+        stats.record_quiet();
+        stats.record_error(CommandType::Get, ErrorType::Timeout);
+        agg.merge(stats.snapshot());
+
+        assert_eq!(agg.timeouts(), 1);
+        assert_eq!(agg.timeout_rate(), 25.0);
+        assert!(agg.to_json().unwrap().contains("\"Timeout\": 1"));
+    }
 }
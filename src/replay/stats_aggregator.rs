@@ -1,9 +1,13 @@
+use super::control::LiveStats;
 use super::stats::{AggregatedStats, StatsSnapshot};
-use tokio::sync::mpsc;
+use crate::logging::REPORT_TARGET;
+use tokio::sync::{mpsc, watch};
 
+/// Merge the snapshots of every connection until all of them are done. A
+/// cancelled replay still counts the final snapshots of its connections.
 pub async fn spawn_stats_aggregator(
     mut rx: mpsc::Receiver<StatsSnapshot>,
-    cancel_token: tokio_util::sync::CancellationToken,
+    live_stats: watch::Sender<LiveStats>,
 ) -> tokio::task::JoinHandle<AggregatedStats> {
     tokio::spawn(async move {
         let mut agg_stats = AggregatedStats::new();
@@ -11,14 +15,17 @@ pub async fn spawn_stats_aggregator(
 
         loop {
             tokio::select! {
-                _ = cancel_token.cancelled() => {
-                    tracing::info!("Stats aggregator cancelled");
-                    break;
-                }
                 snapshot_opt = rx.recv() => {
                     match snapshot_opt {
                         Some(snapshot) => {
                             agg_stats.merge(snapshot);
+                            live_stats.send_replace(LiveStats {
+                                operations: agg_stats.total_operations(),
+                                errors: agg_stats.total_errors(),
+                                get_misses: agg_stats.get_misses(),
+                                elapsed_secs: agg_stats.elapsed_secs(),
+                                throughput: agg_stats.throughput(),
+                            });
                         }
                         None => {
                             tracing::info!("Stats aggregator receiver closed");
@@ -38,6 +45,7 @@ pub async fn spawn_stats_aggregator(
                     }
 
                     tracing::info!(
+                        target: REPORT_TARGET,
                         "[{:.0}s] Operations: {} | Throughput: {:.0} ops/sec",
                         elapsed,
                         total_ops,
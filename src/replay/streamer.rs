@@ -1,84 +1,250 @@
-use crate::profile::Event;
-use anyhow::Result;
+//! Reads the events of a profile from disk as they are replayed
+//!
+//! Only the metadata trailer is read up front, by seeking to the end of the
+//! file; events then go through a fixed read window (and, for compressed
+//! profiles, one inflated block), so memory stays bounded whatever the size of
+//! the profile. Reading a time range seeks to it through the index section of
+//! the profile when it has one.
+
+use crate::profile::checkpoint::{read_record_from, Record};
+use crate::profile::checksum::{crc32c, RecordChecksums, Trailer, CHECKSUM_RECORD_LEN};
+use crate::profile::compression::{BlockReader, BLOCK_MAGIC};
+use crate::profile::index::{decode_index, seek};
+use crate::profile::packed::EventDecoder;
+use crate::profile::{Compression, Event, ProfileMetadata, TimeRange};
+use anyhow::{anyhow, Result};
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+/// Bytes of the file read at once
+const READ_WINDOW: usize = 256 << 10;
+
+/// Where the events of a profile are in its file
+#[derive(Debug, Clone, Copy)]
+struct EventRegion {
+    /// Offset of the first record to read: the start of the file, or an
+    /// index entry
+    start: u64,
+    /// Bytes of the file holding events, from its start
+    len: u64,
+    compression: Compression,
+    /// Bytes of (inflated) events to read, when the region ends before its
+    /// last record: at the last checkpoint of a crashed recording
+    limit: Option<u64>,
+    /// Profile version the events are encoded with
+    version: u8,
+}
+
+impl EventRegion {
+    /// Reader of the events of the region, from its first one
+    fn open(&self, file: &File) -> Result<BufReader<Box<dyn Read + Send>>> {
+        let mut file = file.try_clone()?;
+        file.seek(SeekFrom::Start(self.start))?;
+        let region = file.take(self.len - self.start);
+        let recovered = self.limit.is_some();
+        let events: Box<dyn Read + Send> = match self.compression {
+            Compression::None => Box::new(region),
+            Compression::Deflate if self.start > 0 => {
+                Box::new(BlockReader::at_block(region, self.start, recovered))
+            }
+            Compression::Deflate => Box::new(BlockReader::new(region, recovered)?),
+        };
+        let events = match self.limit {
+            Some(limit) => Box::new(events.take(limit)),
+            None => events,
+        };
+        Ok(BufReader::with_capacity(READ_WINDOW, events))
+    }
+
+    /// Verifier of the checksums of the records `open` reads
+    fn checksums(&self) -> RecordChecksums {
+        match self.compression {
+            Compression::None => RecordChecksums::starting_at(self.start),
+            Compression::Deflate => RecordChecksums::new(self.compression),
+        }
+    }
+}
 
 pub struct ProfileStreamer {
-    data: Vec<u8>,
-    event_end_offset: usize,
-    current_offset: usize,
+    file: File,
+    metadata: ProfileMetadata,
+    region: EventRegion,
+    /// First and last timestamps of the events to read
+    range: (u64, u64),
+    events: BufReader<Box<dyn Read + Send>>,
+    decoder: EventDecoder,
+    checksums: RecordChecksums,
+    /// Bytes of the record being read
+    record: Vec<u8>,
 }
 
 impl ProfileStreamer {
     pub fn new(path: &str) -> Result<Self> {
-        let mut file = File::open(path)?;
-        let mut data = Vec::new();
-        file.read_to_end(&mut data)?;
+        Self::with_range(path, TimeRange::default())
+    }
 
-        if data.len() < 6 {
-            return Err(anyhow::anyhow!("file too small"));
+    /// Streamer of the events of `range` only. The first event after the
+    /// range ends it.
+    pub fn with_range(path: &str, range: TimeRange) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        if file_len < 6 {
+            return Err(anyhow!("file too small"));
         }
 
-        // Last 4 bytes are the end marker
-        let end_marker_pos = data.len() - 4;
-        let end_marker = u32::from_le_bytes([
-            data[end_marker_pos],
-            data[end_marker_pos + 1],
-            data[end_marker_pos + 2],
-            data[end_marker_pos + 3],
-        ]);
-
-        if end_marker != 0xDEADBEEF {
-            return Err(anyhow::anyhow!("invalid file format: missing end marker"));
-        }
+        // Trailer: metadata length, its checksum, then the end marker
+        let mut tail = vec![0u8; Trailer::MAX_LEN.min(file_len as usize)];
+        file.seek(SeekFrom::End(-(tail.len() as i64)))?;
+        file.read_exact(&mut tail)?;
 
-        // Read metadata length
-        let metadata_len_pos = end_marker_pos - 2;
-        let metadata_len =
-            u16::from_le_bytes([data[metadata_len_pos], data[metadata_len_pos + 1]]) as usize;
+        let (metadata, mut region) = if let Some(trailer) = Trailer::parse(&tail) {
+            let metadata_start = trailer.metadata_start(file_len)?;
+            let mut metadata = vec![0u8; trailer.metadata_len as usize];
+            file.seek(SeekFrom::Start(metadata_start))?;
+            file.read_exact(&mut metadata)?;
+            trailer.verify(&metadata, metadata_start)?;
+            let metadata = ProfileMetadata::decode(&metadata)?;
+            let region = EventRegion {
+                start: 0,
+                len: metadata.events_end(metadata_start),
+                compression: metadata.compression,
+                limit: None,
+                version: metadata.version,
+            };
+            (metadata, region)
+        } else {
+            // Crashed recording: fall back to its last checkpoint
+            let (metadata, region) = Self::recover(&mut file, file_len)?;
+            tracing::warn!(
+                "{} has no trailer, replaying {} events up to its last checkpoint",
+                path,
+                metadata.total_events
+            );
+            (metadata, region)
+        };
 
-        let event_end_offset = metadata_len_pos - metadata_len;
+        let range = range.resolve(metadata.time_range.0);
+        if range.0 > metadata.time_range.0 {
+            region.start = Self::seek(&mut file, &metadata, range.0)?;
+        }
 
+        let events = region.open(&file)?;
         Ok(ProfileStreamer {
-            data,
-            event_end_offset,
-            current_offset: 0,
+            file,
+            metadata,
+            region,
+            range,
+            events,
+            decoder: EventDecoder::new(region.version),
+            checksums: region.checksums(),
+            record: Vec::new(),
         })
     }
 
-    pub fn next_event(&mut self) -> Result<Option<Event>> {
-        // Check if we've reached the metadata section
-        if self.current_offset >= self.event_end_offset {
-            return Ok(None);
+    /// Offset of the last index entry before the events from `from` on, or
+    /// of the first event for a profile without an index
+    fn seek(file: &mut File, metadata: &ProfileMetadata, from: u64) -> Result<u64> {
+        let Some(index) = metadata.index else {
+            return Ok(0);
+        };
+        let mut section = vec![0u8; index.len() as usize];
+        file.seek(SeekFrom::Start(index.offset))?;
+        file.read_exact(&mut section)?;
+        if crc32c(&section) != index.crc {
+            return Err(anyhow!(
+                "checksum mismatch in the index at bytes {}..{}: the profile is corrupt",
+                index.offset,
+                index.offset + index.len()
+            ));
         }
+        let entries = decode_index(&section)?;
+        Ok(seek(&entries, from).map_or(0, |entry| entry.offset))
+    }
+
+    /// Metadata of the last checkpoint of a profile missing its trailer, and
+    /// the events up to it, found in one pass over the file
+    fn recover(file: &mut File, file_len: u64) -> Result<(ProfileMetadata, EventRegion)> {
+        let mut magic = [0u8; BLOCK_MAGIC.len()];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut magic)?;
+        let compression = if magic == BLOCK_MAGIC {
+            Compression::Deflate
+        } else {
+            Compression::None
+        };
 
-        // Check if we have room for length prefix
-        if self.current_offset + 2 > self.event_end_offset {
-            return Ok(None);
+        let whole = EventRegion {
+            start: 0,
+            len: file_len,
+            compression,
+            limit: Some(u64::MAX),
+            version: 0,
+        };
+        let mut events = whole.open(file)?;
+        let mut buf = Vec::new();
+        let mut offset = 0;
+        let mut last = None;
+        // A truncated record ends the recording as well
+        while let Ok(Some(record)) = read_record_from(&mut events, &mut buf) {
+            match record {
+                Record::Event(event) => offset += 2 + event.len() as u64,
+                Record::Checksum(_) => offset += CHECKSUM_RECORD_LEN as u64,
+                Record::Checkpoint(bytes) => {
+                    let Ok(metadata) = ProfileMetadata::decode(bytes) else {
+                        break;
+                    };
+                    offset += 4 + bytes.len() as u64;
+                    last = Some((metadata, offset));
+                }
+            }
         }
 
-        // Read length prefix
-        let len = u16::from_le_bytes([
-            self.data[self.current_offset],
-            self.data[self.current_offset + 1],
-        ]) as usize;
-        self.current_offset += 2;
+        let (metadata, end) = last
+            .ok_or_else(|| anyhow!("invalid file format: missing end marker and no checkpoint"))?;
+        let region = EventRegion {
+            limit: Some(end),
+            version: metadata.version,
+            ..whole
+        };
+        Ok((metadata, region))
+    }
 
-        // Check if we have room for event data
-        if self.current_offset + len > self.event_end_offset {
-            return Err(anyhow::anyhow!("event data exceeds file boundary"));
-        }
+    pub fn next_event(&mut self) -> Result<Option<Event>> {
+        loop {
+            let Some(record) = read_record_from(&mut self.events, &mut self.record)? else {
+                return Ok(None);
+            };
+            self.checksums.check(&record)?;
 
-        // Deserialize event
-        let event_bytes = &self.data[self.current_offset..self.current_offset + len];
-        let event: Event = bincode::deserialize(event_bytes)?;
-        self.current_offset += len;
+            // Checkpoints only matter to recovery
+            match record {
+                Record::Event(event_bytes) => {
+                    let event = self.decoder.decode(event_bytes)?;
+                    let (from, to) = self.range;
+                    if event.timestamp > to {
+                        // Nothing more to read until a reset
+                        self.events = BufReader::new(Box::new(std::io::empty()));
+                        return Ok(None);
+                    }
+                    if event.timestamp >= from {
+                        return Ok(Some(event));
+                    }
+                }
+                Record::Checksum(_) => self.decoder.restart(),
+                Record::Checkpoint(_) => {}
+            }
+        }
+    }
 
-        Ok(Some(event))
+    pub fn metadata(&self) -> &ProfileMetadata {
+        &self.metadata
     }
 
+    /// Start over from the first event of the range, to loop over the profile
     pub fn reset(&mut self) -> Result<()> {
-        self.current_offset = 0;
+        self.events = self.region.open(&self.file)?;
+        self.decoder = EventDecoder::new(self.region.version);
+        self.checksums = self.region.checksums();
         Ok(())
     }
 }
@@ -0,0 +1,379 @@
+//! Synthetic workloads: a small spec file describing the traffic mix, and
+//! the task that feeds TrafficGenerator output to connection queues
+
+use super::analyzer::AnalysisResult;
+use super::connection_task::ConnectionMessage;
+use super::control::ControlHandle;
+use super::generator::{InterArrival, KeyPopularity, TrafficGenerator};
+use super::scheduler::{TimingWheel, SCHEDULE_AHEAD, TICK};
+use crate::profile::CommandType;
+use anyhow::{anyhow, Context, Result};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// Synthetic workload description, read from a flat TOML file:
+///
+/// ```toml
+/// connections = 8
+/// get = 90
+/// set = 9
+/// delete = 1
+/// key_size = [16, 32]
+/// value_size = [100, 1000, 4000]
+/// keyspace = 1_000_000
+/// zipf_s = 0.99
+/// zipf_q = 0
+/// interarrival_us = 250
+/// get_after_set = 0.2
+/// locality_window = 1000
+/// ```
+///
+/// Command weights are relative; sizes are sampled uniformly from the list.
+/// Without `keyspace`, every event uses a fresh random key. `interarrival_us`
+/// is the mean gap between two commands of one connection (Poisson arrivals).
+/// `get_after_set` is the probability that a GET targets one of the last
+/// `locality_window` keys that were set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticSpec {
+    pub connections: u16,
+    pub command_weights: HashMap<CommandType, u64>,
+    pub key_sizes: Vec<u32>,
+    pub value_sizes: Vec<u32>,
+    pub keyspace: Option<u64>,
+    pub zipf_s: f64,
+    pub zipf_q: f64,
+    pub interarrival_us: Option<f64>,
+    pub get_after_set: f64,
+    pub locality_window: usize,
+}
+
+impl Default for SyntheticSpec {
+    fn default() -> Self {
+        SyntheticSpec {
+            connections: 4,
+            command_weights: HashMap::from([(CommandType::Get, 90), (CommandType::Set, 10)]),
+            key_sizes: vec![16],
+            value_sizes: vec![100],
+            keyspace: None,
+            zipf_s: 0.0,
+            zipf_q: 0.0,
+            interarrival_us: None,
+            get_after_set: 0.0,
+            locality_window: 1000,
+        }
+    }
+}
+
+impl SyntheticSpec {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .context(format!("failed to read synthetic spec: {}", path))?;
+        Self::parse(&text).context(format!("invalid synthetic spec: {}", path))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut spec = SyntheticSpec::default();
+        let mut weights_seen = false;
+
+        for (lineno, raw) in text.lines().enumerate() {
+            let line = raw.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("line {}: expected 'key = value'", lineno + 1))?;
+            let (key, value) = (key.trim(), value.trim());
+            let err = |e: anyhow::Error| e.context(format!("line {}: {}", lineno + 1, key));
+
+            match key {
+                "connections" => {
+                    let connections = parse_number(value).map_err(err)?;
+                    spec.connections = u16::try_from(connections).map_err(|_| {
+                        anyhow!(
+                            "line {}: connections must be at most {}",
+                            lineno + 1,
+                            u16::MAX
+                        )
+                    })?;
+                }
+                "key_size" => spec.key_sizes = parse_sizes(value).map_err(err)?,
+                "value_size" => spec.value_sizes = parse_sizes(value).map_err(err)?,
+                "keyspace" => spec.keyspace = Some(parse_number(value).map_err(err)?),
+                "zipf_s" => spec.zipf_s = parse_float(value).map_err(err)?,
+                "zipf_q" => spec.zipf_q = parse_float(value).map_err(err)?,
+                "get_after_set" => spec.get_after_set = parse_float(value).map_err(err)?,
+                "locality_window" => {
+                    spec.locality_window = parse_number(value).map_err(err)? as usize
+                }
+                "interarrival_us" => spec.interarrival_us = Some(parse_float(value).map_err(err)?),
+                _ => {
+                    let Some(cmd_type) = CommandType::from_name(key) else {
+                        return Err(anyhow!("line {}: unknown key '{}'", lineno + 1, key));
+                    };
+                    if !weights_seen {
+                        spec.command_weights.clear();
+                        weights_seen = true;
+                    }
+                    spec.command_weights
+                        .insert(cmd_type, parse_number(value).map_err(err)?);
+                }
+            }
+        }
+
+        if spec.connections == 0 {
+            return Err(anyhow!("connections must be at least 1"));
+        }
+        if spec.keyspace == Some(0) {
+            return Err(anyhow!("keyspace must be at least 1"));
+        }
+        if !(0.0..=1.0).contains(&spec.get_after_set) {
+            return Err(anyhow!("get_after_set must be between 0 and 1"));
+        }
+        if spec.command_weights.values().sum::<u64>() == 0 {
+            return Err(anyhow!("at least one command weight must be non-zero"));
+        }
+
+        Ok(spec)
+    }
+
+    /// Express the spec as the distribution TrafficGenerator samples from
+    pub fn to_analysis(&self) -> AnalysisResult {
+        AnalysisResult {
+            total_events: 0,
+            command_distribution: self.command_weights.clone(),
+            key_size_distribution: self.key_sizes.iter().map(|&s| (s, 1)).collect(),
+            value_size_distribution: self.value_sizes.iter().map(|&s| (s, 1)).collect(),
+            key_frequencies: Vec::new(),
+            hot_keys: Vec::new(),
+            hot_keys_by_command: HashMap::new(),
+            inter_arrival_distribution: Vec::new(),
+        }
+    }
+
+    pub fn popularity(&self) -> KeyPopularity {
+        match self.keyspace {
+            Some(keyspace) => KeyPopularity::Zipf {
+                keyspace,
+                s: self.zipf_s,
+                q: self.zipf_q,
+            },
+            None => KeyPopularity::Uniform,
+        }
+    }
+
+    /// Arrival model: the spec's mean gap, else one matching `rate` (total
+    /// ops/sec across connections), else back-to-back
+    pub fn inter_arrival(&self, rate: Option<u64>) -> InterArrival {
+        match (self.interarrival_us, rate) {
+            (Some(mean_micros), _) => InterArrival::Poisson { mean_micros },
+            (None, Some(rate)) if rate > 0 => InterArrival::Poisson {
+                mean_micros: self.connections as f64 * 1_000_000.0 / rate as f64,
+            },
+            _ => InterArrival::Immediate,
+        }
+    }
+
+    pub fn generator(&self) -> TrafficGenerator {
+        TrafficGenerator::new(self.to_analysis())
+            .with_connections(self.connections)
+            .with_popularity(self.popularity())
+            .with_get_after_set(self.get_after_set, self.locality_window)
+    }
+}
+
+impl std::fmt::Display for SyntheticSpec {
+    /// The spec in the form `parse` reads back
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |sizes: &[u32]| {
+            let sizes: Vec<String> = sizes.iter().map(u32::to_string).collect();
+            format!("[{}]", sizes.join(", "))
+        };
+        writeln!(f, "connections = {}", self.connections)?;
+        for cmd_type in CommandType::ALL {
+            if let Some(weight) = self.command_weights.get(&cmd_type) {
+                writeln!(f, "{} = {}", cmd_type.name(), weight)?;
+            }
+        }
+        writeln!(f, "key_size = {}", list(&self.key_sizes))?;
+        writeln!(f, "value_size = {}", list(&self.value_sizes))?;
+        if let Some(keyspace) = self.keyspace {
+            writeln!(f, "keyspace = {}", keyspace)?;
+        }
+        writeln!(f, "zipf_s = {:?}", self.zipf_s)?;
+        writeln!(f, "zipf_q = {:?}", self.zipf_q)?;
+        if let Some(mean_micros) = self.interarrival_us {
+            writeln!(f, "interarrival_us = {:?}", mean_micros)?;
+        }
+        writeln!(f, "get_after_set = {:?}", self.get_after_set)?;
+        write!(f, "locality_window = {}", self.locality_window)
+    }
+}
+
+fn parse_number(value: &str) -> Result<u64> {
+    value
+        .replace('_', "")
+        .parse()
+        .map_err(|_| anyhow!("expected an integer, got '{}'", value))
+}
+
+fn parse_float(value: &str) -> Result<f64> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("expected a number, got '{}'", value))
+}
+
+fn parse_sizes(value: &str) -> Result<Vec<u32>> {
+    let inner = match value.strip_prefix('[') {
+        Some(rest) => rest
+            .strip_suffix(']')
+            .ok_or_else(|| anyhow!("unterminated array"))?,
+        None => value,
+    };
+    let sizes = inner
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| parse_number(s).map(|n| n as u32))
+        .collect::<Result<Vec<_>>>()?;
+
+    if sizes.is_empty() {
+        return Err(anyhow!("expected at least one size"));
+    }
+    Ok(sizes)
+}
+
+/// Most sending time the rate limiter saves up while there is nothing to
+/// send, so a stall is not followed by a burst
+const MAX_BUDGET: Duration = SCHEDULE_AHEAD;
+
+/// Feed generated events to connection queues until the duration elapses
+/// (or forever). With a rate, every event is dispatched at its generated
+/// timestamp through a timing wheel; without one, the queues' backpressure
+/// sets the pace (closed loop). The generated timestamps are paced for
+/// `base_rate`: changing the rate through `control` speeds the timeline up
+/// or down in proportion. When the spec's own inter-arrival outpaces the
+/// rate, the timeline waits for the rate instead of piling events up.
+pub async fn synthetic_task(
+    mut generator: TrafficGenerator,
+    connection_queues: HashMap<u16, mpsc::Sender<ConnectionMessage>>,
+    base_rate: Option<u64>,
+    duration: Option<Duration>,
+    mut control: ControlHandle,
+    cancel_token: tokio_util::sync::CancellationToken,
+) -> Result<()> {
+    let start = Instant::now();
+    let mut sent = 0u64;
+    let mut wheel = TimingWheel::new();
+    let mut expired = Vec::new();
+    let mut due = VecDeque::new();
+    let mut pending = generator.next_command();
+    let tick_micros = TICK.as_micros() as u64;
+    // Position in the generated timeline (µs), and events the rate allows to send
+    let mut trace_micros = 0.0f64;
+    let mut budget = 0.0f64;
+    let mut last = start;
+
+    'outer: loop {
+        if cancel_token.is_cancelled() {
+            tracing::info!("Synthetic task cancelled");
+            break;
+        }
+        if *control.paused.borrow_and_update() {
+            if !control.wait_until_resumed(&cancel_token).await {
+                tracing::info!("Synthetic task cancelled while paused");
+                break;
+            }
+            // Time spent paused neither advances the timeline nor earns budget
+            last = Instant::now();
+        }
+        let now = Instant::now();
+        if duration.is_some_and(|d| now - start >= d) {
+            tracing::info!("Synthetic replay duration reached");
+            break;
+        }
+
+        let rate = *control.rate.borrow_and_update();
+        let speed = match (rate, base_rate) {
+            (Some(rate), Some(base_rate)) => rate as f64 / base_rate as f64,
+            _ => 1.0,
+        };
+        if let Some(rate) = rate {
+            let dt = (now - last).as_secs_f64();
+            let max_budget = (rate as f64 * MAX_BUDGET.as_secs_f64()).max(1.0);
+            budget = (budget + dt * rate as f64).min(max_budget);
+            last = now;
+
+            // Events still waiting for budget: the rate is behind the
+            // timeline, which holds until they are sent
+            if due.is_empty() {
+                trace_micros += dt * speed * 1e6;
+
+                // Schedule what falls within the lookahead, then dispatch what is due
+                let horizon = (trace_micros + SCHEDULE_AHEAD.as_micros() as f64 * speed) as u64;
+                while pending.timestamp <= horizon {
+                    let next = generator.next_command();
+                    let event = std::mem::replace(&mut pending, next);
+                    wheel.insert(event.timestamp / tick_micros, event);
+                }
+                wheel.advance(trace_micros as u64 / tick_micros, &mut expired);
+                due.extend(expired.drain(..));
+            }
+        } else {
+            let next = generator.next_command();
+            due.push_back(std::mem::replace(&mut pending, next));
+            budget = f64::INFINITY;
+        }
+
+        // The rate also caps dispatch when the spec sets its own inter-arrival
+        while budget >= 1.0 {
+            let Some(event) = due.pop_front() else {
+                break;
+            };
+            let conn_id = event.conn_id;
+            // Another worker of a distributed replay sends it
+            let Some(tx) = connection_queues.get(&conn_id) else {
+                continue;
+            };
+
+            tokio::select! {
+                _ = cancel_token.cancelled() => {
+                    tracing::info!("Synthetic task cancelled during send");
+                    break 'outer;
+                }
+                result = tx.send(ConnectionMessage::Event(event, Instant::now())) => {
+                    if result.is_err() {
+                        tracing::warn!("Connection {} task closed unexpectedly", conn_id);
+                        break 'outer;
+                    }
+                }
+            }
+            sent += 1;
+            budget -= 1.0;
+        }
+
+        if let Some(rate) = rate {
+            // A single timer per wakeup: the next deadline, or the next send
+            // while capped, and never further than the lookahead. Control
+            // changes wake the task early.
+            let wait = if due.is_empty() {
+                wheel.next_deadline().map_or(SCHEDULE_AHEAD, |deadline| {
+                    let ahead = (deadline * tick_micros) as f64 - trace_micros;
+                    Duration::from_secs_f64((ahead / speed).max(0.0) / 1e6)
+                })
+            } else {
+                Duration::from_secs_f64((1.0 - budget).max(0.0) / rate as f64)
+            };
+            let wake = Instant::now() + wait.clamp(TICK, SCHEDULE_AHEAD);
+            tokio::select! {
+                _ = tokio::time::sleep_until(wake.into()) => {}
+                _ = control.rate.changed() => {}
+                _ = control.paused.changed() => {}
+            }
+        }
+    }
+
+    tracing::debug!("Synthetic task generated {} events", sent);
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Throughput of a profile over time, in fixed intervals
+//!
+//! Rates over the whole capture hide its bursts: the series counts the
+//! commands and bytes of every interval of the capture, overall and per
+//! command, so its peaks can be told from its mean.
+
+use crate::profile::{CommandType, Event};
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Intervals the series is automatically cut into, shortest first
+pub const AUTO_INTERVALS: [Duration; 6] = [
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+    Duration::from_secs(600),
+    Duration::from_secs(3600),
+    Duration::from_secs(86_400),
+];
+
+/// Commands and bytes of one interval
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ThroughputBucket {
+    pub commands: u64,
+    /// Keys, stored values and returned values
+    pub bytes: u64,
+    pub per_command: HashMap<CommandType, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ThroughputSeries {
+    /// Timestamp the first interval starts at
+    pub start: u64,
+    /// Serialized in µs, as `interval_micros`
+    #[serde(rename = "interval_micros", serialize_with = "micros")]
+    pub interval: Duration,
+    /// Every interval from the first event to the last, quiet ones included
+    pub buckets: Vec<ThroughputBucket>,
+}
+
+impl ThroughputSeries {
+    /// Series of `events` in intervals of `interval` (at least 1µs)
+    pub fn new(events: &[Event], interval: Duration) -> Self {
+        let start = events.iter().map(|e| e.timestamp).min().unwrap_or(0);
+        let width = (interval.as_micros() as u64).max(1);
+        let mut buckets: Vec<ThroughputBucket> = Vec::new();
+        for event in events {
+            let at = ((event.timestamp - start) / width) as usize;
+            if at >= buckets.len() {
+                buckets.resize(at + 1, ThroughputBucket::default());
+            }
+            let bucket = &mut buckets[at];
+            bucket.commands += 1;
+            bucket.bytes += event.key_size as u64
+                + event.value_size.map_or(0, |size| size.get() as u64)
+                + event
+                    .response
+                    .and_then(|r| r.value_size)
+                    .map_or(0, |size| size.get() as u64);
+            *bucket.per_command.entry(event.cmd_type).or_insert(0) += 1;
+        }
+        ThroughputSeries {
+            start,
+            interval: Duration::from_micros(width),
+            buckets,
+        }
+    }
+
+    /// Shortest of `AUTO_INTERVALS` cutting a capture lasting `duration`
+    /// (in µs) into at most `rows` intervals
+    pub fn auto_interval(duration: u64, rows: usize) -> Duration {
+        AUTO_INTERVALS
+            .iter()
+            .copied()
+            .find(|interval| duration / (interval.as_micros() as u64) < rows as u64)
+            .unwrap_or(AUTO_INTERVALS[AUTO_INTERVALS.len() - 1])
+    }
+
+    /// Per-second rate of a count over one interval
+    pub fn rate(&self, count: u64) -> f64 {
+        count as f64 / self.interval.as_secs_f64()
+    }
+
+    /// Commands of the series, in their order
+    pub fn commands(&self) -> Vec<CommandType> {
+        let mut commands: Vec<CommandType> = self
+            .buckets
+            .iter()
+            .flat_map(|bucket| bucket.per_command.keys().copied())
+            .collect();
+        commands.sort();
+        commands.dedup();
+        commands
+    }
+
+    /// Index and commands of the busiest interval
+    pub fn peak(&self) -> Option<(usize, u64)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, bucket)| (bucket.commands, std::cmp::Reverse(*i)))
+            .map(|(i, bucket)| (i, bucket.commands))
+    }
+
+    /// Write the series as CSV, one row per interval: its start (µs since the
+    /// epoch), commands/s, bytes/s, then commands/s of each command
+    pub fn write_csv(&self, mut out: impl Write) -> io::Result<()> {
+        let commands = self.commands();
+        let mut header = vec![
+            "timestamp".to_string(),
+            "ops_per_sec".to_string(),
+            "bytes_per_sec".to_string(),
+        ];
+        header.extend(
+            commands
+                .iter()
+                .map(|cmd_type| format!("{}_ops_per_sec", cmd_type.name())),
+        );
+        writeln!(out, "{}", header.join(","))?;
+        let width = self.interval.as_micros() as u64;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            let mut fields = vec![
+                (self.start + i as u64 * width).to_string(),
+                self.rate(bucket.commands).to_string(),
+                self.rate(bucket.bytes).to_string(),
+            ];
+            fields.extend(commands.iter().map(|cmd_type| {
+                self.rate(*bucket.per_command.get(cmd_type).unwrap_or(&0))
+                    .to_string()
+            }));
+            writeln!(out, "{}", fields.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+fn micros<S: Serializer>(interval: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(interval.as_micros() as u64)
+}
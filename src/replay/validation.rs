@@ -0,0 +1,133 @@
+//! Comparison of replayed responses with the responses recorded in the profile
+
+use crate::profile::{CommandType, Event, Response, ResponseStatus};
+use crate::record::{FramedResponse, MemcacheParser};
+use serde::{Deserialize, Serialize};
+
+/// Outcome of a response read from the target: `None` when it is not a
+/// response membench understands
+pub fn classify_response(response: &[u8]) -> Option<Response> {
+    match MemcacheParser::new().frame_response(response) {
+        FramedResponse::Response(response, _, _) => Some(response),
+        FramedResponse::Skip(_) | FramedResponse::Incomplete => None,
+    }
+}
+
+/// A replayed response that differs from the recorded one
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mismatch {
+    pub conn_id: u16,
+    /// Recorded timestamp of the command, in µs
+    pub timestamp: u64,
+    pub cmd_type: CommandType,
+    pub key_hash: u64,
+    pub recorded: Response,
+    /// `None` when the target's response could not be parsed
+    pub replayed: Option<Response>,
+}
+
+/// Divergence of the replayed responses from the recorded ones, keeping the
+/// earliest mismatching commands
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseValidation {
+    /// Responses compared: commands whose recorded response is known
+    pub compared: u64,
+    /// Different status: hit, miss or error
+    pub status_mismatches: u64,
+    /// Same status, different value size
+    pub value_size_mismatches: u64,
+    /// Earliest mismatches by recorded timestamp, at most `limit`
+    pub mismatches: Vec<Mismatch>,
+    #[serde(skip)]
+    limit: usize,
+}
+
+impl ResponseValidation {
+    /// Validation keeping the first `limit` mismatching commands
+    pub fn new(limit: usize) -> Self {
+        ResponseValidation {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// Mismatching commands kept
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The comparisons so far, leaving this one empty with the same limit
+    pub fn take(&mut self) -> Self {
+        std::mem::replace(self, ResponseValidation::new(self.limit))
+    }
+
+    /// Compare the response `replayed` from the target to the one recorded for `event`
+    pub fn check(&mut self, event: &Event, replayed: &[u8]) {
+        let Some(recorded) = event.response else {
+            return;
+        };
+        self.compared += 1;
+        let replayed = classify_response(replayed);
+        match replayed {
+            Some(replayed) if replayed == recorded => return,
+            Some(replayed) if replayed.status == recorded.status => {
+                self.value_size_mismatches += 1;
+            }
+            _ => self.status_mismatches += 1,
+        }
+        self.keep(Mismatch {
+            conn_id: event.conn_id,
+            timestamp: event.timestamp,
+            cmd_type: event.cmd_type,
+            key_hash: event.key_hash,
+            recorded,
+            replayed,
+        });
+    }
+
+    /// Mismatching commands over all comparisons
+    pub fn total_mismatches(&self) -> u64 {
+        self.status_mismatches + self.value_size_mismatches
+    }
+
+    /// Share of compared responses that matched the recording, in percent
+    pub fn match_percent(&self) -> f64 {
+        if self.compared == 0 {
+            return 100.0;
+        }
+        (self.compared - self.total_mismatches()) as f64 / self.compared as f64 * 100.0
+    }
+
+    /// Add the comparisons of `other`, made on another connection or later
+    pub fn merge(&mut self, other: ResponseValidation) {
+        self.compared += other.compared;
+        self.status_mismatches += other.status_mismatches;
+        self.value_size_mismatches += other.value_size_mismatches;
+        self.limit = self.limit.max(other.limit);
+        for mismatch in other.mismatches {
+            self.keep(mismatch);
+        }
+    }
+
+    fn keep(&mut self, mismatch: Mismatch) {
+        let position = self.mismatches.partition_point(|kept| {
+            (kept.timestamp, kept.conn_id) <= (mismatch.timestamp, mismatch.conn_id)
+        });
+        if position < self.limit {
+            self.mismatches.insert(position, mismatch);
+            self.mismatches.truncate(self.limit);
+        }
+    }
+}
+
+/// Short description of a response for the mismatch report
+pub fn describe(response: Option<Response>) -> String {
+    match response {
+        None => "unparsed".to_string(),
+        Some(Response {
+            status: ResponseStatus::Hit,
+            value_size: Some(size),
+        }) => format!("Hit ({} bytes)", size),
+        Some(response) => format!("{:?}", response.status),
+    }
+}
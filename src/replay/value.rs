@@ -0,0 +1,143 @@
+//! Payloads of the values written by a replay
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::str::FromStr;
+
+/// Fills the values of the storage commands a connection sends
+pub trait ValueGenerator: Send {
+    /// Append a value of `size` bytes, for the key hashed to `key_hash`, to `buf`
+    fn fill(&mut self, key_hash: u64, size: usize, buf: &mut Vec<u8>);
+}
+
+/// The same byte over and over: compresses to almost nothing
+pub struct Repeated(pub u8);
+
+impl ValueGenerator for Repeated {
+    fn fill(&mut self, _key_hash: u64, size: usize, buf: &mut Vec<u8>) {
+        buf.resize(buf.len() + size, self.0);
+    }
+}
+
+/// Random bytes: incompressible
+pub struct RandomBytes {
+    rng: StdRng,
+}
+
+impl RandomBytes {
+    pub fn new() -> Self {
+        RandomBytes {
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+
+impl Default for RandomBytes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValueGenerator for RandomBytes {
+    fn fill(&mut self, _key_hash: u64, size: usize, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.resize(start + size, 0);
+        self.rng.fill(&mut buf[start..]);
+    }
+}
+
+/// Bytes of each block of a compressible value: its random head, then one
+/// byte repeated
+const COMPRESSIBLE_BLOCK: usize = 64;
+
+/// Random bytes diluted with repeated ones, so the value compresses to about
+/// `ratio` of its size
+pub struct Compressible {
+    ratio: f64,
+    rng: StdRng,
+}
+
+impl Compressible {
+    pub fn new(ratio: f64) -> Self {
+        Compressible {
+            ratio: ratio.clamp(0.0, 1.0),
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+
+impl ValueGenerator for Compressible {
+    fn fill(&mut self, _key_hash: u64, size: usize, buf: &mut Vec<u8>) {
+        let random = (COMPRESSIBLE_BLOCK as f64 * self.ratio).round() as usize;
+        let start = buf.len();
+        buf.resize(start + size, b'x');
+        for block in buf[start..].chunks_mut(COMPRESSIBLE_BLOCK) {
+            let head = random.min(block.len());
+            self.rng.fill(&mut block[..head]);
+        }
+    }
+}
+
+/// Bytes derived from the key hash: every write of a key carries the same
+/// value, from one replay to the next
+pub struct KeyPattern;
+
+impl ValueGenerator for KeyPattern {
+    fn fill(&mut self, key_hash: u64, size: usize, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.resize(start + size, 0);
+        StdRng::seed_from_u64(key_hash).fill(&mut buf[start..]);
+    }
+}
+
+/// Built-in value generator, as selected on the command line
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ValueFill {
+    /// `x` repeated
+    #[default]
+    Repeat,
+    Random,
+    /// Compressing to about this share of the value size, from 0 to 1
+    Compressible(f64),
+    /// Derived from the key hash
+    Pattern,
+}
+
+impl ValueFill {
+    /// A generator for one connection
+    pub fn generator(&self) -> Box<dyn ValueGenerator> {
+        match *self {
+            ValueFill::Repeat => Box::new(Repeated(b'x')),
+            ValueFill::Random => Box::new(RandomBytes::new()),
+            ValueFill::Compressible(ratio) => Box::new(Compressible::new(ratio)),
+            ValueFill::Pattern => Box::new(KeyPattern),
+        }
+    }
+}
+
+impl FromStr for ValueFill {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repeat" => Ok(ValueFill::Repeat),
+            "random" => Ok(ValueFill::Random),
+            "pattern" => Ok(ValueFill::Pattern),
+            s if s.starts_with("compressible:") => s
+                .strip_prefix("compressible:")
+                .and_then(|ratio| ratio.parse::<f64>().ok())
+                .filter(|ratio| (0.0..=1.0).contains(ratio))
+                .map(ValueFill::Compressible)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid compression ratio: '{}'. Use a number from 0 to 1",
+                        s
+                    )
+                }),
+            _ => Err(format!(
+                "Invalid value fill: '{}'. Use 'repeat', 'random', 'compressible:RATIO' or 'pattern'",
+                s
+            )),
+        }
+    }
+}
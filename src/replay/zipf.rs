@@ -0,0 +1,182 @@
+//! Zipf / Zipf-Mandelbrot fitting of key popularity
+
+use serde::Serialize;
+
+/// Maximum number of (rank, frequency) points used for regression.
+/// Ranks are sampled log-uniformly so the head and the tail carry equal weight.
+const MAX_FIT_POINTS: usize = 1000;
+
+/// Candidate Mandelbrot offsets tried when fitting `f(r) ∝ 1 / (r + q)^s`
+const Q_CANDIDATES: &[f64] = &[
+    0.0, 0.25, 0.5, 1.0, 1.5, 2.0, 3.0, 4.0, 5.0, 7.5, 10.0, 15.0, 20.0, 30.0, 50.0, 75.0, 100.0,
+];
+
+/// Result of fitting a rank/frequency curve
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ZipfFit {
+    /// Exponent `s`
+    pub s: f64,
+    /// Mandelbrot rank offset `q` (0 for plain Zipf)
+    pub q: f64,
+    /// Coefficient of determination of the log-log regression
+    pub r_squared: f64,
+}
+
+/// Fit plain Zipf (`q = 0`) to key frequencies sorted in descending order
+pub fn fit_zipf(frequencies: &[u64]) -> Option<ZipfFit> {
+    let points = sample_points(frequencies);
+    regress(&points, 0.0)
+}
+
+/// Fit Zipf-Mandelbrot to key frequencies sorted in descending order,
+/// picking the rank offset `q` with the best goodness of fit
+pub fn fit_zipf_mandelbrot(frequencies: &[u64]) -> Option<ZipfFit> {
+    let points = sample_points(frequencies);
+    Q_CANDIDATES
+        .iter()
+        .filter_map(|&q| regress(&points, q))
+        .max_by(|a, b| a.r_squared.total_cmp(&b.r_squared))
+}
+
+/// Pick (rank, frequency) points, 1-based ranks, log-uniformly spaced
+fn sample_points(frequencies: &[u64]) -> Vec<(f64, f64)> {
+    let n = frequencies.len();
+    if n <= MAX_FIT_POINTS {
+        return frequencies
+            .iter()
+            .enumerate()
+            .map(|(i, &f)| ((i + 1) as f64, f as f64))
+            .collect();
+    }
+
+    let log_n = (n as f64).ln();
+    let mut points = Vec::with_capacity(MAX_FIT_POINTS);
+    let mut last_rank = 0usize;
+    for i in 0..MAX_FIT_POINTS {
+        let rank = (log_n * i as f64 / (MAX_FIT_POINTS - 1) as f64)
+            .exp()
+            .round()
+            .clamp(1.0, n as f64) as usize;
+        if rank == last_rank {
+            continue;
+        }
+        last_rank = rank;
+        points.push((rank as f64, frequencies[rank - 1] as f64));
+    }
+    points
+}
+
+/// Least-squares fit of `ln f = c - s * ln(r + q)`
+fn regress(points: &[(f64, f64)], q: f64) -> Option<ZipfFit> {
+    let xy: Vec<(f64, f64)> = points
+        .iter()
+        .filter(|(_, f)| *f > 0.0)
+        .map(|(r, f)| ((r + q).ln(), f.ln()))
+        .collect();
+    if xy.len() < 2 {
+        return None;
+    }
+
+    let n = xy.len() as f64;
+    let mean_x = xy.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = xy.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx: f64 = xy.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = xy.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let syy: f64 = xy.iter().map(|(_, y)| (y - mean_y).powi(2)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+
+    let slope = sxy / sxx;
+    // A perfectly flat curve is explained exactly by s = 0
+    let r_squared = if syy == 0.0 {
+        1.0
+    } else {
+        (sxy * sxy) / (sxx * syy)
+    };
+
+    Some(ZipfFit {
+        s: -slope,
+        q,
+        r_squared,
+    })
+}
+
+/// Sampler of ranks `1..=n` with `P(k) ∝ 1 / (k + q)^s`, using
+/// Hörmann & Derflinger's rejection-inversion (constant memory in `n`)
+#[derive(Debug, Clone)]
+pub struct ZipfSampler {
+    n: u64,
+    s: f64,
+    q: f64,
+    h_integral_x1: f64,
+    h_integral_n: f64,
+    threshold: f64,
+}
+
+impl ZipfSampler {
+    pub fn new(n: u64, s: f64, q: f64) -> Self {
+        let n = n.max(1);
+        let mut sampler = ZipfSampler {
+            n,
+            s,
+            q: q.max(0.0),
+            h_integral_x1: 0.0,
+            h_integral_n: 0.0,
+            threshold: 0.0,
+        };
+        sampler.h_integral_x1 = sampler.h_integral(1.5) - sampler.h(1.0);
+        sampler.h_integral_n = sampler.h_integral(n as f64 + 0.5);
+        sampler.threshold =
+            2.0 - sampler.h_integral_inverse(sampler.h_integral(2.5) - sampler.h(2.0));
+        sampler
+    }
+
+    pub fn keyspace(&self) -> u64 {
+        self.n
+    }
+
+    /// Draw a rank in `1..=n` (1 is the most popular)
+    pub fn sample(&self, rng: &mut impl rand::Rng) -> u64 {
+        loop {
+            let u = self.h_integral_n + rng.gen::<f64>() * (self.h_integral_x1 - self.h_integral_n);
+            let x = self.h_integral_inverse(u);
+            let k = x.round().clamp(1.0, self.n as f64);
+            if k - x <= self.threshold || u >= self.h_integral(k + 0.5) - self.h(k) {
+                return k as u64;
+            }
+        }
+    }
+
+    fn h(&self, x: f64) -> f64 {
+        (-self.s * (x + self.q).ln()).exp()
+    }
+
+    fn h_integral(&self, x: f64) -> f64 {
+        let log_x = (x + self.q).ln();
+        helper2((1.0 - self.s) * log_x) * log_x
+    }
+
+    fn h_integral_inverse(&self, x: f64) -> f64 {
+        let t = (x * (1.0 - self.s)).max(-1.0);
+        (helper1(t) * x).exp() - self.q
+    }
+}
+
+/// `ln(1 + x) / x`, continuous at 0
+fn helper1(x: f64) -> f64 {
+    if x.abs() > 1e-8 {
+        x.ln_1p() / x
+    } else {
+        1.0 - x * (0.5 - x * (1.0 / 3.0 - 0.25 * x))
+    }
+}
+
+/// `(e^x - 1) / x`, continuous at 0
+fn helper2(x: f64) -> f64 {
+    if x.abs() > 1e-8 {
+        x.exp_m1() / x
+    } else {
+        1.0 + x * 0.5 * (1.0 + x * (1.0 / 3.0) * (1.0 + 0.25 * x))
+    }
+}
@@ -0,0 +1,153 @@
+//! Sample command: downsample a profile to a representative share of its
+//! events
+
+use crate::profile::Event;
+use crate::record::{EventSampler, ProfileWriter};
+use crate::replay::ProfileStreamer;
+use anyhow::{anyhow, Context, Result};
+use siphasher::sip::SipHasher13;
+use std::fmt;
+use std::hash::Hasher;
+use std::str::FromStr;
+
+/// What is drawn to decide which events are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SampleBy {
+    /// A share of the keys, with every event on them: the commands on a key
+    /// stay together, so hits and misses replay as recorded
+    #[default]
+    Keys,
+    /// Each event independently, as `record --sample-events` does: exact in
+    /// expectation for every distribution, but a kept GET may lose the SET
+    /// before it
+    Events,
+}
+
+impl FromStr for SampleBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keys" => Ok(SampleBy::Keys),
+            "events" => Ok(SampleBy::Events),
+            _ => Err(format!("Invalid sampling: '{}'. Use 'keys' or 'events'", s)),
+        }
+    }
+}
+
+impl fmt::Display for SampleBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleBy::Keys => write!(f, "keys"),
+            SampleBy::Events => write!(f, "events"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleReport {
+    pub events_read: u64,
+    pub events_kept: u64,
+}
+
+/// Decides which events of a profile are kept
+enum Sampler {
+    /// Keeps the keys whose salted hash is below the threshold
+    Keys {
+        seed: u64,
+        threshold: u64,
+    },
+    Events(Box<EventSampler>),
+}
+
+impl Sampler {
+    fn new(ratio: f64, by: SampleBy, seed: u64) -> Self {
+        match by {
+            SampleBy::Keys => Sampler::Keys {
+                seed,
+                threshold: (ratio * u64::MAX as f64) as u64,
+            },
+            SampleBy::Events => Sampler::Events(Box::new(EventSampler::new(ratio, seed))),
+        }
+    }
+
+    fn keeps(&mut self, event: &Event) -> bool {
+        match self {
+            Sampler::Keys { seed, threshold } => {
+                let mut hasher = SipHasher13::new_with_keys(*seed, 0);
+                hasher.write_u64(event.key_hash);
+                hasher.finish() < *threshold || *threshold == u64::MAX
+            }
+            Sampler::Events(sampler) => sampler.keeps(),
+        }
+    }
+}
+
+/// Write `ratio` of the events of `input` to `output`, drawn by `by` from
+/// `seed`, in the order recorded so every connection keeps its order. The
+/// ratio joins the event sampling of the metadata, so analysis scales the
+/// sampled counts back up.
+pub fn sample(
+    input: &str,
+    output: &str,
+    ratio: f64,
+    by: SampleBy,
+    seed: u64,
+) -> Result<SampleReport> {
+    if !(ratio > 0.0 && ratio <= 1.0) {
+        return Err(anyhow!("the ratio must be in (0, 1], not {}", ratio));
+    }
+    if std::fs::canonicalize(output).ok() == Some(std::fs::canonicalize(input)?) {
+        return Err(anyhow!("output must differ from the input being sampled"));
+    }
+
+    let mut streamer = ProfileStreamer::new(input).context(format!("failed to open {}", input))?;
+    let metadata = streamer.metadata().clone();
+
+    let mut writer = ProfileWriter::with_compression(output, metadata.compression)?;
+    if let Some(rate) = metadata.connection_sampling {
+        writer.set_connection_sampling(rate);
+    }
+    writer.set_event_sampling(metadata.event_sampling.unwrap_or(1.0) * ratio);
+    if let Some(buckets) = metadata.size_buckets {
+        writer.set_size_buckets(buckets);
+    }
+    if metadata.plaintext_keys {
+        writer.set_plaintext_keys();
+    }
+    writer.set_sources(metadata.sources.clone());
+
+    let mut sampler = Sampler::new(ratio, by, seed);
+    let mut events_kept = 0u64;
+    while let Some(event) = streamer.next_event()? {
+        if !sampler.keeps(&event) {
+            continue;
+        }
+        if let Some(&protocol) = metadata.connection_protocols.get(&event.conn_id) {
+            writer.record_protocol(event.conn_id, protocol);
+        }
+        writer.write_event(&event)?;
+        events_kept += 1;
+    }
+    writer.finish()?;
+
+    Ok(SampleReport {
+        events_read: metadata.total_events,
+        events_kept,
+    })
+}
+
+pub fn run(input: &str, output: &str, ratio: f64, by: SampleBy, seed: u64) -> Result<()> {
+    let report = sample(input, output, ratio, by, seed)?;
+
+    println!(
+        "Kept {} of {} events ({:.2}%, sampled by {}) in {}",
+        report.events_kept,
+        report.events_read,
+        report.events_kept as f64 * 100.0 / report.events_read.max(1) as f64,
+        by,
+        output
+    );
+
+    Ok(())
+}
@@ -0,0 +1,5 @@
+//! Sample command implementation
+
+pub mod main;
+
+pub use main::{run as run_sample, sample, SampleBy, SampleReport};
@@ -0,0 +1,150 @@
+//! Split command: divide a profile into shards, by connection or time window
+
+use crate::profile::ProfileMetadata;
+use crate::record::rotation::rotated_path;
+use crate::record::ProfileWriter;
+use crate::replay::ProfileStreamer;
+use anyhow::{anyhow, Context, Result};
+use siphasher::sip::SipHasher13;
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::hash::Hasher;
+use std::time::Duration;
+
+/// How the events of a profile are divided
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitBy {
+    /// Into `shards` profiles, each connection in the one its id hashes to
+    Connection { shards: u32 },
+    /// Into one profile per `window` of the capture, from its first event
+    Time { window: Duration },
+}
+
+/// A profile written by a split
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shard {
+    pub path: String,
+    pub events: u64,
+}
+
+/// Shard being written
+struct Open {
+    path: String,
+    writer: ProfileWriter,
+    events: u64,
+}
+
+/// Write the events of `input` to numbered profiles named after `output`
+/// (`shard.profile` becomes `shard.0000.profile`, `shard.0001.profile`...),
+/// each valid on its own with recomputed metadata. Time windows without
+/// events have no profile, so their numbers are skipped.
+pub fn split(input: &str, output: &str, by: SplitBy) -> Result<Vec<Shard>> {
+    match by {
+        SplitBy::Connection { shards: 0 } => return Err(anyhow!("split into at least one shard")),
+        SplitBy::Time { window } if window.as_micros() == 0 => {
+            return Err(anyhow!("time windows must be longer than zero"))
+        }
+        _ => {}
+    }
+
+    let mut streamer = ProfileStreamer::new(input).context(format!("failed to open {}", input))?;
+    let metadata = streamer.metadata().clone();
+    let mut open = BTreeMap::new();
+    let mut finished = Vec::new();
+
+    // Every connection shard exists, even if no connection hashes to it
+    if let SplitBy::Connection { shards } = by {
+        for index in 0..shards as u64 {
+            open.insert(index, open_shard(output, index, &metadata)?);
+        }
+    }
+
+    let mut start = None;
+    while let Some(event) = streamer.next_event()? {
+        let mut index = match by {
+            SplitBy::Connection { shards } => shard_of(event.conn_id, shards),
+            SplitBy::Time { window } => {
+                let start = *start.get_or_insert(event.timestamp);
+                event.timestamp.saturating_sub(start) / window.as_micros() as u64
+            }
+        };
+        if let SplitBy::Time { .. } = by {
+            // Events are close to time order: the window before stays open
+            // for late ones, older windows are done with
+            while let Some(entry) = open.first_entry() {
+                if *entry.key() + 1 >= index {
+                    break;
+                }
+                finished.push(finish(entry.remove())?);
+            }
+            // Later still, an event goes to the oldest window left
+            if let Some(&oldest) = open.keys().next() {
+                index = index.max(oldest);
+            }
+        }
+
+        let shard = match open.entry(index) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(open_shard(output, index, &metadata)?),
+        };
+        if let Some(&protocol) = metadata.connection_protocols.get(&event.conn_id) {
+            shard.writer.record_protocol(event.conn_id, protocol);
+        }
+        shard.writer.write_event(&event)?;
+        shard.events += 1;
+    }
+
+    for shard in open.into_values() {
+        finished.push(finish(shard)?);
+    }
+    Ok(finished)
+}
+
+/// Shard of the connection `conn_id`, out of `shards`
+fn shard_of(conn_id: u16, shards: u32) -> u64 {
+    let mut hasher = SipHasher13::new();
+    hasher.write_u16(conn_id);
+    hasher.finish() % u64::from(shards)
+}
+
+/// Start the `index`th shard, with the recording options of the input
+fn open_shard(output: &str, index: u64, metadata: &ProfileMetadata) -> Result<Open> {
+    let path = rotated_path(output, index as u32);
+    let mut writer = ProfileWriter::with_compression(&path, metadata.compression)?;
+    if let Some(rate) = metadata.connection_sampling {
+        writer.set_connection_sampling(rate);
+    }
+    if let Some(probability) = metadata.event_sampling {
+        writer.set_event_sampling(probability);
+    }
+    if let Some(buckets) = metadata.size_buckets {
+        writer.set_size_buckets(buckets);
+    }
+    if metadata.plaintext_keys {
+        writer.set_plaintext_keys();
+    }
+    writer.set_sources(metadata.sources.clone());
+    Ok(Open {
+        path,
+        writer,
+        events: 0,
+    })
+}
+
+fn finish(shard: Open) -> Result<Shard> {
+    shard.writer.finish()?;
+    Ok(Shard {
+        path: shard.path,
+        events: shard.events,
+    })
+}
+
+pub fn run(input: &str, output: &str, by: SplitBy) -> Result<()> {
+    let shards = split(input, output, by)?;
+
+    println!("Split {} into {} profiles:", input, shards.len());
+    for shard in &shards {
+        println!("  {}: {} events", shard.path, shard.events);
+    }
+
+    Ok(())
+}
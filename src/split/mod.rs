@@ -0,0 +1,5 @@
+//! Split command implementation
+
+pub mod main;
+
+pub use main::{run as run_split, split, Shard, SplitBy};
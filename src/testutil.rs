@@ -0,0 +1,244 @@
+//! Process management for end-to-end tests against a real memcached
+//!
+//! Enabled with the `testutil` feature. `ManagedMemcached` runs a private
+//! memcached daemon for the lifetime of the value, and `MemtierLoad` drives
+//! memtier_benchmark against it.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Whether `tool` can be found in PATH
+pub fn is_tool_available(tool: &str) -> bool {
+    Command::new("which")
+        .arg(tool)
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// A memcached daemon owned by the test, killed when dropped
+pub struct ManagedMemcached {
+    child: Child,
+    port: u16,
+}
+
+impl ManagedMemcached {
+    /// How long `start` waits for the daemon to accept connections
+    pub const READY_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Start memcached on 127.0.0.1:`port` with `memory_mb` of cache and wait
+    /// until it is ready. Fails if something already listens on the port, so a
+    /// test never runs against a daemon it does not own.
+    pub fn start(port: u16, memory_mb: u32) -> Result<Self> {
+        if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+            bail!("port {} is already in use", port);
+        }
+
+        let child = Command::new("memcached")
+            .args(["-l", "127.0.0.1", "-p", &port.to_string()])
+            .args(["-m", &memory_mb.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to start memcached")?;
+
+        let memcached = ManagedMemcached { child, port };
+        memcached.wait_ready(Self::READY_TIMEOUT)?;
+        Ok(memcached)
+    }
+
+    /// Start memcached with 256MB of cache on a port nobody listens on
+    pub fn start_on_free_port() -> Result<Self> {
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        Self::start(port, 256)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// `host:port` address, as taken by `replay --target`
+    pub fn addr(&self) -> String {
+        format!("127.0.0.1:{}", self.port)
+    }
+
+    /// Wait until the daemon answers `version`
+    pub fn wait_ready(&self, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if self
+                .command("version")
+                .is_ok_and(|reply| reply.starts_with("VERSION"))
+            {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        bail!(
+            "memcached on port {} not ready after {:?}",
+            self.port,
+            timeout
+        )
+    }
+
+    /// Drop every item in the cache
+    pub fn flush(&self) -> Result<()> {
+        let reply = self.command("flush_all")?;
+        if reply != "OK" {
+            bail!("flush_all failed: {}", reply);
+        }
+        Ok(())
+    }
+
+    /// Send one ASCII command and return the first line of the reply
+    pub fn command(&self, command: &str) -> Result<String> {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port))?;
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        stream.write_all(format!("{}\r\n", command).as_bytes())?;
+
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+        Ok(reply.trim_end().to_string())
+    }
+
+    /// Kill the daemon and wait for it to exit
+    pub fn stop(mut self) -> Result<()> {
+        self.kill()
+    }
+
+    fn kill(&mut self) -> Result<()> {
+        if self.child.try_wait()?.is_none() {
+            self.child.kill()?;
+            self.child.wait()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ManagedMemcached {
+    fn drop(&mut self) {
+        let _ = self.kill();
+    }
+}
+
+/// Result of a memtier_benchmark run
+#[derive(Debug, Clone)]
+pub struct MemtierReport {
+    pub stdout: String,
+    /// Total throughput, when the summary could be parsed
+    pub ops_per_sec: Option<f64>,
+}
+
+/// A memtier_benchmark invocation against one server
+#[derive(Debug, Clone)]
+pub struct MemtierLoad {
+    port: u16,
+    protocol: String,
+    clients: usize,
+    requests: usize,
+    test_time: Option<Duration>,
+    extra_args: Vec<String>,
+}
+
+impl MemtierLoad {
+    /// ASCII protocol load (what membench parses) from one client
+    pub fn new(port: u16) -> Self {
+        MemtierLoad {
+            port,
+            protocol: "memcache_text".to_string(),
+            clients: 1,
+            requests: 100,
+            test_time: None,
+            extra_args: Vec::new(),
+        }
+    }
+
+    pub fn with_protocol(mut self, protocol: &str) -> Self {
+        self.protocol = protocol.to_string();
+        self
+    }
+
+    pub fn with_clients(mut self, clients: usize) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    pub fn with_requests(mut self, requests: usize) -> Self {
+        self.requests = requests;
+        self
+    }
+
+    /// Run for this long instead of a fixed number of requests
+    pub fn with_test_time(mut self, test_time: Duration) -> Self {
+        self.test_time = Some(test_time);
+        self
+    }
+
+    /// Any other memtier_benchmark arguments
+    pub fn with_arg(mut self, arg: &str) -> Self {
+        self.extra_args.push(arg.to_string());
+        self
+    }
+
+    /// Run memtier_benchmark to completion
+    pub fn run(&self) -> Result<MemtierReport> {
+        if !is_tool_available("memtier_benchmark") {
+            bail!("memtier_benchmark not found in PATH");
+        }
+
+        let mut command = Command::new("memtier_benchmark");
+        command
+            .args(["--server", "127.0.0.1", "--port", &self.port.to_string()])
+            .args(["--protocol", &self.protocol])
+            .args(["--clients", &self.clients.to_string()])
+            .args(["--requests", &self.requests.to_string()])
+            .arg("--hide-histogram");
+        if let Some(test_time) = self.test_time {
+            command.args(["--test-time", &test_time.as_secs().max(1).to_string()]);
+        }
+        command.args(&self.extra_args);
+
+        let output = command
+            .output()
+            .context("Failed to run memtier_benchmark")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "memtier_benchmark failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let ops_per_sec = parse_throughput(&stdout);
+        Ok(MemtierReport {
+            stdout,
+            ops_per_sec,
+        })
+    }
+}
+
+/// Throughput of a memtier_benchmark summary ("Throughput: 12345.67 Ops/sec",
+/// or the Ops/sec column of the "Totals" row)
+pub fn parse_throughput(output: &str) -> Option<f64> {
+    for line in output.lines() {
+        if line.contains("Ops/sec") {
+            if let Some(start) = line.find(':') {
+                let rest = &line[start + 1..];
+                if let Some(end) = rest.find("Ops/sec") {
+                    if let Ok(throughput) = rest[..end].trim().parse() {
+                        return Some(throughput);
+                    }
+                }
+            }
+        }
+        if let Some(totals) = line.strip_prefix("Totals") {
+            if let Some(throughput) = totals.split_whitespace().next() {
+                return throughput.parse().ok();
+            }
+        }
+    }
+    None
+}
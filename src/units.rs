@@ -0,0 +1,66 @@
+//! Parsing of human-friendly CLI quantities (durations, counts, probabilities)
+
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+
+/// Parse a duration such as `500ms`, `30s`, `10m`, `2h` (bare numbers are seconds)
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.');
+    let (number, unit) = s.split_at(split.unwrap_or(s.len()));
+    let value: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("invalid duration: '{}'", s))?;
+
+    let secs = match unit {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => {
+            return Err(anyhow!(
+                "invalid duration unit in '{}'. Use ms, s, m or h",
+                s
+            ))
+        }
+    };
+
+    Ok(Duration::from_secs_f64(secs))
+}
+
+/// Parse a count such as `5000`, `100k`, `1.5M`
+pub fn parse_count(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let (number, multiplier) = match s.chars().last() {
+        Some('k') | Some('K') => (&s[..s.len() - 1], 1_000.0),
+        Some('m') | Some('M') => (&s[..s.len() - 1], 1_000_000.0),
+        Some('g') | Some('G') => (&s[..s.len() - 1], 1_000_000_000.0),
+        _ => (s, 1.0),
+    };
+    let value: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("invalid count: '{}'", s))?;
+    if value < 0.0 {
+        return Err(anyhow!("invalid count: '{}'", s));
+    }
+
+    Ok((value * multiplier).round() as u64)
+}
+
+/// Parse a probability in (0, 1], such as `0.01` or `1%`
+pub fn parse_probability(s: &str) -> Result<f64> {
+    let s = s.trim();
+    let value = match s.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+        None => s.parse::<f64>(),
+    }
+    .map_err(|_| anyhow!("invalid probability: '{}'", s))?;
+    if !(value > 0.0 && value <= 1.0) {
+        return Err(anyhow!(
+            "invalid probability: '{}'. Use a value in (0, 1]",
+            s
+        ));
+    }
+
+    Ok(value)
+}
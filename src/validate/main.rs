@@ -0,0 +1,393 @@
+//! Validate command: check every structure of a profile against the others
+
+use crate::profile::checkpoint::{read_record, Record};
+use crate::profile::checksum::{RecordChecksums, Trailer, CHECKSUM_VERSION, WIDE_LENGTH_VERSION};
+use crate::profile::compression::BLOCK_MAGIC;
+use crate::profile::index::{decode_index, IndexEntry, INDEX_VERSION};
+use crate::profile::mmap::MappedFile;
+use crate::profile::packed::EventDecoder;
+use crate::profile::{checksum, CommandType, Compression, KeySketch, ProfileMetadata};
+use anyhow::{anyhow, Result};
+use flate2::read::DeflateDecoder;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+
+/// Magic of the metadata of every profile
+const METADATA_MAGIC: u32 = 0xDEADBEEF;
+
+/// What a valid profile holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub version: u8,
+    pub compression: Compression,
+    pub events: u64,
+    pub checksum_records: u64,
+    /// Entries of the index, when the profile has one
+    pub index_entries: Option<u64>,
+}
+
+/// Where a record of the event region is
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    /// Offset in the file of an uncompressed profile
+    File(u64),
+    /// Offset in the inflated block at `block` in the file
+    Block { block: u64, at: usize },
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::File(at) => write!(f, "byte {}", at),
+            Location::Block { block, at } => {
+                write!(f, "byte {} of the block at byte {}", at, block)
+            }
+        }
+    }
+}
+
+/// What the walk over the event region found so far
+struct Walk {
+    decoder: EventDecoder,
+    checksums: RecordChecksums,
+    events: u64,
+    checksum_records: u64,
+    time_range: Option<(u64, u64)>,
+    latest: u64,
+    connections: HashSet<u16>,
+    commands: HashMap<CommandType, u64>,
+    keys: KeySketch,
+    /// Index entry of every run of records with an event, as the writer
+    /// would have written it
+    runs: Vec<IndexEntry>,
+    /// Offset in the file of the run being read, until its first event
+    run: Option<u64>,
+    /// Last record covered by a checksum, if any is not
+    unchecked: Option<Location>,
+}
+
+impl Walk {
+    fn new(metadata: &ProfileMetadata, start: u64) -> Self {
+        Walk {
+            decoder: EventDecoder::new(metadata.version),
+            checksums: RecordChecksums::new(metadata.compression),
+            events: 0,
+            checksum_records: 0,
+            time_range: None,
+            latest: 0,
+            connections: HashSet::new(),
+            commands: HashMap::new(),
+            keys: KeySketch::new(),
+            runs: Vec::new(),
+            run: Some(start),
+            unchecked: None,
+        }
+    }
+
+    fn record(&mut self, record: &Record, at: Location) -> Result<()> {
+        self.checksums.check(record)?;
+        match *record {
+            Record::Event(bytes) => {
+                let event = self.decoder.decode(bytes).map_err(|e| {
+                    anyhow!("event {} at {} does not decode: {}", self.events, at, e)
+                })?;
+                if let Some(offset) = self.run.take() {
+                    self.runs.push(IndexEntry {
+                        timestamp: self.latest,
+                        offset,
+                        events: self.events,
+                    });
+                }
+                let range = self
+                    .time_range
+                    .get_or_insert((event.timestamp, event.timestamp));
+                range.1 = event.timestamp;
+                self.latest = self.latest.max(event.timestamp);
+                self.connections.insert(event.conn_id);
+                *self.commands.entry(event.cmd_type).or_insert(0) += 1;
+                self.keys.insert(event.key_hash);
+                self.events += 1;
+                self.unchecked.get_or_insert(at);
+            }
+            Record::Checkpoint(bytes) => {
+                ProfileMetadata::decode(bytes)
+                    .map_err(|e| anyhow!("checkpoint at {} does not decode: {}", at, e))?;
+                self.unchecked.get_or_insert(at);
+            }
+            Record::Checksum(_) => {
+                self.decoder.restart();
+                self.checksum_records += 1;
+                self.unchecked = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Walk the records of `records`, the whole region or one inflated block
+    fn records(&mut self, records: &[u8], location: impl Fn(usize) -> Location) -> Result<()> {
+        let mut offset = 0;
+        while offset < records.len() {
+            let Some((record, next)) = read_record(records, offset) else {
+                return Err(anyhow!(
+                    "record at {} runs past the end of the {}",
+                    location(offset),
+                    match location(offset) {
+                        Location::File(_) => "event region",
+                        Location::Block { .. } => "block",
+                    }
+                ));
+            };
+            self.record(&record, location(offset))?;
+            // In an uncompressed profile, a run of records ends at its checksum
+            if let (Record::Checksum(_), Location::File(at)) = (record, location(next)) {
+                self.run = Some(at);
+            }
+            offset = next;
+        }
+        Ok(())
+    }
+}
+
+/// Check the profile at `path` from its trailer to its last event: the end
+/// marker, the metadata and its checksum, every record of the event region
+/// (length prefixes, checksums, decoding), the index and the counts of the
+/// metadata. The error describes the first inconsistency found.
+pub fn validate(path: &str) -> Result<ValidationReport> {
+    let data = MappedFile::open(path)?;
+    let file_len = data.len() as u64;
+
+    let tail = &data[data.len().saturating_sub(Trailer::MAX_LEN)..];
+    let trailer = Trailer::parse(tail).ok_or_else(|| {
+        anyhow!(
+            "no end marker in the last bytes of the file: the recording did not finish (run repair to recover it)"
+        )
+    })?;
+    let metadata_start = trailer.metadata_start(file_len)?;
+    let metadata_end = metadata_start + trailer.metadata_len;
+    let metadata_bytes = &data[metadata_start as usize..metadata_end as usize];
+    trailer.verify(metadata_bytes, metadata_start)?;
+    let metadata = ProfileMetadata::decode(metadata_bytes).map_err(|e| {
+        anyhow!(
+            "metadata at bytes {}..{} does not decode: {}",
+            metadata_start,
+            metadata_end,
+            e
+        )
+    })?;
+    if metadata.magic != METADATA_MAGIC {
+        return Err(anyhow!(
+            "metadata at byte {} has magic {:#010x}, expected {:#010x}",
+            metadata_start,
+            metadata.magic,
+            METADATA_MAGIC
+        ));
+    }
+    if metadata.version > WIDE_LENGTH_VERSION {
+        return Err(anyhow!(
+            "profile version {} is newer than this build reads (up to {})",
+            metadata.version,
+            WIDE_LENGTH_VERSION
+        ));
+    }
+    if (metadata.version >= WIDE_LENGTH_VERSION) != trailer.wide {
+        return Err(anyhow!(
+            "version {} profile whose trailer has a {}-bit metadata length",
+            metadata.version,
+            if trailer.wide { 32 } else { 16 }
+        ));
+    }
+    if metadata.version >= CHECKSUM_VERSION && trailer.metadata_crc.is_none() {
+        return Err(anyhow!(
+            "version {} profile whose trailer has no metadata checksum",
+            metadata.version
+        ));
+    }
+    if metadata.version >= INDEX_VERSION && metadata.index.is_none() {
+        return Err(anyhow!(
+            "version {} profile without an index",
+            metadata.version
+        ));
+    }
+
+    // Every record of the event region
+    let events_end = metadata.events_end(metadata_start);
+    if events_end > metadata_start {
+        return Err(anyhow!(
+            "the index starts at byte {}, after the metadata at byte {}",
+            events_end,
+            metadata_start
+        ));
+    }
+    let region = &data[..events_end as usize];
+    let walk = match metadata.compression {
+        Compression::None => {
+            let mut walk = Walk::new(&metadata, 0);
+            walk.records(region, |at| Location::File(at as u64))?;
+            walk
+        }
+        Compression::Deflate => walk_blocks(region, &metadata)?,
+    };
+    if metadata.version >= CHECKSUM_VERSION {
+        if let Some(at) = walk.unchecked {
+            return Err(anyhow!(
+                "records from {} on are not covered by a checksum",
+                at
+            ));
+        }
+    }
+
+    if let Some(index) = metadata.index {
+        let index_end = index.offset + index.len();
+        if index_end != metadata_start {
+            return Err(anyhow!(
+                "the index at bytes {}..{} does not end where the metadata starts, at byte {}",
+                index.offset,
+                index_end,
+                metadata_start
+            ));
+        }
+        let section = &data[index.offset as usize..index_end as usize];
+        if checksum::crc32c(section) != index.crc {
+            return Err(anyhow!(
+                "checksum mismatch in the index at bytes {}..{}: the profile is corrupt",
+                index.offset,
+                index_end
+            ));
+        }
+        let entries = decode_index(section)?;
+        for (i, (entry, run)) in entries.iter().zip(&walk.runs).enumerate() {
+            if entry != run {
+                return Err(anyhow!(
+                    "index entry {} points at byte {} after {} events (latest timestamp {}), but run {} starts at byte {} after {} events (latest timestamp {})",
+                    i, entry.offset, entry.events, entry.timestamp, i, run.offset, run.events, run.timestamp
+                ));
+            }
+        }
+        if entries.len() != walk.runs.len() {
+            return Err(anyhow!(
+                "the index has {} entries for {} runs of events",
+                entries.len(),
+                walk.runs.len()
+            ));
+        }
+    }
+
+    check_counts(&metadata, &walk)?;
+    Ok(ValidationReport {
+        version: metadata.version,
+        compression: metadata.compression,
+        events: walk.events,
+        checksum_records: walk.checksum_records,
+        index_entries: metadata.index.map(|index| index.entries),
+    })
+}
+
+/// Walk the blocks of a compressed event region, each a run of records
+fn walk_blocks(region: &[u8], metadata: &ProfileMetadata) -> Result<Walk> {
+    if !region.starts_with(&BLOCK_MAGIC) {
+        return Err(anyhow!(
+            "compressed profile whose event region does not start with the block header"
+        ));
+    }
+    let mut walk = Walk::new(metadata, BLOCK_MAGIC.len() as u64);
+    let mut offset = BLOCK_MAGIC.len();
+    let mut block = Vec::new();
+    while offset < region.len() {
+        let compressed = region
+            .get(offset..offset + 4)
+            .map(|len| u32::from_le_bytes(len.try_into().expect("4 bytes")) as usize)
+            .and_then(|len| region.get(offset + 4..offset + 4 + len))
+            .ok_or_else(|| {
+                anyhow!(
+                    "block at byte {} runs past the end of the event region, at byte {}",
+                    offset,
+                    region.len()
+                )
+            })?;
+        block.clear();
+        DeflateDecoder::new(compressed)
+            .read_to_end(&mut block)
+            .map_err(|e| anyhow!("block at byte {} does not inflate: {}", offset, e))?;
+
+        walk.run = Some(offset as u64);
+        let at = offset as u64;
+        walk.records(&block, |offset| Location::Block {
+            block: at,
+            at: offset,
+        })?;
+        offset += 4 + compressed.len();
+    }
+    Ok(walk)
+}
+
+/// Check the counts of the metadata against the events walked
+fn check_counts(metadata: &ProfileMetadata, walk: &Walk) -> Result<()> {
+    if metadata.total_events != walk.events {
+        return Err(anyhow!(
+            "the metadata counts {} events, the event region holds {}",
+            metadata.total_events,
+            walk.events
+        ));
+    }
+    let time_range = walk.time_range.unwrap_or((0, 0));
+    if metadata.time_range != time_range {
+        return Err(anyhow!(
+            "the metadata gives the time range {}..{}, the events span {}..{}",
+            metadata.time_range.0,
+            metadata.time_range.1,
+            time_range.0,
+            time_range.1
+        ));
+    }
+    if metadata.unique_connections as usize != walk.connections.len() {
+        return Err(anyhow!(
+            "the metadata counts {} connections, the events have {}",
+            metadata.unique_connections,
+            walk.connections.len()
+        ));
+    }
+    for cmd_type in CommandType::ALL {
+        let expected = metadata.command_distribution.get(&cmd_type).copied();
+        let actual = walk.commands.get(&cmd_type).copied();
+        if expected.unwrap_or(0) != actual.unwrap_or(0) {
+            return Err(anyhow!(
+                "the metadata counts {} {} commands, the events have {}",
+                expected.unwrap_or(0),
+                cmd_type.name(),
+                actual.unwrap_or(0)
+            ));
+        }
+    }
+    if let Some(sketch) = &metadata.key_sketch {
+        if *sketch != walk.keys {
+            return Err(anyhow!(
+                "the key sketch of the metadata (about {} keys) does not match the events (about {} keys)",
+                sketch.estimate(),
+                walk.keys.estimate()
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn run(path: &str) -> Result<()> {
+    let report = validate(path)?;
+
+    println!(
+        "{}: valid version {} profile ({:?}), {} events, {} checksum records{}",
+        path,
+        report.version,
+        report.compression,
+        report.events,
+        report.checksum_records,
+        report
+            .index_entries
+            .map_or(String::new(), |entries| format!(
+                ", index of {} entries",
+                entries
+            ))
+    );
+
+    Ok(())
+}
@@ -0,0 +1,5 @@
+//! Validate command implementation
+
+pub mod main;
+
+pub use main::{run as run_validate, validate, ValidationReport};
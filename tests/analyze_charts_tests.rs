@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use membench::analyze::charts::{bar, bar_chart, size_ranges, sparkline};
+
+    #[test]
+    fn test_bars_are_drawn_in_eighths() {
+        assert_eq!(bar(1.0, 4), "████");
+        assert_eq!(bar(0.5, 4), "██");
+        assert_eq!(bar(1.0 / 32.0, 4), "▏");
+        assert_eq!(bar(0.0, 4), "");
+        assert_eq!(bar(2.0, 4), "████");
+    }
+
+    #[test]
+    fn test_bar_chart_fits_the_width() {
+        let rows = vec![
+            ("10 bytes".to_string(), 300),
+            ("200 bytes".to_string(), 100),
+        ];
+        let lines = bar_chart(&rows, 60);
+        assert_eq!(lines.len(), 2);
+        // Shares under 100% are a column short
+        for line in &lines {
+            assert_eq!(line.chars().count(), 59, "{}", line);
+        }
+        assert!(lines[0].starts_with("   10 bytes │████"));
+        assert!(lines[0].ends_with("300 (75.0%)"));
+        assert!(lines[1].ends_with("100 (25.0%)"));
+    }
+
+    #[test]
+    fn test_sparkline_keeps_bursts_when_merging() {
+        assert_eq!(sparkline(&[0.0, 1.0, 2.0, 4.0, 8.0], 10), " ▁▂▄█");
+        // Ten values to five characters: the burst is kept
+        let values = [1.0, 1.0, 1.0, 1.0, 8.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+        let line = sparkline(&values, 5);
+        assert_eq!(line.chars().count(), 5);
+        assert_eq!(line, "▁▁█▁▁");
+        assert_eq!(sparkline(&[0.0, 0.0], 10), "  ");
+    }
+
+    #[test]
+    fn test_sizes_group_by_power_of_two_past_the_rows() {
+        let sizes = [(10, 5), (3, 1), (0, 2)];
+        assert_eq!(
+            size_ranges(&sizes, 16),
+            vec![(0, 0, 2), (3, 3, 1), (10, 10, 5)]
+        );
+
+        let sizes: Vec<(u32, u64)> = (1..=100).map(|size| (size, 1)).collect();
+        let ranges = size_ranges(&sizes, 16);
+        assert_eq!(ranges[0], (1, 1, 1));
+        assert_eq!(ranges[1], (2, 2, 1));
+        assert_eq!(ranges[2], (3, 4, 2));
+        assert_eq!(ranges.last(), Some(&(65, 128, 36)));
+        assert_eq!(ranges.iter().map(|r| r.2).sum::<u64>(), 100);
+    }
+}
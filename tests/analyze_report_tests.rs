@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod tests {
+    use membench::analyze::{AnalyzeOptions, AnalyzeReport, ReportFormat};
+    use membench::profile::{CommandType, Event, Response, ResponseStatus};
+    use membench::record::ProfileWriter;
+    use membench::replay::{DistributionAnalyzer, ProfileReader};
+    use std::num::NonZero;
+    use std::time::Duration;
+    use tempfile::NamedTempFile;
+
+    fn event(i: u64) -> Event {
+        let cmd_type = if i.is_multiple_of(4) {
+            CommandType::Set
+        } else {
+            CommandType::Get
+        };
+        Event {
+            value_size: if cmd_type == CommandType::Set {
+                NonZero::new(100)
+            } else {
+                None
+            },
+            response: Some(Response {
+                status: if i.is_multiple_of(5) {
+                    ResponseStatus::Miss
+                } else {
+                    ResponseStatus::Hit
+                },
+                value_size: None,
+            }),
+            latency: Some(100 + (i % 10) as u32),
+            ..Event::new(1_000_000 + i * 1000, (i % 3) as u16, cmd_type, i % 50, 10)
+        }
+    }
+
+    fn report(options: &AnalyzeOptions) -> AnalyzeReport {
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+        let mut writer = ProfileWriter::new(path).unwrap();
+        for i in 0..2000 {
+            writer.write_event(&event(i)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let reader = ProfileReader::new(path).unwrap();
+        let analysis = DistributionAnalyzer::analyze(reader.events());
+        AnalyzeReport::new(path, reader.metadata(), reader.events(), analysis, options).unwrap()
+    }
+
+    #[test]
+    fn test_output_formats_parse() {
+        assert_eq!("json".parse(), Ok(ReportFormat::Json));
+        assert_eq!("Text".parse(), Ok(ReportFormat::Text));
+        assert!("yaml"
+            .parse::<ReportFormat>()
+            .unwrap_err()
+            .contains("not supported"));
+        assert!("xml".parse::<ReportFormat>().is_err());
+    }
+
+    #[test]
+    fn test_report_holds_every_section() {
+        let report = report(&AnalyzeOptions {
+            interval: Some(Duration::from_millis(500)),
+            ..AnalyzeOptions::default()
+        });
+        assert_eq!(report.analysis.total_events, 2000);
+        assert_eq!(report.unique_keys, 50);
+        let responses = report.responses.unwrap();
+        assert_eq!(responses.answered, 2000);
+        assert_eq!(responses.gets_answered, 1500);
+        assert_eq!(responses.get_hits, 1200);
+        assert_eq!(responses.get_hit_rate, Some(0.8));
+        let latency = report.latency.unwrap();
+        assert_eq!(latency.overall.answered, 2000);
+        assert_eq!(latency.overall.max, 109);
+        assert_eq!(latency.per_command.len(), 2);
+        assert_eq!(report.throughput.buckets.len(), 4);
+        // Optional sections stay out unless asked for
+        assert!(report.miss_ratio_curve.is_none());
+        assert!(report.connections.is_none());
+    }
+
+    #[test]
+    fn test_json_report() {
+        let report = report(&AnalyzeOptions {
+            mrc: Some(1.0),
+            per_connection: true,
+            ..AnalyzeOptions::default()
+        });
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+
+        assert_eq!(json["metadata"]["total_events"], 2000);
+        assert_eq!(json["metadata"]["estimated_unique_keys"], 50);
+        assert!(json["metadata"].get("key_sketch").is_none());
+        assert_eq!(json["analysis"]["command_distribution"]["Get"], 1500);
+        assert!(json["analysis"].get("key_frequencies").is_none());
+        assert_eq!(json["responses"]["get_hit_rate"], 0.8);
+        assert_eq!(json["throughput"]["interval_micros"], 1_000_000);
+        assert_eq!(json["throughput"]["buckets"][0]["per_command"]["Set"], 250);
+        assert_eq!(json["reuse"]["cold_accesses"], 50);
+        assert_eq!(json["connections"].as_array().unwrap().len(), 3);
+        let points = json["miss_ratio_curve"]["points"].as_array().unwrap();
+        assert_eq!(points.len(), 12);
+        assert!(points[11]["hit_ratio"].as_f64().unwrap() > 0.9);
+    }
+
+    #[test]
+    fn test_synthetic_spec_parses_back() {
+        use membench::replay::SyntheticSpec;
+
+        let report = report(&AnalyzeOptions::default());
+        let spec = report.synthetic_spec(3).unwrap();
+        assert_eq!(spec.connections, 3);
+        assert_eq!(spec.command_weights.get(&CommandType::Get), Some(&1500));
+        assert_eq!(spec.command_weights.get(&CommandType::Set), Some(&500));
+        assert_eq!(spec.key_sizes, vec![10]);
+        assert_eq!(spec.value_sizes, vec![100]);
+        assert_eq!(spec.keyspace, Some(50));
+        assert!(spec.interarrival_us.is_some());
+
+        let printed = spec.to_string();
+        assert!(printed.contains("get = 1500\nset = 500\n"));
+        assert_eq!(SyntheticSpec::parse(&printed).unwrap(), spec);
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use membench::record::Anonymizer;
+    use membench::record::{read_key_file, Anonymizer, HashAlgorithm};
 
     #[test]
     fn test_hash_deterministic() {
@@ -38,4 +38,84 @@ mod tests {
             "different salts should produce different hashes"
         );
     }
+
+    #[test]
+    fn test_hash_segments_keeps_namespaces() {
+        let plain = Anonymizer::new(12345);
+        assert_eq!(plain.hash_segments(b"user:1234:profile"), None);
+
+        let anon = Anonymizer::new(12345).with_delimiter(b':');
+        let profile = anon.hash_segments(b"user:1234:profile").unwrap();
+        let session = anon.hash_segments(b"user:5678:session").unwrap();
+        assert_eq!(profile.len(), 3);
+        assert_eq!(profile[0], session[0], "shared namespace should hash alike");
+        assert_ne!(profile[1], session[1]);
+        assert_eq!(profile[0], anon.hash_key(b"user") as u32);
+        // The whole-key hash is unaffected by the delimiter
+        assert_eq!(
+            anon.hash_key(b"user:1234:profile"),
+            plain.hash_key(b"user:1234:profile")
+        );
+
+        let other_salt = Anonymizer::new(54321).with_delimiter(b':');
+        assert_ne!(
+            other_salt.hash_segments(b"user:1234:profile").unwrap(),
+            profile
+        );
+        assert_eq!(anon.hash_segments(b"flat").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_hash_algorithms() {
+        let algorithms = [
+            HashAlgorithm::SipHash13,
+            HashAlgorithm::HmacSha256,
+            HashAlgorithm::Blake3,
+        ];
+        for algorithm in algorithms {
+            assert_eq!(
+                algorithm.to_string().parse::<HashAlgorithm>(),
+                Ok(algorithm)
+            );
+        }
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+
+        // SipHash-1-3 with a salt keeps the hashes of earlier recordings
+        assert_eq!(
+            Anonymizer::with_salt(HashAlgorithm::SipHash13, 12345).hash_key(b"testkey"),
+            Anonymizer::new(12345).hash_key(b"testkey")
+        );
+
+        let hashes: Vec<u64> = algorithms
+            .iter()
+            .map(|&algorithm| Anonymizer::with_key(algorithm, b"secret").hash_key(b"testkey"))
+            .collect();
+        assert_ne!(hashes[0], hashes[1]);
+        assert_ne!(hashes[1], hashes[2]);
+        for (&algorithm, &hash) in algorithms.iter().zip(&hashes) {
+            let same_key = Anonymizer::with_key(algorithm, b"secret");
+            assert_eq!(same_key.hash_key(b"testkey"), hash);
+            let other_key = Anonymizer::with_key(algorithm, b"other");
+            assert_ne!(other_key.hash_key(b"testkey"), hash);
+        }
+    }
+
+    #[test]
+    fn test_read_key_file_requires_private_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("anonymizer.key");
+        std::fs::write(&path, b"0123456789abcdef").unwrap();
+        let path_str = path.to_str().unwrap();
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let err = read_key_file(path_str).unwrap_err();
+        assert!(err.to_string().contains("chmod 600"), "{}", err);
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(read_key_file(path_str).unwrap(), b"0123456789abcdef");
+
+        assert!(read_key_file(dir.path().join("missing").to_str().unwrap()).is_err());
+    }
 }
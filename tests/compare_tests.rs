@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod tests {
+    use membench::compare::compare;
+    use membench::profile::{CommandType, Event, Response, ResponseStatus};
+    use membench::record::ProfileWriter;
+    use std::num::NonZero;
+    use tempfile::NamedTempFile;
+
+    fn event(timestamp: u64, cmd_type: CommandType, key_hash: u64, hit: bool) -> Event {
+        Event {
+            value_size: if cmd_type == CommandType::Set {
+                NonZero::new(100)
+            } else {
+                None
+            },
+            response: (cmd_type == CommandType::Get).then_some(Response {
+                status: if hit {
+                    ResponseStatus::Hit
+                } else {
+                    ResponseStatus::Miss
+                },
+                value_size: None,
+            }),
+            ..Event::new(timestamp, 1, cmd_type, key_hash, 10)
+        }
+    }
+
+    fn write_profile(events: &[Event]) -> NamedTempFile {
+        let temp = NamedTempFile::new().unwrap();
+        let mut writer = ProfileWriter::new(temp.path().to_str().unwrap()).unwrap();
+        for event in events {
+            writer.write_event(event).unwrap();
+        }
+        writer.finish().unwrap();
+        temp
+    }
+
+    /// 1000 events over 10 seconds on keys `keys`, a `sets` share of them
+    /// sets, with one GET in `hit_every` hitting
+    fn traffic(keys: std::ops::Range<u64>, sets: u64, hit_every: u64) -> Vec<Event> {
+        (0..1000)
+            .map(|i| {
+                let cmd_type = if i % 10 < sets {
+                    CommandType::Set
+                } else {
+                    CommandType::Get
+                };
+                let key = keys.start + i % (keys.end - keys.start);
+                event(1_000_000 + i * 10_000, cmd_type, key, i % hit_every == 0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_profile_compared_to_itself() {
+        let a = write_profile(&traffic(0..100, 2, 2));
+        let path = a.path().to_str().unwrap();
+        let report = compare(path, path).unwrap();
+
+        assert_eq!(report.profiles[0].events, 1000);
+        assert_eq!(report.command_distance, 0.0);
+        assert_eq!(report.key_sizes.distance, 0.0);
+        assert_eq!(report.value_sizes.a, report.value_sizes.b);
+        assert_eq!(report.keys.jaccard, 1.0);
+        assert_eq!(report.keys.shared_traffic_b, 1.0);
+        assert_eq!(report.throughput.a, report.throughput.b);
+        assert_eq!(
+            report.profiles[0].get_hit_rate,
+            report.profiles[1].get_hit_rate
+        );
+    }
+
+    #[test]
+    fn test_differences_are_reported() {
+        let a = write_profile(&traffic(0..100, 2, 2));
+        let b = write_profile(&traffic(50..200, 5, 4));
+        let report = compare(a.path().to_str().unwrap(), b.path().to_str().unwrap()).unwrap();
+
+        let set = report
+            .commands
+            .iter()
+            .find(|share| share.cmd_type == CommandType::Set)
+            .unwrap();
+        assert_eq!((set.a, set.b), (0.2, 0.5));
+        assert!((report.command_distance - 0.3).abs() < 1e-9);
+
+        // Keys 50..100 are shared out of 0..200
+        assert_eq!(
+            (report.keys.keys_a, report.keys.keys_b, report.keys.shared),
+            (100, 150, 50)
+        );
+        assert_eq!(report.keys.jaccard, 0.25);
+        assert!((report.keys.shared_traffic_a - 0.5).abs() < 1e-9);
+
+        let (hits_a, hits_b) = (
+            report.profiles[0].get_hit_rate.unwrap(),
+            report.profiles[1].get_hit_rate.unwrap(),
+        );
+        assert!(hits_a > hits_b, "{} vs {}", hits_a, hits_b);
+
+        let value_sizes = (report.value_sizes.a.unwrap(), report.value_sizes.b.unwrap());
+        assert_eq!((value_sizes.0.p50, value_sizes.1.p99), (100, 100));
+        assert_eq!(report.throughput.a.mean, 100.0);
+    }
+}
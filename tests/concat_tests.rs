@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+    use membench::concat::concat;
+    use membench::profile::{CommandType, Compression, Event, Protocol, SampleRate};
+    use membench::record::ProfileWriter;
+    use membench::replay::ProfileReader;
+    use std::time::Duration;
+
+    fn event(timestamp: u64, conn_id: u16, key_hash: u64) -> Event {
+        Event::new(timestamp, conn_id, CommandType::Get, key_hash, 10)
+    }
+
+    /// `count` events from `start` on, every 10us, on connections 1 and 2
+    fn write_capture(path: &str, start: u64, count: u64, protocol: Protocol) {
+        let mut writer = ProfileWriter::new(path).unwrap();
+        writer.record_protocol(1, protocol);
+        for i in 0..count {
+            writer
+                .write_event(&event(start + i * 10, 1 + (i % 2) as u16, i))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_concat_rebases_each_profile_after_the_last() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let (a, b, out) = (path("a.profile"), path("b.profile"), path("out.profile"));
+        write_capture(&a, 5_000, 10, Protocol::Binary);
+        // Captured earlier than the first: only the order given matters
+        write_capture(&b, 1_000, 5, Protocol::Ascii);
+
+        let inputs = [a.clone(), b.clone(), a.clone()];
+        let report = concat(
+            &inputs,
+            &out,
+            Duration::from_micros(100),
+            Compression::Deflate,
+        )
+        .unwrap();
+        assert_eq!(report.events, 25);
+        assert_eq!(report.time_range, (5_000, 5_090 + 100 + 40 + 100 + 90));
+
+        let reader = ProfileReader::new(&out).unwrap();
+        let timestamps: Vec<u64> = reader.events().iter().map(|e| e.timestamp).collect();
+        assert_eq!(
+            &timestamps[..11],
+            &[5_000, 5_010, 5_020, 5_030, 5_040, 5_050, 5_060, 5_070, 5_080, 5_090, 5_190]
+        );
+        assert_eq!(timestamps[14], 5_230);
+        assert_eq!(timestamps[15], 5_330);
+        assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let metadata = reader.metadata();
+        assert_eq!(metadata.total_events, 25);
+        assert_eq!(metadata.unique_connections, 2);
+        assert_eq!(metadata.time_range, report.time_range);
+        assert_eq!(metadata.compression, Compression::Deflate);
+        // Connections keep the protocol they were first seen with
+        assert_eq!(
+            metadata.connection_protocols.get(&1),
+            Some(&Protocol::Binary)
+        );
+    }
+
+    #[test]
+    fn test_concat_refuses_different_sampling() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let (a, b, out) = (path("a.profile"), path("b.profile"), path("out.profile"));
+        write_capture(&a, 0, 4, Protocol::Meta);
+        let mut writer = ProfileWriter::new(&b).unwrap();
+        writer.set_connection_sampling(SampleRate {
+            numerator: 1,
+            denominator: 4,
+        });
+        writer.write_event(&event(0, 1, 0)).unwrap();
+        writer.finish().unwrap();
+
+        let err = concat(&[a.clone(), b], &out, Duration::ZERO, Compression::None)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("connection sampling"), "{}", err);
+        assert!(concat(
+            std::slice::from_ref(&a),
+            &out,
+            Duration::ZERO,
+            Compression::None
+        )
+        .is_err());
+        assert!(concat(
+            &[a.clone(), a.clone()],
+            &a,
+            Duration::ZERO,
+            Compression::None
+        )
+        .is_err());
+    }
+}
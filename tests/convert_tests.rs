@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use membench::convert::{convert, TableFormat};
+    use membench::profile::{CommandType, Event, Flags, Response, ResponseStatus};
+    use membench::record::ProfileWriter;
+    use std::num::NonZero;
+
+    fn event(i: u64) -> Event {
+        Event {
+            response: Some(Response {
+                status: ResponseStatus::Miss,
+                value_size: None,
+            }),
+            latency: Some(80),
+            ..Event::new(1_000_000 + i, 3, CommandType::Get, 0xabcd + i, 6)
+        }
+    }
+
+    #[test]
+    fn test_table_format_follows_the_extension() {
+        assert_eq!(TableFormat::of_path("out.csv"), Ok(TableFormat::Csv));
+        assert_eq!(TableFormat::of_path("OUT.CSV"), Ok(TableFormat::Csv));
+        assert_eq!(
+            TableFormat::of_path("out.xlsx"),
+            Err("Invalid table format: 'xlsx'. Use 'csv'".to_string())
+        );
+        assert!(TableFormat::of_path("out").is_err());
+    }
+
+    #[test]
+    fn test_profile_converts_to_csv_with_json_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("capture.profile");
+        let output = dir.path().join("capture.csv");
+        let input = input.to_str().unwrap();
+        let output = output.to_str().unwrap();
+
+        let mut writer = ProfileWriter::new(input).unwrap();
+        writer.write_event(&event(0)).unwrap();
+        let mut set = Event {
+            cmd_type: CommandType::Set,
+            value_size: NonZero::new(512),
+            ttl: NonZero::new(60),
+            flags: Flags::empty().with_quiet(),
+            key: Some(b"user:\"1\",x".to_vec()),
+            ..event(1)
+        };
+        set.response = None;
+        writer.write_event(&set).unwrap();
+        writer.finish().unwrap();
+
+        let report = convert(input, output, TableFormat::Csv).unwrap();
+        assert_eq!(report.events, 2);
+
+        let table = std::fs::read_to_string(output).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "timestamp,conn_id,command,key_hash,key_size,value_size,response_status,response_value_size,latency_us,ttl,server_port,client_id,key_segments,flags,key",
+                "1000000,3,get,0x000000000000abcd,6,,miss,,80,,,,,,",
+                "1000001,3,set,0x000000000000abce,6,512,,,80,60,,,,quiet,\"user:\"\"1\"\",x\"",
+            ]
+        );
+
+        let metadata: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report.metadata_path).unwrap()).unwrap();
+        assert_eq!(report.metadata_path, format!("{}.json", output));
+        assert_eq!(metadata["total_events"], 2);
+        assert_eq!(metadata["time_range"][1], 1_000_001);
+        assert_eq!(metadata["command_distribution"]["Set"], 1);
+        assert_eq!(metadata["estimated_unique_keys"], 2);
+        assert!(metadata.get("key_sketch").is_none());
+    }
+}
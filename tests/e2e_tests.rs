@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
     use membench::{
-        profile::{CommandType, Event, Flags},
+        profile::{CommandType, Event},
         record::ProfileWriter,
         replay::{DistributionAnalyzer, ProfileReader},
     };
@@ -20,21 +20,22 @@ mod tests {
 
         for i in 0..100 {
             let event = Event {
-                timestamp: 1000 + i as u64,
-                conn_id: i % 4,
-                cmd_type: if i % 5 == 0 {
-                    CommandType::Set
-                } else {
-                    CommandType::Get
-                },
-                key_hash: (i as u64).wrapping_mul(0x123456789),
-                key_size: 10 + (i % 20) as u32,
                 value_size: if i % 5 == 0 {
                     std::num::NonZero::new(100 + (i % 200) as u32)
                 } else {
                     None
                 },
-                flags: Flags::empty(),
+                ..Event::new(
+                    1000 + i as u64,
+                    i % 4,
+                    if i % 5 == 0 {
+                        CommandType::Set
+                    } else {
+                        CommandType::Get
+                    },
+                    (i as u64).wrapping_mul(0x123456789),
+                    10 + (i % 20) as u32,
+                )
             };
             writer.write_event(&event).unwrap();
         }
@@ -86,23 +87,10 @@ mod tests {
 
         // Create events with specific values
         let original_events = vec![
+            Event::new(12345, 7, CommandType::Get, 0xdeadbeef, 42),
             Event {
-                timestamp: 12345,
-                conn_id: 7,
-                cmd_type: CommandType::Get,
-                key_hash: 0xdeadbeef,
-                key_size: 42,
-                value_size: None,
-                flags: Flags::empty(),
-            },
-            Event {
-                timestamp: 54321,
-                conn_id: 3,
-                cmd_type: CommandType::Set,
-                key_hash: 0xcafebabe,
-                key_size: 16,
                 value_size: std::num::NonZero::new(256),
-                flags: Flags::empty(),
+                ..Event::new(54321, 3, CommandType::Set, 0xcafebabe, 16)
             },
         ];
 
@@ -143,22 +131,23 @@ mod tests {
 
         for i in 0..event_count {
             let event = Event {
-                timestamp: (i as u64).wrapping_mul(1234567),
-                conn_id: (i % 32) as u16,
-                cmd_type: match i % 6 {
-                    0 => CommandType::Get,
-                    1 => CommandType::Set,
-                    2 => CommandType::Delete,
-                    _ => CommandType::Noop,
-                },
-                key_hash: (i as u64).wrapping_mul(987654321),
-                key_size: (i % 256) as u32,
                 value_size: if i % 2 == 0 {
                     std::num::NonZero::new((i as u32).wrapping_mul(256))
                 } else {
                     None
                 },
-                flags: Flags::empty(),
+                ..Event::new(
+                    (i as u64).wrapping_mul(1234567),
+                    (i % 32) as u16,
+                    match i % 6 {
+                        0 => CommandType::Get,
+                        1 => CommandType::Set,
+                        2 => CommandType::Delete,
+                        _ => CommandType::Noop,
+                    },
+                    (i as u64).wrapping_mul(987654321),
+                    (i % 256) as u32,
+                )
             };
             writer.write_event(&event).unwrap();
         }
@@ -0,0 +1,135 @@
+#[cfg(test)]
+mod tests {
+    use membench::filter::{filter, CommandSet, ConnIds, EventFilter, KeyHashes, SizeRange};
+    use membench::profile::{
+        CommandType, Compression, Event, Protocol, Response, ResponseStatus, TimeRange,
+    };
+    use membench::record::ProfileWriter;
+    use membench::replay::ProfileReader;
+    use std::num::NonZero;
+
+    /// Event `i` of a capture: a SET of `i` bytes every fourth event, GET hits
+    /// of `2 * i` bytes otherwise, one every second over connections 0 to 4
+    fn event(i: u64) -> Event {
+        let set = i.is_multiple_of(4);
+        Event {
+            value_size: NonZero::new(i as u32).filter(|_| set),
+            response: (!set).then(|| Response {
+                status: ResponseStatus::Hit,
+                value_size: NonZero::new(2 * i as u32),
+            }),
+            ..Event::new(
+                1_000_000 + i * 1_000_000,
+                (i % 5) as u16,
+                if set {
+                    CommandType::Set
+                } else {
+                    CommandType::Get
+                },
+                i % 10,
+                10,
+            )
+        }
+    }
+
+    #[test]
+    fn test_predicates_parse() {
+        let commands: CommandSet = "get, SET".parse().unwrap();
+        assert_eq!(commands.0.len(), 2);
+        assert!("get,fetch".parse::<CommandSet>().is_err());
+
+        let conn_ids: ConnIds = "0,10-20".parse().unwrap();
+        assert!(conn_ids.contains(0) && conn_ids.contains(15) && !conn_ids.contains(21));
+        assert!("20-10".parse::<ConnIds>().is_err());
+
+        let hashes: KeyHashes = "17,0xff".parse().unwrap();
+        assert!(hashes.0.contains(&17) && hashes.0.contains(&255));
+        assert!("0xzz".parse::<KeyHashes>().is_err());
+
+        assert_eq!(
+            "1k-64k".parse(),
+            Ok(SizeRange {
+                min: 1000,
+                max: 64_000
+            })
+        );
+        assert_eq!(
+            "100-".parse(),
+            Ok(SizeRange {
+                min: 100,
+                max: u32::MAX
+            })
+        );
+        assert_eq!("-512".parse(), Ok(SizeRange { min: 0, max: 512 }));
+        assert!("512".parse::<SizeRange>().is_err());
+        assert!("9-1".parse::<SizeRange>().is_err());
+    }
+
+    #[test]
+    fn test_filter_keeps_matching_events_with_recomputed_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("day.profile");
+        let input = input.to_str().unwrap();
+        let output = dir.path().join("incident.profile");
+        let output = output.to_str().unwrap();
+
+        let mut writer = ProfileWriter::with_compression(input, Compression::Deflate).unwrap();
+        for conn_id in 0..5 {
+            writer.record_protocol(conn_id, Protocol::Meta);
+        }
+        for i in 0..200 {
+            writer.write_event(&event(i)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let event_filter = EventFilter {
+            range: TimeRange {
+                from: Some("50s".parse().unwrap()),
+                to: Some("149s".parse().unwrap()),
+            },
+            commands: Some("get".parse().unwrap()),
+            conn_ids: Some("1-2".parse().unwrap()),
+            key_hashes: None,
+            value_size: Some("-200".parse().unwrap()),
+        };
+        let report = filter(input, output, &event_filter).unwrap();
+        assert_eq!(report.events_read, 200);
+
+        let expected: Vec<u64> = (50..150u64)
+            .filter(|&i| !i.is_multiple_of(4) && [1, 2].contains(&(i % 5)) && 2 * i <= 200)
+            .collect();
+        assert_eq!(report.events_kept, expected.len() as u64);
+
+        let reader = ProfileReader::new(output).unwrap();
+        let timestamps: Vec<u64> = reader.events().iter().map(|e| e.timestamp).collect();
+        let expected_timestamps: Vec<u64> = expected.iter().map(|&i| event(i).timestamp).collect();
+        assert_eq!(timestamps, expected_timestamps);
+
+        let metadata = reader.metadata();
+        assert_eq!(metadata.total_events, expected.len() as u64);
+        assert_eq!(metadata.unique_connections, 2);
+        assert_eq!(
+            metadata.command_distribution.get(&CommandType::Get),
+            Some(&(expected.len() as u64))
+        );
+        assert_eq!(metadata.command_distribution.get(&CommandType::Set), None);
+        assert_eq!(
+            metadata.time_range,
+            (
+                *expected_timestamps.first().unwrap(),
+                *expected_timestamps.last().unwrap()
+            )
+        );
+        assert_eq!(metadata.connection_protocols.len(), 2);
+        assert_eq!(metadata.compression, Compression::Deflate);
+
+        // Key hashes alone
+        let event_filter = EventFilter {
+            key_hashes: Some("3,0x7".parse().unwrap()),
+            ..EventFilter::default()
+        };
+        let report = filter(input, output, &event_filter).unwrap();
+        assert_eq!(report.events_kept, 40);
+        assert!(filter(input, input, &event_filter).is_err());
+    }
+}
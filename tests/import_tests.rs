@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod tests {
+    use membench::import::{import, TraceFormat};
+    use membench::inspect::{format_event, Format};
+    use membench::profile::{
+        CommandType, Compression, Event, Flags, Protocol, Response, ResponseStatus,
+    };
+    use membench::record::Anonymizer;
+    use membench::replay::ProfileReader;
+    use std::num::NonZero;
+
+    const TRACE: &str = "\
+0,key-a,10,0,7,get,0
+0,key-b,12,300,7,set,3600
+0,key-a,10,0,8,gets,0
+1,key-b,12,0,8,delete,0
+3,key-c,9,50,7,add,0
+";
+
+    #[test]
+    fn test_twitter_trace_imports_as_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("cluster.csv");
+        let output = dir.path().join("cluster.profile");
+        std::fs::write(&input, TRACE).unwrap();
+        let input = input.to_str().unwrap();
+        let output = output.to_str().unwrap();
+
+        let anonymizer = Anonymizer::new(42);
+        let report = import(
+            input,
+            output,
+            TraceFormat::TwitterCache,
+            &anonymizer,
+            Compression::Deflate,
+        )
+        .unwrap();
+        assert_eq!(report.events, 5);
+        assert_eq!(report.connections, 2);
+
+        let reader = ProfileReader::new(output).unwrap();
+        let events = reader.events();
+        let commands: Vec<CommandType> = events.iter().map(|e| e.cmd_type).collect();
+        assert_eq!(
+            commands,
+            [
+                CommandType::Get,
+                CommandType::Set,
+                CommandType::Get,
+                CommandType::Delete,
+                CommandType::Add
+            ]
+        );
+        // The three requests of the first second are spread over it
+        let timestamps: Vec<u64> = events.iter().map(|e| e.timestamp).collect();
+        assert_eq!(timestamps, [0, 333_333, 666_666, 1_000_000, 3_000_000]);
+
+        assert_eq!(events[0].key_hash, anonymizer.hash_key(b"key-a"));
+        assert_eq!(events[0].key_hash, events[2].key_hash);
+        assert_eq!(events[0].conn_id, events[1].conn_id);
+        assert_ne!(events[0].conn_id, events[2].conn_id);
+        assert_eq!(events[1].value_size.unwrap().get(), 300);
+        assert_eq!(events[1].ttl.unwrap().get(), 3600);
+        assert!(events[0].value_size.is_none() && events[0].ttl.is_none());
+        assert_eq!(events[0].client_id, Some(anonymizer.hash_key(b"7") as u32));
+
+        let metadata = reader.metadata();
+        assert_eq!(metadata.unique_connections, 2);
+        assert_eq!(
+            metadata.connection_protocols.get(&events[2].conn_id),
+            Some(&Protocol::Ascii)
+        );
+    }
+
+    #[test]
+    fn test_jsonl_dump_imports_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("events.jsonl");
+        let output = dir.path().join("events.profile");
+
+        let events = [
+            Event {
+                flags: Flags::empty().with_quiet(),
+                value_size: NonZero::new(512),
+                ttl: NonZero::new(60),
+                server_port: NonZero::new(11211),
+                key_segments: Some(vec![1, 2]),
+                client_id: Some(9),
+                ..Event::new(1_700_000_000_000_000, 3, CommandType::Set, u64::MAX - 1, 6)
+            },
+            Event {
+                response: Some(Response {
+                    status: ResponseStatus::Hit,
+                    value_size: NonZero::new(512),
+                }),
+                latency: Some(120),
+                ..Event::new(1_700_000_000_000_250, 4, CommandType::Get, 0xabcd, 6)
+            },
+        ];
+        let mut trace: String = events
+            .iter()
+            .map(|event| format_event(event, Format::Jsonl) + "\n")
+            .collect();
+        // Plaintext keys are hashed on import
+        trace.push_str(r#"{"timestamp":1700000000001000,"command":"delete","key":"user:1"}"#);
+        std::fs::write(&input, trace).unwrap();
+
+        let anonymizer = Anonymizer::new(7);
+        let report = import(
+            input.to_str().unwrap(),
+            output.to_str().unwrap(),
+            TraceFormat::Jsonl,
+            &anonymizer,
+            Compression::None,
+        )
+        .unwrap();
+        assert_eq!(report.events, 3);
+        assert_eq!(report.connections, 3);
+
+        let reader = ProfileReader::new(output.to_str().unwrap()).unwrap();
+        let imported = reader.events();
+        for (imported, event) in imported.iter().zip(&events) {
+            assert_eq!(
+                format_event(imported, Format::Jsonl),
+                format_event(event, Format::Jsonl)
+            );
+        }
+        assert_eq!(imported[2].key_hash, anonymizer.hash_key(b"user:1"));
+        assert_eq!(imported[2].key_size, 6);
+        assert_eq!(imported[2].conn_id, 0);
+        assert!(imported[2].key.is_none());
+    }
+
+    #[test]
+    fn test_malformed_lines_are_reported_by_number() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("bad.csv");
+        let output = dir.path().join("bad.profile");
+        std::fs::write(&input, "0,key-a,10,0,7,get,0\n1,key-b,12,0,7,fetch,0\n").unwrap();
+
+        let err = import(
+            input.to_str().unwrap(),
+            output.to_str().unwrap(),
+            TraceFormat::TwitterCache,
+            &Anonymizer::new(1),
+            Compression::None,
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(
+            err.ends_with("line 2: unknown operation: 'fetch'"),
+            "{}",
+            err
+        );
+
+        std::fs::write(
+            &input,
+            "{\"timestamp\":1,\"command\":\"get\",\"key_size\":3}\n",
+        )
+        .unwrap();
+        let err = import(
+            input.to_str().unwrap(),
+            output.to_str().unwrap(),
+            TraceFormat::Jsonl,
+            &Anonymizer::new(1),
+            Compression::None,
+        )
+        .err()
+        .unwrap()
+        .to_string();
+        assert!(err.ends_with("line 1: missing key_hash or key"), "{}", err);
+
+        assert!("twitter-cache".parse::<TraceFormat>().is_ok());
+        assert!("jsonl".parse::<TraceFormat>().is_ok());
+        assert!("csv".parse::<TraceFormat>().is_err());
+    }
+}
@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+    use membench::filter::EventFilter;
+    use membench::inspect::{format_event, Format};
+    use membench::profile::{CommandType, Event, Flags, Response, ResponseStatus};
+    use membench::record::ProfileWriter;
+    use std::num::NonZero;
+    use std::process::Command;
+
+    /// 10:00:01.5 UTC
+    const TIMESTAMP: u64 = (20_000 * 86_400 + 10 * 3600 + 1) * 1_000_000 + 500_000;
+
+    fn event(i: u64) -> Event {
+        Event {
+            value_size: NonZero::new(100).filter(|_| !i.is_multiple_of(2)),
+            response: Some(Response {
+                status: ResponseStatus::Hit,
+                value_size: NonZero::new(100).filter(|_| i.is_multiple_of(2)),
+            }),
+            latency: Some(250),
+            ..Event::new(
+                TIMESTAMP + i,
+                7,
+                if i.is_multiple_of(2) {
+                    CommandType::Get
+                } else {
+                    CommandType::Set
+                },
+                0xabcd + i,
+                12,
+            )
+        }
+    }
+
+    #[test]
+    fn test_events_format_as_text_and_jsonl() {
+        assert_eq!(
+            format_event(&event(0), Format::Text),
+            "10:00:01.500000 conn=7 get key=0x000000000000abcd key_size=12 response=hit:100 latency=250us"
+        );
+
+        let mut set = event(1);
+        set.flags = Flags::empty().with_quiet();
+        set.key = Some(b"user:1".to_vec());
+        let line = format_event(&set, Format::Jsonl);
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["command"], "set");
+        assert_eq!(json["key_hash"], "0x000000000000abce");
+        assert_eq!(json["value_size"], 100);
+        assert_eq!(json["response"]["status"], "hit");
+        assert!(json["response"]["value_size"].is_null());
+        assert_eq!(json["flags"][0], "quiet");
+        assert_eq!(json["key"], "user:1");
+        assert!(json.get("ttl").is_none());
+    }
+
+    #[test]
+    fn test_filter_predicates_parse_from_one_argument() {
+        let filter: EventFilter = "from=10:00 commands=get,set conn-ids=3-5".parse().unwrap();
+        assert!(filter.range.from.is_some() && filter.range.to.is_none());
+        assert_eq!(filter.commands.unwrap().0.len(), 2);
+        assert!(filter.conn_ids.unwrap().contains(4));
+        assert!("commands".parse::<EventFilter>().is_err());
+        assert!("size=1".parse::<EventFilter>().is_err());
+        assert!("commands=fetch".parse::<EventFilter>().is_err());
+    }
+
+    #[test]
+    fn test_inspect_prints_the_tail_of_matching_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.profile");
+        let path = path.to_str().unwrap();
+        let mut writer = ProfileWriter::new(path).unwrap();
+        for i in 0..10 {
+            writer.write_event(&event(i)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_membench"))
+            .args(["inspect", path, "--format", "jsonl", "--tail", "2"])
+            .args(["--filter", "commands=set"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let hashes: Vec<String> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let json: serde_json::Value = serde_json::from_str(line).unwrap();
+                json["key_hash"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(hashes, ["0x000000000000abd4", "0x000000000000abd6"]);
+
+        let output = Command::new(env!("CARGO_BIN_EXE_membench"))
+            .args(["inspect", path, "--head", "3"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 3);
+    }
+}
@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod tests {
+    use membench::logging::RotatingFileWriter;
+    use std::io::Write;
+
+    #[test]
+    fn test_log_file_rotates_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("membench.log");
+        let path_str = path.to_str().unwrap();
+
+        let mut writer = RotatingFileWriter::new(path_str, Some(20)).unwrap();
+        writer.write_all(b"first line 123\n").unwrap();
+        writer.write_all(b"second line 45\n").unwrap();
+        writer.write_all(b"third line 678\n").unwrap();
+        writer.flush().unwrap();
+
+        let rotated = |n: usize| dir.path().join(format!("membench.log.{}", n));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "third line 678\n");
+        assert_eq!(
+            std::fs::read_to_string(rotated(1)).unwrap(),
+            "second line 45\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(rotated(2)).unwrap(),
+            "first line 123\n"
+        );
+    }
+
+    #[test]
+    fn test_log_file_appends_without_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("membench.log");
+        let path_str = path.to_str().unwrap();
+
+        RotatingFileWriter::new(path_str, None)
+            .unwrap()
+            .write_all(b"one\n")
+            .unwrap();
+        RotatingFileWriter::new(path_str, None)
+            .unwrap()
+            .write_all(b"two\n")
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        assert!(!dir.path().join("membench.log.1").exists());
+    }
+}
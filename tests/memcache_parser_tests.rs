@@ -13,6 +13,29 @@ mod tests {
         assert_eq!(cmd.key_range, 3..10); // "testkey"
     }
 
+    #[test]
+    fn test_parse_multi_key_retrievals() {
+        let parser = MemcacheParser::new();
+
+        let input = b"gets a bb ccc\r\n";
+        let (cmd, _) = parser.parse_command(input).unwrap();
+        assert_eq!(&input[cmd.key_range.clone()], b"a");
+        let more: Vec<&[u8]> = cmd.more_keys.iter().map(|r| &input[r.clone()]).collect();
+        assert_eq!(more, vec![&b"bb"[..], b"ccc"]);
+
+        let input = b"gat 60 a b\r\n";
+        let (cmd, _) = parser.parse_command(input).unwrap();
+        assert_eq!(cmd.cmd_type, CommandType::Gat);
+        assert_eq!(&input[cmd.key_range.clone()], b"a");
+        assert_eq!(cmd.more_keys, vec![9..10]);
+
+        // Other commands have one key
+        let (cmd, _) = parser.parse_command(b"touch a 60\r\n").unwrap();
+        assert!(cmd.more_keys.is_empty());
+        let (cmd, _) = parser.parse_command(b"get a\r\n").unwrap();
+        assert!(cmd.more_keys.is_empty());
+    }
+
     #[test]
     fn test_parse_set_request() {
         let input = b"ms mykey 5\r\nhello\r\n";
@@ -22,4 +45,361 @@ mod tests {
         assert_eq!(cmd.cmd_type, CommandType::Set);
         assert_eq!(cmd.value_size, Some(5));
     }
+
+    #[test]
+    fn test_parse_detects_protocol() {
+        use membench::profile::Protocol;
+        let parser = MemcacheParser::new();
+
+        let (cmd, _rest) = parser.parse_command(b"mg testkey v\r\n").unwrap();
+        assert_eq!(cmd.protocol, Protocol::Meta);
+
+        let (cmd, _rest) = parser.parse_command(b"get testkey\r\n").unwrap();
+        assert_eq!(cmd.protocol, Protocol::Ascii);
+    }
+
+    #[test]
+    fn test_parse_ascii_set_value_size() {
+        let parser = MemcacheParser::new();
+        let (cmd, _rest) = parser
+            .parse_command(b"set mykey 7 0 5\r\nhello\r\n")
+            .unwrap();
+        assert_eq!(cmd.cmd_type, CommandType::Set);
+        assert_eq!(cmd.value_size, Some(5));
+    }
+
+    #[test]
+    fn test_frame_request_waits_for_data_block() {
+        use membench::record::Framed;
+        let parser = MemcacheParser::new();
+
+        assert!(matches!(
+            parser.frame_request(b"get fo"),
+            Framed::Incomplete
+        ));
+        assert!(matches!(
+            parser.frame_request(b"ms mykey 5\r\nhel"),
+            Framed::Incomplete
+        ));
+        assert!(matches!(
+            parser.frame_request(b"ms mykey 5\r\nhello\r\nmg next v\r\n"),
+            Framed::Command(_, 19)
+        ));
+        assert!(matches!(
+            parser.frame_request(b"stats\r\nget foo\r\n"),
+            Framed::Other(7)
+        ));
+        assert!(matches!(
+            parser.frame_request(b"bogus\r\nget foo\r\n"),
+            Framed::Skip(7)
+        ));
+    }
+
+    #[test]
+    fn test_frame_meta_responses() {
+        use membench::profile::ResponseStatus;
+        use membench::record::FramedResponse;
+        let parser = MemcacheParser::new();
+
+        let FramedResponse::Response(response, 13, None) =
+            parser.frame_response(b"VA 5\r\nhello\r\nHD\r\n")
+        else {
+            panic!("VA response not framed");
+        };
+        assert_eq!(response.status, ResponseStatus::Hit);
+        assert_eq!(response.value_size.map(|size| size.get()), Some(5));
+
+        let FramedResponse::Response(response, 4, None) = parser.frame_response(b"EN\r\n") else {
+            panic!("EN response not framed");
+        };
+        assert_eq!(response.status, ResponseStatus::Miss);
+        assert!(matches!(
+            parser.frame_response(b"VA 5\r\nhel"),
+            FramedResponse::Incomplete
+        ));
+    }
+
+    #[test]
+    fn test_frame_ascii_responses() {
+        use membench::profile::ResponseStatus;
+        use membench::record::parser::retrieved_values;
+        use membench::record::FramedResponse;
+        let parser = MemcacheParser::new();
+
+        // A multi-get answers with every value, then END
+        let input = b"VALUE a 0 3\r\nabc\r\nVALUE b 0 2\r\nde\r\nEND\r\nSTORED\r\n";
+        let FramedResponse::Response(response, length, None) = parser.frame_response(input) else {
+            panic!("retrieval not framed");
+        };
+        assert_eq!(length, input.len() - 8);
+        assert_eq!(response.status, ResponseStatus::Hit);
+        assert_eq!(response.value_size.map(|size| size.get()), Some(5));
+        assert_eq!(
+            retrieved_values(&input[..length]),
+            vec![(&b"a"[..], 3), (&b"b"[..], 2)]
+        );
+        assert!(retrieved_values(b"END\r\n").is_empty());
+        assert!(matches!(
+            parser.frame_response(b"VALUE a 0 3\r\nabc\r\n"),
+            FramedResponse::Incomplete
+        ));
+
+        for (line, status) in [
+            (&b"END\r\n"[..], ResponseStatus::Miss),
+            (b"STORED\r\n", ResponseStatus::Hit),
+            (b"NOT_FOUND\r\n", ResponseStatus::Miss),
+            (b"SERVER_ERROR out of memory\r\n", ResponseStatus::Error),
+        ] {
+            let FramedResponse::Response(response, length, None) = parser.frame_response(line)
+            else {
+                panic!("{:?} not framed", line);
+            };
+            assert_eq!(response.status, status);
+            assert_eq!(length, line.len());
+        }
+        assert!(matches!(
+            parser.frame_response(b"STAT pid 1\r\n"),
+            FramedResponse::Skip(12)
+        ));
+    }
+
+    /// A binary protocol frame: header, extras, key, value
+    fn binary_frame(
+        magic: u8,
+        opcode: u8,
+        status: u16,
+        opaque: u32,
+        extras: &[u8],
+        key: &[u8],
+        value: &[u8],
+    ) -> Vec<u8> {
+        let mut frame = vec![magic, opcode];
+        frame.extend_from_slice(&(key.len() as u16).to_be_bytes());
+        frame.push(extras.len() as u8);
+        frame.push(0);
+        frame.extend_from_slice(&status.to_be_bytes());
+        let body = extras.len() + key.len() + value.len();
+        frame.extend_from_slice(&(body as u32).to_be_bytes());
+        frame.extend_from_slice(&opaque.to_be_bytes());
+        frame.extend_from_slice(&[0; 8]);
+        frame.extend_from_slice(extras);
+        frame.extend_from_slice(key);
+        frame.extend_from_slice(value);
+        frame
+    }
+
+    #[test]
+    fn test_frame_binary_requests() {
+        use membench::profile::Protocol;
+        use membench::record::Framed;
+        let parser = MemcacheParser::new();
+
+        let set = binary_frame(0x80, 0x01, 0, 7, &[0; 8], b"mykey", b"hello");
+        assert!(matches!(
+            parser.frame_request(&set[..30]),
+            Framed::Incomplete
+        ));
+        let Framed::Command(cmd, length) = parser.frame_request(&set) else {
+            panic!("expected a binary set");
+        };
+        assert_eq!(length, 24 + 8 + 5 + 5);
+        assert_eq!(cmd.cmd_type, CommandType::Set);
+        assert_eq!(&set[cmd.key_range.clone()], b"mykey");
+        assert_eq!(cmd.value_size, Some(5));
+        assert_eq!(cmd.protocol, Protocol::Binary);
+        assert_eq!(cmd.opaque, Some(7));
+
+        // Set extras: flags, then exptime
+        let mut extras = [0u8; 8];
+        extras[4..].copy_from_slice(&600u32.to_be_bytes());
+        let set_ttl = binary_frame(0x80, 0x01, 0, 7, &extras, b"mykey", b"hello");
+        let Framed::Command(cmd, _) = parser.frame_request(&set_ttl) else {
+            panic!("expected a binary set");
+        };
+        assert_eq!(cmd.exptime, 600);
+
+        let getkq = binary_frame(0x80, 0x0d, 0, 8, &[], b"mykey", b"");
+        let Framed::Command(cmd, 29) = parser.frame_request(&getkq) else {
+            panic!("expected a binary getkq");
+        };
+        assert_eq!(cmd.cmd_type, CommandType::Get);
+        assert!(cmd.flags.has_quiet());
+        assert_eq!(cmd.value_size, None);
+
+        // Answered but not recorded (stat), and quiet with no answer (quitq)
+        let stat = binary_frame(0x80, 0x10, 0, 9, &[], b"", b"");
+        assert!(matches!(parser.frame_request(&stat), Framed::Other(24)));
+        let quitq = binary_frame(0x80, 0x17, 0, 10, &[], b"", b"");
+        assert!(matches!(parser.frame_request(&quitq), Framed::Skip(24)));
+
+        let incr = binary_frame(0x80, 0x05, 0, 11, &[0; 20], b"n", b"");
+        let Framed::Command(cmd, _) = parser.frame_request(&incr) else {
+            panic!("expected a binary incr");
+        };
+        assert_eq!(cmd.cmd_type, CommandType::Incr);
+        assert_eq!(cmd.value_size, None);
+
+        // A set with a cas value in its header is a cas
+        let mut cas = set.clone();
+        cas[23] = 1;
+        let Framed::Command(cmd, _) = parser.frame_request(&cas) else {
+            panic!("expected a binary cas");
+        };
+        assert_eq!(cmd.cmd_type, CommandType::Cas);
+        assert_eq!(cmd.value_size, Some(5));
+    }
+
+    #[test]
+    fn test_encoded_requests_frame_back() {
+        use membench::record::binary::{command_opcode, encode_request};
+        use membench::record::Framed;
+        let parser = MemcacheParser::new();
+
+        for cmd_type in CommandType::ALL {
+            let cas = u64::from(cmd_type == CommandType::Cas);
+            let value: &[u8] = if cmd_type.has_value() { b"abc" } else { b"" };
+            let frame = encode_request(command_opcode(cmd_type), 42, cas, &[0; 8], b"key", value);
+            let Framed::Command(cmd, length) = parser.frame_request(&frame) else {
+                panic!("expected a binary {:?}", cmd_type);
+            };
+            assert_eq!(length, frame.len());
+            assert_eq!(cmd.cmd_type, cmd_type);
+            assert_eq!(cmd.opaque, Some(42));
+            assert_eq!(&frame[cmd.key_range.clone()], b"key");
+            assert_eq!(cmd.value_size, cmd_type.has_value().then_some(3));
+        }
+    }
+
+    #[test]
+    fn test_frame_binary_responses() {
+        use membench::profile::ResponseStatus;
+        use membench::record::FramedResponse;
+        let parser = MemcacheParser::new();
+
+        let hit = binary_frame(0x81, 0x00, 0, 3, &[0; 4], b"", b"hello");
+        let FramedResponse::Response(response, 33, Some(3)) = parser.frame_response(&hit) else {
+            panic!("expected a binary get hit");
+        };
+        assert_eq!(response.status, ResponseStatus::Hit);
+        assert_eq!(response.value_size.map(|size| size.get()), Some(5));
+
+        let cases = [
+            (0x0001, ResponseStatus::Miss),
+            (0x0002, ResponseStatus::Miss),
+            (0x0081, ResponseStatus::Error),
+        ];
+        for (status, expected) in cases {
+            let frame = binary_frame(0x81, 0x01, status, 4, &[], b"", b"");
+            let FramedResponse::Response(response, 24, Some(4)) = parser.frame_response(&frame)
+            else {
+                panic!("expected a binary response for status {status:#x}");
+            };
+            assert_eq!(response.status, expected);
+            assert_eq!(response.value_size, None);
+        }
+        assert!(matches!(
+            parser.frame_response(&hit[..10]),
+            FramedResponse::Incomplete
+        ));
+    }
+
+    #[test]
+    fn test_parse_full_ascii_command_set() {
+        let parser = MemcacheParser::new();
+        let cases: [(&[u8], CommandType, Option<u32>); 11] = [
+            (b"add k 0 0 3\r\n", CommandType::Add, Some(3)),
+            (b"replace k 0 0 3\r\n", CommandType::Replace, Some(3)),
+            (b"append k 0 0 3\r\n", CommandType::Append, Some(3)),
+            (b"prepend k 0 0 3\r\n", CommandType::Prepend, Some(3)),
+            (b"cas k 0 0 3 42\r\n", CommandType::Cas, Some(3)),
+            (b"incr k 5\r\n", CommandType::Incr, None),
+            (b"decr k 5\r\n", CommandType::Decr, None),
+            (b"touch k 60\r\n", CommandType::Touch, None),
+            (b"gets k\r\n", CommandType::Get, None),
+            (b"gat 60 k\r\n", CommandType::Gat, None),
+            (b"gats 60 k\r\n", CommandType::Gat, None),
+        ];
+        for (input, cmd_type, value_size) in cases {
+            let (cmd, _rest) = parser.parse_command(input).unwrap();
+            assert_eq!(cmd.cmd_type, cmd_type);
+            assert_eq!(cmd.value_size, value_size);
+            assert_eq!(&input[cmd.key_range], b"k");
+        }
+    }
+
+    #[test]
+    fn test_parse_meta_modes() {
+        let parser = MemcacheParser::new();
+        let cases: [(&[u8], CommandType); 9] = [
+            (b"ms k 3 ME\r\n", CommandType::Add),
+            (b"ms k 3 T60 MR\r\n", CommandType::Replace),
+            (b"ms k 3 Ma\r\n", CommandType::Append),
+            (b"ms k 3 MP\r\n", CommandType::Prepend),
+            (b"ms k 3 C42\r\n", CommandType::Cas),
+            (b"ma k\r\n", CommandType::Incr),
+            (b"ma k MD D5\r\n", CommandType::Decr),
+            (b"mg k T60\r\n", CommandType::Touch),
+            (b"mg k v T60\r\n", CommandType::Gat),
+        ];
+        for (input, cmd_type) in cases {
+            let (cmd, _rest) = parser.parse_command(input).unwrap();
+            assert_eq!(cmd.cmd_type, cmd_type, "{}", String::from_utf8_lossy(input));
+        }
+
+        // mn carries no key
+        let (cmd, _rest) = parser.parse_command(b"mn\r\n").unwrap();
+        assert_eq!(cmd.cmd_type, CommandType::Noop);
+        assert!(cmd.key_range.is_empty());
+    }
+
+    #[test]
+    fn test_parse_quiet_commands() {
+        let parser = MemcacheParser::new();
+        let cases: [(&[u8], bool); 7] = [
+            (b"set k 0 0 3 noreply\r\n", true),
+            (b"get k noreply\r\n", false),
+            (b"delete k noreply\r\n", true),
+            (b"incr k 1 noreply\r\n", true),
+            (b"mg k v q\r\n", true),
+            (b"set k 0 0 3\r\n", false),
+            (b"mg k v\r\n", false),
+        ];
+        for (input, quiet) in cases {
+            let (cmd, _rest) = parser.parse_command(input).unwrap();
+            assert_eq!(
+                cmd.flags.has_quiet(),
+                quiet,
+                "{}",
+                String::from_utf8_lossy(input)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_exptime() {
+        use membench::record::parser::ttl_from_exptime;
+        let parser = MemcacheParser::new();
+        let cases: [(&[u8], i64); 7] = [
+            (b"set k 0 300 3\r\n", 300),
+            (b"cas k 0 60 3 42\r\n", 60),
+            (b"touch k 30\r\n", 30),
+            (b"gat 45 k\r\n", 45),
+            (b"ms k 3 T120\r\n", 120),
+            (b"mg k v T15\r\n", 15),
+            (b"set k 0 0 3\r\n", 0),
+        ];
+        for (input, exptime) in cases {
+            let (cmd, _rest) = parser.parse_command(input).unwrap();
+            assert_eq!(cmd.exptime, exptime, "{}", String::from_utf8_lossy(input));
+        }
+
+        let now = 1_700_000_000;
+        let ttl = |exptime| ttl_from_exptime(exptime, now).map(|ttl| ttl.get());
+        assert_eq!(ttl(0), None);
+        assert_eq!(ttl(300), Some(300));
+        // Unix times are relative to the command; past ones expire at once
+        assert_eq!(ttl(now as i64 + 3600 * 24 * 60), Some(3600 * 24 * 60));
+        assert_eq!(ttl(now as i64 - 10), Some(1));
+        assert_eq!(ttl(-1), Some(1));
+    }
 }
@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod tests {
+    use membench::merge::merge;
+    use membench::profile::{CommandType, Compression, Event, Protocol, SampleRate};
+    use membench::record::ProfileWriter;
+    use membench::replay::ProfileReader;
+
+    fn event(timestamp: u64, conn_id: u16, key_hash: u64) -> Event {
+        Event::new(timestamp, conn_id, CommandType::Get, key_hash, 10)
+    }
+
+    /// A frontend capture: `count` events from `start` on, every `step`,
+    /// alternating between connections 3 and 8
+    fn write_frontend(path: &str, start: u64, step: u64, count: u64, protocol: Protocol) {
+        let mut writer = ProfileWriter::new(path).unwrap();
+        writer.record_protocol(3, protocol);
+        for i in 0..count {
+            let conn_id = if i % 2 == 0 { 3 } else { 8 };
+            writer
+                .write_event(&event(start + i * step, conn_id, start + i))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_merge_interleaves_and_renumbers_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let (a, b, merged) = (path("a.profile"), path("b.profile"), path("merged.profile"));
+        write_frontend(&a, 1000, 10, 50, Protocol::Ascii);
+        write_frontend(&b, 1005, 10, 30, Protocol::Meta);
+
+        let report = merge(&[a.clone(), b.clone()], &merged, Compression::Deflate).unwrap();
+        assert_eq!(report.events_merged, 80);
+        assert_eq!(report.connections, 4);
+
+        let reader = ProfileReader::new(&merged).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.total_events, 80);
+        assert_eq!(metadata.unique_connections, 4);
+        assert_eq!(metadata.time_range, (1000, 1490));
+        assert_eq!(metadata.compression, Compression::Deflate);
+
+        let timestamps: Vec<u64> = reader.events().iter().map(|e| e.timestamp).collect();
+        assert!(timestamps.windows(2).all(|pair| pair[0] <= pair[1]));
+        // Connections 3 and 8 of each frontend stay apart
+        for event in reader.events() {
+            let expected = if event.timestamp % 10 == 0 {
+                (event.key_hash - 1000) % 2
+            } else {
+                2 + (event.key_hash - 1005) % 2
+            };
+            assert_eq!(event.conn_id as u64, expected);
+        }
+        assert_eq!(metadata.connection_protocols[&0], Protocol::Ascii);
+        assert_eq!(metadata.connection_protocols[&2], Protocol::Meta);
+        assert_eq!(metadata.connection_protocols.len(), 2);
+
+        let sources: Vec<_> = metadata
+            .sources
+            .iter()
+            .map(|s| {
+                (
+                    s.name.as_str(),
+                    s.total_events,
+                    s.first_conn_id,
+                    s.unique_connections,
+                )
+            })
+            .collect();
+        assert_eq!(sources, [("a.profile", 50, 0, 2), ("b.profile", 30, 2, 2)]);
+        assert_eq!(metadata.sources[1].time_range, (1005, 1295));
+
+        // Merging a merged profile keeps the sources it was merged from
+        let c = path("c.profile");
+        write_frontend(&c, 2000, 1, 4, Protocol::Meta);
+        let again = path("again.profile");
+        merge(&[c, merged], &again, Compression::None).unwrap();
+        let reader = ProfileReader::new(&again).unwrap();
+        let sources: Vec<_> = reader
+            .metadata()
+            .sources
+            .iter()
+            .map(|s| (s.name.as_str(), s.first_conn_id))
+            .collect();
+        assert_eq!(
+            sources,
+            [("c.profile", 0), ("a.profile", 2), ("b.profile", 4)]
+        );
+    }
+
+    #[test]
+    fn test_merge_refuses_profiles_recorded_differently() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        let (a, b) = (path("a.profile"), path("b.profile"));
+        write_frontend(&a, 1000, 10, 10, Protocol::Meta);
+
+        let mut writer = ProfileWriter::new(&b).unwrap();
+        writer.set_connection_sampling(SampleRate {
+            numerator: 1,
+            denominator: 2,
+        });
+        writer.write_event(&event(1000, 0, 1)).unwrap();
+        writer.finish().unwrap();
+
+        let err = merge(&[a.clone(), b], &path("out.profile"), Compression::None)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("connection sampling"), "{}", err);
+        assert!(merge(&[a.clone(), a.clone()], &a, Compression::None).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use membench::record::PacketCapture;
+    use membench::record::{PacketCapture, Ports};
     use std::fs;
     use std::path::PathBuf;
 
@@ -42,7 +42,7 @@ mod tests {
         let pcap_str = pcap_path.to_str().unwrap();
 
         // This should work (open as file)
-        let result = PacketCapture::from_source(pcap_str, 11211);
+        let result = PacketCapture::from_source(pcap_str, &Ports::from(11211), None);
         match result {
             Ok(_) => {
                 println!("✓ Successfully opened PCAP file: {}", pcap_str);
@@ -0,0 +1,157 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::checkpoint::{read_record, Record};
+    use membench::profile::checksum::{crc32c, Trailer};
+    use membench::profile::{CommandType, Compression, Event};
+    use membench::record::ProfileWriter;
+    use membench::repair::repair;
+    use membench::replay::{ProfileReader, ProfileStreamer};
+    use tempfile::NamedTempFile;
+
+    fn write_profile(path: &str, count: u64, compression: Compression) {
+        let mut writer = ProfileWriter::with_compression(path, compression).unwrap();
+        for i in 0..count {
+            writer
+                .write_event(&Event::new(1000 + i, 1, CommandType::Get, i, 10))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    /// Offset of the first event after the first checksum record, and the
+    /// events before it
+    fn second_block(data: &[u8]) -> (usize, usize) {
+        let mut offset = 0;
+        let mut events = 0;
+        while let Some((record, next)) = read_record(data, offset) {
+            match record {
+                Record::Checksum(_) => return (next, events),
+                _ => events += 1,
+            }
+            offset = next;
+        }
+        panic!("no checksum record");
+    }
+
+    #[test]
+    fn test_crc32c_check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(b""), 0);
+    }
+
+    #[test]
+    fn test_corrupt_events_are_named_by_byte_range() {
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+        write_profile(path, 10_000, Compression::None);
+        ProfileReader::new(path).unwrap();
+
+        // Flip a byte of the key hash of the first event of the second block
+        let mut data = std::fs::read(path).unwrap();
+        let (start, _) = second_block(&data);
+        data[start + 2 + 14] ^= 0xff;
+        std::fs::write(path, &data).unwrap();
+
+        let err = ProfileReader::new(path).err().unwrap().to_string();
+        assert!(
+            err.starts_with(&format!("checksum mismatch in bytes {}..", start)),
+            "{}",
+            err
+        );
+
+        let mut streamer = ProfileStreamer::new(path).unwrap();
+        let err = loop {
+            match streamer.next_event() {
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("corruption went unnoticed"),
+                Err(e) => break e.to_string(),
+            }
+        };
+        assert!(err.contains(&format!("bytes {}..", start)), "{}", err);
+    }
+
+    #[test]
+    fn test_corrupt_metadata_is_detected() {
+        for compression in [Compression::None, Compression::Deflate] {
+            let temp = NamedTempFile::new().unwrap();
+            let path = temp.path().to_str().unwrap();
+            write_profile(path, 100, compression);
+
+            let mut data = std::fs::read(path).unwrap();
+            let trailer = Trailer::parse(&data[data.len() - Trailer::MAX_LEN..]).unwrap();
+            assert!(trailer.metadata_crc.is_some());
+            let metadata_start = trailer.metadata_start(data.len() as u64).unwrap() as usize;
+            // Total events, right after the magic and version
+            data[metadata_start + 5] ^= 0x01;
+            std::fs::write(path, &data).unwrap();
+
+            let err = ProfileReader::new(path).err().unwrap().to_string();
+            assert!(err.contains("checksum mismatch in the metadata"), "{}", err);
+            let err = ProfileStreamer::new(path).err().unwrap().to_string();
+            assert!(err.contains("checksum mismatch in the metadata"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_repair_keeps_blocks_before_a_checksum_mismatch() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        let input_path = input.path().to_str().unwrap();
+        let output_path = output.path().to_str().unwrap();
+        write_profile(input_path, 10_000, Compression::None);
+
+        let mut data = std::fs::read(input_path).unwrap();
+        let (start, events) = second_block(&data);
+        data[start + 2 + 14] ^= 0xff;
+        std::fs::write(input_path, &data).unwrap();
+
+        let report = repair(input_path, output_path).unwrap();
+        assert_eq!(report.events_recovered, events as u64);
+        let reader = ProfileReader::new(output_path).unwrap();
+        assert_eq!(reader.events().len(), events);
+    }
+
+    #[test]
+    fn test_metadata_past_64_kib_is_kept_whole() {
+        use membench::profile::Protocol;
+        use membench::validate::validate;
+
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+        let mut writer = ProfileWriter::new(path).unwrap();
+        // The protocols of 12,000 connections take about 72 KB
+        for conn_id in 0..12_000u16 {
+            writer.record_protocol(conn_id, Protocol::Ascii);
+            writer
+                .write_event(&Event::new(
+                    1000 + conn_id as u64,
+                    conn_id,
+                    CommandType::Get,
+                    conn_id as u64,
+                    10,
+                ))
+                .unwrap();
+        }
+        writer.checkpoint().unwrap();
+        writer.finish().unwrap();
+
+        let reader = ProfileReader::new(path).unwrap();
+        assert_eq!(reader.metadata().connection_protocols.len(), 12_000);
+        validate(path).unwrap();
+
+        // Without its trailer, the profile is recovered from the checkpoint
+        let data = std::fs::read(path).unwrap();
+        let trailer = Trailer::parse(&data[data.len() - Trailer::MAX_LEN..]).unwrap();
+        assert!(trailer.wide);
+        assert!(trailer.metadata_len > u16::MAX as u64);
+        let unfinished = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        std::fs::write(unfinished.path(), &data[..data.len() - 4]).unwrap();
+        let report = repair(
+            unfinished.path().to_str().unwrap(),
+            output.path().to_str().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(report.events_recovered, 12_000);
+    }
+}
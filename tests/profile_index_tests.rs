@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::index::{seek, IndexEntry, INDEX_INTERVAL};
+    use membench::profile::{CommandType, Compression, Event, TimeBound, TimeRange};
+    use membench::record::ProfileWriter;
+    use membench::replay::{ProfileReader, ProfileStreamer};
+    use std::collections::HashMap;
+    use tempfile::NamedTempFile;
+
+    /// 10:00:00 UTC on some day, in microseconds since the epoch
+    const TEN_AM: u64 = (20_000 * 86_400 + 10 * 3600) * 1_000_000;
+
+    fn event(timestamp: u64, key_hash: u64) -> Event {
+        Event::new(
+            timestamp,
+            (key_hash % 7) as u16,
+            CommandType::Get,
+            key_hash,
+            10,
+        )
+    }
+
+    /// One event every 10ms from 09:59:00 on
+    fn write_profile(path: &str, count: u64, compression: Compression) {
+        let mut writer = ProfileWriter::with_compression(path, compression).unwrap();
+        for i in 0..count {
+            writer
+                .write_event(&event(TEN_AM - 60_000_000 + i * 10_000, i))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    fn range(from: &str, to: &str) -> TimeRange {
+        TimeRange {
+            from: Some(from.parse().unwrap()),
+            to: Some(to.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_time_bounds_parse() {
+        assert_eq!("10:05".parse(), Ok(TimeBound::OfDay(36_300)));
+        assert_eq!("10:05:30".parse(), Ok(TimeBound::OfDay(36_330)));
+        assert_eq!("90s".parse(), Ok(TimeBound::Offset(90_000_000)));
+        assert!("24:00".parse::<TimeBound>().is_err());
+        assert!("10:".parse::<TimeBound>().is_err());
+        assert!("soon".parse::<TimeBound>().is_err());
+    }
+
+    #[test]
+    fn test_time_of_day_resolves_after_the_start() {
+        let start = TEN_AM - 60_000_000;
+        assert_eq!(TimeBound::OfDay(36_000).resolve(start), TEN_AM);
+        // Past midnight of a capture started the evening before
+        let evening = TEN_AM + 13 * 3_600_000_000;
+        assert_eq!(
+            TimeBound::OfDay(1800).resolve(evening),
+            TEN_AM + 14 * 3_600_000_000 + 1_800_000_000
+        );
+        assert_eq!(
+            range("10:00", "10:05").resolve(start),
+            (TEN_AM, TEN_AM + 300_000_000)
+        );
+        assert_eq!(
+            range("1m", "2m").resolve(start),
+            (TEN_AM, TEN_AM + 60_000_000)
+        );
+    }
+
+    #[test]
+    fn test_seek_starts_before_every_event_of_the_range() {
+        let entries: Vec<IndexEntry> = [0, 100, 200, 300]
+            .iter()
+            .enumerate()
+            .map(|(i, &timestamp)| IndexEntry {
+                timestamp,
+                offset: i as u64 * 1000,
+                events: i as u64 * 10,
+            })
+            .collect();
+        assert_eq!(seek(&entries, 0), None);
+        assert_eq!(seek(&entries, 100).unwrap().offset, 0);
+        assert_eq!(seek(&entries, 101).unwrap().offset, 1000);
+        assert_eq!(seek(&entries, 1000).unwrap().offset, 3000);
+    }
+
+    #[test]
+    fn test_range_reads_match_a_full_scan() {
+        for compression in [Compression::None, Compression::Deflate] {
+            let temp = NamedTempFile::new().unwrap();
+            let path = temp.path().to_str().unwrap();
+            write_profile(path, 60_000, compression);
+
+            let full = ProfileReader::new(path).unwrap();
+            let index = full.metadata().index.unwrap();
+            assert_eq!(index.entries, 60_000u64.div_ceil(INDEX_INTERVAL));
+
+            let expected: Vec<u64> = full
+                .events()
+                .iter()
+                .filter(|e| e.timestamp >= TEN_AM && e.timestamp <= TEN_AM + 300_000_000)
+                .map(|e| e.key_hash)
+                .collect();
+            assert_eq!(expected.len(), 30_001);
+
+            let reader = ProfileReader::with_range(path, range("10:00", "10:05")).unwrap();
+            let hashes: Vec<u64> = reader.events().iter().map(|e| e.key_hash).collect();
+            assert_eq!(hashes, expected);
+            assert_eq!(reader.metadata().total_events, 30_001);
+            assert_eq!(reader.metadata().time_range, (TEN_AM, TEN_AM + 300_000_000));
+
+            // The streamer starts over at the range on reset
+            let mut streamer = ProfileStreamer::with_range(path, range("10:00", "10:05")).unwrap();
+            for _ in 0..2 {
+                let mut count = 0;
+                let first = streamer.next_event().unwrap().unwrap();
+                assert_eq!(first.timestamp, TEN_AM);
+                while streamer.next_event().unwrap().is_some() {
+                    count += 1;
+                }
+                assert_eq!(count + 1, expected.len());
+                assert!(streamer.next_event().unwrap().is_none());
+                streamer.reset().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_profile_without_index_is_scanned() {
+        // Layout of version 2: bincode events, each prefixed by its u16 length
+        let mut profile = Vec::new();
+        for i in 0..100u64 {
+            let event = event(1000 + i * 1_000_000, i);
+            let encoded = bincode::serialize(&(
+                event.timestamp,
+                event.conn_id,
+                event.cmd_type,
+                event.flags,
+                event.key_hash,
+                event.key_size,
+                event.value_size,
+            ))
+            .unwrap();
+            profile.extend_from_slice(&(encoded.len() as u16).to_le_bytes());
+            profile.extend_from_slice(&encoded);
+        }
+        let metadata = bincode::serialize(&(
+            0xDEADBEEFu32,
+            2u8,
+            100u64,
+            (1000u64, 1000 + 99 * 1_000_000u64),
+            7u32,
+            HashMap::from([(CommandType::Get, 100u64)]),
+        ))
+        .unwrap();
+        profile.extend_from_slice(&metadata);
+        profile.extend_from_slice(&(metadata.len() as u16).to_le_bytes());
+        profile.extend_from_slice(&0xDEADBEEFu32.to_le_bytes());
+
+        let temp = NamedTempFile::new().unwrap();
+        std::fs::write(temp.path(), &profile).unwrap();
+        let path = temp.path().to_str().unwrap();
+
+        let reader = ProfileReader::with_range(path, range("10s", "19s")).unwrap();
+        let hashes: Vec<u64> = reader.events().iter().map(|e| e.key_hash).collect();
+        assert_eq!(hashes, (10..20).collect::<Vec<_>>());
+    }
+}
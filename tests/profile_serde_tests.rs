@@ -4,15 +4,7 @@ mod tests {
 
     #[test]
     fn test_event_serialization() {
-        let event = Event {
-            timestamp: 1000000,
-            conn_id: 1,
-            cmd_type: CommandType::Get,
-            key_hash: 0x123456789abcdef0,
-            key_size: 10,
-            value_size: None,
-            flags: Flags::empty(),
-        };
+        let event = Event::new(1000000, 1, CommandType::Get, 0x123456789abcdef0, 10);
 
         let encoded = bincode::serialize(&event).expect("encode");
         let decoded: Event = bincode::deserialize(&encoded).expect("decode");
@@ -20,4 +12,161 @@ mod tests {
         assert_eq!(decoded.timestamp, event.timestamp);
         assert_eq!(decoded.key_hash, event.key_hash);
     }
+
+    #[test]
+    fn test_version_2_events_decode() {
+        use membench::profile::{Response, ResponseStatus};
+        use std::num::NonZero;
+
+        /// Event layout of version 2 profiles
+        #[derive(serde::Serialize)]
+        struct LegacyEvent {
+            timestamp: u64,
+            conn_id: u16,
+            cmd_type: CommandType,
+            flags: Flags,
+            key_hash: u64,
+            key_size: u32,
+            value_size: Option<NonZero<u32>>,
+        }
+
+        let legacy = bincode::serialize(&LegacyEvent {
+            timestamp: 7,
+            conn_id: 3,
+            cmd_type: CommandType::Set,
+            flags: Flags::empty(),
+            key_hash: 42,
+            key_size: 10,
+            value_size: NonZero::new(100),
+        })
+        .unwrap();
+        let event = Event::decode(&legacy).expect("decode legacy");
+        assert_eq!(event.conn_id, 3);
+        assert_eq!(event.value_size.map(|size| size.get()), Some(100));
+        assert!(event.response.is_none());
+
+        // Bincode events were never released with more fields: a longer
+        // record is not mistaken for one
+        let current = Event {
+            response: Some(Response {
+                status: ResponseStatus::Miss,
+                value_size: None,
+            }),
+            ..event
+        };
+        assert!(Event::decode(&bincode::serialize(&current).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_packed_events_round_trip_in_order() {
+        use membench::profile::packed::{EventDecoder, EventEncoder, HEADER_LEN, PACKED_VERSION};
+        use membench::profile::{Response, ResponseStatus};
+        use std::num::NonZero;
+
+        let bare = Event {
+            flags: Flags::empty().with_quiet(),
+            ..Event::new(5_000_000_000, 3, CommandType::Gat, 0xfeed_beef, 12)
+        };
+        let full = Event {
+            timestamp: bare.timestamp + 250,
+            value_size: NonZero::new(300_000),
+            response: Some(Response {
+                status: ResponseStatus::Hit,
+                value_size: NonZero::new(300_000),
+            }),
+            ttl: NonZero::new(3600),
+            server_port: NonZero::new(11211),
+            key_segments: Some(vec![1, 2, 3]),
+            key: Some(b"user:123".to_vec()),
+            client_id: Some(0xc0ffee),
+            latency: Some(120),
+            ..bare.clone()
+        };
+        // Backwards in time, then a jump too far for a delta
+        let earlier = Event {
+            timestamp: bare.timestamp - 10,
+            ..bare.clone()
+        };
+        let later = Event {
+            timestamp: u64::MAX - 1,
+            ..bare.clone()
+        };
+
+        let mut encoder = EventEncoder::new();
+        let records: Vec<Vec<u8>> = [&bare, &full, &earlier, &later]
+            .iter()
+            .map(|event| {
+                let mut record = Vec::new();
+                encoder.encode(event, &mut record);
+                record
+            })
+            .collect();
+
+        let mut decoder = EventDecoder::new(PACKED_VERSION);
+        for (record, event) in records.iter().zip([&bare, &full, &earlier, &later]) {
+            let decoded = decoder.decode(record).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", event));
+        }
+
+        // An event with only a delta is its fixed-width header, far below bincode
+        assert_eq!(records[2].len(), HEADER_LEN);
+        assert!(records[2].len() < bincode::serialize(&earlier).unwrap().len() * 2 / 3);
+
+        let mut decoder = EventDecoder::new(PACKED_VERSION);
+        assert!(decoder.decode(&records[1][..records[1].len() - 1]).is_err());
+        assert!(decoder
+            .decode(&[records[0].clone(), vec![0]].concat())
+            .is_err());
+    }
+
+    #[test]
+    fn test_version_2_profile_still_reads() {
+        use membench::replay::{ProfileReader, ProfileStreamer};
+        use tempfile::NamedTempFile;
+
+        // Layout of version 2: bincode events, each prefixed by its u16 length
+        let mut profile = Vec::new();
+        for i in 0..3u64 {
+            let encoded = bincode::serialize(&(
+                1000 + i,
+                1u16,
+                CommandType::Get,
+                Flags::empty(),
+                i,
+                8u32,
+                None::<std::num::NonZero<u32>>,
+            ))
+            .unwrap();
+            profile.extend_from_slice(&(encoded.len() as u16).to_le_bytes());
+            profile.extend_from_slice(&encoded);
+        }
+        let metadata = bincode::serialize(&(
+            0xDEADBEEFu32,
+            2u8,
+            3u64,
+            (1000u64, 1002u64),
+            1u32,
+            std::collections::HashMap::from([(CommandType::Get, 3u64)]),
+        ))
+        .unwrap();
+        profile.extend_from_slice(&metadata);
+        profile.extend_from_slice(&(metadata.len() as u16).to_le_bytes());
+        profile.extend_from_slice(&0xDEADBEEFu32.to_le_bytes());
+
+        let temp = NamedTempFile::new().unwrap();
+        std::fs::write(temp.path(), &profile).unwrap();
+        let path = temp.path().to_str().unwrap();
+
+        let reader = ProfileReader::new(path).unwrap();
+        assert_eq!(reader.metadata().version, 2);
+        let hashes: Vec<u64> = reader.events().iter().map(|e| e.key_hash).collect();
+        assert_eq!(hashes, [0, 1, 2]);
+
+        let mut streamer = ProfileStreamer::new(path).unwrap();
+        let mut timestamps = Vec::new();
+        while let Some(event) = streamer.next_event().unwrap() {
+            timestamps.push(event.timestamp);
+        }
+        assert_eq!(timestamps, [1000, 1001, 1002]);
+    }
 }
@@ -0,0 +1,44 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::KeySketch;
+
+    fn sketch_of(keys: impl Iterator<Item = u64>) -> KeySketch {
+        let mut sketch = KeySketch::new();
+        for key in keys {
+            sketch.insert(key);
+        }
+        sketch
+    }
+
+    fn assert_near(estimate: u64, actual: u64) {
+        // Well past three standard errors of 1.6%
+        let error = (estimate as f64 - actual as f64).abs() / actual as f64;
+        assert!(error < 0.05, "estimated {} for {}", estimate, actual);
+    }
+
+    #[test]
+    fn test_small_counts_are_close_to_exact() {
+        assert_eq!(KeySketch::new().estimate(), 0);
+        assert_eq!(sketch_of(std::iter::repeat_n(42, 100)).estimate(), 1);
+        assert_near(sketch_of(0..100).estimate(), 100);
+    }
+
+    #[test]
+    fn test_large_counts_stay_within_the_error() {
+        for count in [10_000u64, 1_000_000] {
+            // Sequential key hashes, as imported traces may have
+            assert_near(sketch_of(0..count).estimate(), count);
+        }
+        let repeated = sketch_of((0..500_000u64).map(|i| i % 50_000));
+        assert_near(repeated.estimate(), 50_000);
+    }
+
+    #[test]
+    fn test_merged_sketches_count_the_union() {
+        let mut a = sketch_of(0..60_000);
+        let b = sketch_of(40_000..100_000);
+        a.merge(&b);
+        assert_near(a.estimate(), 100_000);
+        assert_eq!(a, sketch_of(0..100_000));
+    }
+}
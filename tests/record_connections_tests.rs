@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod tests {
+    use membench::record::{ConnectionTracker, FourTuple};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn client(src_port: u16) -> FourTuple {
+        FourTuple {
+            src_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            src_port,
+            dst_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            dst_port: 11211,
+        }
+    }
+
+    #[test]
+    fn test_conn_id_is_stable_per_tuple() {
+        let mut connections = ConnectionTracker::new();
+        assert_eq!(connections.conn_id(client(1000)), Some(0));
+        assert_eq!(connections.conn_id(client(1001)), Some(1));
+        assert_eq!(connections.conn_id(client(1000)), Some(0));
+        assert_eq!(connections.active(), 2);
+        assert_eq!(connections.opened(), 2);
+    }
+
+    #[test]
+    fn test_closed_ids_are_reused_lowest_first() {
+        let mut connections = ConnectionTracker::new();
+        for port in 1000..1004 {
+            connections.conn_id(client(port));
+        }
+        assert_eq!(connections.close(client(1002)), Some(2));
+        assert_eq!(connections.close(client(1001)), Some(1));
+        assert_eq!(connections.close(client(1001)), None);
+
+        assert_eq!(connections.conn_id(client(2000)), Some(1));
+        assert_eq!(connections.conn_id(client(2001)), Some(2));
+        assert_eq!(connections.conn_id(client(2002)), Some(4));
+        assert_eq!(connections.opened(), 7);
+        assert_eq!(connections.peak(), 5);
+    }
+
+    #[test]
+    fn test_syn_on_known_tuple_opens_new_connection() {
+        let mut connections = ConnectionTracker::new();
+        connections.conn_id(client(1000));
+        connections.conn_id(client(1001));
+        // The tuple of the first connection is reused after a close we missed
+        assert_eq!(connections.open(client(1000)), Some(0));
+        assert_eq!(connections.opened(), 3);
+        assert_eq!(connections.active(), 2);
+    }
+}
@@ -0,0 +1,376 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event, Flags, Protocol, Response, ResponseStatus};
+    use membench::record::parser::ParsedCommand;
+    use membench::record::{FourTuple, ResponseCorrelator};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn client(src_port: u16) -> FourTuple {
+        FourTuple {
+            src_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            src_port,
+            dst_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            dst_port: 11211,
+        }
+    }
+
+    fn event(key_hash: u64) -> Event {
+        Event::new(key_hash, 0, CommandType::Get, key_hash, 8)
+    }
+
+    fn command(event: &Event, protocol: Protocol, opaque: Option<u32>) -> ParsedCommand {
+        ParsedCommand {
+            cmd_type: event.cmd_type,
+            key_range: 0..0,
+            more_keys: Vec::new(),
+            value_size: None,
+            flags: event.flags,
+            protocol,
+            opaque,
+            exptime: 0,
+        }
+    }
+
+    fn send(
+        correlator: &mut ResponseCorrelator,
+        client: FourTuple,
+        event: Event,
+        protocol: Protocol,
+        opaque: Option<u32>,
+    ) {
+        let command = command(&event, protocol, opaque);
+        correlator.request(client, event, &command);
+    }
+
+    fn quiet(key_hash: u64, cmd_type: CommandType) -> Event {
+        Event {
+            cmd_type,
+            flags: Flags::empty().with_quiet(),
+            ..event(key_hash)
+        }
+    }
+
+    fn status(status: ResponseStatus) -> Response {
+        Response {
+            status,
+            value_size: None,
+        }
+    }
+
+    #[test]
+    fn test_responses_pair_in_order_per_connection() {
+        let mut correlator = ResponseCorrelator::new();
+        send(&mut correlator, client(1), event(1), Protocol::Ascii, None);
+        send(&mut correlator, client(2), event(2), Protocol::Ascii, None);
+        send(&mut correlator, client(1), event(3), Protocol::Ascii, None);
+
+        // Nothing is released before the oldest command is answered
+        assert!(correlator.response(client(2), status(ResponseStatus::Miss), None, 100));
+        assert!(correlator.pop_ready().is_none());
+
+        assert!(correlator.response(client(1), status(ResponseStatus::Hit), None, 100));
+        assert!(correlator.response(client(1), status(ResponseStatus::Error), None, 100));
+        assert!(!correlator.response(client(1), status(ResponseStatus::Hit), None, 100));
+
+        let released: Vec<(u64, ResponseStatus)> = std::iter::from_fn(|| correlator.pop_ready())
+            .map(|e| (e.key_hash, e.response.unwrap().status))
+            .collect();
+        assert_eq!(
+            released,
+            vec![
+                (1, ResponseStatus::Hit),
+                (2, ResponseStatus::Miss),
+                (3, ResponseStatus::Error)
+            ]
+        );
+        assert_eq!(correlator.answered(), 3);
+    }
+
+    #[test]
+    fn test_close_releases_unanswered_commands() {
+        let mut correlator = ResponseCorrelator::new();
+        send(&mut correlator, client(1), event(1), Protocol::Ascii, None);
+        send(&mut correlator, client(2), event(2), Protocol::Ascii, None);
+        correlator.close(client(1));
+
+        let first = correlator.pop_ready().unwrap();
+        assert_eq!(first.key_hash, 1);
+        assert!(first.response.is_none());
+        assert!(correlator.pop_ready().is_none());
+
+        let rest: Vec<Event> = correlator.drain().collect();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(correlator.unanswered(), 2);
+    }
+
+    #[test]
+    fn test_pushed_out_command_keeps_its_response_slot() {
+        let mut correlator = ResponseCorrelator::new();
+        send(&mut correlator, client(1), event(0), Protocol::Ascii, None);
+        for i in 1..=membench::record::correlation::MAX_UNANSWERED as u64 {
+            send(&mut correlator, client(2), event(i), Protocol::Ascii, None);
+        }
+        // Too many commands are waiting: the oldest is released unanswered
+        assert!(correlator.pop_ready().unwrap().response.is_none());
+
+        // Its late response is dropped rather than given to a later command
+        send(
+            &mut correlator,
+            client(1),
+            event(u64::MAX),
+            Protocol::Ascii,
+            None,
+        );
+        assert!(!correlator.response(client(1), status(ResponseStatus::Hit), None, 100));
+        assert!(correlator.response(client(1), status(ResponseStatus::Miss), None, 100));
+        let last = correlator.drain().last().unwrap();
+        assert_eq!(last.response.unwrap().status, ResponseStatus::Miss);
+    }
+
+    #[test]
+    fn test_binary_responses_resolve_quiet_commands() {
+        let mut correlator = ResponseCorrelator::new();
+        send(
+            &mut correlator,
+            client(1),
+            quiet(1, CommandType::Get),
+            Protocol::Binary,
+            Some(1),
+        );
+        send(
+            &mut correlator,
+            client(1),
+            quiet(2, CommandType::Get),
+            Protocol::Binary,
+            Some(2),
+        );
+        send(
+            &mut correlator,
+            client(1),
+            quiet(3, CommandType::Set),
+            Protocol::Binary,
+            Some(3),
+        );
+        let noop = Event {
+            cmd_type: CommandType::Noop,
+            ..event(4)
+        };
+        let noop = command(&noop, Protocol::Binary, Some(4));
+        correlator.unrecorded(client(1), Some(&noop));
+        send(
+            &mut correlator,
+            client(1),
+            event(5),
+            Protocol::Binary,
+            Some(5),
+        );
+
+        // A response to a command never seen changes nothing
+        assert!(!correlator.response(client(1), status(ResponseStatus::Hit), Some(99), 100));
+        // Only the second getq hits; the noop-like unrecorded command answers
+        // the rest of the quiet batch
+        assert!(correlator.response(client(1), status(ResponseStatus::Hit), Some(2), 100));
+        assert!(!correlator.response(client(1), status(ResponseStatus::Hit), Some(4), 100));
+        assert!(correlator.response(client(1), status(ResponseStatus::Error), Some(5), 100));
+
+        let released: Vec<(u64, ResponseStatus)> = std::iter::from_fn(|| correlator.pop_ready())
+            .map(|e| (e.key_hash, e.response.unwrap().status))
+            .collect();
+        assert_eq!(
+            released,
+            vec![
+                (1, ResponseStatus::Miss),
+                (2, ResponseStatus::Hit),
+                (3, ResponseStatus::Hit),
+                (5, ResponseStatus::Error)
+            ]
+        );
+        assert_eq!(correlator.answered(), 4);
+        assert_eq!(correlator.unanswered(), 0);
+    }
+
+    #[test]
+    fn test_noreply_commands_take_no_response() {
+        let mut correlator = ResponseCorrelator::new();
+        send(
+            &mut correlator,
+            client(1),
+            quiet(1, CommandType::Set),
+            Protocol::Ascii,
+            None,
+        );
+        send(&mut correlator, client(1), event(2), Protocol::Ascii, None);
+
+        // The miss answers the get, not the set sent before it
+        assert!(correlator.response(client(1), status(ResponseStatus::Miss), None, 100));
+        let released: Vec<(u64, Option<ResponseStatus>)> =
+            std::iter::from_fn(|| correlator.pop_ready())
+                .map(|e| (e.key_hash, e.response.map(|response| response.status)))
+                .collect();
+        assert_eq!(released, vec![(1, None), (2, Some(ResponseStatus::Miss))]);
+        assert_eq!(correlator.answered(), 1);
+        assert_eq!(correlator.unanswered(), 0);
+    }
+
+    #[test]
+    fn test_meta_quiet_commands_resolve_by_later_responses() {
+        let mut correlator = ResponseCorrelator::new();
+        let noop = Event {
+            cmd_type: CommandType::Noop,
+            ..event(6)
+        };
+        // ms q, mg q, mg q, mg, mg q, mn
+        let batch = [
+            quiet(1, CommandType::Set),
+            quiet(2, CommandType::Get),
+            quiet(3, CommandType::Get),
+            event(4),
+            quiet(5, CommandType::Get),
+            noop,
+        ];
+        for command in batch {
+            send(&mut correlator, client(1), command, Protocol::Meta, None);
+        }
+
+        // HD is not sent for a quiet ms: the hit is the first mg's
+        assert!(correlator.response(client(1), status(ResponseStatus::Hit), None, 100));
+        // EN is not sent for a quiet mg: the miss is the plain mg's
+        assert!(correlator.response(client(1), status(ResponseStatus::Miss), None, 100));
+        // The MN ends the batch: the last mg missed too
+        assert!(correlator.noop(client(1), status(ResponseStatus::Hit), 100));
+
+        let released: Vec<(u64, ResponseStatus)> = std::iter::from_fn(|| correlator.pop_ready())
+            .map(|e| (e.key_hash, e.response.unwrap().status))
+            .collect();
+        assert_eq!(
+            released,
+            vec![
+                (1, ResponseStatus::Hit),
+                (2, ResponseStatus::Hit),
+                (3, ResponseStatus::Miss),
+                (4, ResponseStatus::Miss),
+                (5, ResponseStatus::Miss),
+                (6, ResponseStatus::Hit)
+            ]
+        );
+        assert_eq!(correlator.answered(), 6);
+    }
+
+    #[test]
+    fn test_meta_quiet_hit_answers_the_quiet_command() {
+        let mut correlator = ResponseCorrelator::new();
+        send(
+            &mut correlator,
+            client(1),
+            quiet(1, CommandType::Get),
+            Protocol::Meta,
+            None,
+        );
+        send(&mut correlator, client(1), event(2), Protocol::Meta, None);
+
+        assert!(correlator.response(client(1), status(ResponseStatus::Hit), None, 100));
+        assert!(correlator.response(client(1), status(ResponseStatus::Miss), None, 100));
+        let released: Vec<(u64, ResponseStatus)> = std::iter::from_fn(|| correlator.pop_ready())
+            .map(|e| (e.key_hash, e.response.unwrap().status))
+            .collect();
+        assert_eq!(
+            released,
+            vec![(1, ResponseStatus::Hit), (2, ResponseStatus::Miss)]
+        );
+    }
+
+    #[test]
+    fn test_unrecorded_lines_take_their_error() {
+        let mut correlator = ResponseCorrelator::new();
+        correlator.unrecorded(client(1), None);
+        send(&mut correlator, client(1), event(1), Protocol::Ascii, None);
+
+        // The ERROR goes to the unknown line
+        assert!(!correlator.response(client(1), status(ResponseStatus::Error), None, 100));
+        assert!(correlator.response(client(1), status(ResponseStatus::Hit), None, 100));
+        let answered = correlator.pop_ready().unwrap();
+        assert_eq!(answered.response.unwrap().status, ResponseStatus::Hit);
+    }
+
+    #[test]
+    fn test_responses_record_latency() {
+        let mut correlator = ResponseCorrelator::new();
+        send(
+            &mut correlator,
+            client(1),
+            event(1_000),
+            Protocol::Ascii,
+            None,
+        );
+        send(
+            &mut correlator,
+            client(1),
+            event(1_500),
+            Protocol::Ascii,
+            None,
+        );
+        send(
+            &mut correlator,
+            client(1),
+            event(2_000),
+            Protocol::Ascii,
+            None,
+        );
+
+        assert!(correlator.response(client(1), status(ResponseStatus::Hit), None, 1_250));
+        assert!(correlator.response(client(1), status(ResponseStatus::Miss), None, 1_600));
+        let latencies: Vec<Option<u32>> = correlator.drain().map(|e| e.latency).collect();
+        // The last command was never answered
+        assert_eq!(latencies, vec![Some(250), Some(100), None]);
+    }
+
+    #[test]
+    fn test_multi_key_retrieval_answers_each_key() {
+        let mut correlator = ResponseCorrelator::new();
+        let get = command(&event(0), Protocol::Ascii, None);
+        // The second key was not recorded
+        let keys = vec![
+            (b"a".to_vec(), Some(event(1))),
+            (b"b".to_vec(), None),
+            (b"c".to_vec(), Some(event(3))),
+            (b"a".to_vec(), Some(event(4))),
+        ];
+        correlator.request_keys(client(1), keys, &get);
+        send(&mut correlator, client(1), event(5), Protocol::Ascii, None);
+
+        let values: [(&[u8], u32); 3] = [(b"c", 2), (b"b", 7), (b"a", 3)];
+        let hit = status(ResponseStatus::Hit);
+        assert!(correlator.retrieval(client(1), hit, &values, 10));
+        assert!(correlator.response(client(1), status(ResponseStatus::Miss), None, 20));
+
+        let released: Vec<(u64, ResponseStatus, Option<u32>, Option<u32>)> =
+            std::iter::from_fn(|| correlator.pop_ready())
+                .map(|e| {
+                    let response = e.response.unwrap();
+                    let size = response.value_size.map(|size| size.get());
+                    (e.key_hash, response.status, size, e.latency)
+                })
+                .collect();
+        assert_eq!(
+            released,
+            vec![
+                (1, ResponseStatus::Hit, Some(3), Some(9)),
+                (3, ResponseStatus::Hit, Some(2), Some(7)),
+                // Asked twice, returned once
+                (4, ResponseStatus::Miss, None, Some(6)),
+                (5, ResponseStatus::Miss, None, Some(15)),
+            ]
+        );
+        assert_eq!(correlator.answered(), 4);
+
+        // A connection closing before the response leaves every key unanswered
+        let keys = vec![
+            (b"a".to_vec(), Some(event(6))),
+            (b"b".to_vec(), Some(event(7))),
+        ];
+        correlator.request_keys(client(1), keys, &get);
+        correlator.close(client(1));
+        assert_eq!(correlator.drain().count(), 2);
+        assert_eq!(correlator.unanswered(), 2);
+    }
+}
@@ -0,0 +1,140 @@
+#[cfg(test)]
+mod tests {
+    use membench::record::{parse_ip, Defragmented, FragmentLimits, FragmentReassembler};
+    use std::time::Duration;
+
+    /// IPv4 packet holding a TCP segment to port 11211
+    fn ipv4_tcp(payload: &[u8]) -> Vec<u8> {
+        let mut tcp = vec![0x9c, 0x40, 0x2b, 0xcb]; // 40000 -> 11211
+        tcp.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x18, 0, 0, 0, 0, 0, 0]);
+        tcp.extend_from_slice(payload);
+        let mut ip = vec![0x45, 0];
+        ip.extend_from_slice(&((20 + tcp.len()) as u16).to_be_bytes());
+        ip.extend_from_slice(&[0x12, 0x34, 0, 0, 64, 6, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        ip.extend(tcp);
+        ip
+    }
+
+    /// Split an IPv4 packet into fragments of `size` bytes of data (a multiple of 8)
+    fn fragment_ipv4(ip: &[u8], size: usize) -> Vec<Vec<u8>> {
+        let data = &ip[20..];
+        data.chunks(size)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut fragment = ip[..20].to_vec();
+                fragment[2..4].copy_from_slice(&((20 + chunk.len()) as u16).to_be_bytes());
+                let more = (i + 1) * size < data.len();
+                let flags_offset = (i * size / 8) as u16 | if more { 0x2000 } else { 0 };
+                fragment[6..8].copy_from_slice(&flags_offset.to_be_bytes());
+                fragment.extend_from_slice(chunk);
+                fragment
+            })
+            .collect()
+    }
+
+    /// Split the IPv6 version of the packet into fragments behind a fragment header
+    fn fragment_ipv6(ipv4: &[u8], size: usize) -> Vec<Vec<u8>> {
+        let data = &ipv4[20..];
+        data.chunks(size)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut fragment = vec![0x60, 0, 0, 0];
+                fragment.extend_from_slice(&((8 + chunk.len()) as u16).to_be_bytes());
+                fragment.extend_from_slice(&[44, 64]);
+                fragment.extend_from_slice(&[0; 15]);
+                fragment.push(1);
+                fragment.extend_from_slice(&[0; 15]);
+                fragment.push(1);
+                let more = (i + 1) * size < data.len();
+                let offset_more = ((i * size / 8) as u16) << 3 | more as u16;
+                fragment.extend_from_slice(&[6, 0]);
+                fragment.extend_from_slice(&offset_more.to_be_bytes());
+                fragment.extend_from_slice(&0xcafeu32.to_be_bytes());
+                fragment.extend_from_slice(chunk);
+                fragment
+            })
+            .collect()
+    }
+
+    fn push_all(defragmenter: &mut FragmentReassembler, fragments: &[Vec<u8>]) -> Option<Vec<u8>> {
+        let mut datagram = None;
+        for fragment in fragments {
+            match defragmenter.push(fragment, 0) {
+                Defragmented::Reassembled(packet) => datagram = Some(packet),
+                Defragmented::Pending => {}
+                Defragmented::Whole(_) => panic!("fragment taken as a whole packet"),
+            }
+        }
+        datagram
+    }
+
+    #[test]
+    fn test_unfragmented_packets_pass_through() {
+        let mut defragmenter = FragmentReassembler::new(FragmentLimits::default());
+        let ip = ipv4_tcp(b"get foo\r\n");
+        assert_eq!(defragmenter.push(&ip, 0), Defragmented::Whole(&ip[..]));
+    }
+
+    #[test]
+    fn test_reassemble_ipv4_out_of_order() {
+        let payload = vec![b'x'; 3000];
+        let ip = ipv4_tcp(&payload);
+        let mut fragments = fragment_ipv4(&ip, 1480);
+        fragments.reverse();
+        // A duplicate is ignored
+        fragments.insert(1, fragments[0].clone());
+
+        let mut defragmenter = FragmentReassembler::new(FragmentLimits::default());
+        let datagram = push_all(&mut defragmenter, &fragments).unwrap();
+        assert_eq!(datagram, ip);
+        assert_eq!(parse_ip(&datagram).unwrap().payload, &payload[..]);
+        assert_eq!(defragmenter.reassembled(), 1);
+        assert_eq!(defragmenter.buffered(), 0);
+    }
+
+    #[test]
+    fn test_reassemble_ipv6() {
+        let payload = vec![b'y'; 2000];
+        let ip = ipv4_tcp(&payload);
+        let fragments = fragment_ipv6(&ip, 1232);
+
+        let mut defragmenter = FragmentReassembler::new(FragmentLimits::default());
+        let datagram = push_all(&mut defragmenter, &fragments).unwrap();
+        let segment = parse_ip(&datagram).unwrap();
+        assert_eq!(segment.payload, &payload[..]);
+        assert_eq!(segment.tuple.dst_port, 11211);
+    }
+
+    #[test]
+    fn test_incomplete_datagrams_time_out_and_are_evicted() {
+        let limits = FragmentLimits {
+            timeout: Duration::from_secs(1),
+            max_bytes: 2000,
+        };
+        let mut defragmenter = FragmentReassembler::new(limits);
+        let fragments = fragment_ipv4(&ipv4_tcp(&[b'z'; 3000]), 1480);
+
+        // The rest of the datagram comes after the timeout
+        assert_eq!(defragmenter.push(&fragments[0], 0), Defragmented::Pending);
+        assert_eq!(
+            defragmenter.push(&fragments[1], 2_000_000),
+            Defragmented::Pending
+        );
+        assert_eq!(defragmenter.abandoned(), 1);
+
+        // Holding a third fragment would exceed the memory cap
+        let mut other = ipv4_tcp(&[b'w'; 3000]);
+        other[5] = 0x35;
+        let other = fragment_ipv4(&other, 1480);
+        assert_eq!(
+            defragmenter.push(&other[0], 2_000_000),
+            Defragmented::Pending
+        );
+        assert_eq!(defragmenter.abandoned(), 2);
+        assert_eq!(defragmenter.buffered(), 1480);
+        assert_eq!(
+            defragmenter.push(&fragments[2], 2_000_000),
+            Defragmented::Pending
+        );
+    }
+}
@@ -0,0 +1,1404 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, ResponseStatus};
+    use membench::record::{parse_packet, run_record, LinkType};
+    use membench::replay::ProfileReader;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    const CLIENT: [u8; 4] = [10, 0, 0, 1];
+    const SERVER: [u8; 4] = [10, 0, 0, 2];
+
+    /// IPv4 packet holding one TCP segment
+    fn ipv4_tcp(src: [u8; 4], sport: u16, dst: [u8; 4], dport: u16, payload: &[u8]) -> Vec<u8> {
+        ipv4_tcp_at(src, sport, dst, dport, 1000, payload)
+    }
+
+    /// IPv4 packet holding the TCP segment at sequence number `seq`
+    fn ipv4_tcp_at(
+        src: [u8; 4],
+        sport: u16,
+        dst: [u8; 4],
+        dport: u16,
+        seq: u32,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut tcp = Vec::new();
+        tcp.extend_from_slice(&sport.to_be_bytes());
+        tcp.extend_from_slice(&dport.to_be_bytes());
+        tcp.extend_from_slice(&seq.to_be_bytes());
+        tcp.extend_from_slice(&0u32.to_be_bytes()); // ack
+        tcp.push(8 << 4); // 32-byte header: 12 bytes of options
+        tcp.push(0x18); // PSH, ACK
+        tcp.extend_from_slice(&[0; 6]); // window, checksum, urgent pointer
+        tcp.extend_from_slice(&[1, 1, 8, 10, 0, 0, 0, 0, 0, 0, 0, 0]); // NOP, NOP, timestamps
+        tcp.extend_from_slice(payload);
+
+        let mut ip = vec![0x45, 0];
+        ip.extend_from_slice(&((20 + tcp.len()) as u16).to_be_bytes());
+        ip.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]); // id, DF, ttl, tcp, checksum
+        ip.extend_from_slice(&src);
+        ip.extend_from_slice(&dst);
+        ip.extend_from_slice(&tcp);
+        ip
+    }
+
+    /// IPv6 packet holding one TCP segment behind the given extension headers,
+    /// each given as (header type, header bytes without its next header field)
+    fn ipv6_tcp(extensions: &[(u8, Vec<u8>)], dport: u16, payload: &[u8]) -> Vec<u8> {
+        let mut tcp = ipv4_tcp(CLIENT, 40000, SERVER, dport, payload)[20..].to_vec();
+        let mut next_header = 6;
+        for (header_type, header) in extensions.iter().rev() {
+            let mut extension = vec![next_header];
+            extension.extend_from_slice(header);
+            extension.extend(tcp);
+            tcp = extension;
+            next_header = *header_type;
+        }
+
+        let mut ip = vec![0x60, 0, 0, 0];
+        ip.extend_from_slice(&(tcp.len() as u16).to_be_bytes());
+        ip.extend_from_slice(&[next_header, 64]);
+        ip.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        ip.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        ip.extend(tcp);
+        ip
+    }
+
+    fn ethernet(ip: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&0x0800u16.to_be_bytes());
+        frame.extend_from_slice(ip);
+        frame
+    }
+
+    /// Classic pcap file with one packet per frame
+    fn pcap_file(linktype: u32, frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&4u16.to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&65535u32.to_le_bytes());
+        data.extend_from_slice(&linktype.to_le_bytes());
+        for (i, frame) in frames.iter().enumerate() {
+            data.extend_from_slice(&(1_700_000_000u32 + i as u32).to_le_bytes());
+            data.extend_from_slice(&0u32.to_le_bytes());
+            data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            data.extend_from_slice(frame);
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_ethernet_frame() {
+        let frame = ethernet(&ipv4_tcp(CLIENT, 40000, SERVER, 11211, b"get foo\r\n"));
+        let segment = parse_packet(LinkType::Ethernet, &frame).unwrap();
+
+        assert_eq!(segment.payload, b"get foo\r\n");
+        assert_eq!(segment.tuple.src_ip, IpAddr::V4(Ipv4Addr::from(CLIENT)));
+        assert_eq!(segment.tuple.src_port, 40000);
+        assert_eq!(segment.tuple.dst_ip, IpAddr::V4(Ipv4Addr::from(SERVER)));
+        assert_eq!(segment.tuple.dst_port, 11211);
+        assert_eq!(segment.seq, 1000);
+        assert_eq!(segment.tuple.reversed().src_port, 11211);
+    }
+
+    #[test]
+    fn test_parse_loopback_and_raw_framings() {
+        let ip = ipv4_tcp(CLIENT, 40000, SERVER, 11211, b"mg foo v\r\n");
+
+        // macOS lo0 stores AF_INET in host (little-endian) order
+        let mut null = 2u32.to_le_bytes().to_vec();
+        null.extend_from_slice(&ip);
+        assert_eq!(
+            parse_packet(LinkType::Null, &null).unwrap().payload,
+            b"mg foo v\r\n"
+        );
+
+        let mut sll = vec![0; 14];
+        sll.extend_from_slice(&0x0800u16.to_be_bytes());
+        sll.extend_from_slice(&ip);
+        assert_eq!(
+            parse_packet(LinkType::LinuxSll, &sll).unwrap().payload,
+            b"mg foo v\r\n"
+        );
+
+        assert_eq!(
+            parse_packet(LinkType::Raw, &ip).unwrap().payload,
+            b"mg foo v\r\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_drops_padding_and_rejects_non_tcp() {
+        // Ethernet pads short frames past the IP total length
+        let mut frame = ethernet(&ipv4_tcp(CLIENT, 40000, SERVER, 11211, b""));
+        frame.extend_from_slice(&[0; 6]);
+        assert!(parse_packet(LinkType::Ethernet, &frame)
+            .unwrap()
+            .payload
+            .is_empty());
+
+        // ARP
+        let mut arp = vec![0; 12];
+        arp.extend_from_slice(&0x0806u16.to_be_bytes());
+        arp.extend_from_slice(&[0; 28]);
+        assert!(parse_packet(LinkType::Ethernet, &arp).is_none());
+
+        // UDP
+        let mut udp = ipv4_tcp(CLIENT, 40000, SERVER, 11211, b"get foo\r\n");
+        udp[9] = 17;
+        assert!(parse_packet(LinkType::Raw, &udp).is_none());
+
+        // Truncated
+        assert!(parse_packet(LinkType::Ethernet, &frame[..30]).is_none());
+    }
+
+    #[test]
+    fn test_link_type_from_dlt() {
+        assert_eq!(LinkType::from_dlt(1), Some(LinkType::Ethernet));
+        assert_eq!(LinkType::from_dlt(0), Some(LinkType::Null));
+        assert_eq!(LinkType::from_dlt(113), Some(LinkType::LinuxSll));
+        assert_eq!(LinkType::from_dlt(105), None);
+    }
+
+    #[test]
+    fn test_record_uses_exact_tcp_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let frames = vec![
+            // The key contains "get", which fooled the old payload scan
+            ethernet(&ipv4_tcp(
+                CLIENT,
+                40000,
+                SERVER,
+                11211,
+                b"ms budget 5\r\nhello\r\n",
+            )),
+            ethernet(&ipv4_tcp(SERVER, 11211, CLIENT, 40000, b"HD\r\n")),
+            ethernet(&ipv4_tcp_at(
+                CLIENT,
+                40000,
+                SERVER,
+                11211,
+                1020,
+                b"mg budget v\r\n",
+            )),
+            ethernet(&ipv4_tcp_at(
+                SERVER,
+                11211,
+                CLIENT,
+                40000,
+                1004,
+                b"VA 5\r\nhello\r\n",
+            )),
+        ];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let events = reader.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].cmd_type, CommandType::Set);
+        assert_eq!(events[0].key_size, 6);
+        assert_eq!(events[0].value_size.map(|size| size.get()), Some(5));
+        assert_eq!(events[1].cmd_type, CommandType::Get);
+        assert_eq!(events[1].key_hash, events[0].key_hash);
+
+        // Each command carries the response the server sent back
+        let responses: Vec<_> = events.iter().map(|e| e.response.unwrap()).collect();
+        assert_eq!(responses[0].status, ResponseStatus::Hit);
+        assert_eq!(responses[1].status, ResponseStatus::Hit);
+        assert_eq!(responses[1].value_size.map(|size| size.get()), Some(5));
+    }
+
+    #[test]
+    fn test_record_reassembles_split_and_reordered_requests() {
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let request = |seq: u32, payload: &[u8]| {
+            ethernet(&ipv4_tcp_at(CLIENT, 40000, SERVER, 11211, seq, payload))
+        };
+        let frames = vec![
+            // A SET whose data block spans two segments
+            request(1, b"set user:1 0 0 10\r\nhello"),
+            // The third segment arrives before the second
+            request(38, b"r:2 v\r\n"),
+            request(25, b"world\r\nmg use"),
+            // Retransmission of the first segment
+            request(1, b"set user:1 0 0 10\r\nhello"),
+        ];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let events = reader.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].cmd_type, CommandType::Set);
+        assert_eq!(events[0].key_size, 6);
+        assert_eq!(events[0].value_size.map(|size| size.get()), Some(10));
+        assert_eq!(events[1].cmd_type, CommandType::Get);
+        assert_eq!(events[1].key_size, 6);
+    }
+
+    /// Overwrite the TCP flags of an Ethernet frame built by `ethernet`
+    fn with_flags(mut frame: Vec<u8>, flags: u8) -> Vec<u8> {
+        frame[14 + 20 + 13] = flags;
+        frame
+    }
+
+    #[test]
+    fn test_record_assigns_conn_id_per_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let request = |sport: u16, seq: u32, payload: &[u8]| {
+            ethernet(&ipv4_tcp_at(CLIENT, sport, SERVER, 11211, seq, payload))
+        };
+        let frames = vec![
+            request(40000, 1, b"mg a v\r\n"),
+            request(40001, 1, b"mg b v\r\n"),
+            request(40000, 9, b"mg c v\r\n"),
+            // The server closes the first connection
+            with_flags(
+                ethernet(&ipv4_tcp_at(SERVER, 11211, CLIENT, 40000, 1, b"")),
+                0x11,
+            ),
+            // A new connection takes over the released conn_id
+            with_flags(request(40002, 99, b""), 0x02),
+            request(40002, 100, b"mg d v\r\n"),
+            request(40001, 9, b"mg e v\r\n"),
+        ];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let conn_ids: Vec<u16> = reader.events().iter().map(|e| e.conn_id).collect();
+        assert_eq!(conn_ids, vec![0, 1, 0, 0, 1]);
+        assert_eq!(reader.metadata().unique_connections, 2);
+    }
+
+    /// Binary protocol frame with no extras
+    fn binary(
+        magic: u8,
+        opcode: u8,
+        status: u16,
+        opaque: u32,
+        key: &[u8],
+        value: &[u8],
+    ) -> Vec<u8> {
+        let mut frame = vec![magic, opcode];
+        frame.extend_from_slice(&(key.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&status.to_be_bytes());
+        frame.extend_from_slice(&((key.len() + value.len()) as u32).to_be_bytes());
+        frame.extend_from_slice(&opaque.to_be_bytes());
+        frame.extend_from_slice(&[0; 8]);
+        frame.extend_from_slice(key);
+        frame.extend_from_slice(value);
+        frame
+    }
+
+    #[test]
+    fn test_record_parses_binary_protocol() {
+        use membench::profile::Protocol;
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        // A multiget: two getkq and a noop, of which only the second key hits
+        let requests = [
+            binary(0x80, 0x0d, 0, 1, b"key:1", b""),
+            binary(0x80, 0x0d, 0, 2, b"key:2", b""),
+            binary(0x80, 0x0a, 0, 3, b"", b""),
+        ]
+        .concat();
+        let responses = [
+            binary(0x81, 0x0d, 0, 2, b"key:2", b"hello"),
+            binary(0x81, 0x0a, 0, 3, b"", b""),
+        ]
+        .concat();
+        let frames = vec![
+            ethernet(&ipv4_tcp(CLIENT, 40000, SERVER, 11211, &requests)),
+            ethernet(&ipv4_tcp(SERVER, 11211, CLIENT, 40000, &responses)),
+        ];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let events = reader.events();
+        let commands: Vec<CommandType> = events.iter().map(|e| e.cmd_type).collect();
+        assert_eq!(
+            commands,
+            vec![CommandType::Get, CommandType::Get, CommandType::Noop]
+        );
+        assert!(events[0].flags.has_quiet());
+        assert_eq!(events[0].key_size, 5);
+        let responses: Vec<_> = events.iter().map(|e| e.response.unwrap()).collect();
+        assert_eq!(responses[0].status, ResponseStatus::Miss);
+        assert_eq!(responses[1].status, ResponseStatus::Hit);
+        assert_eq!(responses[1].value_size.map(|size| size.get()), Some(5));
+        assert_eq!(responses[2].status, ResponseStatus::Hit);
+        assert_eq!(
+            reader.metadata().connection_protocols.get(&0),
+            Some(&Protocol::Binary)
+        );
+    }
+
+    #[test]
+    fn test_record_stops_at_capture_limits() {
+        use membench::record::{
+            run_with_options, CaptureLimits, Passthrough, Ports, RecordOptions,
+        };
+        use std::time::Duration;
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+
+        let frames: Vec<Vec<u8>> = (0..5)
+            .map(|i| {
+                ethernet(&ipv4_tcp_at(
+                    CLIENT,
+                    40000,
+                    SERVER,
+                    11211,
+                    1 + i * 9,
+                    format!("mg k{} v\r\n", i).as_bytes(),
+                ))
+            })
+            .collect();
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        let record = |limits: CaptureLimits| {
+            let profile_path = dir.path().join("capture.profile");
+            let options = RecordOptions {
+                salt: Some(42),
+                limits,
+                ..RecordOptions::default()
+            };
+            run_with_options(
+                pcap_path.to_str().unwrap(),
+                &Ports::from(11211),
+                profile_path.to_str().unwrap(),
+                &options,
+                &mut Passthrough,
+            )
+            .unwrap();
+            // Each stop leaves a profile with valid metadata
+            let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+            assert_eq!(reader.metadata().total_events, reader.events().len() as u64);
+            reader.events().len()
+        };
+
+        assert_eq!(record(CaptureLimits::default()), 5);
+        let max_events = CaptureLimits {
+            max_events: Some(2),
+            ..CaptureLimits::default()
+        };
+        assert_eq!(record(max_events), 2);
+        // Every event of this capture takes the same number of bytes
+        let max_size = CaptureLimits {
+            max_size: Some(1),
+            ..CaptureLimits::default()
+        };
+        assert_eq!(record(max_size), 1);
+        let duration = CaptureLimits {
+            duration: Some(Duration::ZERO),
+            ..CaptureLimits::default()
+        };
+        assert_eq!(record(duration), 0);
+    }
+
+    #[test]
+    fn test_record_rotates_by_size() {
+        use membench::record::{run_with_options, Passthrough, Ports, RecordOptions, Rotation};
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let frames: Vec<Vec<u8>> = (0..5)
+            .map(|i| {
+                let request = format!("ms k{} 2\r\nhi\r\n", i);
+                ethernet(&ipv4_tcp_at(
+                    CLIENT,
+                    40000,
+                    SERVER,
+                    11211,
+                    1 + i * request.len() as u32,
+                    request.as_bytes(),
+                ))
+            })
+            .collect();
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        // Each profile is full after one event
+        let options = RecordOptions {
+            salt: Some(42),
+            rotation: Rotation {
+                every: None,
+                size: Some(2),
+            },
+            ..RecordOptions::default()
+        };
+        run_with_options(
+            pcap_path.to_str().unwrap(),
+            &Ports::from(11211),
+            profile_path.to_str().unwrap(),
+            &options,
+            &mut Passthrough,
+        )
+        .unwrap();
+
+        assert!(!profile_path.exists());
+        let counts: Vec<usize> = (0..5)
+            .map(|i| dir.path().join(format!("capture.{:04}.profile", i)))
+            .take_while(|path| path.exists())
+            .map(|path| {
+                let reader = ProfileReader::new(path.to_str().unwrap()).unwrap();
+                assert!(reader.metadata().connection_protocols.contains_key(&0));
+                reader.events().len()
+            })
+            .collect();
+        assert_eq!(counts, vec![1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_record_stores_capture_counters() {
+        use membench::profile::CaptureSummary;
+
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let mut arp = vec![0; 12];
+        arp.extend_from_slice(&0x0806u16.to_be_bytes());
+        arp.extend_from_slice(&[0; 28]);
+        let frames = vec![
+            ethernet(&ipv4_tcp(CLIENT, 40000, SERVER, 11211, b"bogus\r\n")),
+            arp,
+            ethernet(&ipv4_tcp_at(
+                CLIENT,
+                40000,
+                SERVER,
+                11211,
+                1007,
+                b"mg key v\r\n",
+            )),
+        ];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        assert_eq!(reader.events().len(), 1);
+        // Files report no kernel drops
+        assert_eq!(
+            reader.metadata().capture,
+            Some(CaptureSummary {
+                packets_received: 3,
+                packets_dropped: None,
+                packets_skipped: 1,
+                parse_errors: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_record_from_pcapng() {
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcapng");
+        let profile_path = dir.path().join("capture.profile");
+
+        // Section header, then an Ethernet and a raw IP interface
+        let block = |block_type: u32, mut body: Vec<u8>| {
+            body.resize(body.len().div_ceil(4) * 4, 0);
+            let len = (12 + body.len() as u32).to_le_bytes();
+            let mut block = block_type.to_le_bytes().to_vec();
+            block.extend_from_slice(&len);
+            block.extend(body);
+            block.extend_from_slice(&len);
+            block
+        };
+        let packet = |interface: u32, data: Vec<u8>| {
+            let mut body = interface.to_le_bytes().to_vec();
+            body.extend_from_slice(&[0; 8]);
+            body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            body.extend(data);
+            block(6, body)
+        };
+        let mut data = block(
+            0x0A0D0D0A,
+            [&0x1A2B3C4Du32.to_le_bytes()[..], &[1, 0, 0, 0], &[0xff; 8]].concat(),
+        );
+        data.extend(block(1, vec![1, 0, 0, 0, 0, 0, 0, 0]));
+        data.extend(block(1, vec![101, 0, 0, 0, 0, 0, 0, 0]));
+        data.extend(packet(
+            0,
+            ethernet(&ipv4_tcp(CLIENT, 40000, SERVER, 11211, b"mg a v\r\n")),
+        ));
+        data.extend(packet(
+            1,
+            ipv4_tcp_at(CLIENT, 40000, SERVER, 11211, 1008, b"mg b v\r\n"),
+        ));
+        // Traffic of another port is left out, as by the libpcap filter
+        data.extend(packet(
+            1,
+            ipv4_tcp(CLIENT, 40001, SERVER, 6379, b"mg c v\r\n"),
+        ));
+        // Interface statistics: 3 packets dropped on the first interface
+        let mut statistics = vec![0; 12];
+        statistics.extend_from_slice(&[5, 0, 8, 0]);
+        statistics.extend_from_slice(&3u64.to_le_bytes());
+        data.extend(block(5, statistics));
+        std::fs::write(&pcap_path, data).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        assert_eq!(reader.events().len(), 2);
+        assert!(reader
+            .events()
+            .iter()
+            .all(|e| e.cmd_type == CommandType::Get));
+        let capture = reader.metadata().capture.unwrap();
+        assert_eq!(capture.packets_received, 2);
+        assert_eq!(capture.packets_dropped, Some(3));
+    }
+
+    #[test]
+    fn test_record_keeps_packet_timestamps() {
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        // One second between packets, years before the recording
+        let frames: Vec<Vec<u8>> = (0..3)
+            .map(|i| {
+                ethernet(&ipv4_tcp_at(
+                    CLIENT,
+                    40000,
+                    SERVER,
+                    11211,
+                    1 + i * 9,
+                    format!("mg k{} v\r\n", i).as_bytes(),
+                ))
+            })
+            .collect();
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let timestamps: Vec<u64> = reader.events().iter().map(|e| e.timestamp).collect();
+        assert_eq!(
+            timestamps,
+            vec![
+                1_700_000_000_000_000,
+                1_700_000_001_000_000,
+                1_700_000_002_000_000
+            ]
+        );
+        assert_eq!(
+            reader.metadata().time_range,
+            (1_700_000_000_000_000, 1_700_000_002_000_000)
+        );
+    }
+
+    #[test]
+    fn test_record_from_stdin() {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let dir = tempfile::tempdir().unwrap();
+        let profile_path = dir.path().join("capture.profile");
+        let frames = vec![ethernet(&ipv4_tcp(
+            CLIENT,
+            40000,
+            SERVER,
+            11211,
+            b"mg key v\r\n",
+        ))];
+
+        let mut record = Command::new(env!("CARGO_BIN_EXE_membench"))
+            .args(["record", "-", profile_path.to_str().unwrap()])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        record
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(&pcap_file(1, &frames))
+            .unwrap();
+        assert!(record.wait().unwrap().success());
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        assert_eq!(reader.events().len(), 1);
+        assert_eq!(reader.events()[0].cmd_type, CommandType::Get);
+    }
+
+    #[test]
+    fn test_parse_ipv6_and_extension_headers() {
+        let ip = ipv6_tcp(&[], 11211, b"mg foo v\r\n");
+        let mut frame = vec![0; 12];
+        frame.extend_from_slice(&0x86ddu16.to_be_bytes());
+        frame.extend_from_slice(&ip);
+        let segment = parse_packet(LinkType::Ethernet, &frame).unwrap();
+        assert_eq!(segment.payload, b"mg foo v\r\n");
+        assert_eq!(segment.tuple.src_ip, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(segment.tuple.dst_port, 11211);
+
+        // Hop-by-hop options (8 bytes) and the first fragment of a datagram
+        let hop_by_hop = vec![0, 1, 4, 0, 0, 0, 0];
+        let first_fragment = vec![0, 0, 1, 0, 0, 0, 1];
+        let ip = ipv6_tcp(&[(0, hop_by_hop), (44, first_fragment)], 11211, b"x\r\n");
+        assert_eq!(parse_packet(LinkType::Raw, &ip).unwrap().payload, b"x\r\n");
+
+        // Later fragments carry no TCP header
+        let later_fragment = vec![0, 0, 8, 0, 0, 0, 1];
+        let ip = ipv6_tcp(&[(44, later_fragment)], 11211, b"x\r\n");
+        assert!(parse_packet(LinkType::Raw, &ip).is_none());
+
+        // macOS lo0 stores AF_INET6 (30) in host order
+        let mut null = 30u32.to_le_bytes().to_vec();
+        null.extend_from_slice(&ipv6_tcp(&[], 11211, b"y\r\n"));
+        assert_eq!(
+            parse_packet(LinkType::Null, &null).unwrap().payload,
+            b"y\r\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_vlan_tagged_frames() {
+        let ip = ipv4_tcp(CLIENT, 40000, SERVER, 11211, b"get foo\r\n");
+        let tagged = |tags: &[u16], ethertype: u16, ip: &[u8]| {
+            let mut frame = vec![0; 12];
+            for tag in tags {
+                frame.extend_from_slice(&tag.to_be_bytes());
+                frame.extend_from_slice(&[0, 42]); // VLAN id
+            }
+            frame.extend_from_slice(&ethertype.to_be_bytes());
+            frame.extend_from_slice(ip);
+            frame
+        };
+
+        let frame = tagged(&[0x8100], 0x0800, &ip);
+        assert_eq!(
+            parse_packet(LinkType::Ethernet, &frame).unwrap().payload,
+            b"get foo\r\n"
+        );
+        // QinQ carrying IPv6
+        let frame = tagged(&[0x88a8, 0x8100], 0x86dd, &ipv6_tcp(&[], 11211, b"z\r\n"));
+        assert_eq!(
+            parse_packet(LinkType::Ethernet, &frame).unwrap().payload,
+            b"z\r\n"
+        );
+        // A tagged frame that is not IP
+        let frame = tagged(&[0x8100], 0x0806, &[0; 28]);
+        assert!(parse_packet(LinkType::Ethernet, &frame).is_none());
+    }
+
+    #[test]
+    fn test_record_reassembles_ip_fragments() {
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        // A 3000-byte SET split over three IPv4 fragments, sent out of order
+        let value = vec![b'v'; 3000];
+        let mut request = b"set big 0 0 3000\r\n".to_vec();
+        request.extend_from_slice(&value);
+        request.extend_from_slice(b"\r\n");
+        let ip = ipv4_tcp(CLIENT, 40000, SERVER, 11211, &request);
+        let data = &ip[20..];
+        let mut frames: Vec<Vec<u8>> = data
+            .chunks(1480)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut fragment = ip[..20].to_vec();
+                fragment[2..4].copy_from_slice(&((20 + chunk.len()) as u16).to_be_bytes());
+                let more = (i + 1) * 1480 < data.len();
+                let flags_offset = (i * 1480 / 8) as u16 | if more { 0x2000 } else { 0 };
+                fragment[6..8].copy_from_slice(&flags_offset.to_be_bytes());
+                fragment.extend_from_slice(chunk);
+                ethernet(&fragment)
+            })
+            .collect();
+        frames.swap(0, 2);
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        assert_eq!(reader.events().len(), 1);
+        assert_eq!(reader.events()[0].cmd_type, CommandType::Set);
+        assert_eq!(
+            reader.events()[0].value_size.map(|size| size.get()),
+            Some(3000)
+        );
+    }
+
+    #[test]
+    fn test_record_several_server_ports() {
+        use membench::record::{run_with_options, Passthrough, Ports, RecordOptions};
+
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let frames = vec![
+            ethernet(&ipv4_tcp(CLIENT, 40000, SERVER, 11211, b"get a\r\n")),
+            ethernet(&ipv4_tcp(CLIENT, 40001, SERVER, 11215, b"get b\r\n")),
+            // Outside the selected ports
+            ethernet(&ipv4_tcp(CLIENT, 40002, SERVER, 11300, b"get c\r\n")),
+            ethernet(&ipv4_tcp(SERVER, 11215, CLIENT, 40001, b"END\r\n")),
+        ];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        let options = RecordOptions {
+            salt: Some(42),
+            ..RecordOptions::default()
+        };
+        run_with_options(
+            pcap_path.to_str().unwrap(),
+            &"11211-11218".parse::<Ports>().unwrap(),
+            profile_path.to_str().unwrap(),
+            &options,
+            &mut Passthrough,
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let mut servers: Vec<_> = reader
+            .events()
+            .iter()
+            .map(|event| {
+                (
+                    event.server_port.map(|port| port.get()),
+                    event.response.map(|response| response.status),
+                )
+            })
+            .collect();
+        servers.sort();
+        assert_eq!(
+            servers,
+            vec![
+                (Some(11211), None),
+                (Some(11215), Some(ResponseStatus::Miss))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_samples_connections() {
+        use membench::profile::SampleRate;
+        use membench::record::{
+            run_with_options, ConnectionSampler, FourTuple, Passthrough, Ports, RecordOptions,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        // One GET on each of 64 connections
+        let frames: Vec<Vec<u8>> = (0..64)
+            .map(|i| ethernet(&ipv4_tcp(CLIENT, 40000 + i, SERVER, 11211, b"get k\r\n")))
+            .collect();
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        let rate: SampleRate = "1/4".parse().unwrap();
+        let options = RecordOptions {
+            salt: Some(42),
+            sample_connections: Some(rate),
+            ..RecordOptions::default()
+        };
+        run_with_options(
+            pcap_path.to_str().unwrap(),
+            &Ports::from(11211),
+            profile_path.to_str().unwrap(),
+            &options,
+            &mut Passthrough,
+        )
+        .unwrap();
+
+        let sampler = ConnectionSampler::new(rate);
+        let kept = (0..64)
+            .filter(|i| {
+                sampler.keeps(&FourTuple {
+                    src_ip: IpAddr::V4(Ipv4Addr::from(CLIENT)),
+                    src_port: 40000 + i,
+                    dst_ip: IpAddr::V4(Ipv4Addr::from(SERVER)),
+                    dst_port: 11211,
+                })
+            })
+            .count();
+        assert!(kept > 4 && kept < 32, "kept {} of 64 connections", kept);
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        assert_eq!(reader.events().len(), kept);
+        assert_eq!(reader.metadata().unique_connections as usize, kept);
+        assert_eq!(reader.metadata().connection_sampling, Some(rate));
+    }
+
+    #[test]
+    fn test_parse_sample_rate() {
+        use membench::profile::SampleRate;
+
+        let rate: SampleRate = "1/16".parse().unwrap();
+        assert_eq!(rate.to_string(), "1/16");
+        assert_eq!(rate.fraction(), 0.0625);
+        for invalid in ["16", "0/16", "17/16", "1/0", "a/b"] {
+            assert!(
+                invalid.parse::<SampleRate>().is_err(),
+                "{:?} parsed",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_record_samples_events() {
+        use membench::record::{run_with_options, Passthrough, Ports, RecordOptions};
+
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let requests = b"get k\r\n".repeat(400);
+        let frames = vec![ethernet(&ipv4_tcp(CLIENT, 40000, SERVER, 11211, &requests))];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        let record = || {
+            let options = RecordOptions {
+                salt: Some(42),
+                sample_events: Some(0.25),
+                ..RecordOptions::default()
+            };
+            run_with_options(
+                pcap_path.to_str().unwrap(),
+                &Ports::from(11211),
+                profile_path.to_str().unwrap(),
+                &options,
+                &mut Passthrough,
+            )
+            .unwrap();
+            ProfileReader::new(profile_path.to_str().unwrap()).unwrap()
+        };
+
+        let reader = record();
+        let kept = reader.events().len();
+        assert!(kept > 50 && kept < 150, "kept {} of 400 commands", kept);
+        assert_eq!(reader.metadata().event_sampling, Some(0.25));
+        assert_eq!(reader.metadata().sampling_scale(), 4.0);
+
+        // The same salt samples the same commands
+        assert_eq!(record().events().len(), kept);
+    }
+
+    #[test]
+    fn test_record_hashes_key_segments() {
+        use membench::record::{run_with_options, Passthrough, Ports, RecordOptions};
+
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let frames = vec![ethernet(&ipv4_tcp(
+            CLIENT,
+            40000,
+            SERVER,
+            11211,
+            b"get user:1:profile\r\nget user:2:profile\r\nget flat\r\n",
+        ))];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        let options = RecordOptions {
+            salt: Some(42),
+            key_delimiter: Some(b':'),
+            ..RecordOptions::default()
+        };
+        run_with_options(
+            pcap_path.to_str().unwrap(),
+            &Ports::from(11211),
+            profile_path.to_str().unwrap(),
+            &options,
+            &mut Passthrough,
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let segments: Vec<Vec<u32>> = reader
+            .events()
+            .iter()
+            .map(|event| event.key_segments.clone().unwrap())
+            .collect();
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].len(), 3);
+        assert_eq!(segments[0][0], segments[1][0]);
+        assert_ne!(segments[0][1], segments[1][1]);
+        assert_eq!(segments[0][2], segments[1][2]);
+        assert_eq!(segments[2].len(), 1);
+    }
+
+    #[test]
+    fn test_record_anonymizes_with_key_file() {
+        use membench::record::{
+            run_with_options, Anonymizer, HashAlgorithm, Passthrough, Ports, RecordOptions,
+        };
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+        let key_path = dir.path().join("capture.key");
+
+        let frames = vec![ethernet(&ipv4_tcp(
+            CLIENT,
+            40000,
+            SERVER,
+            11211,
+            b"get testkey\r\n",
+        ))];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+        std::fs::write(&key_path, b"a secret shared by the recordings").unwrap();
+        std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+        let options = RecordOptions {
+            key_file: Some(key_path.to_str().unwrap().to_string()),
+            anonymizer: HashAlgorithm::HmacSha256,
+            ..RecordOptions::default()
+        };
+        run_with_options(
+            pcap_path.to_str().unwrap(),
+            &Ports::from(11211),
+            profile_path.to_str().unwrap(),
+            &options,
+            &mut Passthrough,
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let expected = Anonymizer::with_key(
+            HashAlgorithm::HmacSha256,
+            b"a secret shared by the recordings",
+        )
+        .hash_key(b"testkey");
+        assert_eq!(reader.events().len(), 1);
+        assert_eq!(reader.events()[0].key_hash, expected);
+
+        // A key file other users can read is refused before capture starts
+        std::fs::set_permissions(&key_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let err = run_with_options(
+            pcap_path.to_str().unwrap(),
+            &Ports::from(11211),
+            profile_path.to_str().unwrap(),
+            &options,
+            &mut Passthrough,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("chmod 600"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_size_buckets() {
+        use membench::profile::SizeBuckets;
+
+        assert_eq!("16".parse(), Ok(SizeBuckets::Multiple(16)));
+        assert_eq!("pow2".parse(), Ok(SizeBuckets::PowerOfTwo));
+        for buckets in [SizeBuckets::Multiple(16), SizeBuckets::PowerOfTwo] {
+            assert_eq!(buckets.to_string().parse(), Ok(buckets));
+        }
+        for invalid in ["0", "1", "-4", "pow3", ""] {
+            assert!(
+                invalid.parse::<SizeBuckets>().is_err(),
+                "{:?} parsed",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_size_buckets_round_up() {
+        use membench::profile::{Event, Response, SizeBucketing, SizeBuckets};
+        use std::num::NonZero;
+
+        let multiple = SizeBuckets::Multiple(16);
+        assert_eq!(multiple.bucket(0), 0);
+        assert_eq!(multiple.bucket(1), 16);
+        assert_eq!(multiple.bucket(16), 16);
+        assert_eq!(multiple.bucket(17), 32);
+        assert_eq!(multiple.label(32), "17-32");
+        assert_eq!(multiple.bucket(u32::MAX), u32::MAX);
+
+        let pow2 = SizeBuckets::PowerOfTwo;
+        assert_eq!(pow2.bucket(0), 0);
+        assert_eq!(pow2.bucket(1), 1);
+        assert_eq!(pow2.bucket(5), 8);
+        assert_eq!(pow2.bucket(1024), 1024);
+        assert_eq!(pow2.bucket(u32::MAX), u32::MAX);
+        assert_eq!(pow2.label(1), "1");
+        assert_eq!(pow2.label(8), "5-8");
+
+        let buckets = SizeBucketing {
+            key: Some(SizeBuckets::Multiple(16)),
+            value: Some(SizeBuckets::PowerOfTwo),
+        };
+        let mut event = Event {
+            value_size: NonZero::new(100),
+            response: Some(Response {
+                status: ResponseStatus::Hit,
+                value_size: NonZero::new(3000),
+            }),
+            ..Event::new(0, 0, CommandType::Get, 1, 245)
+        };
+        buckets.apply(&mut event);
+        // Rounded keys stay within the 250-byte ASCII limit
+        assert_eq!(event.key_size, 250);
+        assert_eq!(event.value_size, NonZero::new(128));
+        assert_eq!(event.response.unwrap().value_size, NonZero::new(4096));
+    }
+
+    #[test]
+    fn test_record_buckets_sizes() {
+        use membench::profile::{SizeBucketing, SizeBuckets};
+        use membench::record::{run_with_options, Passthrough, Ports, RecordOptions};
+
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let frames = vec![ethernet(&ipv4_tcp(
+            CLIENT,
+            40000,
+            SERVER,
+            11211,
+            b"set user:1 0 0 5\r\nhello\r\nget a-longer-key-name\r\n",
+        ))];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        let buckets = SizeBucketing {
+            key: Some(SizeBuckets::Multiple(16)),
+            value: Some(SizeBuckets::PowerOfTwo),
+        };
+        let options = RecordOptions {
+            salt: Some(42),
+            size_buckets: buckets,
+            ..RecordOptions::default()
+        };
+        run_with_options(
+            pcap_path.to_str().unwrap(),
+            &Ports::from(11211),
+            profile_path.to_str().unwrap(),
+            &options,
+            &mut Passthrough,
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        assert_eq!(reader.metadata().size_buckets, Some(buckets));
+        let sizes: Vec<(u32, Option<u32>)> = reader
+            .events()
+            .iter()
+            .map(|event| (event.key_size, event.value_size.map(|size| size.get())))
+            .collect();
+        assert_eq!(sizes, vec![(16, Some(8)), (32, None)]);
+    }
+
+    #[test]
+    fn test_record_plaintext_keys() {
+        use membench::record::{run_with_options, Passthrough, Ports, RecordOptions};
+
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let frames = vec![ethernet(&ipv4_tcp(
+            CLIENT,
+            40000,
+            SERVER,
+            11211,
+            b"get user:1\r\nget user:2\r\n",
+        ))];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        let record = |plaintext_keys| {
+            let options = RecordOptions {
+                salt: Some(42),
+                plaintext_keys,
+                ..RecordOptions::default()
+            };
+            run_with_options(
+                pcap_path.to_str().unwrap(),
+                &Ports::from(11211),
+                profile_path.to_str().unwrap(),
+                &options,
+                &mut Passthrough,
+            )
+            .unwrap();
+            ProfileReader::new(profile_path.to_str().unwrap()).unwrap()
+        };
+
+        let anonymized = record(false);
+        assert!(!anonymized.metadata().plaintext_keys);
+        assert!(anonymized.events().iter().all(|event| event.key.is_none()));
+
+        let plaintext = record(true);
+        assert!(plaintext.metadata().plaintext_keys);
+        let keys: Vec<&[u8]> = plaintext
+            .events()
+            .iter()
+            .map(|event| event.key.as_deref().unwrap())
+            .collect();
+        assert_eq!(keys, vec![&b"user:1"[..], &b"user:2"[..]]);
+        // Hashes are recorded as usual
+        assert_eq!(
+            plaintext.events()[0].key_hash,
+            anonymized.events()[0].key_hash
+        );
+    }
+
+    #[test]
+    fn test_record_client_ids() {
+        use membench::record::Anonymizer;
+
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let other_client = [10, 0, 0, 3];
+        let frames = vec![
+            ethernet(&ipv4_tcp(CLIENT, 40000, SERVER, 11211, b"get a\r\n")),
+            ethernet(&ipv4_tcp(other_client, 40001, SERVER, 11211, b"get b\r\n")),
+            ethernet(&ipv4_tcp(CLIENT, 40002, SERVER, 11211, b"get c\r\n")),
+        ];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let ids: Vec<u32> = reader
+            .events()
+            .iter()
+            .map(|event| event.client_id.unwrap())
+            .collect();
+        // Connections from one address share its id, hashed with the salt
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids[0], ids[2]);
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(
+            ids[0],
+            Anonymizer::new(42).hash_ip(IpAddr::V4(Ipv4Addr::from(CLIENT)))
+        );
+    }
+
+    #[test]
+    fn test_record_server_latency() {
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        // Frames are captured one second apart
+        let frames = vec![
+            ethernet(&ipv4_tcp(
+                CLIENT,
+                40000,
+                SERVER,
+                11211,
+                b"get a\r\nget b\r\n",
+            )),
+            ethernet(&ipv4_tcp(SERVER, 11211, CLIENT, 40000, b"END\r\n")),
+        ];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let latencies: Vec<Option<u32>> =
+            reader.events().iter().map(|event| event.latency).collect();
+        assert_eq!(latencies, vec![Some(1_000_000), None]);
+    }
+
+    #[test]
+    fn test_record_pairs_responses_around_quiet_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        // A noreply set and an unknown line, then a quiet meta batch
+        let requests = b"set a 0 0 1 noreply\r\nx\r\nbogus\r\nget b\r\n\
+            ms c 1 q\r\ny\r\nmg d v q\r\nmn\r\n";
+        let frames = vec![
+            ethernet(&ipv4_tcp(CLIENT, 40000, SERVER, 11211, requests)),
+            ethernet(&ipv4_tcp(
+                SERVER,
+                11211,
+                CLIENT,
+                40000,
+                b"ERROR\r\nEND\r\nMN\r\n",
+            )),
+        ];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let responses: Vec<(CommandType, Option<ResponseStatus>)> = reader
+            .events()
+            .iter()
+            .map(|event| (event.cmd_type, event.response.map(|r| r.status)))
+            .collect();
+        assert_eq!(
+            responses,
+            vec![
+                // Never answered
+                (CommandType::Set, None),
+                // The ERROR was the unknown line's
+                (CommandType::Get, Some(ResponseStatus::Miss)),
+                // Stored and missed, neither answered before the MN
+                (CommandType::Set, Some(ResponseStatus::Hit)),
+                (CommandType::Get, Some(ResponseStatus::Miss)),
+                (CommandType::Noop, Some(ResponseStatus::Hit)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_splits_multi_key_gets() {
+        let dir = tempfile::tempdir().unwrap();
+        let pcap_path = dir.path().join("capture.pcap");
+        let profile_path = dir.path().join("capture.profile");
+
+        let frames = vec![
+            ethernet(&ipv4_tcp(
+                CLIENT,
+                40000,
+                SERVER,
+                11211,
+                b"get a bb ccc\r\nget d\r\n",
+            )),
+            ethernet(&ipv4_tcp(
+                SERVER,
+                11211,
+                CLIENT,
+                40000,
+                b"VALUE ccc 0 2\r\nhi\r\nVALUE a 0 3\r\nxyz\r\nEND\r\nEND\r\n",
+            )),
+        ];
+        std::fs::write(&pcap_path, pcap_file(1, &frames)).unwrap();
+
+        run_record(
+            pcap_path.to_str().unwrap(),
+            11211,
+            profile_path.to_str().unwrap(),
+            Some(42),
+        )
+        .unwrap();
+
+        let reader = ProfileReader::new(profile_path.to_str().unwrap()).unwrap();
+        let events = reader.events();
+        let keys: Vec<(u32, Option<ResponseStatus>, Option<u32>)> = events
+            .iter()
+            .map(|event| {
+                (
+                    event.key_size,
+                    event.response.map(|r| r.status),
+                    event
+                        .response
+                        .and_then(|r| r.value_size)
+                        .map(|size| size.get()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (1, Some(ResponseStatus::Hit), Some(3)),
+                (2, Some(ResponseStatus::Miss), None),
+                (3, Some(ResponseStatus::Hit), Some(2)),
+                // The second END is the next command's
+                (1, Some(ResponseStatus::Miss), None),
+            ]
+        );
+        assert!(events
+            .iter()
+            .all(|event| event.cmd_type == CommandType::Get));
+        assert_ne!(events[0].key_hash, events[3].key_hash);
+    }
+}
@@ -0,0 +1,180 @@
+#[cfg(test)]
+mod tests {
+    use membench::record::pcapng::{is_pcapng, PcapngReader};
+
+    /// Writes pcapng blocks in either byte order
+    struct Builder {
+        big_endian: bool,
+        data: Vec<u8>,
+    }
+
+    impl Builder {
+        fn new(big_endian: bool) -> Self {
+            let mut builder = Builder {
+                big_endian,
+                data: Vec::new(),
+            };
+            let mut body = builder.u32(0x1A2B3C4D);
+            body.extend(builder.u16(1));
+            body.extend(builder.u16(0));
+            body.extend_from_slice(&[0xff; 8]); // unknown section length
+            builder.block(0x0A0D0D0A, body);
+            builder
+        }
+
+        fn u16(&self, value: u16) -> Vec<u8> {
+            if self.big_endian {
+                value.to_be_bytes().to_vec()
+            } else {
+                value.to_le_bytes().to_vec()
+            }
+        }
+
+        fn u32(&self, value: u32) -> Vec<u8> {
+            if self.big_endian {
+                value.to_be_bytes().to_vec()
+            } else {
+                value.to_le_bytes().to_vec()
+            }
+        }
+
+        fn u64(&self, value: u64) -> Vec<u8> {
+            if self.big_endian {
+                value.to_be_bytes().to_vec()
+            } else {
+                value.to_le_bytes().to_vec()
+            }
+        }
+
+        fn option(&self, code: u16, value: &[u8]) -> Vec<u8> {
+            let mut option = self.u16(code);
+            option.extend(self.u16(value.len() as u16));
+            option.extend_from_slice(value);
+            option.resize(option.len().div_ceil(4) * 4, 0);
+            option
+        }
+
+        fn block(&mut self, block_type: u32, mut body: Vec<u8>) {
+            body.resize(body.len().div_ceil(4) * 4, 0);
+            let len = 12 + body.len() as u32;
+            let header = if block_type == 0x0A0D0D0A {
+                block_type.to_le_bytes().to_vec()
+            } else {
+                self.u32(block_type)
+            };
+            let len = self.u32(len);
+            self.data.extend(header);
+            self.data.extend(&len);
+            self.data.extend(body);
+            self.data.extend(&len);
+        }
+
+        fn interface(mut self, link_type: u16, tsresol: Option<u8>) -> Self {
+            let mut body = self.u16(link_type);
+            body.extend(self.u16(0));
+            body.extend(self.u32(0));
+            if let Some(tsresol) = tsresol {
+                body.extend(self.option(9, &[tsresol]));
+                body.extend(self.option(0, &[]));
+            }
+            self.block(1, body);
+            self
+        }
+
+        fn packet(mut self, interface: u32, ts: u64, data: &[u8]) -> Self {
+            let mut body = self.u32(interface);
+            body.extend(self.u32((ts >> 32) as u32));
+            body.extend(self.u32(ts as u32));
+            body.extend(self.u32(data.len() as u32));
+            body.extend(self.u32(data.len() as u32));
+            body.extend_from_slice(data);
+            self.block(6, body);
+            self
+        }
+
+        fn simple_packet(mut self, data: &[u8]) -> Self {
+            let mut body = self.u32(data.len() as u32);
+            body.extend_from_slice(data);
+            self.block(3, body);
+            self
+        }
+
+        fn statistics(mut self, interface: u32, ifdrop: u64) -> Self {
+            let mut body = self.u32(interface);
+            body.extend(self.u32(0));
+            body.extend(self.u32(0));
+            let ifdrop = self.u64(ifdrop);
+            body.extend(self.option(5, &ifdrop));
+            body.extend(self.option(0, &[]));
+            self.block(5, body);
+            self
+        }
+    }
+
+    /// Data, link type and timestamp of a packet read
+    type Packet = (Vec<u8>, i32, Option<u64>);
+
+    fn read_all(data: &[u8]) -> (Vec<Packet>, Option<u64>) {
+        let mut reader = PcapngReader::new(data).unwrap();
+        let mut packets = Vec::new();
+        while reader.read_packet().unwrap() {
+            let packet = reader.packet().unwrap();
+            packets.push((packet.data.to_vec(), packet.datalink, packet.timestamp));
+        }
+        (packets, reader.dropped())
+    }
+
+    #[test]
+    fn test_read_pcapng_interfaces_and_timestamps() {
+        for big_endian in [false, true] {
+            let data = Builder::new(big_endian)
+                .interface(1, None)
+                // Nanosecond timestamps
+                .interface(101, Some(9))
+                .packet(0, 1_700_000_000_000_001, b"ether")
+                .packet(1, 1_700_000_000_000_002_000, b"raw ip")
+                .simple_packet(b"simple")
+                .data;
+            assert!(is_pcapng(&data));
+
+            let (packets, dropped) = read_all(&data);
+            assert_eq!(
+                packets,
+                vec![
+                    (b"ether".to_vec(), 1, Some(1_700_000_000_000_001)),
+                    (b"raw ip".to_vec(), 101, Some(1_700_000_000_000_002)),
+                    (b"simple".to_vec(), 1, None),
+                ]
+            );
+            assert_eq!(dropped, None);
+        }
+    }
+
+    #[test]
+    fn test_read_pcapng_drop_statistics() {
+        let data = Builder::new(false)
+            .interface(1, None)
+            .interface(1, None)
+            .packet(0, 1, b"a")
+            .statistics(0, 5)
+            .statistics(0, 7)
+            .statistics(1, 2)
+            .data;
+        let (packets, dropped) = read_all(&data);
+        assert_eq!(packets.len(), 1);
+        // The last statistics of each interface count
+        assert_eq!(dropped, Some(9));
+    }
+
+    #[test]
+    fn test_read_pcapng_rejects_other_formats() {
+        let pcap = 0xa1b2c3d4u32.to_le_bytes();
+        assert!(!is_pcapng(&pcap));
+        assert!(PcapngReader::new(&pcap[..]).is_err());
+
+        // A packet whose interface was never described
+        let data = Builder::new(false).packet(0, 1, b"a").data;
+        let mut reader = PcapngReader::new(&data[..]).unwrap();
+        assert!(reader.datalink().is_err());
+    }
+}
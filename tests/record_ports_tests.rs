@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests {
+    use membench::record::capture::capture_filter;
+    use membench::record::Ports;
+
+    #[test]
+    fn test_parse_port_lists_and_ranges() {
+        let ports: Ports = "11300, 11211-11214,11213-11216".parse().unwrap();
+        assert_eq!(ports.to_string(), "11211-11216,11300");
+        assert!(ports.contains(11211));
+        assert!(ports.contains(11216));
+        assert!(ports.contains(11300));
+        assert!(!ports.contains(11217));
+
+        assert_eq!(Ports::from(11211), "11211".parse().unwrap());
+        for invalid in ["", "0", "11218-11211", "memcache", "11211,", "70000"] {
+            assert!(invalid.parse::<Ports>().is_err(), "{:?} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn test_capture_filter_combines_ports() {
+        let ports: Ports = "11211,11300-11308".parse().unwrap();
+        assert_eq!(
+            ports.filter(),
+            "tcp port 11211 or tcp portrange 11300-11308"
+        );
+        assert!(capture_filter(&ports, None)
+            .starts_with("(tcp port 11211 or tcp portrange 11300-11308) or "));
+    }
+
+    #[test]
+    fn test_capture_filter_ands_user_expression() {
+        let ports = Ports::from(11211);
+        let filter = capture_filter(&ports, Some("not host 10.0.0.9"));
+        assert_eq!(
+            filter,
+            format!("({}) and (not host 10.0.0.9)", capture_filter(&ports, None))
+        );
+    }
+}
@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests {
+    use membench::record::{FourTuple, StreamReassembler, TcpSegment};
+    use std::net::{IpAddr, Ipv4Addr};
+
+    const SYN: u8 = 0x02;
+    const ACK: u8 = 0x10;
+
+    fn tuple(src_port: u16) -> FourTuple {
+        FourTuple {
+            src_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            src_port,
+            dst_ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            dst_port: 11211,
+        }
+    }
+
+    fn segment(seq: u32, flags: u8, payload: &[u8]) -> TcpSegment<'_> {
+        TcpSegment {
+            tuple: tuple(40000),
+            seq,
+            ack: 0,
+            flags,
+            payload,
+        }
+    }
+
+    /// Push `segment`, returning the stream contents it made available
+    fn push(reassembler: &mut StreamReassembler, segment: TcpSegment<'_>) -> Option<Vec<u8>> {
+        reassembler.push(&segment).map(|data| data.clone())
+    }
+
+    #[test]
+    fn test_in_order_segments_concatenate() {
+        let mut reassembler = StreamReassembler::new();
+        assert_eq!(push(&mut reassembler, segment(99, SYN, b"")), None);
+        assert_eq!(
+            push(&mut reassembler, segment(100, ACK, b"get ")),
+            Some(b"get ".to_vec())
+        );
+        assert_eq!(
+            push(&mut reassembler, segment(104, ACK, b"foo\r\n")),
+            Some(b"get foo\r\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_out_of_order_segment_waits_for_gap() {
+        let mut reassembler = StreamReassembler::new();
+        push(&mut reassembler, segment(1, ACK, b"ab"));
+        assert_eq!(push(&mut reassembler, segment(5, ACK, b"ef")), None);
+        assert_eq!(
+            push(&mut reassembler, segment(3, ACK, b"cd")),
+            Some(b"abcdef".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_retransmissions_and_overlaps_are_deduplicated() {
+        let mut reassembler = StreamReassembler::new();
+        push(&mut reassembler, segment(1, ACK, b"abcd"));
+        // Exact duplicate
+        assert_eq!(push(&mut reassembler, segment(1, ACK, b"abcd")), None);
+        // Overlapping retransmission carrying new bytes
+        assert_eq!(
+            push(&mut reassembler, segment(3, ACK, b"cdef")),
+            Some(b"abcdef".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_consumed_bytes_are_not_returned_again() {
+        let mut reassembler = StreamReassembler::new();
+        reassembler
+            .push(&segment(1, ACK, b"get a\r\nget"))
+            .unwrap()
+            .drain(..7);
+        assert_eq!(
+            push(&mut reassembler, segment(11, ACK, b" b\r\n")),
+            Some(b"get b\r\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_sequence_numbers_wrap() {
+        let mut reassembler = StreamReassembler::new();
+        push(&mut reassembler, segment(u32::MAX - 1, ACK, b"ab"));
+        assert_eq!(
+            push(&mut reassembler, segment(0, ACK, b"cd")),
+            Some(b"abcd".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_gap_is_skipped_when_too_much_is_pending() {
+        let mut reassembler = StreamReassembler::new();
+        push(&mut reassembler, segment(1, ACK, b"partial"));
+
+        // The segment at 8 is lost; everything after it piles up
+        let chunk = vec![b'x'; 64 * 1024];
+        let mut seq = 100;
+        let mut resumed = None;
+        for _ in 0..17 {
+            resumed = push(&mut reassembler, segment(seq, ACK, &chunk));
+            seq += chunk.len() as u32;
+            if resumed.is_some() {
+                break;
+            }
+        }
+
+        // The partial data before the gap is dropped, the rest is delivered
+        let resumed = resumed.expect("gap skipped");
+        assert!(resumed.iter().all(|&b| b == b'x'));
+        assert!(resumed.len() > membench::record::reassembly::MAX_PENDING_BYTES);
+    }
+
+    #[test]
+    fn test_streams_are_independent_and_removable() {
+        let mut reassembler = StreamReassembler::new();
+        push(&mut reassembler, segment(1, ACK, b"a"));
+        let other = TcpSegment {
+            tuple: tuple(40001),
+            ..segment(500, ACK, b"b")
+        };
+        assert_eq!(reassembler.push(&other).cloned(), Some(b"b".to_vec()));
+        assert_eq!(reassembler.len(), 2);
+
+        reassembler.remove(&tuple(40000));
+        assert_eq!(reassembler.len(), 1);
+    }
+}
@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event};
+    use membench::record::{EventTransform, Passthrough, TransformChain};
+
+    fn event(key_hash: u64) -> Event {
+        Event::new(1000, 1, CommandType::Get, key_hash, 8)
+    }
+
+    #[test]
+    fn test_passthrough_keeps_events() {
+        let mut transform = Passthrough;
+        let kept = transform.transform(event(7), b"key").unwrap();
+        assert_eq!(kept.key_hash, 7);
+    }
+
+    #[test]
+    fn test_closure_sees_raw_key() {
+        // Drop one tenant's traffic by key prefix
+        let mut transform =
+            |event: Event, key: &[u8]| (!key.starts_with(b"tenant-b:")).then_some(event);
+        assert!(transform.transform(event(1), b"tenant-a:42").is_some());
+        assert!(transform.transform(event(2), b"tenant-b:42").is_none());
+    }
+
+    #[test]
+    fn test_chain_applies_in_order_and_stops_on_drop() {
+        let mut chain = TransformChain::new()
+            .with(|event: Event, _: &[u8]| {
+                Some(Event {
+                    conn_id: 9,
+                    ..event
+                })
+            })
+            .with(|event: Event, _: &[u8]| event.key_hash.is_multiple_of(2).then_some(event));
+        assert_eq!(chain.len(), 2);
+
+        assert_eq!(chain.transform(event(4), b"k").map(|e| e.conn_id), Some(9));
+        assert!(chain.transform(event(5), b"k").is_none());
+    }
+}
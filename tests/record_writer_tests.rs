@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use membench::profile::{CommandType, Event, Flags};
+    use membench::profile::{CommandType, Event};
     use membench::record::ProfileWriter;
     use tempfile::NamedTempFile;
 
@@ -11,15 +11,7 @@ mod tests {
 
         let mut writer = ProfileWriter::new(path).unwrap();
 
-        let event = Event {
-            timestamp: 1000,
-            conn_id: 1,
-            cmd_type: CommandType::Get,
-            key_hash: 0x123456789,
-            key_size: 10,
-            value_size: None,
-            flags: Flags::empty(),
-        };
+        let event = Event::new(1000, 1, CommandType::Get, 0x123456789, 10);
 
         writer.write_event(&event).unwrap();
         writer.finish().unwrap();
@@ -28,4 +20,324 @@ mod tests {
         let metadata = std::fs::metadata(path).unwrap();
         assert!(metadata.len() > 0);
     }
+
+    #[test]
+    fn test_connection_protocols_round_trip() {
+        use membench::profile::Protocol;
+        use membench::replay::ProfileReader;
+
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+
+        let mut writer = ProfileWriter::new(path).unwrap();
+        writer.record_protocol(1, Protocol::Ascii);
+        writer.record_protocol(1, Protocol::Meta);
+        writer.record_protocol(2, Protocol::Binary);
+        writer.finish().unwrap();
+
+        let reader = ProfileReader::new(path).unwrap();
+        let protocols = &reader.metadata().connection_protocols;
+        assert_eq!(protocols.get(&1), Some(&Protocol::Ascii));
+        assert_eq!(protocols.get(&2), Some(&Protocol::Binary));
+    }
+
+    #[test]
+    fn test_decode_metadata_without_protocols() {
+        use membench::profile::ProfileMetadata;
+        use std::collections::HashMap;
+
+        // Layout of profiles recorded before connection protocols were tracked
+        let legacy = bincode::serialize(&(
+            0xDEADBEEFu32,
+            2u8,
+            7u64,
+            (10u64, 20u64),
+            3u32,
+            HashMap::from([(CommandType::Get, 7u64)]),
+        ))
+        .unwrap();
+
+        let metadata = ProfileMetadata::decode(&legacy).unwrap();
+        assert_eq!(metadata.total_events, 7);
+        assert_eq!(metadata.unique_connections, 3);
+        assert!(metadata.connection_protocols.is_empty());
+    }
+
+    #[test]
+    fn test_compressed_profile_round_trip() {
+        use membench::profile::{Compression, Protocol};
+        use membench::replay::{ProfileReader, ProfileStreamer};
+
+        let plain = NamedTempFile::new().unwrap();
+        let compressed = NamedTempFile::new().unwrap();
+        let plain_path = plain.path().to_str().unwrap();
+        let compressed_path = compressed.path().to_str().unwrap();
+
+        let events: Vec<Event> = (0..20_000u64)
+            .map(|i| Event::new(1000 + i, (i % 4) as u16, CommandType::Get, i % 100, 10))
+            .collect();
+        for (path, compression) in [
+            (plain_path, Compression::None),
+            (compressed_path, Compression::Deflate),
+        ] {
+            let mut writer = ProfileWriter::with_compression(path, compression).unwrap();
+            writer.record_protocol(1, Protocol::Meta);
+            for event in &events {
+                writer.write_event(event).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+
+        let plain_len = std::fs::metadata(plain_path).unwrap().len();
+        let compressed_len = std::fs::metadata(compressed_path).unwrap().len();
+        assert!(compressed_len * 4 < plain_len);
+
+        let reader = ProfileReader::new(compressed_path).unwrap();
+        assert_eq!(reader.metadata().compression, Compression::Deflate);
+        assert_eq!(reader.metadata().total_events, events.len() as u64);
+        assert_eq!(
+            reader.metadata().connection_protocols.get(&1),
+            Some(&Protocol::Meta)
+        );
+        let keys = |events: &[Event]| {
+            events
+                .iter()
+                .map(|event| (event.timestamp, event.key_hash))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(reader.events()), keys(&events));
+
+        let mut streamer = ProfileStreamer::new(compressed_path).unwrap();
+        let mut streamed = Vec::new();
+        while let Some(event) = streamer.next_event().unwrap() {
+            streamed.push(event);
+        }
+        assert_eq!(keys(&streamed), keys(&events));
+    }
+
+    #[test]
+    fn test_streamer_reads_past_its_window_and_resets() {
+        use membench::profile::Compression;
+        use membench::replay::ProfileStreamer;
+
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+        // Events with keys spanning several read windows and compressed blocks
+        let events = (0..100_000u64).map(|i| Event {
+            value_size: std::num::NonZero::new(100),
+            key: Some(format!("key:{:08}", i).into_bytes()),
+            ..Event::new(1000 + i, 1, CommandType::Set, i, 10)
+        });
+
+        for compression in [Compression::None, Compression::Deflate] {
+            let mut writer = ProfileWriter::with_compression(path, compression).unwrap();
+            for event in events.clone() {
+                writer.write_event(&event).unwrap();
+            }
+            writer.finish().unwrap();
+            if compression == Compression::None {
+                assert!(std::fs::metadata(path).unwrap().len() > 1 << 20);
+            }
+
+            let mut streamer = ProfileStreamer::new(path).unwrap();
+            for _ in 0..2 {
+                let mut streamed = 0;
+                while let Some(event) = streamer.next_event().unwrap() {
+                    assert_eq!(event.key_hash, streamed);
+                    streamed += 1;
+                }
+                assert_eq!(streamed, 100_000);
+                streamer.reset().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_mapped_file_holds_file_contents() {
+        use membench::profile::mmap::MappedFile;
+
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+        assert!(MappedFile::open(path).unwrap().is_empty());
+
+        let contents: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(path, &contents).unwrap();
+        let mapped = MappedFile::open(path).unwrap();
+        assert_eq!(&mapped[..], &contents[..]);
+        assert!(MappedFile::open("/nonexistent/profile").is_err());
+    }
+
+    #[test]
+    fn test_decode_metadata_in_the_layout_of_its_version() {
+        use membench::profile::{Compression, ProfileMetadata, Protocol};
+        use std::collections::HashMap;
+
+        // Version 2 metadata never held connection protocols: a longer
+        // layout is rejected rather than read in part
+        let fields = (
+            0xDEADBEEFu32,
+            2u8,
+            7u64,
+            (10u64, 20u64),
+            3u32,
+            HashMap::from([(CommandType::Get, 7u64)]),
+        );
+        let protocols = HashMap::from([(1u16, Protocol::Binary)]);
+        let old = bincode::serialize(&(fields.clone(), protocols.clone())).unwrap();
+        assert!(ProfileMetadata::decode(&old).is_err());
+
+        // Version 3 and 4: compression up to the plaintext key flag
+        let fields = (0xDEADBEEFu32, 3u8, fields.2, fields.3, fields.4, fields.5);
+        let old = bincode::serialize(&(
+            fields.clone(),
+            protocols,
+            Compression::Deflate,
+            None::<membench::profile::CaptureSummary>,
+            None::<membench::profile::SampleRate>,
+            Some(0.5f64),
+            None::<membench::profile::SizeBucketing>,
+            true,
+        ))
+        .unwrap();
+        let metadata = ProfileMetadata::decode(&old).unwrap();
+        assert_eq!(metadata.version, 3);
+        assert_eq!(metadata.total_events, 7);
+        assert_eq!(
+            metadata.connection_protocols.get(&1),
+            Some(&Protocol::Binary)
+        );
+        assert_eq!(metadata.compression, Compression::Deflate);
+        assert_eq!(metadata.event_sampling, Some(0.5));
+        assert!(metadata.plaintext_keys);
+        assert!(metadata.index.is_none());
+
+        // A version newer than this build is named as such
+        let newer = bincode::serialize(&(0xDEADBEEFu32, 99u8, fields.2)).unwrap();
+        let err = ProfileMetadata::decode(&newer).unwrap_err().to_string();
+        assert!(err.contains("profile version 99 is newer"), "{}", err);
+    }
+
+    #[test]
+    fn test_read_profile_up_to_last_checkpoint() {
+        use membench::profile::{Compression, Protocol};
+        use membench::replay::{ProfileReader, ProfileStreamer};
+
+        for compression in [Compression::None, Compression::Deflate] {
+            let temp = NamedTempFile::new().unwrap();
+            let path = temp.path().to_str().unwrap();
+
+            let event = |i: u64| Event::new(1000 + i, (i % 2) as u16, CommandType::Get, i, 10);
+            let mut writer = ProfileWriter::with_compression(path, compression).unwrap();
+            writer.record_protocol(1, Protocol::Binary);
+            for i in 0..10 {
+                writer.write_event(&event(i)).unwrap();
+            }
+            writer.checkpoint().unwrap();
+            for i in 10..15 {
+                writer.write_event(&event(i)).unwrap();
+            }
+            writer.checkpoint().unwrap();
+            for i in 15..20 {
+                writer.write_event(&event(i)).unwrap();
+            }
+            // Crash: the trailer is never written
+            drop(writer);
+
+            let reader = ProfileReader::new(path).unwrap();
+            assert_eq!(reader.events().len(), 15);
+            assert_eq!(reader.metadata().total_events, 15);
+            assert_eq!(reader.metadata().time_range, (1000, 1014));
+            assert_eq!(
+                reader.metadata().connection_protocols.get(&1),
+                Some(&Protocol::Binary)
+            );
+
+            let mut streamer = ProfileStreamer::new(path).unwrap();
+            let mut streamed = 0;
+            while let Some(event) = streamer.next_event().unwrap() {
+                assert_eq!(event.key_hash, streamed);
+                streamed += 1;
+            }
+            assert_eq!(streamed, 15);
+        }
+    }
+
+    #[test]
+    fn test_checkpoints_are_skipped_in_finished_profiles() {
+        use membench::replay::ProfileReader;
+
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+
+        let mut writer = ProfileWriter::new(path).unwrap();
+        for i in 0..4 {
+            writer.checkpoint().unwrap();
+            writer
+                .write_event(&Event::new(i, 0, CommandType::Set, i, 10))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let reader = ProfileReader::new(path).unwrap();
+        assert_eq!(reader.events().len(), 4);
+        assert_eq!(reader.metadata().total_events, 4);
+    }
+
+    #[test]
+    fn test_decode_version_5_metadata() {
+        use membench::profile::{Compression, IndexSection, ProfileMetadata};
+        use std::collections::HashMap;
+
+        // Version 5 added the index section, before sources and the key sketch
+        let index = IndexSection {
+            offset: 100,
+            entries: 1,
+            crc: 9,
+        };
+        let old = bincode::serialize(&(
+            (
+                0xDEADBEEFu32,
+                5u8,
+                7u64,
+                (10u64, 20u64),
+                3u32,
+                HashMap::from([(CommandType::Get, 7u64)]),
+                HashMap::<u16, membench::profile::Protocol>::new(),
+                Compression::None,
+            ),
+            None::<membench::profile::CaptureSummary>,
+            None::<membench::profile::SampleRate>,
+            None::<f64>,
+            None::<membench::profile::SizeBucketing>,
+            false,
+            Some(index),
+        ))
+        .unwrap();
+
+        let metadata = ProfileMetadata::decode(&old).unwrap();
+        assert_eq!(metadata.total_events, 7);
+        assert_eq!(metadata.index, Some(index));
+        assert!(metadata.sources.is_empty());
+        assert!(metadata.key_sketch.is_none());
+    }
+
+    #[test]
+    fn test_writer_sketches_distinct_keys() {
+        use membench::replay::ProfileReader;
+
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+        let mut writer = ProfileWriter::new(path).unwrap();
+        for i in 0..3000u64 {
+            // 1000 keys, each written three times
+            writer
+                .write_event(&Event::new(1000 + i, 1, CommandType::Get, i % 1000, 10))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let reader = ProfileReader::new(path).unwrap();
+        let estimate = reader.metadata().key_sketch.as_ref().unwrap().estimate();
+        assert!((970..=1030).contains(&estimate), "{}", estimate);
+    }
 }
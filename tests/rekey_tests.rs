@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Compression, Event, Protocol, SampleRate};
+    use membench::record::ProfileWriter;
+    use membench::rekey::rekey;
+    use membench::replay::ProfileReader;
+
+    fn event(i: u64) -> Event {
+        Event {
+            key_segments: Some(vec![7, (i % 3) as u32]),
+            key: Some(format!("user:{}", i % 3).into_bytes()),
+            client_id: Some((i % 2) as u32),
+            ..Event::new(1000 + i, (i % 2) as u16, CommandType::Get, i % 3, 10)
+        }
+    }
+
+    #[test]
+    fn test_rekey_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.profile");
+        let input = input.to_str().unwrap();
+        let rate = SampleRate {
+            numerator: 1,
+            denominator: 4,
+        };
+
+        let mut writer = ProfileWriter::with_compression(input, Compression::Deflate).unwrap();
+        writer.record_protocol(0, Protocol::Meta);
+        writer.set_connection_sampling(rate);
+        writer.set_plaintext_keys();
+        for i in 0..30 {
+            writer.write_event(&event(i)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let rekeyed = |salt: u64| {
+            let output = dir.path().join(format!("rekeyed-{}.profile", salt));
+            let output = output.to_str().unwrap().to_string();
+            let report = rekey(input, &output, salt).unwrap();
+            assert_eq!(report.events_rekeyed, 30);
+            assert!(report.plaintext_keys_removed);
+            ProfileReader::new(&output).unwrap()
+        };
+
+        let reader = rekeyed(1);
+        let original = ProfileReader::new(input).unwrap();
+        assert_eq!(reader.metadata().compression, Compression::Deflate);
+        assert_eq!(reader.metadata().connection_sampling, Some(rate));
+        assert_eq!(
+            reader.metadata().connection_protocols.get(&0),
+            Some(&Protocol::Meta)
+        );
+        assert!(!reader.metadata().plaintext_keys);
+
+        for (before, after) in original.events().iter().zip(reader.events()) {
+            assert_eq!(after.timestamp, before.timestamp);
+            assert_ne!(after.key_hash, before.key_hash);
+            assert_ne!(after.client_id, before.client_id);
+            assert!(after.key.is_none());
+        }
+        // Key identity and namespaces survive the rekey
+        let events = reader.events();
+        assert_eq!(events[0].key_hash, events[3].key_hash);
+        assert_ne!(events[0].key_hash, events[1].key_hash);
+        assert_eq!(events[0].client_id, events[2].client_id);
+        assert_ne!(events[0].client_id, events[1].client_id);
+        let segments = |i: usize| events[i].key_segments.clone().unwrap();
+        assert_eq!(segments(0)[0], segments(1)[0]);
+        assert_ne!(segments(0)[0], 7);
+        assert_eq!(segments(0)[1], segments(3)[1]);
+
+        // Another salt gives other hashes
+        assert_ne!(rekeyed(2).events()[0].key_hash, events[0].key_hash);
+    }
+
+    #[test]
+    fn test_rekey_refuses_to_overwrite_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.profile");
+        let input = input.to_str().unwrap();
+        let mut writer = ProfileWriter::new(input).unwrap();
+        writer.write_event(&event(0)).unwrap();
+        writer.finish().unwrap();
+
+        assert!(rekey(input, input, 1).is_err());
+    }
+}
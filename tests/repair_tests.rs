@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event};
+    use membench::record::ProfileWriter;
+    use membench::repair::repair;
+    use membench::replay::ProfileReader;
+    use tempfile::NamedTempFile;
+
+    fn write_profile(path: &str, count: u64) {
+        let mut writer = ProfileWriter::new(path).unwrap();
+        for i in 0..count {
+            writer
+                .write_event(&Event::new(
+                    1000 + i,
+                    (i % 3) as u16,
+                    CommandType::Get,
+                    i,
+                    10,
+                ))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_repair_truncated_profile() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        let input_path = input.path().to_str().unwrap();
+        let output_path = output.path().to_str().unwrap();
+
+        write_profile(input_path, 100);
+        // Simulate a crash: drop the trailer and half of the last event
+        let data = std::fs::read(input_path).unwrap();
+        let event_len = 2 + u16::from_le_bytes([data[0], data[1]]) as usize;
+        std::fs::write(input_path, &data[..event_len * 100 - event_len / 2]).unwrap();
+        assert!(ProfileReader::new(input_path).is_err());
+
+        let report = repair(input_path, output_path).unwrap();
+        assert_eq!(report.events_recovered, 99);
+        assert_eq!(report.bytes_discarded, (event_len - event_len / 2) as u64);
+        assert!(!report.had_trailer);
+
+        let reader = ProfileReader::new(output_path).unwrap();
+        assert_eq!(reader.events().len(), 99);
+        assert_eq!(reader.metadata().total_events, 99);
+        assert_eq!(reader.metadata().unique_connections, 3);
+        assert_eq!(reader.metadata().time_range, (1000, 1098));
+    }
+
+    #[test]
+    fn test_repair_intact_profile() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        let input_path = input.path().to_str().unwrap();
+        let output_path = output.path().to_str().unwrap();
+
+        write_profile(input_path, 10);
+        let report = repair(input_path, output_path).unwrap();
+        assert!(report.had_trailer);
+        assert_eq!(report.events_recovered, 10);
+        assert_eq!(report.bytes_discarded, 0);
+
+        assert!(repair(input_path, input_path).is_err());
+    }
+
+    #[test]
+    fn test_repair_truncated_compressed_profile() {
+        use membench::profile::compression::BLOCK_SIZE;
+        use membench::profile::Compression;
+
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        let input_path = input.path().to_str().unwrap();
+        let output_path = output.path().to_str().unwrap();
+
+        // Enough events for several blocks
+        let mut writer = ProfileWriter::with_compression(input_path, Compression::Deflate).unwrap();
+        let count = 3 * BLOCK_SIZE as u64 / 20;
+        for i in 0..count {
+            writer
+                .write_event(&Event::new(1000 + i, 0, CommandType::Get, i, 10))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        // Drop the trailer, the index and the tail of the last block
+        let events_end = ProfileReader::new(input_path)
+            .unwrap()
+            .metadata()
+            .index
+            .unwrap()
+            .offset as usize;
+        let data = std::fs::read(input_path).unwrap();
+        std::fs::write(input_path, &data[..events_end - 100]).unwrap();
+        assert!(ProfileReader::new(input_path).is_err());
+
+        let report = repair(input_path, output_path).unwrap();
+        assert!(!report.had_trailer);
+        assert!(report.events_recovered > 0 && report.events_recovered < count);
+        assert!(report.bytes_discarded > 0);
+
+        let reader = ProfileReader::new(output_path).unwrap();
+        assert_eq!(reader.metadata().compression, Compression::Deflate);
+        assert_eq!(reader.events().len() as u64, report.events_recovered);
+        for (i, event) in reader.events().iter().enumerate() {
+            assert_eq!(event.key_hash, i as u64);
+        }
+    }
+
+    #[test]
+    fn test_repair_keeps_protocols_of_checkpoint() {
+        use membench::profile::Protocol;
+
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        let input_path = input.path().to_str().unwrap();
+        let output_path = output.path().to_str().unwrap();
+
+        let mut writer = ProfileWriter::new(input_path).unwrap();
+        writer.record_protocol(0, Protocol::Ascii);
+        for i in 0..10 {
+            if i == 5 {
+                writer.checkpoint().unwrap();
+            }
+            writer
+                .write_event(&Event::new(i, 0, CommandType::Get, i, 10))
+                .unwrap();
+        }
+        drop(writer);
+
+        // Events past the last checkpoint are recovered too
+        let report = repair(input_path, output_path).unwrap();
+        assert_eq!(report.events_recovered, 10);
+        assert_eq!(report.bytes_discarded, 0);
+
+        let reader = ProfileReader::new(output_path).unwrap();
+        assert_eq!(reader.events().len(), 10);
+        assert_eq!(
+            reader.metadata().connection_protocols.get(&0),
+            Some(&Protocol::Ascii)
+        );
+    }
+}
@@ -1,28 +1,15 @@
 #[cfg(test)]
 mod tests {
-    use membench::profile::{CommandType, Event, Flags};
+    use membench::profile::{CommandType, Event};
     use membench::replay::DistributionAnalyzer;
 
     #[test]
     fn test_analyze_command_distribution() {
         let events = vec![
+            Event::new(1000, 1, CommandType::Get, 0x1, 10),
             Event {
-                timestamp: 1000,
-                conn_id: 1,
-                cmd_type: CommandType::Get,
-                key_hash: 0x1,
-                key_size: 10,
-                value_size: None,
-                flags: Flags::empty(),
-            },
-            Event {
-                timestamp: 2000,
-                conn_id: 1,
-                cmd_type: CommandType::Set,
-                key_hash: 0x2,
-                key_size: 20,
                 value_size: std::num::NonZero::new(50),
-                flags: Flags::empty(),
+                ..Event::new(2000, 1, CommandType::Set, 0x2, 20)
             },
         ];
 
@@ -42,24 +29,8 @@ mod tests {
     #[test]
     fn test_analyze_hit_rate() {
         let events = vec![
-            Event {
-                timestamp: 1000,
-                conn_id: 1,
-                cmd_type: CommandType::Get,
-                key_hash: 0x1,
-                key_size: 10,
-                value_size: None,
-                flags: Flags::empty(),
-            },
-            Event {
-                timestamp: 2000,
-                conn_id: 1,
-                cmd_type: CommandType::Get,
-                key_hash: 0x2,
-                key_size: 10,
-                value_size: None,
-                flags: Flags::empty(),
-            },
+            Event::new(1000, 1, CommandType::Get, 0x1, 10),
+            Event::new(2000, 1, CommandType::Get, 0x2, 10),
         ];
 
         let analysis = DistributionAnalyzer::analyze(&events);
@@ -75,23 +46,10 @@ mod tests {
     #[test]
     fn test_analyze_size_distributions() {
         let events = vec![
+            Event::new(1000, 1, CommandType::Get, 0x1, 10),
             Event {
-                timestamp: 1000,
-                conn_id: 1,
-                cmd_type: CommandType::Get,
-                key_hash: 0x1,
-                key_size: 10,
-                value_size: None,
-                flags: Flags::empty(),
-            },
-            Event {
-                timestamp: 2000,
-                conn_id: 1,
-                cmd_type: CommandType::Set,
-                key_hash: 0x2,
-                key_size: 20,
                 value_size: std::num::NonZero::new(50),
-                flags: Flags::empty(),
+                ..Event::new(2000, 1, CommandType::Set, 0x2, 20)
             },
         ];
 
@@ -113,4 +71,33 @@ mod tests {
             .iter()
             .any(|(size, count)| *size == 50 && *count == 1));
     }
+
+    #[test]
+    fn test_analyze_key_frequencies() {
+        let events: Vec<Event> = [0x1, 0x2, 0x1, 0x3, 0x1, 0x2]
+            .iter()
+            .enumerate()
+            .map(|(i, &key_hash)| Event::new(1000 * i as u64, 1, CommandType::Get, key_hash, 10))
+            .collect();
+
+        let analysis = DistributionAnalyzer::analyze(&events);
+
+        assert_eq!(analysis.key_frequencies, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_analyze_inter_arrival_per_connection() {
+        // conn 1: gaps 100, 100; conn 2: gap 3000 (interleaving must not matter)
+        let events: Vec<Event> = [(1, 1000), (2, 1050), (1, 1100), (1, 1200), (2, 4050)]
+            .iter()
+            .map(|&(conn_id, timestamp)| Event::new(timestamp, conn_id, CommandType::Get, 0x1, 10))
+            .collect();
+
+        let analysis = DistributionAnalyzer::analyze(&events);
+        let mut dist = analysis.inter_arrival_distribution.clone();
+        dist.sort_unstable();
+
+        assert_eq!(dist, vec![(128, 2), (4096, 1)]);
+        assert!(analysis.mean_inter_arrival_micros().is_some());
+    }
 }
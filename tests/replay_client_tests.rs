@@ -19,4 +19,655 @@ mod tests {
         let result = ReplayClient::new("127.0.0.1:11211", ProtocolMode::Meta).await;
         let _ = result;
     }
+
+    #[tokio::test]
+    async fn test_keepalive_uses_protocol_noop() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 64];
+            let n = socket.read(&mut buf).await.unwrap();
+            socket.write_all(b"MN\r\n").await.unwrap();
+            buf[..n].to_vec()
+        });
+
+        let mut client = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        client.send_keepalive().await.unwrap();
+        assert_eq!(server.await.unwrap(), b"mn\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_establish_connections_in_parallel() {
+        use membench::replay::{establish_connections, ConnectLimits};
+        use std::time::Duration;
+
+        // The backlog holds the connections: nothing needs to accept them
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let connections: Vec<_> = (0..50).map(|id| (id, ProtocolMode::Meta)).collect();
+        let limits = ConnectLimits {
+            parallelism: 8,
+            timeout: Duration::from_secs(5),
+        };
+
+        let clients = establish_connections(&addr, &connections, limits)
+            .await
+            .unwrap();
+        assert_eq!(clients.len(), 50);
+        assert!(clients.contains_key(&49));
+    }
+
+    #[tokio::test]
+    async fn test_establish_connections_reports_unreachable() {
+        use membench::replay::{establish_connections, ConnectLimits};
+
+        // A port that was just released refuses connections
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let connections: Vec<_> = (0..3).map(|id| (id, ProtocolMode::Ascii)).collect();
+
+        let err = establish_connections(&addr, &connections, ConnectLimits::default())
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("3 of 3 connections"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_commands_replay_as_recorded() {
+        use membench::profile::{CommandType, Event};
+        use membench::record::MemcacheParser;
+        use tokio::io::AsyncReadExt;
+
+        for mode in [ProtocolMode::Ascii, ProtocolMode::Meta] {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap().to_string();
+            let server = tokio::spawn(async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut received = Vec::new();
+                socket.read_to_end(&mut received).await.unwrap();
+                received
+            });
+
+            // ASCII has no noop of its own (it replays as `version`)
+            let commands = CommandType::ALL
+                .into_iter()
+                .filter(|c| *c != CommandType::Noop);
+            let mut client = ReplayClient::new(&addr, mode).await.unwrap();
+            for cmd_type in commands.clone() {
+                let event = Event {
+                    value_size: cmd_type
+                        .has_value()
+                        .then(|| std::num::NonZero::new(3).unwrap()),
+                    ttl: std::num::NonZero::new(90),
+                    ..Event::new(0, 0, cmd_type, 0xfeed, 12)
+                };
+                client.send_command(&event).await.unwrap();
+            }
+            drop(client);
+
+            // Recording the replayed traffic gives back the same commands
+            let received = server.await.unwrap();
+            let parser = MemcacheParser::new();
+            let mut rest = &received[..];
+            for cmd_type in commands {
+                let (cmd, after) = parser.parse_command(rest).unwrap();
+                assert_eq!(cmd.cmd_type, cmd_type, "{:?} in {:?} mode", cmd_type, mode);
+                assert_eq!(cmd.value_size, cmd_type.has_value().then_some(3));
+                assert_eq!(cmd.key_range.len(), 12);
+                // Every command but get, delete and incr/decr carries the TTL
+                let expected_exptime = match cmd_type {
+                    CommandType::Get
+                    | CommandType::Delete
+                    | CommandType::Incr
+                    | CommandType::Decr => 0,
+                    _ => 90,
+                };
+                assert_eq!(
+                    cmd.exptime, expected_exptime,
+                    "{:?} in {:?} mode",
+                    cmd_type, mode
+                );
+                rest = match cmd.value_size {
+                    Some(size) => &after[size as usize + 2..],
+                    None => after,
+                };
+            }
+            assert!(rest.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_keys_replay_as_recorded() {
+        use membench::profile::{CommandType, Event};
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            socket.read_to_end(&mut received).await.unwrap();
+            received
+        });
+
+        let event = |key: Option<&[u8]>| Event {
+            key: key.map(|key| key.to_vec()),
+            ..Event::new(0, 0, CommandType::Get, 0xfeed, 8)
+        };
+        let mut client = ReplayClient::new(&addr, ProtocolMode::Ascii).await.unwrap();
+        client
+            .send_command(&event(Some(b"user:123")))
+            .await
+            .unwrap();
+        // A key that is not valid text falls back to a generated one
+        client
+            .send_command(&event(Some(b"\xff\xfe")))
+            .await
+            .unwrap();
+        drop(client);
+
+        let received = String::from_utf8(server.await.unwrap()).unwrap();
+        assert_eq!(received, "get user:123\r\nget 00000000\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_key_prefix_prepended_within_key_limit() {
+        use membench::profile::{CommandType, Event};
+        use membench::replay::client::MAX_KEY_LEN;
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            socket.read_to_end(&mut received).await.unwrap();
+            received
+        });
+
+        let event = |key_size: u32, key: Option<&[u8]>| Event {
+            key: key.map(|key| key.to_vec()),
+            ..Event::new(0, 0, CommandType::Get, 0xfeed, key_size)
+        };
+        let mut client = ReplayClient::new(&addr, ProtocolMode::Ascii)
+            .await
+            .unwrap()
+            .with_key_prefix("run1:");
+        client.send_command(&event(8, None)).await.unwrap();
+        client
+            .send_command(&event(8, Some(b"user:123")))
+            .await
+            .unwrap();
+        // The longest keys give up room for the prefix
+        client
+            .send_command(&event(MAX_KEY_LEN as u32, None))
+            .await
+            .unwrap();
+        drop(client);
+
+        let received = String::from_utf8(server.await.unwrap()).unwrap();
+        let keys: Vec<&str> = received
+            .lines()
+            .map(|line| line.strip_prefix("get ").unwrap())
+            .collect();
+        assert_eq!(keys[0], "run1:00000000");
+        assert_eq!(keys[1], "run1:user:123");
+        assert!(keys[2].starts_with("run1:0000"));
+        assert_eq!(keys[2].len(), MAX_KEY_LEN);
+    }
+
+    #[test]
+    fn test_ttl_policy_scales_and_caps() {
+        use membench::replay::TtlPolicy;
+        use std::num::NonZero;
+        use std::time::Duration;
+
+        let ttl = NonZero::new(100);
+        assert_eq!(TtlPolicy::default().apply(ttl), ttl);
+        assert_eq!(TtlPolicy::default().apply(None), None);
+
+        let scaled = TtlPolicy {
+            scale: 0.5,
+            max: Some(Duration::from_secs(30)),
+        };
+        assert_eq!(scaled.apply(NonZero::new(40)), NonZero::new(20));
+        assert_eq!(scaled.apply(ttl), NonZero::new(30));
+        // A short TTL scaled down still expires rather than living forever
+        assert_eq!(scaled.apply(NonZero::new(1)), NonZero::new(1));
+
+        let disabled = TtlPolicy {
+            scale: 0.0,
+            max: None,
+        };
+        assert_eq!(disabled.apply(ttl), None);
+    }
+
+    #[tokio::test]
+    async fn test_next_response_frames_pipelined_responses() {
+        use tokio::io::AsyncWriteExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            socket
+                .write_all(b"VA 2\r\nhi\r\nEN\r\nHD\r\n")
+                .await
+                .unwrap();
+            socket
+        });
+
+        let mut client = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        let mut responses = Vec::new();
+        while responses.len() < 3 {
+            client.receive().await.unwrap();
+            while let Some(response) = client.next_response() {
+                responses.push(response);
+            }
+        }
+        assert_eq!(
+            responses,
+            vec![
+                b"VA 2\r\nhi\r\n".to_vec(),
+                b"EN\r\n".to_vec(),
+                b"HD\r\n".to_vec()
+            ]
+        );
+        assert!(client.is_miss(&responses[1]));
+        drop(server);
+    }
+
+    /// Replay three GETs through a connection task configured by `config`,
+    /// against a server that answers only once all three are in. The first
+    /// GET was due 50ms ago. Returns the GETs completed, their misses and the
+    /// highest latency (µs).
+    async fn replay_three_gets(
+        config: membench::replay::connection_task::ConnectionConfig,
+    ) -> (u64, u64, u64) {
+        use membench::profile::{CommandType, Event};
+        use membench::replay::{spawn_connection_task, ConnectionMessage};
+        use std::time::{Duration, Instant};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::sync::mpsc;
+
+        // A connection waiting for each response would stall
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 256];
+            while received.iter().filter(|&&b| b == b'\n').count() < 3 {
+                let n = socket.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket.write_all(b"EN\r\nEN\r\nEN\r\n").await.unwrap();
+            socket
+        });
+
+        let client = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        let (tx, rx) = mpsc::channel(8);
+        let (stats_tx, mut stats_rx) = mpsc::channel(8);
+        let task = spawn_connection_task(
+            client,
+            &addr,
+            rx,
+            stats_tx,
+            1,
+            config,
+            tokio_util::sync::CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        let due = Instant::now() - Duration::from_millis(50);
+        for (key_hash, intended) in [(1, due), (2, Instant::now()), (3, Instant::now())] {
+            let event = Event::new(0, 1, CommandType::Get, key_hash, 8);
+            tx.send(ConnectionMessage::Event(event, intended))
+                .await
+                .unwrap();
+        }
+        drop(tx);
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("a pipelined connection does not wait for each response")
+            .unwrap()
+            .unwrap();
+
+        let (mut gets, mut misses, mut max_latency) = (0, 0, 0);
+        while let Some(snapshot) = stats_rx.recv().await {
+            gets += snapshot
+                .success_counts
+                .get(&CommandType::Get)
+                .copied()
+                .unwrap_or(0);
+            misses += snapshot.get_misses;
+            if let Some(histogram) = snapshot.histograms.get(&CommandType::Get) {
+                max_latency = max_latency.max(histogram.max());
+            }
+        }
+        drop(server);
+        (gets, misses, max_latency)
+    }
+
+    #[tokio::test]
+    async fn test_open_loop_pipelines_commands() {
+        use membench::replay::connection_task::ConnectionConfig;
+
+        let config = ConnectionConfig {
+            pipeline_depth: 8,
+            open_loop: true,
+            ..ConnectionConfig::new(ProtocolMode::Meta)
+        };
+        let (gets, misses, max_latency) = replay_three_gets(config).await;
+        assert_eq!((gets, misses), (3, 3));
+        // Latency counts from when the first GET was due
+        assert!(max_latency >= 50_000, "{}", max_latency);
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_depth_times_from_send() {
+        use membench::replay::connection_task::ConnectionConfig;
+
+        let config = ConnectionConfig {
+            pipeline_depth: 3,
+            ..ConnectionConfig::new(ProtocolMode::Meta)
+        };
+        let (gets, misses, max_latency) = replay_three_gets(config).await;
+        assert_eq!((gets, misses), (3, 3));
+        // Closed loop: latency counts from when each GET went out
+        assert!(max_latency < 50_000, "{}", max_latency);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_connection_completes_commands_in_flight() {
+        use membench::profile::{CommandType, Event};
+        use membench::replay::connection_task::ConnectionConfig;
+        use membench::replay::{spawn_connection_task, ConnectionMessage};
+        use std::time::{Duration, Instant};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::sync::{mpsc, oneshot};
+        use tokio_util::sync::CancellationToken;
+
+        // The server answers only once the replay is stopping
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (received_tx, received_rx) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 256];
+            while received.iter().filter(|&&b| b == b'\n').count() < 2 {
+                let n = socket.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            received_tx.send(()).unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            socket.write_all(b"EN\r\nEN\r\n").await.unwrap();
+            socket
+        });
+
+        let client = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        let (tx, rx) = mpsc::channel(8);
+        let (stats_tx, mut stats_rx) = mpsc::channel(8);
+        let cancel = CancellationToken::new();
+        let config = ConnectionConfig {
+            pipeline_depth: 4,
+            ..ConnectionConfig::new(ProtocolMode::Meta)
+        };
+        let task = spawn_connection_task(client, &addr, rx, stats_tx, 1, config, cancel.clone())
+            .await
+            .unwrap();
+        for key_hash in [1, 2] {
+            let event = Event::new(0, 1, CommandType::Get, key_hash, 8);
+            tx.send(ConnectionMessage::Event(event, Instant::now()))
+                .await
+                .unwrap();
+        }
+
+        received_rx.await.unwrap();
+        cancel.cancel();
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        let mut gets = 0;
+        while let Some(snapshot) = stats_rx.recv().await {
+            gets += snapshot
+                .success_counts
+                .get(&CommandType::Get)
+                .copied()
+                .unwrap_or(0);
+        }
+        assert_eq!(gets, 2);
+        drop((tx, server));
+    }
+
+    #[tokio::test]
+    async fn test_binary_protocol_round_trip() {
+        use membench::profile::{CommandType, Event};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 24 + 8];
+            socket.read_exact(&mut request).await.unwrap();
+            // Key not found, echoing the opaque
+            let mut response = vec![0x81, request[1], 0, 0, 0, 0, 0x00, 0x01];
+            response.extend_from_slice(&[0; 4]);
+            response.extend_from_slice(&request[12..16]);
+            response.extend_from_slice(&[0; 8]);
+            socket.write_all(&response).await.unwrap();
+            request
+        });
+
+        let mut client = ReplayClient::new(&addr, ProtocolMode::Binary)
+            .await
+            .unwrap();
+        let event = Event::new(0, 1, CommandType::Get, 0xabcd, 8);
+        client.send_command(&event).await.unwrap();
+        let response = client.read_response().await.unwrap();
+        assert_eq!(response.len(), 24);
+        assert!(client.is_miss(&response));
+
+        let request = server.await.unwrap();
+        assert_eq!(&request[..2], &[0x80, 0x00]);
+        assert_eq!(&request[2..4], &8u16.to_be_bytes());
+        assert_eq!(&request[24..], b"00000000");
+        assert_eq!("binary".parse::<ProtocolMode>(), Ok(ProtocolMode::Binary));
+    }
+
+    #[tokio::test]
+    async fn test_coalesced_gets_split_multi_get_response() {
+        use membench::profile::{CommandType, Event, Response, ResponseStatus};
+        use membench::replay::connection_task::ConnectionConfig;
+        use membench::replay::{spawn_connection_task, ConnectionMessage};
+        use std::num::NonZero;
+        use std::time::{Duration, Instant};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::sync::mpsc;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 256];
+            while !received.ends_with(b"\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket
+                .write_all(b"VALUE a 0 2\r\nxx\r\nVALUE c 0 3\r\nyyy\r\nEND\r\n")
+                .await
+                .unwrap();
+            (socket, received)
+        });
+
+        let client = ReplayClient::new(&addr, ProtocolMode::Ascii).await.unwrap();
+        let (tx, rx) = mpsc::channel(8);
+        let (stats_tx, mut stats_rx) = mpsc::channel(8);
+        let config = ConnectionConfig {
+            coalesce_gets: Some(Duration::from_millis(50)),
+            validate: Some(10),
+            ..ConnectionConfig::new(ProtocolMode::Ascii)
+        };
+        let recorded = [
+            (b"a", ResponseStatus::Hit, NonZero::new(2)),
+            (b"b", ResponseStatus::Miss, None),
+            (b"c", ResponseStatus::Hit, NonZero::new(3)),
+        ];
+        for (key, status, value_size) in recorded {
+            let event = Event {
+                response: Some(Response { status, value_size }),
+                key: Some(key.to_vec()),
+                ..Event::new(0, 1, CommandType::Get, 0, 1)
+            };
+            tx.send(ConnectionMessage::Event(event, Instant::now()))
+                .await
+                .unwrap();
+        }
+        drop(tx);
+        let task = spawn_connection_task(
+            client,
+            &addr,
+            rx,
+            stats_tx,
+            1,
+            config,
+            tokio_util::sync::CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        let (_, received) = server.await.unwrap();
+        assert_eq!(received, b"get a b c\r\n");
+        let (mut gets, mut misses, mut multi_gets, mut coalesced) = (0, 0, 0, 0);
+        let mut mismatches = 0;
+        while let Some(snapshot) = stats_rx.recv().await {
+            gets += snapshot
+                .success_counts
+                .get(&CommandType::Get)
+                .copied()
+                .unwrap_or(0);
+            misses += snapshot.get_misses;
+            multi_gets += snapshot.multi_gets;
+            coalesced += snapshot.coalesced_gets;
+            mismatches += snapshot.validation.unwrap().total_mismatches();
+        }
+        // Each key gets its own outcome out of the multi-value response
+        assert_eq!((gets, misses), (3, 1));
+        assert_eq!((multi_gets, coalesced), (1, 3));
+        assert_eq!(mismatches, 0);
+    }
+
+    #[tokio::test]
+    async fn test_quiet_commands_skip_their_responses() {
+        use membench::profile::{CommandType, Event, Flags};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let event = |cmd_type, flags| Event {
+            flags,
+            value_size: std::num::NonZero::new(3),
+            key: Some(b"k".to_vec()),
+            ..Event::new(0, 1, cmd_type, 0, 1)
+        };
+        let quiet_set = event(CommandType::Set, Flags::empty().with_quiet());
+        let get = event(CommandType::Get, Flags::empty());
+
+        // Meta: a quiet command is still answered on errors, with its opaque
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 256];
+            while received.iter().filter(|&&b| b == b'\n').count() < 3 {
+                let n = socket.read(&mut buf).await.unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            socket.write_all(b"NS Oquiet\r\nEN\r\n").await.unwrap();
+            (socket, received)
+        });
+        let mut client = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        assert!(client.is_quiet(&quiet_set));
+        assert!(!client.is_quiet(&get));
+        client.send_command(&quiet_set).await.unwrap();
+        client.send_command(&get).await.unwrap();
+        assert_eq!(client.read_response().await.unwrap(), b"EN\r\n");
+        let (_, received) = server.await.unwrap();
+        assert_eq!(received, b"ms k 3 q Oquiet\r\nxxx\r\nmg k v\r\n");
+
+        // ASCII: noreply where the command allows it
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            socket.read_to_end(&mut received).await.unwrap();
+            received
+        });
+        let mut client = ReplayClient::new(&addr, ProtocolMode::Ascii).await.unwrap();
+        let quiet_get = event(CommandType::Get, Flags::empty().with_quiet());
+        assert!(!client.is_quiet(&quiet_get));
+        client.send_command(&quiet_set).await.unwrap();
+        client
+            .send_command(&event(CommandType::Delete, Flags::empty().with_quiet()))
+            .await
+            .unwrap();
+        client.send_command(&quiet_get).await.unwrap();
+        drop(client);
+        let received = String::from_utf8(server.await.unwrap()).unwrap();
+        assert_eq!(
+            received,
+            "set k 0 0 3 noreply\r\nxxx\r\ndelete k noreply\r\nget k\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unix_socket_target() {
+        use membench::profile::{CommandType, Event};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("memcached.sock");
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; b"mg 00000000 v\r\n".len()];
+            socket.read_exact(&mut request).await.unwrap();
+            socket.write_all(b"EN\r\n").await.unwrap();
+            request
+        });
+
+        let target = format!("unix:{}", path.display());
+        let mut client = ReplayClient::new(&target, ProtocolMode::Meta)
+            .await
+            .unwrap();
+        let event = Event::new(0, 1, CommandType::Get, 0, 8);
+        client.send_command(&event).await.unwrap();
+        let response = client.read_response().await.unwrap();
+        assert!(client.is_miss(&response));
+        assert_eq!(server.await.unwrap(), b"mg 00000000 v\r\n");
+
+        // A socket path nothing listens on fails to connect
+        let missing = format!("unix:{}", dir.path().join("missing.sock").display());
+        assert!(ReplayClient::new(&missing, ProtocolMode::Meta)
+            .await
+            .is_err());
+    }
 }
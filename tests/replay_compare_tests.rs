@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::CommandType;
+    use membench::replay::stats::{AggregatedStats, ConnectionStats};
+    use membench::replay::LatencyPairing;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pairing_matches_commands_in_order() {
+        let mut pairing = LatencyPairing::default();
+        // B lags behind A: its latencies arrive later, and in smaller batches
+        pairing.add(1, 0, vec![Some(100), Some(200), None, Some(300)]);
+        pairing.add(2, 1, vec![Some(50)]);
+        pairing.add(1, 1, vec![Some(90)]);
+        assert_eq!(
+            (pairing.b_faster, pairing.b_slower, pairing.ties),
+            (1, 0, 0)
+        );
+        pairing.add(1, 1, vec![Some(250), Some(10), Some(300)]);
+
+        // The command in A's warmup is left out
+        assert_eq!(
+            (pairing.b_faster, pairing.b_slower, pairing.ties),
+            (1, 1, 1)
+        );
+        assert_eq!(pairing.compared(), 3);
+    }
+
+    #[test]
+    fn test_comparison_report() {
+        let mut agg = AggregatedStats::new();
+        agg.set_target_names(vec![
+            "current:11211".to_string(),
+            "candidate:11211".to_string(),
+        ]);
+
+        let mut a = ConnectionStats::new(1).with_latency_log();
+        let mut b = ConnectionStats::new(1).with_target(1).with_latency_log();
+        for i in 1..=100 {
+            a.record_success(CommandType::Get, Duration::from_micros(100 + i));
+            // The candidate regresses on one command in ten
+            let regression = if i % 10 == 0 { 1000 } else { 0 };
+            b.record_success(CommandType::Get, Duration::from_micros(90 + i + regression));
+        }
+        agg.merge(a.snapshot());
+        agg.merge(b.snapshot());
+
+        let report = agg.comparison().unwrap();
+        assert_eq!(report.target_a, "current:11211");
+        assert_eq!(report.target_b, "candidate:11211");
+        assert_eq!((report.b_faster, report.b_slower, report.ties), (90, 10, 0));
+        let p50 = &report.percentiles[0];
+        assert_eq!(p50.percentile, 50.0);
+        assert!(p50.delta_micros < 0, "{:?}", p50);
+        let max = report.percentiles.last().unwrap();
+        assert!(max.delta_micros > 900, "{:?}", max);
+        assert!(max.delta_percent() > 400.0);
+        assert!(agg.to_json().unwrap().contains("\"comparison\""));
+
+        // Without mirroring there is nothing to compare
+        let mut plain = AggregatedStats::new();
+        let mut stats = ConnectionStats::new(1);
+        stats.record_success(CommandType::Get, Duration::from_micros(100));
+        plain.merge(stats.snapshot());
+        assert!(plain.comparison().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mirror_sends_every_message_to_both_targets() {
+        use membench::profile::Event;
+        use membench::replay::compare::mirror;
+        use membench::replay::ConnectionMessage;
+        use std::time::Instant;
+        use tokio::sync::mpsc;
+
+        let (tx, rx) = mpsc::channel(16);
+        let (to_a, mut at_a) = mpsc::channel(16);
+        let (to_b, mut at_b) = mpsc::channel(16);
+        let router = tokio::spawn(mirror(rx, vec![to_a, to_b], None));
+        for key_hash in 0..5 {
+            let event = Event::new(0, 1, CommandType::Get, key_hash, 8);
+            tx.send(ConnectionMessage::Event(event, Instant::now()))
+                .await
+                .unwrap();
+        }
+        tx.send(ConnectionMessage::EndOfIteration).await.unwrap();
+        drop(tx);
+        router.await.unwrap();
+
+        for queue in [&mut at_a, &mut at_b] {
+            let mut keys = Vec::new();
+            let mut ends = 0;
+            while let Some(message) = queue.recv().await {
+                match message {
+                    ConnectionMessage::Event(event, _) => keys.push(event.key_hash),
+                    ConnectionMessage::EndOfIteration => ends += 1,
+                }
+            }
+            assert_eq!(keys, vec![0, 1, 2, 3, 4]);
+            assert_eq!(ends, 1);
+        }
+    }
+}
@@ -0,0 +1,49 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event};
+    use membench::replay::connection_report;
+    use membench::replay::connections::busiest_share;
+
+    fn event(timestamp: u64, conn_id: u16, cmd_type: CommandType, key_hash: u64) -> Event {
+        Event::new(timestamp, conn_id, cmd_type, key_hash, 10)
+    }
+
+    #[test]
+    fn test_connections_are_reported_busiest_first() {
+        let mut events = Vec::new();
+        // Connection 1: 8 gets over keys 0..4, for 2 seconds
+        for i in 0..8 {
+            events.push(event(1_000_000 + i * 250_000, 1, CommandType::Get, i % 4));
+        }
+        // Connection 2: 2 sets of keys 3 and 10, at once
+        events.push(event(1_500_000, 2, CommandType::Set, 3));
+        events.push(event(1_500_000, 2, CommandType::Set, 10));
+        events.sort_by_key(|e| e.timestamp);
+
+        let report = connection_report(&events);
+        assert_eq!(report.len(), 2);
+        let (busy, quiet) = (&report[0], &report[1]);
+        assert_eq!((busy.conn_id, busy.events), (1, 8));
+        assert_eq!(busy.per_command.get(&CommandType::Get), Some(&8));
+        assert_eq!(busy.duration(), 1_750_000);
+        assert!((busy.rate().unwrap() - 8.0 / 1.75).abs() < 1e-9);
+        assert_eq!((busy.keys, busy.shared_keys), (4, 1));
+        assert_eq!(busy.overlap(), 0.25);
+
+        assert_eq!((quiet.conn_id, quiet.events), (2, 2));
+        assert_eq!(quiet.rate(), None);
+        assert_eq!((quiet.keys, quiet.shared_keys), (2, 1));
+
+        assert_eq!(busiest_share(&report, 1, 10), 0.8);
+    }
+
+    #[test]
+    fn test_keys_of_one_connection_are_not_shared() {
+        let events: Vec<Event> = (0..10)
+            .map(|i| event(i, (i % 2) as u16, CommandType::Get, 100 * (i % 2) + i % 3))
+            .collect();
+        let report = connection_report(&events);
+        assert!(report.iter().all(|stats| stats.shared_keys == 0));
+        assert!(connection_report(&[]).is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use membench::replay::{bind_control_socket, serve_control, ReplayControl};
+    use std::sync::Arc;
+    use tokio_util::sync::CancellationToken;
+
+    #[test]
+    fn test_pause_and_resume() {
+        let control = ReplayControl::new(None);
+        let handle = control.handle();
+
+        assert_eq!(control.execute("pause"), "ok paused");
+        assert!(*handle.paused.borrow());
+        assert_eq!(control.execute("resume"), "ok resumed");
+        assert!(!*handle.paused.borrow());
+    }
+
+    #[test]
+    fn test_rate_changes_synthetic_rate() {
+        let control = ReplayControl::new(Some(1000));
+        let handle = control.handle();
+
+        assert_eq!(control.execute("rate 20k"), "ok rate 20000");
+        assert_eq!(*handle.rate.borrow(), Some(20000));
+        assert!(control.execute("rate 0").starts_with("error"));
+        assert!(control.execute("rate fast").starts_with("error"));
+        assert_eq!(*handle.rate.borrow(), Some(20000));
+    }
+
+    #[test]
+    fn test_rate_rejected_without_rate() {
+        let control = ReplayControl::new(None);
+        assert!(control.execute("rate 1000").starts_with("error"));
+        assert_eq!(*control.handle().rate.borrow(), None);
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let control = ReplayControl::new(None);
+        assert!(control
+            .execute("stop now")
+            .starts_with("error: unknown command"));
+    }
+
+    #[tokio::test]
+    async fn test_control_socket_round_trip() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("control.sock");
+        let path = path.to_str().unwrap().to_string();
+
+        let control = Arc::new(ReplayControl::new(Some(500)));
+        let listener = bind_control_socket(&path).unwrap();
+        let cancel_token = CancellationToken::new();
+        let server = tokio::spawn({
+            let (control, path, cancel_token) =
+                (Arc::clone(&control), path.clone(), cancel_token.clone());
+            async move { serve_control(control, listener, &path, cancel_token).await }
+        });
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"pause\nstats\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok paused");
+        let stats = lines.next_line().await.unwrap().unwrap();
+        assert!(stats.contains("paused=true rate=500"), "{}", stats);
+
+        cancel_token.cancel();
+        server.await.unwrap();
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_bind_refuses_regular_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let path = file.path().to_str().unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        assert!(bind_control_socket(path).is_err());
+        assert!(file.path().exists());
+    }
+}
@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::CommandType;
+    use membench::replay::distributed::{
+        collect_snapshots, gather_workers, start_workers, WireSnapshot,
+    };
+    use membench::replay::stats::{AggregatedStats, ConnectionStats};
+    use membench::replay::{Partition, WorkerLink};
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_partition_splits_connections() {
+        let connections = vec![9, 3, 0, 7, 1, 8, 2, 6, 4, 5];
+        let shares: Vec<Vec<u16>> = (0..3)
+            .map(|index| Partition { index, workers: 3 }.select(connections.clone()))
+            .collect();
+        assert_eq!(shares[0], vec![0, 3, 6, 9]);
+        assert_eq!(shares[1], vec![1, 4, 7]);
+        assert_eq!(shares[2], vec![2, 5, 8]);
+
+        let partition = Partition {
+            index: 0,
+            workers: 3,
+        };
+        assert_eq!(partition.share(100_000), 33_334);
+        assert_eq!(partition.share(1), 1);
+
+        let mut sorted = connections.clone();
+        sorted.sort_unstable();
+        for (index, share) in shares.iter().enumerate() {
+            for &conn_id in share {
+                assert_eq!(partition.owner(&sorted, conn_id), Some(index));
+            }
+        }
+        assert_eq!(partition.owner(&sorted, 10), None);
+    }
+
+    #[test]
+    fn test_snapshot_survives_the_wire() {
+        let mut stats = ConnectionStats::new(7).with_target(1).with_validation(5);
+        for latency in [100, 200, 3_000] {
+            stats.record_success(CommandType::Get, Duration::from_micros(latency));
+        }
+        stats.record_miss();
+        let snapshot = stats.snapshot();
+
+        let bytes = bincode::serialize(&WireSnapshot::encode(&snapshot).unwrap()).unwrap();
+        let wire: WireSnapshot = bincode::deserialize(&bytes).unwrap();
+        let decoded = wire.decode().unwrap();
+
+        assert_eq!(decoded.connection_id, 7);
+        assert_eq!(decoded.target, 1);
+        assert_eq!(decoded.success_counts[&CommandType::Get], 3);
+        assert_eq!(decoded.get_misses, 1);
+        let histogram = &decoded.histograms[&CommandType::Get];
+        assert_eq!(histogram.len(), 3);
+        assert_eq!(
+            histogram.max(),
+            snapshot.histograms[&CommandType::Get].max()
+        );
+        assert_eq!(decoded.validation.unwrap().limit(), 5);
+
+        // Times keep their order, within clock resolution
+        let (first, last) = (decoded.first_operation, decoded.last_operation);
+        assert!(first.is_some() && first <= last);
+    }
+
+    #[tokio::test]
+    async fn test_coordinator_starts_workers_and_collects_snapshots() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let workers: Vec<_> = (0..2)
+            .map(|_| {
+                let addr = addr.clone();
+                tokio::spawn(async move {
+                    let mut link = WorkerLink::join(&addr).await.unwrap();
+                    let partition = link.partition;
+                    link.wait_for_start().await.unwrap();
+
+                    for conn_id in partition.select((0..4).collect()) {
+                        let mut stats = ConnectionStats::new(conn_id);
+                        stats.record_success(CommandType::Set, Duration::from_micros(50));
+                        link.send_snapshot(&stats.snapshot()).await.unwrap();
+                    }
+                    link.finish(vec!["cache:11211".to_string()]).await.unwrap();
+                    partition
+                })
+            })
+            .collect();
+
+        let mut sessions = gather_workers(&listener, 2).await.unwrap();
+        start_workers(&mut sessions, Duration::from_millis(10))
+            .await
+            .unwrap();
+        let (tx, mut rx) = mpsc::channel(16);
+        let mut targets = Vec::new();
+        for session in sessions {
+            targets = collect_snapshots(session, tx.clone()).await.unwrap();
+        }
+        drop(tx);
+
+        let mut stats = AggregatedStats::new();
+        while let Some(snapshot) = rx.recv().await {
+            stats.merge(snapshot);
+        }
+        assert_eq!(stats.total_operations(), 4);
+        assert_eq!(targets, vec!["cache:11211".to_string()]);
+
+        let mut indices = Vec::new();
+        for worker in workers {
+            let partition = worker.await.unwrap();
+            assert_eq!(partition.workers, 2);
+            indices.push(partition.index);
+        }
+        indices.sort_unstable();
+        assert_eq!(indices, vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_worker_leaving_early_is_reported() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let worker = tokio::spawn(async move {
+            let mut link = WorkerLink::join(&addr).await.unwrap();
+            link.wait_for_start().await.unwrap();
+            // Dropped without finishing
+        });
+
+        let mut sessions = gather_workers(&listener, 1).await.unwrap();
+        start_workers(&mut sessions, Duration::ZERO).await.unwrap();
+        worker.await.unwrap();
+
+        let (tx, _rx) = mpsc::channel(16);
+        let session = sessions.pop().unwrap();
+        let err = collect_snapshots(session, tx).await.err().unwrap();
+        assert!(err.to_string().contains("disconnected"), "{}", err);
+    }
+}
@@ -0,0 +1,90 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event};
+    use membench::replay::{FidelityReport, TrafficSummary};
+
+    fn event(conn_id: u16, cmd_type: CommandType, key_size: u32) -> Event {
+        Event::new(0, conn_id, cmd_type, 0, key_size)
+    }
+
+    fn summary(events: &[Event]) -> TrafficSummary {
+        let mut summary = TrafficSummary::new();
+        for event in events {
+            summary.record(event);
+        }
+        summary
+    }
+
+    #[test]
+    fn test_identical_traffic_is_faithful() {
+        let events = vec![
+            event(1, CommandType::Get, 10),
+            event(1, CommandType::Set, 20),
+            event(2, CommandType::Get, 10),
+        ];
+        let expected = summary(&events);
+        let sent = summary(&[events.clone(), events].concat());
+
+        let report = FidelityReport::compare(&expected, &sent, Some(2));
+        assert_eq!(report.expected_total, Some(6));
+        assert_eq!(report.completion(), Some(1.0));
+        assert_eq!(report.command_mix_divergence(), 0.0);
+        assert_eq!(report.key_size_divergence, 0.0);
+        assert!(report.connection_shortfalls.is_empty());
+    }
+
+    #[test]
+    fn test_dropped_events_are_reported() {
+        let expected = summary(&[
+            event(1, CommandType::Get, 10),
+            event(1, CommandType::Get, 10),
+            event(2, CommandType::Set, 30),
+            event(2, CommandType::Set, 30),
+        ]);
+        // Connection 2 failed before sending anything
+        let sent = summary(&[
+            event(1, CommandType::Get, 10),
+            event(1, CommandType::Get, 10),
+        ]);
+
+        let report = FidelityReport::compare(&expected, &sent, Some(1));
+        assert_eq!(report.completion(), Some(0.5));
+        assert_eq!(report.command_mix_divergence(), 0.5);
+        assert_eq!(report.key_size_divergence, 0.5);
+        assert_eq!(report.connection_shortfalls, vec![(2, 2, 0)]);
+    }
+
+    #[test]
+    fn test_infinite_loop_compares_shares_only() {
+        let expected = summary(&[event(1, CommandType::Get, 10)]);
+        let sent: Vec<Event> = (0..5).map(|_| event(1, CommandType::Get, 10)).collect();
+        let sent = summary(&sent);
+
+        let report = FidelityReport::compare(&expected, &sent, None);
+        assert_eq!(report.expected_total, None);
+        assert_eq!(report.completion(), None);
+        assert!(report.connection_shortfalls.is_empty());
+    }
+
+    #[test]
+    fn test_latencies_compare_recorded_and_replayed() {
+        use hdrhistogram::Histogram;
+
+        let mut summary = TrafficSummary::new();
+        summary.record(&event(1, CommandType::Get, 10));
+        let report = FidelityReport::compare(&summary, &summary, Some(1));
+        assert!(report.latencies.is_empty());
+
+        let mut recorded = Histogram::<u64>::new(3).unwrap();
+        let mut replayed = Histogram::<u64>::new(3).unwrap();
+        for micros in 1..=100 {
+            recorded.record(micros).unwrap();
+            replayed.record(micros * 10).unwrap();
+        }
+        let report = report.with_latencies(&recorded, &replayed);
+        let percentiles: Vec<f64> = report.latencies.iter().map(|(p, _, _)| *p).collect();
+        assert_eq!(percentiles, vec![50.0, 90.0, 99.0, 99.9]);
+        assert_eq!(report.latencies[0].1, 50);
+        assert_eq!(report.latencies[0].2, 500);
+    }
+}
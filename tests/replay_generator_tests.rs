@@ -0,0 +1,173 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::CommandType;
+    use membench::replay::{AnalysisResult, TrafficGenerator};
+    use std::collections::HashMap;
+
+    fn analysis() -> AnalysisResult {
+        let mut cmd_dist = HashMap::new();
+        cmd_dist.insert(CommandType::Get, 80);
+        cmd_dist.insert(CommandType::Set, 20);
+
+        AnalysisResult {
+            total_events: 100,
+            command_distribution: cmd_dist,
+            key_size_distribution: vec![(10, 50), (20, 50)],
+            value_size_distribution: vec![(100, 50), (200, 50)],
+            key_frequencies: Vec::new(),
+            hot_keys: Vec::new(),
+            hot_keys_by_command: HashMap::new(),
+            inter_arrival_distribution: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_generator_produces_commands() {
+        let mut gen = TrafficGenerator::new(analysis());
+
+        for _ in 0..100 {
+            let event = gen.next_command();
+            assert!(matches!(
+                event.cmd_type,
+                CommandType::Get | CommandType::Set
+            ));
+            assert!(event.key_size == 10 || event.key_size == 20);
+            match event.cmd_type {
+                CommandType::Set => assert!(event.value_size.is_some()),
+                _ => assert!(event.value_size.is_none()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_generator_spreads_connections() {
+        let mut gen = TrafficGenerator::new(analysis()).with_connections(4);
+
+        for _ in 0..100 {
+            assert!(gen.next_command().conn_id < 4);
+        }
+    }
+
+    #[test]
+    fn test_seeded_generator_is_reproducible() {
+        let mut a = TrafficGenerator::new(analysis())
+            .with_connections(4)
+            .with_seed(42);
+        let mut b = TrafficGenerator::new(analysis())
+            .with_connections(4)
+            .with_seed(42);
+
+        for _ in 0..100 {
+            let (x, y) = (a.next_command(), b.next_command());
+            assert_eq!(x.cmd_type, y.cmd_type);
+            assert_eq!(x.conn_id, y.conn_id);
+            assert_eq!(x.key_hash, y.key_hash);
+            assert_eq!(x.key_size, y.key_size);
+            assert_eq!(x.value_size, y.value_size);
+        }
+    }
+
+    #[test]
+    fn test_different_seeds_diverge() {
+        let mut a = TrafficGenerator::new(analysis()).with_seed(1);
+        let mut b = TrafficGenerator::new(analysis()).with_seed(2);
+
+        let a_keys: Vec<u64> = (0..10).map(|_| a.next_command().key_hash).collect();
+        let b_keys: Vec<u64> = (0..10).map(|_| b.next_command().key_hash).collect();
+        assert_ne!(a_keys, b_keys);
+    }
+
+    #[test]
+    fn test_zipf_popularity_repeats_keys_with_stable_sizes() {
+        use membench::replay::KeyPopularity;
+        use std::collections::HashMap;
+
+        let mut gen = TrafficGenerator::new(analysis())
+            .with_seed(3)
+            .with_popularity(KeyPopularity::Zipf {
+                keyspace: 100,
+                s: 1.0,
+                q: 0.0,
+            });
+
+        let mut sizes: HashMap<u64, u32> = HashMap::new();
+        for _ in 0..1000 {
+            let event = gen.next_command();
+            let size = *sizes.entry(event.key_hash).or_insert(event.key_size);
+            assert_eq!(size, event.key_size, "key size must be stable per key");
+        }
+        assert!(sizes.len() <= 100);
+    }
+
+    #[test]
+    fn test_popularity_derived_from_analysis() {
+        let mut with_keys = analysis();
+        with_keys.key_frequencies = (1..=50u64).map(|rank| 1000 / rank).collect();
+
+        let mut gen = TrafficGenerator::new(with_keys).with_seed(5);
+        let distinct: std::collections::HashSet<u64> =
+            (0..1000).map(|_| gen.next_command().key_hash).collect();
+
+        assert!(distinct.len() <= 50);
+    }
+
+    #[test]
+    fn test_timestamps_ordered_with_poisson_gaps() {
+        use membench::replay::InterArrival;
+
+        let mut gen = TrafficGenerator::new(analysis())
+            .with_connections(4)
+            .with_inter_arrival(InterArrival::Poisson {
+                mean_micros: 1000.0,
+            })
+            .with_seed(11);
+
+        let events: Vec<_> = (0..20_000).map(|_| gen.next_command()).collect();
+        assert!(events.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+        // 4 connections at 1 event/ms each: ~4 events per ms overall
+        let span = events.last().unwrap().timestamp as f64;
+        let per_ms = events.len() as f64 / (span / 1000.0);
+        assert!((per_ms - 4.0).abs() < 0.2, "rate = {} events/ms", per_ms);
+    }
+
+    #[test]
+    fn test_empirical_gaps_from_analysis() {
+        let mut with_gaps = analysis();
+        with_gaps.inter_arrival_distribution = vec![(1024, 1)];
+
+        let mut gen = TrafficGenerator::new(with_gaps).with_seed(9);
+        let timestamps: Vec<u64> = (0..100).map(|_| gen.next_command().timestamp).collect();
+
+        for gap in timestamps.windows(2).map(|w| w[1] - w[0]) {
+            assert!((513..=1024).contains(&gap), "gap = {}", gap);
+        }
+    }
+
+    #[test]
+    fn test_get_after_set_reuses_recent_keys() {
+        let mut gen = TrafficGenerator::new(analysis())
+            .with_get_after_set(1.0, 16)
+            .with_seed(13);
+
+        let mut recent: Vec<u64> = Vec::new();
+        let mut reused = 0;
+        for _ in 0..1000 {
+            let event = gen.next_command();
+            match event.cmd_type {
+                CommandType::Set => {
+                    recent.push(event.key_hash);
+                    if recent.len() > 16 {
+                        recent.remove(0);
+                    }
+                }
+                CommandType::Get if !recent.is_empty() => {
+                    assert!(recent.contains(&event.key_hash));
+                    reused += 1;
+                }
+                _ => {}
+            }
+        }
+        assert!(reused > 0);
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event};
+    use membench::replay::{DistributionAnalyzer, HeavyHitters};
+
+    #[test]
+    fn test_counts_are_exact_below_capacity() {
+        let mut sketch = HeavyHitters::new(16);
+        for key in [1, 2, 2, 3, 3, 3] {
+            sketch.insert(key);
+        }
+        let top = sketch.top(2);
+        assert_eq!(top.len(), 2);
+        assert_eq!((top[0].key_hash, top[0].count, top[0].error), (3, 3, 0));
+        assert_eq!((top[1].key_hash, top[1].count), (2, 2));
+        assert!((top[0].share - 0.5).abs() < 1e-9);
+        assert_eq!(sketch.total(), 6);
+    }
+
+    #[test]
+    fn test_heavy_keys_survive_a_long_tail() {
+        let mut sketch = HeavyHitters::new(100);
+        // Keys 0..5 take 5%, 4%, ... 1% of 1M events; the rest go to 100k
+        // keys seen ten times or fewer
+        let mut tail = 1000u64;
+        for i in 0..1_000_000u64 {
+            let slot = i % 100;
+            let key = if slot < 15 {
+                match slot {
+                    0..=4 => 0,
+                    5..=8 => 1,
+                    9..=11 => 2,
+                    12..=13 => 3,
+                    _ => 4,
+                }
+            } else {
+                tail = 1000 + (tail * 7 + 13) % 100_000;
+                tail
+            };
+            sketch.insert(key);
+        }
+        let top = sketch.top(5);
+        let keys: Vec<u64> = top.iter().map(|hot| hot.key_hash).collect();
+        assert_eq!(keys, [0, 1, 2, 3, 4]);
+        for (hot, expected) in top.iter().zip([50_000u64, 40_000, 30_000, 20_000, 10_000]) {
+            // Never under the true count, and over it by at most the error
+            assert!(hot.count >= expected && hot.count - hot.error <= expected);
+            assert!(hot.error <= 1_000_000 / 100);
+        }
+    }
+
+    #[test]
+    fn test_analyzer_reports_hot_keys_per_command() {
+        let events: Vec<Event> = (0..100u64)
+            .map(|i| {
+                Event::new(
+                    i,
+                    1,
+                    if i < 70 {
+                        CommandType::Get
+                    } else {
+                        CommandType::Set
+                    },
+                    if i < 70 { 7 + i % 2 * (i % 5) } else { i },
+                    10,
+                )
+            })
+            .collect();
+
+        let analysis = DistributionAnalyzer::analyze(&events);
+        assert_eq!(analysis.hot_keys[0].key_hash, 7);
+        assert!(analysis.hot_keys.len() <= 10);
+        let gets = &analysis.hot_keys_by_command[&CommandType::Get];
+        assert_eq!(gets[0].key_hash, 7);
+        assert!(gets[0].share > 0.5);
+        let sets = &analysis.hot_keys_by_command[&CommandType::Set];
+        assert_eq!(sets.len(), 10);
+        assert!(sets.iter().all(|hot| hot.count == 1 && hot.key_hash >= 70));
+    }
+}
@@ -0,0 +1,88 @@
+#[cfg(test)]
+mod tests {
+    use membench::replay::HashRing;
+
+    /// Spread of keys over targets: well mixed key hashes, as anonymized keys are
+    fn key_hashes() -> impl Iterator<Item = u64> {
+        (0..30_000u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+
+    #[test]
+    fn test_ring_spreads_keys_evenly() {
+        let ring = HashRing::new(&["a:11211", "b:11211", "c:11211"]);
+        let mut counts = [0u64; 3];
+        for key_hash in key_hashes() {
+            counts[ring.target_for(key_hash)] += 1;
+        }
+        for count in counts {
+            // Within a third of the fair share
+            assert!((6_666..13_334).contains(&count), "{:?}", counts);
+        }
+
+        // The same targets always map keys alike
+        let again = HashRing::new(&["a:11211", "b:11211", "c:11211"]);
+        assert!(
+            key_hashes().all(|key_hash| ring.target_for(key_hash) == again.target_for(key_hash))
+        );
+    }
+
+    #[test]
+    fn test_adding_a_target_moves_only_its_keys() {
+        let three = HashRing::new(&["a:11211", "b:11211", "c:11211"]);
+        let four = HashRing::new(&["a:11211", "b:11211", "c:11211", "d:11211"]);
+        let mut moved = 0;
+        for key_hash in key_hashes() {
+            let before = three.target_for(key_hash);
+            let after = four.target_for(key_hash);
+            if before != after {
+                assert_eq!(after, 3, "a key moved between existing targets");
+                moved += 1;
+            }
+        }
+        // About a quarter of the keys go to the new target
+        assert!((4_000..11_000).contains(&moved), "{}", moved);
+    }
+
+    #[tokio::test]
+    async fn test_route_by_key_splits_a_connection() {
+        use membench::profile::{CommandType, Event};
+        use membench::replay::ketama::route_by_key;
+        use membench::replay::ConnectionMessage;
+        use std::sync::Arc;
+        use std::time::Instant;
+        use tokio::sync::mpsc;
+
+        let ring = Arc::new(HashRing::new(&["a:11211", "b:11211"]));
+        let (tx, rx) = mpsc::channel(64);
+        let (to_a, mut at_a) = mpsc::channel(64);
+        let (to_b, mut at_b) = mpsc::channel(64);
+        let router = tokio::spawn(route_by_key(rx, vec![to_a, to_b], Arc::clone(&ring)));
+
+        for key_hash in key_hashes().take(20) {
+            let event = Event::new(0, 1, CommandType::Get, key_hash, 8);
+            tx.send(ConnectionMessage::Event(event, Instant::now()))
+                .await
+                .unwrap();
+        }
+        tx.send(ConnectionMessage::EndOfIteration).await.unwrap();
+        drop(tx);
+        router.await.unwrap();
+
+        for (index, queue) in [&mut at_a, &mut at_b].into_iter().enumerate() {
+            let mut events = 0;
+            let mut ends = 0;
+            while let Some(message) = queue.recv().await {
+                match message {
+                    ConnectionMessage::Event(event, _) => {
+                        assert_eq!(ring.target_for(event.key_hash), index);
+                        events += 1;
+                    }
+                    ConnectionMessage::EndOfIteration => ends += 1,
+                }
+            }
+            assert!(events > 0);
+            // Every target's connection ends the iteration
+            assert_eq!(ends, 1);
+        }
+    }
+}
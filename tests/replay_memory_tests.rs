@@ -0,0 +1,133 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Compression, Event, TimeRange};
+    use membench::record::ProfileWriter;
+    use membench::replay::connection_task::ConnectionMessage;
+    use membench::replay::{
+        reader_task, LoopMode, Pacing, ProfileStreamer, ReplayControl, Routes, TrafficSummary,
+    };
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::TempDir;
+    use tokio::sync::mpsc;
+    use tokio_util::sync::CancellationToken;
+
+    /// Allocator keeping track of the bytes allocated, and of their peak
+    struct Counting;
+
+    static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    const EVENTS: u64 = 400_000;
+
+    /// Memory a replay may take on top of what was allocated before it
+    const BOUND: usize = 1 << 20;
+
+    /// Bytes the streamer reads from the file at once
+    const READ_WINDOW: usize = 256 << 10;
+
+    fn write_profile(path: &str, compression: Compression) {
+        let mut writer = ProfileWriter::with_compression(path, compression).unwrap();
+        for timestamp in 0..EVENTS {
+            let conn_id = (timestamp % 8) as u16;
+            let event = Event {
+                value_size: std::num::NonZeroU32::new(100),
+                ..Event::new(timestamp, conn_id, CommandType::Set, timestamp * 7919, 16)
+            };
+            writer.write_event(&event).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    /// Both passes of a profile replay: summarizing the profile, then
+    /// sending its events to the connection queues
+    async fn replay(path: &str) -> u64 {
+        let mut streamer = ProfileStreamer::with_range(path, TimeRange::default()).unwrap();
+        let mut summary = TrafficSummary::new();
+        while let Some(event) = streamer.next_event().unwrap() {
+            summary.record(&event);
+        }
+        drop(streamer);
+
+        let mut queues = HashMap::new();
+        let mut receivers = Vec::new();
+        for conn_id in 0..8 {
+            let (tx, mut rx) = mpsc::channel(16);
+            queues.insert(conn_id, tx);
+            receivers.push(tokio::spawn(async move {
+                let mut sent = 0;
+                while let Some(message) = rx.recv().await {
+                    if let ConnectionMessage::Event(..) = message {
+                        sent += 1;
+                    }
+                }
+                sent
+            }));
+        }
+        let control = ReplayControl::new(None);
+        let reader = reader_task(
+            path,
+            TimeRange::default(),
+            Routes::local(queues),
+            LoopMode::Once,
+            Pacing::None,
+            control.handle(),
+            CancellationToken::new(),
+        );
+        reader.await.unwrap();
+        let mut sent = 0;
+        for receiver in receivers {
+            sent += receiver.await.unwrap();
+        }
+        assert_eq!(summary.total(), sent);
+        sent
+    }
+
+    #[tokio::test]
+    async fn test_replay_memory_does_not_grow_with_profile() {
+        // Holding the events would take far more than the bound
+        assert!(EVENTS as usize * std::mem::size_of::<Event>() > 16 * BOUND);
+
+        let temp_dir = TempDir::new().unwrap();
+        for compression in [Compression::None, Compression::Deflate] {
+            let path = temp_dir.path().join("large.bin");
+            let path = path.to_str().unwrap();
+            write_profile(path, compression);
+            let profile_len = std::fs::metadata(path).unwrap().len() as usize;
+            assert!(
+                profile_len > 4 * READ_WINDOW,
+                "profile of {} bytes",
+                profile_len
+            );
+
+            let before = ALLOCATED.load(Ordering::Relaxed);
+            PEAK.store(before, Ordering::Relaxed);
+            assert_eq!(replay(path).await, EVENTS);
+            let used = PEAK.load(Ordering::Relaxed) - before;
+            assert!(
+                used < BOUND,
+                "{:?} replay of {} bytes took {} bytes",
+                compression,
+                profile_len,
+                used
+            );
+        }
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event};
+    use membench::replay::miss_ratio_curve;
+    use std::collections::VecDeque;
+    use std::num::NonZero;
+
+    fn event(key_hash: u64, value_size: u32) -> Event {
+        Event {
+            value_size: NonZero::new(value_size),
+            ..Event::new(0, 1, CommandType::Get, key_hash, 10)
+        }
+    }
+
+    /// Hit ratio of an LRU cache of `capacity` bytes over `events`
+    fn simulate_lru(events: &[Event], capacity: u64) -> f64 {
+        let mut cache: VecDeque<(u64, u64)> = VecDeque::new();
+        let mut hits = 0;
+        for event in events {
+            let size = event.key_size as u64 + event.value_size.unwrap().get() as u64;
+            if let Some(at) = cache.iter().position(|(key, _)| *key == event.key_hash) {
+                hits += 1;
+                cache.remove(at);
+            }
+            cache.push_front((event.key_hash, size));
+            while cache.iter().map(|(_, size)| size).sum::<u64>() > capacity {
+                cache.pop_back();
+            }
+        }
+        hits as f64 / events.len() as f64
+    }
+
+    /// Accesses skewed towards low keys, each key with a fixed value size
+    fn skewed_events(count: u64, keys: u64) -> Vec<Event> {
+        let mut state = 12345u64;
+        (0..count)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let uniform = (state >> 33) as f64 / (1u64 << 31) as f64;
+                let key = (uniform * uniform * keys as f64) as u64;
+                event(key, 50 + (key % 7) as u32 * 10)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unsampled_curve_is_exact() {
+        let events = skewed_events(5000, 300);
+        let curve = miss_ratio_curve(&events, 1.0);
+        assert_eq!(curve.accesses, 5000);
+        for capacity in [100, 1000, 4000, 10_000, 30_000] {
+            let expected = simulate_lru(&events, capacity);
+            assert!(
+                (curve.hit_ratio(capacity) - expected).abs() < 1e-9,
+                "{} bytes: {} vs {}",
+                capacity,
+                curve.hit_ratio(capacity),
+                expected
+            );
+        }
+        // Every reuse hits once all the keys fit
+        let keys = events
+            .iter()
+            .map(|e| e.key_hash)
+            .collect::<std::collections::HashSet<_>>();
+        let reuses = 5000 - keys.len() as u64;
+        assert_eq!(curve.hit_ratio(curve.footprint), reuses as f64 / 5000.0);
+    }
+
+    #[test]
+    fn test_sampled_curve_approximates_the_full_one() {
+        let events = skewed_events(200_000, 20_000);
+        let full = miss_ratio_curve(&events, 1.0);
+        let sampled = miss_ratio_curve(&events, 0.1);
+        assert!(sampled.accesses < full.accesses / 5);
+        let footprint_error = sampled.footprint as f64 / full.footprint as f64 - 1.0;
+        assert!(footprint_error.abs() < 0.1, "{}", footprint_error);
+        for (bytes, hit_ratio) in full.points(8) {
+            let error = sampled.hit_ratio(bytes) - hit_ratio;
+            assert!(error.abs() < 0.03, "{} bytes: off by {}", bytes, error);
+        }
+    }
+
+    #[test]
+    fn test_points_double_up_to_the_footprint() {
+        let events = skewed_events(1000, 100);
+        let curve = miss_ratio_curve(&events, 1.0);
+        let points = curve.points(4);
+        assert_eq!(points.len(), 4);
+        let largest = curve.footprint.next_power_of_two();
+        assert_eq!(points[3].0, largest);
+        assert_eq!(points[0].0, largest / 8);
+        assert!(points.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    }
+}
@@ -0,0 +1,64 @@
+#[cfg(test)]
+mod tests {
+    use membench::replay::ramp::ramp_rate;
+    use membench::replay::Ramp;
+    use std::time::{Duration, Instant};
+    use tokio::sync::watch;
+    use tokio_util::sync::CancellationToken;
+
+    #[test]
+    fn test_ramp_fraction() {
+        let ramp = Ramp {
+            up: Some(Duration::from_secs(60)),
+            down: Some(Duration::from_secs(30)),
+        };
+        let total = Some(Duration::from_secs(300));
+        let at = |secs| ramp.fraction(Duration::from_secs(secs), total);
+
+        assert_eq!(at(0), 0.0);
+        assert_eq!(at(30), 0.5);
+        assert_eq!(at(60), 1.0);
+        assert_eq!(at(200), 1.0);
+        assert_eq!(at(285), 0.5);
+        assert_eq!(at(300), 0.0);
+
+        // Without an end, there is no ramp-down
+        assert_eq!(ramp.fraction(Duration::from_secs(1_000), None), 1.0);
+        let start = Instant::now();
+        assert_eq!(
+            ramp.down_from(start, total),
+            Some(start + Duration::from_secs(270))
+        );
+        assert_eq!(ramp.down_from(start, None), None);
+    }
+
+    #[tokio::test]
+    async fn test_ramp_up_reaches_the_requested_rate() {
+        let ramp = Ramp {
+            up: Some(Duration::from_millis(500)),
+            down: None,
+        };
+        let (rate, watcher) = watch::channel(Some(10_000));
+        let cancel = CancellationToken::new();
+        let handle = tokio::spawn(ramp_rate(
+            ramp,
+            Instant::now(),
+            None,
+            rate.clone(),
+            cancel.clone(),
+        ));
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let midway = watcher.borrow().unwrap();
+        assert!((1_000..9_000).contains(&midway), "{}", midway);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(*watcher.borrow(), Some(10_000));
+
+        // Past the ramp, the rate follows the control socket
+        rate.send_replace(Some(20_000));
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        cancel.cancel();
+        assert_eq!(handle.await.unwrap(), Some(20_000));
+    }
+}
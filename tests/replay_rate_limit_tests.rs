@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+    use membench::replay::RateLimiter;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_rate_limiter_caps_throughput() {
+        // 100/s holds one token: the first passes, each next waits 10ms
+        let limiter = RateLimiter::new(100);
+        let start = Instant::now();
+        for _ in 0..11 {
+            limiter.acquire().await;
+        }
+        assert!(
+            start.elapsed() >= Duration::from_millis(95),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_shared_across_tasks() {
+        let limiter = Arc::new(RateLimiter::new(200));
+        let start = Instant::now();
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let limiter = Arc::clone(&limiter);
+                tokio::spawn(async move {
+                    for _ in 0..10 {
+                        limiter.acquire().await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        // 40 commands at 200/s over all tasks: 39 of them wait 5ms each
+        assert!(
+            start.elapsed() >= Duration::from_millis(190),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_rate_change() {
+        let limiter = RateLimiter::new(10);
+        assert_eq!(limiter.rate(), 10);
+        limiter.acquire().await;
+
+        limiter.set_rate(100_000);
+        assert_eq!(limiter.rate(), 100_000);
+        let start = Instant::now();
+        for _ in 0..100 {
+            limiter.acquire().await;
+        }
+        assert!(
+            start.elapsed() < Duration::from_millis(50),
+            "{:?}",
+            start.elapsed()
+        );
+    }
+}
@@ -1,9 +1,104 @@
 #[cfg(test)]
 mod tests {
-    use membench::profile::{CommandType, Event, Flags};
+    use membench::profile::{CommandType, Event, TimeRange};
     use membench::record::ProfileWriter;
-    use membench::replay::ProfileReader;
+    use membench::replay::connection_task::ConnectionMessage;
+    use membench::replay::{reader_task, LoopMode, Pacing, ProfileReader, ReplayControl, Routes};
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
     use tempfile::TempDir;
+    use tokio::sync::mpsc;
+    use tokio_util::sync::CancellationToken;
+
+    fn get_event(timestamp: u64) -> Event {
+        Event::new(timestamp, 1, CommandType::Get, timestamp, 10)
+    }
+
+    /// Replay `timestamps` through the reader task, returning when each
+    /// event reached its connection queue
+    async fn dispatch_times(timestamps: &[u64], pacing: Pacing) -> Vec<Duration> {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("paced.bin");
+        let path = path.to_str().unwrap();
+        let mut writer = ProfileWriter::new(path).unwrap();
+        for &timestamp in timestamps {
+            writer.write_event(&get_event(timestamp)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let (tx, mut rx) = mpsc::channel(16);
+        let control = ReplayControl::new(None);
+        let start = Instant::now();
+        let reader = reader_task(
+            path,
+            TimeRange::default(),
+            Routes::local(HashMap::from([(1, tx)])),
+            LoopMode::Once,
+            pacing,
+            control.handle(),
+            CancellationToken::new(),
+        );
+        let receiver = async {
+            let mut times = Vec::new();
+            while let Some(message) = rx.recv().await {
+                if let ConnectionMessage::Event(..) = message {
+                    times.push(start.elapsed());
+                }
+            }
+            times
+        };
+        let (result, times) = tokio::join!(reader, receiver);
+        result.unwrap();
+        times
+    }
+
+    #[tokio::test]
+    async fn test_reader_leaves_other_workers_connections() {
+        use membench::replay::Partition;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("shared.bin");
+        let path = path.to_str().unwrap();
+        let mut writer = ProfileWriter::new(path).unwrap();
+        for timestamp in 0..10 {
+            let conn_id = 1 + (timestamp % 2) as u16;
+            writer
+                .write_event(&Event::new(timestamp, conn_id, CommandType::Get, 0, 10))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        // The first of two workers replays connection 1, the other connection 2
+        let (tx, mut rx) = mpsc::channel(16);
+        let routes = Routes {
+            queues: HashMap::from([(1, tx)]),
+            partition: Some((
+                Partition {
+                    index: 0,
+                    workers: 2,
+                },
+                vec![1, 2],
+            )),
+        };
+        let control = ReplayControl::new(None);
+        reader_task(
+            path,
+            TimeRange::default(),
+            routes,
+            LoopMode::Once,
+            Pacing::None,
+            control.handle(),
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        let mut sent = Vec::new();
+        while let Ok(ConnectionMessage::Event(event, _)) = rx.try_recv() {
+            sent.push(event.conn_id);
+        }
+        assert_eq!(sent, vec![1; 5]);
+    }
 
     #[test]
     fn test_read_profile() {
@@ -13,15 +108,7 @@ mod tests {
 
         // Write a profile
         let mut writer = ProfileWriter::new(path).unwrap();
-        let event = Event {
-            timestamp: 1000,
-            conn_id: 1,
-            cmd_type: CommandType::Get,
-            key_hash: 0x123456789,
-            key_size: 10,
-            value_size: None,
-            flags: Flags::empty(),
-        };
+        let event = Event::new(1000, 1, CommandType::Get, 0x123456789, 10);
         writer.write_event(&event).unwrap();
         writer.finish().unwrap();
 
@@ -32,4 +119,47 @@ mod tests {
         assert_eq!(metadata.total_events, 1);
         assert_eq!(metadata.unique_connections, 1);
     }
+
+    #[test]
+    fn test_parse_pacing() {
+        assert_eq!("none".parse::<Pacing>(), Ok(Pacing::None));
+        assert_eq!(
+            "recorded".parse::<Pacing>(),
+            Ok(Pacing::Recorded { speed: 1.0 })
+        );
+        assert_eq!(Pacing::default(), Pacing::None);
+        assert!("fast".parse::<Pacing>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_recorded_pacing_keeps_gaps() {
+        // A burst of two, an idle gap of 200ms, then the last event
+        let pacing = Pacing::Recorded { speed: 1.0 };
+        let times = dispatch_times(&[1_000_000, 1_000_050, 1_200_000], pacing).await;
+        assert_eq!(times.len(), 3);
+        assert!(times[1] < Duration::from_millis(100), "{:?}", times);
+        assert!(times[2] >= Duration::from_millis(199), "{:?}", times);
+    }
+
+    #[tokio::test]
+    async fn test_no_pacing_ignores_gaps() {
+        let times = dispatch_times(&[1_000_000, 1_000_050, 1_200_000], Pacing::None).await;
+        assert_eq!(times.len(), 3);
+        assert!(times[2] < Duration::from_millis(150), "{:?}", times);
+    }
+
+    #[tokio::test]
+    async fn test_speed_scales_recorded_gaps() {
+        // A 400ms gap replayed at 4x takes 100ms
+        let pacing = Pacing::Recorded { speed: 4.0 };
+        let times = dispatch_times(&[1_000_000, 1_400_000], pacing).await;
+        assert_eq!(times.len(), 2);
+        assert!(times[1] >= Duration::from_millis(99), "{:?}", times);
+        assert!(times[1] < Duration::from_millis(300), "{:?}", times);
+
+        // And at half speed twice as long
+        let pacing = Pacing::Recorded { speed: 0.5 };
+        let times = dispatch_times(&[1_000_000, 1_100_000], pacing).await;
+        assert!(times[1] >= Duration::from_millis(199), "{:?}", times);
+    }
 }
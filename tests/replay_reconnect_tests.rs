@@ -0,0 +1,245 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event};
+    use membench::replay::connection_task::ConnectionConfig;
+    use membench::replay::stats::AggregatedStats;
+    use membench::replay::{
+        spawn_connection_task, ConnectionMessage, ProtocolMode, ReconnectPolicy, ReplayClient,
+        RequestTimeout,
+    };
+    use std::time::{Duration, Instant};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+    use tokio_util::sync::CancellationToken;
+
+    fn get(key_hash: u64) -> ConnectionMessage {
+        let event = Event::new(0, 1, CommandType::Get, key_hash, 8);
+        ConnectionMessage::Event(event, Instant::now())
+    }
+
+    /// Server closing its first connection on the first command, then
+    /// answering every command with a miss
+    async fn flaky_server() -> (String, tokio::task::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 256];
+            let n = socket.read(&mut buf).await.unwrap();
+            assert!(n > 0);
+            drop(socket);
+
+            let Ok((mut socket, _)) = listener.accept().await else {
+                return;
+            };
+            loop {
+                match socket.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        let commands = buf[..n].iter().filter(|&&b| b == b'\n').count();
+                        socket.write_all(&b"EN\r\n".repeat(commands)).await.unwrap();
+                    }
+                }
+            }
+        });
+        (addr, server)
+    }
+
+    #[tokio::test]
+    async fn test_connection_reconnects_after_error() {
+        let (addr, server) = flaky_server().await;
+        let client = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        let (tx, rx) = mpsc::channel(8);
+        let (stats_tx, mut stats_rx) = mpsc::channel(8);
+        let config = ConnectionConfig {
+            reconnect: ReconnectPolicy {
+                max_retries: 3,
+                backoff: Duration::from_millis(10),
+            },
+            ..ConnectionConfig::new(ProtocolMode::Meta)
+        };
+        let task = spawn_connection_task(
+            client,
+            &addr,
+            rx,
+            stats_tx,
+            1,
+            config,
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        for key_hash in [1, 2, 3] {
+            tx.send(get(key_hash)).await.unwrap();
+        }
+        drop(tx);
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        let mut stats = AggregatedStats::new();
+        while let Some(snapshot) = stats_rx.recv().await {
+            stats.merge(snapshot);
+        }
+        assert_eq!(stats.reconnects(), 1);
+        assert_eq!(stats.total_errors(), 1);
+        assert_eq!(stats.total_operations(), 2);
+        assert!(stats.to_json().unwrap().contains("\"reconnects\": 1"));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_ends_connection_on_first_error() {
+        let (addr, server) = flaky_server().await;
+        let client = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        let (tx, rx) = mpsc::channel(8);
+        let (stats_tx, _stats_rx) = mpsc::channel(8);
+        let config = ConnectionConfig {
+            reconnect: ReconnectPolicy::fail_fast(),
+            ..ConnectionConfig::new(ProtocolMode::Meta)
+        };
+        let task = spawn_connection_task(
+            client,
+            &addr,
+            rx,
+            stats_tx,
+            1,
+            config,
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        tx.send(get(1)).await.unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(result.is_err());
+        server.abort();
+    }
+
+    /// Server never answering its first connection, then answering every
+    /// command with a miss
+    async fn stalled_server() -> (String, tokio::task::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (_stalled, _) = listener.accept().await.unwrap();
+            let Ok((mut socket, _)) = listener.accept().await else {
+                return;
+            };
+            let mut buf = [0u8; 256];
+            loop {
+                match socket.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        let commands = buf[..n].iter().filter(|&&b| b == b'\n').count();
+                        socket.write_all(&b"EN\r\n".repeat(commands)).await.unwrap();
+                    }
+                }
+            }
+        });
+        (addr, server)
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_counts_and_replaces_connection() {
+        let (addr, server) = stalled_server().await;
+        let client = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        let (tx, rx) = mpsc::channel(8);
+        let (stats_tx, mut stats_rx) = mpsc::channel(8);
+        let config = ConnectionConfig {
+            request_timeout: Some(Duration::from_millis(50)),
+            reconnect: ReconnectPolicy {
+                max_retries: 3,
+                backoff: Duration::from_millis(10),
+            },
+            ..ConnectionConfig::new(ProtocolMode::Meta)
+        };
+        let task = spawn_connection_task(
+            client,
+            &addr,
+            rx,
+            stats_tx,
+            1,
+            config,
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        for key_hash in [1, 2] {
+            tx.send(get(key_hash)).await.unwrap();
+        }
+        drop(tx);
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        let mut stats = AggregatedStats::new();
+        while let Some(snapshot) = stats_rx.recv().await {
+            stats.merge(snapshot);
+        }
+        assert_eq!(stats.timeouts(), 1);
+        assert_eq!(stats.reconnects(), 1);
+        assert_eq!(stats.total_operations(), 1);
+        assert_eq!(stats.timeout_rate(), 50.0);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_request_timeout_fails_fast() {
+        let (addr, server) = stalled_server().await;
+        let client = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        let (tx, rx) = mpsc::channel(8);
+        let (stats_tx, _stats_rx) = mpsc::channel(8);
+        let config = ConnectionConfig {
+            pipeline_depth: 4,
+            request_timeout: Some(Duration::from_millis(50)),
+            reconnect: ReconnectPolicy::fail_fast(),
+            ..ConnectionConfig::new(ProtocolMode::Meta)
+        };
+        let task = spawn_connection_task(
+            client,
+            &addr,
+            rx,
+            stats_tx,
+            1,
+            config,
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        for key_hash in [1, 2] {
+            tx.send(get(key_hash)).await.unwrap();
+        }
+        let err = tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap_err();
+        assert!(err.is::<RequestTimeout>(), "{}", err);
+        server.abort();
+    }
+
+    #[test]
+    fn test_reconnect_backoff_doubles_up_to_cap() {
+        let policy = ReconnectPolicy {
+            max_retries: 10,
+            backoff: Duration::from_millis(100),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(9), Duration::from_secs(5));
+        assert!(ReconnectPolicy::fail_fast().is_fail_fast());
+        assert!(!ReconnectPolicy::default().is_fail_fast());
+    }
+}
@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event};
+    use membench::replay::analyze_reuse;
+    use std::collections::HashMap;
+    use std::num::NonZero;
+
+    fn event(timestamp: u64, key_hash: u64, value_size: u32) -> Event {
+        Event {
+            value_size: NonZero::new(value_size),
+            ..Event::new(timestamp, 1, CommandType::Set, key_hash, 10)
+        }
+    }
+
+    /// Reuse distances by the definition: distinct keys since the previous
+    /// access to the same key
+    fn naive_distances(keys: &[u64]) -> (u64, HashMap<u64, u64>) {
+        let mut cold = 0;
+        let mut buckets = HashMap::new();
+        for (i, key) in keys.iter().enumerate() {
+            match keys[..i].iter().rposition(|k| k == key) {
+                Some(previous) => {
+                    let mut between: Vec<u64> = keys[previous + 1..i].to_vec();
+                    between.sort_unstable();
+                    between.dedup();
+                    let bucket = (between.len() as u64 + 1).next_power_of_two();
+                    *buckets.entry(bucket).or_insert(0) += 1;
+                }
+                None => cold += 1,
+            }
+        }
+        (cold, buckets)
+    }
+
+    #[test]
+    fn test_distances_match_the_definition() {
+        let mut key = 17u64;
+        let keys: Vec<u64> = (0..2000)
+            .map(|_| {
+                key = (key * 31 + 7) % 1009;
+                key % 97
+            })
+            .collect();
+        let events: Vec<Event> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, &key)| event(i as u64, key, 100))
+            .collect();
+
+        let reuse = analyze_reuse(&events, 1);
+        let (cold, buckets) = naive_distances(&keys);
+        assert_eq!(reuse.cold_accesses, cold);
+        assert_eq!(
+            reuse.distances.iter().copied().collect::<HashMap<_, _>>(),
+            buckets
+        );
+        assert_eq!(reuse.accesses(), keys.len() as u64);
+    }
+
+    #[test]
+    fn test_cyclic_accesses_only_hit_a_large_enough_cache() {
+        // 8 keys in a loop: every reuse is 7 keys away
+        let events: Vec<Event> = (0..800).map(|i| event(i, i % 8, 100)).collect();
+        let reuse = analyze_reuse(&events, 1);
+        assert_eq!(reuse.cold_accesses, 8);
+        assert_eq!(reuse.distances, vec![(8, 792)]);
+        assert_eq!(reuse.lru_hit_ratio(4), 0.0);
+        assert!((reuse.lru_hit_ratio(8) - 0.99).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_working_sets_per_window() {
+        // Keys 0..10 in the first second, keys 0..20 in the second
+        let mut events: Vec<Event> = (0..100).map(|i| event(i * 10_000, i % 10, 100)).collect();
+        events.extend((0..100).map(|i| event(1_000_000 + i * 10_000, i % 20, 200)));
+
+        let reuse = analyze_reuse(&events, 2);
+        assert_eq!(reuse.working_sets.len(), 2);
+        let (first, second) = (reuse.working_sets[0], reuse.working_sets[1]);
+        assert_eq!((first.start, first.keys, first.bytes), (0, 10, 10 * 110));
+        assert_eq!(
+            (second.end, second.keys, second.bytes),
+            (1_990_000, 20, 20 * 210)
+        );
+        assert!(first.end < second.start);
+
+        assert!(analyze_reuse(&[], 2).working_sets.is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use membench::replay::TimingWheel;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_expires_at_deadline() {
+        let mut wheel = TimingWheel::new();
+        wheel.insert(5, "a");
+        wheel.insert(70, "b");
+        wheel.insert(5000, "c");
+        assert_eq!(wheel.next_deadline(), Some(5));
+
+        let mut expired = Vec::new();
+        wheel.advance(4, &mut expired);
+        assert!(expired.is_empty());
+        wheel.advance(5, &mut expired);
+        assert_eq!(expired, vec!["a"]);
+        assert_eq!(wheel.next_deadline(), Some(70));
+
+        wheel.advance(4999, &mut expired);
+        assert_eq!(expired, vec!["a", "b"]);
+        wheel.advance(10_000, &mut expired);
+        assert_eq!(expired, vec!["a", "b", "c"]);
+        assert!(wheel.is_empty());
+        assert_eq!(wheel.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_past_deadlines_expire_immediately() {
+        let mut wheel = TimingWheel::new();
+        let mut expired = Vec::new();
+        wheel.advance(100, &mut expired);
+
+        wheel.insert(3, 1);
+        wheel.advance(100, &mut expired);
+        assert_eq!(expired, vec![1]);
+    }
+
+    #[test]
+    fn test_random_deadlines_expire_in_order() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut wheel = TimingWheel::new();
+        let mut deadlines: Vec<u64> = (0..10_000).map(|_| rng.gen_range(0..5_000_000)).collect();
+        for &deadline in &deadlines {
+            wheel.insert(deadline, deadline);
+        }
+        assert_eq!(wheel.len(), deadlines.len());
+
+        let mut expired = Vec::new();
+        let mut now = 0;
+        while !wheel.is_empty() {
+            now += rng.gen_range(1..20_000);
+            let before = expired.len();
+            wheel.advance(now, &mut expired);
+            assert!(expired[before..].iter().all(|&d| d <= now));
+            assert!(wheel.next_deadline().is_none_or(|d| d > now));
+        }
+
+        deadlines.sort_unstable();
+        assert_eq!(expired, deadlines);
+    }
+
+    #[test]
+    fn test_far_deadlines_are_parked() {
+        let mut wheel = TimingWheel::new();
+        let far = 1u64 << 40;
+        wheel.insert(far, "far");
+
+        let mut expired = Vec::new();
+        wheel.advance(far - 1, &mut expired);
+        assert!(expired.is_empty());
+        assert_eq!(wheel.next_deadline(), Some(far));
+        wheel.advance(far, &mut expired);
+        assert_eq!(expired, vec!["far"]);
+    }
+}
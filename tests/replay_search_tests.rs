@@ -0,0 +1,69 @@
+#[cfg(test)]
+mod tests {
+    use membench::replay::{RateSearch, RateStep};
+    use std::time::Duration;
+
+    /// Run a search against a target that sustains up to `capacity` ops/sec
+    fn simulate(search: &RateSearch, capacity: u64) -> Vec<RateStep> {
+        let mut steps = Vec::new();
+        while let Some(rate) = search.next_rate(&steps) {
+            let (achieved, p99_us) = if rate <= capacity {
+                (rate as f64, 500)
+            } else {
+                (capacity as f64, 5000)
+            };
+            steps.push(search.evaluate(rate, achieved, p99_us, 0));
+        }
+        steps
+    }
+
+    #[test]
+    fn test_evaluate_checks_sla_errors_and_throughput() {
+        let search = RateSearch::new(Duration::from_millis(2), 1000, Duration::from_secs(1));
+        assert!(search.evaluate(1000, 990.0, 2000, 0).passed);
+        assert!(!search.evaluate(1000, 990.0, 2001, 0).passed);
+        assert!(!search.evaluate(1000, 990.0, 100, 1).passed);
+        // The target fell behind the offered rate
+        assert!(!search.evaluate(1000, 900.0, 100, 0).passed);
+    }
+
+    #[test]
+    fn test_finds_capacity_within_precision() {
+        let search = RateSearch::new(Duration::from_millis(2), 1000, Duration::from_secs(1));
+        let steps = simulate(&search, 37_000);
+
+        // Doubles until the first violation
+        let offered: Vec<u64> = steps.iter().map(|s| s.offered).collect();
+        assert_eq!(
+            &offered[..7],
+            &[1000, 2000, 4000, 8000, 16000, 32000, 64000]
+        );
+
+        let best = RateSearch::best(&steps).unwrap();
+        assert!(best.offered <= 37_000);
+        assert!(best.offered as f64 >= 37_000.0 * (1.0 - search.precision));
+    }
+
+    #[test]
+    fn test_backs_off_when_start_rate_fails() {
+        let search = RateSearch::new(Duration::from_millis(2), 10_000, Duration::from_secs(1));
+        let steps = simulate(&search, 3000);
+
+        assert_eq!(steps[1].offered, 5000);
+        assert_eq!(steps[2].offered, 2500);
+        let best = RateSearch::best(&steps).unwrap();
+        assert!((2850..=3000).contains(&best.offered));
+    }
+
+    #[test]
+    fn test_gives_up_when_nothing_passes() {
+        let search = RateSearch::new(Duration::from_millis(2), 8, Duration::from_secs(1));
+        let steps = simulate(&search, 0);
+
+        assert_eq!(
+            steps.iter().map(|s| s.offered).collect::<Vec<_>>(),
+            vec![8, 4, 2, 1]
+        );
+        assert!(RateSearch::best(&steps).is_none());
+    }
+}
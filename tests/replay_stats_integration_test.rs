@@ -45,3 +45,14 @@ fn test_stats_reset_after_snapshot() {
     // After snapshot, stats should be reset
     assert_eq!(stats.get_count(), 0);
 }
+
+#[test]
+fn test_requested_rate_exported() {
+    let mut agg = AggregatedStats::new();
+    assert!(!agg.to_json().unwrap().contains("requested_rate"));
+
+    agg.set_requested_rate(Some(50000));
+    assert_eq!(agg.requested_rate(), Some(50000));
+    let json: serde_json::Value = serde_json::from_str(&agg.to_json().unwrap()).unwrap();
+    assert_eq!(json["requested_rate"], 50000);
+}
@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::CommandType;
+    use membench::replay::SyntheticSpec;
+
+    #[test]
+    fn test_parse_spec() {
+        let spec = SyntheticSpec::parse(
+            r#"
+            # read-heavy mix
+            connections = 8
+            get = 90
+            set = 10
+            key_size = [16, 32]
+            value_size = 1_000
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(spec.connections, 8);
+        assert_eq!(spec.command_weights.get(&CommandType::Get), Some(&90));
+        assert_eq!(spec.command_weights.get(&CommandType::Set), Some(&10));
+        assert_eq!(spec.command_weights.get(&CommandType::Delete), None);
+        assert_eq!(spec.key_sizes, vec![16, 32]);
+        assert_eq!(spec.value_sizes, vec![1000]);
+    }
+
+    #[test]
+    fn test_parse_spec_rejects_unknown_key() {
+        let err = SyntheticSpec::parse("conections = 4").unwrap_err();
+        assert!(err.to_string().contains("unknown key"));
+    }
+
+    #[test]
+    fn test_parse_spec_rejects_too_many_connections() {
+        let err = SyntheticSpec::parse("connections = 70000").unwrap_err();
+        assert!(err.to_string().contains("at most 65535"));
+
+        let spec = SyntheticSpec::parse("connections = 65535").unwrap();
+        assert_eq!(spec.connections, u16::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_rate_caps_a_faster_spec() {
+        use membench::replay::synthetic::synthetic_task;
+        use membench::replay::{ConnectionMessage, ReplayControl};
+        use std::collections::HashMap;
+        use std::time::Duration;
+        use tokio::sync::mpsc;
+
+        // The spec asks for 100k ops/sec, the rate for 200
+        let spec = SyntheticSpec::parse("connections = 1\ninterarrival_us = 10").unwrap();
+        let rate = Some(200);
+        let generator = spec
+            .generator()
+            .with_inter_arrival(spec.inter_arrival(rate))
+            .with_seed(7);
+        let (tx, mut rx) = mpsc::channel(100_000);
+        let control = ReplayControl::new(rate);
+
+        synthetic_task(
+            generator,
+            HashMap::from([(0, tx)]),
+            rate,
+            Some(Duration::from_millis(500)),
+            control.handle(),
+            tokio_util::sync::CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        let mut sent = 0;
+        while let Ok(message) = rx.try_recv() {
+            assert!(matches!(message, ConnectionMessage::Event(..)));
+            sent += 1;
+        }
+        assert!((50..=150).contains(&sent), "sent {} events", sent);
+    }
+
+    #[test]
+    fn test_spec_generator_uses_connections() {
+        let spec = SyntheticSpec::parse("connections = 3\nset = 1").unwrap();
+        let mut gen = spec.generator();
+
+        for _ in 0..50 {
+            let event = gen.next_command();
+            assert_eq!(event.cmd_type, CommandType::Set);
+            assert!(event.conn_id < 3);
+        }
+    }
+
+    #[test]
+    fn test_parse_spec_popularity() {
+        use membench::replay::KeyPopularity;
+
+        let spec = SyntheticSpec::parse("keyspace = 10_000\nzipf_s = 0.99\nzipf_q = 2.5").unwrap();
+        assert_eq!(
+            spec.popularity(),
+            KeyPopularity::Zipf {
+                keyspace: 10_000,
+                s: 0.99,
+                q: 2.5
+            }
+        );
+
+        let default = SyntheticSpec::parse("").unwrap();
+        assert_eq!(default.popularity(), KeyPopularity::Uniform);
+    }
+
+    #[test]
+    fn test_parse_spec_locality() {
+        let spec = SyntheticSpec::parse("get_after_set = 0.25\nlocality_window = 64").unwrap();
+        assert_eq!(spec.get_after_set, 0.25);
+        assert_eq!(spec.locality_window, 64);
+
+        assert!(SyntheticSpec::parse("get_after_set = 1.5").is_err());
+    }
+
+    #[test]
+    fn test_parse_spec_accepts_every_command() {
+        let spec = SyntheticSpec::parse("get = 80\nadd = 5\nincr = 10\ngat = 5").unwrap();
+        assert_eq!(spec.command_weights.get(&CommandType::Add), Some(&5));
+        assert_eq!(spec.command_weights.get(&CommandType::Incr), Some(&10));
+        assert_eq!(spec.command_weights.get(&CommandType::Gat), Some(&5));
+        assert_eq!(spec.command_weights.get(&CommandType::Set), None);
+    }
+}
@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event, Response, ResponseStatus};
+    use membench::replay::ThroughputSeries;
+    use std::num::NonZero;
+    use std::time::Duration;
+
+    fn event(timestamp: u64, cmd_type: CommandType) -> Event {
+        Event {
+            value_size: if cmd_type == CommandType::Set {
+                NonZero::new(100)
+            } else {
+                None
+            },
+            response: (cmd_type == CommandType::Get).then_some(Response {
+                status: ResponseStatus::Hit,
+                value_size: NonZero::new(50),
+            }),
+            ..Event::new(timestamp, 1, cmd_type, timestamp, 10)
+        }
+    }
+
+    /// A burst of 30 gets in the first second, nothing in the second, and 5
+    /// sets in the third
+    fn bursty() -> Vec<Event> {
+        let start = 1_000_000_000;
+        let mut events: Vec<Event> = (0..30)
+            .map(|i| event(start + i * 10_000, CommandType::Get))
+            .collect();
+        events.extend((0..5).map(|i| event(start + 2_000_000 + i * 100_000, CommandType::Set)));
+        events
+    }
+
+    #[test]
+    fn test_intervals_count_commands_and_bytes() {
+        let series = ThroughputSeries::new(&bursty(), Duration::from_secs(1));
+        assert_eq!(series.start, 1_000_000_000);
+        assert_eq!(series.buckets.len(), 3);
+        let counts: Vec<u64> = series.buckets.iter().map(|b| b.commands).collect();
+        assert_eq!(counts, vec![30, 0, 5]);
+        assert_eq!(series.buckets[0].bytes, 30 * (10 + 50));
+        assert_eq!(series.buckets[2].bytes, 5 * (10 + 100));
+        assert_eq!(
+            series.buckets[2].per_command.get(&CommandType::Set),
+            Some(&5)
+        );
+        assert_eq!(series.commands(), vec![CommandType::Get, CommandType::Set]);
+        assert_eq!(series.peak(), Some((0, 30)));
+
+        // Rates are per second whatever the interval
+        let series = ThroughputSeries::new(&bursty(), Duration::from_millis(100));
+        assert_eq!(series.buckets.len(), 25);
+        assert_eq!(series.rate(series.buckets[0].commands), 100.0);
+    }
+
+    #[test]
+    fn test_auto_interval_bounds_the_rows() {
+        let minute = 60_000_000;
+        assert_eq!(
+            ThroughputSeries::auto_interval(30_000_000, 60),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            ThroughputSeries::auto_interval(5 * minute, 60),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            ThroughputSeries::auto_interval(24 * 60 * minute, 60),
+            Duration::from_secs(3600)
+        );
+    }
+
+    #[test]
+    fn test_csv_has_a_column_per_command() {
+        let series = ThroughputSeries::new(&bursty(), Duration::from_secs(1));
+        let mut csv = Vec::new();
+        series.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "timestamp,ops_per_sec,bytes_per_sec,get_ops_per_sec,set_ops_per_sec",
+                "1000000000,30,1800,30,0",
+                "1001000000,0,0,0,0",
+                "1002000000,5,550,0,5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_series() {
+        let series = ThroughputSeries::new(&[], Duration::from_secs(1));
+        assert!(series.buckets.is_empty());
+        assert_eq!(series.peak(), None);
+    }
+}
@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Event, Response, ResponseStatus};
+    use membench::replay::validation::classify_response;
+    use membench::replay::ResponseValidation;
+    use std::num::NonZero;
+
+    fn get(conn_id: u16, timestamp: u64, response: Option<Response>) -> Event {
+        Event {
+            response,
+            ..Event::new(timestamp, conn_id, CommandType::Get, timestamp, 8)
+        }
+    }
+
+    fn hit(size: u32) -> Option<Response> {
+        Some(Response {
+            status: ResponseStatus::Hit,
+            value_size: NonZero::new(size),
+        })
+    }
+
+    fn miss() -> Option<Response> {
+        Some(Response {
+            status: ResponseStatus::Miss,
+            value_size: None,
+        })
+    }
+
+    #[test]
+    fn test_classify_response() {
+        assert_eq!(classify_response(b"VA 5\r\nhello\r\n"), hit(5));
+        assert_eq!(classify_response(b"VALUE k 0 3\r\nabc\r\nEND\r\n"), hit(3));
+        assert_eq!(classify_response(b"EN\r\n"), miss());
+        assert_eq!(
+            classify_response(b"SERVER_ERROR out of memory\r\n").map(|r| r.status),
+            Some(ResponseStatus::Error)
+        );
+        assert_eq!(classify_response(b"BOGUS\r\n"), None);
+    }
+
+    #[test]
+    fn test_check_counts_divergence() {
+        let mut validation = ResponseValidation::new(10);
+        validation.check(&get(1, 100, hit(5)), b"VA 5\r\nhello\r\n");
+        validation.check(&get(1, 200, hit(5)), b"EN\r\n");
+        validation.check(&get(1, 300, hit(5)), b"VA 3\r\nabc\r\n");
+        // Nothing recorded: nothing to compare
+        validation.check(&get(1, 400, None), b"EN\r\n");
+
+        assert_eq!(validation.compared, 3);
+        assert_eq!(validation.status_mismatches, 1);
+        assert_eq!(validation.value_size_mismatches, 1);
+        assert_eq!(validation.mismatches.len(), 2);
+        assert_eq!(validation.mismatches[0].timestamp, 200);
+        assert_eq!(validation.mismatches[0].replayed, miss());
+        assert_eq!(validation.mismatches[1].replayed, hit(3));
+        assert!((validation.match_percent() - 100.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_merge_keeps_earliest_mismatches() {
+        let mut first = ResponseValidation::new(2);
+        first.check(&get(1, 300, miss()), b"HD\r\n");
+        first.check(&get(1, 500, miss()), b"HD\r\n");
+        first.check(&get(1, 600, miss()), b"HD\r\n");
+        assert_eq!(first.mismatches.len(), 2);
+
+        let mut second = ResponseValidation::new(2);
+        second.check(&get(2, 100, miss()), b"HD\r\n");
+        second.check(&get(2, 400, miss()), b"HD\r\n");
+
+        first.merge(second.take());
+        assert_eq!(second.compared, 0);
+        assert_eq!(first.compared, 5);
+        assert_eq!(first.total_mismatches(), 5);
+        let kept: Vec<_> = first
+            .mismatches
+            .iter()
+            .map(|m| (m.conn_id, m.timestamp))
+            .collect();
+        assert_eq!(kept, vec![(2, 100), (1, 300)]);
+    }
+}
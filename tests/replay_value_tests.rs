@@ -0,0 +1,257 @@
+#[cfg(test)]
+mod tests {
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use membench::profile::{CommandType, Event};
+    use membench::replay::{ProtocolMode, ReplayClient, ValueFill};
+    use std::io::Write;
+    use std::num::NonZero;
+
+    fn value(fill: ValueFill, key_hash: u64, size: usize) -> Vec<u8> {
+        let mut buf = Vec::new();
+        fill.generator().fill(key_hash, size, &mut buf);
+        buf
+    }
+
+    fn compressed_len(data: &[u8]) -> usize {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap().len()
+    }
+
+    #[test]
+    fn test_value_fill_parsing() {
+        assert_eq!("repeat".parse::<ValueFill>(), Ok(ValueFill::Repeat));
+        assert_eq!("random".parse::<ValueFill>(), Ok(ValueFill::Random));
+        assert_eq!("pattern".parse::<ValueFill>(), Ok(ValueFill::Pattern));
+        assert_eq!(
+            "compressible:0.5".parse::<ValueFill>(),
+            Ok(ValueFill::Compressible(0.5))
+        );
+        assert!("compressible:1.5".parse::<ValueFill>().is_err());
+        assert!("compressible".parse::<ValueFill>().is_err());
+        assert!("zeros".parse::<ValueFill>().is_err());
+    }
+
+    #[test]
+    fn test_values_have_the_requested_size() {
+        for fill in [
+            ValueFill::Repeat,
+            ValueFill::Random,
+            ValueFill::Compressible(0.3),
+            ValueFill::Pattern,
+        ] {
+            for size in [0, 1, 63, 64, 1000] {
+                assert_eq!(value(fill, 7, size).len(), size, "{:?}", fill);
+            }
+        }
+        assert_eq!(value(ValueFill::Repeat, 7, 3), b"xxx");
+    }
+
+    #[test]
+    fn test_pattern_is_deterministic_per_key() {
+        let first = value(ValueFill::Pattern, 42, 256);
+        assert_eq!(value(ValueFill::Pattern, 42, 256), first);
+        assert_ne!(value(ValueFill::Pattern, 43, 256), first);
+        // A shorter value of the same key is a prefix of the longer one
+        assert_eq!(value(ValueFill::Pattern, 42, 100), first[..100]);
+    }
+
+    #[test]
+    fn test_compressibility_follows_the_fill() {
+        let size = 64 * 1024;
+        let random = compressed_len(&value(ValueFill::Random, 1, size));
+        let half = compressed_len(&value(ValueFill::Compressible(0.5), 1, size));
+        let repeat = compressed_len(&value(ValueFill::Repeat, 1, size));
+        assert!(random > size * 95 / 100, "random compressed to {}", random);
+        assert!(
+            half > size * 40 / 100 && half < size * 65 / 100,
+            "half compressible compressed to {}",
+            half
+        );
+        assert!(repeat < size / 100, "repeat compressed to {}", repeat);
+        assert_ne!(
+            value(ValueFill::Random, 1, 64),
+            value(ValueFill::Random, 1, 64)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_sends_generated_values() {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut received = Vec::new();
+            socket.read_to_end(&mut received).await.unwrap();
+            received
+        });
+
+        let event = Event {
+            value_size: NonZero::new(32),
+            ..Event::new(0, 0, CommandType::Set, 0xfeed, 8)
+        };
+        let mut client = ReplayClient::new(&addr, ProtocolMode::Meta)
+            .await
+            .unwrap()
+            .with_values(ValueFill::Pattern.generator());
+        client.send_command(&event).await.unwrap();
+        drop(client);
+
+        let mut expected = b"ms 00000000 32\r\n".to_vec();
+        expected.extend(value(ValueFill::Pattern, 0xfeed, 32));
+        expected.extend_from_slice(b"\r\n");
+        assert_eq!(server.await.unwrap(), expected);
+    }
+
+    fn get(cmd_type: CommandType) -> Event {
+        Event::new(0, 1, cmd_type, 0xfeed, 8)
+    }
+
+    /// A client of `protocol_mode` verifying hits, connected to a listener
+    /// that never answers
+    async fn verifying_client(protocol_mode: ProtocolMode) -> ReplayClient {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        ReplayClient::new(&addr, protocol_mode)
+            .await
+            .unwrap()
+            .with_verification()
+    }
+
+    fn text_hit(header: &str, value: &[u8], trailer: &[u8]) -> Vec<u8> {
+        [header.as_bytes(), value, b"\r\n", trailer].concat()
+    }
+
+    #[tokio::test]
+    async fn test_verification_checks_key_and_value() {
+        let event = get(CommandType::Get);
+        let written = value(ValueFill::Pattern, 0xfeed, 64);
+        let foreign = value(ValueFill::Pattern, 0xbeef, 64);
+
+        let ascii = verifying_client(ProtocolMode::Ascii).await;
+        let response = text_hit("VALUE 00000000 0 64\r\n", &written, b"END\r\n");
+        assert_eq!(ascii.check_integrity(&event, &response), Some(true));
+        // An older, shorter write of the key is intact too
+        let response = text_hit("VALUE 00000000 0 10\r\n", &written[..10], b"END\r\n");
+        assert_eq!(ascii.check_integrity(&event, &response), Some(true));
+        let response = text_hit("VALUE 11111111 0 64\r\n", &written, b"END\r\n");
+        assert_eq!(ascii.check_integrity(&event, &response), Some(false));
+        let response = text_hit("VALUE 00000000 0 64\r\n", &foreign, b"END\r\n");
+        assert_eq!(ascii.check_integrity(&event, &response), Some(false));
+        assert_eq!(ascii.check_integrity(&event, b"END\r\n"), None);
+        // Only retrieval hits are checked
+        assert_eq!(
+            ascii.check_integrity(&get(CommandType::Set), b"STORED\r\n"),
+            None
+        );
+
+        let meta = verifying_client(ProtocolMode::Meta).await;
+        let response = text_hit("VA 64\r\n", &written, b"");
+        assert_eq!(meta.check_integrity(&event, &response), Some(true));
+        let response = text_hit("VA 64\r\n", &foreign, b"");
+        assert_eq!(meta.check_integrity(&event, &response), Some(false));
+
+        let binary = verifying_client(ProtocolMode::Binary).await;
+        let frame = |status: u16, value: &[u8]| {
+            let mut frame = vec![0x81, 0x00, 0, 0, 4, 0];
+            frame.extend_from_slice(&status.to_be_bytes());
+            frame.extend_from_slice(&(4 + value.len() as u32).to_be_bytes());
+            frame.extend_from_slice(&[0; 12]);
+            frame.extend_from_slice(&[0; 4]); // flags
+            frame.extend_from_slice(value);
+            frame
+        };
+        assert_eq!(
+            binary.check_integrity(&event, &frame(0, &written)),
+            Some(true)
+        );
+        assert_eq!(
+            binary.check_integrity(&event, &frame(0, &foreign)),
+            Some(false)
+        );
+        assert_eq!(binary.check_integrity(&event, &frame(1, b"")), None);
+
+        // Without verification nothing is checked
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let plain = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        let response = text_hit("VA 64\r\n", &foreign, b"");
+        assert_eq!(plain.check_integrity(&event, &response), None);
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_hits_counted_as_integrity_errors() {
+        use membench::replay::connection_task::ConnectionConfig;
+        use membench::replay::stats::AggregatedStats;
+        use membench::replay::{spawn_connection_task, ConnectionMessage};
+        use std::time::{Duration, Instant};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::sync::mpsc;
+        use tokio_util::sync::CancellationToken;
+
+        // Answers the first GET with the value written, the second with another's
+        let written = value(ValueFill::Pattern, 0xfeed, 16);
+        let foreign = value(ValueFill::Pattern, 0xbeef, 16);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 256];
+            for value in [written, foreign] {
+                let n = socket.read(&mut buf).await.unwrap();
+                assert!(buf[..n].starts_with(b"mg "));
+                socket
+                    .write_all(&text_hit("VA 16\r\n", &value, b""))
+                    .await
+                    .unwrap();
+            }
+            socket
+        });
+
+        let client = ReplayClient::new(&addr, ProtocolMode::Meta).await.unwrap();
+        let (tx, rx) = mpsc::channel(8);
+        let (stats_tx, mut stats_rx) = mpsc::channel(8);
+        let config = ConnectionConfig {
+            verify: true,
+            ..ConnectionConfig::new(ProtocolMode::Meta)
+        };
+        let task = spawn_connection_task(
+            client,
+            &addr,
+            rx,
+            stats_tx,
+            1,
+            config,
+            CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        for _ in 0..2 {
+            tx.send(ConnectionMessage::Event(
+                get(CommandType::Get),
+                Instant::now(),
+            ))
+            .await
+            .unwrap();
+        }
+        drop(tx);
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        let mut stats = AggregatedStats::new();
+        while let Some(snapshot) = stats_rx.recv().await {
+            stats.merge(snapshot);
+        }
+        assert_eq!(stats.verified_hits(), 1);
+        assert_eq!(stats.integrity_errors(), 1);
+        assert_eq!(stats.total_operations(), 1);
+        assert!(stats.to_json().unwrap().contains("\"IntegrityError\": 1"));
+        drop(server.await.unwrap());
+    }
+}
@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use membench::replay::zipf::{fit_zipf, fit_zipf_mandelbrot};
+
+    fn zipf_frequencies(n: usize, s: f64, q: f64) -> Vec<u64> {
+        (1..=n)
+            .map(|rank| (1_000_000.0 / (rank as f64 + q).powf(s)).round() as u64)
+            .collect()
+    }
+
+    #[test]
+    fn test_fit_recovers_zipf_exponent() {
+        let frequencies = zipf_frequencies(5000, 1.1, 0.0);
+
+        let fit = fit_zipf(&frequencies).unwrap();
+
+        assert!((fit.s - 1.1).abs() < 0.05, "s = {}", fit.s);
+        assert!(fit.r_squared > 0.99);
+    }
+
+    #[test]
+    fn test_fit_mandelbrot_finds_offset() {
+        let frequencies = zipf_frequencies(5000, 0.9, 10.0);
+
+        let plain = fit_zipf(&frequencies).unwrap();
+        let fit = fit_zipf_mandelbrot(&frequencies).unwrap();
+
+        assert_eq!(fit.q, 10.0);
+        assert!((fit.s - 0.9).abs() < 0.05, "s = {}", fit.s);
+        assert!(fit.r_squared > plain.r_squared);
+    }
+
+    #[test]
+    fn test_fit_needs_two_keys() {
+        assert!(fit_zipf(&[]).is_none());
+        assert!(fit_zipf(&[42]).is_none());
+    }
+
+    #[test]
+    fn test_sampler_stays_in_keyspace_and_favors_head() {
+        use membench::replay::ZipfSampler;
+        use rand::SeedableRng;
+
+        let sampler = ZipfSampler::new(1000, 1.0, 0.0);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut counts = vec![0u64; 1001];
+        for _ in 0..100_000 {
+            let rank = sampler.sample(&mut rng);
+            assert!((1..=1000).contains(&rank));
+            counts[rank as usize] += 1;
+        }
+
+        // P(1) = 1 / H(1000) ≈ 13.4%
+        let p1 = counts[1] as f64 / 100_000.0;
+        assert!((p1 - 0.134).abs() < 0.01, "p1 = {}", p1);
+        assert!(counts[1] > counts[2] && counts[2] > counts[10]);
+    }
+}
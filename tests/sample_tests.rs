@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::{CommandType, Compression, Event};
+    use membench::record::ProfileWriter;
+    use membench::replay::ProfileReader;
+    use membench::sample::{sample, SampleBy};
+    use std::collections::HashMap;
+    use std::num::NonZero;
+    use tempfile::NamedTempFile;
+
+    /// Ten events on each of 2000 keys, a third of the keys written
+    fn write_profile(path: &str) {
+        let mut writer = ProfileWriter::with_compression(path, Compression::Deflate).unwrap();
+        for i in 0..20_000u64 {
+            let key = i % 2000;
+            let set = key.is_multiple_of(3);
+            writer
+                .write_event(&Event {
+                    value_size: NonZero::new(100).filter(|_| set),
+                    ..Event::new(
+                        1000 + i,
+                        (i % 8) as u16,
+                        if set {
+                            CommandType::Set
+                        } else {
+                            CommandType::Get
+                        },
+                        key,
+                        10,
+                    )
+                })
+                .unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_key_sampling_keeps_whole_keys() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        let input = input.path().to_str().unwrap();
+        let output = output.path().to_str().unwrap();
+        write_profile(input);
+
+        let report = sample(input, output, 0.1, SampleBy::Keys, 7).unwrap();
+        assert_eq!(report.events_read, 20_000);
+        assert!((1500..2500).contains(&report.events_kept), "{:?}", report);
+
+        let reader = ProfileReader::new(output).unwrap();
+        let mut per_key = HashMap::new();
+        let mut last = HashMap::new();
+        for event in reader.events() {
+            *per_key.entry(event.key_hash).or_insert(0) += 1;
+            // Every connection keeps its order
+            let previous = last.insert(event.conn_id, event.timestamp);
+            assert!(previous.is_none_or(|previous| previous < event.timestamp));
+        }
+        assert!(per_key.values().all(|&events| events == 10));
+
+        let metadata = reader.metadata();
+        assert_eq!(metadata.total_events, report.events_kept);
+        assert_eq!(metadata.event_sampling, Some(0.1));
+        assert!((metadata.sampling_scale() - 10.0).abs() < 1e-9);
+        let sets = metadata.command_distribution[&CommandType::Set] as f64;
+        let share = sets / metadata.total_events as f64;
+        assert!((share - 1.0 / 3.0).abs() < 0.1, "{}", share);
+
+        // The same seed keeps the same keys
+        let again = NamedTempFile::new().unwrap();
+        let again = again.path().to_str().unwrap();
+        assert_eq!(
+            sample(input, again, 0.1, SampleBy::Keys, 7).unwrap(),
+            report
+        );
+    }
+
+    #[test]
+    fn test_event_sampling_and_ratio_bounds() {
+        let input = NamedTempFile::new().unwrap();
+        let output = NamedTempFile::new().unwrap();
+        let input = input.path().to_str().unwrap();
+        let output = output.path().to_str().unwrap();
+        write_profile(input);
+
+        let report = sample(input, output, 0.25, SampleBy::Events, 1).unwrap();
+        assert!((4500..5500).contains(&report.events_kept), "{:?}", report);
+
+        let all = sample(input, output, 1.0, SampleBy::Keys, 1).unwrap();
+        assert_eq!(all.events_kept, 20_000);
+        assert!(sample(input, output, 0.0, SampleBy::Keys, 1).is_err());
+        assert!(sample(input, input, 0.5, SampleBy::Keys, 1).is_err());
+    }
+}
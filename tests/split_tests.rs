@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod tests {
+    use membench::merge::merge;
+    use membench::profile::{CommandType, Compression, Event, Protocol};
+    use membench::record::ProfileWriter;
+    use membench::replay::ProfileReader;
+    use membench::split::{split, SplitBy};
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    /// One event every 100ms over connections 0 to 9, for 60 seconds
+    fn write_capture(path: &str) {
+        let mut writer = ProfileWriter::with_compression(path, Compression::Deflate).unwrap();
+        writer.record_protocol(4, Protocol::Ascii);
+        for i in 0..600u64 {
+            writer
+                .write_event(&Event::new(
+                    5_000_000 + i * 100_000,
+                    (i % 10) as u16,
+                    CommandType::Get,
+                    i,
+                    10,
+                ))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_split_by_connection_keeps_connections_whole() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("capture.profile");
+        let input = input.to_str().unwrap();
+        let output = dir.path().join("shard.profile");
+        write_capture(input);
+
+        let shards = split(
+            input,
+            output.to_str().unwrap(),
+            SplitBy::Connection { shards: 3 },
+        )
+        .unwrap();
+        assert_eq!(shards.len(), 3);
+        assert!(shards[1].path.ends_with("shard.0001.profile"));
+        assert_eq!(shards.iter().map(|s| s.events).sum::<u64>(), 600);
+
+        let mut seen = HashSet::new();
+        for shard in &shards {
+            let reader = ProfileReader::new(&shard.path).unwrap();
+            assert_eq!(reader.metadata().total_events, shard.events);
+            assert_eq!(reader.metadata().compression, Compression::Deflate);
+            let conn_ids: HashSet<u16> = reader.events().iter().map(|e| e.conn_id).collect();
+            assert_eq!(
+                reader.metadata().unique_connections as usize,
+                conn_ids.len()
+            );
+            assert!(seen.is_disjoint(&conn_ids));
+            assert_eq!(
+                reader.metadata().connection_protocols.contains_key(&4),
+                conn_ids.contains(&4)
+            );
+            seen.extend(conn_ids);
+        }
+        assert_eq!(seen.len(), 10);
+
+        // Merging the shards back gives every event again, in order
+        let merged = dir.path().join("merged.profile");
+        let merged = merged.to_str().unwrap();
+        let paths: Vec<String> = shards.into_iter().map(|s| s.path).collect();
+        merge(&paths, merged, Compression::None).unwrap();
+        let reader = ProfileReader::new(merged).unwrap();
+        let hashes: Vec<u64> = reader.events().iter().map(|e| e.key_hash).collect();
+        assert_eq!(hashes, (0..600).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_split_by_time_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("capture.profile");
+        let input = input.to_str().unwrap();
+        let output = dir.path().join("minute");
+        write_capture(input);
+
+        let shards = split(
+            input,
+            output.to_str().unwrap(),
+            SplitBy::Time {
+                window: Duration::from_secs(25),
+            },
+        )
+        .unwrap();
+        let events: Vec<u64> = shards.iter().map(|s| s.events).collect();
+        assert_eq!(events, [250, 250, 100]);
+        assert!(shards[2].path.ends_with("minute.0002"));
+
+        let reader = ProfileReader::new(&shards[1].path).unwrap();
+        assert_eq!(reader.metadata().time_range, (30_000_000, 54_900_000));
+        assert!(split(input, "x", SplitBy::Connection { shards: 0 }).is_err());
+    }
+}
@@ -4,146 +4,46 @@
 /// - memcached daemon installed and available
 /// - memtier_benchmark installed
 ///
-/// Run with: cargo test --test system_tests -- --ignored --nocapture
+/// Run with: cargo test --features testutil --test system_tests -- --ignored --nocapture
 #[cfg(test)]
 mod system_tests {
-    use std::io::Write;
-    use std::net::TcpStream;
-    use std::process::{Child, Command};
-    use std::thread;
+    use membench::testutil::{is_tool_available, parse_throughput, ManagedMemcached, MemtierLoad};
     use std::time::Duration;
 
-    const MEMCACHED_HOST: &str = "127.0.0.1";
-    const MEMCACHED_PORT: u16 = 11211;
-    const MEMCACHED_ADDR: &str = "127.0.0.1:11211";
-
-    /// Check if a tool is available in PATH
-    fn is_tool_available(tool: &str) -> bool {
-        Command::new("which")
-            .arg(tool)
-            .output()
-            .map(|output| output.status.success())
-            .unwrap_or(false)
-    }
-
-    /// Check if memcached is running on the default port
-    fn is_memcached_running() -> bool {
-        TcpStream::connect(MEMCACHED_ADDR).is_ok()
-    }
-
-    /// Wait for memcached to start (up to 5 seconds)
-    fn wait_for_memcached(timeout_secs: u64) -> bool {
-        let start = std::time::Instant::now();
-        while start.elapsed().as_secs() < timeout_secs {
-            if is_memcached_running() {
-                return true;
-            }
-            thread::sleep(Duration::from_millis(100));
-        }
-        false
-    }
-
-    /// Start memcached daemon on default port
-    fn start_memcached() -> Result<Child, String> {
-        // Check if already running
-        if is_memcached_running() {
-            println!("memcached already running on {}", MEMCACHED_ADDR);
-            return Err("memcached already running".to_string());
+    /// Start a private memcached, or explain why the test is skipped
+    fn start_memcached() -> Option<ManagedMemcached> {
+        if !is_tool_available("memcached") || !is_tool_available("memtier_benchmark") {
+            println!("SKIPPED: memcached or memtier_benchmark not available");
+            return None;
         }
-
-        let child = Command::new("memcached")
-            .arg("-l")
-            .arg(MEMCACHED_HOST)
-            .arg("-p")
-            .arg(MEMCACHED_PORT.to_string())
-            .arg("-m")
-            .arg("256") // 256MB max memory
-            .spawn()
-            .map_err(|e| format!("Failed to start memcached: {}", e))?;
-
-        println!("Started memcached on {}", MEMCACHED_ADDR);
-
-        // Wait for it to be ready
-        if !wait_for_memcached(5) {
-            return Err("memcached failed to start within 5 seconds".to_string());
+        match ManagedMemcached::start_on_free_port() {
+            Ok(memcached) => {
+                println!("Started memcached on {}", memcached.addr());
+                Some(memcached)
+            }
+            Err(e) => {
+                println!("SKIPPED: {}", e);
+                None
+            }
         }
-
-        Ok(child)
     }
 
-    /// Flush memcached data
-    fn flush_memcached() -> Result<(), String> {
-        let mut stream = TcpStream::connect(MEMCACHED_ADDR)
-            .map_err(|e| format!("Failed to connect to memcached: {}", e))?;
-
-        stream
-            .write_all(b"flush_all\r\n")
-            .map_err(|e| format!("Failed to send flush command: {}", e))?;
-
-        Ok(())
-    }
-
-    /// Generate load with memtier_benchmark
-    fn generate_load_with_memtier(
-        num_clients: usize,
-        requests_per_client: usize,
-        test_time_secs: u64,
-    ) -> Result<(), String> {
-        if !is_tool_available("memtier_benchmark") {
-            return Err("memtier_benchmark not found in PATH".to_string());
-        }
-
-        let output = Command::new("memtier_benchmark")
-            .arg("--server")
-            .arg(MEMCACHED_HOST)
-            .arg("--port")
-            .arg(MEMCACHED_PORT.to_string())
-            .arg("--protocol")
-            .arg("memcache_text") // ASCII protocol (what membench supports)
-            .arg("--clients")
-            .arg(num_clients.to_string())
-            .arg("--requests")
-            .arg(requests_per_client.to_string())
-            .arg("--test-time")
-            .arg(test_time_secs.to_string())
-            .arg("--hide-histogram") // Don't clutter output
-            .output()
-            .map_err(|e| format!("Failed to run memtier_benchmark: {}", e))?;
-
-        if !output.status.success() {
-            return Err(format!(
-                "memtier_benchmark failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
+    // ==================== TESTS ====================
 
-        println!(
-            "memtier_benchmark output:\n{}",
-            String::from_utf8_lossy(&output.stdout)
+    #[test]
+    fn test_parse_memtier_throughput() {
+        let summary = "Type         Ops/sec     Hits/sec   Misses/sec\n\
+                       ------------------------------------------\n\
+                       Sets         1234.50          ---          ---\n\
+                       Totals      12345.67      1000.00       200.00\n";
+        assert_eq!(parse_throughput(summary), Some(12345.67));
+        assert_eq!(
+            parse_throughput("Throughput:    999.5 Ops/sec"),
+            Some(999.5)
         );
-
-        Ok(())
+        assert_eq!(parse_throughput("no summary"), None);
     }
 
-    /// Parse memtier_benchmark output to extract throughput
-    fn extract_throughput_from_output(output: &str) -> Option<f64> {
-        for line in output.lines() {
-            if line.contains("Ops/sec") {
-                // Expected format: "Throughput:    12345.67 Ops/sec"
-                if let Some(start) = line.find(':') {
-                    let rest = &line[start + 1..];
-                    if let Some(end) = rest.find("Ops/sec") {
-                        let throughput_str = rest[..end].trim();
-                        return throughput_str.parse().ok();
-                    }
-                }
-            }
-        }
-        None
-    }
-
-    // ==================== TESTS ====================
-
     /// Test 1: Verify memcached is available and can be started
     #[test]
     #[ignore]
@@ -159,28 +59,18 @@ mod system_tests {
             panic!("memtier_benchmark not found in PATH. Install with: brew install memtier-benchmark (macOS) or apt-get install memtier (Linux)");
         }
 
-        match start_memcached() {
-            Ok(mut child) => {
-                println!("✓ memcached started successfully");
-
-                // Verify it's responding
-                match TcpStream::connect(MEMCACHED_ADDR) {
-                    Ok(mut stream) => {
-                        stream
-                            .write_all(b"version\r\n")
-                            .expect("Failed to write version command");
-                        println!("✓ memcached is responding to commands");
-
-                        // Kill memcached
-                        let _ = child.kill();
-                        let _ = child.wait();
-                        println!("✓ memcached stopped cleanly");
-                    }
-                    Err(e) => panic!("Failed to connect to memcached: {}", e),
-                }
-            }
-            Err(e) => panic!("Failed to start memcached: {}", e),
-        }
+        let memcached = ManagedMemcached::start_on_free_port()
+            .unwrap_or_else(|e| panic!("Failed to start memcached: {}", e));
+        println!("✓ memcached started successfully");
+
+        let version = memcached
+            .command("version")
+            .expect("Failed to send version");
+        assert!(version.starts_with("VERSION"), "{}", version);
+        println!("✓ memcached is responding to commands");
+
+        memcached.stop().expect("Failed to stop memcached");
+        println!("✓ memcached stopped cleanly");
     }
 
     /// Test 2: Generate load with memtier_benchmark
@@ -189,28 +79,19 @@ mod system_tests {
     fn test_memtier_load_generation() {
         println!("\n=== TEST: memtier_benchmark Load Generation ===");
 
-        if !is_tool_available("memcached") || !is_tool_available("memtier_benchmark") {
-            println!("SKIPPED: memcached or memtier_benchmark not available");
+        let Some(memcached) = start_memcached() else {
             return;
-        }
-
-        let mut memcached = match start_memcached() {
-            Ok(child) => child,
-            Err(e) => {
-                println!("SKIPPED: {}", e);
-                return;
-            }
         };
 
         // Generate light load: 2 clients, 100 requests each, 5 second test
-        match generate_load_with_memtier(2, 100, 5) {
-            Ok(()) => println!("✓ memtier_benchmark load generation succeeded"),
-            Err(e) => panic!("Load generation failed: {}", e),
-        }
-
-        // Cleanup
-        let _ = memcached.kill();
-        let _ = memcached.wait();
+        let report = MemtierLoad::new(memcached.port())
+            .with_clients(2)
+            .with_requests(100)
+            .with_test_time(Duration::from_secs(5))
+            .run()
+            .unwrap_or_else(|e| panic!("Load generation failed: {}", e));
+        println!("memtier_benchmark output:\n{}", report.stdout);
+        println!("✓ memtier_benchmark load generation succeeded");
     }
 
     /// Test 3: Verify data is captured in expected format
@@ -219,60 +100,27 @@ mod system_tests {
     fn test_memtier_with_ascii_protocol() {
         println!("\n=== TEST: memtier_benchmark with ASCII Protocol ===");
 
-        if !is_tool_available("memcached") || !is_tool_available("memtier_benchmark") {
-            println!("SKIPPED: memcached or memtier_benchmark not available");
+        let Some(memcached) = start_memcached() else {
             return;
-        }
-
-        let mut memcached = match start_memcached() {
-            Ok(child) => child,
-            Err(e) => {
-                println!("SKIPPED: {}", e);
-                return;
-            }
         };
 
         // Clear any existing data
-        let _ = flush_memcached();
+        memcached.flush().expect("Failed to flush memcached");
 
         // Generate a small load specifically with ASCII protocol
         // This should be parseable by membench
-        let output = Command::new("memtier_benchmark")
-            .arg("--server")
-            .arg(MEMCACHED_HOST)
-            .arg("--port")
-            .arg(MEMCACHED_PORT.to_string())
-            .arg("--protocol")
-            .arg("memcache_text") // ASCII protocol
-            .arg("--clients")
-            .arg("1")
-            .arg("--requests")
-            .arg("50")
-            .arg("--test-time")
-            .arg("3")
-            .output()
-            .expect("Failed to run memtier_benchmark");
-
-        if output.status.success() {
-            println!("✓ ASCII protocol load generation succeeded");
-
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            println!("Benchmark output:\n{}", stdout);
-
-            if let Some(throughput) = extract_throughput_from_output(&stdout) {
-                println!("✓ Extracted throughput: {:.2} Ops/sec", throughput);
-                assert!(throughput > 0.0, "Throughput should be positive");
-            }
-        } else {
-            panic!(
-                "memtier_benchmark failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
+        let report = MemtierLoad::new(memcached.port())
+            .with_requests(50)
+            .with_test_time(Duration::from_secs(3))
+            .run()
+            .unwrap_or_else(|e| panic!("{}", e));
+        println!("✓ ASCII protocol load generation succeeded");
+        println!("Benchmark output:\n{}", report.stdout);
+
+        if let Some(throughput) = report.ops_per_sec {
+            println!("✓ Extracted throughput: {:.2} Ops/sec", throughput);
+            assert!(throughput > 0.0, "Throughput should be positive");
         }
-
-        // Cleanup
-        let _ = memcached.kill();
-        let _ = memcached.wait();
     }
 
     /// Test 4: Full workflow - Capture from memtier, Analyze, Replay
@@ -281,11 +129,6 @@ mod system_tests {
     fn test_capture_analyze_replay_workflow() {
         println!("\n=== TEST: Capture → Analyze → Replay Workflow ===");
 
-        if !is_tool_available("memcached") || !is_tool_available("memtier_benchmark") {
-            println!("SKIPPED: memcached or memtier_benchmark not available");
-            return;
-        }
-
         use tempfile::TempDir;
 
         let temp_dir = match TempDir::new() {
@@ -302,19 +145,16 @@ mod system_tests {
         // For now, we demonstrate the structure:
 
         println!("Step 1: Starting memcached...");
-        let mut memcached = match start_memcached() {
-            Ok(child) => child,
-            Err(e) => {
-                println!("SKIPPED: {}", e);
-                return;
-            }
+        let Some(memcached) = start_memcached() else {
+            return;
         };
 
         println!("Step 2: Generating load with memtier_benchmark...");
-        if let Err(e) = generate_load_with_memtier(1, 100, 5) {
+        let load = MemtierLoad::new(memcached.port())
+            .with_test_time(Duration::from_secs(5))
+            .run();
+        if let Err(e) = load {
             println!("SKIPPED: Load generation failed: {}", e);
-            let _ = memcached.kill();
-            let _ = memcached.wait();
             return;
         }
 
@@ -334,10 +174,6 @@ mod system_tests {
         println!("  - Generate traffic with TrafficGenerator::new()");
 
         println!("✓ Workflow structure validated");
-
-        // Cleanup
-        let _ = memcached.kill();
-        let _ = memcached.wait();
     }
 
     /// Test 5: Verify distribution consistency
@@ -346,17 +182,8 @@ mod system_tests {
     fn test_workload_characteristics() {
         println!("\n=== TEST: Workload Characteristics ===");
 
-        if !is_tool_available("memcached") || !is_tool_available("memtier_benchmark") {
-            println!("SKIPPED: memcached or memtier_benchmark not available");
+        let Some(memcached) = start_memcached() else {
             return;
-        }
-
-        let mut memcached = match start_memcached() {
-            Ok(child) => child,
-            Err(e) => {
-                println!("SKIPPED: {}", e);
-                return;
-            }
         };
 
         // Test with different workload patterns
@@ -372,40 +199,24 @@ mod system_tests {
                 name, clients, requests, time
             );
 
-            let output = Command::new("memtier_benchmark")
-                .arg("--server")
-                .arg(MEMCACHED_HOST)
-                .arg("--port")
-                .arg(MEMCACHED_PORT.to_string())
-                .arg("--protocol")
-                .arg("memcache_text")
-                .arg("--clients")
-                .arg(clients.to_string())
-                .arg("--requests")
-                .arg(requests.to_string())
-                .arg("--test-time")
-                .arg(time.to_string())
-                .output()
-                .expect("Failed to run memtier_benchmark");
-
-            if output.status.success() {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-
-                // Extract key metrics
-                for line in stdout.lines() {
-                    if line.contains("Ops/sec") || line.contains("Avg. Latency") {
-                        println!("  {}", line.trim());
+            let load = MemtierLoad::new(memcached.port())
+                .with_clients(clients)
+                .with_requests(requests)
+                .with_test_time(Duration::from_secs(time))
+                .run();
+            match load {
+                Ok(report) => {
+                    // Extract key metrics
+                    for line in report.stdout.lines() {
+                        if line.contains("Ops/sec") || line.contains("Avg. Latency") {
+                            println!("  {}", line.trim());
+                        }
                     }
                 }
-            } else {
-                eprintln!("  Failed: {}", String::from_utf8_lossy(&output.stderr));
+                Err(e) => eprintln!("  Failed: {}", e),
             }
         }
 
         println!("\n✓ Workload characteristics tested");
-
-        // Cleanup
-        let _ = memcached.kill();
-        let _ = memcached.wait();
     }
 }
@@ -0,0 +1,33 @@
+#[cfg(test)]
+mod tests {
+    use membench::units::{parse_count, parse_duration, parse_probability};
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(parse_duration("5").unwrap(), Duration::from_secs(5));
+        assert!(parse_duration("5 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("5000").unwrap(), 5000);
+        assert_eq!(parse_count("100k").unwrap(), 100_000);
+        assert_eq!(parse_count("1.5M").unwrap(), 1_500_000);
+        assert!(parse_count("lots").is_err());
+    }
+
+    #[test]
+    fn test_parse_probability() {
+        assert_eq!(parse_probability("0.01").unwrap(), 0.01);
+        assert_eq!(parse_probability("25%").unwrap(), 0.25);
+        assert_eq!(parse_probability("1").unwrap(), 1.0);
+        for invalid in ["0", "1.5", "-0.1", "NaN", "often"] {
+            assert!(parse_probability(invalid).is_err(), "{:?} parsed", invalid);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use membench::profile::checksum::Trailer;
+    use membench::profile::{CommandType, Compression, Event, ProfileMetadata};
+    use membench::record::ProfileWriter;
+    use membench::validate::validate;
+    use tempfile::NamedTempFile;
+
+    fn write_profile(path: &str, count: u64, compression: Compression) {
+        let mut writer = ProfileWriter::with_compression(path, compression).unwrap();
+        for i in 0..count {
+            writer
+                .write_event(&Event::new(
+                    1000 + i * 10,
+                    (i % 5) as u16,
+                    if i.is_multiple_of(3) {
+                        CommandType::Set
+                    } else {
+                        CommandType::Get
+                    },
+                    i,
+                    10,
+                ))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    /// Offset and length of the metadata of a finished profile
+    fn metadata_bounds(data: &[u8]) -> (usize, usize) {
+        let trailer = Trailer::parse(&data[data.len() - Trailer::MAX_LEN..]).unwrap();
+        let start = trailer.metadata_start(data.len() as u64).unwrap() as usize;
+        (start, trailer.metadata_len as usize)
+    }
+
+    #[test]
+    fn test_valid_profiles_pass() {
+        for compression in [Compression::None, Compression::Deflate] {
+            let temp = NamedTempFile::new().unwrap();
+            let path = temp.path().to_str().unwrap();
+            write_profile(path, 10_000, compression);
+
+            let report = validate(path).unwrap();
+            assert_eq!(report.events, 10_000);
+            assert_eq!(report.compression, compression);
+            assert!(report.checksum_records > 0);
+            assert!(report.index_entries.unwrap() > 0);
+        }
+    }
+
+    #[test]
+    fn test_empty_profile_passes() {
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+        write_profile(path, 0, Compression::None);
+        assert_eq!(validate(path).unwrap().events, 0);
+    }
+
+    #[test]
+    fn test_corrupt_event_is_located() {
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+        write_profile(path, 10_000, Compression::None);
+
+        // A byte of the key hash of the first event
+        let mut data = std::fs::read(path).unwrap();
+        data[2 + 14] ^= 0xff;
+        std::fs::write(path, &data).unwrap();
+
+        let err = validate(path).err().unwrap().to_string();
+        assert!(err.starts_with("checksum mismatch in bytes 0.."), "{}", err);
+    }
+
+    #[test]
+    fn test_truncated_profile_has_no_end_marker() {
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+        write_profile(path, 1000, Compression::Deflate);
+
+        let data = std::fs::read(path).unwrap();
+        std::fs::write(path, &data[..data.len() - 3]).unwrap();
+        let err = validate(path).err().unwrap().to_string();
+        assert!(err.contains("no end marker"), "{}", err);
+    }
+
+    #[test]
+    fn test_metadata_counts_are_cross_checked() {
+        let temp = NamedTempFile::new().unwrap();
+        let path = temp.path().to_str().unwrap();
+        write_profile(path, 1000, Compression::Deflate);
+
+        // Rewrite the metadata with a wrong count and a matching checksum
+        let data = std::fs::read(path).unwrap();
+        let (start, len) = metadata_bounds(&data);
+        let mut metadata = ProfileMetadata::decode(&data[start..start + len]).unwrap();
+        metadata.total_events += 1;
+        let encoded = bincode::serialize(&metadata).unwrap();
+        assert_eq!(encoded.len(), len);
+        let mut profile = data[..start].to_vec();
+        profile.extend_from_slice(&encoded);
+        profile.extend_from_slice(&Trailer::encode(&encoded).unwrap());
+        std::fs::write(path, &profile).unwrap();
+
+        let err = validate(path).err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "the metadata counts 1001 events, the event region holds 1000"
+        );
+    }
+}